@@ -0,0 +1,185 @@
+#![no_std]
+#![no_main]
+
+extern crate lib;
+use lib::time::{Duration, Instant};
+use lib::*;
+
+/// Bytes moved through the kernel for each size
+const TOTAL: usize = 4 << 20;
+/// Sizes of the reads and writes, the largest is what a memfd holds
+const SIZES: [usize; 5] = [64, 512, 4096, 16384, 65536];
+
+const COPY_MODE: &str = "mem.copy_mode";
+const MODE_BULK: usize = 0;
+const MODE_BYTES: usize = 1;
+
+/// Pages of memory touched before forking, for `bench fork`
+const FORK_PAGES: [usize; 4] = [0, 64, 1024, 8192];
+/// Forks timed for each size
+const FORKS: u32 = 32;
+const PAGE: usize = 4096;
+
+/// Time to write and read back `TOTAL` bytes, `size` at a time
+fn round_trips(fd: u8, buf: &mut [u8], size: usize) -> Option<Duration> {
+    let start = Instant::now();
+    for _ in 0..TOTAL / size {
+        if sys_write(fd, &buf[..size])? != size || sys_read(fd, &mut buf[..size])? != size {
+            return None;
+        }
+    }
+    Some(start.elapsed())
+}
+
+/// Instructions and cycles this process was charged, zero without
+/// performance counters
+fn counters() -> (u64, u64) {
+    sys_get_rusage(0).map_or((0, 0), |usage| (usage.instructions, usage.cycles))
+}
+
+/// MiB per second moved in `time`, each byte is copied twice
+fn throughput(time: Duration) -> u64 {
+    ((2 * TOTAL as u128 * 1_000_000_000 / time.as_nanos().max(1)) >> 20) as u64
+}
+
+/// Average time for a fork and the wait for the child, which writes
+/// to `writes` pages of `mem` and exits
+fn forks(mem: &mut [u8], writes: usize) -> Option<Duration> {
+    let start = Instant::now();
+    for _ in 0..FORKS {
+        match sys_try_fork().ok()? {
+            0 => {
+                for page in mem.chunks_mut(PAGE).take(writes) {
+                    page[0] = 1;
+                }
+                sys_exit(0);
+            }
+            pid => {
+                sys_wait_pid(pid);
+            }
+        }
+    }
+    Some(start.elapsed() / FORKS)
+}
+
+/// Fork with more and more memory mapped, the page tables are shared
+/// until written, so only the pages written should cost
+fn fork_bench() -> isize {
+    println!("fork + exit + wait, {} times each", FORKS);
+    println!(
+        "{:>8} {:>14} {:>16} {:>15}",
+        "pages", "no write us", "one write us", "all written us"
+    );
+
+    // pages are only mapped once touched, the first ones here
+    let len = FORK_PAGES[FORK_PAGES.len() - 1] * PAGE;
+    let Some(addr) = sys_mmap(0, len, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS) else {
+        errln!("Failed to map {} bytes.", len);
+        return 1;
+    };
+    let mem = unsafe { core::slice::from_raw_parts_mut(addr as *mut u8, len) };
+
+    for pages in FORK_PAGES {
+        for page in mem.chunks_mut(PAGE).take(pages) {
+            page[0] = 1;
+        }
+
+        let times = (forks(mem, 0), forks(mem, 1.min(pages)), forks(mem, pages));
+        let (Some(none), Some(one), Some(all)) = times else {
+            errln!("Fork failed.");
+            return 1;
+        };
+        println!(
+            "{:>8} {:>14} {:>16} {:>15}",
+            pages,
+            none.as_micros(),
+            one.as_micros(),
+            all.as_micros()
+        );
+    }
+
+    0
+}
+
+fn main(args: &[&str]) -> isize {
+    if args.get(1) == Some(&"fork") {
+        return fork_bench();
+    }
+
+    let Some(old_mode) = sys_sysctl_get(COPY_MODE) else {
+        errln!("The kernel has no {} to compare with.", COPY_MODE);
+        return 1;
+    };
+    if sys_sysctl_set(COPY_MODE, old_mode).is_err() {
+        errln!("Only root may switch {}.", COPY_MODE);
+        return 1;
+    }
+
+    let Some(fd) = sys_memfd() else {
+        errln!("Failed to create a memfd.");
+        return 1;
+    };
+
+    let mut buf = vec![0x5au8; SIZES[SIZES.len() - 1]];
+
+    println!("memfd write + read, {} MiB each way", TOTAL >> 20);
+    println!(
+        "{:>8} {:>12} {:>12} {:>8} {:>11} {:>6}",
+        "size", "bytes MiB/s", "bulk MiB/s", "speedup", "bulk ins/op", "IPC"
+    );
+
+    let mut failed = false;
+    for size in SIZES {
+        let _ = sys_sysctl_set(COPY_MODE, MODE_BYTES);
+        let bytes = round_trips(fd, &mut buf, size);
+        let _ = sys_sysctl_set(COPY_MODE, MODE_BULK);
+        let before = counters();
+        let bulk = round_trips(fd, &mut buf, size);
+        let after = counters();
+        let (instructions, cycles) = (after.0 - before.0, after.1 - before.1);
+
+        let (Some(bytes), Some(bulk)) = (bytes, bulk) else {
+            errln!("Short read or write of {} bytes.", size);
+            failed = true;
+            break;
+        };
+
+        // in hundredths, there is no floating point
+        let speedup = bytes.as_nanos() * 100 / bulk.as_nanos().max(1);
+        let per_op = instructions / (TOTAL / size) as u64;
+        let ipc = instructions * 100 / cycles.max(1);
+        println!(
+            "{:>8} {:>12} {:>12} {:>4}.{:02}x {:>11} {:>3}.{:02}",
+            size,
+            throughput(bytes),
+            throughput(bulk),
+            speedup / 100,
+            speedup % 100,
+            per_op,
+            ipc / 100,
+            ipc % 100
+        );
+    }
+
+    let _ = sys_sysctl_set(COPY_MODE, old_mode);
+    sys_close(fd);
+
+    if failed {
+        1
+    } else {
+        0
+    }
+}
+
+entry!(main);
+allow_syscalls!(
+    Read,
+    Close,
+    MemFd,
+    Sysctl,
+    ClockMonotonic,
+    GetRusage,
+    Fork,
+    WaitPid,
+    Mmap
+);
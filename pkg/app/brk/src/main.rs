@@ -5,7 +5,7 @@ use lib::*;
 
 extern crate lib;
 
-fn main() -> isize {
+fn main(_args: &[&str]) -> isize {
 
     println!("hello, this is a brk test!");
 
@@ -21,4 +21,5 @@ fn main() -> isize {
     0
 }
 
-entry!(main);
\ No newline at end of file
+entry!(main);
+allow_syscalls!(Brk);
\ No newline at end of file
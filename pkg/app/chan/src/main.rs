@@ -0,0 +1,84 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use lib::ipc;
+use lib::*;
+
+extern crate lib;
+
+const WORKERS: u64 = 4;
+/// The workers count the primes below it, a slice each
+const LIMIT: u64 = 20_000;
+/// Primes below `LIMIT`
+const EXPECTED: u64 = 2262;
+/// Reports the channel holds before a worker blocks sending
+const CAPACITY: usize = 2;
+
+fn is_prime(n: u64) -> bool {
+    n >= 2
+        && (2..)
+            .take_while(|d| d * d <= n)
+            .all(|d| !n.is_multiple_of(d))
+}
+
+fn main(_args: &[&str]) -> isize {
+    // which worker, the numbers it looked at, and the primes among them
+    let Some((tx, rx)) = ipc::channel::<(u64, String, u64)>(CAPACITY) else {
+        errln!("chan: failed to create a channel");
+        return 1;
+    };
+
+    let mut pids = Vec::new();
+    for worker in 0..WORKERS {
+        let pid = sys_fork();
+        if pid == 0 {
+            drop(rx);
+
+            let (start, end) = (LIMIT * worker / WORKERS, LIMIT * (worker + 1) / WORKERS);
+            let count = (start..end).filter(|&n| is_prime(n)).count() as u64;
+            if !tx.send(&(worker, format!("{}..{}", start, end), count)) {
+                errln!("chan: the report of worker {} is too long", worker);
+                return 1;
+            }
+            return 0;
+        }
+        pids.push(pid);
+    }
+
+    // the parent sends nothing, each worker reports once
+    drop(tx);
+
+    let (mut total, mut reports) = (0, 0);
+    for _ in 0..WORKERS {
+        let Some((worker, range, count)) = rx.recv() else {
+            errln!("chan: a report is not a worker, a range and a count");
+            continue;
+        };
+        println!("worker {} found {} primes in {}", worker, count, range);
+        total += count;
+        reports += 1;
+    }
+
+    for pid in pids {
+        sys_wait_pid(pid);
+    }
+
+    println!("{} primes below {} from {} workers", total, LIMIT, reports);
+    if total != EXPECTED || reports != WORKERS {
+        errln!(
+            "chan: expected {} primes from {} workers",
+            EXPECTED,
+            WORKERS
+        );
+        return 1;
+    }
+
+    0
+}
+
+entry!(main);
+allow_syscalls!(Mmap, Munmap, GetPid, Sem, Fork, WaitPid);
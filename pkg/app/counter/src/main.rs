@@ -5,24 +5,48 @@ use lib::*;
 
 extern crate lib;
 
-static LOCK: SpinLock = lib::SpinLock::new();
 const THREAD_COUNT: usize = 8;
-static mut COUNTER: isize = 0;
-static mut COUNTER_SEM: isize = 0;
 
-fn main() -> isize {
+/// The counters and their lock, in a shared mapping,
+/// as forked processes get their own copy of statics
+struct Shared {
+    lock: SpinLock,
+    counter: isize,
+    counter_sem: isize,
+}
+
+static mut SHARED: *mut Shared = core::ptr::null_mut();
+
+fn main(_args: &[&str]) -> isize {
+    let addr = sys_mmap(
+        0,
+        core::mem::size_of::<Shared>(),
+        PROT_READ | PROT_WRITE,
+        MAP_SHARED | MAP_ANONYMOUS,
+    )
+    .expect("failed to map shared memory");
+
+    unsafe {
+        SHARED = addr as *mut Shared;
+        SHARED.write(Shared {
+            lock: SpinLock::new(),
+            counter: 0,
+            counter_sem: 0,
+        });
+    }
+
     let pid = sys_fork();
 
     if pid == 0 {
         test_spin_lock();
-        sys_exit(0);
+        exit(0);
     } else {
         test_semaphore();
         sys_wait_pid(pid);
     }
 
-    println!("COUNTER result: {}", unsafe { COUNTER });
-    println!("COUNTER_SEM result: {}", unsafe { COUNTER_SEM });
+    println!("COUNTER result: {}", unsafe { (*SHARED).counter });
+    println!("COUNTER_SEM result: {}", unsafe { (*SHARED).counter_sem });
 
     0
 }
@@ -36,7 +60,7 @@ fn test_spin_lock() {
         if pid == 0 {
             do_counter_inc();
 
-            sys_exit(0);
+            exit(0);
         } else {
             pids[i] = pid; // only parent knows child's pid
         }
@@ -57,15 +81,14 @@ fn test_spin_lock() {
 fn test_semaphore() {
     const HOLD: usize = THREAD_COUNT / 2;
     let mut pids = [0u16; HOLD];
-    let key = 0x1234;
-    sys_new_sem(key, 1);
+    let sem = Semaphore::create(0x1234, 1).expect("failed to create semaphore");
 
     for i in 0..HOLD {
         let pid = sys_fork();
         if pid == 0 {
-            do_counter_inc_semaphore();
+            do_counter_inc_semaphore(&sem);
 
-            sys_exit(0);
+            exit(0);
         } else {
             pids[i] = pid; // only parent knows child's pid
         }
@@ -79,28 +102,22 @@ fn test_semaphore() {
         println!("#{} waiting for #{}...", cpid, pids[i]);
         sys_wait_pid(pids[i]);
     }
-
-    sys_del_sem(key);
 }
 
-fn do_counter_inc_semaphore() {
-    let key = 0x1234;
-    
-
+fn do_counter_inc_semaphore(sem: &Semaphore) {
     for _ in 0..100 {
-        sys_wait_sem(key);
+        let _guard = sem.acquire();
         inc_counter_sem();
-        sys_signal_sem(key);
     }
-
 }
 
 fn do_counter_inc() {
     for _ in 0..100 {
         // FIXME: protect the critical section  
-        LOCK.acquire();      
+        let lock = unsafe { &(*SHARED).lock };
+        lock.acquire();
         inc_counter();
-        LOCK.release();
+        lock.release();
     }
 }
 
@@ -111,22 +128,22 @@ fn do_counter_inc() {
 fn inc_counter() {
     unsafe {
         delay();
-        let mut val = COUNTER;
+        let mut val = (*SHARED).counter;
         delay();
         val += 1;
         delay();
-        COUNTER = val;
+        (*SHARED).counter = val;
     }
 }
 
 fn inc_counter_sem() {
     unsafe {
         delay();
-        let mut val = COUNTER_SEM;
+        let mut val = (*SHARED).counter_sem;
         delay();
         val += 1;
         delay();
-        COUNTER_SEM = val;
+        (*SHARED).counter_sem = val;
     }
 }
 
@@ -139,3 +156,4 @@ fn delay() {
 }
 
 entry!(main);
+allow_syscalls!(Sem, Fork, Mmap, Stat, WaitPid);
@@ -0,0 +1,58 @@
+#![no_std]
+#![no_main]
+
+use lib::fs::File;
+use lib::*;
+
+extern crate lib;
+
+/// Bytes asked of each `sys_send_file`, the kernel copies them in chunks
+const CHUNK: usize = 64 * 1024;
+
+const USAGE: &str = "Usage: cp <source> <dest>";
+
+fn main(args: &[&str]) -> isize {
+    let [_, source, dest] = args else {
+        errln!("{}", USAGE);
+        return 2;
+    };
+
+    let input = match File::open(source) {
+        Ok(file) => file,
+        Err(errno) => {
+            errln!("cp: failed to open {}: errno {}", source, errno);
+            return 1;
+        }
+    };
+    let output = match File::create(dest) {
+        Ok(file) => file,
+        Err(errno) => {
+            errln!("cp: failed to create {}: errno {}", dest, errno);
+            return 1;
+        }
+    };
+
+    // the bytes go from one file to the other in the kernel, with no
+    // buffer here, until the source has no more
+    let mut total = 0;
+    loop {
+        match sys_send_file(output.fd(), input.fd(), None, CHUNK) {
+            Some(0) => break,
+            Some(count) => total += count,
+            None => {
+                errln!("cp: failed to copy {} to {}", source, dest);
+                return 1;
+            }
+        }
+    }
+
+    if let Some(size) = input.size().filter(|&size| size != total) {
+        errln!("cp: copied {} of {} bytes", total, size);
+        return 1;
+    }
+
+    0
+}
+
+entry!(main);
+allow_syscalls!(Open, SendFile, Fstat, Close);
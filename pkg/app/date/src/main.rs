@@ -0,0 +1,97 @@
+#![no_std]
+#![no_main]
+
+use lib::args::{self, Arg};
+use lib::string::String;
+use lib::time::{self, DATE_FORMAT};
+use lib::*;
+
+extern crate lib;
+
+const USAGE: &str = "[-u] [-s time] [-z offset] [+format]";
+const OPTIONS: [(&str, &str); 4] = [
+    ("-u", "print UTC instead of local time"),
+    (
+        "-s <time>",
+        "set the time, YYYY-MM-DD HH:MM[:SS] or HH:MM[:SS]",
+    ),
+    ("-z <offset>", "set the time zone, UTC or [+-]HH[:MM]"),
+    (
+        "+<format>",
+        "print with strftime-like %Y %m %d %H %M %S ...",
+    ),
+];
+
+fn main(args: &[&str]) -> isize {
+    let mut parser = args::Parser::new(&args[1..]);
+    let mut utc = false;
+    let mut set = None;
+    let mut zone = None;
+    let mut format = DATE_FORMAT;
+
+    while let Some(arg) = parser.next() {
+        match arg {
+            Arg::Short('u') => utc = true,
+            Arg::Short('s') => set = parser.value().map(String::from),
+            Arg::Short('z') => zone = parser.value(),
+            Arg::Value(value) if value.starts_with('+') => format = &value[1..],
+            // the shell splits `-s 2024-01-01 12:00` in two words
+            Arg::Value(value) if set.is_some() => {
+                let set = set.as_mut().unwrap();
+                set.push(' ');
+                set.push_str(value);
+            }
+            other => {
+                args::unexpected("date", other);
+                args::print_usage("date", USAGE, &OPTIONS);
+                return 2;
+            }
+        }
+    }
+
+    if let Some(zone) = zone {
+        let Some(offset) = time::parse_offset(zone) else {
+            errln!("date: invalid time zone: {}", zone);
+            return 2;
+        };
+        if let Err(errno) = sys_set_utc_offset(offset) {
+            report("cannot set the time zone", errno);
+            return 1;
+        }
+    }
+
+    if let Some(set) = set {
+        let offset = time::local_offset();
+        let today = time::local_now().date_naive();
+        let Some(local) = time::parse(&set, today) else {
+            errln!("date: invalid time: {}", set);
+            return 2;
+        };
+        let Some(utc) = local.and_local_timezone(offset).single() else {
+            errln!("date: invalid time: {}", set);
+            return 2;
+        };
+        if let Err(errno) = sys_set_time(utc.to_utc()) {
+            report("cannot set the time", errno);
+            return 1;
+        }
+    }
+
+    let now = match utc {
+        true => sys_time().fixed_offset(),
+        false => time::local_now(),
+    };
+    println!("{}", time::format(&now, format));
+
+    0
+}
+
+fn report(what: &str, errno: usize) {
+    match errno {
+        errno::EPERM => errln!("date: {}: permission denied", what),
+        errno => errln!("date: {}: error {}", what, errno),
+    }
+}
+
+entry!(main);
+allow_syscalls!(Time, SetTime, UtcOffset);
@@ -37,13 +37,18 @@ fn dinner(set: u16) {
 
 }
 
-fn main() -> isize {
+fn main(_args: &[&str]) -> isize {
     let mut pids = [0u16; 5];
+
+    for chopstick in CHOPSTICK.iter() {
+        chopstick.init(1).expect("failed to create semaphore");
+    }
+
     for i in 0..CHOPSTICK.len() {
         let pid = sys_fork();
         if pid == 0 { // child
             dinner(i as u16);
-            sys_exit(0);
+            exit(0);
         } else {
             pids[i] = pid;
         }
@@ -59,4 +64,5 @@ fn main() -> isize {
 
 }
 
-entry!(main);
\ No newline at end of file
+entry!(main);
+allow_syscalls!(Sem, Fork, Stat, WaitPid);
\ No newline at end of file
@@ -18,7 +18,7 @@ fn factorial(n: u64) -> u64 {
     }
 }
 
-fn main() -> usize {
+fn main(_args: &[&str]) -> usize {
     print!("Input n: ");
 
     let input = lib::stdin().read_line();
@@ -44,3 +44,4 @@ fn main() -> usize {
 }
 
 entry!(main);
+allow_syscalls!(Read, Fstat, Stat);
@@ -73,19 +73,19 @@ fn print(id: usize) {
     }
 }
 
-fn main() -> isize {
+fn main(_args: &[&str]) -> isize {
     let mut pids = [0u16; 3];
 
     for i in 0..3 {
-        PROCESS[i].init(0);
+        PROCESS[i].init(0).expect("failed to create semaphore");
     }
-    WAITER.init(1);
+    WAITER.init(1).expect("failed to create semaphore");
 
     for i in 0..PROCESS.len() {
         let pid = sys_fork();
         if pid == 0 { // child
             print(i);
-            sys_exit(0);
+            exit(0);
         } else {
             pids[i] = pid;
         }
@@ -103,4 +103,5 @@ fn main() -> isize {
 
 }
 
-entry!(main);
\ No newline at end of file
+entry!(main);
+allow_syscalls!(Sem, Time, Fork, WaitPid);
\ No newline at end of file
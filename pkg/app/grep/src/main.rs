@@ -0,0 +1,49 @@
+#![no_std]
+#![no_main]
+
+use lib::time::Instant;
+use lib::*;
+
+extern crate lib;
+
+fn main(args: &[&str]) -> isize {
+    let Some(&pattern) = args.get(1) else {
+        errln!("Usage: grep <string>");
+        return 2;
+    };
+
+    // search what comes down a pipe to its end,
+    // or what is typed until an empty line
+    if stdin().is_console() {
+        errln!("Type lines to search, an empty line to end.");
+    }
+
+    let start = Instant::now();
+    let mut out = BufWriter::new(1);
+    let mut matched = 0usize;
+
+    for line in stdin().lines() {
+        if line.contains(pattern) {
+            out.write(line.as_bytes());
+            out.write(b"\n");
+            matched += 1;
+        }
+    }
+
+    out.flush();
+    errln!(
+        "grep: {} lines matched in {} us",
+        matched,
+        start.elapsed().as_micros()
+    );
+
+    // like grep, 1 when nothing matched
+    if matched == 0 {
+        1
+    } else {
+        0
+    }
+}
+
+entry!(main);
+allow_syscalls!(Read, Fstat, ClockMonotonic);
@@ -0,0 +1,89 @@
+#![no_std]
+#![no_main]
+
+use lib::compress::gzip::{gunzip, GzipEncoder};
+use lib::compress::lz4::{self, Lz4Encoder};
+use lib::compress::Error;
+use lib::vec::Vec;
+use lib::*;
+
+extern crate lib;
+
+/// There are no files to compress yet, so the samples are built in
+const SAMPLE: &[u8] = include_bytes!("../fixtures/sample.txt");
+/// The sample compressed on the host, by `gzip -9n` and `lz4 -l -9`
+const HOST_GZIP: &[u8] = include_bytes!("../fixtures/sample.txt.gz");
+const HOST_LZ4: &[u8] = include_bytes!("../fixtures/sample.txt.lz4");
+
+/// Feed the encoders in pieces, as a stream would
+const CHUNK_SIZE: usize = 100;
+
+fn gzip(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut encoder = GzipEncoder::new(Vec::new())?;
+    for chunk in data.chunks(CHUNK_SIZE) {
+        encoder.write(chunk)?;
+    }
+    encoder.finish()
+}
+
+fn lz4(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut encoder = Lz4Encoder::new(Vec::new())?;
+    for chunk in data.chunks(CHUNK_SIZE) {
+        encoder.write(chunk)?;
+    }
+    encoder.finish()
+}
+
+fn check(
+    name: &str,
+    compressed: Result<Vec<u8>, Error>,
+    decompress: fn(&[u8], &mut Vec<u8>) -> Result<usize, Error>,
+) -> bool {
+    let compressed = match compressed {
+        Ok(compressed) => compressed,
+        Err(err) => {
+            errln!("{:<12}: failed to compress: {}", name, err);
+            return false;
+        }
+    };
+
+    let mut out = Vec::new();
+    let ok = match decompress(&compressed, &mut out) {
+        Ok(_) if out == SAMPLE => true,
+        Ok(_) => {
+            errln!("{:<12}: output differs from the sample", name);
+            false
+        }
+        Err(err) => {
+            errln!("{:<12}: failed to decompress: {}", name, err);
+            false
+        }
+    };
+
+    println!(
+        "{:<12}: {:>5} -> {:>5} bytes ({:>3}%) {}",
+        name,
+        SAMPLE.len(),
+        compressed.len(),
+        compressed.len() * 100 / SAMPLE.len(),
+        if ok { "ok" } else { "FAILED" }
+    );
+    ok
+}
+
+fn main(_args: &[&str]) -> isize {
+    let results = [
+        check("gzip", gzip(SAMPLE), gunzip),
+        check("gzip (host)", Ok(HOST_GZIP.to_vec()), gunzip),
+        check("lz4", lz4(SAMPLE), lz4::decompress),
+        check("lz4 (host)", Ok(HOST_LZ4.to_vec()), lz4::decompress),
+    ];
+
+    match results.iter().all(|&ok| ok) {
+        true => 0,
+        false => 1,
+    }
+}
+
+entry!(main);
+allow_syscalls!();
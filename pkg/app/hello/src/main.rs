@@ -5,9 +5,13 @@ use lib::*;
 
 extern crate lib;
 
-fn main() -> usize {
+fn main(args: &[&str]) -> usize {
     println!("Hello, world!!!");
 
+    if args.len() > 1 {
+        println!("Arguments: {:?}", &args[1..]);
+    }
+
     let time = lib::sys_time();
     println!("Now at: {}", time);
 
@@ -34,3 +38,4 @@ fn huge_stack() {
 }
 
 entry!(main);
+allow_syscalls!(Time);
@@ -0,0 +1,82 @@
+#![no_std]
+#![no_main]
+
+use lib::vec::Vec;
+use lib::*;
+
+extern crate lib;
+
+/// Parse the hex digits of `line` into `code`, two to a byte
+///
+/// whitespace is skipped and `#` starts a comment.
+/// Return false on anything else, or an odd count of digits.
+fn parse_line(line: &str, code: &mut Vec<u8>) -> bool {
+    let line = line.split('#').next().unwrap_or_default();
+    let mut digits = line
+        .chars()
+        .filter(|ch| !ch.is_ascii_whitespace())
+        .map(|ch| ch.to_digit(16));
+
+    while let Some(high) = digits.next() {
+        match (high, digits.next()) {
+            (Some(high), Some(Some(low))) => code.push((high << 4 | low) as u8),
+            _ => return false,
+        }
+    }
+
+    true
+}
+
+fn main(_args: &[&str]) -> isize {
+    println!("Type machine code as hex bytes, an empty line to run it.");
+    println!("It is called with no arguments, what it leaves in rax is printed,");
+    println!("e.g. `b8 2a 00 00 00 c3` is `mov eax, 42; ret`.");
+
+    let mut code = Vec::new();
+    loop {
+        let line = stdin().read_line();
+        if line.is_empty() {
+            break;
+        }
+        if !parse_line(&line, &mut code) {
+            errln!("Not hex bytes: {}", line);
+            return 1;
+        }
+    }
+
+    if code.is_empty() {
+        errln!("No code to run.");
+        return 1;
+    }
+
+    // written while writable, then made executable and read-only
+    let Some(addr) = sys_mmap(
+        0,
+        code.len(),
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS,
+    ) else {
+        errln!("Failed to map {} bytes.", code.len());
+        return 1;
+    };
+
+    unsafe { core::ptr::copy_nonoverlapping(code.as_ptr(), addr as *mut u8, code.len()) };
+
+    if !sys_mprotect(addr, code.len(), PROT_READ | PROT_EXEC) {
+        errln!("Failed to make {:#x} executable.", addr);
+        return 1;
+    }
+
+    println!("Running {} bytes at {:#x}...", code.len(), addr);
+
+    let entry: extern "C" fn() -> usize = unsafe { core::mem::transmute(addr) };
+    let ret = entry();
+
+    println!("Returned {} ({:#x})", ret, ret);
+
+    sys_munmap(addr, code.len());
+    0
+}
+
+entry!(main);
+allow_syscalls!(Read, Fstat, Mmap, MProtect, Munmap);
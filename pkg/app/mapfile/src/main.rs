@@ -0,0 +1,65 @@
+#![no_std]
+#![no_main]
+
+use lib::fs::File;
+use lib::*;
+
+extern crate lib;
+
+const PATH: &str = "/tmp/mapfile.dat";
+const PAGE: usize = 4096;
+
+/// Map the first page of `file` shared, to read and write
+fn map(file: &File) -> Option<*mut u8> {
+    sys_mmap_file(0, PAGE, PROT_READ | PROT_WRITE, MAP_SHARED, file.fd(), 0)
+        .ok()
+        .map(|addr| addr as *mut u8)
+}
+
+fn first(page: *mut u8) -> char {
+    unsafe { page.read_volatile() as char }
+}
+
+fn main(_args: &[&str]) -> isize {
+    if !File::create(PATH).is_ok_and(|mut file| file.write_all(&[b'o'; PAGE])) {
+        errln!("mapfile: failed to write {}", PATH);
+        return 1;
+    }
+    let Ok(file) = File::open_with(PATH, O_RDWR) else {
+        errln!("mapfile: failed to open {}", PATH);
+        return 1;
+    };
+    let (Some(shared), Some(other)) = (map(&file), map(&file)) else {
+        errln!("mapfile: failed to map {}", PATH);
+        return 1;
+    };
+    let before = first(other);
+
+    // the child writes the page it was forked with, written back on exit
+    let pid = sys_fork();
+    if pid == 0 {
+        unsafe { shared.write_volatile(b'c') };
+        return 0;
+    }
+    sys_wait_pid(pid);
+
+    // the other mapping of the file kept the page it read before
+    let Some(new) = map(&file) else {
+        errln!("mapfile: failed to map {} again", PATH);
+        return 1;
+    };
+    let seen = (before, first(shared), first(other), first(new));
+    println!(
+        "other mapping before: {}, forked mapping: {}, other mapping: {}, new mapping: {}",
+        seen.0, seen.1, seen.2, seen.3
+    );
+
+    if seen != ('o', 'c', 'o', 'c') {
+        errln!("mapfile: expected o, c, o and c");
+        return 1;
+    }
+    0
+}
+
+entry!(main);
+allow_syscalls!(Open, Write, Close, Mmap, Fork, WaitPid);
@@ -2,18 +2,18 @@
 #![no_main]
 
 extern crate alloc;
-use lib::{collections::VecDeque, *};
+use lib::*;
 
 extern crate lib;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 struct Message {
     pid: u16,
     val: usize,
 }
 
 const SIZE: u32 = 8;
-static mut MQ: VecDeque<Message> = VecDeque::new();
+static mut MQ: *mut Queue = core::ptr::null_mut();
 static MUTEX: Semaphore = Semaphore::new(1);
 static EMPTY: Semaphore = Semaphore::new(2);
 static FULL: Semaphore = Semaphore::new(3);
@@ -24,14 +24,48 @@ impl Message {
     }
 }
 
+/// A ring of messages in a shared mapping,
+/// as forked processes get their own copy of the heap
+struct Queue {
+    head: usize,
+    len: usize,
+    slots: [Message; SIZE as usize],
+}
+
+impl Queue {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn push_back(&mut self, msg: Message) {
+        assert!(self.len < self.slots.len(), "queue is full");
+        self.slots[(self.head + self.len) % self.slots.len()] = msg;
+        self.len += 1;
+    }
+
+    fn pop_front(&mut self) -> Option<Message> {
+        if self.is_empty() {
+            return None;
+        }
+        let msg = self.slots[self.head];
+        self.head = (self.head + 1) % self.slots.len();
+        self.len -= 1;
+        Some(msg)
+    }
+}
+
 fn produce(pid: u16) {
     for i in 0..10 {
         let msg = Message::new(pid, i as usize);
         EMPTY.wait();
         MUTEX.wait();
-        println!("producer pid: {}, send: {}, now queue length: {}", msg.pid, msg.val, unsafe { MQ.len() });
+        println!("producer pid: {}, send: {}, now queue length: {}", msg.pid, msg.val, unsafe { (*MQ).len() });
         unsafe {
-            MQ.push_back(msg);
+            (*MQ).push_back(msg);
         }
         MUTEX.signal();
         FULL.signal();
@@ -42,22 +76,32 @@ fn consume(pid: u16) {
     for _ in 0..10 {
         FULL.wait();
         MUTEX.wait();
-        let msg = unsafe { MQ.pop_front().unwrap() };
-        println!("consumer pid: {}, recv from {}: {}, now queue length: {}", pid, msg.pid, msg.val, unsafe { MQ.len() });
+        let msg = unsafe { (*MQ).pop_front().unwrap() };
+        println!("consumer pid: {}, recv from {}: {}, now queue length: {}", pid, msg.pid, msg.val, unsafe { (*MQ).len() });
         MUTEX.signal();
         EMPTY.signal();
         //println!("pid: {}, val: {}", msg.pid, msg.val);
     }
 }
 
-fn main() -> isize {
+fn main(_args: &[&str]) -> isize {
     
     // 创建16个pid
     let mut pids = [0u16; 16];
 
-    MUTEX.init(1);
-    EMPTY.init(SIZE as usize);
-    FULL.init(0);
+    let addr = sys_mmap(
+        0,
+        core::mem::size_of::<Queue>(),
+        PROT_READ | PROT_WRITE,
+        MAP_SHARED | MAP_ANONYMOUS,
+    )
+    .expect("failed to map shared memory");
+    // the mapping starts zeroed, an empty queue
+    unsafe { MQ = addr as *mut Queue };
+
+    MUTEX.init(1).expect("failed to create semaphore");
+    EMPTY.init(SIZE as usize).expect("failed to create semaphore");
+    FULL.init(0).expect("failed to create semaphore");
 
     for i in 0..16 {
         let pid = sys_fork();
@@ -68,7 +112,7 @@ fn main() -> isize {
             } else {
                 consume(pid);
             }
-            sys_exit(0);
+            exit(0);
         } else { // 父进程
             pids[i as usize] = pid;
         }
@@ -81,9 +125,10 @@ fn main() -> isize {
         sys_wait_pid(pid);
     }
 
-    println!("Message Queue Test Passed! is empty ? : {:?}", unsafe { MQ.is_empty() });
+    println!("Message Queue Test Passed! is empty ? : {:?}", unsafe { (*MQ).is_empty() });
 
     0
 }
 
-entry!(main);
\ No newline at end of file
+entry!(main);
+allow_syscalls!(Sem, Fork, Mmap, Stat, WaitPid);
\ No newline at end of file
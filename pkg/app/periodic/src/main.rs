@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+
+extern crate lib;
+use lib::time::{sleep, Duration, Instant};
+use lib::*;
+
+/// A task doing `work` of cpu time every `period`, reserving `budget` for it
+struct Task {
+    period: Duration,
+    budget: Duration,
+    work: Duration,
+}
+
+const TASKS: [Task; 2] = [
+    Task {
+        period: Duration::from_millis(50),
+        budget: Duration::from_millis(15),
+        work: Duration::from_millis(10),
+    },
+    Task {
+        period: Duration::from_millis(100),
+        budget: Duration::from_millis(40),
+        work: Duration::from_millis(25),
+    },
+];
+const ROUNDS: u32 = 20;
+
+/// Keep the cpu busy for `duration`
+fn spin(duration: Duration) {
+    let start = Instant::now();
+    while start.elapsed() < duration {
+        core::hint::spin_loop();
+    }
+}
+
+/// Run `task` for `ROUNDS` periods, return the deadlines it missed
+fn run(id: usize, task: &Task) -> u32 {
+    if let Err(errno) = sys_sched_rt(0, task.period, task.budget) {
+        errln!("task {}: reservation refused, errno {}", id, errno);
+        return ROUNDS;
+    }
+
+    let start = Instant::now();
+    let mut missed = 0;
+    let mut worst = Duration::ZERO;
+    for round in 0..ROUNDS {
+        let release = start + task.period * round;
+        spin(task.work);
+
+        let response = Instant::now() - release;
+        worst = worst.max(response);
+        if response > task.period {
+            missed += 1;
+        }
+
+        let next = release + task.period;
+        sleep(next - Instant::now());
+    }
+
+    println!(
+        "task {}: {} ms every {} ms, worst response {} ms, {} of {} deadlines missed",
+        id,
+        task.work.as_millis(),
+        task.period.as_millis(),
+        worst.as_millis(),
+        missed,
+        ROUNDS
+    );
+    missed
+}
+
+fn main(_args: &[&str]) -> isize {
+    let longest = TASKS
+        .iter()
+        .map(|task| task.period)
+        .max()
+        .unwrap_or_default();
+
+    // a cpu-bound process of the normal class, it only gets what is left
+    let hog = sys_fork();
+    if hog == 0 {
+        spin(longest * (ROUNDS + 1));
+        sys_exit(0);
+    }
+
+    let mut tasks = [0; TASKS.len()];
+    for (id, task) in TASKS.iter().enumerate() {
+        tasks[id] = sys_fork();
+        if tasks[id] == 0 {
+            sys_exit(run(id, task) as usize);
+        }
+    }
+
+    // the tasks take most of the cpu, so another large reservation is refused
+    sleep(Duration::from_millis(10));
+    match sys_sched_rt(0, Duration::from_millis(10), Duration::from_millis(6)) {
+        Err(errno::EBUSY) => println!("admission: a 60% reservation is refused, as expected"),
+        Err(errno) => println!("admission: refused with errno {}", errno),
+        Ok(()) => {
+            println!("admission: a 60% reservation was admitted");
+            sys_sched_rt(0, Duration::ZERO, Duration::ZERO).ok();
+        }
+    }
+
+    let missed: isize = tasks.iter().map(|&pid| sys_wait_pid(pid)).sum();
+    sys_wait_pid(hog);
+
+    println!("{} deadlines missed in total", missed);
+    missed
+}
+
+entry!(main);
+allow_syscalls!(SchedRt, Fork, WaitPid, Sleep, ClockMonotonic);
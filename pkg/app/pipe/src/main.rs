@@ -0,0 +1,114 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+use lib::*;
+
+extern crate lib;
+
+/// Bytes the child sends, several times what the pipe holds
+const TOTAL: usize = 64 * 1024;
+
+fn pattern(i: usize) -> u8 {
+    (i * 7 + i / 256) as u8
+}
+
+/// Send `TOTAL` bytes from a forked child, the writer blocks on the full pipe
+fn test_fork() -> isize {
+    let Some((read, write)) = sys_pipe() else {
+        errln!("pipe: failed to open a pipe");
+        return 1;
+    };
+
+    let pid = sys_fork();
+    if pid == 0 {
+        sys_close(read);
+
+        let mut buf = [0u8; 1000];
+        let mut sent = 0;
+        while sent < TOTAL {
+            let len = buf.len().min(TOTAL - sent);
+            for (i, byte) in buf[..len].iter_mut().enumerate() {
+                *byte = pattern(sent + i);
+            }
+            if !write_all(write, &buf[..len]) {
+                errln!("pipe: write failed after {} bytes", sent);
+                return 1;
+            }
+            sent += len;
+        }
+
+        // the read end sees EOF once this exits
+        return 0;
+    }
+
+    // or the parent would never see EOF
+    sys_close(write);
+
+    let mut buf = [0u8; 512];
+    let mut received = 0;
+    loop {
+        match sys_read(read, &mut buf) {
+            Some(0) => break,
+            Some(count) => {
+                for (i, &byte) in buf[..count].iter().enumerate() {
+                    assert_eq!(byte, pattern(received + i));
+                }
+                received += count;
+            }
+            None => {
+                errln!("pipe: read failed after {} bytes", received);
+                return 1;
+            }
+        }
+    }
+
+    sys_close(read);
+    assert_eq!(sys_wait_pid(pid), 0);
+    assert_eq!(received, TOTAL);
+
+    println!("Received {} bytes from #{}", received, pid);
+    0
+}
+
+/// Spawn `app` writing to a pipe and print its output, one `| ` per line
+fn test_spawn(app: &str) -> isize {
+    let Some((read, write)) = sys_pipe() else {
+        errln!("pipe: failed to open a pipe");
+        return 1;
+    };
+
+    let pid = sys_spawn_with_stdout(app, write);
+    sys_close(write);
+    if pid == 0 {
+        errln!("pipe: failed to spawn {}", app);
+        sys_close(read);
+        return 1;
+    }
+
+    let mut buf = [0u8; 256];
+    let mut line_start = true;
+    while let Some(count) = sys_read(read, &mut buf).filter(|&count| count > 0) {
+        for &byte in &buf[..count] {
+            if line_start {
+                print!("| ");
+            }
+            print!("{}", byte as char);
+            line_start = byte == b'\n';
+        }
+    }
+
+    sys_close(read);
+    let ret = sys_wait_pid(pid);
+    println!("{} exited with {}", app, ret);
+    0
+}
+
+fn main(args: &[&str]) -> isize {
+    match args.get(1) {
+        Some(app) => test_spawn(app),
+        None => test_fork(),
+    }
+}
+
+entry!(main);
@@ -0,0 +1,275 @@
+#![no_std]
+#![no_main]
+
+use lib::compress::gzip::{gunzip, is_gzip};
+use lib::fs::archive::Archive;
+use lib::hash::{sha256, Hex};
+use lib::time::{Duration, Instant};
+use lib::vec::Vec;
+use lib::*;
+
+extern crate lib;
+
+const STDIN: u8 = 0;
+const STDOUT: u8 = 1;
+
+const SOH: u8 = 0x01;
+const STX: u8 = 0x02;
+const EOT: u8 = 0x04;
+const ACK: u8 = 0x06;
+const NAK: u8 = 0x15;
+const CAN: u8 = 0x18;
+/// Pads the last block
+const SUB: u8 = 0x1a;
+/// Asks the sender for CRC-16 instead of checksums
+const CRC_MODE: u8 = b'C';
+
+/// Times to ask for the first block, the first ones for CRC-16
+const START_TRIES: usize = 20;
+const CRC_TRIES: usize = 5;
+/// Bad blocks in a row before giving up
+const MAX_ERRORS: usize = 10;
+
+const START_TIMEOUT: Duration = Duration::from_millis(3000);
+const BYTE_TIMEOUT: Duration = Duration::from_millis(1000);
+const PURGE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Largest file kept, there is nowhere to put it but memory
+const MAX_SIZE: usize = 1 << 20;
+
+#[derive(Debug)]
+enum RecvError {
+    NoSender,
+    Cancelled,
+    TooManyErrors,
+    OutOfSync,
+    TooLarge,
+}
+
+/// Raw serial input, read in as large pieces as there are
+struct Input {
+    buf: [u8; 1100],
+    start: usize,
+    end: usize,
+}
+
+impl Input {
+    fn new() -> Self {
+        Self {
+            buf: [0; 1100],
+            start: 0,
+            end: 0,
+        }
+    }
+
+    fn byte(&mut self, timeout: Duration) -> Option<u8> {
+        let deadline = Instant::now() + timeout;
+        while self.start == self.end {
+            match sys_read(STDIN, &mut self.buf) {
+                Some(count) if count > 0 => {
+                    self.start = 0;
+                    self.end = count;
+                }
+                _ if Instant::now() >= deadline => return None,
+                _ => sys_yield(),
+            }
+        }
+
+        self.start += 1;
+        Some(self.buf[self.start - 1])
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> bool {
+        buf.iter_mut()
+            .all(|slot| self.byte(BYTE_TIMEOUT).map(|b| *slot = b).is_some())
+    }
+
+    /// Drop what the sender is still sending of a bad block
+    fn purge(&mut self) {
+        while self.byte(PURGE_TIMEOUT).is_some() {}
+    }
+}
+
+fn send(byte: u8) {
+    sys_write(STDOUT, &[byte]);
+}
+
+fn cancel() {
+    sys_write(STDOUT, &[CAN; 3]);
+}
+
+fn crc16(data: &[u8]) -> u16 {
+    data.iter().fold(0, |mut crc, &b| {
+        crc ^= (b as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                crc << 1 ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+        crc
+    })
+}
+
+fn block_ok(data: &[u8], check: &[u8], crc: bool) -> bool {
+    match crc {
+        true => crc16(data).to_be_bytes() == check,
+        false => data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == check[0],
+    }
+}
+
+fn receive() -> Result<Vec<u8>, RecvError> {
+    let mut input = Input::new();
+    let mut file = Vec::new();
+    let mut block = [0u8; 1024 + 4];
+
+    let mut crc = true;
+    let mut started = false;
+    let mut tries = 0;
+    let mut errors = 0;
+    let mut expected: u8 = 1;
+
+    loop {
+        if !started {
+            if tries == START_TRIES {
+                return Err(RecvError::NoSender);
+            }
+            crc = tries < CRC_TRIES;
+            send(if crc { CRC_MODE } else { NAK });
+            tries += 1;
+        }
+
+        let timeout = if started {
+            BYTE_TIMEOUT
+        } else {
+            START_TIMEOUT
+        };
+        let size = match input.byte(timeout) {
+            Some(SOH) => 128,
+            Some(STX) => 1024,
+            Some(EOT) => {
+                send(ACK);
+                break;
+            }
+            Some(CAN) => match input.byte(BYTE_TIMEOUT) {
+                Some(CAN) => return Err(RecvError::Cancelled),
+                _ => continue,
+            },
+            // line noise before the first block
+            Some(_) if !started => continue,
+            Some(_) | None => {
+                if started {
+                    errors += 1;
+                    if errors == MAX_ERRORS {
+                        cancel();
+                        return Err(RecvError::TooManyErrors);
+                    }
+                    input.purge();
+                    send(NAK);
+                }
+                continue;
+            }
+        };
+        started = true;
+
+        // block number, its complement, data, then the check
+        let block = &mut block[..2 + size + if crc { 2 } else { 1 }];
+        if !input.read(block)
+            || block[0] != !block[1]
+            || !block_ok(&block[2..2 + size], &block[2 + size..], crc)
+        {
+            errors += 1;
+            if errors == MAX_ERRORS {
+                cancel();
+                return Err(RecvError::TooManyErrors);
+            }
+            input.purge();
+            send(NAK);
+            continue;
+        }
+        errors = 0;
+
+        if block[0] == expected {
+            if file.len() + size > MAX_SIZE {
+                cancel();
+                return Err(RecvError::TooLarge);
+            }
+            file.extend_from_slice(&block[2..2 + size]);
+            expected = expected.wrapping_add(1);
+            send(ACK);
+        } else if block[0] == expected.wrapping_sub(1) {
+            // our ACK was lost, the sender sent the block again
+            send(ACK);
+        } else {
+            cancel();
+            return Err(RecvError::OutOfSync);
+        }
+    }
+
+    while file.last() == Some(&SUB) {
+        file.pop();
+    }
+    Ok(file)
+}
+
+fn report(file: &[u8]) {
+    println!("recv: {} bytes, sha256 {}", file.len(), Hex(&sha256(file)));
+
+    let mut unpacked = Vec::new();
+    let file = if is_gzip(file) {
+        match gunzip(file, &mut unpacked) {
+            Ok(_) => {
+                println!("recv: gzip, {} bytes unpacked", unpacked.len());
+                &unpacked[..]
+            }
+            Err(err) => {
+                println!("recv: gzip, but {}", err);
+                file
+            }
+        }
+    } else {
+        file
+    };
+
+    if let Ok(archive) = Archive::new(file) {
+        println!("recv: {:?} archive", archive.format());
+        for entry in archive.entries() {
+            match entry {
+                Ok(entry) => println!("  {:>8} {}", entry.size(), entry.name),
+                Err(err) => {
+                    println!("  {}", err);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn main(_args: &[&str]) -> isize {
+    println!("recv: send a file with XMODEM now, 128 or 1K blocks, CRC or checksum");
+    println!("recv: there is no filesystem yet, the file is dropped on exit");
+    flush_stdout();
+
+    if sys_fcntl(STDIN, F_SETRAW, 1).is_none() {
+        errln!("recv: failed to put the console in raw mode");
+        return 1;
+    }
+
+    let result = receive();
+    sys_fcntl(STDIN, F_SETRAW, 0);
+
+    match result {
+        Ok(file) => {
+            report(&file);
+            0
+        }
+        Err(err) => {
+            errln!("recv: {:?}", err);
+            1
+        }
+    }
+}
+
+entry!(main);
+allow_syscalls!(Read, Fcntl, ClockMonotonic);
@@ -0,0 +1,142 @@
+#![no_std]
+#![no_main]
+
+extern crate alloc;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+extern crate lib;
+use lib::sched::*;
+use lib::time::{self, Duration};
+use lib::*;
+
+const WIDTH: usize = 64;
+
+static GATE: Semaphore = Semaphore::new(0x5c4ed);
+
+/// Keep the cpu busy until preempted a few times
+fn busy() {
+    let mut sum = 0u64;
+    for i in 0..20_000_000u64 {
+        sum = core::hint::black_box(sum.wrapping_add(i));
+    }
+    core::hint::black_box(sum);
+}
+
+/// Give the cpu away again and again
+fn polite() {
+    for _ in 0..10 {
+        sys_yield();
+    }
+}
+
+/// Block on a semaphore the parent signals later
+fn blocked() {
+    GATE.wait();
+}
+
+fn main(_args: &[&str]) -> isize {
+    GATE.init(0).expect("failed to create semaphore");
+
+    let workers: [(&str, fn()); 3] = [("busy", busy), ("yield", polite), ("sem", blocked)];
+    let mut pids = [0u16; 3];
+
+    for (i, (_, work)) in workers.iter().enumerate() {
+        let pid = sys_fork();
+        if pid == 0 {
+            work();
+            exit(0);
+        }
+        pids[i] = pid;
+    }
+
+    time::sleep(Duration::from_millis(100));
+    GATE.signal();
+
+    for pid in pids {
+        sys_wait_pid(pid);
+    }
+
+    let mut histories = [[SchedEvent::default(); SCHED_HISTORY_LEN]; 3];
+    let mut counts = [0usize; 3];
+    for ((pid, history), count) in pids.iter().zip(&mut histories).zip(&mut counts) {
+        *count = sys_sched_stat(*pid, history).unwrap_or(0);
+    }
+
+    let events = || {
+        histories
+            .iter()
+            .zip(counts)
+            .flat_map(|(history, count)| history[..count].iter())
+    };
+
+    let start = events().map(|e| e.tick).min().unwrap_or(0);
+    let end = events().map(|e| e.tick).max().unwrap_or(0) + 1;
+    let per_column = (end - start).div_ceil(WIDTH as u64).max(1);
+
+    println!(
+        "Scheduling timeline, ticks {} to {}, {} per column",
+        start, end, per_column
+    );
+    println!("  # running   - ready   b blocked");
+    println!();
+
+    let rows = workers
+        .iter()
+        .zip(pids)
+        .zip(histories.iter().zip(counts))
+        .map(|(((name, _), pid), (history, count))| (*name, pid, &history[..count]))
+        .collect::<Vec<_>>();
+
+    for (name, pid, history) in rows.iter() {
+        let line = render(history, start, end, per_column);
+        println!("{:>6} #{:<3} |{}|", name, pid, line);
+    }
+
+    println!();
+    for (name, pid, history) in rows.iter() {
+        print!("{:>6} #{:<3} :", name, pid);
+        for kind in SCHED_ENQUEUE..=SCHED_EXIT {
+            let event = SchedEvent {
+                kind,
+                ..Default::default()
+            };
+            let count = history.iter().filter(|e| e.kind == kind).count();
+            print!(" {} {}", count, event.kind_name());
+        }
+        println!();
+    }
+
+    0
+}
+
+/// Draw the state of a process over time, one char per column
+fn render(history: &[SchedEvent], start: u64, end: u64, per_column: u64) -> String {
+    let columns = ((end - start).div_ceil(per_column)) as usize;
+    let mut line = vec![b' '; columns];
+
+    for (i, event) in history.iter().enumerate() {
+        let state = match event.kind {
+            SCHED_ENQUEUE | SCHED_PREEMPT | SCHED_YIELD => b'-',
+            SCHED_DISPATCH => b'#',
+            SCHED_BLOCK => b'b',
+            _ => b' ',
+        };
+
+        let until = history.get(i + 1).map_or(event.tick + 1, |next| next.tick);
+        let from = ((event.tick - start) / per_column) as usize;
+        let to = ((until.max(event.tick + 1) - start).div_ceil(per_column) as usize).min(columns);
+
+        for c in line[from..to].iter_mut() {
+            // a running mark wins when a column holds several states
+            if *c != b'#' {
+                *c = state;
+            }
+        }
+    }
+
+    String::from_utf8(line).unwrap_or_default()
+}
+
+entry!(main);
+allow_syscalls!(Sem, Sleep, Fork, SchedStat, WaitPid);
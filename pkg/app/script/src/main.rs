@@ -0,0 +1,142 @@
+#![no_std]
+#![no_main]
+
+use lib::fs::File;
+use lib::signal::SIGKILL;
+use lib::time::{self, Duration};
+use lib::ttyrec::Frame;
+use lib::vec::Vec;
+use lib::*;
+
+extern crate lib;
+
+const DEFAULT_FILE: &str = "/typescript";
+const SHELL: &str = "sh";
+
+const STDIN: u8 = 0;
+const STDOUT: u8 = 1;
+
+/// How long to wait for a key when the console has none
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+fn now_micros() -> u64 {
+    sys_time_nanos() as u64 / 1000
+}
+
+/// Run the shell on the slave of the pty, as its stdin, stdout and stderr
+fn run_shell(master: u8, slave: u8) -> ! {
+    sys_close(master);
+    for fd in 0..3 {
+        sys_dup2(slave, fd);
+    }
+    sys_close(slave);
+
+    let errno = sys_exec(SHELL, &[SHELL]);
+    errln!("script: failed to run {}: errno {}", SHELL, errno);
+    exit(1)
+}
+
+/// Pass what is typed on the console to the pty, until killed
+///
+/// reading the console does not block, it returns nothing yet.
+fn forward_input(master: u8) {
+    let mut buf = [0u8; 64];
+    loop {
+        match sys_read(STDIN, &mut buf) {
+            Some(0) | None => time::sleep(POLL_INTERVAL),
+            Some(count) => {
+                if !write_all(master, &buf[..count]) {
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Show and record what the shell writes, until every slave fd is closed
+///
+/// return the bytes recorded, `None` if writing the file failed.
+fn record(master: u8, file: &mut File) -> Option<usize> {
+    let mut buf = [0u8; 1024];
+    let mut frame = Vec::new();
+    let mut total = 0;
+
+    while let Some(count) = sys_read(master, &mut buf).filter(|&count| count > 0) {
+        let data = &buf[..count];
+        write_all(STDOUT, data);
+
+        frame.clear();
+        Frame {
+            time: now_micros(),
+            data,
+        }
+        .write_to(&mut frame);
+        if !file.write_all(&frame) {
+            return None;
+        }
+        total += count;
+    }
+
+    Some(total)
+}
+
+fn main(args: &[&str]) -> isize {
+    let path = args.get(1).copied().unwrap_or(DEFAULT_FILE);
+
+    let mut file = match File::create(path) {
+        Ok(file) => file,
+        Err(errno) => {
+            errln!("script: failed to create {}: errno {}", path, errno);
+            return 1;
+        }
+    };
+    let Some((master, slave)) = sys_open_pty() else {
+        errln!("script: failed to open a pty");
+        return 1;
+    };
+    // the shell edits and echoes lines itself, as on the console
+    sys_fcntl(master, F_SETRAW, 1);
+
+    println!("Script started, the session is recorded to {}", path);
+
+    let pid = match sys_try_fork() {
+        Ok(0) => run_shell(master, slave),
+        Ok(pid) => pid,
+        Err(errno) => {
+            errln!("script: failed to fork: errno {}", errno);
+            return 1;
+        }
+    };
+    // or the master would never see the shell exit
+    sys_close(slave);
+
+    let input = match sys_spawn_thread(move || forward_input(master)) {
+        Ok(input) => input,
+        Err(errno) => {
+            errln!("script: failed to start a thread: errno {}", errno);
+            let _ = sys_kill(pid, SIGKILL);
+            sys_wait_pid(pid);
+            return 1;
+        }
+    };
+
+    let recorded = record(master, &mut file);
+
+    let _ = sys_kill(input.tid(), SIGKILL);
+    let _ = input.join();
+    sys_wait_pid(pid);
+    sys_close(master);
+
+    match recorded {
+        Some(bytes) => {
+            println!("Script done, {} bytes recorded to {}", bytes, path);
+            0
+        }
+        None => {
+            errln!("script: failed to write {}", path);
+            1
+        }
+    }
+}
+
+entry!(main);
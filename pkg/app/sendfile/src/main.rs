@@ -0,0 +1,111 @@
+#![no_std]
+#![no_main]
+
+use lib::fs::{File, SeekFrom};
+use lib::*;
+
+extern crate lib;
+
+/// The disk the ESP is on, read through the cache of the ATA driver
+const DISK: &str = "/dev/disk0";
+const COPY: &str = "/tmp/sendfile.out";
+/// Bytes copied each time, four times what the disk caches, so a copy
+/// misses the cache after some of it is out already
+const LEN: usize = 512 * 1024;
+
+/// Copy `LEN` bytes of `disk` to `COPY`, from `offset` if there is one,
+/// in as many calls as it takes
+fn copy(disk: &File, mut offset: Option<&mut usize>) -> bool {
+    let Ok(out) = File::create(COPY) else {
+        return false;
+    };
+
+    let mut total = 0;
+    while total < LEN {
+        match sys_send_file(out.fd(), disk.fd(), offset.as_deref_mut(), LEN - total) {
+            Some(0) | None => return false,
+            Some(count) => total += count,
+        }
+    }
+    true
+}
+
+fn read_exact(file: &mut File, mut buf: &mut [u8]) -> bool {
+    while !buf.is_empty() {
+        match file.read(buf) {
+            Some(0) | None => return false,
+            Some(count) => buf = &mut buf[count..],
+        }
+    }
+    true
+}
+
+/// Whether `COPY` holds the bytes of `disk` from `start`, read plainly
+fn same(disk: &mut File, start: usize) -> bool {
+    let Ok(mut copy) = File::open(COPY) else {
+        return false;
+    };
+    if disk.seek(SeekFrom::Start(start)).is_err() {
+        return false;
+    }
+
+    let (mut expected, mut copied) = ([0u8; 4096], [0u8; 4096]);
+    (0..LEN / expected.len()).all(|_| {
+        read_exact(disk, &mut expected) && read_exact(&mut copy, &mut copied) && expected == copied
+    })
+}
+
+fn main(_args: &[&str]) -> isize {
+    let mut disk = match File::open(DISK) {
+        Ok(file) => file,
+        Err(errno) => {
+            errln!("sendfile: failed to open {}: errno {}", DISK, errno);
+            return 1;
+        }
+    };
+
+    // nothing of the disk is cached yet, the copy from its position
+    // moves the position past the bytes copied
+    let copied = copy(&disk, None);
+    let pos = disk.seek(SeekFrom::Current(0));
+    let matches = copied && same(&mut disk, 0);
+    println!(
+        "from the position: copied {}, position {:?}, {}",
+        copied,
+        pos,
+        if matches {
+            "same bytes"
+        } else {
+            "different bytes"
+        }
+    );
+    if !matches || pos != Ok(LEN) {
+        return 1;
+    }
+
+    // the copy from an offset moves the offset and leaves the position
+    let before = disk.seek(SeekFrom::Current(0));
+    let mut offset = LEN;
+    let copied = copy(&disk, Some(&mut offset));
+    let after = disk.seek(SeekFrom::Current(0));
+    let matches = copied && same(&mut disk, LEN);
+    println!(
+        "from an offset: copied {}, offset {}, position kept {}, {}",
+        copied,
+        offset,
+        before == after,
+        if matches {
+            "same bytes"
+        } else {
+            "different bytes"
+        }
+    );
+    if !matches || offset != 2 * LEN || before != after {
+        return 1;
+    }
+
+    0
+}
+
+entry!(main);
+allow_syscalls!(Open, SendFile, Seek, Read, Fstat, Close);
@@ -8,15 +8,50 @@ pub const fn help_text() -> &'static str {
 Usage:
     help        | show this help
     ps          | show process list
-    ls          | show app list
-    exec <name> | execute program
-    kill <pid>  | kill process
+    ls          | show apps with their descriptions
+    exec <name> [args]
+                | execute program with arguments
+    <name> [args] | <name> [args] ...
+                | execute programs, the output of each is the input of the next
+    record <name>
+                | execute program, recording its syscall results
+    replay <name>
+                | execute program with the results recorded last time
+    kill [-SIG] <pid>
+                | send a signal to a process, TERM by default
+    jobs        | show the programs stopped with Ctrl + Z or in the background
+    fg [pid]    | continue a job in the foreground, the last one by default
+    bg [pid]    | continue a stopped program without waiting for it
+    cat <file>  | print a file by its path, or one shared by the host
+    maps [pid]  | show the user mappings of a process
+    info [pid]  | show the state, cpu time and memory of a process
+    parts       | show the disks and their partitions, by type, GUID and size
+    mount [<image> <dir>]
+                | mount a FAT16 image file or disk on a directory, or show them
+    free        | show the memory and swap in use, and the pages swapped
+    swapon <device|file>
+                | swap to a disk, a partition or a file, overwriting it
+    sysctl [name [value]]
+                | show or set kernel tunables
+    ulimit -t [seconds]
+                | show or set the cpu time limit of programs run
+    umask [mode]
+                | show or set the file mode mask, in octal
+    renice <nice> [pid]
+                | set the niceness of a process, -20 to 19, the shell by default
+    prio [priority] [pid]
+                | show or set the scheduler priority of a process, 0 the highest
+    echo <words>
+                | print words, `$(name)` is replaced by the output of program
+    shutdown [code]
+                | power off, `xtask test` passes when code is 0
     clear       | clear screen
     exit        | exit shell
 
 Shortcuts:
     Ctrl + D    | exit shell
     Ctrl + C    | cancel current command
+    Ctrl + Z    | stop the running program, see `fg` and `bg`
 "#
     )
 }
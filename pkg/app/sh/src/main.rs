@@ -6,21 +6,32 @@ extern crate alloc;
 mod consts;
 mod services;
 
-use alloc::string::ToString;
+use alloc::string::{String, ToString};
 use alloc::vec::Vec;
 use lib::*;
 
 extern crate lib;
 
-fn main() -> usize {
+fn main(_args: &[&str]) -> usize {
     println!("            <<< Welcome to YatSenOS shell >>>            ");
     println!("                                 type `help` for help");
+    let mut jobs = Vec::new();
     loop {
+        services::reap_jobs(&mut jobs);
         print!("$ ");
-        let input = stdin().read_line();
-        let line: Vec<&str> = input.trim().split(' ').collect();
+        let Some(input) = stdin().try_read_line() else {
+            println!();
+            break;
+        };
+        if input.contains('|') {
+            let stages: Vec<&str> = input.split('|').collect();
+            jobs.extend(services::pipeline(&stages));
+            continue;
+        }
+        let words: Vec<String> = input.trim().split(' ').map(services::expand).collect();
+        let line: Vec<&str> = words.iter().map(String::as_str).collect();
         match line[0] {
-            "\x04" | "exit" => {
+            "exit" => {
                 println!();
                 break;
             }
@@ -28,27 +39,72 @@ fn main() -> usize {
             "ls" => sys_list_app(),
             "exec" => {
                 if line.len() < 2 {
-                    println!("Usage: exec <file>");
+                    println!("Usage: exec <file> [args]");
                     continue;
                 }
 
-                services::exec(line[1]);
+                jobs.extend(services::exec(&line[1..]));
             }
-            "kill" => {
+            "record" | "replay" => {
                 if line.len() < 2 {
-                    println!("Usage: kill <pid>");
+                    println!("Usage: {} <file>", line[0]);
                     continue;
                 }
-                let pid = line[1].to_string().parse::<u16>();
+
+                let mode = if line[0] == "record" {
+                    TRACE_RECORD
+                } else {
+                    TRACE_REPLAY
+                };
+                jobs.extend(services::exec_traced(line[1], mode));
+            }
+            "kill" => {
+                // kill [-SIG] <pid>
+                let (sig, pid) = match &line[1..] {
+                    [pid] => (None, pid),
+                    [sig, pid] if sig.starts_with('-') => (Some(&sig[1..]), pid),
+                    _ => {
+                        println!("Usage: kill [-SIG] <pid>");
+                        continue;
+                    }
+                };
+                let pid = pid.to_string().parse::<u16>();
 
                 if pid.is_err() {
                     errln!("Cannot parse pid");
                     continue;
                 }
 
-                services::kill(pid.unwrap());
+                services::kill(pid.unwrap(), sig);
+            }
+            "jobs" => services::jobs(&jobs),
+            "fg" => services::fg(&mut jobs, line.get(1).copied()),
+            "bg" => services::bg(&mut jobs, line.get(1).copied()),
+            "cat" => {
+                if line.len() < 2 {
+                    println!("Usage: cat <file>");
+                    continue;
+                }
+
+                services::cat(line[1]);
+            }
+            "maps" => services::maps(line.get(1).copied()),
+            "info" => services::info(line.get(1).copied()),
+            "parts" => services::parts(),
+            "mount" => services::mount(&line[1..]),
+            "free" => services::free(),
+            "swapon" => services::swap_on(&line[1..]),
+            "sysctl" => services::sysctl(&line[1..]),
+            "ulimit" => services::ulimit(&line[1..]),
+            "umask" => services::umask(line.get(1).copied()),
+            "renice" => services::renice(&line[1..]),
+            "prio" => services::prio(&line[1..]),
+            "shutdown" => services::shutdown(line.get(1).copied()),
+            "echo" => println!("{}", line[1..].join(" ")),
+            "help" => {
+                print!("{}", consts::help_text());
+                sys_list_app();
             }
-            "help" => print!("{}", consts::help_text()),
             "clear" => print!("\x1b[1;1H\x1b[2J"),
             _ => {
                 if line[0].is_empty() {
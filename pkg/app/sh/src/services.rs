@@ -1,25 +1,657 @@
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lib::args::{self, Arg};
+use lib::sched::{
+    EXIT_CPU_LIMIT, NICE_MAX, NICE_MIN, PRIO_KEEP, PRIO_LEVELS, PROC_BLOCKED, PROC_DEAD,
+    PROC_READY, PROC_RUNNING, PROC_STOPPED, RLIMIT_CPU,
+};
+use lib::signal::*;
+use lib::time::Instant;
 use lib::*;
 
-pub fn exec(name: &str) {
-    let start = sys_time();
+/// Cpu time limit in seconds for every program run, 0 means unlimited
+static CPU_LIMIT: AtomicUsize = AtomicUsize::new(0);
 
-    let pid = sys_spawn(name.to_ascii_lowercase().as_str());
+/// A program stopped with Ctrl+Z, continued by `fg` or `bg`
+pub struct Job {
+    pub pid: u16,
+    pub name: String,
+    /// continued by `bg`, it is reported when it exits
+    pub running: bool,
+}
+
+/// Run the app named by the first of `args`, passing it all of them
+pub fn exec(args: &[&str]) -> Option<Job> {
+    let start = Instant::now();
+
+    let name = args[0].to_ascii_lowercase();
+    let pid = sys_spawn_args(&name, args, env::vars());
+
+    wait(args[0], pid, start)
+}
+
+/// Run `a | b | c`, what each writes to stdout the next one reads
+///
+/// each stage is forked, gets the ends of the pipes next to it as stdin
+/// and stdout and execs its app. The shell closes its copies as it goes,
+/// so each stage reads to the end of the input once the one before exits.
+/// The last is waited for in the foreground, then the others.
+pub fn pipeline(stages: &[&str]) -> Option<Job> {
+    let stages: Vec<Vec<String>> = stages
+        .iter()
+        .map(|stage| stage.split_whitespace().map(expand).collect())
+        .collect();
+    if stages.len() < 2 || stages.iter().any(Vec::is_empty) {
+        errln!("Usage: <name> [args] | <name> [args] ...");
+        return None;
+    }
+    let stages: Vec<Vec<&str>> = stages
+        .iter()
+        .map(|stage| stage.iter().map(String::as_str).collect())
+        .collect();
+
+    let start = Instant::now();
+    let mut pids = Vec::new();
+    // the read end of the pipe from the stage before
+    let mut stdin = None;
+    for (i, args) in stages.iter().enumerate() {
+        let pipe = if i + 1 < stages.len() {
+            let Some(pipe) = sys_pipe() else {
+                errln!("failed to create a pipe");
+                break;
+            };
+            Some(pipe)
+        } else {
+            None
+        };
+
+        let mut open: Vec<u8> = stdin.into_iter().collect();
+        if let Some((read, write)) = pipe {
+            open.extend([read, write]);
+        }
+        pids.push(fork_piped(args, stdin, pipe.map(|pipe| pipe.1), &open));
+
+        if let Some(fd) = stdin {
+            sys_close(fd);
+        }
+        if let Some(pipe) = pipe {
+            sys_close(pipe.1);
+        }
+        stdin = pipe.map(|pipe| pipe.0);
+    }
+    // left open when a pipe could not be created
+    if let Some(fd) = stdin {
+        sys_close(fd);
+    }
+
+    for &pid in pids.iter().flatten() {
+        apply_limits(pid);
+    }
+    let last = if pids.len() == stages.len() {
+        pids.pop().flatten()
+    } else {
+        None
+    };
+    let job = last.and_then(|pid| wait_foreground(stages[stages.len() - 1][0], pid, start));
+
+    // a stopped last stage leaves the others to `reap_jobs`
+    if job.is_none() {
+        for pid in pids.into_iter().flatten() {
+            sys_wait_pid(pid);
+        }
+    }
+    job
+}
+
+/// Fork a child that makes `stdin` and `stdout`, ends of pipes, its fd 0
+/// and 1, closes the pipe ends in `open` and execs the app named by the
+/// first of `args`
+fn fork_piped(args: &[&str], stdin: Option<u8>, stdout: Option<u8>, open: &[u8]) -> Option<u16> {
+    match sys_try_fork() {
+        Ok(0) => {
+            if let Some(fd) = stdin {
+                sys_dup2(fd, 0);
+            }
+            if let Some(fd) = stdout {
+                sys_dup2(fd, 1);
+            }
+            for &fd in open {
+                sys_close(fd);
+            }
+
+            let errno = sys_exec(&args[0].to_ascii_lowercase(), args);
+            errln!("failed to spawn process: {}: errno {}", args[0], errno);
+            sys_exit(127);
+        }
+        Ok(pid) => Some(pid),
+        Err(errno) => {
+            errln!("failed to fork for {}: errno {}", args[0], errno);
+            None
+        }
+    }
+}
+
+/// Run an app with its syscalls recorded or replayed
+pub fn exec_traced(name: &str, mode: usize) -> Option<Job> {
+    let start = Instant::now();
+
+    let pid = sys_spawn_traced(name.to_ascii_lowercase().as_str(), mode);
+
+    wait(name, pid, start)
+}
+
+/// Run an app and collect what it writes to stdout
+pub fn capture(name: &str) -> Option<String> {
+    let fd = sys_memfd()?;
+    let pid = sys_spawn_with_stdout(name.to_ascii_lowercase().as_str(), fd);
+    if pid == 0 {
+        sys_close(fd);
+        return None;
+    }
+
+    apply_limits(pid);
+    sys_wait_pid(pid);
+
+    let mut output = Vec::new();
+    let mut buf = [0u8; 256];
+    while let Some(count) = sys_read(fd, &mut buf).filter(|&count| count > 0) {
+        output.extend_from_slice(&buf[..count]);
+    }
+    sys_close(fd);
+
+    Some(String::from_utf8_lossy(&output).into_owned())
+}
 
+/// Replace a `$(name)` word with the output of app `name`
+pub fn expand(word: &str) -> String {
+    match word.strip_prefix("$(").and_then(|w| w.strip_suffix(')')) {
+        Some(name) => match capture(name) {
+            Some(output) => output.trim_end().to_string(),
+            None => {
+                errln!("failed to spawn process: {}", name);
+                String::new()
+            }
+        },
+        None => word.to_string(),
+    }
+}
+
+fn wait(name: &str, pid: u16, start: Instant) -> Option<Job> {
     if pid == 0 {
         errln!("failed to spawn process: {}", name);
-        return;
+        return None;
     }
 
-    let ret = sys_wait_pid(pid);
-    let time = sys_time() - start;
+    apply_limits(pid);
+    wait_foreground(name, pid, start)
+}
+
+/// Wait for a program in the foreground, one stopped by Ctrl+Z becomes a job
+fn wait_foreground(name: &str, pid: u16, start: Instant) -> Option<Job> {
+    let ret = match sys_wait_pid_untraced(pid) {
+        WaitStatus::Exited(ret) => ret,
+        WaitStatus::Stopped => {
+            println!("\n[+] process #{} stopped, `fg {}` to continue", pid, pid);
+            return Some(Job {
+                pid,
+                name: name.to_string(),
+                running: false,
+            });
+        }
+    };
+    let time = start.elapsed();
 
     println!(
         "[+] process exited with code {} @ {}s",
         ret,
-        time.num_seconds()
+        time.as_secs()
     );
+    if ret == EXIT_CPU_LIMIT as isize {
+        println!("[!] killed for exceeding its cpu time limit");
+    } else if ret == exit_code_of(SIGSEGV) as isize {
+        println!("[!] killed for a segmentation fault");
+    }
+    None
 }
 
-pub fn kill(pid: u16) {
-    sys_kill(pid);
+/// List the stopped jobs and the ones in the background
+pub fn jobs(jobs: &[Job]) {
+    for job in jobs {
+        let state = if job.running { "running" } else { "stopped" };
+        println!("  #{:<5} {:<8} {}", job.pid, state, job.name);
+    }
+}
+
+/// Report the jobs that have exited since the last prompt, and take them
+/// off the list
+///
+/// any other child that exited is reaped too, the shell waits for every
+/// program it runs in the foreground.
+pub fn reap_jobs(jobs: &mut Vec<Job>) {
+    while let Ok(Some((pid, ret))) = sys_try_wait_any() {
+        let Some(index) = jobs.iter().position(|job| job.pid == pid) else {
+            continue;
+        };
+        let job = jobs.remove(index);
+        println!(
+            "[+] process #{} ({}) exited with code {}",
+            pid, job.name, ret
+        );
+    }
+}
+
+/// Continue a stopped job in the foreground, the last one by default
+pub fn fg(jobs: &mut Vec<Job>, pid: Option<&str>) {
+    let job = match take_job(jobs, pid) {
+        Some(job) => job,
+        None => return,
+    };
+
+    if !sys_resume(job.pid) {
+        errln!("fg: process #{} has exited", job.pid);
+        return;
+    }
+
+    jobs.extend(wait_foreground(&job.name, job.pid, Instant::now()));
+}
+
+/// Continue a stopped job without waiting for it, the last one by default
+pub fn bg(jobs: &mut Vec<Job>, pid: Option<&str>) {
+    let mut job = match take_job(jobs, pid) {
+        Some(job) => job,
+        None => return,
+    };
+
+    if sys_resume(job.pid) {
+        println!("[+] process #{} continued in the background", job.pid);
+        job.running = true;
+        jobs.push(job);
+    } else {
+        errln!("bg: process #{} has exited", job.pid);
+    }
+}
+
+fn take_job(jobs: &mut Vec<Job>, pid: Option<&str>) -> Option<Job> {
+    let index = match pid.map(str::parse::<u16>) {
+        None => jobs.len().checked_sub(1),
+        Some(Ok(pid)) => jobs.iter().position(|job| job.pid == pid),
+        Some(Err(_)) => {
+            errln!("Cannot parse pid");
+            return None;
+        }
+    };
+
+    match index {
+        Some(index) => Some(jobs.remove(index)),
+        None => {
+            errln!("no such job");
+            None
+        }
+    }
+}
+
+/// Limit a program just spawned, the time it used so far counts too
+fn apply_limits(pid: u16) {
+    let limit = CPU_LIMIT.load(Ordering::Relaxed);
+    if limit != 0 && sys_prlimit(pid, RLIMIT_CPU, limit).is_none() {
+        errln!("failed to limit the cpu time of #{}", pid);
+    }
+}
+
+/// Show or set the limits of the programs run, like `ulimit`
+pub fn ulimit(words: &[&str]) {
+    let mut parser = args::Parser::new(words);
+    let mut value = None;
+
+    while let Some(arg) = parser.next() {
+        match arg {
+            Arg::Short('t') => value = parser.value(),
+            other => {
+                args::unexpected("ulimit", other);
+                args::print_usage(
+                    "ulimit",
+                    "-t [seconds]",
+                    &[("-t", "cpu time, 0 for unlimited")],
+                );
+                return;
+            }
+        }
+    }
+
+    match value.map(str::parse::<usize>) {
+        None => match CPU_LIMIT.load(Ordering::Relaxed) {
+            0 => println!("cpu time: unlimited"),
+            secs => println!("cpu time: {}s", secs),
+        },
+        Some(Ok(secs)) => CPU_LIMIT.store(secs, Ordering::Relaxed),
+        Some(Err(_)) => errln!("cannot parse seconds"),
+    }
+}
+
+/// Show or set the file mode creation mask, in octal
+pub fn umask(mask: Option<&str>) {
+    match mask.map(|mask| u16::from_str_radix(mask, 8)) {
+        None => println!("{:04o}", sys_umask(None)),
+        Some(Ok(mask)) if mask <= 0o777 => {
+            sys_umask(Some(mask));
+        }
+        Some(_) => errln!("umask: invalid mode, expected octal 0-777"),
+    }
+}
+
+/// Set the niceness of a process, the shell itself by default
+///
+/// only the shell and its children may be reniced, the programs run later
+/// inherit the niceness of the shell
+pub fn renice(args: &[&str]) {
+    let nice = match args.first().map(|nice| nice.parse::<isize>()) {
+        Some(Ok(nice)) => nice,
+        _ => {
+            println!("Usage: renice <nice> [pid]");
+            return;
+        }
+    };
+    let pid = match args.get(1).map(|pid| pid.parse::<u16>()) {
+        None => sys_get_pid(),
+        Some(Ok(pid)) => pid,
+        Some(Err(_)) => {
+            errln!("Cannot parse pid");
+            return;
+        }
+    };
+
+    match sys_renice(pid, nice) {
+        Ok(old) => {
+            let new = nice.clamp(NICE_MIN, NICE_MAX);
+            println!("#{}: old niceness {}, new niceness {}", pid, old, new);
+        }
+        Err(errno::EPERM) => errln!("renice: #{}: permission denied", pid),
+        Err(_) => errln!("renice: #{}: no such process", pid),
+    }
+}
+
+/// Show or set the scheduler priority of a process, the shell by default
+///
+/// the programs run later inherit the priority of the shell
+pub fn prio(args: &[&str]) {
+    let priority = match args.first().map(|prio| prio.parse::<usize>()) {
+        None => PRIO_KEEP,
+        Some(Ok(priority)) if priority < PRIO_LEVELS => priority,
+        _ => {
+            println!("Usage: prio [0-{}] [pid]", PRIO_LEVELS - 1);
+            return;
+        }
+    };
+    let pid = match args.get(1).map(|pid| pid.parse::<u16>()) {
+        None => sys_get_pid(),
+        Some(Ok(pid)) => pid,
+        Some(Err(_)) => {
+            errln!("Cannot parse pid");
+            return;
+        }
+    };
+
+    match sys_set_priority(pid, priority) {
+        Ok(old) if priority == PRIO_KEEP => println!("#{}: priority {}", pid, old),
+        Ok(old) => println!("#{}: old priority {}, new priority {}", pid, old, priority),
+        Err(errno::EPERM) => errln!("prio: #{}: permission denied", pid),
+        Err(_) => errln!("prio: #{}: no such process", pid),
+    }
+}
+
+/// Print a file by its path, or one the host shares, see `xtask --share`
+pub fn cat(name: &str) {
+    let fd = if name.starts_with('/') {
+        match sys_open(name, O_RDONLY) {
+            Ok(fd) => fd,
+            Err(_) => {
+                errln!("cat: {}: cannot open", name);
+                return;
+            }
+        }
+    } else {
+        match sys_host_open(name) {
+            Some(fd) => fd,
+            None => {
+                errln!("cat: {}: not shared by the host", name);
+                return;
+            }
+        }
+    };
+
+    let mut buf = [0u8; 256];
+    while let Some(count) = sys_read(fd, &mut buf).filter(|&count| count > 0) {
+        sys_write(1, &buf[..count]);
+    }
+    sys_close(fd);
+}
+
+/// Power off, `code` is the result reported to `xtask test`
+pub fn shutdown(code: Option<&str>) {
+    match code.map(|code| code.parse::<isize>()) {
+        None => sys_shutdown(0),
+        Some(Ok(code)) => sys_shutdown(code),
+        Some(Err(_)) => errln!("shutdown: invalid code"),
+    }
+}
+
+/// Send `sig` to `pid`, `SIGTERM` by default, as a number or a name like `TERM`
+pub fn kill(pid: u16, sig: Option<&str>) {
+    let sig = match sig.map(signal_number) {
+        None => SIGTERM,
+        Some(Some(sig)) => sig,
+        Some(None) => {
+            errln!("kill: unknown signal");
+            return;
+        }
+    };
+
+    if sys_kill(pid, sig).is_err() {
+        errln!("kill: no process #{}", pid);
+    }
+}
+
+fn signal_number(name: &str) -> Option<usize> {
+    let name = name.trim_start_matches("SIG");
+    let sig = match name {
+        "INT" => SIGINT,
+        "KILL" => SIGKILL,
+        "SEGV" => SIGSEGV,
+        "TERM" => SIGTERM,
+        "CHLD" => SIGCHLD,
+        "CONT" => SIGCONT,
+        "STOP" => SIGSTOP,
+        _ => name.parse().ok()?,
+    };
+    is_signal(sig).then_some(sig)
+}
+
+/// Print the disks and their partitions, as `/dev/partitions` lists them
+pub fn parts() {
+    println!(
+        "{:<10} {:>10} {:>10} {:>10} | type | guid | label",
+        "name", "start", "blocks", "size"
+    );
+    cat("/dev/partitions");
+}
+
+/// Mount the FAT16 image in a file, or on a disk, on a directory,
+/// or print the mount points without arguments
+pub fn mount(args: &[&str]) {
+    match args {
+        [] => cat("/dev/mounts"),
+        [image, dir] => match sys_mount(image, dir) {
+            Ok(n) => println!("{} on {} through /dev/loop{}", image, dir, n),
+            Err(errno::EPERM) => errln!("mount: permission denied"),
+            Err(errno::EEXIST) => errln!("mount: {}: already mounted", dir),
+            Err(errno::EINVAL) => errln!("mount: {}: not a FAT16 image", image),
+            Err(_) => errln!("mount: {}: cannot mount", image),
+        },
+        _ => println!("Usage: mount [<image> <dir>]"),
+    }
+}
+
+/// Print the memory and swap in use, in KiB
+pub fn free() {
+    let info = sys_sysinfo();
+    let kib = |bytes: u64| bytes / 1024;
+
+    println!("{:<6} {:>10} {:>10} {:>10}", "", "total", "used", "free");
+    println!(
+        "{:<6} {:>10} {:>10} {:>10}",
+        "Mem:",
+        kib(info.total_ram),
+        kib(info.total_ram - info.free_ram),
+        kib(info.free_ram)
+    );
+    println!(
+        "{:<6} {:>10} {:>10} {:>10}",
+        "Swap:",
+        kib(info.total_swap),
+        kib(info.total_swap - info.free_swap),
+        kib(info.free_swap)
+    );
+    println!(
+        "{} KiB shared, {} pages swapped out, {} swapped in",
+        kib(info.shared_ram),
+        info.swap_outs,
+        info.swap_ins
+    );
+}
+
+/// Swap to a disk, a partition or a file
+pub fn swap_on(args: &[&str]) {
+    let [path] = args else {
+        println!("Usage: swapon <device|file>");
+        return;
+    };
+
+    match sys_swap_on(path) {
+        Ok(()) => println!("swapping to {}", path),
+        Err(errno::EPERM) => errln!("swapon: permission denied"),
+        Err(errno::EBUSY) => errln!("swapon: swap is on already"),
+        Err(errno::EINVAL) => errln!("swapon: {}: too small", path),
+        Err(_) => errln!("swapon: {}: cannot swap to it", path),
+    }
+}
+
+/// Print the user mappings of a process, the shell itself by default
+pub fn maps(pid: Option<&str>) {
+    let pid = match pid.map(|pid| pid.parse::<u16>()) {
+        None => sys_get_pid(),
+        Some(Ok(pid)) => pid,
+        Some(Err(_)) => {
+            errln!("Cannot parse pid");
+            return;
+        }
+    };
+
+    let mut entries = vec![MapEntry::default(); 64];
+    let count = match sys_maps(pid, &mut entries) {
+        Ok(count) => count,
+        Err(errno::EPERM) => {
+            errln!("maps: permission denied");
+            return;
+        }
+        Err(_) => {
+            errln!("cannot read the mappings of #{}", pid);
+            return;
+        }
+    };
+
+    for m in entries.iter().take(count) {
+        let flag = |bit, ch| if m.flags & bit != 0 { ch } else { '-' };
+        let exec = if m.flags & PAGE_NO_EXECUTE != 0 {
+            '-'
+        } else {
+            'x'
+        };
+        println!(
+            "{:012x}-{:012x} r{}{}{} {:012x} {:>4}K",
+            m.start,
+            m.end,
+            flag(PAGE_WRITABLE, 'w'),
+            exec,
+            flag(PAGE_USER, 'u'),
+            m.phys,
+            m.page_size / 1024
+        );
+    }
+
+    if count > entries.len() {
+        println!("... {} more", count - entries.len());
+    }
+}
+
+/// Print the state and usage of a process, the shell itself by default
+pub fn info(pid: Option<&str>) {
+    let pid = match pid.map(|pid| pid.parse::<u16>()) {
+        None => 0,
+        Some(Ok(pid)) => pid,
+        Some(Err(_)) => {
+            errln!("Cannot parse pid");
+            return;
+        }
+    };
+
+    let Some(info) = sys_proc_info(pid) else {
+        errln!("no such process #{}", pid);
+        return;
+    };
+
+    let state = match info.state {
+        PROC_RUNNING => "running",
+        PROC_READY => "ready",
+        PROC_BLOCKED => "blocked",
+        PROC_STOPPED => "stopped",
+        PROC_DEAD => "exited",
+        _ => "unknown",
+    };
+    let secs = |nanos: u64| nanos as f64 / 1e9;
+
+    println!("#{} {}, parent #{}, {}", info.pid, info.name(), info.ppid, state);
+    println!(
+        "cpu    : {:.3}s user, {:.3}s kernel, {} + {} ticks",
+        secs(info.user_time),
+        secs(info.kernel_time),
+        info.user_ticks,
+        info.kernel_ticks
+    );
+    println!("memory : {} KiB", info.memory / 1024);
+}
+
+pub fn sysctl(args: &[&str]) {
+    match args {
+        [] => {
+            let mut buf = vec![0u8; 1024];
+            // once more if the list did not fit
+            let mut len = sys_sysctl_list(&mut buf).unwrap_or(0);
+            if len > buf.len() {
+                buf.resize(len, 0);
+                len = sys_sysctl_list(&mut buf).unwrap_or(0).min(buf.len());
+            }
+            print!("{}", String::from_utf8_lossy(&buf[..len]));
+        }
+        [name] => match sys_sysctl_get(name) {
+            Some(value) => println!("{} = {}", name, value),
+            None => errln!("unknown tunable: {}", name),
+        },
+        [name, value, ..] => {
+            let value = match value.parse::<usize>() {
+                Ok(value) => value,
+                Err(_) => {
+                    errln!("Cannot parse value");
+                    return;
+                }
+            };
+
+            match sys_sysctl_set(name, value) {
+                Ok(old) => println!("{} = {} (was {})", name, value, old),
+                Err(errno::EPERM) => errln!("sysctl: permission denied"),
+                Err(_) => errln!("unknown tunable: {}", name),
+            }
+        }
+    }
 }
@@ -0,0 +1,28 @@
+#![no_std]
+#![no_main]
+
+use lib::hash::{crc32, sha256, Hex};
+use lib::*;
+
+extern crate lib;
+
+fn main(_args: &[&str]) -> isize {
+    // there are no files to read yet, so hash what is typed,
+    // each line without its newline, until an empty line
+    println!("Type lines to hash, an empty line to quit.");
+
+    loop {
+        let line = stdin().read_line();
+        if line.is_empty() {
+            break;
+        }
+
+        let data = line.as_bytes();
+        println!("{}  {:08x}  -", Hex(&sha256(data)), crc32(data));
+    }
+
+    0
+}
+
+entry!(main);
+allow_syscalls!(Read, Fstat);
@@ -0,0 +1,39 @@
+#![no_std]
+#![no_main]
+
+use lib::vec::Vec;
+use lib::time::Instant;
+use lib::*;
+
+extern crate lib;
+
+fn main(_args: &[&str]) -> isize {
+    // sort what comes down a pipe to its end, or what is typed until
+    // an empty line, every line is kept on the heap until then
+    if stdin().is_console() {
+        errln!("Type lines to sort, an empty line to end.");
+    }
+
+    let start = Instant::now();
+    let mut lines: Vec<_> = stdin().lines().collect();
+
+    lines.sort_unstable();
+
+    let mut out = BufWriter::new(1);
+    for line in lines.iter() {
+        out.write(line.as_bytes());
+        out.write(b"\n");
+    }
+    out.flush();
+
+    errln!(
+        "sort: {} lines in {} us",
+        lines.len(),
+        start.elapsed().as_micros()
+    );
+
+    0
+}
+
+entry!(main);
+allow_syscalls!(Read, Fstat, ClockMonotonic);
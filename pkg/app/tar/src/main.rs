@@ -0,0 +1,109 @@
+#![no_std]
+#![no_main]
+
+use lib::fs::archive::{Archive, Entry, EntryKind};
+use lib::string::String;
+use lib::*;
+
+extern crate lib;
+
+/// Apps take no arguments and there is no filesystem yet,
+/// so the archives to read are built in
+const ARCHIVES: [(&str, &[u8]); 2] = [
+    ("demo.tar", include_bytes!("../fixtures/demo.tar")),
+    ("demo.cpio", include_bytes!("../fixtures/demo.cpio")),
+];
+
+fn kind_char(kind: EntryKind) -> char {
+    match kind {
+        EntryKind::File => '-',
+        EntryKind::Dir => 'd',
+        EntryKind::Symlink => 'l',
+        EntryKind::Other(_) => '?',
+    }
+}
+
+fn mode_string(mode: u32) -> String {
+    let mut s = String::with_capacity(9);
+    for shift in [6, 3, 0] {
+        let bits = mode >> shift;
+        s.push(if bits & 4 != 0 { 'r' } else { '-' });
+        s.push(if bits & 2 != 0 { 'w' } else { '-' });
+        s.push(if bits & 1 != 0 { 'x' } else { '-' });
+    }
+    s
+}
+
+/// One line like `tar -tv`
+fn list(entry: &Entry) {
+    let mtime = DateTime::from_timestamp(entry.mtime as i64, 0).unwrap_or_default();
+    print!(
+        "{}{} {}/{} {:>6} {} {}",
+        kind_char(entry.kind),
+        mode_string(entry.mode),
+        entry.uid,
+        entry.gid,
+        entry.size(),
+        mtime.naive_utc(),
+        entry.name
+    );
+    match entry.kind {
+        EntryKind::Symlink => println!(" -> {}", entry.link),
+        _ => println!(),
+    }
+}
+
+/// Write the regular files to stdout, like `tar -xO`
+fn extract(entry: &Entry) {
+    if entry.kind != EntryKind::File {
+        return;
+    }
+
+    println!("==> {} <==", entry.name);
+    match core::str::from_utf8(entry.data) {
+        Ok(text) => print!("{}", text),
+        Err(_) => println!("({} bytes of binary data)", entry.size()),
+    }
+}
+
+fn run(name: &str, data: &[u8]) -> bool {
+    let archive = match Archive::new(data) {
+        Ok(archive) => archive,
+        Err(err) => {
+            errln!("tar: {}: {}", name, err);
+            return false;
+        }
+    };
+
+    println!("{} ({:?}):", name, archive.format());
+    for pass in [list, extract] {
+        for entry in archive.entries() {
+            match entry {
+                Ok(entry) => pass(&entry),
+                Err(err) => {
+                    errln!("tar: {}: {}", name, err);
+                    return false;
+                }
+            }
+        }
+    }
+    println!();
+
+    true
+}
+
+fn main(_args: &[&str]) -> isize {
+    let mut ok = true;
+    for (name, data) in ARCHIVES {
+        ok &= run(name, data);
+    }
+
+    if ok {
+        0
+    } else {
+        1
+    }
+}
+
+entry!(main);
+allow_syscalls!();
@@ -0,0 +1,105 @@
+#![no_std]
+#![no_main]
+
+use lib::gfx::term::Terminal;
+use lib::gfx::text::{Font, DEFAULT_FONT};
+use lib::gfx::{Canvas, Color, FrameBuffer};
+use lib::*;
+
+extern crate lib;
+
+const SHELL: &str = "sh";
+
+const STDIN: u8 = 0;
+
+/// Run the shell on the slave of the pty, as its stdin, stdout and stderr
+fn run_shell(master: u8, slave: u8) -> ! {
+    sys_close(master);
+    for fd in 0..3 {
+        sys_dup2(slave, fd);
+    }
+    sys_close(slave);
+
+    let errno = sys_exec(SHELL, &[SHELL]);
+    errln!("term: failed to run {}: errno {}", SHELL, errno);
+    exit(1)
+}
+
+/// Pass what is typed on the console to the pty and draw what the shell
+/// writes, until every slave fd is closed
+fn run(master: u8, term: &mut Terminal, fb: &mut FrameBuffer) {
+    let mut fds = [PollFd::new(STDIN, POLLIN), PollFd::new(master, POLLIN)];
+    let mut buf = [0u8; 1024];
+
+    while sys_poll(&mut fds, None).is_ok() {
+        if fds[0].revents & POLLIN != 0 {
+            // the console may have nothing yet, even when polled ready
+            if let Some(count) = sys_read(STDIN, &mut buf).filter(|&count| count > 0) {
+                if !write_all(master, &buf[..count]) {
+                    return;
+                }
+            }
+        }
+
+        if fds[1].revents & POLLIN != 0 {
+            match sys_read(master, &mut buf) {
+                Some(count) if count > 0 => {
+                    term.write(&buf[..count]);
+                    term.surface().blit(fb, 0, 0);
+                }
+                _ => return,
+            }
+        }
+    }
+}
+
+fn main(_args: &[&str]) -> isize {
+    let font = match Font::load(DEFAULT_FONT) {
+        Ok(font) => font,
+        Err(errno) => {
+            errln!("term: failed to load {}: errno {}", DEFAULT_FONT, errno);
+            return 1;
+        }
+    };
+    let mut fb = match FrameBuffer::map() {
+        Ok(fb) => fb,
+        Err(errno) => {
+            errln!("term: failed to take the framebuffer: errno {}", errno);
+            return 1;
+        }
+    };
+
+    let (cols, rows) = (fb.width() / font.width, fb.height() / font.height);
+    let mut term = Terminal::new(font, cols, rows);
+
+    let Some((master, slave)) = sys_open_pty() else {
+        errln!("term: failed to open a pty");
+        return 1;
+    };
+    // the shell edits and echoes lines itself, as on the console
+    sys_fcntl(master, F_SETRAW, 1);
+    sys_fcntl(master, F_SETWINSZ, cols | rows << 16);
+
+    let pid = match sys_try_fork() {
+        Ok(0) => run_shell(master, slave),
+        Ok(pid) => pid,
+        Err(errno) => {
+            errln!("term: failed to fork: errno {}", errno);
+            return 1;
+        }
+    };
+    // or the master would never see the shell exit
+    sys_close(slave);
+
+    // the cells may leave a margin at the right and bottom
+    fb.clear(Color::BLACK);
+    term.surface().blit(&mut fb, 0, 0);
+
+    run(master, &mut term, &mut fb);
+
+    sys_wait_pid(pid);
+    sys_close(master);
+    0
+}
+
+entry!(main);
@@ -0,0 +1,44 @@
+#![no_std]
+#![no_main]
+
+extern crate lib;
+use lib::time::Instant;
+use lib::*;
+
+const STDOUT: u8 = 1;
+const RATE: usize = 256;
+const LINES: usize = 16;
+
+fn main(_args: &[&str]) -> isize {
+    let before = sys_fstat(STDOUT).unwrap_or_default();
+
+    if sys_fcntl(STDOUT, F_SETRATE, RATE).is_none() {
+        errln!("Failed to limit stdout.");
+        return 1;
+    }
+
+    let start = Instant::now();
+    for i in 0..LINES {
+        write_all(STDOUT, b"throttled output, line ");
+        write_all(STDOUT, &[b'0' + (i / 10) as u8, b'0' + (i % 10) as u8, b'\n']);
+    }
+    let elapsed = start.elapsed();
+
+    let after = sys_fstat(STDOUT).unwrap_or_default();
+    sys_fcntl(STDOUT, F_SETRATE, 0);
+
+    println!();
+    println!("limit     : {} bytes/s", after.rate);
+    println!("elapsed   : {} ms", elapsed.as_millis());
+    println!(
+        "written   : {} bytes in {} ops",
+        after.write_bytes - before.write_bytes,
+        after.write_ops - before.write_ops
+    );
+    println!("throttled : {} ops", after.throttled - before.throttled);
+
+    0
+}
+
+entry!(main);
+allow_syscalls!(Fcntl, Fstat, ClockMonotonic);
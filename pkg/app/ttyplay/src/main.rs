@@ -0,0 +1,60 @@
+#![no_std]
+#![no_main]
+
+use lib::fs::File;
+use lib::time::{self, Duration};
+use lib::ttyrec::Frames;
+use lib::*;
+
+extern crate lib;
+
+const STDOUT: u8 = 1;
+
+/// Longer pauses are cut to it, the recording mostly waited for typing then
+const MAX_PAUSE_MICROS: u64 = 3_000_000;
+
+const USAGE: &str = "Usage: ttyplay [-s speed] <file>";
+
+fn main(args: &[&str]) -> isize {
+    let (speed, path) = match args {
+        [_, path] => (Some(1), *path),
+        [_, "-s", speed, path] => (speed.parse::<u64>().ok().filter(|&s| s > 0), *path),
+        _ => (None, ""),
+    };
+    let Some(speed) = speed else {
+        errln!("{}", USAGE);
+        return 2;
+    };
+
+    let data = match File::open(path).map(|mut file| file.read_to_end()) {
+        Ok(Some(data)) => data,
+        Ok(None) => {
+            errln!("ttyplay: failed to read {}", path);
+            return 1;
+        }
+        Err(errno) => {
+            errln!("ttyplay: failed to open {}: errno {}", path, errno);
+            return 1;
+        }
+    };
+
+    let mut frames = Frames::new(&data);
+    let mut last = None;
+    for frame in frames.by_ref() {
+        if let Some(last) = last {
+            let pause = frame.time.saturating_sub(last).min(MAX_PAUSE_MICROS);
+            time::sleep(Duration::from_micros(pause / speed));
+        }
+        last = Some(frame.time);
+        write_all(STDOUT, frame.data);
+    }
+
+    if !frames.remainder().is_empty() {
+        errln!("ttyplay: {} ends in the middle of a frame", path);
+        return 1;
+    }
+    0
+}
+
+entry!(main);
+allow_syscalls!(Open, Read, Fstat, Close, Sleep);
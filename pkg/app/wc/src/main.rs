@@ -0,0 +1,34 @@
+#![no_std]
+#![no_main]
+
+use lib::time::Instant;
+use lib::*;
+
+extern crate lib;
+
+fn main(_args: &[&str]) -> isize {
+    // count what comes down a pipe to its end, or what is typed
+    // until an empty line, each line with its newline
+    if stdin().is_console() {
+        errln!("Type lines to count, an empty line to end.");
+    }
+
+    let start = Instant::now();
+    let (mut lines, mut words, mut bytes) = (0usize, 0usize, 0usize);
+
+    for line in stdin().lines() {
+        lines += 1;
+        words += line.split_ascii_whitespace().count();
+        bytes += line.len() + 1;
+    }
+
+    println!("{:>7} {:>7} {:>7}", lines, words, bytes);
+
+    // on stderr, so the counts alone go down a pipeline
+    errln!("wc: {} us", start.elapsed().as_micros());
+
+    0
+}
+
+entry!(main);
+allow_syscalls!(Read, Fstat, ClockMonotonic);
@@ -19,8 +19,16 @@ pub struct Config<'a> {
     pub cmdline: &'a str,
     /// Load apps into memory, when no fs implemented in kernel
     pub load_apps: bool,
+    /// Load the boot partition into memory, for the kernel to mount
+    pub load_disk: bool,
     /// Log level
     pub log_level: &'a str,
+    /// The I/O port of QEMU's debugcon, 0 for none
+    pub debugcon_port: u16,
+    /// The I/O port of QEMU's isa-debug-exit, 0 for none
+    pub debug_exit_port: u16,
+    /// Seconds local time is ahead of UTC
+    pub utc_offset: i32,
 }
 
 const DEFAULT_CONFIG: Config = Config {
@@ -32,7 +40,11 @@ const DEFAULT_CONFIG: Config = Config {
     initramfs: None,
     cmdline: "",
     load_apps: false,
+    load_disk: false,
     log_level: "info",
+    debugcon_port: 0xE9,
+    debug_exit_port: 0,
+    utc_offset: 0,
 };
 
 impl<'a> Config<'a> {
@@ -72,8 +84,37 @@ impl<'a> Config<'a> {
             "initramfs" => self.initramfs = Some(value),
             "cmdline" => self.cmdline = value,
             "load_apps" => self.load_apps = r10 != 0,
+            "load_disk" => self.load_disk = r10 != 0,
             "log_level" => self.log_level = value,
+            "debugcon_port" => self.debugcon_port = r16 as u16,
+            "debug_exit_port" => self.debug_exit_port = r16 as u16,
+            "timezone" => match parse_utc_offset(value) {
+                Some(offset) => self.utc_offset = offset,
+                None => warn!("invalid timezone: {}", value),
+            },
             _ => warn!("undefined config key: {}", key),
         }
     }
 }
+
+/// Seconds of an offset from UTC, `UTC` or `[+-]HH[:MM]`
+fn parse_utc_offset(value: &str) -> Option<i32> {
+    if value.eq_ignore_ascii_case("utc") {
+        return Some(0);
+    }
+
+    let (sign, rest) = match value.as_bytes().first()? {
+        b'+' => (1, &value[1..]),
+        b'-' => (-1, &value[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+
+    let hours = u8::from_str(hours).ok().filter(|&h| h <= 14)?;
+    let minutes = u8::from_str(minutes).ok().filter(|&m| m < 60)?;
+    Some(sign * (hours as i32 * 3600 + minutes as i32 * 60))
+}
@@ -1,5 +1,7 @@
-use crate::App;
+use crate::manifest::MANIFEST_NAME;
+use crate::{App, AppInfo};
 use arrayvec::{ArrayString, ArrayVec};
+use uefi::proto::media::block::BlockIO;
 use uefi::proto::media::file::*;
 use uefi::proto::media::fs::SimpleFileSystem;
 use uefi::table::boot::*;
@@ -59,6 +61,31 @@ pub fn load_file(bs: &BootServices, file: &mut RegularFile) -> &'static mut [u8]
     &mut buf[..len]
 }
 
+/// Decompress a buffer created using 'load_file' if it is gzip compressed
+///
+/// the compressed buffer is freed, the result is in new pages.
+pub fn decompress_file(bs: &BootServices, buf: &'static mut [u8]) -> &'static mut [u8] {
+    if !compress::gzip::is_gzip(buf) {
+        return buf;
+    }
+
+    let size = compress::gzip::original_size(buf).expect("Truncated gzip file");
+    let pages = size / 0x1000 + 1;
+
+    let mem_start = bs
+        .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, pages)
+        .expect("Failed to allocate pages");
+
+    let out = unsafe { core::slice::from_raw_parts_mut(mem_start as *mut u8, pages * 0x1000) };
+    let mut sink = compress::SliceSink::new(out);
+    compress::gzip::gunzip(buf, &mut sink).expect("Failed to decompress file");
+    let len = sink.len();
+
+    info!("Decompress file, {} -> {} bytes", buf.len(), len);
+    free_file(bs, buf);
+    &mut out[..len]
+}
+
 /// Load apps into memory, when no fs implemented in kernel
 ///
 /// List all file under "APP" and load them.
@@ -74,6 +101,8 @@ pub fn load_apps(bs: &BootServices) -> ArrayVec<App<'static>, 16> {
         .into_directory()
         .expect("App directory not found");
 
+    let manifest = load_manifest(bs, &mut handle);
+
     let mut apps = ArrayVec::new();
 
     let mut buffer = [0u8; 0x100];
@@ -98,15 +127,32 @@ pub fn load_apps(bs: &BootServices) -> ArrayVec<App<'static>, 16> {
                     continue;
                 }
 
+                // the kernel reads the others from disk
+                if apps.is_full() {
+                    warn!("No room for more than {} apps, the rest are left on disk", apps.len());
+                    break;
+                }
+
                 let mut file = file.into_regular_file().unwrap();
                 let buf = load_file(bs, &mut file);
 
-                let elf = ElfFile::new(buf).expect("Failed to parse ELF file");
                 let mut name = ArrayString::<16>::new();
 
                 info.file_name().as_str_in_buf(&mut name).unwrap();
 
-                apps.push(App { name, elf });
+                let info = manifest
+                    .and_then(|manifest| AppInfo::find(manifest, name))
+                    .unwrap_or_else(|| AppInfo::new(name));
+
+                if !info.hash.is_empty() && !hash::matches_hex(&hash::sha256(buf), &info.hash) {
+                    warn!("App \"{}\" does not match its sha256 in the manifest, skipped", name);
+                    free_file(bs, buf);
+                    continue;
+                }
+
+                let elf = ElfFile::new(buf).expect("Failed to parse ELF file");
+
+                apps.push(App::new(info, elf));
             }
             None => break,
         }
@@ -117,9 +163,114 @@ pub fn load_apps(bs: &BootServices) -> ArrayVec<App<'static>, 16> {
     apps
 }
 
+/// Load the app manifest under `dir`, if there is one
+fn load_manifest(bs: &BootServices, dir: &mut Directory) -> Option<&'static str> {
+    let mut buf = [0; 16];
+    let cstr_path = uefi::CStr16::from_str_with_buf(MANIFEST_NAME, &mut buf).unwrap();
+
+    let file = dir
+        .open(cstr_path, FileMode::Read, FileAttribute::empty())
+        .ok()?;
+    let mut file = file.into_regular_file()?;
+
+    match core::str::from_utf8(load_file(bs, &mut file)) {
+        Ok(manifest) => Some(manifest),
+        Err(_) => {
+            warn!("App manifest is not valid utf8, ignored");
+            None
+        }
+    }
+}
+
+/// Load the boot partition into memory, for the kernel to mount
+///
+/// only up to the last cluster in use is read: the partition QEMU makes of
+/// a folder is hundreds of MiB, mostly empty. `None` if it is not FAT16.
+pub fn load_boot_disk(bs: &BootServices) -> Option<&'static [u8]> {
+    let handle = bs
+        .get_handle_for_protocol::<SimpleFileSystem>()
+        .expect("Failed to get handle for SimpleFileSystem");
+
+    // the filesystem driver has the partition open, it must not be taken away
+    let block_io = unsafe {
+        bs.open_protocol::<BlockIO>(
+            OpenProtocolParams {
+                handle,
+                agent: bs.image_handle(),
+                controller: None,
+            },
+            OpenProtocolAttributes::GetProtocol,
+        )
+    }
+    .expect("Failed to get BlockIO of the boot partition");
+
+    let media_id = block_io.media().media_id();
+    let block_size = block_io.media().block_size() as usize;
+
+    let read = |lba: usize, buf: &mut [u8]| {
+        block_io
+            .read_blocks(media_id, lba as u64, buf)
+            .expect("Failed to read the boot partition")
+    };
+
+    let boot = alloc_pages(bs, block_size);
+    read(0, boot);
+
+    let u16_at = |i: usize| u16::from_le_bytes([boot[i], boot[i + 1]]) as usize;
+    let sector_size = u16_at(11);
+    let sectors_per_cluster = boot[13] as usize;
+    let reserved = u16_at(14);
+    let fats = boot[16] as usize;
+    let root_entries = u16_at(17);
+    let fat_size = u16_at(22);
+    free_file(bs, boot);
+
+    // FAT32 keeps the size of its FATs elsewhere
+    if fat_size == 0 || sector_size == 0 || block_size % sector_size != 0 {
+        warn!("Boot partition is not FAT16, not loaded");
+        return None;
+    }
+
+    let blocks = |bytes: usize| bytes.div_ceil(block_size);
+
+    // the FAT16 entry of the last cluster in use
+    let fat_bytes = fat_size * sector_size;
+    let fat = alloc_pages(bs, blocks(fat_bytes) * block_size);
+    read(reserved * sector_size / block_size, fat);
+    let last_cluster = fat[..fat_bytes]
+        .chunks_exact(2)
+        .rposition(|entry| entry.iter().any(|&byte| byte != 0))
+        .unwrap_or(0)
+        .max(1);
+    free_file(bs, fat);
+
+    let data_start = (reserved + fats * fat_size) * sector_size + root_entries * 32;
+    let len = data_start + (last_cluster - 1) * sectors_per_cluster * sector_size;
+    let disk = alloc_pages(bs, blocks(len) * block_size);
+    read(0, disk);
+
+    info!("Load boot partition to memory, size = {}", disk.len());
+    Some(disk)
+}
+
+/// Allocate pages holding at least `len` bytes, freed by `free_file`
+fn alloc_pages(bs: &BootServices, len: usize) -> &'static mut [u8] {
+    let pages = len / 0x1000 + 1;
+
+    let mem_start = bs
+        .allocate_pages(AllocateType::AnyPages, MemoryType::LOADER_DATA, pages)
+        .expect("Failed to allocate pages");
+
+    unsafe { core::slice::from_raw_parts_mut(mem_start as *mut u8, len) }
+}
+
 /// Free ELF files for which the buffer was created using 'load_file'
 pub fn free_elf(bs: &BootServices, elf: ElfFile) {
-    let buffer = elf.input;
+    free_file(bs, elf.input);
+}
+
+/// Free a buffer created using 'load_file'
+pub fn free_file(bs: &BootServices, buffer: &[u8]) {
     let pages = buffer.len() / 0x1000 + 1;
     let mem_start = buffer.as_ptr() as u64;
 
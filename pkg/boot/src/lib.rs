@@ -2,19 +2,23 @@
 pub use uefi::data_types::chars::*;
 pub use uefi::data_types::*;
 pub use uefi::prelude::SystemTable;
-pub use uefi::proto::console::gop::{GraphicsOutput, ModeInfo};
+pub use uefi::proto::console::gop::{GraphicsOutput, ModeInfo, PixelFormat};
 pub use uefi::table::boot::{MemoryAttribute, MemoryDescriptor, MemoryType};
 pub use uefi::table::runtime::*;
 pub use uefi::table::Runtime;
 pub use uefi::Status as UefiStatus;
 
-use arrayvec::{ArrayString, ArrayVec};
+use arrayvec::ArrayVec;
+use syscall_def::{SyscallSet, SYSCALL_NOTE_SECTION};
 use x86_64::structures::paging::page::PageRangeInclusive;
 use xmas_elf::ElfFile;
 
 pub mod allocator;
 pub mod config;
 pub mod fs;
+pub mod manifest;
+
+pub use manifest::AppInfo;
 
 #[macro_use]
 extern crate log;
@@ -37,21 +41,64 @@ pub struct BootInfo {
     // Loaded apps
     pub loaded_apps: Option<ArrayVec<App<'static>, 16>>,
 
+    // The boot partition, up to its last cluster in use
+    pub boot_disk: Option<&'static [u8]>,
+
     // Log Level
     pub log_level: &'static str,
 
+    // Kernel command line
+    pub cmdline: &'static str,
+
     // Kernel pages
     pub kernel_pages: KernelPages,    
+
+    // I/O port of the debug console, 0 for none
+    pub debugcon_port: u16,
+
+    // I/O port of the debug exit device, 0 for none
+    pub debug_exit_port: u16,
+
+    // Seconds local time is ahead of UTC
+    pub utc_offset: i32,
+
+    // The framebuffer of GOP, None when there is no display
+    pub frame_buffer: Option<FrameBufferInfo>,
+}
+
+/// The framebuffer GOP set up, left in its current mode
+#[derive(Debug, Clone, Copy)]
+pub struct FrameBufferInfo {
+    /// Physical address of the first pixel
+    pub addr: u64,
+    /// Size in bytes
+    pub size: usize,
+    /// Resolution, stride and pixel format
+    pub mode: ModeInfo,
 }
 
 /// App information
 pub struct App<'a> {
-    /// The name of app
-    pub name: ArrayString<16>,
+    /// The app information from the manifest
+    pub info: AppInfo,
     /// The ELF file
     pub elf: ElfFile<'a>,
 }
 
+impl<'a> App<'a> {
+    /// An app with the syscalls its ELF declares added to `info`
+    pub fn new(mut info: AppInfo, elf: ElfFile<'a>) -> Self {
+        info.syscalls = declared_syscalls(&elf);
+        Self { info, elf }
+    }
+}
+
+/// The syscalls `elf` declares it makes, `None` if it has no such note
+pub fn declared_syscalls(elf: &ElfFile) -> Option<SyscallSet> {
+    let section = elf.find_section_by_name(SYSCALL_NOTE_SECTION)?;
+    SyscallSet::from_note(section.raw_data(elf))
+}
+
 /// This is copied from https://docs.rs/bootloader/0.10.12/src/bootloader/lib.rs.html
 /// Defines the entry point function.
 ///
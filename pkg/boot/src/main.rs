@@ -18,6 +18,7 @@ use xmas_elf::ElfFile;
 use ysos_boot::allocator::*;
 use ysos_boot::fs::*;
 use ysos_boot::BootInfo;
+use ysos_boot::{FrameBufferInfo, GraphicsOutput, PixelFormat};
 use ysos_boot::MemoryType;
 
 mod config;
@@ -44,7 +45,7 @@ fn efi_main(image: uefi::Handle, mut system_table: SystemTable<Boot>) -> Status
     // 2. Load ELF files
     let elf = {
         let mut file = open_file(bs, config.kernel_path);
-        let buf = load_file(bs, &mut file);
+        let buf = decompress_file(bs, load_file(bs, &mut file));
         ElfFile::new(buf).expect("failed to parse ELF")
     };
     unsafe {
@@ -59,6 +60,24 @@ fn efi_main(image: uefi::Handle, mut system_table: SystemTable<Boot>) -> Status
         None
     };
 
+    let boot_disk = if config.load_disk {
+        info!("Loading boot partition...");
+        load_boot_disk(bs)
+    } else {
+        None
+    };
+
+    let frame_buffer = find_frame_buffer(bs);
+    match &frame_buffer {
+        Some(fb) => info!(
+            "Framebuffer: {:?} at {:#x}, {:?}",
+            fb.mode.resolution(),
+            fb.addr,
+            fb.mode.pixel_format()
+        ),
+        None => info!("No framebuffer found"),
+    }
+
     // 3. Load MemoryMap
     let max_mmap_size = system_table.boot_services().memory_map_size();
     let mmap_storage = Box::leak(
@@ -74,7 +93,8 @@ fn efi_main(image: uefi::Handle, mut system_table: SystemTable<Boot>) -> Status
         .map(|m| m.phys_start + m.page_count * 0x1000)
         .max()
         .unwrap()
-        .max(0x1_0000_0000); // include IOAPIC MMIO area
+        .max(0x1_0000_0000) // include IOAPIC MMIO area
+        .max(frame_buffer.map_or(0, |fb| fb.addr + fb.size as u64));
 
     // 4. Map ELF segments, kernel stack and physical memory to virtual memory
     let mut page_table = current_page_table();
@@ -85,12 +105,21 @@ fn efi_main(image: uefi::Handle, mut system_table: SystemTable<Boot>) -> Status
         Efer::update(|f| f.insert(EferFlags::NO_EXECUTE_ENABLE));
     }
 
-    elf::map_physical_memory(
-        config.physical_memory_offset,
-        max_phys_addr,
-        &mut page_table,
-        &mut UEFIFrameAllocator(bs),
-    );
+    if has_1gib_pages() {
+        elf::map_physical_memory::<Size1GiB>(
+            config.physical_memory_offset,
+            max_phys_addr,
+            &mut page_table,
+            &mut UEFIFrameAllocator(bs),
+        );
+    } else {
+        elf::map_physical_memory::<Size2MiB>(
+            config.physical_memory_offset,
+            max_phys_addr,
+            &mut page_table,
+            &mut UEFIFrameAllocator(bs),
+        );
+    }
 
     let kernel_pages = elf::load_elf(
         &elf,
@@ -146,8 +175,14 @@ fn efi_main(image: uefi::Handle, mut system_table: SystemTable<Boot>) -> Status
         physical_memory_offset: config.physical_memory_offset,
         system_table: runtime,
         loaded_apps: apps,
+        boot_disk,
         log_level: config.log_level,
+        cmdline: config.cmdline,
         kernel_pages: kernel_pages,
+        debugcon_port: config.debugcon_port,
+        debug_exit_port: config.debug_exit_port,
+        utc_offset: config.utc_offset,
+        frame_buffer,
     };
 
     // align stack to 8 bytes
@@ -158,6 +193,36 @@ fn efi_main(image: uefi::Handle, mut system_table: SystemTable<Boot>) -> Status
     }
 }
 
+/// Check CPUID for 1GiB pages, older cpus only have 2MiB ones
+fn has_1gib_pages() -> bool {
+    use core::arch::x86_64::__cpuid;
+
+    const EXTENDED_FEATURES: u32 = 0x8000_0001;
+    const PDPE1GB: u32 = 1 << 26;
+
+    let max_leaf = unsafe { __cpuid(0x8000_0000) }.eax;
+    max_leaf >= EXTENDED_FEATURES && unsafe { __cpuid(EXTENDED_FEATURES) }.edx & PDPE1GB != 0
+}
+
+/// The framebuffer of GOP, `None` on machines without a display
+fn find_frame_buffer(bs: &BootServices) -> Option<FrameBufferInfo> {
+    let handle = bs.get_handle_for_protocol::<GraphicsOutput>().ok()?;
+    let mut gop = bs.open_protocol_exclusive::<GraphicsOutput>(handle).ok()?;
+
+    let mode = gop.current_mode_info();
+    // the framebuffer cannot be written directly in this mode
+    if mode.pixel_format() == PixelFormat::BltOnly {
+        return None;
+    }
+
+    let mut fb = gop.frame_buffer();
+    Some(FrameBufferInfo {
+        addr: fb.as_mut_ptr() as u64,
+        size: fb.size(),
+        mode,
+    })
+}
+
 /// Get current page table from CR3
 fn current_page_table() -> OffsetPageTable<'static> {
     let p4_table_addr = Cr3::read().0.start_address().as_u64();
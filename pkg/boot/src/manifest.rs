@@ -0,0 +1,76 @@
+use arrayvec::ArrayString;
+use syscall_def::SyscallSet;
+
+/// Name of the manifest under the app directory
+///
+/// it starts with a dot, so it is skipped when loading the apps
+pub const MANIFEST_NAME: &str = ".manifest";
+
+/// App information from the manifest generated at build time
+///
+/// the manifest has one line per app, with fields split by `|`:
+/// `name|version|stack pages|sha256|usage|description`,
+/// lines starting with `#` are comments.
+#[derive(Debug, Clone)]
+pub struct AppInfo {
+    /// The name of app
+    pub name: ArrayString<16>,
+    /// The version of app
+    pub version: ArrayString<16>,
+    /// Pages of stack mapped before the app starts, 0 for the default
+    pub stack_pages: u64,
+    /// SHA-256 of the app binary, in hex
+    pub hash: ArrayString<64>,
+    /// How the app expects to be invoked
+    pub usage: ArrayString<48>,
+    /// A short description
+    pub description: ArrayString<64>,
+    /// The syscalls the app declares, from its ELF rather than the manifest
+    pub syscalls: Option<SyscallSet>,
+}
+
+impl AppInfo {
+    /// Info of an app that is not in the manifest
+    pub fn new(name: ArrayString<16>) -> Self {
+        Self {
+            name,
+            version: ArrayString::new(),
+            stack_pages: 0,
+            hash: ArrayString::new(),
+            usage: ArrayString::new(),
+            description: ArrayString::new(),
+            syscalls: None,
+        }
+    }
+
+    /// Find the entry of `name` in the manifest
+    pub fn find(manifest: &str, name: ArrayString<16>) -> Option<Self> {
+        manifest
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .find_map(|line| {
+                let mut fields = line.split('|').map(str::trim);
+                if fields.next()? != name.as_str() {
+                    return None;
+                }
+
+                let mut info = Self::new(name);
+                truncate_into(&mut info.version, fields.next().unwrap_or(""));
+                info.stack_pages = fields.next().and_then(|s| s.parse().ok()).unwrap_or(0);
+                truncate_into(&mut info.hash, fields.next().unwrap_or(""));
+                truncate_into(&mut info.usage, fields.next().unwrap_or(""));
+                truncate_into(&mut info.description, fields.next().unwrap_or(""));
+                Some(info)
+            })
+    }
+}
+
+/// Copy as much of `s` as fits into `buf`
+fn truncate_into<const N: usize>(buf: &mut ArrayString<N>, s: &str) {
+    for c in s.chars() {
+        if buf.try_push(c).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! DEFLATE encoding (RFC 1951)
+//!
+//! matches are found greedily through hash chains and written with the
+//! fixed Huffman codes, which costs some ratio against zlib but needs no
+//! buffering of a whole block to build codes for it.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::inflate::{DIST_BASE, DIST_EXTRA, LENGTH_BASE, LENGTH_EXTRA, WINDOW_SIZE};
+use crate::{Error, Sink};
+
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+/// Candidates tried for each match, more is slower and smaller
+const MAX_CHAIN: usize = 64;
+/// Output kept before it is written to the sink
+const OUT_CHUNK: usize = 4096;
+const END_OF_BLOCK: u16 = 256;
+
+fn reverse(code: u32, len: u32) -> u32 {
+    code.reverse_bits() >> (32 - len)
+}
+
+/// Encodes a DEFLATE stream written in pieces
+pub struct Deflater<S: Sink> {
+    sink: S,
+    /// the window searched for matches, then the bytes not encoded yet
+    data: Vec<u8>,
+    /// the position in the whole input of `data[0]`
+    base: usize,
+    /// the first byte not encoded yet, in `data`
+    pos: usize,
+    /// the last position + 1 of each hash, 0 for none
+    head: Box<[u32]>,
+    /// the position + 1 before each one with the same hash
+    prev: Box<[u32]>,
+    out: Vec<u8>,
+    bit_buf: u64,
+    bit_cnt: u32,
+    started: bool,
+}
+
+impl<S: Sink> Deflater<S> {
+    pub fn new(sink: S) -> Self {
+        Self {
+            sink,
+            data: Vec::new(),
+            base: 0,
+            pos: 0,
+            head: vec![0; 1 << HASH_BITS].into_boxed_slice(),
+            prev: vec![0; WINDOW_SIZE].into_boxed_slice(),
+            out: Vec::with_capacity(OUT_CHUNK),
+            bit_buf: 0,
+            bit_cnt: 0,
+            started: false,
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        // at most a window at a time, so `data` stays small
+        for chunk in data.chunks(WINDOW_SIZE) {
+            self.data.extend_from_slice(chunk);
+            self.encode(false)?;
+        }
+        Ok(())
+    }
+
+    /// Encode what is left and end the stream, return the sink
+    pub fn finish(mut self) -> Result<S, Error> {
+        self.encode(true)?;
+        self.symbol(END_OF_BLOCK);
+
+        // the open block is not final, so end with an empty one that is
+        self.bits(1, 1);
+        self.bits(1, 2);
+        self.symbol(END_OF_BLOCK);
+
+        if self.bit_cnt > 0 {
+            self.out.push(self.bit_buf as u8);
+        }
+        self.sink.write(&self.out)?;
+        Ok(self.sink)
+    }
+
+    fn encode(&mut self, last: bool) -> Result<(), Error> {
+        if !self.started {
+            // one fixed Huffman block for everything
+            self.bits(0, 1);
+            self.bits(1, 2);
+            self.started = true;
+        }
+
+        // keep a longest match of lookahead until the end is known
+        let limit = match last {
+            true => self.data.len(),
+            false => self.data.len().saturating_sub(MAX_MATCH),
+        };
+
+        while self.pos < limit {
+            let (len, dist) = self.longest_match();
+            if len >= MIN_MATCH {
+                self.length(len, dist);
+                for i in 0..len {
+                    self.insert(self.pos + i);
+                }
+                self.pos += len;
+            } else {
+                self.symbol(self.data[self.pos] as u16);
+                self.insert(self.pos);
+                self.pos += 1;
+            }
+
+            if self.out.len() >= OUT_CHUNK {
+                self.sink.write(&self.out)?;
+                self.out.clear();
+            }
+        }
+
+        // drop what is out of the window
+        if self.pos > WINDOW_SIZE * 2 {
+            let drop = self.pos - WINDOW_SIZE;
+            self.data.drain(..drop);
+            self.base += drop;
+            self.pos -= drop;
+        }
+        Ok(())
+    }
+
+    fn hash(&self, pos: usize) -> usize {
+        let bytes = &self.data[pos..pos + MIN_MATCH];
+        let value = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+        (value.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+    }
+
+    fn insert(&mut self, pos: usize) {
+        if pos + MIN_MATCH > self.data.len() {
+            return;
+        }
+        let hash = self.hash(pos);
+        let abs = self.base + pos;
+        self.prev[abs % WINDOW_SIZE] = self.head[hash];
+        self.head[hash] = abs as u32 + 1;
+    }
+
+    fn longest_match(&self) -> (usize, usize) {
+        if self.pos + MIN_MATCH > self.data.len() {
+            return (0, 0);
+        }
+
+        let abs = self.base + self.pos;
+        let max_len = MAX_MATCH.min(self.data.len() - self.pos);
+        let (mut best_len, mut best_dist) = (0, 0);
+
+        let mut next = self.head[self.hash(self.pos)] as usize;
+        for _ in 0..MAX_CHAIN {
+            if next == 0 {
+                break;
+            }
+            let cand = next - 1;
+            // stop at entries out of the window, or overwritten by newer ones
+            if cand >= abs || abs - cand > WINDOW_SIZE || cand < self.base {
+                break;
+            }
+
+            let from = cand - self.base;
+            let len = (0..max_len)
+                .take_while(|&i| self.data[from + i] == self.data[self.pos + i])
+                .count();
+            if len > best_len {
+                best_len = len;
+                best_dist = abs - cand;
+                if len == max_len {
+                    break;
+                }
+            }
+
+            next = self.prev[cand % WINDOW_SIZE] as usize;
+        }
+
+        (best_len, best_dist)
+    }
+
+    fn bits(&mut self, value: u32, len: u32) {
+        self.bit_buf |= (value as u64) << self.bit_cnt;
+        self.bit_cnt += len;
+        while self.bit_cnt >= 8 {
+            self.out.push(self.bit_buf as u8);
+            self.bit_buf >>= 8;
+            self.bit_cnt -= 8;
+        }
+    }
+
+    /// A literal/length symbol in the fixed code
+    fn symbol(&mut self, sym: u16) {
+        let sym = sym as u32;
+        let (code, len) = match sym {
+            0..=143 => (0x30 + sym, 8),
+            144..=255 => (0x190 + sym - 144, 9),
+            256..=279 => (sym - 256, 7),
+            _ => (0xc0 + sym - 280, 8),
+        };
+        // Huffman codes are packed from their top bit
+        self.bits(reverse(code, len), len);
+    }
+
+    fn length(&mut self, len: usize, dist: usize) {
+        let index = LENGTH_BASE
+            .iter()
+            .rposition(|&base| base as usize <= len)
+            .unwrap();
+        self.symbol(257 + index as u16);
+        self.bits(
+            (len - LENGTH_BASE[index] as usize) as u32,
+            LENGTH_EXTRA[index] as u32,
+        );
+
+        let index = DIST_BASE
+            .iter()
+            .rposition(|&base| base as usize <= dist)
+            .unwrap();
+        self.bits(reverse(index as u32, 5), 5);
+        self.bits(
+            (dist - DIST_BASE[index] as usize) as u32,
+            DIST_EXTRA[index] as u32,
+        );
+    }
+}
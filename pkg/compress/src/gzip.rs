@@ -0,0 +1,211 @@
+//! The gzip wrapper around DEFLATE (RFC 1952)
+
+use hash::Crc32;
+
+use crate::deflate::Deflater;
+use crate::inflate::inflate;
+use crate::{CrcSink, Error, Sink};
+
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+const METHOD_DEFLATE: u8 = 8;
+const HEADER_LEN: usize = 10;
+const TRAILER_LEN: usize = 8;
+/// The OS field for "unknown"
+const OS_UNKNOWN: u8 = 255;
+
+const FLAG_HCRC: u8 = 1 << 1;
+const FLAG_EXTRA: u8 = 1 << 2;
+const FLAG_NAME: u8 = 1 << 3;
+const FLAG_COMMENT: u8 = 1 << 4;
+const FLAG_RESERVED: u8 = 0xe0;
+
+#[inline]
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// The length of the data before compression, from the trailer
+///
+/// the trailer only keeps it modulo 4GiB.
+pub fn original_size(data: &[u8]) -> Option<usize> {
+    if data.len() < HEADER_LEN + TRAILER_LEN {
+        return None;
+    }
+    let size = &data[data.len() - 4..];
+    Some(u32::from_le_bytes(size.try_into().unwrap()) as usize)
+}
+
+/// Compresses data written in pieces into a gzip stream
+pub struct GzipEncoder<S: Sink> {
+    deflater: Deflater<S>,
+    crc: Crc32,
+    len: u32,
+}
+
+impl<S: Sink> GzipEncoder<S> {
+    pub fn new(mut sink: S) -> Result<Self, Error> {
+        // no name and no time, so the output only depends on the input
+        sink.write(&[
+            MAGIC[0],
+            MAGIC[1],
+            METHOD_DEFLATE,
+            0,
+            0,
+            0,
+            0,
+            0,
+            0,
+            OS_UNKNOWN,
+        ])?;
+
+        Ok(Self {
+            deflater: Deflater::new(sink),
+            crc: Crc32::new(),
+            len: 0,
+        })
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.crc.update(data);
+        self.len = self.len.wrapping_add(data.len() as u32);
+        self.deflater.write(data)
+    }
+
+    /// End the stream, return the sink
+    pub fn finish(self) -> Result<S, Error> {
+        let mut sink = self.deflater.finish()?;
+        sink.write(&self.crc.finish().to_le_bytes())?;
+        sink.write(&self.len.to_le_bytes())?;
+        Ok(sink)
+    }
+}
+
+fn skip_string(data: &[u8], pos: usize) -> Result<usize, Error> {
+    let rest = data.get(pos..).ok_or(Error::Truncated)?;
+    let len = rest.iter().position(|&b| b == 0).ok_or(Error::Truncated)?;
+    Ok(pos + len + 1)
+}
+
+/// Decompress the gzip stream at the start of `input` into `sink`
+///
+/// the CRC32 and length in the trailer are checked, return the bytes of
+/// `input` taken by the stream.
+pub fn gunzip<S: Sink>(input: &[u8], sink: &mut S) -> Result<usize, Error> {
+    if input.len() < HEADER_LEN {
+        return Err(Error::Truncated);
+    }
+    if !is_gzip(input) || input[2] != METHOD_DEFLATE {
+        return Err(Error::Corrupt);
+    }
+
+    let flags = input[3];
+    if flags & FLAG_RESERVED != 0 {
+        return Err(Error::Corrupt);
+    }
+
+    let mut pos = HEADER_LEN;
+    if flags & FLAG_EXTRA != 0 {
+        let len = input.get(pos..pos + 2).ok_or(Error::Truncated)?;
+        pos += 2 + u16::from_le_bytes([len[0], len[1]]) as usize;
+    }
+    if flags & FLAG_NAME != 0 {
+        pos = skip_string(input, pos)?;
+    }
+    if flags & FLAG_COMMENT != 0 {
+        pos = skip_string(input, pos)?;
+    }
+    if flags & FLAG_HCRC != 0 {
+        pos += 2;
+    }
+
+    let mut out = CrcSink {
+        inner: sink,
+        crc: Crc32::new(),
+        len: 0,
+    };
+    pos += inflate(input.get(pos..).ok_or(Error::Truncated)?, &mut out)?;
+
+    let trailer = input.get(pos..pos + TRAILER_LEN).ok_or(Error::Truncated)?;
+    let crc = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+    let len = u32::from_le_bytes(trailer[4..].try_into().unwrap());
+    if crc != out.crc.finish() || len != out.len {
+        return Err(Error::Checksum);
+    }
+
+    Ok(pos + TRAILER_LEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samples::*;
+    use crate::SliceSink;
+    use alloc::vec::Vec;
+
+    /// `gzip -9 sample.txt`, with the name and time in the header
+    const HOST_GZIP: &[u8] = include_bytes!("../fixtures/sample.txt.gz");
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzipEncoder::new(Vec::new()).unwrap();
+        for chunk in data.chunks(1000) {
+            encoder.write(chunk).unwrap();
+        }
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        for input in inputs() {
+            let stream = gzip(&input);
+            assert_eq!(original_size(&stream), Some(input.len()));
+
+            let mut out = Vec::new();
+            assert_eq!(gunzip(&stream, &mut out), Ok(stream.len()));
+            assert!(out == input, "{} bytes", input.len());
+        }
+    }
+
+    #[test]
+    fn host_stream() {
+        let mut out = Vec::new();
+        assert_eq!(gunzip(HOST_GZIP, &mut out), Ok(HOST_GZIP.len()));
+        assert_eq!(out, SAMPLE);
+    }
+
+    #[test]
+    fn truncated() {
+        for len in 0..HOST_GZIP.len() {
+            assert!(
+                gunzip(&HOST_GZIP[..len], &mut Vec::new()).is_err(),
+                "{} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt() {
+        // a flipped bit fails the stream, unless it is in a field
+        // nothing checks, such as the time
+        let mut stream = HOST_GZIP.to_vec();
+        for bit in 0..stream.len() * 8 {
+            stream[bit / 8] ^= 1 << (bit % 8);
+            let mut out = Vec::new();
+            if gunzip(&stream, &mut out).is_ok() {
+                assert_eq!(out, SAMPLE, "bit {}", bit);
+            }
+            stream[bit / 8] ^= 1 << (bit % 8);
+        }
+
+        let mut out = Vec::new();
+        assert_eq!(gunzip(&gzip(SAMPLE)[..4], &mut out), Err(Error::Truncated));
+        assert_eq!(gunzip(b"not a gzip stream", &mut out), Err(Error::Corrupt));
+    }
+
+    #[test]
+    fn output_full() {
+        let mut buf = [0u8; 100];
+        let mut sink = SliceSink::new(&mut buf);
+        assert_eq!(gunzip(HOST_GZIP, &mut sink), Err(Error::OutputFull));
+    }
+}
@@ -0,0 +1,370 @@
+//! DEFLATE decoding (RFC 1951)
+//!
+//! codes are decoded a bit at a time, as in zlib's `puff`: slower than a
+//! table lookup, but small and easy to check.
+
+use alloc::boxed::Box;
+use alloc::vec;
+
+use crate::{Error, Sink};
+
+/// How far back a match can refer
+pub(crate) const WINDOW_SIZE: usize = 32768;
+
+const MAX_BITS: usize = 15;
+const MAX_LIT_CODES: usize = 288;
+const MAX_DIST_CODES: usize = 30;
+
+pub(crate) const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+pub(crate) const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+pub(crate) const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+pub(crate) const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// The order code length code lengths are sent in
+const CLEN_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    buf: u32,
+    cnt: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            buf: 0,
+            cnt: 0,
+        }
+    }
+
+    /// Take `n` bits, at most 16, the first one lowest
+    fn need(&mut self, n: u32) -> Result<u32, Error> {
+        while self.cnt < n {
+            let byte = *self.data.get(self.pos).ok_or(Error::Truncated)?;
+            self.pos += 1;
+            self.buf |= (byte as u32) << self.cnt;
+            self.cnt += 8;
+        }
+
+        let value = self.buf & ((1 << n) - 1);
+        self.buf >>= n;
+        self.cnt -= n;
+        Ok(value)
+    }
+
+    /// Skip to the next byte, bytes are only read as needed,
+    /// so fewer than 8 bits are ever left
+    fn align(&mut self) {
+        self.buf = 0;
+        self.cnt = 0;
+    }
+}
+
+/// A canonical Huffman code, by the count of codes of each length
+/// and the symbols in code order
+struct Huffman {
+    count: [u16; MAX_BITS + 1],
+    symbol: [u16; MAX_LIT_CODES],
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Self, Error> {
+        let mut count = [0u16; MAX_BITS + 1];
+        for &len in lengths {
+            count[len as usize] += 1;
+        }
+
+        // more codes than there is room for cannot be decoded, fewer
+        // just leave some bit patterns unused
+        let mut left: i32 = 1;
+        for &n in &count[1..] {
+            left = (left << 1) - n as i32;
+            if left < 0 {
+                return Err(Error::Corrupt);
+            }
+        }
+
+        let mut offs = [0u16; MAX_BITS + 1];
+        for len in 1..MAX_BITS {
+            offs[len + 1] = offs[len] + count[len];
+        }
+
+        let mut symbol = [0u16; MAX_LIT_CODES];
+        for (sym, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbol[offs[len as usize] as usize] = sym as u16;
+                offs[len as usize] += 1;
+            }
+        }
+
+        Ok(Self { count, symbol })
+    }
+
+    fn decode(&self, bits: &mut Bits) -> Result<u16, Error> {
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+
+        for len in 1..=MAX_BITS {
+            code |= bits.need(1)? as i32;
+            let count = self.count[len] as i32;
+            if code - count < first {
+                return Ok(self.symbol[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+
+        Err(Error::Corrupt)
+    }
+}
+
+/// Decodes DEFLATE streams, keeping the last 32KiB of output for matches
+pub struct Inflater {
+    window: Box<[u8]>,
+    pos: usize,
+    /// where the part of the window not written to the sink starts
+    flushed: usize,
+    total: usize,
+}
+
+impl Default for Inflater {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Inflater {
+    pub fn new() -> Self {
+        Self {
+            window: vec![0; WINDOW_SIZE].into_boxed_slice(),
+            pos: 0,
+            flushed: 0,
+            total: 0,
+        }
+    }
+
+    /// Decode the raw DEFLATE stream at the start of `input` into `sink`
+    ///
+    /// return the bytes of `input` taken by the stream.
+    pub fn inflate<S: Sink>(&mut self, input: &[u8], sink: &mut S) -> Result<usize, Error> {
+        self.pos = 0;
+        self.flushed = 0;
+        self.total = 0;
+
+        let mut bits = Bits::new(input);
+        loop {
+            let last = bits.need(1)? == 1;
+            match bits.need(2)? {
+                0 => self.stored(&mut bits, sink)?,
+                1 => self.fixed(&mut bits, sink)?,
+                2 => self.dynamic(&mut bits, sink)?,
+                _ => return Err(Error::Corrupt),
+            }
+            if last {
+                break;
+            }
+        }
+
+        self.flush(sink)?;
+        Ok(bits.pos)
+    }
+
+    fn put<S: Sink>(&mut self, byte: u8, sink: &mut S) -> Result<(), Error> {
+        self.window[self.pos] = byte;
+        self.pos += 1;
+        self.total += 1;
+
+        if self.pos == WINDOW_SIZE {
+            self.flush(sink)?;
+            self.pos = 0;
+            self.flushed = 0;
+        }
+        Ok(())
+    }
+
+    fn flush<S: Sink>(&mut self, sink: &mut S) -> Result<(), Error> {
+        sink.write(&self.window[self.flushed..self.pos])?;
+        self.flushed = self.pos;
+        Ok(())
+    }
+
+    fn stored<S: Sink>(&mut self, bits: &mut Bits, sink: &mut S) -> Result<(), Error> {
+        bits.align();
+        let len = bits.need(16)?;
+        if bits.need(16)? != !len & 0xffff {
+            return Err(Error::Corrupt);
+        }
+
+        let data = bits
+            .data
+            .get(bits.pos..bits.pos + len as usize)
+            .ok_or(Error::Truncated)?;
+        bits.pos += len as usize;
+
+        for &byte in data {
+            self.put(byte, sink)?;
+        }
+        Ok(())
+    }
+
+    fn fixed<S: Sink>(&mut self, bits: &mut Bits, sink: &mut S) -> Result<(), Error> {
+        let mut lengths = [0u8; MAX_LIT_CODES];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+
+        let lencode = Huffman::new(&lengths)?;
+        let distcode = Huffman::new(&[5; MAX_DIST_CODES])?;
+        self.codes(bits, sink, &lencode, &distcode)
+    }
+
+    fn dynamic<S: Sink>(&mut self, bits: &mut Bits, sink: &mut S) -> Result<(), Error> {
+        let nlen = bits.need(5)? as usize + 257;
+        let ndist = bits.need(5)? as usize + 1;
+        let ncode = bits.need(4)? as usize + 4;
+        if nlen > MAX_LIT_CODES || ndist > MAX_DIST_CODES {
+            return Err(Error::Corrupt);
+        }
+
+        let mut clens = [0u8; 19];
+        for &index in &CLEN_ORDER[..ncode] {
+            clens[index] = bits.need(3)? as u8;
+        }
+        let clencode = Huffman::new(&clens)?;
+
+        let mut lengths = [0u8; MAX_LIT_CODES + MAX_DIST_CODES];
+        let mut index = 0;
+        while index < nlen + ndist {
+            let sym = clencode.decode(bits)?;
+            if sym < 16 {
+                lengths[index] = sym as u8;
+                index += 1;
+                continue;
+            }
+
+            let (value, repeat) = match sym {
+                16 if index > 0 => (lengths[index - 1], 3 + bits.need(2)?),
+                16 => return Err(Error::Corrupt),
+                17 => (0, 3 + bits.need(3)?),
+                _ => (0, 11 + bits.need(7)?),
+            };
+            let end = index + repeat as usize;
+            if end > nlen + ndist {
+                return Err(Error::Corrupt);
+            }
+            lengths[index..end].fill(value);
+            index = end;
+        }
+
+        // a block that cannot end is no block
+        if lengths[256] == 0 {
+            return Err(Error::Corrupt);
+        }
+
+        let lencode = Huffman::new(&lengths[..nlen])?;
+        let distcode = Huffman::new(&lengths[nlen..nlen + ndist])?;
+        self.codes(bits, sink, &lencode, &distcode)
+    }
+
+    fn codes<S: Sink>(
+        &mut self,
+        bits: &mut Bits,
+        sink: &mut S,
+        lencode: &Huffman,
+        distcode: &Huffman,
+    ) -> Result<(), Error> {
+        loop {
+            let sym = lencode.decode(bits)? as usize;
+            if sym < 256 {
+                self.put(sym as u8, sink)?;
+                continue;
+            }
+            if sym == 256 {
+                return Ok(());
+            }
+
+            let sym = sym - 257;
+            if sym >= LENGTH_BASE.len() {
+                return Err(Error::Corrupt);
+            }
+            let len = LENGTH_BASE[sym] as usize + bits.need(LENGTH_EXTRA[sym] as u32)? as usize;
+
+            let sym = distcode.decode(bits)? as usize;
+            if sym >= DIST_BASE.len() {
+                return Err(Error::Corrupt);
+            }
+            let dist = DIST_BASE[sym] as usize + bits.need(DIST_EXTRA[sym] as u32)? as usize;
+            if dist > self.total {
+                return Err(Error::Corrupt);
+            }
+
+            for _ in 0..len {
+                let byte = self.window[(self.pos + WINDOW_SIZE - dist) % WINDOW_SIZE];
+                self.put(byte, sink)?;
+            }
+        }
+    }
+}
+
+/// Decode the raw DEFLATE stream at the start of `input` into `sink`
+///
+/// return the bytes of `input` taken by the stream.
+pub fn inflate<S: Sink>(input: &[u8], sink: &mut S) -> Result<usize, Error> {
+    Inflater::new().inflate(input, sink)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samples::noise;
+
+    #[test]
+    fn stored_and_fixed_blocks() {
+        // a stored block, then "abc" in a fixed block, both from zlib
+        let stream = [
+            0x00, 0x03, 0x00, 0xfc, 0xff, b'x', b'y', b'z', 0x4b, 0x4c, 0x4a, 0x06, 0x00,
+        ];
+        let mut out = Vec::new();
+        assert_eq!(inflate(&stream, &mut out), Ok(stream.len()));
+        assert_eq!(out, b"xyzabc");
+    }
+
+    #[test]
+    fn garbage() {
+        // random bytes are rejected, or decode to something, but never panic
+        for len in [1, 2, 10, 100, 1000, 10000] {
+            let input = noise(len);
+            for start in 0..len.min(64) {
+                let _ = inflate(&input[start..], &mut Vec::new());
+            }
+        }
+
+        let mut out = Vec::new();
+        // block type 3 is reserved
+        assert_eq!(inflate(&[0x07], &mut out), Err(Error::Corrupt));
+        // the length of a stored block does not match its complement
+        assert_eq!(
+            inflate(&[0x01, 0x03, 0x00, 0x00, 0x00], &mut out),
+            Err(Error::Corrupt)
+        );
+    }
+}
@@ -0,0 +1,137 @@
+//! Compression
+//!
+//! DEFLATE with the gzip wrapper, and LZ4 blocks in the legacy frame.
+//! Output goes through a [`Sink`] as it is made, so neither side needs a
+//! buffer for the whole result: the decoders keep the window they refer
+//! back to, the encoders the window they search and a little lookahead.
+
+#![cfg_attr(not(test), no_std)]
+
+extern crate alloc;
+
+pub mod deflate;
+pub mod gzip;
+pub mod inflate;
+pub mod lz4;
+
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// the input ends in the middle of the stream
+    Truncated,
+    /// the input is not valid for the format
+    Corrupt,
+    /// the checksum or the length in the trailer does not match
+    Checksum,
+    /// the output does not fit the sink
+    OutputFull,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Error::Truncated => "unexpected end of input",
+            Error::Corrupt => "corrupt input",
+            Error::Checksum => "checksum mismatch",
+            Error::OutputFull => "output buffer is full",
+        })
+    }
+}
+
+/// Where compressed or decompressed data is written
+pub trait Sink {
+    fn write(&mut self, data: &[u8]) -> Result<(), Error>;
+}
+
+impl Sink for Vec<u8> {
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.extend_from_slice(data);
+        Ok(())
+    }
+}
+
+/// Fills a fixed buffer, then fails with `OutputFull`
+pub struct SliceSink<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> SliceSink<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// Bytes written so far
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Sink for SliceSink<'_> {
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        let end = self.len + data.len();
+        if end > self.buf.len() {
+            return Err(Error::OutputFull);
+        }
+        self.buf[self.len..end].copy_from_slice(data);
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Also keeps the CRC32 and length of what is written
+pub(crate) struct CrcSink<'a, S: Sink> {
+    pub inner: &'a mut S,
+    pub crc: hash::Crc32,
+    pub len: u32,
+}
+
+impl<S: Sink> Sink for CrcSink<'_, S> {
+    fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.crc.update(data);
+        self.len = self.len.wrapping_add(data.len() as u32);
+        self.inner.write(data)
+    }
+}
+
+/// Inputs for the tests of the formats
+#[cfg(test)]
+pub(crate) mod samples {
+    use alloc::vec;
+    use alloc::vec::Vec;
+
+    pub const SAMPLE: &[u8] = include_bytes!("../fixtures/sample.txt");
+
+    /// Bytes from a fixed LCG, which barely compress
+    pub fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_u32;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    /// Empty, short, text, long runs and noise, past the window and
+    /// block sizes
+    pub fn inputs() -> Vec<Vec<u8>> {
+        vec![
+            Vec::new(),
+            b"a".to_vec(),
+            b"abcabcabcabcabcabcabcabcabcabc".to_vec(),
+            SAMPLE.to_vec(),
+            SAMPLE.repeat(300),
+            vec![0; 200 * 1024],
+            noise(100 * 1024),
+        ]
+    }
+}
@@ -0,0 +1,297 @@
+//! LZ4 blocks, and the legacy frame made by `lz4 -l`
+//!
+//! the legacy frame is a magic number, then blocks compressed apart, each
+//! after its compressed length. It has no checksums, but is simple enough
+//! to write without buffering more than a block.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use crate::{Error, Sink};
+
+pub const LEGACY_MAGIC: u32 = 0x184c_2102;
+/// Bytes the encoder puts in a block
+pub const BLOCK_SIZE: usize = 64 * 1024;
+/// Largest block the legacy frame allows
+pub const LEGACY_BLOCK_MAX: usize = 8 * 1024 * 1024;
+
+const MIN_MATCH: usize = 4;
+/// The last bytes of a block are always literals
+const LAST_LITERALS: usize = 5;
+/// The last match starts at least this far from the end
+const MF_LIMIT: usize = 12;
+const HASH_BITS: u32 = 12;
+const MAX_OFFSET: usize = u16::MAX as usize;
+
+#[inline]
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(data[pos..pos + 4].try_into().unwrap())
+}
+
+fn push_len(out: &mut Vec<u8>, mut len: usize) {
+    while len >= 255 {
+        out.push(255);
+        len -= 255;
+    }
+    out.push(len as u8);
+}
+
+/// Literals, then a match unless it is the last sequence
+fn push_sequence(out: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let lit = literals.len();
+    let mlen = matched.map_or(0, |(_, len)| len - MIN_MATCH);
+    out.push((lit.min(15) << 4 | mlen.min(15)) as u8);
+    if lit >= 15 {
+        push_len(out, lit - 15);
+    }
+    out.extend_from_slice(literals);
+
+    if let Some((offset, _)) = matched {
+        out.extend_from_slice(&(offset as u16).to_le_bytes());
+        if mlen >= 15 {
+            push_len(out, mlen - 15);
+        }
+    }
+}
+
+/// Compress `input` as one block, appended to `out`
+pub fn compress_block(input: &[u8], out: &mut Vec<u8>) {
+    let mut table = vec![0u32; 1 << HASH_BITS];
+    let mut anchor = 0;
+    let mut pos = 0;
+
+    while pos + MF_LIMIT < input.len() {
+        let seq = read_u32(input, pos);
+        let hash = (seq.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize;
+        let cand = table[hash] as usize;
+        table[hash] = pos as u32 + 1;
+
+        if cand == 0 || pos - (cand - 1) > MAX_OFFSET || read_u32(input, cand - 1) != seq {
+            pos += 1;
+            continue;
+        }
+
+        let cand = cand - 1;
+        let max_len = input.len() - LAST_LITERALS - pos;
+        let len = MIN_MATCH
+            + (MIN_MATCH..max_len)
+                .take_while(|&i| input[cand + i] == input[pos + i])
+                .count();
+
+        push_sequence(out, &input[anchor..pos], Some((pos - cand, len)));
+        pos += len;
+        anchor = pos;
+    }
+
+    push_sequence(out, &input[anchor..], None);
+}
+
+/// Decompress one block appended to `out`, into at most `max` bytes
+///
+/// matches may only refer back into this block.
+pub fn decompress_block(input: &[u8], out: &mut Vec<u8>, max: usize) -> Result<(), Error> {
+    let start = out.len();
+    let mut pos = 0;
+
+    let read_len = |pos: &mut usize, mut len: usize| -> Result<usize, Error> {
+        loop {
+            let byte = *input.get(*pos).ok_or(Error::Truncated)?;
+            *pos += 1;
+            len += byte as usize;
+            if byte != 255 {
+                return Ok(len);
+            }
+        }
+    };
+
+    loop {
+        let token = *input.get(pos).ok_or(Error::Truncated)?;
+        pos += 1;
+
+        let mut lit = (token >> 4) as usize;
+        if lit == 15 {
+            lit = read_len(&mut pos, lit)?;
+        }
+        let literals = input.get(pos..pos + lit).ok_or(Error::Truncated)?;
+        if out.len() - start + lit > max {
+            return Err(Error::OutputFull);
+        }
+        out.extend_from_slice(literals);
+        pos += lit;
+
+        // the last sequence has no match
+        if pos == input.len() {
+            return Ok(());
+        }
+
+        let offset = input.get(pos..pos + 2).ok_or(Error::Truncated)?;
+        let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+        pos += 2;
+        if offset == 0 || offset > out.len() - start {
+            return Err(Error::Corrupt);
+        }
+
+        let mut len = (token & 15) as usize;
+        if len == 15 {
+            len = read_len(&mut pos, len)?;
+        }
+        len += MIN_MATCH;
+        if out.len() - start + len > max {
+            return Err(Error::OutputFull);
+        }
+
+        // the match may overlap what it writes
+        for _ in 0..len {
+            out.push(out[out.len() - offset]);
+        }
+    }
+}
+
+/// Compresses data written in pieces into a legacy frame
+pub struct Lz4Encoder<S: Sink> {
+    sink: S,
+    block: Vec<u8>,
+    out: Vec<u8>,
+}
+
+impl<S: Sink> Lz4Encoder<S> {
+    pub fn new(mut sink: S) -> Result<Self, Error> {
+        sink.write(&LEGACY_MAGIC.to_le_bytes())?;
+        Ok(Self {
+            sink,
+            block: Vec::with_capacity(BLOCK_SIZE),
+            out: Vec::new(),
+        })
+    }
+
+    pub fn write(&mut self, mut data: &[u8]) -> Result<(), Error> {
+        while !data.is_empty() {
+            let n = data.len().min(BLOCK_SIZE - self.block.len());
+            self.block.extend_from_slice(&data[..n]);
+            data = &data[n..];
+
+            if self.block.len() == BLOCK_SIZE {
+                self.flush_block()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush_block(&mut self) -> Result<(), Error> {
+        self.out.clear();
+        compress_block(&self.block, &mut self.out);
+        self.sink.write(&(self.out.len() as u32).to_le_bytes())?;
+        self.sink.write(&self.out)?;
+        self.block.clear();
+        Ok(())
+    }
+
+    /// Compress what is left, return the sink
+    pub fn finish(mut self) -> Result<S, Error> {
+        if !self.block.is_empty() {
+            self.flush_block()?;
+        }
+        Ok(self.sink)
+    }
+}
+
+/// Decompress the legacy frame at the start of `input` into `sink`
+///
+/// return the bytes of `input` taken by the frame.
+pub fn decompress<S: Sink>(input: &[u8], sink: &mut S) -> Result<usize, Error> {
+    if input.len() < 4 {
+        return Err(Error::Truncated);
+    }
+    if read_u32(input, 0) != LEGACY_MAGIC {
+        return Err(Error::Corrupt);
+    }
+
+    let mut pos = 4;
+    let mut block = Vec::new();
+    // the frame ends with the input, or where another frame starts
+    while pos < input.len() {
+        let len = input.get(pos..pos + 4).ok_or(Error::Truncated)?;
+        let len = u32::from_le_bytes(len.try_into().unwrap());
+        if len == LEGACY_MAGIC {
+            break;
+        }
+        pos += 4;
+
+        let data = input.get(pos..pos + len as usize).ok_or(Error::Truncated)?;
+        pos += len as usize;
+
+        block.clear();
+        decompress_block(data, &mut block, LEGACY_BLOCK_MAX)?;
+        sink.write(&block)?;
+    }
+
+    Ok(pos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::samples::*;
+
+    /// `lz4 -l -9 sample.txt`
+    const HOST_LZ4: &[u8] = include_bytes!("../fixtures/sample.txt.lz4");
+
+    fn lz4(data: &[u8]) -> Vec<u8> {
+        let mut encoder = Lz4Encoder::new(Vec::new()).unwrap();
+        for chunk in data.chunks(1000) {
+            encoder.write(chunk).unwrap();
+        }
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        for input in inputs() {
+            let frame = lz4(&input);
+            let mut out = Vec::new();
+            assert_eq!(decompress(&frame, &mut out), Ok(frame.len()));
+            assert!(out == input, "{} bytes", input.len());
+        }
+    }
+
+    #[test]
+    fn host_frame() {
+        let mut out = Vec::new();
+        assert_eq!(decompress(HOST_LZ4, &mut out), Ok(HOST_LZ4.len()));
+        assert_eq!(out, SAMPLE);
+    }
+
+    #[test]
+    fn truncated() {
+        // the magic alone is an empty frame
+        for len in (0..HOST_LZ4.len()).filter(|&len| len != 4) {
+            assert!(
+                decompress(&HOST_LZ4[..len], &mut Vec::new()).is_err(),
+                "{} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn corrupt() {
+        // there is no checksum, so a flipped bit only has to not panic
+        let mut frame = HOST_LZ4.to_vec();
+        for bit in 0..frame.len() * 8 {
+            frame[bit / 8] ^= 1 << (bit % 8);
+            let _ = decompress(&frame, &mut Vec::new());
+            frame[bit / 8] ^= 1 << (bit % 8);
+        }
+
+        let mut out = Vec::new();
+        // a match before the start of the block
+        assert_eq!(
+            decompress_block(&[0x00, 0x01, 0x00], &mut out, 100),
+            Err(Error::Corrupt)
+        );
+        assert_eq!(
+            decompress_block(&[0x10, b'a', 0x05, 0x00], &mut out, 100),
+            Err(Error::Corrupt)
+        );
+    }
+}
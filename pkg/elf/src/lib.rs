@@ -14,16 +14,17 @@ use xmas_elf::{program, ElfFile};
 
 /// Map physical memory [0, max_addr)
 ///
-/// to virtual space [offset, offset + max_addr)
-pub fn map_physical_memory(
+/// to virtual space [offset, offset + max_addr), with pages of size `S`,
+/// 2MiB pages work on every x86_64 cpu, 1GiB ones need `pdpe1gb`
+pub fn map_physical_memory<S: PageSize + core::fmt::Debug>(
     offset: u64,
     max_addr: u64,
-    page_table: &mut impl Mapper<Size2MiB>,
+    page_table: &mut impl Mapper<S>,
     frame_allocator: &mut impl FrameAllocator<Size4KiB>,
 ) {
     trace!("Mapping physical memory...");
-    let start_frame = PhysFrame::containing_address(PhysAddr::new(0));
-    let end_frame = PhysFrame::containing_address(PhysAddr::new(max_addr));
+    let start_frame = PhysFrame::<S>::containing_address(PhysAddr::new(0));
+    let end_frame = PhysFrame::<S>::containing_address(PhysAddr::new(max_addr));
 
     for frame in PhysFrame::range_inclusive(start_frame, end_frame) {
         let page = Page::containing_address(VirtAddr::new(frame.start_address().as_u64() + offset));
@@ -107,7 +108,8 @@ pub fn map_range(
         page_range.count()
     );
 
-    let mut flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    // the pages hold a stack or a heap, never code
+    let mut flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE | PageTableFlags::NO_EXECUTE;
 
     if user_access {
         flags |= PageTableFlags::USER_ACCESSIBLE;
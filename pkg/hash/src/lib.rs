@@ -0,0 +1,61 @@
+//! Checksums and hashes
+//!
+//! one `no_std` implementation for the bootloader, the kernel, the apps
+//! and xtask, so the hashes written at build time are checked with the
+//! same code that made them. Both hashers can be fed in pieces.
+
+#![cfg_attr(not(test), no_std)]
+
+mod crc32;
+mod sha256;
+
+pub use crc32::*;
+pub use sha256::*;
+
+use core::fmt;
+
+/// Shows bytes as lowercase hex, without allocating
+pub struct Hex<'a>(pub &'a [u8]);
+
+impl fmt::Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for b in self.0 {
+            write!(f, "{:02x}", b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compare `digest` with a hex string, in either case
+pub fn matches_hex(digest: &[u8], hex: &str) -> bool {
+    fn nibble(c: u8) -> Option<u8> {
+        (c as char).to_digit(16).map(|d| d as u8)
+    }
+
+    hex.len() == digest.len() * 2
+        && hex
+            .as_bytes()
+            .as_chunks::<2>()
+            .0
+            .iter()
+            .zip(digest)
+            .all(|(&[hi, lo], &b)| match (nibble(hi), nibble(lo)) {
+                (Some(hi), Some(lo)) => hi << 4 | lo == b,
+                _ => false,
+            })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_matches_in_either_case() {
+        let digest = [0x01, 0xab, 0xff];
+        assert!(matches_hex(&digest, "01abff"));
+        assert!(matches_hex(&digest, "01ABFF"));
+        assert!(!matches_hex(&digest, "01abfe"));
+        assert!(!matches_hex(&digest, "01abf"));
+        assert!(!matches_hex(&digest, "01abfg"));
+    }
+}
@@ -0,0 +1,101 @@
+//! CPU features found by CPUID at boot
+//!
+//! subsystems check these and pick an implementation at runtime, instead
+//! of assuming what QEMU provides, so the kernel boots on older machines.
+//! The kernel is built without SSE and does not save vector state on a
+//! switch, so SSE4.2, AVX2 and XSAVE are only reported for now.
+
+use x86::cpuid::CpuId;
+use x86_64::registers::control::{Cr4, Cr4Flags};
+use x86_64::registers::model_specific::FsBase;
+use x86_64::registers::segmentation::{Segment64, FS};
+use x86_64::VirtAddr;
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct Features: u32 {
+        const SSE4_2 = 1 << 0;
+        const AVX2 = 1 << 1;
+        const RDRAND = 1 << 2;
+        /// `rdfsbase` and friends, enabled in CR4 when present
+        const FSGSBASE = 1 << 3;
+        const XSAVE = 1 << 4;
+        const HUGE_PAGES_1G = 1 << 5;
+        /// enhanced `rep movsb`, faster than moving qwords
+        const ERMS = 1 << 6;
+        /// architectural performance monitoring with the fixed counters
+        /// of instructions retired and unhalted cycles, see `pmc`
+        const PERFMON = 1 << 7;
+    }
+}
+
+static FEATURES: spin::Once<Features> = spin::Once::new();
+
+pub fn init() {
+    let features = *FEATURES.call_once(detect);
+
+    if features.contains(Features::FSGSBASE) {
+        unsafe { Cr4::update(|flags| flags.insert(Cr4Flags::FSGSBASE)) };
+    }
+
+    info!("CPU Features     : {:?}", features);
+}
+
+fn detect() -> Features {
+    let cpuid = CpuId::new();
+    let mut features = Features::empty();
+
+    if let Some(info) = cpuid.get_feature_info() {
+        features.set(Features::SSE4_2, info.has_sse42());
+        features.set(Features::RDRAND, info.has_rdrand());
+        features.set(Features::XSAVE, info.has_xsave());
+    }
+
+    if let Some(info) = cpuid.get_extended_feature_info() {
+        features.set(Features::AVX2, info.has_avx2());
+        features.set(Features::FSGSBASE, info.has_fsgsbase());
+        features.set(Features::ERMS, info.has_rep_movsb_stosb());
+    }
+
+    if let Some(info) = cpuid.get_performance_monitoring_info() {
+        // the global control of the fixed counters came with version 2
+        features.set(
+            Features::PERFMON,
+            info.version_id() >= 2 && info.fixed_function_counters() >= 2,
+        );
+    }
+
+    if let Some(info) = cpuid.get_extended_processor_and_feature_identifiers() {
+        features.set(Features::HUGE_PAGES_1G, info.has_1gib_pages());
+    }
+
+    features
+}
+
+/// The features of the cpu, none before `init`
+#[inline]
+pub fn features() -> Features {
+    FEATURES.get().copied().unwrap_or(Features::empty())
+}
+
+#[inline]
+pub fn has(feature: Features) -> bool {
+    features().contains(feature)
+}
+
+/// The FS base of the running code, where thread local storage starts
+pub fn fs_base() -> VirtAddr {
+    if has(Features::FSGSBASE) {
+        FS::read_base()
+    } else {
+        FsBase::read()
+    }
+}
+
+pub fn set_fs_base(addr: VirtAddr) {
+    if has(Features::FSGSBASE) {
+        unsafe { FS::write_base(addr) };
+    } else {
+        FsBase::write(addr);
+    }
+}
@@ -0,0 +1,322 @@
+//! SATA disks of an AHCI controller, read and written with DMA
+//!
+//! the controller is found on PCI by its class, its registers are the
+//! memory of BAR 5. Each port with a disk gets a frame for its command
+//! list, received FIS and command table, and one for the data of a block.
+//! A command is issued in slot 0 and polled until the controller is done,
+//! the data is moved by the controller itself.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ptr::{read_volatile, write_volatile};
+use core::sync::atomic::{fence, Ordering};
+use spin::{Mutex, Once};
+use x86_64::structures::paging::FrameAllocator;
+
+use super::ata::{identify_model, identify_word};
+use super::block::{Block, BlockDevice, BlockError, BLOCK_SIZE};
+use super::pci::{self, COMMAND_BUS_MASTER, COMMAND_MEMORY};
+use crate::memory::{get_frame_alloc_for_sure, physical_to_virtual};
+
+/// The class, subclass and programming interface of an AHCI controller
+const PCI_CLASS_AHCI: (u8, u8, u8) = (0x01, 0x06, 0x01);
+const ABAR_INDEX: u8 = 5;
+
+const HBA_GHC: u64 = 0x04;
+const HBA_PI: u64 = 0x0c;
+const GHC_AE: u32 = 1 << 31;
+
+const PORT_BASE: u64 = 0x100;
+const PORT_SIZE: u64 = 0x80;
+const PORT_CLB: u64 = 0x00;
+const PORT_CLBU: u64 = 0x04;
+const PORT_FB: u64 = 0x08;
+const PORT_FBU: u64 = 0x0c;
+const PORT_IS: u64 = 0x10;
+const PORT_CMD: u64 = 0x18;
+const PORT_TFD: u64 = 0x20;
+const PORT_SIG: u64 = 0x24;
+const PORT_SSTS: u64 = 0x28;
+const PORT_SERR: u64 = 0x30;
+const PORT_CI: u64 = 0x38;
+
+const CMD_ST: u32 = 1 << 0;
+const CMD_FRE: u32 = 1 << 4;
+const CMD_FR: u32 = 1 << 14;
+const CMD_CR: u32 = 1 << 15;
+
+/// The port has a device and talks to it
+const SSTS_PRESENT: u32 = 0x3;
+/// Signature of a SATA disk, ATAPI and others differ
+const SIG_ATA: u32 = 0x0000_0101;
+/// Task file error in the interrupt status of a port
+const IS_TFES: u32 = 1 << 30;
+
+const TFD_ERR: u32 = 1 << 0;
+const TFD_DRQ: u32 = 1 << 3;
+const TFD_BSY: u32 = 1 << 7;
+
+/// Where each structure is in the frame of a port
+const CLB_OFFSET: u64 = 0x000;
+const FB_OFFSET: u64 = 0x400;
+const CTBA_OFFSET: u64 = 0x500;
+/// Where the physical region table is in the command table
+const PRDT_OFFSET: u64 = 0x80;
+
+const FIS_TYPE_H2D: u8 = 0x27;
+/// Dwords of a host to device register FIS
+const FIS_H2D_LEN: u32 = 5;
+
+const CMD_READ_DMA_EXT: u8 = 0x25;
+const CMD_WRITE_DMA_EXT: u8 = 0x35;
+const CMD_IDENTIFY: u8 = 0xec;
+
+static DISKS: Once<Vec<Arc<AhciDisk>>> = Once::new();
+
+/// The registers and DMA memory of a port
+struct Port {
+    /// virtual address of the registers
+    regs: u64,
+    /// physical address of the frame of the command structures
+    mem: u64,
+    /// physical address of the frame a block is moved through
+    data: u64,
+}
+
+impl Port {
+    fn read(&self, reg: u64) -> u32 {
+        unsafe { read_volatile((self.regs + reg) as *const u32) }
+    }
+
+    fn write(&self, reg: u64, value: u32) {
+        unsafe { write_volatile((self.regs + reg) as *mut u32, value) }
+    }
+
+    /// The memory at physical `addr`
+    fn virt<T>(addr: u64) -> *mut T {
+        physical_to_virtual(addr) as *mut T
+    }
+
+    fn stop(&self) {
+        self.write(PORT_CMD, self.read(PORT_CMD) & !CMD_ST);
+        while self.read(PORT_CMD) & CMD_CR != 0 {}
+        self.write(PORT_CMD, self.read(PORT_CMD) & !CMD_FRE);
+        while self.read(PORT_CMD) & CMD_FR != 0 {}
+    }
+
+    fn start(&self) {
+        while self.read(PORT_CMD) & CMD_CR != 0 {}
+        self.write(PORT_CMD, self.read(PORT_CMD) | CMD_FRE);
+        self.write(PORT_CMD, self.read(PORT_CMD) | CMD_ST);
+    }
+
+    /// Point the port at its command list and received FIS
+    fn setup(&self) {
+        self.stop();
+
+        unsafe { core::ptr::write_bytes(Self::virt::<u8>(self.mem), 0, 4096) };
+        let clb = self.mem + CLB_OFFSET;
+        let fb = self.mem + FB_OFFSET;
+        self.write(PORT_CLB, clb as u32);
+        self.write(PORT_CLBU, (clb >> 32) as u32);
+        self.write(PORT_FB, fb as u32);
+        self.write(PORT_FBU, (fb >> 32) as u32);
+
+        // the bits are cleared by writing them
+        self.write(PORT_SERR, !0);
+        self.write(PORT_IS, !0);
+
+        self.start();
+    }
+
+    /// Run `command` on `count` blocks at `lba`, moved through the data frame
+    fn command(&self, command: u8, lba: usize, count: usize, write: bool) -> Result<(), ()> {
+        while self.read(PORT_TFD) & (TFD_BSY | TFD_DRQ) != 0 {}
+
+        let ctba = self.mem + CTBA_OFFSET;
+        unsafe {
+            // the header of slot 0: FIS length, direction, one PRDT entry
+            let header = Self::virt::<u32>(self.mem + CLB_OFFSET);
+            header.write_volatile(FIS_H2D_LEN | (write as u32) << 6 | 1 << 16);
+            header.add(1).write_volatile(0);
+            header.add(2).write_volatile(ctba as u32);
+            header.add(3).write_volatile((ctba >> 32) as u32);
+
+            let table = Self::virt::<u8>(ctba);
+            core::ptr::write_bytes(table, 0, PRDT_OFFSET as usize + 16);
+
+            let fis = core::slice::from_raw_parts_mut(table, 20);
+            fis[0] = FIS_TYPE_H2D;
+            // the FIS is a command, not a control
+            fis[1] = 1 << 7;
+            fis[2] = command;
+            fis[4..7].copy_from_slice(&lba.to_le_bytes()[0..3]);
+            // LBA addressing
+            fis[7] = 1 << 6;
+            fis[8..11].copy_from_slice(&lba.to_le_bytes()[3..6]);
+            fis[12..14].copy_from_slice(&(count as u16).to_le_bytes());
+
+            let prdt = Self::virt::<u32>(ctba + PRDT_OFFSET);
+            prdt.write_volatile(self.data as u32);
+            prdt.add(1).write_volatile((self.data >> 32) as u32);
+            prdt.add(3).write_volatile((count * BLOCK_SIZE - 1) as u32);
+        }
+
+        // the controller must see the command before it is issued
+        fence(Ordering::SeqCst);
+        self.write(PORT_IS, !0);
+        self.write(PORT_CI, 1);
+
+        while self.read(PORT_CI) & 1 != 0 {
+            if self.read(PORT_IS) & IS_TFES != 0 {
+                return Err(());
+            }
+        }
+        fence(Ordering::SeqCst);
+
+        match self.read(PORT_TFD) & TFD_ERR {
+            0 => Ok(()),
+            _ => Err(()),
+        }
+    }
+
+    /// Copy the data frame to `buf`
+    fn load(&self, buf: &mut Block) {
+        unsafe { buf.copy_from_slice(&*Self::virt::<Block>(self.data)) }
+    }
+
+    /// Copy `buf` to the data frame
+    fn store(&self, buf: &Block) {
+        unsafe { (*Self::virt::<Block>(self.data)).copy_from_slice(buf) }
+    }
+}
+
+pub struct AhciDisk {
+    port: Mutex<Port>,
+    index: usize,
+    sectors: usize,
+    /// as the disk reports it, trailing spaces removed
+    pub model: String,
+}
+
+impl AhciDisk {
+    /// Set up port `index` at `regs` and IDENTIFY its disk
+    fn new(regs: u64, index: usize) -> Option<Self> {
+        let port = Port {
+            regs: regs + PORT_BASE + PORT_SIZE * index as u64,
+            mem: 0,
+            data: 0,
+        };
+        if port.read(PORT_SSTS) & 0xf != SSTS_PRESENT || port.read(PORT_SIG) != SIG_ATA {
+            return None;
+        }
+
+        let mut frames = get_frame_alloc_for_sure();
+        let port = Port {
+            mem: frames.allocate_frame()?.start_address().as_u64(),
+            data: frames.allocate_frame()?.start_address().as_u64(),
+            ..port
+        };
+        drop(frames);
+
+        port.setup();
+        port.command(CMD_IDENTIFY, 0, 1, false).ok()?;
+        let mut id = [0; BLOCK_SIZE];
+        port.load(&mut id);
+
+        // 48-bit LBA keeps the sector count in words 100..104
+        let sectors = match identify_word(&id, 83) & (1 << 10) {
+            0 => identify_word(&id, 60) | identify_word(&id, 61) << 16,
+            _ => (100..104)
+                .rev()
+                .fold(0, |sum, i| sum << 16 | identify_word(&id, i)),
+        };
+
+        Some(Self {
+            port: Mutex::new(port),
+            index,
+            sectors,
+            model: identify_model(&id),
+        })
+    }
+}
+
+impl BlockDevice for AhciDisk {
+    fn block_count(&self) -> usize {
+        self.sectors
+    }
+
+    fn read_block(&self, lba: usize, buf: &mut Block) -> Result<(), BlockError> {
+        if lba >= self.sectors {
+            return Err(BlockError::OutOfRange(lba));
+        }
+
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let port = self.port.lock();
+            port.command(CMD_READ_DMA_EXT, lba, 1, false)
+                .map_err(|_| BlockError::Io(lba))?;
+            port.load(buf);
+            Ok(())
+        })
+    }
+
+    fn write_block(&self, lba: usize, buf: &Block) -> Result<(), BlockError> {
+        if lba >= self.sectors {
+            return Err(BlockError::OutOfRange(lba));
+        }
+
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let port = self.port.lock();
+            port.store(buf);
+            port.command(CMD_WRITE_DMA_EXT, lba, 1, true)
+                .map_err(|_| BlockError::Io(lba))
+        })
+    }
+}
+
+/// Look for the disks of every AHCI controller, needs the frame allocator
+pub fn init() {
+    let disks = DISKS.call_once(|| {
+        let mut disks = Vec::new();
+        for dev in pci::scan() {
+            if (dev.class, dev.subclass, dev.prog_if) != PCI_CLASS_AHCI {
+                continue;
+            }
+            let Some(abar) = dev.memory_bar(ABAR_INDEX) else {
+                continue;
+            };
+
+            dev.enable(COMMAND_MEMORY | COMMAND_BUS_MASTER);
+            let regs = physical_to_virtual(abar);
+            let hba = |reg: u64| (regs + reg) as *mut u32;
+            let ports = unsafe {
+                write_volatile(hba(HBA_GHC), read_volatile(hba(HBA_GHC)) | GHC_AE);
+                read_volatile(hba(HBA_PI))
+            };
+
+            info!(
+                "AHCI: controller {:04x}:{:04x} at {:#x}",
+                dev.vendor_id, dev.device_id, abar
+            );
+            for index in (0..32).filter(|i| ports & (1 << i) != 0) {
+                if let Some(disk) = AhciDisk::new(regs, index) {
+                    disks.push(Arc::new(disk));
+                }
+            }
+        }
+        disks
+    });
+
+    for disk in disks {
+        info!(
+            "AHCI: port {}: {}, {} sectors",
+            disk.index, disk.model, disk.sectors
+        );
+    }
+}
+
+/// Every SATA disk found, in port order
+pub fn disks() -> &'static [Arc<AhciDisk>] {
+    DISKS.get().map(Vec::as_slice).unwrap_or_default()
+}
@@ -0,0 +1,453 @@
+//! ATA disks on the legacy IDE channels, read a sector at a time with PIO
+//!
+//! each channel has a master and a slave drive, found by IDENTIFY at init.
+//! Once interrupts are set up, see `enable_irq`, a syscall reads in the
+//! background: the request of each drive waits in its queue, the channel
+//! reads one at a time, a sector for each interrupt, into the cache of the
+//! drive. Other reads poll the drive until done, see `drivers::block`.
+//! Only 28-bit LBA is used, enough for the first 128 GiB of a disk.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::num::NonZeroUsize;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lru::LruCache;
+use spin::{Mutex, Once};
+use x86_64::instructions::port::Port;
+
+use super::block::{
+    self, Block, BlockDevice, BlockError, Request, RequestQueue, BLOCK_SIZE, STATS,
+};
+use crate::proc::ProcessId;
+
+/// The I/O and control ports of the primary and secondary channel
+const CHANNELS: [(u16, u16); 2] = [(0x1f0, 0x3f6), (0x170, 0x376)];
+
+const REG_DATA: u16 = 0;
+const REG_SECTOR_COUNT: u16 = 2;
+const REG_LBA_LOW: u16 = 3;
+const REG_LBA_MID: u16 = 4;
+const REG_LBA_HIGH: u16 = 5;
+const REG_DRIVE: u16 = 6;
+const REG_COMMAND: u16 = 7;
+const REG_STATUS: u16 = 7;
+
+const STATUS_ERR: u8 = 1 << 0;
+const STATUS_DRQ: u8 = 1 << 3;
+const STATUS_DF: u8 = 1 << 5;
+const STATUS_BSY: u8 = 1 << 7;
+
+/// Device control bit that masks the interrupt of the channel
+const CONTROL_NIEN: u8 = 1 << 1;
+
+const CMD_READ_SECTORS: u8 = 0x20;
+const CMD_IDENTIFY: u8 = 0xec;
+
+/// Largest sector a 28-bit LBA reaches, plus one
+const LBA28_LIMIT: usize = 1 << 28;
+/// Blocks each drive keeps once read
+const CACHE_BLOCKS: usize = 256;
+
+/// A request of a drive the channel reads, the blocks read so far
+struct Active {
+    drive: &'static AtaDrive,
+    req: Request,
+    data: Vec<u8>,
+}
+
+/// The read each channel works on, taken with interrupts disabled,
+/// selecting a drive and running a command must not be interleaved
+static ACTIVE: [Mutex<Option<Active>>; 2] = [Mutex::new(None), Mutex::new(None)];
+static DRIVES: Once<Vec<Arc<AtaDrive>>> = Once::new();
+/// Whether the drives raise interrupts, so a syscall may sleep on them
+static ASYNC: AtomicBool = AtomicBool::new(false);
+
+pub struct AtaDrive {
+    channel: usize,
+    slave: bool,
+    sectors: usize,
+    /// as the drive reports it, trailing spaces removed
+    pub model: String,
+    queue: Mutex<RequestQueue>,
+    /// and whether the block was read ahead and not read since
+    cache: Mutex<LruCache<usize, (Box<Block>, bool)>>,
+    /// the blocks being read ahead
+    ahead: Mutex<BTreeSet<usize>>,
+}
+
+/// Word `i` of the data IDENTIFY returns
+pub fn identify_word(id: &Block, i: usize) -> usize {
+    u16::from_le_bytes([id[i * 2], id[i * 2 + 1]]) as usize
+}
+
+/// The model in the data IDENTIFY returns, trailing spaces removed
+pub fn identify_model(id: &Block) -> String {
+    // 40 chars, each word holding two swapped
+    (27..47)
+        .flat_map(|i| {
+            let [low, high] = (identify_word(id, i) as u16).to_le_bytes();
+            [high as char, low as char]
+        })
+        .collect::<String>()
+        .trim_end()
+        .into()
+}
+
+fn inb(port: u16) -> u8 {
+    unsafe { Port::new(port).read() }
+}
+
+fn outb(port: u16, value: u8) {
+    unsafe { Port::new(port).write(value) }
+}
+
+impl AtaDrive {
+    fn io(&self) -> u16 {
+        CHANNELS[self.channel].0
+    }
+
+    /// Select the drive, `lba_high` is the top 4 bits of a 28-bit LBA
+    fn select(io: u16, ctrl: u16, slave: bool, lba_high: u8) {
+        outb(
+            io + REG_DRIVE,
+            0xe0 | (slave as u8) << 4 | (lba_high & 0x0f),
+        );
+        // the drive takes 400ns to show its status, 4 reads of the port
+        for _ in 0..4 {
+            inb(ctrl);
+        }
+    }
+
+    /// Whether a sector can be read, `None` while the drive is busy
+    fn data_ready(io: u16) -> Option<Result<(), ()>> {
+        let status = inb(io + REG_STATUS);
+        if status & STATUS_BSY != 0 {
+            None
+        } else if status & (STATUS_ERR | STATUS_DF) != 0 {
+            Some(Err(()))
+        } else if status & STATUS_DRQ != 0 {
+            Some(Ok(()))
+        } else {
+            None
+        }
+    }
+
+    /// Wait until the drive is not busy, then for data or an error
+    fn wait_data(io: u16) -> Result<(), ()> {
+        loop {
+            if let Some(ready) = Self::data_ready(io) {
+                return ready;
+            }
+        }
+    }
+
+    /// Send a read of `count` sectors from `lba`, at most 256
+    fn command_read(&self, lba: usize, count: usize) {
+        let (io, ctrl) = CHANNELS[self.channel];
+        Self::select(io, ctrl, self.slave, (lba >> 24) as u8);
+        // a count of 0 is 256 sectors
+        outb(io + REG_SECTOR_COUNT, count as u8);
+        outb(io + REG_LBA_LOW, lba as u8);
+        outb(io + REG_LBA_MID, (lba >> 8) as u8);
+        outb(io + REG_LBA_HIGH, (lba >> 16) as u8);
+        outb(io + REG_COMMAND, CMD_READ_SECTORS);
+    }
+
+    fn read_words(io: u16, buf: &mut [u8]) {
+        let mut data = Port::<u16>::new(io + REG_DATA);
+        for word in buf.as_chunks_mut::<2>().0 {
+            *word = unsafe { data.read() }.to_le_bytes();
+        }
+    }
+
+    /// IDENTIFY the drive, `None` if there is none or it is not ATA
+    fn identify(channel: usize, slave: bool) -> Option<Self> {
+        let (io, ctrl) = CHANNELS[channel];
+
+        // nothing is attached where the status floats high
+        if inb(io + REG_STATUS) == 0xff {
+            return None;
+        }
+
+        outb(ctrl, CONTROL_NIEN);
+        Self::select(io, ctrl, slave, 0);
+        for reg in [REG_SECTOR_COUNT, REG_LBA_LOW, REG_LBA_MID, REG_LBA_HIGH] {
+            outb(io + reg, 0);
+        }
+        outb(io + REG_COMMAND, CMD_IDENTIFY);
+
+        if inb(io + REG_STATUS) == 0 {
+            return None;
+        }
+        while inb(io + REG_STATUS) & STATUS_BSY != 0 {}
+
+        // ATAPI and SATA drives answer with a signature here instead
+        if inb(io + REG_LBA_MID) != 0 || inb(io + REG_LBA_HIGH) != 0 {
+            return None;
+        }
+        Self::wait_data(io).ok()?;
+
+        let mut id = [0; BLOCK_SIZE];
+        Self::read_words(io, &mut id);
+        let word = |i: usize| identify_word(&id, i);
+
+        Some(Self {
+            channel,
+            slave,
+            sectors: (word(60) | word(61) << 16).min(LBA28_LIMIT),
+            model: identify_model(&id),
+            queue: Mutex::new(RequestQueue::default()),
+            cache: Mutex::new(LruCache::new(NonZeroUsize::new(CACHE_BLOCKS).unwrap())),
+            ahead: Mutex::new(BTreeSet::new()),
+        })
+    }
+
+    /// The channel and position of the drive, e.g. `primary master`
+    pub fn position(&self) -> (&'static str, &'static str) {
+        (
+            ["primary", "secondary"][self.channel],
+            if self.slave { "slave" } else { "master" },
+        )
+    }
+}
+
+impl core::fmt::Debug for AtaDrive {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_struct("AtaDrive")
+            .field("channel", &self.channel)
+            .field("slave", &self.slave)
+            .field("sectors", &self.sectors)
+            .field("model", &self.model)
+            .finish()
+    }
+}
+
+/// Read the sector the drive of `active` has ready
+///
+/// return the waiters of the request once it is read or failed.
+fn service(
+    channel: usize,
+    active: &mut Option<Active>,
+    ready: Result<(), ()>,
+) -> Option<Vec<ProcessId>> {
+    let (io, _) = CHANNELS[channel];
+    let current = active.as_mut()?;
+
+    if ready.is_err() {
+        warn!(
+            "ATA: failed to read blocks {}..{}",
+            current.req.lba,
+            current.req.lba + current.req.count
+        );
+        return active.take().map(|active| active.req.waiters);
+    }
+
+    let start = current.data.len();
+    current.data.resize(start + BLOCK_SIZE, 0);
+    AtaDrive::read_words(io, &mut current.data[start..]);
+    if current.data.len() < current.req.count * BLOCK_SIZE {
+        return None;
+    }
+
+    let Active { drive, req, data } = active.take()?;
+    let mut cache = drive.cache.lock();
+    let mut ahead = drive.ahead.lock();
+    for (i, block) in data.as_chunks::<BLOCK_SIZE>().0.iter().enumerate() {
+        let lba = req.lba + i;
+        cache.put(lba, (Box::new(*block), ahead.remove(&lba)));
+    }
+    Some(req.waiters)
+}
+
+/// Start the next request of a drive of `channel`, if the channel is idle
+fn start_next(channel: usize, active: &mut Option<Active>) {
+    if active.is_some() {
+        return;
+    }
+
+    for drive in drives().iter().filter(|drive| drive.channel == channel) {
+        if let Some(req) = drive.queue.lock().take_next() {
+            drive.command_read(req.lba, req.count);
+            *active = Some(Active {
+                drive,
+                data: Vec::with_capacity(req.count * BLOCK_SIZE),
+                req,
+            });
+            return;
+        }
+    }
+}
+
+impl BlockDevice for AtaDrive {
+    fn block_count(&self) -> usize {
+        self.sectors
+    }
+
+    fn read_block(&self, lba: usize, buf: &mut Block) -> Result<(), BlockError> {
+        if lba >= self.sectors {
+            return Err(BlockError::OutOfRange(lba));
+        }
+
+        let mut completed = Vec::new();
+        let ret = x86_64::instructions::interrupts::without_interrupts(|| {
+            if let Some((block, ahead)) = self.cache.lock().get_mut(&lba) {
+                buf.copy_from_slice(block.as_ref());
+                STATS.hits.fetch_add(1, Ordering::Relaxed);
+                if core::mem::take(ahead) {
+                    STATS.read_ahead_hits.fetch_add(1, Ordering::Relaxed);
+                }
+                return Ok(());
+            }
+            STATS.misses.fetch_add(1, Ordering::Relaxed);
+
+            let pid = crate::proc::current_pid();
+            if ASYNC.load(Ordering::Relaxed) && block::may_sleep(pid) {
+                self.queue.lock().submit(lba, Some(pid));
+                start_next(self.channel, &mut ACTIVE[self.channel].lock());
+                block::sleep(pid);
+                return Err(BlockError::Pending(lba));
+            }
+
+            let io = self.io();
+            let mut active = ACTIVE[self.channel].lock();
+
+            // the read the channel works on goes first, its interrupt is held off
+            while active.is_some() {
+                let ready = Self::wait_data(io);
+                if let Some(waiters) = service(self.channel, &mut active, ready) {
+                    completed.extend(waiters);
+                }
+            }
+
+            self.command_read(lba, 1);
+            let ret = Self::wait_data(io).map_err(|_| BlockError::Io(lba));
+            if ret.is_ok() {
+                Self::read_words(io, buf);
+                self.ahead.lock().remove(&lba);
+                self.cache.lock().put(lba, (Box::new(*buf), false));
+            }
+
+            start_next(self.channel, &mut active);
+            ret
+        });
+
+        if !completed.is_empty() {
+            block::complete(completed);
+        }
+        ret
+    }
+
+    fn read_cached(&self, lba: usize, buf: &mut Block) -> bool {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            match self.cache.lock().peek(&lba) {
+                Some((block, _)) => {
+                    buf.copy_from_slice(block.as_ref());
+                    true
+                }
+                None => false,
+            }
+        })
+    }
+
+    /// Queue the blocks not cached nor asked for yet, at most half the cache
+    fn prefetch(&self, lba: usize, count: usize) {
+        if !ASYNC.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let count = count
+            .min(CACHE_BLOCKS / 2)
+            .min(self.sectors.saturating_sub(lba));
+
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let mut active = ACTIVE[self.channel].lock();
+            let mut queued = 0;
+            {
+                let mut queue = self.queue.lock();
+                let cache = self.cache.lock();
+                let mut ahead = self.ahead.lock();
+                for lba in lba..lba + count {
+                    let reading = active.as_ref().is_some_and(|active| {
+                        core::ptr::eq(active.drive, self) && active.req.contains(lba)
+                    });
+                    if reading || cache.contains(&lba) || queue.contains(lba) {
+                        continue;
+                    }
+                    queue.submit(lba, None);
+                    ahead.insert(lba);
+                    queued += 1;
+                }
+            }
+
+            STATS.read_ahead.fetch_add(queued, Ordering::Relaxed);
+            start_next(self.channel, &mut active);
+        })
+    }
+}
+
+/// The interrupt of `channel`, a sector of the read it works on is ready
+pub fn interrupt(channel: usize) {
+    let waiters = {
+        let mut active = ACTIVE[channel].lock();
+        // reading the status acknowledges the interrupt of the drive
+        let ready = match AtaDrive::data_ready(CHANNELS[channel].0) {
+            Some(ready) => ready,
+            None => return,
+        };
+        let waiters = service(channel, &mut active, ready);
+        start_next(channel, &mut active);
+        waiters
+    };
+
+    if let Some(waiters) = waiters {
+        block::complete(waiters);
+    }
+}
+
+/// Let the drives raise their interrupts, the channels have them enabled
+pub fn enable_irq() {
+    for channel in 0..CHANNELS.len() {
+        if has_drives(channel) {
+            let _active = ACTIVE[channel].lock();
+            outb(CHANNELS[channel].1, 0);
+        }
+    }
+    ASYNC.store(true, Ordering::Relaxed);
+}
+
+/// Whether `channel` has a drive, so its interrupt is worth enabling
+pub fn has_drives(channel: usize) -> bool {
+    drives().iter().any(|drive| drive.channel == channel)
+}
+
+/// Look for the drives of both channels
+pub fn init() {
+    let drives = DRIVES.call_once(|| {
+        let mut drives = Vec::new();
+        for (channel, active) in ACTIVE.iter().enumerate() {
+            let _active = active.lock();
+            for slave in [false, true] {
+                if let Some(drive) = AtaDrive::identify(channel, slave) {
+                    drives.push(Arc::new(drive));
+                }
+            }
+        }
+        drives
+    });
+
+    for drive in drives {
+        let (channel, position) = drive.position();
+        info!(
+            "ATA: {} {}: {}, {} sectors",
+            channel, position, drive.model, drive.sectors
+        );
+    }
+}
+
+/// Every ATA drive found, in channel order, masters first
+pub fn drives() -> &'static [Arc<AtaDrive>] {
+    DRIVES.get().map(Vec::as_slice).unwrap_or_default()
+}
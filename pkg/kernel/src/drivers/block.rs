@@ -0,0 +1,444 @@
+//! Block devices, read a sector at a time by the filesystems
+//!
+//! the devices are the boot partition the bootloader loads into memory,
+//! see `boot::fs::load_boot_disk`, the ATA disks, see `drivers::ata`,
+//! and the SATA disks of an AHCI controller, see `drivers::ahci`.
+//!
+//! a disk reads in the background: a syscall that misses the cache of the
+//! disk queues a `Request` and fails with `BlockError::Pending`, then the
+//! process sleeps until the interrupt of the disk completes the request,
+//! and runs the syscall again, see `end_syscall`. Reads outside a syscall,
+//! or of a syscall that slept too often, wait for the disk instead.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+
+use crate::proc::ProcessId;
+use crate::sysctl::Tunable;
+
+/// Bytes of a block, the sector size of every device here
+pub const BLOCK_SIZE: usize = 512;
+
+pub type Block = [u8; BLOCK_SIZE];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockError {
+    /// the block is past the end of the device
+    OutOfRange(usize),
+    /// the device failed to read the block
+    Io(usize),
+    /// the block is being read, the syscall runs again once it is
+    Pending(usize),
+    /// the device cannot be written
+    ReadOnly,
+}
+
+impl fmt::Display for BlockError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::OutOfRange(lba) => write!(f, "block {} is out of range", lba),
+            Self::Io(lba) => write!(f, "failed to read block {}", lba),
+            Self::Pending(lba) => write!(f, "block {} is being read", lba),
+            Self::ReadOnly => write!(f, "read-only device"),
+        }
+    }
+}
+
+pub trait BlockDevice: Send + Sync {
+    /// Number of blocks of the device
+    fn block_count(&self) -> usize;
+
+    /// Read block `lba` into `buf`
+    fn read_block(&self, lba: usize, buf: &mut Block) -> Result<(), BlockError>;
+
+    /// Write `buf` to block `lba`
+    fn write_block(&self, _lba: usize, _buf: &Block) -> Result<(), BlockError> {
+        Err(BlockError::ReadOnly)
+    }
+
+    /// Read block `lba` into `buf` only if the device need not be waited for
+    fn read_cached(&self, _lba: usize, _buf: &mut Block) -> bool {
+        false
+    }
+
+    /// Start reading blocks `lba..lba + count` into the cache of the device,
+    /// so later reads of them need not wait
+    fn prefetch(&self, _lba: usize, _count: usize) {}
+}
+
+/// A device backed by memory the bootloader filled
+pub struct RamDisk {
+    data: &'static [u8],
+}
+
+impl RamDisk {
+    pub fn new(data: &'static [u8]) -> Self {
+        Self { data }
+    }
+}
+
+impl BlockDevice for RamDisk {
+    fn block_count(&self) -> usize {
+        self.data.len() / BLOCK_SIZE
+    }
+
+    fn read_cached(&self, lba: usize, buf: &mut Block) -> bool {
+        self.read_block(lba, buf).is_ok()
+    }
+
+    fn read_block(&self, lba: usize, buf: &mut Block) -> Result<(), BlockError> {
+        let start = lba * BLOCK_SIZE;
+        let block = self
+            .data
+            .get(start..start + BLOCK_SIZE)
+            .ok_or(BlockError::OutOfRange(lba))?;
+        buf.copy_from_slice(block);
+        Ok(())
+    }
+}
+
+/// The blocks `start..start + count` of another device, e.g. a partition
+pub struct Slice {
+    dev: Arc<dyn BlockDevice>,
+    start: usize,
+    count: usize,
+}
+
+impl Slice {
+    pub fn new(dev: Arc<dyn BlockDevice>, start: usize, count: usize) -> Self {
+        let count = count.min(dev.block_count().saturating_sub(start));
+        Self { dev, start, count }
+    }
+}
+
+impl BlockDevice for Slice {
+    fn block_count(&self) -> usize {
+        self.count
+    }
+
+    fn read_block(&self, lba: usize, buf: &mut Block) -> Result<(), BlockError> {
+        if lba >= self.count {
+            return Err(BlockError::OutOfRange(lba));
+        }
+        self.dev.read_block(self.start + lba, buf)
+    }
+
+    fn write_block(&self, lba: usize, buf: &Block) -> Result<(), BlockError> {
+        if lba >= self.count {
+            return Err(BlockError::OutOfRange(lba));
+        }
+        self.dev.write_block(self.start + lba, buf)
+    }
+
+    fn read_cached(&self, lba: usize, buf: &mut Block) -> bool {
+        lba < self.count && self.dev.read_cached(self.start + lba, buf)
+    }
+
+    fn prefetch(&self, lba: usize, count: usize) {
+        let count = count.min(self.count.saturating_sub(lba));
+        if count > 0 {
+            self.dev.prefetch(self.start + lba, count);
+        }
+    }
+}
+
+/// Most blocks a file is read ahead of its reads, 0 to never read ahead
+pub static READ_AHEAD: Tunable = Tunable::new("block.readahead", 32);
+
+/// How the caches of the disks did, shown in `/dev/diskstats`
+pub struct CacheStats {
+    pub hits: AtomicUsize,
+    pub misses: AtomicUsize,
+    /// blocks read ahead of a read that asked for them
+    pub read_ahead: AtomicUsize,
+    /// hits on blocks read ahead, counted once each
+    pub read_ahead_hits: AtomicUsize,
+}
+
+pub static STATS: CacheStats = CacheStats {
+    hits: AtomicUsize::new(0),
+    misses: AtomicUsize::new(0),
+    read_ahead: AtomicUsize::new(0),
+    read_ahead_hits: AtomicUsize::new(0),
+};
+
+impl CacheStats {
+    /// One `name value` line for each counter, and the hit rates
+    pub fn report(&self) -> String {
+        let load = |counter: &AtomicUsize| counter.load(Ordering::Relaxed);
+        let (hits, misses) = (load(&self.hits), load(&self.misses));
+        let (ahead, ahead_hits) = (load(&self.read_ahead), load(&self.read_ahead_hits));
+        let percent = |part: usize, total: usize| match total {
+            0 => 0,
+            total => part * 100 / total,
+        };
+
+        let mut out = String::new();
+        let _ = writeln!(out, "hits {}", hits);
+        let _ = writeln!(out, "misses {}", misses);
+        let _ = writeln!(out, "hit_rate {}%", percent(hits, hits + misses));
+        let _ = writeln!(out, "read_ahead {}", ahead);
+        let _ = writeln!(out, "read_ahead_hits {}", ahead_hits);
+        let _ = writeln!(out, "read_ahead_used {}%", percent(ahead_hits, ahead));
+        out
+    }
+}
+
+/// Most blocks one request reads, as the sector count of a command
+pub const MAX_REQUEST_BLOCKS: usize = 64;
+/// Times a syscall may sleep on the disk before it waits for it
+const SLEEP_LIMIT: usize = 32;
+
+/// Blocks `lba..lba + count` to read, and the processes waiting for them
+#[derive(Debug)]
+pub struct Request {
+    pub lba: usize,
+    pub count: usize,
+    pub waiters: Vec<ProcessId>,
+}
+
+impl Request {
+    pub fn contains(&self, lba: usize) -> bool {
+        (self.lba..self.lba + self.count).contains(&lba)
+    }
+
+    fn wait(&mut self, pid: Option<ProcessId>) {
+        if let Some(pid) = pid.filter(|pid| !self.waiters.contains(pid)) {
+            self.waiters.push(pid);
+        }
+    }
+}
+
+/// The reads waiting for a device, in the order of an elevator
+///
+/// requests are served going up from the last block read, then down
+/// again once none is left above (LOOK). A block next to a request
+/// waiting is merged into it, so the device reads both with one command.
+#[derive(Debug, Default)]
+pub struct RequestQueue {
+    /// ordered by block, never overlapping
+    requests: Vec<Request>,
+    head: usize,
+    down: bool,
+}
+
+impl RequestQueue {
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
+
+    /// Whether a request waiting reads block `lba`
+    pub fn contains(&self, lba: usize) -> bool {
+        self.requests.iter().any(|req| req.contains(lba))
+    }
+
+    /// Queue a read of block `lba` that `pid` waits for, if any
+    pub fn submit(&mut self, lba: usize, pid: Option<ProcessId>) {
+        if let Some(req) = self.requests.iter_mut().find(|req| req.contains(lba)) {
+            req.wait(pid);
+            return;
+        }
+
+        let at = self.requests.partition_point(|req| req.lba < lba);
+        let grows = |req: &Request| req.count < MAX_REQUEST_BLOCKS;
+
+        // right after the one before
+        if at > 0
+            && self.requests[at - 1].lba + self.requests[at - 1].count == lba
+            && grows(&self.requests[at - 1])
+        {
+            let req = &mut self.requests[at - 1];
+            req.count += 1;
+            req.wait(pid);
+
+            // and it may now touch the one after
+            if at < self.requests.len()
+                && self.requests[at].lba == lba + 1
+                && self.requests[at - 1].count + self.requests[at].count <= MAX_REQUEST_BLOCKS
+            {
+                let next = self.requests.remove(at);
+                let req = &mut self.requests[at - 1];
+                req.count += next.count;
+                for pid in next.waiters {
+                    req.wait(Some(pid));
+                }
+            }
+            return;
+        }
+
+        // right before the one after
+        if at < self.requests.len() && self.requests[at].lba == lba + 1 && grows(&self.requests[at])
+        {
+            let req = &mut self.requests[at];
+            req.lba = lba;
+            req.count += 1;
+            req.wait(pid);
+            return;
+        }
+
+        self.requests.insert(
+            at,
+            Request {
+                lba,
+                count: 1,
+                waiters: pid.into_iter().collect(),
+            },
+        );
+    }
+
+    /// Take the request to serve next
+    pub fn take_next(&mut self) -> Option<Request> {
+        if self.requests.is_empty() {
+            return None;
+        }
+
+        let above = self.requests.partition_point(|req| req.lba < self.head);
+        let below = self.requests.partition_point(|req| req.lba <= self.head);
+        if !self.down && above == self.requests.len() {
+            self.down = true;
+        } else if self.down && below == 0 {
+            self.down = false;
+        }
+
+        let req = match self.down {
+            false => self.requests.remove(above.min(self.requests.len() - 1)),
+            true => self.requests.remove(below.max(1) - 1),
+        };
+        self.head = match self.down {
+            false => req.lba + req.count,
+            true => req.lba,
+        };
+        Some(req)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SleepState {
+    Running,
+    /// the syscall asked for a block not read yet
+    Waiting,
+    /// the block it asked for was read before the syscall returned
+    Ready,
+}
+
+/// The syscalls running now that may sleep on a disk, by process
+///
+/// a process keeps its entry while the syscall runs again, the times
+/// it slept with it.
+static SYSCALLS: Mutex<BTreeMap<ProcessId, (SleepState, usize)>> = Mutex::new(BTreeMap::new());
+
+/// What the syscall dispatcher does once a syscall returns
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallEnd {
+    /// the syscall is done, its result stands
+    Done,
+    /// run the syscall again, the block it waited for was read already
+    Restart,
+    /// block the process, it runs the syscall again when woken
+    Sleep,
+}
+
+/// A syscall of `pid` starts, a read of it may sleep from now on
+pub fn begin_syscall(pid: ProcessId) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        SYSCALLS
+            .lock()
+            .entry(pid)
+            .or_insert((SleepState::Running, 0))
+            .0 = SleepState::Running;
+    })
+}
+
+/// The syscall of `pid` returned, see `SyscallEnd`
+pub fn end_syscall(pid: ProcessId) -> SyscallEnd {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut syscalls = SYSCALLS.lock();
+        match syscalls.get(&pid).map(|&(state, _)| state) {
+            Some(SleepState::Waiting) => SyscallEnd::Sleep,
+            Some(SleepState::Ready) => SyscallEnd::Restart,
+            _ => {
+                syscalls.remove(&pid);
+                SyscallEnd::Done
+            }
+        }
+    })
+}
+
+/// Whether the syscall of `pid` failed on a block it sleeps on,
+/// its result is dropped anyway
+pub fn is_pending(pid: ProcessId) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        SYSCALLS
+            .lock()
+            .get(&pid)
+            .is_some_and(|&(state, _)| state != SleepState::Running)
+    })
+}
+
+/// Whether a read of `pid` may sleep instead of waiting for the disk,
+/// as it does for good once it has
+pub fn may_sleep(pid: ProcessId) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        SYSCALLS
+            .lock()
+            .get(&pid)
+            .is_some_and(|&(state, slept)| state != SleepState::Running || slept < SLEEP_LIMIT)
+    })
+}
+
+/// The syscall of `pid` sleeps until the request it is a waiter of completes
+pub fn sleep(pid: ProcessId) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        if let Some((state, slept)) = SYSCALLS.lock().get_mut(&pid) {
+            if *state == SleepState::Running {
+                *slept += 1;
+            }
+            *state = SleepState::Waiting;
+        }
+    })
+}
+
+/// The syscall of `pid` returns what it has done instead of sleeping,
+/// as one that wrote part of its output cannot run again
+///
+/// the block it missed is still read into the cache. Waking the process
+/// once it is does no harm, a blocked syscall runs again anyway.
+pub fn cancel_sleep(pid: ProcessId) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        if let Some((state, _)) = SYSCALLS.lock().get_mut(&pid) {
+            *state = SleepState::Running;
+        }
+    })
+}
+
+/// Run `f` with the reads of `pid` waiting for the disk, for a page
+/// fault, which cannot sleep and run again as a syscall does
+pub fn wait_for_disk<T>(pid: ProcessId, f: impl FnOnce() -> T) -> T {
+    let syscall =
+        x86_64::instructions::interrupts::without_interrupts(|| SYSCALLS.lock().remove(&pid));
+    let ret = f();
+    if let Some(syscall) = syscall {
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            SYSCALLS.lock().insert(pid, syscall);
+        });
+    }
+    ret
+}
+
+/// A request completed, wake its waiters, see `sleep`
+pub fn complete(waiters: Vec<ProcessId>) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut syscalls = SYSCALLS.lock();
+        for pid in &waiters {
+            if let Some((state, _)) = syscalls.get_mut(pid) {
+                *state = SleepState::Ready;
+            }
+        }
+    });
+    crate::proc::wake_blocked(waiters);
+}
@@ -0,0 +1,31 @@
+//! QEMU's `isa-debug-exit` device, how the kernel reports a test result
+//!
+//! a value written to its port makes QEMU exit with `(value << 1) | 1`.
+//! Without the device the write does nothing, so it is always tried
+//! before a shutdown, and only `xtask test` adds the device. The port
+//! is `debug_exit_port` of `boot.conf`, 0 for none.
+
+use core::sync::atomic::{AtomicU16, Ordering};
+use x86_64::instructions::port::Port;
+
+/// Kept in sync with `xtask::qemu::TEST_SUCCESS`
+pub const TEST_SUCCESS: u32 = 0x10;
+/// Kept in sync with `xtask::qemu::TEST_FAILURE`
+pub const TEST_FAILURE: u32 = 0x11;
+
+static PORT: AtomicU16 = AtomicU16::new(0);
+
+pub fn init(boot_info: &'static boot::BootInfo) {
+    PORT.store(boot_info.debug_exit_port, Ordering::Relaxed);
+}
+
+/// Make QEMU exit with the test result, return if there is no device
+pub fn exit(success: bool) {
+    let port = PORT.load(Ordering::Relaxed);
+    if port == 0 {
+        return;
+    }
+
+    let code = if success { TEST_SUCCESS } else { TEST_FAILURE };
+    unsafe { Port::<u32>::new(port).write(code) };
+}
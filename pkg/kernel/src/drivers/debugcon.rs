@@ -0,0 +1,34 @@
+//! QEMU's debug console, `-debugcon`, a port every byte written to is
+//! passed to the host
+//!
+//! it needs no setup and no lock, so it works from the first instruction
+//! of the kernel, before the serial port is initialized. The port is 0xE9
+//! until `boot.conf` says otherwise, `debugcon_port=0` turns it off.
+
+use core::sync::atomic::{AtomicU16, Ordering};
+use x86_64::instructions::port::Port;
+
+const DEFAULT_PORT: u16 = 0xE9;
+
+static PORT: AtomicU16 = AtomicU16::new(DEFAULT_PORT);
+
+pub fn init(boot_info: &'static boot::BootInfo) {
+    PORT.store(boot_info.debugcon_port, Ordering::Relaxed);
+}
+
+#[inline]
+pub fn is_enabled() -> bool {
+    PORT.load(Ordering::Relaxed) != 0
+}
+
+pub fn write_bytes(bytes: &[u8]) {
+    let port = PORT.load(Ordering::Relaxed);
+    if port == 0 {
+        return;
+    }
+
+    let mut port = Port::<u8>::new(port);
+    for &byte in bytes {
+        unsafe { port.write(byte) };
+    }
+}
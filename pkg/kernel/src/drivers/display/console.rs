@@ -0,0 +1,386 @@
+//! The text console drawn on the framebuffer
+//!
+//! the text is kept as cells, so the lines scrolled off the top are kept
+//! in a scrollback buffer and drawn again when paged back to. While paged
+//! back, output still goes to the cells but nothing is drawn.
+
+use alloc::collections::VecDeque;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+
+use super::fb::{Color, FrameBuffer};
+use super::font::Font;
+
+/// Lines kept after they scroll off the top
+const SCROLLBACK_LINES: usize = 512;
+const TAB_WIDTH: usize = 8;
+/// Parameters of an escape sequence kept, the rest are ignored
+const MAX_PARAMS: usize = 8;
+
+/// The 16 colors of ANSI escape codes, as VGA shows them
+const PALETTE: [Color; 16] = [
+    Color::new(0x00, 0x00, 0x00),
+    Color::new(0xaa, 0x00, 0x00),
+    Color::new(0x00, 0xaa, 0x00),
+    Color::new(0xaa, 0x55, 0x00),
+    Color::new(0x00, 0x00, 0xaa),
+    Color::new(0xaa, 0x00, 0xaa),
+    Color::new(0x00, 0xaa, 0xaa),
+    Color::new(0xaa, 0xaa, 0xaa),
+    Color::new(0x55, 0x55, 0x55),
+    Color::new(0xff, 0x55, 0x55),
+    Color::new(0x55, 0xff, 0x55),
+    Color::new(0xff, 0xff, 0x55),
+    Color::new(0x55, 0x55, 0xff),
+    Color::new(0xff, 0x55, 0xff),
+    Color::new(0x55, 0xff, 0xff),
+    Color::new(0xff, 0xff, 0xff),
+];
+
+const DEFAULT_FG: u8 = 7;
+const DEFAULT_BG: u8 = 0;
+
+/// A character and its colors, as indices into the palette
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    ch: u8,
+    fg: u8,
+    bg: u8,
+}
+
+impl Cell {
+    /// An erased cell keeps the background color in use
+    const fn blank(bg: u8) -> Self {
+        Self {
+            ch: b' ',
+            fg: DEFAULT_FG,
+            bg,
+        }
+    }
+}
+
+/// The colors set by SGR escape sequences
+#[derive(Clone, Copy)]
+struct Attr {
+    fg: u8,
+    bg: u8,
+    bold: bool,
+}
+
+impl Attr {
+    const DEFAULT: Attr = Attr {
+        fg: DEFAULT_FG,
+        bg: DEFAULT_BG,
+        bold: false,
+    };
+
+    fn cell(&self, ch: u8) -> Cell {
+        // bold is drawn as the bright variant
+        let fg = if self.bold && self.fg < 8 {
+            self.fg + 8
+        } else {
+            self.fg
+        };
+        Cell {
+            ch,
+            fg,
+            bg: self.bg,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Normal,
+    /// after ESC
+    Escape,
+    /// after ESC [, until the final byte
+    Csi,
+}
+
+pub struct Console {
+    fb: FrameBuffer,
+    font: Font,
+    cols: usize,
+    rows: usize,
+    /// the lines on the screen, `rows * cols` cells
+    screen: Vec<Cell>,
+    /// lines scrolled off the top, oldest first
+    history: VecDeque<Vec<Cell>>,
+    /// lines paged back into the history, 0 shows the screen
+    view: usize,
+    col: usize,
+    row: usize,
+    attr: Attr,
+    state: State,
+    params: [u16; MAX_PARAMS],
+    /// the parameter being read
+    param: usize,
+}
+
+impl Console {
+    pub fn new(fb: FrameBuffer, font: Font) -> Self {
+        let cols = fb.width() / font.width;
+        let rows = fb.height() / font.height;
+
+        let mut console = Self {
+            fb,
+            font,
+            cols,
+            rows,
+            screen: vec![Cell::blank(DEFAULT_BG); cols * rows],
+            history: VecDeque::new(),
+            view: 0,
+            col: 0,
+            row: 0,
+            attr: Attr::DEFAULT,
+            state: State::Normal,
+            params: [0; MAX_PARAMS],
+            param: 0,
+        };
+        console.redraw();
+        console
+    }
+
+    #[inline]
+    pub fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+
+    /// Page back by `lines` into the scrollback, forward if negative
+    pub fn scroll_view(&mut self, lines: isize) {
+        let view = self
+            .view
+            .saturating_add_signed(lines)
+            .min(self.history.len());
+
+        if view != self.view {
+            self.view = view;
+            self.redraw();
+        }
+    }
+
+    fn draw(&mut self, col: usize, row: usize, cell: Cell, inverted: bool) {
+        // a process draws on the screen, the cells are kept for later
+        if super::is_taken() {
+            return;
+        }
+
+        let (x0, y0) = (col * self.font.width, row * self.font.height);
+        let (mut fg, mut bg) = (PALETTE[cell.fg as usize], PALETTE[cell.bg as usize]);
+        if inverted {
+            core::mem::swap(&mut fg, &mut bg);
+        }
+
+        let glyph = self.font.glyph(cell.ch as char);
+        for (y, bits) in self.font.rows(glyph).enumerate() {
+            for x in 0..self.font.width {
+                let color = if self.font.is_set(bits, x) { fg } else { bg };
+                self.fb.put_pixel(x0 + x, y0 + y, color);
+            }
+        }
+    }
+
+    /// Draw the cell at `index` of the screen, if the screen is shown
+    fn draw_at(&mut self, index: usize) {
+        if self.view == 0 {
+            let cell = self.screen[index];
+            self.draw(index % self.cols, index / self.cols, cell, false);
+        }
+    }
+
+    /// The cursor is drawn inverted, at the last column while a wrap is pending
+    fn draw_cursor(&mut self, shown: bool) {
+        if self.view == 0 {
+            let col = self.col.min(self.cols - 1);
+            let cell = self.screen[self.row * self.cols + col];
+            self.draw(col, self.row, cell, shown);
+        }
+    }
+
+    /// Draw everything again, from the scrollback when paged back
+    pub fn redraw(&mut self) {
+        let first = self.history.len() - self.view;
+
+        for row in 0..self.rows {
+            let line = first + row;
+            for col in 0..self.cols {
+                let cell = match self.history.get(line) {
+                    Some(line) => line[col],
+                    None => self.screen[(line - self.history.len()) * self.cols + col],
+                };
+                self.draw(col, row, cell, false);
+            }
+        }
+
+        self.draw_cursor(true);
+    }
+
+    fn erase(&mut self, start: usize, end: usize) {
+        let blank = Cell::blank(self.attr.bg);
+        for index in start..end {
+            self.screen[index] = blank;
+            self.draw_at(index);
+        }
+    }
+
+    /// Move the screen up a line, the top one goes to the scrollback
+    fn scroll(&mut self) {
+        let top = self.screen[..self.cols].to_vec();
+        if self.history.len() == SCROLLBACK_LINES {
+            self.history.pop_front();
+        }
+        self.history.push_back(top);
+
+        self.screen.copy_within(self.cols.., 0);
+        let blank = Cell::blank(self.attr.bg);
+        let last = self.screen.len() - self.cols;
+        self.screen[last..].fill(blank);
+
+        if self.view == 0 {
+            self.fb
+                .scroll_up(self.font.height, PALETTE[blank.bg as usize]);
+        } else {
+            // keep showing the same lines
+            self.view = (self.view + 1).min(self.history.len());
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.col = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    fn put(&mut self, ch: u8) {
+        if self.col >= self.cols {
+            self.new_line();
+        }
+
+        let index = self.row * self.cols + self.col;
+        self.screen[index] = self.attr.cell(ch);
+        self.draw_at(index);
+        self.col += 1;
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        match self.state {
+            State::Normal => self.write_normal(byte),
+            State::Escape => {
+                self.state = match byte {
+                    b'[' => {
+                        self.params = [0; MAX_PARAMS];
+                        self.param = 0;
+                        State::Csi
+                    }
+                    _ => State::Normal,
+                }
+            }
+            State::Csi => match byte {
+                b'0'..=b'9' => {
+                    if let Some(param) = self.params.get_mut(self.param) {
+                        *param = param
+                            .saturating_mul(10)
+                            .saturating_add((byte - b'0') as u16);
+                    }
+                }
+                b';' => self.param += 1,
+                0x40..=0x7e => {
+                    self.state = State::Normal;
+                    self.execute(byte);
+                }
+                // private markers like `?` and intermediate bytes
+                _ => {}
+            },
+        }
+    }
+
+    fn write_normal(&mut self, byte: u8) {
+        match byte {
+            0x1b => self.state = State::Escape,
+            b'\n' => self.new_line(),
+            b'\r' => self.col = 0,
+            0x08 => self.col = self.col.saturating_sub(1),
+            b'\t' => {
+                self.col = ((self.col / TAB_WIDTH + 1) * TAB_WIDTH).min(self.cols - 1);
+            }
+            // the rest of a UTF-8 sequence, its first byte is drawn as `?`
+            0x80..=0xbf => {}
+            0x00..=0x1f | 0x7f => {}
+            byte => self.put(byte),
+        }
+    }
+
+    /// Parameter `index`, `default` when it is left out or 0
+    fn param(&self, index: usize, default: usize) -> usize {
+        match self.params.get(index) {
+            Some(&value) if value != 0 => value as usize,
+            _ => default,
+        }
+    }
+
+    /// Run the escape sequence ended by `action`
+    fn execute(&mut self, action: u8) {
+        let cursor = self.row * self.cols + self.col.min(self.cols - 1);
+
+        match action {
+            b'm' => self.select_graphic(),
+            b'H' | b'f' => {
+                self.row = (self.param(0, 1) - 1).min(self.rows - 1);
+                self.col = (self.param(1, 1) - 1).min(self.cols - 1);
+            }
+            b'A' => self.row = self.row.saturating_sub(self.param(0, 1)),
+            b'B' => self.row = (self.row + self.param(0, 1)).min(self.rows - 1),
+            b'C' => self.col = (self.col + self.param(0, 1)).min(self.cols - 1),
+            b'D' => self.col = self.col.saturating_sub(self.param(0, 1)),
+            b'J' => match self.param(0, 0) {
+                0 => self.erase(cursor, self.screen.len()),
+                1 => self.erase(0, cursor + 1),
+                _ => self.erase(0, self.screen.len()),
+            },
+            b'K' => {
+                let line = self.row * self.cols;
+                match self.param(0, 0) {
+                    0 => self.erase(cursor, line + self.cols),
+                    1 => self.erase(line, cursor + 1),
+                    _ => self.erase(line, line + self.cols),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// SGR, `ESC [ ... m`, colors and bold
+    fn select_graphic(&mut self) {
+        for index in 0..=self.param.min(MAX_PARAMS - 1) {
+            match self.params[index] {
+                0 => self.attr = Attr::DEFAULT,
+                1 => self.attr.bold = true,
+                22 => self.attr.bold = false,
+                7 => core::mem::swap(&mut self.attr.fg, &mut self.attr.bg),
+                code @ 30..=37 => self.attr.fg = (code - 30) as u8,
+                39 => self.attr.fg = DEFAULT_FG,
+                code @ 40..=47 => self.attr.bg = (code - 40) as u8,
+                49 => self.attr.bg = DEFAULT_BG,
+                code @ 90..=97 => self.attr.fg = (code - 90) as u8 + 8,
+                code @ 100..=107 => self.attr.bg = (code - 100) as u8 + 8,
+                _ => {}
+            }
+        }
+    }
+}
+
+impl fmt::Write for Console {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.draw_cursor(false);
+        for byte in s.bytes() {
+            self.write_byte(byte);
+        }
+        self.draw_cursor(true);
+        Ok(())
+    }
+}
@@ -0,0 +1,107 @@
+//! The framebuffer GOP left for the kernel, see `boot::FrameBufferInfo`
+//!
+//! only the 32 bit RGB and BGR formats are drawn to, which is what
+//! QEMU and most firmware set up.
+
+use boot::{FrameBufferInfo, PixelFormat};
+
+use crate::memory::physical_to_virtual;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0, 0, 0);
+    pub const LIGHT_GRAY: Color = Color::new(0xaa, 0xaa, 0xaa);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+}
+
+pub struct FrameBuffer {
+    base: *mut u32,
+    width: usize,
+    height: usize,
+    /// pixels from the start of a row to the next, at least `width`
+    stride: usize,
+    format: PixelFormat,
+}
+
+// the framebuffer is only reached through the lock of its console
+unsafe impl Send for FrameBuffer {}
+
+impl FrameBuffer {
+    /// The framebuffer in `info`, `None` if its pixels cannot be drawn
+    pub fn new(info: &FrameBufferInfo) -> Option<Self> {
+        let format = info.mode.pixel_format();
+        if !matches!(format, PixelFormat::Rgb | PixelFormat::Bgr) {
+            return None;
+        }
+
+        let (width, height) = info.mode.resolution();
+        Some(Self {
+            base: physical_to_virtual(info.addr) as *mut u32,
+            width,
+            height,
+            stride: info.mode.stride(),
+            format,
+        })
+    }
+
+    #[inline]
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    #[inline]
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    #[inline]
+    fn encode(&self, color: Color) -> u32 {
+        let (r, g, b) = (color.r as u32, color.g as u32, color.b as u32);
+        match self.format {
+            PixelFormat::Rgb => r | g << 8 | b << 16,
+            _ => b | g << 8 | r << 16,
+        }
+    }
+
+    pub fn put_pixel(&mut self, x: usize, y: usize, color: Color) {
+        if x < self.width && y < self.height {
+            let pixel = self.encode(color);
+            unsafe { self.base.add(y * self.stride + x).write_volatile(pixel) };
+        }
+    }
+
+    /// Fill a rectangle, clipped to the screen
+    pub fn fill_rect(&mut self, x: usize, y: usize, width: usize, height: usize, color: Color) {
+        let pixel = self.encode(color);
+        let x_end = (x + width).min(self.width);
+        let y_end = (y + height).min(self.height);
+
+        for y in y..y_end {
+            for x in x..x_end {
+                unsafe { self.base.add(y * self.stride + x).write_volatile(pixel) };
+            }
+        }
+    }
+
+    /// Move everything up by `rows` pixels, the rows uncovered are filled
+    pub fn scroll_up(&mut self, rows: usize, color: Color) {
+        let rows = rows.min(self.height);
+        unsafe {
+            core::ptr::copy(
+                self.base.add(rows * self.stride),
+                self.base,
+                (self.height - rows) * self.stride,
+            );
+        }
+        self.fill_rect(0, self.height - rows, self.width, rows, color);
+    }
+}
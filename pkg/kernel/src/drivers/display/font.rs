@@ -0,0 +1,77 @@
+//! The bitmap font built into the kernel
+//!
+//! a PSF2 file of the ASCII range, 8x16, rendered from DejaVu Sans Mono.
+//! Glyphs are rows of bits, the most significant bit is leftmost.
+
+const PSF2_MAGIC: u32 = 0x864a_b572;
+const PSF2_HEADER_SIZE: usize = 32;
+
+static FONT_DATA: &[u8] = include_bytes!("../../../assets/font.psf");
+
+#[derive(Clone, Copy)]
+pub struct Font {
+    pub width: usize,
+    pub height: usize,
+    bytes_per_row: usize,
+    glyph_size: usize,
+    glyphs: &'static [u8],
+}
+
+impl Font {
+    /// Parse a PSF2 font, `None` if it is not one
+    pub fn parse(data: &'static [u8]) -> Option<Self> {
+        let field = |i: usize| -> Option<usize> {
+            let bytes = data.get(i * 4..i * 4 + 4)?;
+            Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+        };
+
+        if field(0)? != PSF2_MAGIC as usize {
+            return None;
+        }
+
+        let offset = field(2)?;
+        let count = field(4)?;
+        let glyph_size = field(5)?;
+        let height = field(6)?;
+        let width = field(7)?;
+
+        let glyphs = data.get(offset.max(PSF2_HEADER_SIZE)..offset + count * glyph_size)?;
+        Some(Self {
+            width,
+            height,
+            bytes_per_row: width.div_ceil(8),
+            glyph_size,
+            glyphs,
+        })
+    }
+
+    #[inline]
+    fn count(&self) -> usize {
+        self.glyphs.len() / self.glyph_size
+    }
+
+    /// The rows of `ch`, a `?` for what the font lacks
+    pub fn glyph(&self, ch: char) -> &'static [u8] {
+        let index = match ch as usize {
+            index if index < self.count() => index,
+            _ => '?' as usize,
+        };
+        &self.glyphs[index * self.glyph_size..(index + 1) * self.glyph_size]
+    }
+
+    /// Check pixel `x` of a row of a glyph
+    #[inline]
+    pub fn is_set(&self, row: &[u8], x: usize) -> bool {
+        row[x / 8] & (0x80 >> (x % 8)) != 0
+    }
+
+    /// The rows of a glyph, each `bytes_per_row` long
+    pub fn rows<'a>(&self, glyph: &'a [u8]) -> impl Iterator<Item = &'a [u8]> {
+        glyph.chunks(self.bytes_per_row).take(self.height)
+    }
+}
+
+/// The font built into the kernel
+pub fn builtin() -> Font {
+    Font::parse(FONT_DATA).expect("the built-in font is not PSF2")
+}
@@ -0,0 +1,120 @@
+//! The text console on the framebuffer
+//!
+//! it mirrors what is printed to serial, so the machine can be used
+//! with a display and keyboard alone. Colors and cursor movement of ANSI
+//! escape sequences are drawn, and Shift+PgUp/PgDn pages through the
+//! lines scrolled off. `fbcon=off` on the kernel command line turns it off.
+//!
+//! a process may take the framebuffer to draw on it itself, see
+//! `Syscall::MapFrameBuffer`. The console keeps its lines meanwhile
+//! and draws them again once the process exits.
+
+mod console;
+
+pub mod fb;
+pub mod font;
+
+pub use console::Console;
+
+use boot::FrameBufferInfo;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+use fb::FrameBuffer;
+use syscall_def::errno::{EBUSY, ENODEV};
+
+once_mutex!(CONSOLE: Console);
+
+guard_access_fn!(get_console(CONSOLE: Console));
+
+/// The framebuffer, if its pixels can be drawn
+static FRAME_BUFFER: spin::Once<&'static FrameBufferInfo> = spin::Once::new();
+/// The process that took the framebuffer, 0 for none
+static OWNER: AtomicU16 = AtomicU16::new(0);
+/// The console is to be drawn again, it was busy when given the screen back
+static REDRAW: AtomicBool = AtomicBool::new(false);
+
+/// Set up the console, needs the kernel heap for the scrollback
+pub fn init(boot_info: &'static boot::BootInfo) {
+    let Some(info) = boot_info.frame_buffer.as_ref() else {
+        return;
+    };
+
+    let Some(fb) = FrameBuffer::new(info) else {
+        warn!(
+            "Framebuffer format {:?} is not supported",
+            info.mode.pixel_format()
+        );
+        return;
+    };
+    FRAME_BUFFER.call_once(|| info);
+
+    if crate::cmdline::get("fbcon") == Some("off") {
+        return;
+    }
+
+    let console = Console::new(fb, font::builtin());
+    let (cols, rows) = console.size();
+    init_CONSOLE(console);
+    info!("Framebuffer console: {}x{} characters", cols, rows);
+}
+
+/// Write to the console if there is one and it is free
+///
+/// output is dropped rather than waited for, as in interrupt context.
+pub fn print(args: fmt::Arguments) {
+    if let Some(mut console) = get_console() {
+        if REDRAW.swap(false, Ordering::Relaxed) {
+            console.redraw();
+        }
+        fmt::Write::write_fmt(&mut *console, args).ok();
+    }
+}
+
+/// Page back through the scrollback by `pages` screens, forward if negative
+pub fn scroll_page(pages: isize) {
+    if let Some(mut console) = get_console() {
+        let rows = console.size().1 as isize;
+        console.scroll_view(pages * rows);
+    }
+}
+
+/// Show the screen again after paging back
+pub fn scroll_to_bottom() {
+    if let Some(mut console) = get_console() {
+        console.scroll_view(isize::MIN);
+    }
+}
+
+/// Whether a process has taken the framebuffer, the console is not drawn
+#[inline]
+pub fn is_taken() -> bool {
+    OWNER.load(Ordering::Relaxed) != 0
+}
+
+/// Give the framebuffer to `pid` to draw on, until it exits
+///
+/// return `ENODEV` without one that can be drawn, `EBUSY` if another
+/// process has it.
+pub fn take(pid: u16) -> Result<&'static FrameBufferInfo, usize> {
+    let info = FRAME_BUFFER.get().ok_or(ENODEV)?;
+    match OWNER.compare_exchange(0, pid, Ordering::Relaxed, Ordering::Relaxed) {
+        Ok(_) => Ok(info),
+        Err(owner) if owner == pid => Ok(info),
+        Err(_) => Err(EBUSY),
+    }
+}
+
+/// Give the screen back to the console if `pid` has it, e.g. when it exits
+pub fn release(pid: u16) {
+    if OWNER
+        .compare_exchange(pid, 0, Ordering::Relaxed, Ordering::Relaxed)
+        .is_err()
+    {
+        return;
+    }
+
+    match get_console() {
+        Some(mut console) => console.redraw(),
+        None => REDRAW.store(true, Ordering::Relaxed),
+    }
+}
@@ -0,0 +1,103 @@
+//! A console usable from the first instruction of the kernel
+//!
+//! it takes no lock and never allocates: output goes to debugcon right
+//! away, and to a static buffer until the serial port is initialized,
+//! which then replays it. Afterwards the serial port is written directly,
+//! without its lock, so this also works when the lock is held by code
+//! that will never release it, e.g. on a fatal fault.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use super::debugcon;
+use super::uart16550::SerialPort;
+
+const BUF_SIZE: usize = 4096;
+
+struct EarlyBuffer {
+    data: UnsafeCell<[u8; BUF_SIZE]>,
+    len: AtomicUsize,
+}
+
+// a writer only touches the bytes it reserved through `len`
+unsafe impl Sync for EarlyBuffer {}
+
+static BUFFER: EarlyBuffer = EarlyBuffer {
+    data: UnsafeCell::new([0; BUF_SIZE]),
+    len: AtomicUsize::new(0),
+};
+static DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// The serial port is up and the buffer was replayed to it
+static SERIAL_READY: AtomicBool = AtomicBool::new(false);
+
+/// Keep `bytes` for the serial port, as many as there is room for
+fn push(bytes: &[u8]) {
+    let reserved = BUFFER
+        .len
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |len| {
+            Some(len + bytes.len().min(BUF_SIZE - len))
+        })
+        .unwrap();
+    let count = bytes.len().min(BUF_SIZE - reserved);
+
+    unsafe {
+        let data = &mut *BUFFER.data.get();
+        data[reserved..reserved + count].copy_from_slice(&bytes[..count]);
+    }
+
+    if count < bytes.len() {
+        DROPPED.fetch_add(bytes.len() - count, Ordering::Relaxed);
+    }
+}
+
+pub fn write_bytes(bytes: &[u8]) {
+    debugcon::write_bytes(bytes);
+
+    if SERIAL_READY.load(Ordering::Acquire) {
+        let mut serial = SerialPort::new(super::serial::SERIAL_IO_PORT);
+        for &byte in bytes {
+            serial.send(byte);
+        }
+    } else {
+        push(bytes);
+    }
+}
+
+/// Send what was written before `serial` was initialized
+///
+/// called once by `serial::init`, later output goes to serial directly.
+pub fn replay(serial: &mut SerialPort) {
+    let len = BUFFER.len.load(Ordering::Acquire);
+    let data = unsafe { &(&*BUFFER.data.get())[..len] };
+    for &byte in data {
+        serial.send(byte);
+    }
+
+    let dropped = DROPPED.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        fmt::Write::write_fmt(
+            serial,
+            format_args!("[!] {} bytes of early output dropped\n\r", dropped),
+        )
+        .ok();
+    }
+
+    SERIAL_READY.store(true, Ordering::Release);
+}
+
+/// Lock-free writer to the early console
+pub struct EarlyWriter;
+
+impl fmt::Write for EarlyWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+#[doc(hidden)]
+pub fn print(args: fmt::Arguments) {
+    fmt::Write::write_fmt(&mut EarlyWriter, args).ok();
+}
@@ -0,0 +1,115 @@
+//! QEMU fw_cfg, files the host passes with `-fw_cfg name=..,file=..`
+//!
+//! only the port interface is used: an item is selected, then read a byte
+//! at a time from the data port. Slower than DMA, but the files are small
+//! and read once. `xtask --share` passes files under [`SHARE_PREFIX`].
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+use x86_64::instructions::port::Port;
+
+const SELECTOR_PORT: u16 = 0x510;
+const DATA_PORT: u16 = 0x511;
+
+const SIGNATURE_ITEM: u16 = 0x0000;
+const FILE_DIR_ITEM: u16 = 0x0019;
+const SIGNATURE: &[u8; 4] = b"QEMU";
+/// Bytes of a file name in the directory, NUL included
+pub const NAME_LEN: usize = 56;
+
+/// Where the files shared by the host are
+pub const SHARE_PREFIX: &str = "opt/ysos/";
+/// Where the apps shared by the host are
+pub const APP_PREFIX: &str = "opt/ysos/app/";
+
+#[derive(Debug)]
+pub struct FwCfgFile {
+    pub name: String,
+    pub size: u32,
+    select: u16,
+}
+
+/// The file directory, read once
+static FILES: spin::Once<Vec<FwCfgFile>> = spin::Once::new();
+/// Selecting and reading an item must not be interleaved
+static PORTS: Mutex<()> = Mutex::new(());
+
+fn select(item: u16) {
+    unsafe { Port::new(SELECTOR_PORT).write(item) };
+}
+
+fn read_into(buf: &mut [u8]) {
+    let mut data = Port::<u8>::new(DATA_PORT);
+    for byte in buf {
+        *byte = unsafe { data.read() };
+    }
+}
+
+fn read_be<const N: usize>() -> [u8; N] {
+    let mut buf = [0; N];
+    read_into(&mut buf);
+    buf
+}
+
+/// Look for the device and read its file directory, needs the kernel heap
+pub fn init() {
+    let files = FILES.call_once(|| {
+        let _ports = PORTS.lock();
+
+        select(SIGNATURE_ITEM);
+        if &read_be::<4>() != SIGNATURE {
+            return Vec::new();
+        }
+
+        select(FILE_DIR_ITEM);
+        let count = u32::from_be_bytes(read_be());
+        (0..count)
+            .map(|_| {
+                let size = u32::from_be_bytes(read_be());
+                let select = u16::from_be_bytes(read_be());
+                let _reserved: [u8; 2] = read_be();
+                let name: [u8; NAME_LEN] = read_be();
+                let len = name.iter().position(|&b| b == 0).unwrap_or(NAME_LEN);
+
+                FwCfgFile {
+                    name: String::from_utf8_lossy(&name[..len]).into_owned(),
+                    size,
+                    select,
+                }
+            })
+            .collect()
+    });
+
+    let shared = files
+        .iter()
+        .filter(|file| file.name.starts_with(SHARE_PREFIX))
+        .count();
+    info!(
+        "fw_cfg: {} files, {} shared by the host",
+        files.len(),
+        shared
+    );
+}
+
+/// Every file of the device, empty when there is none
+pub fn files() -> &'static [FwCfgFile] {
+    FILES.get().map(Vec::as_slice).unwrap_or_default()
+}
+
+pub fn find(name: &str) -> Option<&'static FwCfgFile> {
+    files().iter().find(|file| file.name == name)
+}
+
+/// The content of `file`
+pub fn read(file: &FwCfgFile) -> Vec<u8> {
+    let mut buf = alloc::vec![0; file.size as usize];
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let _ports = PORTS.lock();
+        select(file.select);
+        read_into(&mut buf);
+    });
+
+    buf
+}
@@ -3,8 +3,15 @@ use crossbeam_queue::ArrayQueue;
 use lazy_static::lazy_static;
 
 type Key = u8;
+
+/// Room for a 1KiB XMODEM block and its header in raw mode
+const INPUT_BUF_SIZE: usize = 2048;
+/// Ctrl+Z, stops the process in the foreground instead of being read
+const SUSPEND_KEY: Key = 0x1A;
+
 lazy_static! {
-    static ref INPUT_BUF: ArrayQueue<Key> = ArrayQueue::new(128);
+    static ref INPUT_BUF: ArrayQueue<Key> = ArrayQueue::new(INPUT_BUF_SIZE);
+    static ref DEFERRED_BUF: ArrayQueue<Key> = ArrayQueue::new(INPUT_BUF_SIZE);
 }
 
 pub fn push_key(key: Key) {
@@ -13,6 +20,41 @@ pub fn push_key(key: Key) {
     }
 }
 
+/// Hold a key back until [`release_deferred`] is called
+pub fn defer_key(key: Key) {
+    if DEFERRED_BUF.push(key).is_err() {
+        warn!("Deferred input buffer is full. Dropping key '{:?}'", key);
+    }
+}
+
+/// Move the keys held back by [`defer_key`] to the input buffer, in order
+pub fn release_deferred() {
+    while let Some(key) = DEFERRED_BUF.pop() {
+        push_key(key);
+    }
+}
+
+/// Take a key from a device, serial or keyboard
+pub fn enqueue(key: Key) {
+    // binary data may hold it, see `serial::set_raw`
+    if key == SUSPEND_KEY && !super::serial::is_raw() && crate::proc::stop_foreground() {
+        return;
+    }
+
+    // hold input back until the slice ends in deterministic mode
+    if crate::proc::deterministic::enabled() {
+        defer_key(key);
+    } else {
+        push_key(key);
+    }
+}
+
+/// Whether a key is waiting to be read
+#[inline]
+pub fn has_key() -> bool {
+    !INPUT_BUF.is_empty()
+}
+
 #[inline]
 pub fn try_pop_key() -> Option<Key> {
     INPUT_BUF.pop()
@@ -0,0 +1,154 @@
+//! PS/2 keyboard, scancode set 1 with a US layout
+//!
+//! keys are turned into what a serial terminal sends, so the shell reads
+//! the same bytes from either: `\r` for Enter, DEL for Backspace and VT
+//! escape sequences for the arrows and the like. Shift+PgUp/PgDn pages
+//! the framebuffer console instead.
+
+use spin::Mutex;
+use x86_64::instructions::port::{Port, PortReadOnly};
+
+#[cfg(feature = "graphics")]
+use super::display;
+use super::input::enqueue;
+
+const DATA_PORT: u16 = 0x60;
+const STATUS_PORT: u16 = 0x64;
+/// Set in the status register when there is a byte to read
+const OUTPUT_FULL: u8 = 1;
+
+/// Set in the scancode of a released key
+const RELEASED: u8 = 0x80;
+/// The next scancode is of an extended key
+const EXTENDED: u8 = 0xE0;
+
+const LEFT_SHIFT: u8 = 0x2A;
+const RIGHT_SHIFT: u8 = 0x36;
+const CTRL: u8 = 0x1D;
+const CAPS_LOCK: u8 = 0x3A;
+
+/// Scancodes up to the space bar, 0 for those without a character
+const NORMAL: &[u8; 58] =
+    b"\0\x1b1234567890-=\x7f\tqwertyuiop[]\r\0asdfghjkl;'`\0\\zxcvbnm,./\0*\0 ";
+const SHIFTED: &[u8; 58] =
+    b"\0\x1b!@#$%^&*()_+\x7f\tQWERTYUIOP{}\r\0ASDFGHJKL:\"~\0|ZXCVBNM<>?\0*\0 ";
+
+const PAGE_UP: u8 = 0x49;
+const PAGE_DOWN: u8 = 0x51;
+
+struct Keyboard {
+    extended: bool,
+    shift: bool,
+    ctrl: bool,
+    caps_lock: bool,
+}
+
+static KEYBOARD: Mutex<Keyboard> = Mutex::new(Keyboard {
+    extended: false,
+    shift: false,
+    ctrl: false,
+    caps_lock: false,
+});
+
+/// Drop what the controller holds from before, e.g. keys typed in the firmware
+pub fn init() {
+    let mut status = PortReadOnly::<u8>::new(STATUS_PORT);
+    let mut data = PortReadOnly::<u8>::new(DATA_PORT);
+    unsafe {
+        while status.read() & OUTPUT_FULL != 0 {
+            data.read();
+        }
+    }
+}
+
+/// Read a scancode from the controller, called on every interrupt
+pub fn receive() {
+    let scancode = unsafe { Port::<u8>::new(DATA_PORT).read() };
+    KEYBOARD.lock().handle(scancode);
+}
+
+/// The escape sequence sent for an extended key
+fn sequence(scancode: u8) -> Option<&'static [u8]> {
+    Some(match scancode {
+        0x48 => b"\x1b[A",
+        0x50 => b"\x1b[B",
+        0x4D => b"\x1b[C",
+        0x4B => b"\x1b[D",
+        0x47 => b"\x1b[H",
+        0x4F => b"\x1b[F",
+        0x52 => b"\x1b[2~",
+        0x53 => b"\x1b[3~",
+        PAGE_UP => b"\x1b[5~",
+        PAGE_DOWN => b"\x1b[6~",
+        // the Enter and `/` of the keypad
+        0x1C => b"\r",
+        0x35 => b"/",
+        _ => return None,
+    })
+}
+
+impl Keyboard {
+    fn handle(&mut self, scancode: u8) {
+        if scancode == EXTENDED {
+            self.extended = true;
+            return;
+        }
+
+        let extended = core::mem::take(&mut self.extended);
+        let pressed = scancode & RELEASED == 0;
+        let code = scancode & !RELEASED;
+
+        match code {
+            // extended shifts are sent around some keys, like Print Screen
+            LEFT_SHIFT | RIGHT_SHIFT if extended => {}
+            LEFT_SHIFT | RIGHT_SHIFT => self.shift = pressed,
+            CTRL => self.ctrl = pressed,
+            CAPS_LOCK if pressed => self.caps_lock = !self.caps_lock,
+            _ if !pressed => {}
+            #[cfg(feature = "graphics")]
+            PAGE_UP if extended && self.shift => display::scroll_page(1),
+            #[cfg(feature = "graphics")]
+            PAGE_DOWN if extended && self.shift => display::scroll_page(-1),
+            _ if extended => {
+                if let Some(bytes) = sequence(code) {
+                    send(bytes);
+                }
+            }
+            _ => {
+                if let Some(byte) = self.translate(code) {
+                    send(&[byte]);
+                }
+            }
+        }
+    }
+
+    fn translate(&self, code: u8) -> Option<u8> {
+        let normal = *NORMAL.get(code as usize)?;
+        let byte = if self.shift {
+            SHIFTED[code as usize]
+        } else {
+            normal
+        };
+
+        let byte = match byte {
+            0 => return None,
+            // Caps Lock swaps the case of letters only
+            b'a'..=b'z' | b'A'..=b'Z' if self.caps_lock => byte ^ 0x20,
+            _ => byte,
+        };
+
+        Some(match byte {
+            b'a'..=b'z' | b'A'..=b'Z' if self.ctrl => byte & 0x1f,
+            _ => byte,
+        })
+    }
+}
+
+/// Typing shows the screen again if it was paged back
+fn send(bytes: &[u8]) {
+    #[cfg(feature = "graphics")]
+    display::scroll_to_bottom();
+    for &byte in bytes {
+        enqueue(byte);
+    }
+}
@@ -1,6 +1,19 @@
 mod uart16550;
 
+pub mod ahci;
+pub mod ata;
+pub mod block;
+pub mod debug_exit;
+pub mod debugcon;
+pub mod early;
+#[cfg(feature = "graphics")]
+pub mod display;
+pub mod fw_cfg;
 pub mod input;
+pub mod keyboard;
+pub mod partition;
+pub mod pci;
+pub mod rtc;
 pub mod serial;
 
 pub use input::{get_line, push_key};
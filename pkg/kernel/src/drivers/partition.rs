@@ -0,0 +1,300 @@
+//! Partition tables of the disks, GPT or MBR
+//!
+//! the ATA drives and then the SATA disks are named `disk0`, `disk1`, ...
+//! in the order they were found, and their partitions `disk0p1`, ... by
+//! their number in the table. A disk whose MBR has a protective entry is
+//! read as GPT, if the header and entries pass their checksums, otherwise
+//! the four primary entries of the MBR are used. Logical partitions in an
+//! extended one are not looked for.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::{self, Write};
+use spin::Once;
+
+use super::block::{Block, BlockDevice, Slice, BLOCK_SIZE};
+use super::{ahci, ata};
+use crate::humanized_size;
+
+/// The MBR type of the entry covering a disk with a GPT
+const MBR_PROTECTIVE: u8 = 0xEE;
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+/// Most GPT entries read, the tables made by tools have 128
+const GPT_MAX_ENTRIES: usize = 256;
+
+/// A GUID, stored mixed endian as GPT does
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Guid(pub [u8; 16]);
+
+impl Guid {
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 16]
+    }
+}
+
+impl fmt::Display for Guid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let b = &self.0;
+        write!(
+            f,
+            "{:02X}{:02X}{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-{:02X}{:02X}-",
+            b[3], b[2], b[1], b[0], b[5], b[4], b[7], b[6], b[8], b[9]
+        )?;
+        b[10..]
+            .iter()
+            .try_for_each(|byte| write!(f, "{:02X}", byte))
+    }
+}
+
+/// The type of a partition, as its table gives it
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PartType {
+    Mbr(u8),
+    Gpt(Guid),
+}
+
+impl PartType {
+    /// The name of a well known type
+    pub fn name(&self) -> Option<&'static str> {
+        match self {
+            Self::Mbr(0x01) => Some("FAT12"),
+            Self::Mbr(0x04 | 0x06 | 0x0E) => Some("FAT16"),
+            Self::Mbr(0x0B | 0x0C) => Some("FAT32"),
+            Self::Mbr(0x07) => Some("NTFS/exFAT"),
+            Self::Mbr(0x82) => Some("Linux swap"),
+            Self::Mbr(0x83) => Some("Linux"),
+            Self::Mbr(0xEF) => Some("EFI system"),
+            Self::Mbr(_) => None,
+            Self::Gpt(guid) => match format!("{}", guid).as_str() {
+                "C12A7328-F81F-11D2-BA4B-00A0C93EC93B" => Some("EFI system"),
+                "EBD0A0A2-B9E5-4433-87C0-68B6B72699C7" => Some("basic data"),
+                "0FC63DAF-8483-4772-8E79-3D69D8477DE4" => Some("Linux"),
+                "0657FD6D-A4AB-43C4-84E5-0933C84B4F4F" => Some("Linux swap"),
+                _ => None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for PartType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.name(), self) {
+            (Some(name), _) => f.write_str(name),
+            (None, Self::Mbr(kind)) => write!(f, "type {:#04x}", kind),
+            (None, Self::Gpt(guid)) => write!(f, "{}", guid),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Partition {
+    /// its number in the table, from 1
+    pub number: usize,
+    pub start: usize,
+    pub count: usize,
+    pub kind: PartType,
+    /// the unique GUID and name of a GPT partition
+    pub guid: Option<Guid>,
+    pub label: String,
+}
+
+/// A disk and the partitions in its table
+pub struct Disk {
+    pub name: String,
+    pub dev: Arc<dyn BlockDevice>,
+    pub partitions: Vec<Partition>,
+}
+
+impl Disk {
+    /// The device of the whole disk or one of its partitions by name,
+    /// e.g. `disk0` or `disk0p2`
+    pub fn find(&self, name: &str) -> Option<Arc<dyn BlockDevice>> {
+        let rest = name.strip_prefix(self.name.as_str())?;
+        if rest.is_empty() {
+            return Some(self.dev.clone());
+        }
+
+        let number = rest.strip_prefix('p')?.parse::<usize>().ok()?;
+        let part = self.partitions.iter().find(|part| part.number == number)?;
+        Some(Arc::new(Slice::new(
+            self.dev.clone(),
+            part.start,
+            part.count,
+        )))
+    }
+
+    /// The names of the disk and then of its partitions
+    pub fn names(&self) -> impl Iterator<Item = String> + '_ {
+        let parts = self
+            .partitions
+            .iter()
+            .map(|part| format!("{}p{}", self.name, part.number));
+        core::iter::once(self.name.clone()).chain(parts)
+    }
+}
+
+static DISKS: Once<Vec<Disk>> = Once::new();
+
+/// Every disk with its partitions, read once the disks are found
+pub fn disks() -> &'static [Disk] {
+    DISKS.call_once(|| {
+        let ata = ata::drives()
+            .iter()
+            .map(|drive| drive.clone() as Arc<dyn BlockDevice>);
+        let sata = ahci::disks()
+            .iter()
+            .map(|disk| disk.clone() as Arc<dyn BlockDevice>);
+
+        ata.chain(sata)
+            .enumerate()
+            .map(|(i, dev)| Disk {
+                name: format!("disk{}", i),
+                partitions: scan(dev.as_ref()),
+                dev,
+            })
+            .collect()
+    })
+}
+
+/// The device named `name` under `/dev`, a disk or a partition
+pub fn find(name: &str) -> Option<Arc<dyn BlockDevice>> {
+    disks().iter().find_map(|disk| disk.find(name))
+}
+
+/// A line for each disk and partition, as shown by `/dev/partitions`
+pub fn report() -> String {
+    let mut out = String::new();
+    for disk in disks() {
+        let blocks = disk.dev.block_count();
+        let (size, unit) = humanized_size((blocks * BLOCK_SIZE) as u64);
+        let _ = writeln!(
+            out,
+            "{:<10} {:>10} {:>10} {:>6.1} {}",
+            disk.name, 0, blocks, size, unit
+        );
+
+        for part in &disk.partitions {
+            let (size, unit) = humanized_size((part.count * BLOCK_SIZE) as u64);
+            let _ = write!(
+                out,
+                "{:<10} {:>10} {:>10} {:>6.1} {} | {}",
+                format!("{}p{}", disk.name, part.number),
+                part.start,
+                part.count,
+                size,
+                unit,
+                part.kind
+            );
+            if let Some(guid) = part.guid {
+                let _ = write!(out, " | {}", guid);
+            }
+            if !part.label.is_empty() {
+                let _ = write!(out, " | {}", part.label);
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// The partitions in the table of `dev`, none if it has no table
+pub fn scan(dev: &dyn BlockDevice) -> Vec<Partition> {
+    let mut mbr = [0; BLOCK_SIZE];
+    if dev.read_block(0, &mut mbr).is_err() || mbr[510..] != [0x55, 0xAA] {
+        return Vec::new();
+    }
+
+    let entries = mbr[446..510].as_chunks::<16>().0;
+    if entries.iter().any(|entry| entry[4] == MBR_PROTECTIVE) {
+        match scan_gpt(dev) {
+            Some(partitions) => return partitions,
+            None => warn!("Partition: bad GPT, using the MBR."),
+        }
+    }
+
+    let u32_at =
+        |entry: &[u8], i: usize| u32::from_le_bytes(entry[i..i + 4].try_into().unwrap()) as usize;
+    entries
+        .iter()
+        .enumerate()
+        // a type of 0 is an unused entry
+        .filter(|(_, entry)| entry[4] != 0 && u32_at(*entry, 12) != 0)
+        .map(|(i, entry)| Partition {
+            number: i + 1,
+            start: u32_at(entry, 8),
+            count: u32_at(entry, 12),
+            kind: PartType::Mbr(entry[4]),
+            guid: None,
+            label: String::new(),
+        })
+        .collect()
+}
+
+/// The partitions of the GPT of `dev`, `None` if a checksum is wrong
+fn scan_gpt(dev: &dyn BlockDevice) -> Option<Vec<Partition>> {
+    let mut header: Block = [0; BLOCK_SIZE];
+    dev.read_block(1, &mut header).ok()?;
+
+    let u32_at = |buf: &[u8], i: usize| u32::from_le_bytes(buf[i..i + 4].try_into().unwrap());
+    let u64_at = |buf: &[u8], i: usize| u64::from_le_bytes(buf[i..i + 8].try_into().unwrap());
+    let size = u32_at(&header, 12) as usize;
+    if &header[..8] != GPT_SIGNATURE || !(92..=BLOCK_SIZE).contains(&size) {
+        return None;
+    }
+
+    // the checksum of the header is taken with its own field zeroed
+    let mut copy = header;
+    copy[16..20].fill(0);
+    if hash::crc32(&copy[..size]) != u32_at(&header, 16) {
+        return None;
+    }
+
+    let lba = u64_at(&header, 72) as usize;
+    let count = u32_at(&header, 80) as usize;
+    let entry_size = u32_at(&header, 84) as usize;
+    if entry_size < 128 || !BLOCK_SIZE.is_multiple_of(entry_size) || count > GPT_MAX_ENTRIES {
+        return None;
+    }
+
+    let mut table = Vec::with_capacity(count * entry_size);
+    let mut block = [0; BLOCK_SIZE];
+    for i in 0..(count * entry_size).div_ceil(BLOCK_SIZE) {
+        dev.read_block(lba + i, &mut block).ok()?;
+        table.extend_from_slice(&block);
+    }
+    table.truncate(count * entry_size);
+    if hash::crc32(&table) != u32_at(&header, 88) {
+        return None;
+    }
+
+    let guid_at = |entry: &[u8], i: usize| Guid(entry[i..i + 16].try_into().unwrap());
+    Some(
+        table
+            .chunks_exact(entry_size)
+            .enumerate()
+            .filter(|(_, entry)| !guid_at(entry, 0).is_zero())
+            .map(|(i, entry)| {
+                let (first, last) = (u64_at(entry, 32) as usize, u64_at(entry, 40) as usize);
+                // the name is UTF-16, padded with NULs
+                let name = entry[56..128]
+                    .as_chunks::<2>()
+                    .0
+                    .iter()
+                    .map(|&c| u16::from_le_bytes(c))
+                    .take_while(|&c| c != 0);
+                Partition {
+                    number: i + 1,
+                    start: first,
+                    count: (last + 1).saturating_sub(first),
+                    kind: PartType::Gpt(guid_at(entry, 0)),
+                    guid: Some(guid_at(entry, 16)),
+                    label: char::decode_utf16(name)
+                        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                        .collect(),
+                }
+            })
+            .collect(),
+    )
+}
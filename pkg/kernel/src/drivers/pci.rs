@@ -0,0 +1,126 @@
+//! PCI configuration space, through the legacy 0xCF8/0xCFC ports
+//!
+//! devices are found by a scan of every bus, device and function,
+//! the drivers then pick theirs by class.
+
+use alloc::vec::Vec;
+use x86_64::instructions::port::Port;
+
+const CONFIG_ADDRESS: u16 = 0xcf8;
+const CONFIG_DATA: u16 = 0xcfc;
+
+const REG_ID: u8 = 0x00;
+const REG_COMMAND: u8 = 0x04;
+const REG_CLASS: u8 = 0x08;
+const REG_HEADER: u8 = 0x0c;
+const REG_BAR0: u8 = 0x10;
+
+/// Command bit that lets the device access memory
+pub const COMMAND_MEMORY: u16 = 1 << 1;
+/// Command bit that lets the device master the bus, for DMA
+pub const COMMAND_BUS_MASTER: u16 = 1 << 2;
+
+#[derive(Debug, Clone, Copy)]
+pub struct PciDevice {
+    pub bus: u8,
+    pub device: u8,
+    pub function: u8,
+    pub vendor_id: u16,
+    pub device_id: u16,
+    pub class: u8,
+    pub subclass: u8,
+    pub prog_if: u8,
+}
+
+fn address(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    1 << 31
+        | (bus as u32) << 16
+        | (device as u32) << 11
+        | (function as u32) << 8
+        | (offset as u32 & 0xfc)
+}
+
+fn read(bus: u8, device: u8, function: u8, offset: u8) -> u32 {
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        Port::new(CONFIG_ADDRESS).write(address(bus, device, function, offset));
+        Port::new(CONFIG_DATA).read()
+    })
+}
+
+fn write(bus: u8, device: u8, function: u8, offset: u8, value: u32) {
+    x86_64::instructions::interrupts::without_interrupts(|| unsafe {
+        Port::new(CONFIG_ADDRESS).write(address(bus, device, function, offset));
+        Port::new(CONFIG_DATA).write(value);
+    })
+}
+
+impl PciDevice {
+    /// The device at the address, `None` if there is none
+    fn probe(bus: u8, device: u8, function: u8) -> Option<Self> {
+        let id = read(bus, device, function, REG_ID);
+        if id & 0xffff == 0xffff {
+            return None;
+        }
+
+        let class = read(bus, device, function, REG_CLASS);
+        Some(Self {
+            bus,
+            device,
+            function,
+            vendor_id: id as u16,
+            device_id: (id >> 16) as u16,
+            class: (class >> 24) as u8,
+            subclass: (class >> 16) as u8,
+            prog_if: (class >> 8) as u8,
+        })
+    }
+
+    pub fn read(&self, offset: u8) -> u32 {
+        read(self.bus, self.device, self.function, offset)
+    }
+
+    pub fn write(&self, offset: u8, value: u32) {
+        write(self.bus, self.device, self.function, offset, value)
+    }
+
+    /// The base address of memory BAR `index`, `None` for an I/O BAR
+    pub fn memory_bar(&self, index: u8) -> Option<u64> {
+        let bar = self.read(REG_BAR0 + index * 4);
+        if bar & 1 != 0 {
+            return None;
+        }
+
+        let base = (bar & !0xf) as u64;
+        // a 64-bit BAR takes the next one for its high half
+        match (bar >> 1) & 0b11 {
+            0b10 => Some(base | (self.read(REG_BAR0 + index * 4 + 4) as u64) << 32),
+            _ => Some(base),
+        }
+    }
+
+    /// Set the `COMMAND_*` bits of `bits`
+    pub fn enable(&self, bits: u16) {
+        let reg = self.read(REG_COMMAND);
+        self.write(REG_COMMAND, reg | bits as u32);
+    }
+}
+
+/// Every function of every device on the buses
+pub fn scan() -> Vec<PciDevice> {
+    let mut devices = Vec::new();
+    for bus in 0..=255 {
+        for device in 0..32 {
+            let Some(first) = PciDevice::probe(bus, device, 0) else {
+                continue;
+            };
+            devices.push(first);
+
+            // the other functions are there only on multifunction devices
+            if (first.read(REG_HEADER) >> 16) & 0x80 == 0 {
+                continue;
+            }
+            devices.extend((1..8).filter_map(|function| PciDevice::probe(bus, device, function)));
+        }
+    }
+    devices
+}
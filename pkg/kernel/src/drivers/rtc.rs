@@ -0,0 +1,85 @@
+//! The real-time clock of the CMOS, where the time of day is kept
+//!
+//! it is read through UEFI, which knows its format, and set here on the
+//! chip: updates are stopped with `SET`, the registers written in the
+//! format of status B, BCD or binary and 12 or 24 hours, then updates
+//! resume. The time kept is UTC, like the kernel reads it.
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use x86_64::instructions::port::Port;
+
+const CMOS_PORT: u16 = 0x70;
+const CMOS_DATA: u16 = 0x71;
+/// Keeps NMIs off while a register is selected
+const NMI_DISABLE: u8 = 0x80;
+
+const REG_SECONDS: u8 = 0x00;
+const REG_MINUTES: u8 = 0x02;
+const REG_HOURS: u8 = 0x04;
+const REG_DAY: u8 = 0x07;
+const REG_MONTH: u8 = 0x08;
+const REG_YEAR: u8 = 0x09;
+const REG_STATUS_B: u8 = 0x0b;
+/// Where QEMU and most firmware keep the century
+const REG_CENTURY: u8 = 0x32;
+
+/// The registers hold binary, not BCD
+const STATUS_B_BINARY: u8 = 1 << 2;
+/// The hours count to 24, not 12 with a PM bit
+const STATUS_B_24H: u8 = 1 << 1;
+/// Updates are stopped while it is set
+const STATUS_B_SET: u8 = 1 << 7;
+/// In the hours register of a 12 hour clock
+const HOURS_PM: u8 = 1 << 7;
+
+fn read(reg: u8) -> u8 {
+    unsafe {
+        Port::new(CMOS_PORT).write(NMI_DISABLE | reg);
+        Port::new(CMOS_DATA).read()
+    }
+}
+
+fn write(reg: u8, value: u8) {
+    unsafe {
+        Port::new(CMOS_PORT).write(NMI_DISABLE | reg);
+        Port::new(CMOS_DATA).write(value);
+    }
+}
+
+/// Set the clock to `time`, UTC, to the second
+///
+/// a year outside 1900..2100 does not fit the century register.
+pub fn set(time: NaiveDateTime) -> bool {
+    if !(1900..2100).contains(&time.year()) {
+        return false;
+    }
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let status = read(REG_STATUS_B);
+        let encode = |value: u32| match status & STATUS_B_BINARY {
+            0 => (((value / 10) << 4) | (value % 10)) as u8,
+            _ => value as u8,
+        };
+
+        let hours = match status & STATUS_B_24H {
+            0 => {
+                let hour = (time.hour() + 11) % 12 + 1;
+                let pm = if time.hour() >= 12 { HOURS_PM } else { 0 };
+                encode(hour) | pm
+            }
+            _ => encode(time.hour()),
+        };
+
+        write(REG_STATUS_B, status | STATUS_B_SET);
+        write(REG_SECONDS, encode(time.second()));
+        write(REG_MINUTES, encode(time.minute()));
+        write(REG_HOURS, hours);
+        write(REG_DAY, encode(time.day()));
+        write(REG_MONTH, encode(time.month()));
+        write(REG_YEAR, encode(time.year() as u32 % 100));
+        write(REG_CENTURY, encode(time.year() as u32 / 100));
+        write(REG_STATUS_B, status & !STATUS_B_SET);
+    });
+
+    true
+}
@@ -1,12 +1,25 @@
 use super::uart16550::SerialPort;
+use core::fmt;
+use core::sync::atomic::{AtomicU16, AtomicUsize, Ordering};
+use crossbeam_queue::ArrayQueue;
 
-const SERIAL_IO_PORT: u16 = 0x3F8; // COM1
+pub const SERIAL_IO_PORT: u16 = 0x3F8; // COM1
+const STAGING_SIZE: usize = 4096;
 
 once_mutex!(pub SERIAL: SerialPort);
 
+/// Output of writers that could not take the serial lock,
+/// e.g. an exception raised while the serial port is in use
+static STAGING: spin::Once<ArrayQueue<u8>> = spin::Once::new();
+static STAGING_DROPPED: AtomicUsize = AtomicUsize::new(0);
+
+/// The process that put the serial line in raw mode, 0 for none
+static RAW_OWNER: AtomicU16 = AtomicU16::new(0);
+
 pub fn init() {
     init_SERIAL(SerialPort::new(SERIAL_IO_PORT));
     get_serial_for_sure().init();
+    super::early::replay(&mut get_serial_for_sure());
 
     println!("{}", crate::get_ascii_header());
     println!("[+] Serial Initialized.");
@@ -14,6 +27,94 @@ pub fn init() {
 
 guard_access_fn!(pub get_serial(SERIAL: SerialPort));
 
+/// Enable the staging buffer, needs the kernel heap
+pub fn init_staging() {
+    STAGING.call_once(|| ArrayQueue::new(STAGING_SIZE));
+}
+
 pub fn backspace() {
     get_serial_for_sure().send(8);
 }
+
+/// Lock-free writer into the staging buffer
+///
+/// bytes are dropped when the buffer is full or not initialized yet
+pub struct StagingWriter;
+
+impl fmt::Write for StagingWriter {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let staging = STAGING.get();
+        for byte in s.bytes() {
+            if !staging.is_some_and(|staging| staging.push(byte).is_ok()) {
+                STAGING_DROPPED.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Write out the staged output through a locked serial port
+pub fn flush_staging(serial: &mut SerialPort) {
+    if let Some(staging) = STAGING.get() {
+        while let Some(byte) = staging.pop() {
+            serial.send(byte);
+        }
+    }
+
+    let dropped = STAGING_DROPPED.swap(0, Ordering::Relaxed);
+    if dropped > 0 {
+        fmt::Write::write_fmt(
+            serial,
+            format_args!("\n\r[!] {} bytes of output dropped\n\r", dropped),
+        )
+        .ok();
+    }
+}
+
+/// Flush the staged output if the serial port is free
+///
+/// called from the clock interrupt as a deferred worker
+pub fn try_flush_staging() {
+    let pending = STAGING.get().is_some_and(|staging| !staging.is_empty())
+        || STAGING_DROPPED.load(Ordering::Relaxed) > 0;
+
+    if pending {
+        if let Some(mut serial) = get_serial() {
+            flush_staging(&mut serial);
+        }
+    }
+}
+
+/// The process that has the serial line in raw mode, if any
+pub fn raw_owner() -> Option<u16> {
+    match RAW_OWNER.load(Ordering::Relaxed) {
+        0 => None,
+        pid => Some(pid),
+    }
+}
+
+#[inline]
+pub fn is_raw() -> bool {
+    raw_owner().is_some()
+}
+
+/// Put the serial line in raw mode for `owner`, or back with `None`
+pub fn set_raw(owner: Option<u16>) {
+    RAW_OWNER.store(owner.unwrap_or(0), Ordering::Relaxed);
+}
+
+/// Leave raw mode if `pid` has it, e.g. when it exits
+pub fn release_raw(pid: u16) {
+    RAW_OWNER
+        .compare_exchange(pid, 0, Ordering::Relaxed, Ordering::Relaxed)
+        .ok();
+}
+
+/// Send `bytes` as they are, for binary data in raw mode
+pub fn write_bytes(bytes: &[u8]) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut serial = get_serial_for_sure();
+        flush_staging(&mut serial);
+        serial.send_all(bytes);
+    });
+}
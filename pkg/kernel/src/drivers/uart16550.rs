@@ -1,6 +1,9 @@
 use core::fmt;
 use x86_64::instructions::port::Port;
 
+/// Bytes the transmit FIFO takes once it is empty
+const TX_FIFO_SIZE: usize = 16;
+
 /// A port-mapped UART 16550 serial interface.
 pub struct SerialPort {
     data: Port<u8>,
@@ -57,6 +60,18 @@ impl SerialPort {
         }
     }
 
+    /// Sends bytes on the serial port, filling the FIFO each time it drains.
+    pub fn send_all(&mut self, data: &[u8]) {
+        for chunk in data.chunks(TX_FIFO_SIZE) {
+            unsafe {
+                while self.line_status.read() & 0x20 == 0 {}
+                for &byte in chunk {
+                    self.data.write(byte);
+                }
+            }
+        }
+    }
+
     /// Receives a byte on the serial port no wait.
     pub fn receive(&mut self) -> Option<u8> {
         unsafe {
@@ -71,9 +86,7 @@ impl SerialPort {
 
 impl fmt::Write for SerialPort {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        for byte in s.bytes() {
-            self.send(byte);
-        }
+        self.send_all(s.as_bytes());
         Ok(())
     }
 }
@@ -0,0 +1,227 @@
+//! The devices a process may open, mounted at `/dev`
+//!
+//! the disks and their partitions are there too, named as in
+//! `drivers::partition`, and the loop devices, see `loopdev`, read and
+//! written as files of their raw blocks, so a volume on a disk is mounted
+//! writable through a loop device.
+
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+use super::vfs::*;
+use super::{loopdev, FsError};
+use crate::drivers::block::{BlockDevice, BLOCK_SIZE, STATS};
+use crate::drivers::partition;
+use crate::resource::{Klog, Null, Resource, StdIO};
+
+#[derive(Clone, Copy)]
+enum Device {
+    Null,
+    Stdin,
+    Stdout,
+    Stderr,
+    Kmsg,
+    /// the counters of the disk caches, read as a file
+    DiskStats,
+    /// the disks and their partitions, read as a file
+    Partitions,
+    /// the loop devices and their files, read as a file
+    Loops,
+    /// the mount points and their filesystems, read as a file
+    Mounts,
+}
+
+const DEVICES: [(&str, Device); 9] = [
+    ("null", Device::Null),
+    ("stdin", Device::Stdin),
+    ("stdout", Device::Stdout),
+    ("stderr", Device::Stderr),
+    ("kmsg", Device::Kmsg),
+    ("diskstats", Device::DiskStats),
+    ("partitions", Device::Partitions),
+    ("loops", Device::Loops),
+    ("mounts", Device::Mounts),
+];
+
+pub struct DevFs;
+
+impl FileSystem for DevFs {
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
+
+    fn root(self: Arc<Self>) -> Arc<dyn Inode> {
+        Arc::new(DevDir)
+    }
+}
+
+struct DevDir;
+
+impl Inode for DevDir {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            kind: FileType::Directory,
+            size: 0,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> FsResult<Arc<dyn Inode>> {
+        DEVICES
+            .iter()
+            .find(|(dev, _)| *dev == name)
+            .map(|&(_, dev)| Arc::new(dev) as Arc<dyn Inode>)
+            .or_else(|| {
+                let dev = partition::find(name).or_else(|| loopdev::find(name))?;
+                Some(Arc::new(Disk(dev)) as Arc<dyn Inode>)
+            })
+            .ok_or(FsError::NotFound)
+    }
+
+    fn read_dir(&self) -> FsResult<Vec<DirEntry>> {
+        let disks = partition::disks()
+            .iter()
+            .flat_map(|disk| disk.names())
+            .chain(loopdev::names());
+        Ok(DEVICES
+            .iter()
+            .map(|(name, dev)| DirEntry {
+                name: name.to_string(),
+                kind: dev.metadata().kind,
+            })
+            .chain(disks.map(|name| DirEntry {
+                name,
+                kind: FileType::File,
+            }))
+            .collect())
+    }
+
+    fn read_at(&self, _offset: usize, _buf: &mut [u8]) -> FsResult<usize> {
+        Err(FsError::IsADirectory)
+    }
+}
+
+impl Device {
+    /// The content of the files read without an fd
+    fn report(&self) -> Option<String> {
+        match self {
+            Self::DiskStats => Some(STATS.report()),
+            Self::Partitions => Some(partition::report()),
+            Self::Loops => Some(loopdev::report()),
+            Self::Mounts => Some(mount_table()),
+            _ => None,
+        }
+    }
+}
+
+impl Inode for Device {
+    fn metadata(&self) -> Metadata {
+        match self.report() {
+            Some(report) => Metadata {
+                kind: FileType::File,
+                size: report.len(),
+            },
+            _ => Metadata {
+                kind: FileType::Device,
+                size: 0,
+            },
+        }
+    }
+
+    /// Only `null` and the files read without an fd, `null` as always empty
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> FsResult<usize> {
+        if let Some(report) = self.report() {
+            let start = offset.min(report.len());
+            let len = buf.len().min(report.len() - start);
+            buf[..len].copy_from_slice(&report.as_bytes()[start..start + len]);
+            return Ok(len);
+        }
+
+        match self {
+            Self::Null => Ok(0),
+            _ => Err(FsError::Unsupported),
+        }
+    }
+
+    fn write_at(&self, _offset: usize, buf: &[u8]) -> FsResult<usize> {
+        match self {
+            Self::Null => Ok(buf.len()),
+            _ => Err(FsError::Unsupported),
+        }
+    }
+
+    fn open(&self) -> FsResult<Arc<dyn Resource>> {
+        Ok(match self {
+            Self::Null => Arc::new(Null),
+            Self::Stdin => Arc::new(StdIO::Stdin),
+            Self::Stdout => Arc::new(StdIO::Stdout),
+            Self::Stderr => Arc::new(StdIO::Stderr),
+            Self::Kmsg => Arc::new(Klog),
+            Self::DiskStats | Self::Partitions | Self::Loops | Self::Mounts => {
+                return Err(FsError::Unsupported)
+            }
+        })
+    }
+}
+
+/// A line for each mount point with the name of its filesystem
+fn mount_table() -> String {
+    let mut out = String::new();
+    for (path, fs) in mounts() {
+        let _ = writeln!(out, "{:<16} {}", path, fs);
+    }
+    out
+}
+
+/// A disk or a partition, its blocks read as a file
+struct Disk(Arc<dyn BlockDevice>);
+
+impl Inode for Disk {
+    fn metadata(&self) -> Metadata {
+        Metadata {
+            kind: FileType::File,
+            size: self.0.block_count() * BLOCK_SIZE,
+        }
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> FsResult<usize> {
+        let size = self.metadata().size;
+        let mut block = [0; BLOCK_SIZE];
+        let mut count = 0;
+
+        while count < buf.len() && offset + count < size {
+            let pos = offset + count;
+            self.0.read_block(pos / BLOCK_SIZE, &mut block)?;
+
+            let start = pos % BLOCK_SIZE;
+            let len = (BLOCK_SIZE - start).min(buf.len() - count);
+            buf[count..count + len].copy_from_slice(&block[start..start + len]);
+            count += len;
+        }
+
+        Ok(count)
+    }
+
+    /// Write over the blocks, a block written in part is read first
+    fn write_at(&self, offset: usize, buf: &[u8]) -> FsResult<usize> {
+        let size = self.metadata().size;
+        let mut block = [0; BLOCK_SIZE];
+        let mut count = 0;
+
+        while count < buf.len() && offset + count < size {
+            let pos = offset + count;
+            let start = pos % BLOCK_SIZE;
+            let len = (BLOCK_SIZE - start).min(buf.len() - count);
+            if len < BLOCK_SIZE {
+                self.0.read_block(pos / BLOCK_SIZE, &mut block)?;
+            }
+
+            block[start..start + len].copy_from_slice(&buf[count..count + len]);
+            self.0.write_block(pos / BLOCK_SIZE, &block)?;
+            count += len;
+        }
+
+        Ok(count)
+    }
+}
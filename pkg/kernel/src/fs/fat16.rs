@@ -0,0 +1,1141 @@
+//! FAT16
+//!
+//! enough to look up files by path, read them, make them and write them:
+//! the partition QEMU makes of a folder with `-drive file=fat:` is FAT16,
+//! with long file names. Nothing is ever removed. A write puts the content
+//! of files straight on the device, then the blocks of the FATs and of the
+//! directories that point at it in one `Transaction`, written by
+//! `Fat16::write_metadata`. A volume with a journal, see `journal`, has
+//! them written through it, and it replayed at mount.
+
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::ops::Range;
+use spin::Mutex;
+
+use super::journal::Journal;
+use super::vfs::{self, FileSystem, FileType, FsResult, Inode, Metadata, Mount};
+use super::FsError;
+use crate::drivers::block::{Block, BlockDevice, BlockError, BLOCK_SIZE};
+
+const DIR_ENTRY_SIZE: usize = 32;
+
+/// A volume with fewer clusters is FAT12
+const MIN_CLUSTERS: usize = 4085;
+/// A volume with as many clusters or more is FAT32
+const MAX_CLUSTERS: usize = 65525;
+/// FAT entries from this up end a cluster chain
+const END_OF_CHAIN: u16 = 0xFFF8;
+/// The end of a chain, as it is written
+const CHAIN_END: u16 = 0xFFFF;
+/// A free cluster in the FAT
+const CLUSTER_FREE: u16 = 0;
+
+/// Most clusters one update of a volume without a journal adds or frees
+const BATCH_CLUSTERS: usize = 256;
+/// Most UCS-2 characters of a long name
+const LONG_NAME_MAX: usize = 255;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+/// The attributes of an entry holding part of a long name,
+/// compared under `ATTR_LONG_NAME_MASK`
+const ATTR_LONG_NAME: u8 = 0x0F;
+const ATTR_LONG_NAME_MASK: u8 = 0x3F;
+
+/// First byte of the entry after the last one of a directory
+const ENTRY_END: u8 = 0x00;
+/// First byte of a deleted entry
+const ENTRY_FREE: u8 = 0xE5;
+
+/// Flags of the case of a short name, as Windows NT sets them
+const CASE_LOWER_NAME: u8 = 0x08;
+const CASE_LOWER_EXT: u8 = 0x10;
+
+/// Set in the sequence number of the last part of a long name
+const LAST_LONG_ENTRY: u8 = 0x40;
+/// Offsets of the UCS-2 characters in a long name entry
+const LONG_NAME_CHARS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+/// The short entry names of a directory and of the one it is in
+const DOT: &[u8; 11] = b".          ";
+const DOT_DOT: &[u8; 11] = b"..         ";
+
+/// The bytes other than letters and digits a short name may have
+const SHORT_NAME_PUNCT: &[u8] = b"!#$%&'()-@^_`{}~";
+
+/// A mounted FAT16 volume
+pub struct Fat16 {
+    dev: Arc<dyn BlockDevice>,
+    sectors_per_cluster: usize,
+    /// first sector of the first FAT
+    fat_start: usize,
+    /// sectors of each FAT, the copies follow the first one
+    fat_size: usize,
+    fats: usize,
+    /// first sector of the root directory, which has a fixed size
+    root_start: usize,
+    /// first sector of cluster 2
+    data_start: usize,
+    clusters: usize,
+    /// in the reserved sectors after the boot sector, if it was formatted
+    journal: Option<Journal>,
+    /// held by an update of the FATs and directories, one at a time
+    update: Mutex<()>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    /// the long name if there is one, the short one otherwise
+    pub name: String,
+    pub attr: u8,
+    /// first cluster of the content, 0 for an empty file or the root
+    pub cluster: u16,
+    pub size: u32,
+    /// the block and the offset in it of the short entry, `None` for the root
+    pub location: Option<(usize, usize)>,
+}
+
+impl DirEntry {
+    pub fn root() -> Self {
+        Self {
+            name: String::from("/"),
+            attr: ATTR_DIRECTORY,
+            cluster: 0,
+            size: 0,
+            location: None,
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attr & ATTR_DIRECTORY != 0
+    }
+}
+
+impl Mount for Fat16 {
+    fn mount(dev: Arc<dyn BlockDevice>) -> Result<Self, FsError> {
+        let mut boot = [0; BLOCK_SIZE];
+        dev.read_block(0, &mut boot)?;
+
+        let u16_at = |i: usize| u16::from_le_bytes([boot[i], boot[i + 1]]) as usize;
+        if boot[510..] != [0x55, 0xAA] || u16_at(11) != BLOCK_SIZE {
+            return Err(FsError::Unsupported);
+        }
+
+        let sectors_per_cluster = boot[13] as usize;
+        let reserved = u16_at(14);
+        let fats = boot[16] as usize;
+        let root_entries = u16_at(17);
+        let fat_size = u16_at(22);
+        let total = match u16_at(19) {
+            0 => u32::from_le_bytes(boot[32..36].try_into().unwrap()) as usize,
+            total => total,
+        };
+
+        // FAT32 keeps the size of its FATs elsewhere
+        if sectors_per_cluster == 0 || fats == 0 || fat_size == 0 {
+            return Err(FsError::Unsupported);
+        }
+
+        let root_start = reserved + fats * fat_size;
+        let data_start = root_start + (root_entries * DIR_ENTRY_SIZE).div_ceil(BLOCK_SIZE);
+        let clusters = total.saturating_sub(data_start) / sectors_per_cluster;
+        if !(MIN_CLUSTERS..MAX_CLUSTERS).contains(&clusters) {
+            return Err(FsError::Unsupported);
+        }
+
+        let journal = Journal::open(dev.as_ref(), 1, reserved.saturating_sub(1))?;
+        if let Some(journal) = &journal {
+            match journal.replay(dev.as_ref()) {
+                Ok(0) => {}
+                Ok(count) => info!("FAT16: replayed {} blocks of the journal.", count),
+                // the boot partition loaded by the bootloader is left as it is
+                Err(FsError::Device(BlockError::ReadOnly)) => {
+                    warn!("FAT16: journal not replayed on a read-only device.")
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        Ok(Self {
+            dev,
+            sectors_per_cluster,
+            fat_start: reserved,
+            fat_size,
+            fats,
+            root_start,
+            data_start,
+            clusters,
+            journal,
+            update: Mutex::new(()),
+        })
+    }
+}
+
+impl FileSystem for Fat16 {
+    fn name(&self) -> &'static str {
+        "fat16"
+    }
+
+    fn root(self: Arc<Self>) -> Arc<dyn Inode> {
+        FatInode::new(self, DirEntry::root())
+    }
+}
+
+impl Fat16 {
+    pub fn clusters(&self) -> usize {
+        self.clusters
+    }
+
+    pub fn cluster_size(&self) -> usize {
+        self.sectors_per_cluster * BLOCK_SIZE
+    }
+
+    pub fn has_journal(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    /// Write blocks of the FATs and directories, each a block number and
+    /// its content, all or none of them if the volume has a journal
+    pub fn write_metadata(&self, blocks: &[(usize, Block)]) -> Result<(), FsError> {
+        match &self.journal {
+            Some(journal) => journal.write(self.dev.as_ref(), blocks),
+            None => blocks
+                .iter()
+                .try_for_each(|(lba, block)| Ok(self.dev.write_block(*lba, block)?)),
+        }
+    }
+
+    /// The entry at `path`, names are split by `/` and matched ignoring case
+    pub fn lookup(&self, path: &str) -> Result<DirEntry, FsError> {
+        let mut entry = DirEntry::root();
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            entry = self
+                .read_dir(&entry)?
+                .into_iter()
+                .find(|entry| entry.name.eq_ignore_ascii_case(name))
+                .ok_or(FsError::NotFound)?;
+        }
+        Ok(entry)
+    }
+
+    /// The entries of directory `dir`, deleted ones and the volume label left out
+    pub fn read_dir(&self, dir: &DirEntry) -> Result<Vec<DirEntry>, FsError> {
+        let mut entries = Vec::new();
+        let mut long_name = LongName::default();
+        let mut block = [0; BLOCK_SIZE];
+
+        for lba in self.dir_blocks(dir)? {
+            self.dev.read_block(lba, &mut block)?;
+            for (i, raw) in block.as_chunks::<DIR_ENTRY_SIZE>().0.iter().enumerate() {
+                let attr = raw[11];
+                match raw[0] {
+                    ENTRY_END => return Ok(entries),
+                    ENTRY_FREE => long_name.clear(),
+                    _ if attr & ATTR_LONG_NAME_MASK == ATTR_LONG_NAME => long_name.push(raw),
+                    _ => {
+                        let name = long_name.take(raw);
+                        if attr & ATTR_VOLUME_ID != 0 {
+                            continue;
+                        }
+                        entries.push(DirEntry {
+                            name: name.unwrap_or_else(|| short_name(raw)),
+                            attr,
+                            cluster: u16::from_le_bytes([raw[26], raw[27]]),
+                            size: u32::from_le_bytes(raw[28..32].try_into().unwrap()),
+                            location: Some((lba, i * DIR_ENTRY_SIZE)),
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// The blocks of directory `dir`, in order
+    fn dir_blocks(&self, dir: &DirEntry) -> Result<Vec<usize>, FsError> {
+        if !dir.is_dir() {
+            return Err(FsError::NotADirectory);
+        }
+
+        Ok(match dir.cluster {
+            0 => (self.root_start..self.data_start).collect(),
+            cluster => self
+                .chain(cluster)?
+                .into_iter()
+                .flat_map(|cluster| self.cluster_blocks(cluster))
+                .collect(),
+        })
+    }
+
+    /// Open the file at `path` to read it from the start
+    pub fn open(&self, path: &str) -> Result<File<'_>, FsError> {
+        self.open_entry(self.lookup(path)?)
+    }
+
+    fn open_entry(&self, entry: DirEntry) -> Result<File<'_>, FsError> {
+        if entry.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+        if entry.size > 0 && !self.is_data_cluster(entry.cluster) {
+            return Err(FsError::Corrupt);
+        }
+
+        Ok(File {
+            fs: self,
+            cluster: entry.cluster,
+            entry,
+            pos: 0,
+        })
+    }
+
+    /// The whole content of the file at `path`
+    pub fn read_file(&self, path: &str) -> Result<Vec<u8>, FsError> {
+        let mut file = self.open(path)?;
+        let mut data = vec![0; file.size()];
+        let len = file.read(&mut data)?;
+        data.truncate(len);
+        Ok(data)
+    }
+
+    fn cluster_lba(&self, cluster: u16) -> usize {
+        self.data_start + (cluster as usize - 2) * self.sectors_per_cluster
+    }
+
+    fn cluster_blocks(&self, cluster: u16) -> Range<usize> {
+        let start = self.cluster_lba(cluster);
+        start..start + self.sectors_per_cluster
+    }
+
+    fn is_data_cluster(&self, cluster: u16) -> bool {
+        (2..self.clusters + 2).contains(&(cluster as usize))
+    }
+
+    /// The cluster after `cluster` in its chain, `None` at the end
+    fn next_cluster(&self, cluster: u16) -> Result<Option<u16>, FsError> {
+        let offset = cluster as usize * 2;
+        let mut block: Block = [0; BLOCK_SIZE];
+        self.dev
+            .read_block(self.fat_start + offset / BLOCK_SIZE, &mut block)?;
+
+        let at = offset % BLOCK_SIZE;
+        match u16::from_le_bytes([block[at], block[at + 1]]) {
+            next if next >= END_OF_CHAIN => Ok(None),
+            next if self.is_data_cluster(next) => Ok(Some(next)),
+            _ => Err(FsError::Corrupt),
+        }
+    }
+
+    /// The cluster after `cluster`, if the block of the FAT it is in is cached
+    fn peek_next_cluster(&self, cluster: u16) -> Option<u16> {
+        let offset = cluster as usize * 2;
+        let mut block: Block = [0; BLOCK_SIZE];
+        if !self
+            .dev
+            .read_cached(self.fat_start + offset / BLOCK_SIZE, &mut block)
+        {
+            return None;
+        }
+
+        let at = offset % BLOCK_SIZE;
+        Some(u16::from_le_bytes([block[at], block[at + 1]]))
+            .filter(|&next| self.is_data_cluster(next))
+    }
+
+    /// Read bytes `offset..offset + len` of `entry` ahead, see `BlockDevice::prefetch`
+    ///
+    /// the chain is followed only through the cached blocks of the FAT,
+    /// so nothing waits for the device, the rest is left to the read.
+    fn prefetch(&self, entry: &DirEntry, offset: usize, len: usize) {
+        let end = (offset + len).min(entry.size as usize);
+        if offset >= end || !self.is_data_cluster(entry.cluster) {
+            return;
+        }
+
+        let cluster_size = self.cluster_size();
+        let mut cluster = entry.cluster;
+        for _ in 0..offset / cluster_size {
+            match self.peek_next_cluster(cluster) {
+                Some(next) => cluster = next,
+                None => return,
+            }
+        }
+
+        let mut pos = offset;
+        loop {
+            let first = pos % cluster_size / BLOCK_SIZE;
+            let last = (end - (pos - pos % cluster_size))
+                .min(cluster_size)
+                .div_ceil(BLOCK_SIZE);
+            self.dev
+                .prefetch(self.cluster_lba(cluster) + first, last - first);
+
+            pos += cluster_size - pos % cluster_size;
+            if pos >= end {
+                return;
+            }
+            match self.peek_next_cluster(cluster) {
+                Some(next) => cluster = next,
+                None => return,
+            }
+        }
+    }
+
+    /// Every cluster of the chain starting at `first`
+    fn chain(&self, first: u16) -> Result<Vec<u16>, FsError> {
+        if !self.is_data_cluster(first) {
+            return Err(FsError::Corrupt);
+        }
+
+        let mut chain = vec![first];
+        while let Some(next) = self.next_cluster(*chain.last().unwrap())? {
+            // a longer chain has a loop
+            if chain.len() == self.clusters {
+                return Err(FsError::Corrupt);
+            }
+            chain.push(next);
+        }
+        Ok(chain)
+    }
+
+    /// The clusters of the content of `entry`, none for an empty file
+    fn file_chain(&self, entry: &DirEntry) -> Result<Vec<u16>, FsError> {
+        match entry.cluster {
+            0 => Ok(Vec::new()),
+            first => self.chain(first),
+        }
+    }
+
+    /// Most clusters one update adds or frees, so the blocks of every FAT
+    /// it changes, with one of a directory, fit in the journal
+    fn batch_clusters(&self) -> usize {
+        match &self.journal {
+            // the FAT entries of the clusters and of the one before them
+            Some(journal) => ((journal.capacity() - 1) / self.fats)
+                .saturating_sub(1)
+                .max(1),
+            None => BATCH_CLUSTERS,
+        }
+    }
+
+    /// `entry` as its short entry is now, the copy of an inode may be old
+    fn reload(&self, entry: &DirEntry) -> Result<DirEntry, FsError> {
+        let Some((lba, at)) = entry.location else {
+            return Ok(entry.clone());
+        };
+
+        let mut block = [0; BLOCK_SIZE];
+        self.dev.read_block(lba, &mut block)?;
+        Ok(DirEntry {
+            cluster: u16::from_le_bytes([block[at + 26], block[at + 27]]),
+            size: u32::from_le_bytes(block[at + 28..at + 32].try_into().unwrap()),
+            ..entry.clone()
+        })
+    }
+
+    /// Write `buf` at `offset` of the file `entry`, a gap before it is
+    /// filled with zeros, and keep `entry` as it is on the volume
+    ///
+    /// a batch of clusters at a time: the content goes straight to the
+    /// device, into clusters nothing points at yet or over the old one,
+    /// then the chain and the entry in one transaction. Stopped by a crash
+    /// or a read to wait for, it leaves the file as after some batch, and
+    /// writing it all again from the start does the same.
+    fn write_file(&self, entry: &mut DirEntry, offset: usize, buf: &[u8]) -> Result<(), FsError> {
+        if entry.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+        let end = offset
+            .checked_add(buf.len())
+            .filter(|&end| end <= u32::MAX as usize)
+            .ok_or(FsError::NoSpace)?;
+
+        let batch = self.batch_clusters() * self.cluster_size();
+        let mut pos = offset.min(entry.size as usize);
+        while pos < end {
+            let stop = (pos - pos % self.cluster_size() + batch).min(end);
+            self.write_batch(entry, pos..stop, offset, buf)?;
+            pos = stop;
+        }
+        Ok(())
+    }
+
+    /// Write bytes `range` of the file `entry`, `buf` from `offset` and
+    /// zeros before it, see `write_file`
+    fn write_batch(
+        &self,
+        entry: &mut DirEntry,
+        range: Range<usize>,
+        offset: usize,
+        buf: &[u8],
+    ) -> Result<(), FsError> {
+        let cluster_size = self.cluster_size();
+        let size = entry.size as usize;
+        let mut tx = Transaction::new(self);
+
+        let mut chain = self.file_chain(entry)?;
+        while chain.len() < range.end.div_ceil(cluster_size) {
+            let cluster = tx.alloc_cluster()?;
+            if let Some(&last) = chain.last() {
+                tx.set_fat(last, cluster)?;
+            }
+            chain.push(cluster);
+        }
+
+        let mut block = [0; BLOCK_SIZE];
+        for at in (range.start - range.start % BLOCK_SIZE..range.end).step_by(BLOCK_SIZE) {
+            let lba = self.cluster_lba(chain[at / cluster_size]) + at % cluster_size / BLOCK_SIZE;
+            block.fill(0);
+            if at < size && (at < range.start || at + BLOCK_SIZE > range.end) {
+                self.dev.read_block(lba, &mut block)?;
+                // what is past the end of the file may be anything
+                if size < at + BLOCK_SIZE {
+                    block[size - at..].fill(0);
+                }
+            }
+
+            // what is before `offset` is the gap, past the old end, so zeros
+            let from = range.start.max(offset).max(at);
+            let to = range.end.min(at + BLOCK_SIZE);
+            if from < to {
+                block[from - at..to - at].copy_from_slice(&buf[from - offset..to - offset]);
+            }
+            self.dev.write_block(lba, &block)?;
+        }
+
+        let updated = DirEntry {
+            cluster: chain[0],
+            size: size.max(range.end) as u32,
+            ..entry.clone()
+        };
+        tx.set_entry(&updated)?;
+        tx.commit()?;
+        *entry = updated;
+        Ok(())
+    }
+
+    /// Cut the file `entry` to `len` bytes, or extend it with zeros, and
+    /// keep `entry` as it is on the volume
+    ///
+    /// clusters past the end are freed a batch at a time, from the last,
+    /// the size following, so a crash leaves a file as after some batch.
+    fn truncate_file(&self, entry: &mut DirEntry, len: usize) -> Result<(), FsError> {
+        if entry.is_dir() {
+            return Err(FsError::IsADirectory);
+        }
+        if len > entry.size as usize {
+            return self.write_file(entry, len, &[]);
+        }
+
+        let keep = len.div_ceil(self.cluster_size());
+        let mut chain = self.file_chain(entry)?;
+        if chain.len() < keep {
+            return Err(FsError::Corrupt);
+        }
+
+        loop {
+            let cut = keep.max(chain.len().saturating_sub(self.batch_clusters()));
+            let mut updated = entry.clone();
+            let mut tx = Transaction::new(self);
+
+            match cut {
+                0 => updated.cluster = 0,
+                cut if cut < chain.len() => tx.set_fat(chain[cut - 1], CHAIN_END)?,
+                _ => {}
+            }
+            for &cluster in &chain[cut..] {
+                tx.set_fat(cluster, CLUSTER_FREE)?;
+            }
+            updated.size = match cut == keep {
+                true => len,
+                false => (cut * self.cluster_size()).min(entry.size as usize),
+            } as u32;
+
+            tx.set_entry(&updated)?;
+            tx.commit()?;
+            *entry = updated;
+            chain.truncate(cut);
+
+            if cut == keep {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Make the entry `name` in directory `dir`, an empty file or a
+    /// directory with `.` and `..`
+    ///
+    /// a name that is no short one gets a long name, and a short one made
+    /// up for it. A full directory is given a cluster more, except the
+    /// root, which has a fixed size.
+    fn create(&self, dir: &DirEntry, name: &str, attr: u8) -> Result<DirEntry, FsError> {
+        if !is_valid_name(name) {
+            return Err(FsError::Unsupported);
+        }
+
+        let mut tx = Transaction::new(self);
+        let mut blocks = self.dir_blocks(dir)?;
+        let mut slots = Vec::new();
+        let mut taken = Vec::new();
+        // the first slot after the last entry
+        let mut end = None;
+        for &lba in &blocks {
+            let block = tx.block(lba)?;
+            for at in (0..BLOCK_SIZE).step_by(DIR_ENTRY_SIZE) {
+                let raw = &block[at..at + DIR_ENTRY_SIZE];
+                if raw[0] == ENTRY_END && end.is_none() {
+                    end = Some(slots.len());
+                }
+                let free = end.is_some() || raw[0] == ENTRY_FREE;
+                if !free && raw[11] & ATTR_LONG_NAME_MASK != ATTR_LONG_NAME {
+                    taken.push(<[u8; 11]>::try_from(&raw[..11]).unwrap());
+                }
+                slots.push(((lba, at), free));
+            }
+        }
+        if self
+            .read_dir(dir)?
+            .iter()
+            .any(|entry| entry.name.eq_ignore_ascii_case(name))
+        {
+            return Err(FsError::Exists);
+        }
+
+        let (short, case, long) = match as_short_name(name) {
+            Some((short, case)) if !taken.contains(&short) => (short, case, Vec::new()),
+            _ => (
+                made_up_short_name(name, &taken).ok_or(FsError::NoSpace)?,
+                0,
+                name.encode_utf16().collect(),
+            ),
+        };
+        let parts = long.len().div_ceil(LONG_NAME_CHARS.len());
+
+        // the long name is in the entries right before the short one
+        let mut block = [0; BLOCK_SIZE];
+        let first = loop {
+            let found = slots
+                .windows(parts + 1)
+                .position(|run| run.iter().all(|(_, free)| *free));
+            if let Some(first) = found {
+                break first;
+            }
+            if dir.cluster == 0 {
+                return Err(FsError::NoSpace);
+            }
+
+            // nothing points at it until the commit, so written right away
+            let cluster = tx.alloc_cluster()?;
+            tx.set_fat(self.cluster_of(*blocks.last().unwrap()), cluster)?;
+            for lba in self.cluster_blocks(cluster) {
+                self.dev.write_block(lba, &block)?;
+                blocks.push(lba);
+                let free = (0..BLOCK_SIZE).step_by(DIR_ENTRY_SIZE);
+                slots.extend(free.map(|at| ((lba, at), true)));
+            }
+        };
+
+        let cluster = match attr & ATTR_DIRECTORY {
+            0 => 0,
+            _ => {
+                let cluster = tx.alloc_cluster()?;
+                let blocks = self.cluster_blocks(cluster);
+                let dot = short_entry(DOT, attr, 0, cluster);
+                let dot_dot = short_entry(DOT_DOT, attr, 0, dir.cluster);
+                block[..DIR_ENTRY_SIZE].copy_from_slice(&dot);
+                block[DIR_ENTRY_SIZE..DIR_ENTRY_SIZE * 2].copy_from_slice(&dot_dot);
+                self.dev.write_block(blocks.start, &block)?;
+                block.fill(0);
+                for lba in blocks.skip(1) {
+                    self.dev.write_block(lba, &block)?;
+                }
+                cluster
+            }
+        };
+
+        let sum = checksum(&short);
+        for (i, &((lba, at), _)) in slots[first..first + parts].iter().enumerate() {
+            let part = long_entry(&long, (parts - i) as u8, i == 0, sum);
+            tx.block_mut(lba)?[at..at + DIR_ENTRY_SIZE].copy_from_slice(&part);
+        }
+        let (lba, at) = slots[first + parts].0;
+        tx.block_mut(lba)?[at..at + DIR_ENTRY_SIZE]
+            .copy_from_slice(&short_entry(&short, attr, case, cluster));
+
+        // slots after the last entry may hold anything, so mark the end again
+        let next = first + parts + 1;
+        match slots.get(next) {
+            Some(&((lba, at), _))
+                if end.is_some_and(|end| end < next) && tx.block(lba)?[at] != ENTRY_END =>
+            {
+                tx.block_mut(lba)?[at] = ENTRY_END;
+            }
+            _ => {}
+        }
+        tx.commit()?;
+
+        Ok(DirEntry {
+            name: name.into(),
+            attr,
+            cluster,
+            size: 0,
+            location: Some((lba, at)),
+        })
+    }
+
+    /// The cluster block `lba` is in
+    fn cluster_of(&self, lba: usize) -> u16 {
+        ((lba - self.data_start) / self.sectors_per_cluster + 2) as u16
+    }
+}
+
+/// The blocks of the FATs and directories one update changes, written
+/// together by `Fat16::write_metadata`
+///
+/// blocks are read through it, so each sees the changes before it. Only
+/// the first FAT is read, the copies are written the same.
+struct Transaction<'a> {
+    fs: &'a Fat16,
+    blocks: BTreeMap<usize, Block>,
+    changed: BTreeSet<usize>,
+    /// where to look for a free cluster next
+    next_free: usize,
+}
+
+impl<'a> Transaction<'a> {
+    fn new(fs: &'a Fat16) -> Self {
+        Self {
+            fs,
+            blocks: BTreeMap::new(),
+            changed: BTreeSet::new(),
+            next_free: 2,
+        }
+    }
+
+    fn block(&mut self, lba: usize) -> Result<&mut Block, FsError> {
+        if !self.blocks.contains_key(&lba) {
+            let mut block = [0; BLOCK_SIZE];
+            self.fs.dev.read_block(lba, &mut block)?;
+            self.blocks.insert(lba, block);
+        }
+        Ok(self.blocks.get_mut(&lba).unwrap())
+    }
+
+    fn block_mut(&mut self, lba: usize) -> Result<&mut Block, FsError> {
+        self.changed.insert(lba);
+        self.block(lba)
+    }
+
+    fn fat(&mut self, cluster: u16) -> Result<u16, FsError> {
+        let offset = cluster as usize * 2;
+        let block = self.block(self.fs.fat_start + offset / BLOCK_SIZE)?;
+        let at = offset % BLOCK_SIZE;
+        Ok(u16::from_le_bytes([block[at], block[at + 1]]))
+    }
+
+    fn set_fat(&mut self, cluster: u16, next: u16) -> Result<(), FsError> {
+        let offset = cluster as usize * 2;
+        let block = self.block_mut(self.fs.fat_start + offset / BLOCK_SIZE)?;
+        let at = offset % BLOCK_SIZE;
+        block[at..at + 2].copy_from_slice(&next.to_le_bytes());
+        Ok(())
+    }
+
+    /// Take a free cluster, as the end of a chain
+    fn alloc_cluster(&mut self) -> Result<u16, FsError> {
+        while self.next_free < self.fs.clusters + 2 {
+            let cluster = self.next_free as u16;
+            self.next_free += 1;
+            if self.fat(cluster)? == CLUSTER_FREE {
+                self.set_fat(cluster, CHAIN_END)?;
+                return Ok(cluster);
+            }
+        }
+        Err(FsError::NoSpace)
+    }
+
+    /// Write the first cluster and the size of `entry` in its short entry
+    fn set_entry(&mut self, entry: &DirEntry) -> Result<(), FsError> {
+        let Some((lba, at)) = entry.location else {
+            return Ok(());
+        };
+
+        let block = self.block_mut(lba)?;
+        block[at + 26..at + 28].copy_from_slice(&entry.cluster.to_le_bytes());
+        block[at + 28..at + 32].copy_from_slice(&entry.size.to_le_bytes());
+        Ok(())
+    }
+
+    /// Write the changed blocks, those of the first FAT to every copy of it
+    fn commit(self) -> Result<(), FsError> {
+        let fats = self.fs.fat_start..self.fs.fat_start + self.fs.fat_size;
+        let mut blocks = Vec::new();
+        for lba in self.changed {
+            let block = self.blocks[&lba];
+            let copies = match fats.contains(&lba) {
+                true => self.fs.fats,
+                false => 1,
+            };
+            blocks.extend((0..copies).map(|i| (lba + i * self.fs.fat_size, block)));
+        }
+        self.fs.write_metadata(&blocks)
+    }
+}
+
+/// A file open for reading, a cluster at a time
+pub struct File<'a> {
+    fs: &'a Fat16,
+    entry: DirEntry,
+    /// the cluster `pos` is in
+    cluster: u16,
+    pos: usize,
+}
+
+impl File<'_> {
+    pub fn size(&self) -> usize {
+        self.entry.size as usize
+    }
+
+    /// Move to `pos`, at most the end, for the next read
+    pub fn seek(&mut self, pos: usize) -> Result<(), FsError> {
+        let pos = pos.min(self.size());
+        // a read at the start of a cluster steps into it, so stay before
+        let index = pos.saturating_sub(1) / self.fs.cluster_size();
+
+        self.cluster = self.entry.cluster;
+        for _ in 0..index {
+            self.cluster = self
+                .fs
+                .next_cluster(self.cluster)?
+                .ok_or(FsError::Corrupt)?;
+        }
+        self.pos = pos;
+        Ok(())
+    }
+
+    /// Read from where the last read stopped, 0 bytes at the end
+    pub fn read(&mut self, buf: &mut [u8]) -> Result<usize, FsError> {
+        let cluster_size = self.fs.cluster_size();
+        let mut block = [0; BLOCK_SIZE];
+        let mut count = 0;
+
+        while count < buf.len() && self.pos < self.size() {
+            let offset = self.pos % cluster_size;
+            if offset == 0 && self.pos > 0 {
+                self.cluster = self
+                    .fs
+                    .next_cluster(self.cluster)?
+                    .ok_or(FsError::Corrupt)?;
+            }
+
+            let lba = self.fs.cluster_lba(self.cluster) + offset / BLOCK_SIZE;
+            self.fs.dev.read_block(lba, &mut block)?;
+
+            let start = offset % BLOCK_SIZE;
+            let len = (BLOCK_SIZE - start)
+                .min(buf.len() - count)
+                .min(self.size() - self.pos);
+            buf[count..count + len].copy_from_slice(&block[start..start + len]);
+
+            count += len;
+            self.pos += len;
+        }
+
+        Ok(count)
+    }
+}
+
+/// An entry of a mounted volume in the VFS
+struct FatInode {
+    fs: Arc<Fat16>,
+    /// as it was when last read or written through this inode
+    entry: Mutex<DirEntry>,
+}
+
+impl FatInode {
+    fn new(fs: Arc<Fat16>, entry: DirEntry) -> Arc<Self> {
+        Arc::new(Self {
+            fs,
+            entry: Mutex::new(entry),
+        })
+    }
+
+    fn entry(&self) -> DirEntry {
+        self.entry.lock().clone()
+    }
+
+    /// Change the file with `update` under the lock of the volume,
+    /// starting from its entry as it is there
+    fn update(&self, update: impl FnOnce(&mut DirEntry) -> FsResult<()>) -> FsResult<()> {
+        let _update = self.fs.update.lock();
+        let mut entry = self.fs.reload(&self.entry())?;
+        // even a failed update may have written some batches
+        let result = update(&mut entry);
+        *self.entry.lock() = entry;
+        result
+    }
+}
+
+impl Inode for FatInode {
+    fn metadata(&self) -> Metadata {
+        let entry = self.entry.lock();
+        Metadata {
+            kind: match entry.is_dir() {
+                true => FileType::Directory,
+                false => FileType::File,
+            },
+            size: entry.size as usize,
+        }
+    }
+
+    /// Names are matched ignoring case, as FAT does
+    fn lookup(&self, name: &str) -> FsResult<Arc<dyn Inode>> {
+        let entry = self
+            .fs
+            .read_dir(&self.entry())?
+            .into_iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+            .ok_or(FsError::NotFound)?;
+
+        Ok(Self::new(self.fs.clone(), entry))
+    }
+
+    fn read_dir(&self) -> FsResult<Vec<vfs::DirEntry>> {
+        Ok(self
+            .fs
+            .read_dir(&self.entry())?
+            .into_iter()
+            .filter(|entry| entry.name != "." && entry.name != "..")
+            .map(|entry| vfs::DirEntry {
+                kind: match entry.is_dir() {
+                    true => FileType::Directory,
+                    false => FileType::File,
+                },
+                name: entry.name,
+            })
+            .collect())
+    }
+
+    fn prefetch(&self, offset: usize, len: usize) {
+        let entry = self.entry();
+        if !entry.is_dir() {
+            self.fs.prefetch(&entry, offset, len);
+        }
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> FsResult<usize> {
+        let mut file = self.fs.open_entry(self.entry())?;
+        file.seek(offset)?;
+        file.read(buf)
+    }
+
+    /// Write at `offset`, a gap before it is filled with zeros
+    fn write_at(&self, offset: usize, buf: &[u8]) -> FsResult<usize> {
+        self.update(|entry| self.fs.write_file(entry, offset, buf))?;
+        Ok(buf.len())
+    }
+
+    fn truncate(&self, len: usize) -> FsResult<()> {
+        self.update(|entry| self.fs.truncate_file(entry, len))
+    }
+
+    /// Make the entry `name`, with a long name if it is no short one
+    fn create(&self, name: &str, kind: FileType) -> FsResult<Arc<dyn Inode>> {
+        let attr = match kind {
+            FileType::File => 0,
+            FileType::Directory => ATTR_DIRECTORY,
+            FileType::Device => return Err(FsError::Unsupported),
+        };
+
+        let _update = self.fs.update.lock();
+        let entry = self.fs.create(&self.entry(), name, attr)?;
+        Ok(Self::new(self.fs.clone(), entry))
+    }
+}
+
+/// The parts of a long name, collected from the entries before a short one
+#[derive(Default)]
+struct LongName {
+    chars: Vec<u16>,
+    checksum: u8,
+    /// sequence number of the part expected next, they count down to 1
+    next: u8,
+}
+
+impl LongName {
+    fn clear(&mut self) {
+        self.chars.clear();
+    }
+
+    fn push(&mut self, raw: &[u8]) {
+        let seq = raw[0] & !LAST_LONG_ENTRY;
+        // the last part comes first
+        if raw[0] & LAST_LONG_ENTRY != 0 {
+            self.chars = vec![0xFFFF; seq as usize * LONG_NAME_CHARS.len()];
+            self.checksum = raw[13];
+            self.next = seq;
+        }
+
+        if self.chars.is_empty() || seq == 0 || seq != self.next || raw[13] != self.checksum {
+            self.clear();
+            return;
+        }
+
+        let start = (seq as usize - 1) * LONG_NAME_CHARS.len();
+        for (i, &at) in LONG_NAME_CHARS.iter().enumerate() {
+            self.chars[start + i] = u16::from_le_bytes([raw[at], raw[at + 1]]);
+        }
+        self.next = seq - 1;
+    }
+
+    /// The long name of the short entry `raw`, if every part of it was found
+    fn take(&mut self, raw: &[u8]) -> Option<String> {
+        let chars = core::mem::take(&mut self.chars);
+        if chars.is_empty() || self.next != 0 || checksum(&raw[..11]) != self.checksum {
+            return None;
+        }
+
+        // the name ends with a NUL unless it fills the parts, then 0xFFFF pads
+        let len = chars
+            .iter()
+            .position(|&c| c == 0 || c == 0xFFFF)
+            .unwrap_or(chars.len());
+        Some(
+            char::decode_utf16(chars[..len].iter().copied())
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect(),
+        )
+    }
+}
+
+/// The checksum of a short name, kept in each part of its long name
+fn checksum(short: &[u8]) -> u8 {
+    short
+        .iter()
+        .fold(0u8, |sum, &byte| sum.rotate_right(1).wrapping_add(byte))
+}
+
+/// The `NAME.EXT` of a short entry, in lower case where the flags say so
+fn short_name(raw: &[u8]) -> String {
+    let part = |bytes: &[u8], lower: bool| -> String {
+        bytes
+            .iter()
+            .map(|&byte| byte as char)
+            .map(|c| if lower { c.to_ascii_lowercase() } else { c })
+            .collect::<String>()
+            .trim_end()
+            .into()
+    };
+
+    let case = raw[12];
+    let mut name = part(&raw[..8], case & CASE_LOWER_NAME != 0);
+    let ext = part(&raw[8..11], case & CASE_LOWER_EXT != 0);
+    if !ext.is_empty() {
+        name.push('.');
+        name.push_str(&ext);
+    }
+    name
+}
+
+/// Whether a file on FAT may be called `name`
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.encode_utf16().count() <= LONG_NAME_MAX
+        && !name.ends_with(['.', ' '])
+        && !name.chars().any(|c| c < ' ' || "\"*/:<>?\\|".contains(c))
+}
+
+fn is_short_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || SHORT_NAME_PUNCT.contains(&byte)
+}
+
+/// The short entry name of `name` and the flags of its case, if it has one
+fn as_short_name(name: &str) -> Option<([u8; 11], u8)> {
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 {
+        return None;
+    }
+
+    let mut short = [b' '; 11];
+    let mut case = 0;
+    for (part, start, lower_flag) in [(base, 0, CASE_LOWER_NAME), (ext, 8, CASE_LOWER_EXT)] {
+        let lower = part.bytes().any(|byte| byte.is_ascii_lowercase());
+        let upper = part.bytes().any(|byte| byte.is_ascii_uppercase());
+        if !part.bytes().all(is_short_char) || lower && upper {
+            return None;
+        }
+        if lower {
+            case |= lower_flag;
+        }
+        short[start..start + part.len()].copy_from_slice(part.to_ascii_uppercase().as_bytes());
+    }
+    Some((short, case))
+}
+
+/// A short entry name for the long `name`, `BASE~N.EXT` with the first
+/// `N` not `taken`
+fn made_up_short_name(name: &str, taken: &[[u8; 11]]) -> Option<[u8; 11]> {
+    let clean = |part: &str| -> Vec<u8> {
+        part.bytes()
+            .filter(|&byte| byte != b' ' && byte != b'.')
+            .map(|byte| match is_short_char(byte) {
+                true => byte.to_ascii_uppercase(),
+                false => b'_',
+            })
+            .collect()
+    };
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((base, ext)) if !base.is_empty() => (clean(base), clean(ext)),
+        _ => (clean(name), Vec::new()),
+    };
+
+    let mut short = [b' '; 11];
+    for (i, &byte) in ext.iter().take(3).enumerate() {
+        short[8 + i] = byte;
+    }
+    (1..=taken.len() + 1).find_map(|n| {
+        let tail = format!("~{}", n);
+        let keep = base.len().min(8 - tail.len());
+        short[..8].fill(b' ');
+        short[..keep].copy_from_slice(&base[..keep]);
+        short[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        (!taken.contains(&short)).then_some(short)
+    })
+}
+
+/// A short entry, of a file or directory made now
+fn short_entry(short: &[u8; 11], attr: u8, case: u8, cluster: u16) -> [u8; DIR_ENTRY_SIZE] {
+    let mut raw = [0; DIR_ENTRY_SIZE];
+    raw[..11].copy_from_slice(short);
+    raw[11] = attr;
+    raw[12] = case;
+    raw[26..28].copy_from_slice(&cluster.to_le_bytes());
+    raw
+}
+
+/// Part `seq` of the long name `chars`, counted from 1
+fn long_entry(chars: &[u16], seq: u8, last: bool, sum: u8) -> [u8; DIR_ENTRY_SIZE] {
+    let mut raw = [0; DIR_ENTRY_SIZE];
+    raw[0] = match last {
+        true => seq | LAST_LONG_ENTRY,
+        false => seq,
+    };
+    raw[11] = ATTR_LONG_NAME;
+    raw[13] = sum;
+
+    let start = (seq as usize - 1) * LONG_NAME_CHARS.len();
+    for (i, &at) in LONG_NAME_CHARS.iter().enumerate() {
+        // a NUL ends a name shorter than its parts, 0xFFFF pads after it
+        let c = match chars.get(start + i) {
+            Some(&c) => c,
+            None if start + i == chars.len() => 0,
+            None => 0xFFFF,
+        };
+        raw[at..at + 2].copy_from_slice(&c.to_le_bytes());
+    }
+    raw
+}
@@ -0,0 +1,114 @@
+//! The files the host shares over fw_cfg, mounted at `/host`
+//!
+//! the directories are made up of the `/` in the file names under
+//! `fw_cfg::SHARE_PREFIX`, a file is read whole on its first read.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Once;
+
+use super::vfs::*;
+use super::FsError;
+use crate::drivers::fw_cfg::{self, FwCfgFile};
+
+pub struct HostFs;
+
+impl FileSystem for HostFs {
+    fn name(&self) -> &'static str {
+        "hostfs"
+    }
+
+    fn root(self: Arc<Self>) -> Arc<dyn Inode> {
+        Arc::new(HostInode::Dir(fw_cfg::SHARE_PREFIX.to_string()))
+    }
+}
+
+enum HostInode {
+    /// the file and its content, once read
+    File(&'static FwCfgFile, Once<Vec<u8>>),
+    /// the prefix of the names of the files in it, ending with `/`
+    Dir(String),
+}
+
+impl HostInode {
+    /// The names under directory `prefix`, each directory once
+    fn names(prefix: &str) -> impl Iterator<Item = (&'static str, bool)> + '_ {
+        fw_cfg::files().iter().filter_map(move |file| {
+            let rest = file.name.strip_prefix(prefix)?;
+            Some(match rest.split_once('/') {
+                Some((dir, _)) => (dir, true),
+                None => (rest, false),
+            })
+        })
+    }
+}
+
+impl Inode for HostInode {
+    fn metadata(&self) -> Metadata {
+        match self {
+            Self::File(file, _) => Metadata {
+                kind: FileType::File,
+                size: file.size as usize,
+            },
+            Self::Dir(_) => Metadata {
+                kind: FileType::Directory,
+                size: 0,
+            },
+        }
+    }
+
+    fn lookup(&self, name: &str) -> FsResult<Arc<dyn Inode>> {
+        let prefix = match self {
+            Self::File(..) => return Err(FsError::NotADirectory),
+            Self::Dir(prefix) => prefix,
+        };
+
+        let path = format!("{}{}", prefix, name);
+        if let Some(file) = fw_cfg::find(&path) {
+            return Ok(Arc::new(Self::File(file, Once::new())));
+        }
+
+        match Self::names(prefix).any(|(entry, dir)| dir && entry == name) {
+            true => Ok(Arc::new(Self::Dir(path + "/"))),
+            false => Err(FsError::NotFound),
+        }
+    }
+
+    fn read_dir(&self) -> FsResult<Vec<DirEntry>> {
+        let prefix = match self {
+            Self::File(..) => return Err(FsError::NotADirectory),
+            Self::Dir(prefix) => prefix,
+        };
+
+        let mut entries: Vec<DirEntry> = Vec::new();
+        for (name, dir) in Self::names(prefix) {
+            if entries.iter().any(|entry| entry.name == name) {
+                continue;
+            }
+            entries.push(DirEntry {
+                name: name.to_string(),
+                kind: if dir {
+                    FileType::Directory
+                } else {
+                    FileType::File
+                },
+            });
+        }
+        Ok(entries)
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> FsResult<usize> {
+        // fw_cfg is read from the start, so the file is read once
+        let data = match self {
+            Self::File(file, data) => data.call_once(|| fw_cfg::read(file)),
+            Self::Dir(_) => return Err(FsError::IsADirectory),
+        };
+
+        let start = offset.min(data.len());
+        let len = buf.len().min(data.len() - start);
+        buf[..len].copy_from_slice(&data[start..start + len]);
+        Ok(len)
+    }
+}
@@ -0,0 +1,147 @@
+//! A write-ahead journal of the metadata of a FAT volume
+//!
+//! the journal is kept in the reserved sectors after the boot sector, so a
+//! volume has one only if it was formatted with room for it, e.g. by
+//! `mkfs.fat -R 64`, and `cargo xtask journal` was run on it once. Blocks
+//! of the FAT and of directories are written in transactions, see
+//! `Fat16::write_metadata`: first a copy of every block to the journal,
+//! then the header listing them, which commits the transaction, then the
+//! blocks to where they belong, and last the header again, marked clean.
+//! A volume found with a committed header at mount was not written
+//! through, so the copies are written again. Content of files is not
+//! journaled, a crash may leave it half written.
+
+use alloc::vec::Vec;
+
+use super::FsError;
+use crate::drivers::block::{Block, BlockDevice, BLOCK_SIZE};
+
+/// Kept in sync with `journal::MAGIC` of xtask
+const MAGIC: &[u8; 8] = b"YSJOURNL";
+
+/// The header states, every block listed is written where it belongs
+const STATE_CLEAN: u32 = 0;
+/// or the copies of them in the journal are still to be written there
+const STATE_COMMITTED: u32 = 1;
+
+/// Bytes of the header before the list of blocks
+const HEADER_SIZE: usize = 20;
+/// Most blocks the header can list
+const MAX_ENTRIES: usize = (BLOCK_SIZE - HEADER_SIZE) / 4;
+
+/// The journal of a volume, in blocks `start..start + len` of its device
+pub struct Journal {
+    start: usize,
+    len: usize,
+}
+
+impl Journal {
+    /// The journal in blocks `start..start + len`, if one was formatted there
+    pub fn open(dev: &dyn BlockDevice, start: usize, len: usize) -> Result<Option<Self>, FsError> {
+        if len < 2 {
+            return Ok(None);
+        }
+
+        let mut header = [0; BLOCK_SIZE];
+        dev.read_block(start, &mut header)?;
+        Ok((&header[..8] == MAGIC).then_some(Self { start, len }))
+    }
+
+    /// Most blocks a transaction may write
+    pub fn capacity(&self) -> usize {
+        (self.len - 1).min(MAX_ENTRIES)
+    }
+
+    /// Write again the blocks of a transaction a crash cut short,
+    /// return how many there were, 0 if the volume was left clean
+    pub fn replay(&self, dev: &dyn BlockDevice) -> Result<usize, FsError> {
+        let mut header = [0; BLOCK_SIZE];
+        dev.read_block(self.start, &mut header)?;
+
+        let u32_at = |i: usize| u32::from_le_bytes(header[i..i + 4].try_into().unwrap());
+        if u32_at(8) != STATE_COMMITTED {
+            return Ok(0);
+        }
+
+        let count = u32_at(12) as usize;
+        if count > self.capacity() {
+            return Err(FsError::Corrupt);
+        }
+
+        let mut blocks = Vec::with_capacity(count);
+        for i in 0..count {
+            let mut block = [0; BLOCK_SIZE];
+            dev.read_block(self.start + 1 + i, &mut block)?;
+            let lba = u32_at(HEADER_SIZE + i * 4) as usize;
+            if self.contains(lba) {
+                return Err(FsError::Corrupt);
+            }
+            blocks.push((lba, block));
+        }
+        // the header is written after the copies, they cannot be torn
+        if checksum(&blocks) != u32_at(16) {
+            return Err(FsError::Corrupt);
+        }
+
+        self.apply(dev, &blocks)?;
+        Ok(count)
+    }
+
+    /// Write `blocks`, each a block number and its content, all or none
+    /// of them even if the system goes down halfway
+    pub fn write(&self, dev: &dyn BlockDevice, blocks: &[(usize, Block)]) -> Result<(), FsError> {
+        if blocks.len() > self.capacity() {
+            return Err(FsError::Unsupported);
+        }
+        if blocks.iter().any(|(lba, _)| self.contains(*lba)) {
+            return Err(FsError::Corrupt);
+        }
+
+        for (i, (_, block)) in blocks.iter().enumerate() {
+            dev.write_block(self.start + 1 + i, block)?;
+        }
+        self.write_header(dev, STATE_COMMITTED, blocks, checksum(blocks))?;
+        self.apply(dev, blocks)
+    }
+
+    fn contains(&self, lba: usize) -> bool {
+        (self.start..self.start + self.len).contains(&lba)
+    }
+
+    /// Write `blocks` where they belong, then mark the journal clean
+    fn apply(&self, dev: &dyn BlockDevice, blocks: &[(usize, Block)]) -> Result<(), FsError> {
+        for (lba, block) in blocks {
+            dev.write_block(*lba, block)?;
+        }
+        self.write_header(dev, STATE_CLEAN, &[], 0)
+    }
+
+    fn write_header(
+        &self,
+        dev: &dyn BlockDevice,
+        state: u32,
+        blocks: &[(usize, Block)],
+        sum: u32,
+    ) -> Result<(), FsError> {
+        let mut header = [0; BLOCK_SIZE];
+        header[..8].copy_from_slice(MAGIC);
+        header[8..12].copy_from_slice(&state.to_le_bytes());
+        header[12..16].copy_from_slice(&(blocks.len() as u32).to_le_bytes());
+        header[16..20].copy_from_slice(&sum.to_le_bytes());
+        for (i, (lba, _)) in blocks.iter().enumerate() {
+            let at = HEADER_SIZE + i * 4;
+            header[at..at + 4].copy_from_slice(&(*lba as u32).to_le_bytes());
+        }
+        Ok(dev.write_block(self.start, &header)?)
+    }
+}
+
+/// FNV-1a of the block numbers and contents of a transaction
+fn checksum(blocks: &[(usize, Block)]) -> u32 {
+    blocks
+        .iter()
+        .flat_map(|(lba, block)| (*lba as u32).to_le_bytes().into_iter().chain(*block))
+        .fold(0x811c_9dc5, |hash, byte| {
+            (hash ^ byte as u32).wrapping_mul(0x0100_0193)
+        })
+}
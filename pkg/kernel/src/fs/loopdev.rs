@@ -0,0 +1,123 @@
+//! Loop devices, a file read as a block device
+//!
+//! the image of a volume kept as a file, e.g. one put on the boot
+//! partition or written to `/tmp`, is mounted like a disk by `mount_image`,
+//! which attaches it as `loop0`, `loop1`, ... under `/dev`. Its blocks are
+//! read and written through the inode of the file, so the device can be
+//! written only if the file can, and a last block the file only has part
+//! of is left out. A device stays attached once its volume is unmounted.
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use spin::RwLock;
+
+use super::vfs::{self, FileType, FsResult, Inode};
+use super::FsError;
+use crate::drivers::block::{Block, BlockDevice, BlockError, BLOCK_SIZE};
+
+/// A file as a block device
+pub struct LoopDevice {
+    file: Arc<dyn Inode>,
+    /// the path the file was found at
+    path: String,
+    count: usize,
+}
+
+impl LoopDevice {
+    /// The file at `path`, with the blocks it has now
+    pub fn new(path: &str) -> FsResult<Self> {
+        let file = vfs::resolve(path)?;
+        let meta = file.metadata();
+        match meta.kind {
+            FileType::File => Ok(Self {
+                file,
+                path: path.into(),
+                count: meta.size / BLOCK_SIZE,
+            }),
+            FileType::Directory => Err(FsError::IsADirectory),
+            FileType::Device => Err(FsError::Unsupported),
+        }
+    }
+}
+
+impl BlockDevice for LoopDevice {
+    fn block_count(&self) -> usize {
+        self.count
+    }
+
+    fn read_block(&self, lba: usize, buf: &mut Block) -> Result<(), BlockError> {
+        if lba >= self.count {
+            return Err(BlockError::OutOfRange(lba));
+        }
+        match self.file.read_at(lba * BLOCK_SIZE, buf) {
+            Ok(BLOCK_SIZE) => Ok(()),
+            // the file was cut since it was attached
+            Ok(_) => Err(BlockError::Io(lba)),
+            Err(err) => Err(block_error(err, lba)),
+        }
+    }
+
+    fn write_block(&self, lba: usize, buf: &Block) -> Result<(), BlockError> {
+        if lba >= self.count {
+            return Err(BlockError::OutOfRange(lba));
+        }
+        match self.file.write_at(lba * BLOCK_SIZE, buf) {
+            Ok(BLOCK_SIZE) => Ok(()),
+            Ok(_) => Err(BlockError::Io(lba)),
+            Err(err) => Err(block_error(err, lba)),
+        }
+    }
+
+    fn prefetch(&self, lba: usize, count: usize) {
+        let count = count.min(self.count.saturating_sub(lba));
+        if count > 0 {
+            self.file.prefetch(lba * BLOCK_SIZE, count * BLOCK_SIZE);
+        }
+    }
+}
+
+/// The error of the device for a failed access to block `lba` of the file,
+/// a read of the disk under it still pends as it would for the disk
+fn block_error(err: FsError, lba: usize) -> BlockError {
+    match err {
+        FsError::Device(err) => err,
+        FsError::ReadOnly => BlockError::ReadOnly,
+        _ => BlockError::Io(lba),
+    }
+}
+
+/// The devices attached, `loop<n>` is the one at `n`
+static LOOPS: RwLock<Vec<Arc<LoopDevice>>> = RwLock::new(Vec::new());
+
+/// Attach `dev` and return its number
+pub fn attach(dev: Arc<LoopDevice>) -> usize {
+    let mut loops = LOOPS.write();
+    loops.push(dev);
+    loops.len() - 1
+}
+
+/// The device named `name` under `/dev`, e.g. `loop0`
+pub fn find(name: &str) -> Option<Arc<dyn BlockDevice>> {
+    let n = name.strip_prefix("loop")?.parse::<usize>().ok()?;
+    let dev = LOOPS.read().get(n)?.clone();
+    Some(dev)
+}
+
+pub fn names() -> Vec<String> {
+    (0..LOOPS.read().len())
+        .map(|n| format!("loop{}", n))
+        .collect()
+}
+
+/// A line for each device and the file behind it, as shown by `/dev/loops`
+pub fn report() -> String {
+    let mut out = String::new();
+    for (n, dev) in LOOPS.read().iter().enumerate() {
+        let name = format!("loop{}", n);
+        let _ = writeln!(out, "{:<10} {:>10} {}", name, dev.count, dev.path);
+    }
+    out
+}
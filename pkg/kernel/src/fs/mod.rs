@@ -0,0 +1,184 @@
+//! Filesystems, mounted together in the VFS
+//!
+//! the boot partition is mounted at `/boot`, with its apps at `/apps`. It is
+//! the one the bootloader loaded, see `load_disk` in the boot config, or
+//! else the disk or partition named by `root=` on the command line, e.g.
+//! `root=disk0p2`, or else the first FAT16 found on an ATA or SATA disk,
+//! the whole disk or one of its partitions, see `drivers::partition`.
+//! Images of FAT16 volumes kept as files, or volumes on the disks under
+//! `/dev`, are mounted by `mount_image`, and written to if they can be.
+
+pub mod devfs;
+pub mod fat16;
+pub mod hostfs;
+pub mod journal;
+pub mod loopdev;
+pub mod ramfs;
+pub mod vfs;
+
+use alloc::format;
+use alloc::string::String;
+use alloc::sync::Arc;
+use boot::BootInfo;
+use core::fmt;
+use syscall_def::errno::*;
+
+use crate::drivers::block::{BlockError, RamDisk};
+use crate::drivers::partition;
+use crate::utils::cmdline;
+pub use fat16::Fat16;
+use loopdev::LoopDevice;
+pub use vfs::{FileType, FsResult, Mount};
+
+/// Where the boot partition is mounted
+pub const BOOT_DIR: &str = "/boot";
+/// Where the apps of the boot partition are mounted again
+pub const APP_DIR: &str = "/apps";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsError {
+    NotFound,
+    NotADirectory,
+    IsADirectory,
+    /// not a filesystem this driver reads
+    Unsupported,
+    /// the structures of the filesystem contradict each other
+    Corrupt,
+    Device(BlockError),
+    ReadOnly,
+    Exists,
+    /// not an absolute path
+    InvalidPath,
+    /// no free cluster, or no free entry in a directory of a fixed size
+    NoSpace,
+}
+
+impl From<BlockError> for FsError {
+    fn from(err: BlockError) -> Self {
+        Self::Device(err)
+    }
+}
+
+impl fmt::Display for FsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "no such file or directory"),
+            Self::NotADirectory => write!(f, "not a directory"),
+            Self::IsADirectory => write!(f, "is a directory"),
+            Self::Unsupported => write!(f, "unsupported filesystem"),
+            Self::Corrupt => write!(f, "corrupt filesystem"),
+            Self::Device(err) => write!(f, "device error: {}", err),
+            Self::ReadOnly => write!(f, "read-only filesystem"),
+            Self::Exists => write!(f, "file exists"),
+            Self::InvalidPath => write!(f, "not an absolute path"),
+            Self::NoSpace => write!(f, "no space left on device"),
+        }
+    }
+}
+
+impl FsError {
+    /// Whether a block is being read, see `drivers::block`
+    pub fn is_pending(&self) -> bool {
+        matches!(self, Self::Device(BlockError::Pending(_)))
+    }
+
+    /// The errno a syscall returns for it
+    pub fn errno(&self) -> usize {
+        match self {
+            Self::NotFound | Self::InvalidPath => ENOENT,
+            Self::NotADirectory => ENOTDIR,
+            Self::IsADirectory => EISDIR,
+            Self::Unsupported => EINVAL,
+            Self::ReadOnly | Self::Device(BlockError::ReadOnly) => EROFS,
+            Self::Corrupt | Self::Device(_) => EIO,
+            Self::Exists => EEXIST,
+            Self::NoSpace => ENOSPC,
+        }
+    }
+}
+
+/// Mount the filesystems, needs the kernel heap and fw_cfg
+pub fn init(boot_info: &'static BootInfo) {
+    vfs::mount("/", Arc::new(ramfs::RamFs::new())).unwrap();
+    vfs::mount("/dev", Arc::new(devfs::DevFs)).unwrap();
+    vfs::mount("/host", Arc::new(hostfs::HostFs)).unwrap();
+    vfs::create("/tmp", FileType::Directory).unwrap();
+
+    let (fs, source) = match boot_info.boot_disk {
+        Some(disk) => match Fat16::mount(Arc::new(RamDisk::new(disk))) {
+            Ok(fs) => (fs, String::from("loaded by the bootloader")),
+            Err(err) => {
+                warn!("Failed to mount boot partition: {}", err);
+                return;
+            }
+        },
+        None => match disk_boot_fs() {
+            Some((fs, name)) => (fs, format!("on {}", name)),
+            None => {
+                info!("No boot partition found, nothing to mount.");
+                return;
+            }
+        },
+    };
+
+    info!(
+        "Boot partition: FAT16, {} clusters of {} bytes, {}{}.",
+        fs.clusters(),
+        fs.cluster_size(),
+        source,
+        if fs.has_journal() { ", journaled" } else { "" }
+    );
+    vfs::mount(BOOT_DIR, Arc::new(fs)).unwrap();
+
+    // the bootloader looks for the apps under `/APP`
+    match vfs::Subtree::new(&format!("{}/APP", BOOT_DIR)) {
+        Ok(apps) => vfs::mount(APP_DIR, Arc::new(apps)).unwrap(),
+        Err(err) => info!("No apps on the boot partition: {}", err),
+    }
+}
+
+/// The FAT16 named by `root=`, or the first one on a disk, and its name
+fn disk_boot_fs() -> Option<(Fat16, String)> {
+    if let Some(root) = cmdline::get("root") {
+        let name = root.trim_start_matches("/dev/");
+        let Some(dev) = partition::find(name) else {
+            warn!("No disk or partition {} for the boot partition.", name);
+            return None;
+        };
+        return match Fat16::mount(dev) {
+            Ok(fs) => Some((fs, name.into())),
+            Err(err) => {
+                warn!("Failed to mount {}: {}", name, err);
+                None
+            }
+        };
+    }
+
+    partition::disks()
+        .iter()
+        .flat_map(|disk| disk.names())
+        .find_map(|name| {
+            let fs = Fat16::mount(partition::find(&name)?).ok()?;
+            Some((fs, name))
+        })
+}
+
+/// Mount the FAT16 image in the file at `image`, or on the disk or
+/// partition at `/dev/<name>`, on `dir` through a loop device, return
+/// the number of the device
+pub fn mount_image(image: &str, dir: &str) -> FsResult<usize> {
+    let dev = Arc::new(LoopDevice::new(image)?);
+    let fs = Fat16::mount(dev.clone())?;
+
+    // attached last, a syscall runs again after a read it waited for
+    vfs::mount(dir, Arc::new(fs))?;
+    let n = loopdev::attach(dev);
+    info!("Mounted {} at {} as loop{}", image, dir, n);
+    Ok(n)
+}
+
+/// Whether the boot partition is mounted
+pub fn has_boot_fs() -> bool {
+    vfs::mounts().iter().any(|(path, _)| path == BOOT_DIR)
+}
+
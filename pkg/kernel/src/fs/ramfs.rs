@@ -0,0 +1,143 @@
+//! A filesystem in the kernel heap, lost at shutdown
+//!
+//! mounted at `/`, it holds the mount points of the others.
+
+use alloc::collections::BTreeMap;
+use alloc::string::{String, ToString};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::vfs::*;
+use super::FsError;
+
+pub struct RamFs {
+    root: Arc<RamInode>,
+}
+
+impl RamFs {
+    pub fn new() -> Self {
+        Self {
+            root: Arc::new(RamInode::Dir(Mutex::new(BTreeMap::new()))),
+        }
+    }
+}
+
+impl Default for RamFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FileSystem for RamFs {
+    fn name(&self) -> &'static str {
+        "ramfs"
+    }
+
+    fn root(self: Arc<Self>) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+enum RamInode {
+    File(Mutex<Vec<u8>>),
+    Dir(Mutex<BTreeMap<String, Arc<RamInode>>>),
+}
+
+impl Inode for RamInode {
+    fn metadata(&self) -> Metadata {
+        match self {
+            Self::File(data) => Metadata {
+                kind: FileType::File,
+                size: data.lock().len(),
+            },
+            Self::Dir(_) => Metadata {
+                kind: FileType::Directory,
+                size: 0,
+            },
+        }
+    }
+
+    fn lookup(&self, name: &str) -> FsResult<Arc<dyn Inode>> {
+        match self {
+            Self::File(_) => Err(FsError::NotADirectory),
+            Self::Dir(entries) => match entries.lock().get(name) {
+                Some(inode) => Ok(inode.clone()),
+                None => Err(FsError::NotFound),
+            },
+        }
+    }
+
+    fn read_dir(&self) -> FsResult<Vec<DirEntry>> {
+        match self {
+            Self::File(_) => Err(FsError::NotADirectory),
+            Self::Dir(entries) => Ok(entries
+                .lock()
+                .iter()
+                .map(|(name, inode)| DirEntry {
+                    name: name.clone(),
+                    kind: inode.metadata().kind,
+                })
+                .collect()),
+        }
+    }
+
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> FsResult<usize> {
+        match self {
+            Self::File(data) => {
+                let data = data.lock();
+                let start = offset.min(data.len());
+                let len = buf.len().min(data.len() - start);
+                buf[..len].copy_from_slice(&data[start..start + len]);
+                Ok(len)
+            }
+            Self::Dir(_) => Err(FsError::IsADirectory),
+        }
+    }
+
+    /// Write at `offset`, a gap before it is filled with zeros
+    fn write_at(&self, offset: usize, buf: &[u8]) -> FsResult<usize> {
+        match self {
+            Self::File(data) => {
+                let mut data = data.lock();
+                let end = offset + buf.len();
+                if data.len() < end {
+                    data.resize(end, 0);
+                }
+                data[offset..end].copy_from_slice(buf);
+                Ok(buf.len())
+            }
+            Self::Dir(_) => Err(FsError::IsADirectory),
+        }
+    }
+
+    fn truncate(&self, len: usize) -> FsResult<()> {
+        match self {
+            Self::File(data) => {
+                data.lock().resize(len, 0);
+                Ok(())
+            }
+            Self::Dir(_) => Err(FsError::IsADirectory),
+        }
+    }
+
+    fn create(&self, name: &str, kind: FileType) -> FsResult<Arc<dyn Inode>> {
+        let entries = match self {
+            Self::File(_) => return Err(FsError::NotADirectory),
+            Self::Dir(entries) => entries,
+        };
+
+        let inode = Arc::new(match kind {
+            FileType::File => Self::File(Mutex::new(Vec::new())),
+            FileType::Directory => Self::Dir(Mutex::new(BTreeMap::new())),
+            FileType::Device => return Err(FsError::Unsupported),
+        });
+
+        let mut entries = entries.lock();
+        if entries.contains_key(name) {
+            return Err(FsError::Exists);
+        }
+        entries.insert(name.to_string(), inode.clone());
+        Ok(inode)
+    }
+}
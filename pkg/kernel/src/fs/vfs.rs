@@ -0,0 +1,277 @@
+//! The virtual filesystem, one tree of every mounted filesystem
+//!
+//! a path is resolved in the filesystem mounted at its longest prefix,
+//! then name by name with `Inode::lookup`. Paths are absolute, `.` and
+//! `..` are dropped before, so `..` never leaves through a mount point.
+
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::RwLock;
+use syscall_def::{O_ACCMODE, O_CREAT, O_RDONLY, O_TRUNC};
+
+use super::FsError;
+use crate::drivers::block::BlockDevice;
+use crate::resource::{OpenFile, Resource};
+
+pub type FsResult<T> = Result<T, FsError>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    File,
+    Directory,
+    /// opened as a resource of its own, see `Inode::open`
+    Device,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Metadata {
+    pub kind: FileType,
+    /// bytes of a file, 0 for the others
+    pub size: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub kind: FileType,
+}
+
+/// A file, directory or device of a filesystem
+///
+/// the defaults are those of a read-only file.
+pub trait Inode: Send + Sync {
+    fn metadata(&self) -> Metadata;
+
+    /// The entry `name` of this directory
+    fn lookup(&self, _name: &str) -> FsResult<Arc<dyn Inode>> {
+        Err(FsError::NotADirectory)
+    }
+
+    fn read_dir(&self) -> FsResult<Vec<DirEntry>> {
+        Err(FsError::NotADirectory)
+    }
+
+    /// Read from `offset`, fewer bytes than `buf` holds only at the end
+    fn read_at(&self, offset: usize, buf: &mut [u8]) -> FsResult<usize>;
+
+    fn write_at(&self, _offset: usize, _buf: &[u8]) -> FsResult<usize> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// Start reading `offset..offset + len` of the file from its device,
+    /// so a read of it soon after need not wait
+    fn prefetch(&self, _offset: usize, _len: usize) {}
+
+    /// Cut or extend the file to `len` bytes
+    fn truncate(&self, _len: usize) -> FsResult<()> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// Make the entry `name` of this directory
+    fn create(&self, _name: &str, _kind: FileType) -> FsResult<Arc<dyn Inode>> {
+        Err(FsError::ReadOnly)
+    }
+
+    /// The resource an fd of this device reads and writes,
+    /// files are read through `read_at` instead
+    fn open(&self) -> FsResult<Arc<dyn Resource>> {
+        Err(FsError::Unsupported)
+    }
+}
+
+pub trait FileSystem: Send + Sync {
+    /// The name of the kind of filesystem, as `mount` shows it
+    fn name(&self) -> &'static str;
+
+    fn root(self: Arc<Self>) -> Arc<dyn Inode>;
+}
+
+/// A filesystem kept on a block device
+pub trait Mount: FileSystem + Sized {
+    /// Read the filesystem starting at the first block of `dev`
+    fn mount(dev: Arc<dyn BlockDevice>) -> FsResult<Self>;
+}
+
+/// A directory of another filesystem, mounted again elsewhere
+pub struct Subtree {
+    root: Arc<dyn Inode>,
+}
+
+impl Subtree {
+    /// The directory at `path`, which is resolved now
+    pub fn new(path: &str) -> FsResult<Self> {
+        let root = resolve(path)?;
+        if root.metadata().kind != FileType::Directory {
+            return Err(FsError::NotADirectory);
+        }
+        Ok(Self { root })
+    }
+}
+
+impl FileSystem for Subtree {
+    fn name(&self) -> &'static str {
+        "bind"
+    }
+
+    fn root(self: Arc<Self>) -> Arc<dyn Inode> {
+        self.root.clone()
+    }
+}
+
+/// Every mounted filesystem by the normalized path it is mounted at
+static MOUNTS: RwLock<BTreeMap<String, Arc<dyn FileSystem>>> = RwLock::new(BTreeMap::new());
+
+/// The names of `path`, with `.` and `..` applied
+fn components(path: &str) -> FsResult<Vec<&str>> {
+    if !path.starts_with('/') {
+        return Err(FsError::InvalidPath);
+    }
+
+    let mut names = Vec::new();
+    for name in path.split('/') {
+        match name {
+            "" | "." => (),
+            ".." => {
+                names.pop();
+            }
+            name => names.push(name),
+        }
+    }
+    Ok(names)
+}
+
+fn join(names: &[&str]) -> String {
+    let mut path = String::new();
+    for name in names {
+        path.push('/');
+        path.push_str(name);
+    }
+    if path.is_empty() {
+        path.push('/');
+    }
+    path
+}
+
+/// Mount `fs` at `path`, made a directory of the filesystem under it if it can be
+pub fn mount(path: &str, fs: Arc<dyn FileSystem>) -> FsResult<()> {
+    let names = components(path)?;
+    let path = join(&names);
+    if MOUNTS.read().contains_key(&path) {
+        return Err(FsError::Exists);
+    }
+
+    // so the mount point shows up where it is listed
+    if let Some((name, parent)) = names.split_last() {
+        if let Ok(parent) = resolve(&join(parent)) {
+            if parent.lookup(name).is_err() {
+                let _ = parent.create(name, FileType::Directory);
+            }
+        }
+    }
+
+    info!("Mounted {} at {}", fs.name(), path);
+    MOUNTS.write().insert(path, fs);
+    Ok(())
+}
+
+pub fn umount(path: &str) -> FsResult<()> {
+    let path = join(&components(path)?);
+    match MOUNTS.write().remove(&path) {
+        Some(_) => Ok(()),
+        None => Err(FsError::NotFound),
+    }
+}
+
+/// The mount points with the name of the filesystem at each
+pub fn mounts() -> Vec<(String, &'static str)> {
+    MOUNTS
+        .read()
+        .iter()
+        .map(|(path, fs)| (path.clone(), fs.name()))
+        .collect()
+}
+
+/// The inode at `path`
+pub fn resolve(path: &str) -> FsResult<Arc<dyn Inode>> {
+    let names = components(path)?;
+
+    // the longest prefix that is mounted, `/` is if anything is
+    let (depth, fs) = {
+        let mounts = MOUNTS.read();
+        (0..=names.len())
+            .rev()
+            .find_map(|depth| Some((depth, mounts.get(&join(&names[..depth]))?.clone())))
+            .ok_or(FsError::NotFound)?
+    };
+
+    let mut inode = fs.root();
+    for name in &names[depth..] {
+        inode = inode.lookup(name)?;
+    }
+    Ok(inode)
+}
+
+pub fn read_dir(path: &str) -> FsResult<Vec<DirEntry>> {
+    resolve(path)?.read_dir()
+}
+
+/// The whole content of the file at `path`
+pub fn read_file(path: &str) -> FsResult<Vec<u8>> {
+    read_all(resolve(path)?.as_ref())
+}
+
+/// Make a file or directory at `path`, in the directory it names
+pub fn create(path: &str, kind: FileType) -> FsResult<Arc<dyn Inode>> {
+    let names = components(path)?;
+    let (name, parent) = names.split_last().ok_or(FsError::Exists)?;
+    let parent = resolve(&join(parent))?;
+    if parent.lookup(name).is_ok() {
+        return Err(FsError::Exists);
+    }
+    parent.create(name, kind)
+}
+
+/// Files may be created and written by `open`, devices are written either way
+const FILES_WRITABLE: bool = cfg!(feature = "fs-write");
+
+/// The resource an fd opened at `path` reads, `flags` as `Syscall::Open` takes
+pub fn open(path: &str, flags: usize) -> FsResult<Arc<dyn Resource>> {
+    let inode = match resolve(path) {
+        Err(FsError::NotFound) if flags & O_CREAT != 0 => {
+            if !FILES_WRITABLE {
+                return Err(FsError::ReadOnly);
+            }
+            create(path, FileType::File)?
+        }
+        inode => inode?,
+    };
+
+    match inode.metadata().kind {
+        FileType::Device => inode.open(),
+        FileType::Directory => Err(FsError::IsADirectory),
+        FileType::File => {
+            if !FILES_WRITABLE && flags & O_ACCMODE != O_RDONLY {
+                return Err(FsError::ReadOnly);
+            }
+            if flags & O_TRUNC != 0 && flags & O_ACCMODE != O_RDONLY {
+                inode.truncate(0)?;
+            }
+            Ok(Arc::new(OpenFile::new(inode, flags)))
+        }
+    }
+}
+
+fn read_all(inode: &dyn Inode) -> FsResult<Vec<u8>> {
+    let meta = inode.metadata();
+    if meta.kind == FileType::Directory {
+        return Err(FsError::IsADirectory);
+    }
+
+    let mut data = vec![0; meta.size];
+    inode.prefetch(0, meta.size);
+    let len = inode.read_at(0, &mut data)?;
+    data.truncate(len);
+    Ok(data)
+}
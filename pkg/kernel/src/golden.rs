@@ -0,0 +1,223 @@
+//! The regression suite of `xtask test`, apps checked against golden outputs
+//!
+//! started instead of init when `test` is on the kernel command line. The
+//! apps run are those with a golden output `<app>.out` under `TEST_DIR`,
+//! which `xtask build` copies from `pkg/app/<app>/golden.out`, or only the
+//! ones named by `test=<app>,<app>`. Each app is spawned alone with its
+//! stdout captured by the kernel, and once it exits, what it wrote is
+//! compared with its golden output. The lines that differ are printed over
+//! serial, and the kernel shuts down with code 1 if any app failed, which
+//! `xtask test` sees through `isa-debug-exit`. What each app wrote is also
+//! framed on debugcon, for `xtask test --bless` to write the golden outputs
+//! from. Runs only repeat exactly with `deterministic=on`.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use x86_64::instructions::interrupts::without_interrupts;
+
+use crate::drivers::debugcon;
+use crate::fs::{vfs, FileType, FsError, BOOT_DIR};
+use crate::proc::{self, deterministic, AppArgs};
+use crate::resource::{Buffer, Resource};
+use crate::utils::cmdline;
+
+/// Where the golden outputs are, under the boot partition
+pub const TEST_DIR: &str = "TEST";
+/// Cpu time an app may use before it is killed, in nanoseconds
+const CPU_LIMIT: u64 = 30_000_000_000;
+/// Most cells of the table a diff is found with, a bigger one is not
+/// diffed but shown from the first line that differs
+const DIFF_MAX_CELLS: usize = 1 << 20;
+
+/// Whether the kernel runs the suite instead of init
+pub fn enabled() -> bool {
+    cmdline::get("test").is_some()
+}
+
+/// Run the apps with golden outputs, return the code to shut down with
+pub fn run() -> isize {
+    let apps = match apps() {
+        Ok(apps) if !apps.is_empty() => apps,
+        Ok(_) => {
+            error!(
+                "Golden tests: no golden outputs under {}/{}.",
+                BOOT_DIR, TEST_DIR
+            );
+            return 1;
+        }
+        Err(err) => {
+            error!(
+                "Golden tests: cannot read {}/{}: {}",
+                BOOT_DIR, TEST_DIR, err
+            );
+            return 1;
+        }
+    };
+
+    info!("Golden tests: running {} apps.", apps.len());
+    let failed: Vec<&str> = apps
+        .iter()
+        .filter(|(app, golden)| !check(app, golden))
+        .map(|(app, _)| app.as_str())
+        .collect();
+
+    match failed.as_slice() {
+        [] => {
+            println!("golden: all {} apps passed", apps.len());
+            0
+        }
+        failed => {
+            println!(
+                "golden: {} of {} apps failed: {}",
+                failed.len(),
+                apps.len(),
+                failed.join(", ")
+            );
+            1
+        }
+    }
+}
+
+/// The apps to run with their golden outputs, by name
+fn apps() -> Result<Vec<(String, Vec<u8>)>, FsError> {
+    let dir = format!("{}/{}", BOOT_DIR, TEST_DIR);
+    let selected: Vec<&str> = cmdline::get("test")
+        .unwrap_or_default()
+        .split(',')
+        .filter(|name| !name.is_empty())
+        .collect();
+
+    let mut apps = Vec::new();
+    for entry in vfs::read_dir(&dir)? {
+        let name = entry.name.to_lowercase();
+        let Some(app) = name.strip_suffix(".out") else {
+            continue;
+        };
+        if entry.kind != FileType::File || !(selected.is_empty() || selected.contains(&app)) {
+            continue;
+        }
+
+        let golden = vfs::read_file(&format!("{}/{}", dir, entry.name))?;
+        apps.push((String::from(app), golden));
+    }
+
+    apps.sort();
+    Ok(apps)
+}
+
+/// Run `app` and compare its stdout with `golden`
+fn check(app: &str, golden: &[u8]) -> bool {
+    let Some(output) = capture(app) else {
+        println!("golden: {}: FAILED to run", app);
+        return false;
+    };
+
+    let mut frame = format!("\n<<<golden {} {}>>>\n", app, output.len()).into_bytes();
+    frame.extend_from_slice(&output);
+    debugcon::write_bytes(&frame);
+
+    if output == golden {
+        println!("golden: {}: ok", app);
+        return true;
+    }
+
+    println!("golden: {}: FAILED, its output differs:", app);
+    let lines = |bytes: &[u8]| -> Vec<String> {
+        String::from_utf8_lossy(bytes)
+            .lines()
+            .map(String::from)
+            .collect()
+    };
+    print_diff(&lines(golden), &lines(&output));
+    false
+}
+
+/// What `app` wrote to its stdout until it exited
+///
+/// the buffer is drained while the app runs, as one that fills it would
+/// otherwise retry its writes until it is killed.
+fn capture(app: &str) -> Option<Vec<u8>> {
+    let buffer = Arc::new(Buffer::new());
+    let fd = proc::open(buffer.clone())?;
+    // each app starts on a fresh slice, so it runs the same alone or in the suite
+    deterministic::restart_slice();
+    let spawned = proc::spawn_args(app, &AppArgs::new(app), Some(fd));
+    proc::close(fd);
+
+    let pid = match spawned {
+        Ok(pid) => pid,
+        Err(err) => {
+            warn!("Golden tests: failed to spawn {}: {}", app, err);
+            return None;
+        }
+    };
+    proc::cpu_limit(pid, Some(CPU_LIMIT));
+
+    let mut output = Vec::new();
+    let ret = loop {
+        // checked before draining, so what it wrote last is not missed
+        let ret = proc::wait_no_block(pid);
+        drain(&buffer, &mut output);
+        if let Some(ret) = ret {
+            break ret;
+        }
+        if !crate::memory::zero_idle_frames() {
+            x86_64::instructions::hlt();
+        }
+    };
+    debug!("Golden tests: {}#{} exited with {}", app, pid, ret);
+    Some(output)
+}
+
+/// Move what is in `buffer` to the end of `output`
+fn drain(buffer: &Buffer, output: &mut Vec<u8>) {
+    let mut buf = [0; 512];
+    loop {
+        // the app writes with interrupts disabled, and would spin on the
+        // lock of the buffer if the kernel was switched out holding it
+        let count = without_interrupts(|| buffer.read(&mut buf)).unwrap_or(0);
+        if count == 0 {
+            return;
+        }
+        output.extend_from_slice(&buf[..count]);
+    }
+}
+
+/// Print the lines to remove from `expected` and to add to get `actual`,
+/// each with its line number in the file it is from
+fn print_diff(expected: &[String], actual: &[String]) {
+    let (n, m) = (expected.len(), actual.len());
+    if (n + 1) * (m + 1) > DIFF_MAX_CELLS {
+        let same = expected.iter().zip(actual).take_while(|(a, b)| a == b);
+        let first = same.count();
+        println!("    too long to diff, differs from line {}", first + 1);
+        return;
+    }
+
+    // the longest common subsequence of `expected[i..]` and `actual[j..]`
+    let width = m + 1;
+    let mut lcs = vec![0u16; (n + 1) * width];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lcs[i * width + j] = match expected[i] == actual[j] {
+                true => lcs[(i + 1) * width + j + 1] + 1,
+                false => lcs[(i + 1) * width + j].max(lcs[i * width + j + 1]),
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && expected[i] == actual[j] {
+            i += 1;
+            j += 1;
+        } else if j < m && (i == n || lcs[i * width + j + 1] >= lcs[(i + 1) * width + j]) {
+            println!("    +{:>4}| {}", j + 1, actual[j]);
+            j += 1;
+        } else {
+            println!("    -{:>4}| {}", i + 1, expected[i]);
+            i += 1;
+        }
+    }
+}
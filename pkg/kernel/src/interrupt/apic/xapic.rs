@@ -25,6 +25,69 @@ impl XApic {
     pub unsafe fn new(addr: u64) -> Self {
         XApic { addr }
     }
+
+    /// Interrupt every `count` bus cycles
+    pub fn set_timer_periodic(&mut self, count: u32) {
+        unsafe {
+            self.write(TIMER, PERIODIC | (T_IRQ0 + IRQ_TIMER));
+            self.write(TICR, count.max(1));
+        }
+    }
+
+    /// Interrupt once after `count` bus cycles
+    pub fn set_timer_oneshot(&mut self, count: u32) {
+        unsafe {
+            self.write(TIMER, T_IRQ0 + IRQ_TIMER);
+            self.write(TICR, count.max(1));
+        }
+    }
+
+    /// Bus cycles left until the timer fires, 0 if it is stopped
+    pub fn timer_remaining(&self) -> u32 {
+        unsafe { self.read(TCCR) }
+    }
+
+    /// Count the timer's bus cycles per second against the PIT
+    ///
+    /// the timer is left stopped and masked.
+    pub fn calibrate_timer(&mut self) -> u64 {
+        use x86_64::instructions::port::Port;
+
+        let mut gate = Port::<u8>::new(PIT_GATE);
+        let mut command = Port::<u8>::new(PIT_COMMAND);
+        let mut channel2 = Port::<u8>::new(PIT_CHANNEL2);
+        let count = (PIT_HZ / CALIBRATE_DIV) as u16;
+
+        unsafe {
+            // channel 2 gate on, speaker off
+            let value = gate.read();
+            gate.write((value & !0x02) | 0x01);
+
+            // one-shot, lobyte then hibyte
+            command.write(0b1011_0000);
+            channel2.write(count as u8);
+            channel2.write((count >> 8) as u8);
+
+            // restart the count with a rising edge on the gate
+            let value = gate.read();
+            gate.write(value & !0x01);
+            gate.write(value | 0x01);
+
+            self.write(TDCR, X1);
+            self.write(TIMER, MASKED | (T_IRQ0 + IRQ_TIMER));
+            self.write(TICR, u32::MAX);
+
+            // channel 2 output goes high when the count is done
+            while gate.read() & 0x20 == 0 {
+                core::hint::spin_loop();
+            }
+
+            let elapsed = u32::MAX - self.read(TCCR);
+            self.write(TICR, 0);
+
+            elapsed as u64 * CALIBRATE_DIV
+        }
+    }
 }
 
 impl LocalApic for XApic {
@@ -156,6 +219,12 @@ const TICR: u32 = 0x0380; // Timer Initial Count
 const TCCR: u32 = 0x0390; // Timer Current Count
 const TDCR: u32 = 0x03E0; // Timer Divide Configuration
 
+const PIT_HZ: u64 = 1_193_182; // PIT input clock
+const PIT_CHANNEL2: u16 = 0x42;
+const PIT_COMMAND: u16 = 0x43;
+const PIT_GATE: u16 = 0x61; // channel 2 gate and output
+const CALIBRATE_DIV: u64 = 100; // calibrate over 1/100 s
+
 const T_IRQ0: u32 = 32; // IRQ 0 corresponds to int T_IRQ
 const IRQ_TIMER: u32 = 0;
 const IRQ_KBD: u32 = 1;
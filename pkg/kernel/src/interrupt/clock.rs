@@ -1,15 +1,140 @@
 use super::consts;
+use crate::memory::physical_to_virtual;
+use crate::utils::cmdline;
 use crate::{memory::gdt, proc::ProcessContext};
+use core::sync::atomic::{AtomicU64, Ordering};
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 
+use super::apic::{XApic, LAPIC_ADDR};
+
+/// Tick frequency of `dyntick` when no `hz=` is given
+const DEFAULT_HZ: u64 = 100;
+/// Longest a dyntick timer sleeps when nothing else is waiting to run,
+/// deferred work such as flushing the serial port still runs this often
+const DYNTICK_IDLE_NANOS: u64 = 100_000_000;
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
 pub unsafe fn reg_idt(idt: &mut InterruptDescriptorTable) {
     idt[consts::Interrupts::IrqBase as u8 + consts::Irq::Timer as u8]
         .set_handler_fn(clock_handler)
         .set_stack_index(gdt::CONTEXT_SWITCH_IST_INDEX);
 }
 
+static TICKS: AtomicU64 = AtomicU64::new(0);
+
+/// Timer ticks since interrupts were enabled
+///
+/// with `dyntick` they come at a varying rate, only when needed.
+#[inline]
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// The calibrated timer, set up when `hz=` or `dyntick` is on the command line
+struct Timer {
+    /// bus cycles of the apic timer per second
+    counts_per_sec: u64,
+    hz: u64,
+    dyntick: bool,
+}
+
+static TIMER: spin::Once<Timer> = spin::Once::new();
+
+impl Timer {
+    fn counts(&self, nanos: u64) -> u32 {
+        let counts = nanos as u128 * self.counts_per_sec as u128 / NANOS_PER_SEC as u128;
+        counts.clamp(1, u32::MAX as u128) as u32
+    }
+
+    #[inline]
+    fn slice(&self) -> u32 {
+        self.counts(NANOS_PER_SEC / self.hz)
+    }
+}
+
+fn lapic() -> XApic {
+    unsafe { XApic::new(physical_to_virtual(LAPIC_ADDR)) }
+}
+
+/// Program the timer from `hz=<n>` and `dyntick` on the command line
+///
+/// without either, the timer keeps the fixed count set by `cpu_init`.
+pub fn init() {
+    let hz = cmdline::get("hz").and_then(|hz| hz.parse::<u64>().ok());
+    let dyntick = cmdline::enabled("dyntick");
+
+    if hz.is_none() && !dyntick {
+        return;
+    }
+
+    let mut lapic = lapic();
+    let timer = Timer {
+        counts_per_sec: lapic.calibrate_timer(),
+        hz: hz.filter(|&hz| hz > 0).unwrap_or(DEFAULT_HZ),
+        dyntick,
+    };
+
+    if timer.dyntick {
+        lapic.set_timer_oneshot(timer.slice());
+    } else {
+        lapic.set_timer_periodic(timer.slice());
+    }
+
+    info!(
+        "Timer: {} Hz{}, {} counts per second.",
+        timer.hz,
+        if timer.dyntick { ", dyntick" } else { "" },
+        timer.counts_per_sec
+    );
+
+    TIMER.call_once(|| timer);
+}
+
+/// Arm a dyntick timer for the next deadline
+///
+/// a full slice if another process is waiting to run, otherwise when the
+/// cpu limit of the current process runs out or a sleeping process wakes,
+/// but no later than the idle period.
+fn rearm(timer: &Timer) {
+    let nanos = if crate::proc::has_ready() {
+        NANOS_PER_SEC / timer.hz
+    } else {
+        crate::proc::cpu_limit_left()
+            .unwrap_or(u64::MAX)
+            .min(crate::proc::next_wake_in().unwrap_or(u64::MAX))
+            .min(DYNTICK_IDLE_NANOS)
+    };
+    lapic().set_timer_oneshot(timer.counts(nanos));
+}
+
+/// Bring a dyntick timer forward to one slice from now
+///
+/// called when a process becomes ready, so it does not wait for a long idle period.
+pub fn kick() {
+    if let Some(timer) = TIMER.get().filter(|timer| timer.dyntick) {
+        let slice = timer.slice();
+        let mut lapic = lapic();
+        if lapic.timer_remaining() > slice {
+            lapic.set_timer_oneshot(slice);
+        }
+    }
+}
+
 pub extern "C" fn clock(mut context: ProcessContext) {
-    crate::proc::switch(&mut context);
+    TICKS.fetch_add(1, Ordering::Relaxed);
+    crate::proc::account_tick(&context);
+    // before the switch, so a process woken now may be picked
+    crate::proc::wake_sleepers();
+    // a process killed for its cpu limit is already switched away from
+    let killed = crate::proc::enforce_cpu_limit(&mut context);
+    if !killed && crate::proc::deterministic::on_tick() {
+        crate::proc::switch(&mut context);
+    }
+    crate::proc::handle_signals(&mut context);
+    crate::drivers::serial::try_flush_staging();
+    if let Some(timer) = TIMER.get().filter(|timer| timer.dyntick) {
+        rearm(timer);
+    }
     super::ack(consts::Interrupts::IrqBase as u8);
 }
 
@@ -1,6 +1,9 @@
 use crate::memory::*;
+use crate::proc::{PageFaultOutcome, ProcessContext};
+use syscall_def::SIGSEGV;
 use x86_64::registers::control::Cr2;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame, PageFaultErrorCode};
+use x86_64::VirtAddr;
 
 pub unsafe fn reg_idt(idt: &mut InterruptDescriptorTable) {
     idt.divide_error.set_handler_fn(divide_error_handler);
@@ -135,18 +138,39 @@ pub extern "x86-interrupt" fn simd_floating_point_handler(stack_frame: Interrupt
     panic!("EXCEPTION: SIMD FLOATING POINT\n\n{:#?}", stack_frame);
 }
 
-pub extern "x86-interrupt" fn page_fault_handler(
-    stack_frame: InterruptStackFrame,
-    err_code: PageFaultErrorCode,
-) {
+/// A user fault nothing maps is a `SIGSEGV` of the process, one of the
+/// kernel panics unless it is in a copy routine, see `uaccess`
+pub extern "C" fn page_fault(err_code: u64, mut context: ProcessContext) {
     let addr = Cr2::read().unwrap();
+    let err_code = PageFaultErrorCode::from_bits_truncate(err_code);
+
+    if let PageFaultOutcome::Fatal { reason } = crate::proc::handle_page_fault(addr, err_code) {
+        let user = err_code.contains(PageFaultErrorCode::USER_MODE);
+
+        // the kernel touched a bad user address inside a copy routine
+        if !user {
+            let ip = context.stack_frame.instruction_pointer.as_u64();
+            if let Some(fixup) = uaccess::search_exception_table(ip) {
+                debug!("Fix up page fault at {:#x}, accessing {:#x}", ip, addr);
+                context.set_instruction_pointer(VirtAddr::new(fixup));
+                return;
+            }
+        }
 
-    if !crate::proc::handle_page_fault(addr, err_code) {
         warn!(
-            "EXCEPTION: PAGE FAULT, ERROR_CODE: {:?}\n\nTrying to access: {:#x}\n{:#?}",
-            err_code, addr, stack_frame
+            "EXCEPTION: PAGE FAULT, ERROR_CODE: {:?}\n\nTrying to access: {:#x}, {}\n{:#?}",
+            err_code, addr, reason, context
         );
+        if let Some(region) = guard::lookup(addr) {
+            warn!("Hit the guard page of the {} at {:#x}", region, addr);
+        }
         crate::proc::current_proc_info();
-        panic!("Failed to handle page fault.");
+
+        if !user {
+            panic!("Failed to handle page fault.");
+        }
+        crate::proc::fault_signal(SIGSEGV, &mut context);
     }
 }
+
+as_handler!(page_fault, PageFaultErrorCode);
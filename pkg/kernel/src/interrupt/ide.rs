@@ -0,0 +1,36 @@
+use super::consts;
+use crate::drivers::ata;
+use crate::proc::ProcessContext;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+pub unsafe fn reg_idt(idt: &mut InterruptDescriptorTable) {
+    idt[consts::Interrupts::IrqBase as u8 + consts::Irq::Ide0 as u8].set_handler_fn(ide0_handler);
+    idt[consts::Interrupts::IrqBase as u8 + consts::Irq::Ide1 as u8].set_handler_fn(ide1_handler);
+}
+
+pub fn init() {
+    for (channel, irq) in [consts::Irq::Ide0, consts::Irq::Ide1]
+        .into_iter()
+        .enumerate()
+    {
+        if ata::has_drives(channel) {
+            let irq = irq as u8;
+            super::enable_irq(irq, 0);
+            debug!("IDE{} IRQ enabled.", channel);
+        }
+    }
+    ata::enable_irq();
+}
+
+pub extern "C" fn ide0(_context: ProcessContext) {
+    super::ack(consts::Irq::Ide0 as u8);
+    ata::interrupt(0);
+}
+
+pub extern "C" fn ide1(_context: ProcessContext) {
+    super::ack(consts::Irq::Ide1 as u8);
+    ata::interrupt(1);
+}
+
+as_handler!(ide0);
+as_handler!(ide1);
@@ -0,0 +1,22 @@
+use super::consts;
+use crate::drivers::keyboard;
+use crate::proc::ProcessContext;
+use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
+
+pub unsafe fn reg_idt(idt: &mut InterruptDescriptorTable) {
+    idt[consts::Interrupts::IrqBase as u8 + consts::Irq::Keyboard as u8]
+        .set_handler_fn(keyboard_handler);
+}
+
+pub fn init() {
+    keyboard::init();
+    super::enable_irq(consts::Irq::Keyboard as u8, 0);
+    debug!("Keyboard IRQ enabled.");
+}
+
+pub extern "C" fn keyboard(_context: ProcessContext) {
+    super::ack(consts::Irq::Keyboard as u8);
+    keyboard::receive();
+}
+
+as_handler!(keyboard);
@@ -2,35 +2,39 @@ mod apic;
 mod clock;
 mod consts;
 mod exception;
+mod ide;
+mod keyboard;
 mod serial;
 mod syscall;
 
+pub use clock::{kick as kick_timer, ticks};
 pub use syscall::SyscallArgs;
 
 use crate::memory::physical_to_virtual;
 use apic::*;
 use x86_64::structures::idt::InterruptDescriptorTable;
 
-lazy_static! {
-    static ref IDT: InterruptDescriptorTable = {
-        let mut idt = InterruptDescriptorTable::new();
-        unsafe {
-            exception::reg_idt(&mut idt);
-            serial::reg_idt(&mut idt);
-            clock::reg_idt(&mut idt);
-            syscall::reg_idt(&mut idt);
-        }
-        idt
-    };
+fn reg_idt(idt: &mut InterruptDescriptorTable) {
+    unsafe {
+        exception::reg_idt(idt);
+        serial::reg_idt(idt);
+        keyboard::reg_idt(idt);
+        ide::reg_idt(idt);
+        clock::reg_idt(idt);
+        syscall::reg_idt(idt);
+    }
 }
 
 /// init interrupts system
 pub fn init() {
-    IDT.load();
+    crate::percpu::current().init_idt(reg_idt);
     debug!("XApic support = {}.", apic::XApic::support());
     let mut lapic = unsafe { XApic::new(physical_to_virtual(LAPIC_ADDR)) };
     lapic.cpu_init();
+    clock::init();
     serial::init();
+    keyboard::init();
+    ide::init();
 
     info!("Interrupts Initialized.");
 }
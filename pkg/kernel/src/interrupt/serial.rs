@@ -1,11 +1,14 @@
 use super::consts;
-use crate::drivers::input::push_key;
-use crate::drivers::serial::get_serial_for_sure;
+use crate::drivers::input::enqueue;
+use crate::drivers::serial::{get_serial_for_sure, is_raw};
+use crate::proc::ProcessContext;
+#[cfg(feature = "debug-tools")]
+use core::sync::atomic::{AtomicBool, Ordering};
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 
 pub unsafe fn reg_idt(idt: &mut InterruptDescriptorTable) {
     idt[consts::Interrupts::IrqBase as u8 + consts::Irq::Serial0 as u8]
-        .set_handler_fn(interrupt_handler);
+        .set_handler_fn(serial_handler);
 }
 
 pub fn init() {
@@ -13,17 +16,76 @@ pub fn init() {
     debug!("Serial0(COM1) IRQ enabled.");
 }
 
-/// Receive character from uart 16550
+/// `BREAK_PREFIX` was received, waiting for the next key
+#[cfg(feature = "debug-tools")]
+static BREAK_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Receive characters from uart 16550
 /// Should be called on every interrupt
-pub fn receive() {
-    let data = get_serial_for_sure().receive();
+///
+/// the FIFO raises the interrupt with several bytes in it, so all of them
+/// are taken. Return true if the monitor is asked for
+pub fn receive() -> bool {
+    let mut monitor = false;
+
+    while let Some(data) = get_serial_for_sure().receive() {
+        // binary data may hold anything, the break key included
+        if is_raw() {
+            enqueue(data);
+            continue;
+        }
+
+        monitor |= receive_key(data);
+    }
+
+    monitor
+}
+
+/// Pass a key on unless it is part of the break sequence,
+/// return true once the whole sequence is received
+#[cfg(feature = "debug-tools")]
+fn receive_key(data: u8) -> bool {
+    use crate::monitor::{BREAK_KEY, BREAK_PREFIX};
+
+    // any other key after the prefix is passed on with it
+    if BREAK_PENDING.swap(false, Ordering::Relaxed) {
+        if data == BREAK_KEY {
+            return true;
+        }
+        enqueue(BREAK_PREFIX);
+    }
 
-    if let Some(data) = data {
-        push_key(data);
+    if data == BREAK_PREFIX {
+        BREAK_PENDING.store(true, Ordering::Relaxed);
+    } else {
+        enqueue(data);
     }
+    false
 }
 
-pub extern "x86-interrupt" fn interrupt_handler(_st: InterruptStackFrame) {
+/// Without the monitor there is no break sequence
+#[cfg(not(feature = "debug-tools"))]
+fn receive_key(data: u8) -> bool {
+    enqueue(data);
+    false
+}
+
+pub extern "C" fn serial(mut context: ProcessContext) {
     super::ack(super::consts::Irq::Serial0 as u8);
-    receive();
+    if receive() && enter_monitor() {
+        crate::proc::switch(&mut context);
+    }
+}
+
+/// Return true if the caller should switch to the next process
+#[cfg(feature = "debug-tools")]
+fn enter_monitor() -> bool {
+    crate::monitor::enter()
+}
+
+#[cfg(not(feature = "debug-tools"))]
+fn enter_monitor() -> bool {
+    false
 }
+
+as_handler!(serial);
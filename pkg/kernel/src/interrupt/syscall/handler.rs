@@ -0,0 +1,445 @@
+//! A handler for each syscall, and the dispatch table made of them
+//!
+//! `dispatch` is generated from `syscall_def::for_each_syscall`, so a
+//! syscall added there does not build until it has a `do_*` handler here.
+//! Numbers not given out decode to `Syscall::None` and get ENOSYS.
+
+use syscall_def::{errno_ret, Syscall, ENOSYS};
+
+use super::service::*;
+use super::SyscallArgs;
+use crate::proc::*;
+
+macro_rules! dispatch {
+    ($($name:ident($arity:literal) = $num:literal,)*) => {
+        paste::paste! {
+            /// Run the handler of `args.syscall`
+            pub fn dispatch(args: &SyscallArgs, context: &mut ProcessContext) {
+                match args.syscall {
+                    $(Syscall::$name => [<do_ $name:snake>](args, context),)*
+                    Syscall::None => context.set_rax(errno_ret(ENOSYS)),
+                }
+            }
+        }
+    };
+}
+
+syscall_def::for_each_syscall!(dispatch);
+
+/// Handlers of the syscalls of subsystems left out of the build, see the
+/// features of the kernel, each returns ENOSYS
+#[allow(unused_macros)]
+macro_rules! unimplemented_syscalls {
+    ($($handler:ident),*) => {
+        $(
+            pub fn $handler(_args: &SyscallArgs, context: &mut ProcessContext) {
+                context.set_rax(errno_ret(ENOSYS));
+            }
+        )*
+    };
+}
+
+#[cfg(not(feature = "debug-tools"))]
+unimplemented_syscalls!(do_spawn_traced, do_sched_stat, do_maps);
+
+#[cfg(not(feature = "graphics"))]
+unimplemented_syscalls!(do_map_frame_buffer);
+
+/// heap end: arg0 or 0 to only read -> heap end: usize
+pub fn do_brk(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_brk(args));
+}
+
+/// addr: arg0, len: arg1, prot: arg2, flags: arg3, fd: arg4 as u8, offset: arg5
+///   -> addr: usize or -errno
+///   (prot | flags << 8: arg2 and 0: arg3 from before six arguments)
+pub fn do_mmap(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_mmap(args));
+}
+
+/// addr: arg0, len: arg1, prot: arg2 -> ret: 0 or -1
+pub fn do_m_protect(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_mprotect(args));
+}
+
+/// addr: arg0, len: arg1 -> ret: 0 or -errno
+pub fn do_munmap(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_munmap(args));
+}
+
+/// addr: arg0, len: arg1, flags: arg2 -> ret: 0 or -errno
+pub fn do_msync(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_msync(args));
+}
+
+/// addr: arg0, len: arg1, advice: arg2 -> ret: 0 or -errno
+pub fn do_madvise(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_madvise(args));
+}
+
+/// key: arg0 as u32, size: arg1 -> size: usize or -errno
+pub fn do_shm_open(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_shm_open(args));
+}
+
+/// key: arg0 as u32, addr: arg1 or 0 for any -> addr: usize or -errno
+pub fn do_shm_map(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_shm_map(args));
+}
+
+/// addr: arg0 -> ret: 0 or -errno
+pub fn do_shm_unmap(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_shm_unmap(args));
+}
+
+/// op: u8, key: u32, val: usize (nanoseconds for SEM_WAIT_TIMEOUT) -> ret: any
+pub fn do_sem(args: &SyscallArgs, context: &mut ProcessContext) {
+    sys_sem(args, context);
+}
+
+/// addr: arg0 as *const u32, op: arg1 (FUTEX_*), val: arg2 -> ret: 0, woken or -errno
+pub fn do_futex(args: &SyscallArgs, context: &mut ProcessContext) {
+    sys_futex(args, context);
+}
+
+/// key: arg0 as u32, capacity: arg1, msg_size: arg2 -> msg_size: usize or -errno
+pub fn do_mq_open(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_mq_open(args));
+}
+
+/// key: arg0 as u32 -> ret: 0 or -errno
+pub fn do_mq_unlink(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_mq_unlink(args));
+}
+
+/// key: arg0 as u32, msg: &[u8] (arg1 as *const u8, arg2 as len) -> ret: 0 or -errno
+pub fn do_mq_send(args: &SyscallArgs, context: &mut ProcessContext) {
+    sys_mq_send(args, context);
+}
+
+/// key: arg0 as u32, buf: &mut [u8] (arg1 as *mut u8, arg2 as len) -> len: usize or -errno
+pub fn do_mq_recv(args: &SyscallArgs, context: &mut ProcessContext) {
+    sys_mq_recv(args, context);
+}
+
+/// None -> pid: u16 or 0 or -1
+pub fn do_fork(_args: &SyscallArgs, context: &mut ProcessContext) {
+    sys_fork(context);
+}
+
+/// entry: arg0, stack_top: arg1, arg: arg2 -> tid: u16 or -errno
+pub fn do_clone(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_clone(args));
+}
+
+/// fd: arg0 as u8, buf: &[u8] (arg1 as *const u8, arg2 as len)
+pub fn do_read(args: &SyscallArgs, context: &mut ProcessContext) {
+    // None if the process blocked on a pipe, the syscall runs again when woken
+    if let Some(ret) = sys_read(args, context) {
+        context.set_rax(ret);
+    }
+}
+
+/// fd: arg0 as u8, buf: &[u8] (arg1 as *const u8, arg2 as len)
+pub fn do_write(args: &SyscallArgs, context: &mut ProcessContext) {
+    if let Some(ret) = sys_write(args, context) {
+        context.set_rax(ret);
+    }
+}
+
+/// fds: &mut [PollFd] (arg0 as *mut PollFd, arg1 as count), deadline: arg2
+/// -> ready: usize or -errno
+pub fn do_poll(args: &SyscallArgs, context: &mut ProcessContext) {
+    // None if the process blocked, the syscall runs again when woken
+    if let Some(ret) = sys_poll(args, context) {
+        context.set_rax(ret);
+    }
+}
+
+/// None -> read fd | write fd << 8 or -errno
+pub fn do_pipe(_args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_pipe());
+}
+
+/// None -> master fd | slave fd << 8 or -errno
+pub fn do_open_pty(_args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_open_pty());
+}
+
+/// None -> fd: u8 or -errno
+pub fn do_mem_fd(_args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_memfd());
+}
+
+/// initial: arg0 as u64, interval: arg1 as u64 (nanoseconds) -> fd: u8 or -errno
+pub fn do_timer_fd(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_timerfd(args));
+}
+
+/// value: arg0 as u64 -> fd: u8 or -errno
+pub fn do_event_fd(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_eventfd(args));
+}
+
+/// name: &str (arg0 as *const u8, arg1 as len) -> fd: u8 or -errno
+pub fn do_host_open(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_host_open(args));
+}
+
+/// mask: arg0 as u16 (UMASK_KEEP to only read) -> old: u16
+pub fn do_umask(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_umask(args));
+}
+
+/// pid: arg0 as u16 (0 for self), usage: arg1 as *mut Rusage -> 0 or -errno
+pub fn do_get_rusage(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_get_rusage(args));
+}
+
+/// info: arg0 as *mut Sysinfo -> 0 or -errno
+pub fn do_sysinfo(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_sysinfo(args));
+}
+
+/// pid: arg0 as u16 (0 for self), info: arg1 as *mut ProcInfo -> 0 or -errno
+pub fn do_proc_info(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_proc_info(args));
+}
+
+/// info: arg0 as *mut FbInfo -> 0 or -errno
+#[cfg(feature = "graphics")]
+pub fn do_map_frame_buffer(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_map_frame_buffer(args));
+}
+
+/// pid: arg0 as u16 (0 for self), resource: arg1, new: arg2 -> old: usize or -errno
+pub fn do_prlimit(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_prlimit(args));
+}
+
+/// delta: arg0 as isize -> NICE_BIAS - new nice: usize
+pub fn do_nice(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_nice(args));
+}
+
+/// pid: arg0 as u16 (0 for self), nice: arg1 as isize -> NICE_BIAS - old nice: usize or -errno
+pub fn do_renice(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_renice(args));
+}
+
+/// pid: arg0 as u16 (0 for self), priority: arg1 as usize or PRIO_KEEP -> old priority or -errno
+pub fn do_set_priority(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_set_priority(args));
+}
+
+/// pid: arg0 as u16 (0 for self), period: arg1 as ns, budget: arg2 as ns (0 to leave)
+/// -> 0 or -errno
+pub fn do_sched_rt(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_sched_rt(args));
+}
+
+/// path: &str (arg0 as *const u8, arg1 as len), flags: arg2 -> fd: u8 or -errno
+pub fn do_open(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_open(args));
+}
+
+/// fd: arg0 as u8 -> ret: 0 or -errno
+pub fn do_close(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_close(args));
+}
+
+/// old: arg0 as u8, new: arg1 as u8 -> new: u8 or -errno
+pub fn do_dup2(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_dup2(args));
+}
+
+/// fd: arg0 as u8, stat: arg1 as *mut FdStat -> ret: 0 or -errno
+pub fn do_fstat(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_fstat(args));
+}
+
+/// fd: arg0 as u8, offset: arg1 as isize, whence: arg2 -> pos: usize or -errno
+pub fn do_seek(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_seek(args));
+}
+
+/// fd: arg0 as u8, cmd: arg1, arg: arg2 -> ret: usize or -errno
+pub fn do_fcntl(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_fcntl(args));
+}
+
+/// fd: arg0 as u8, op: arg1 (LOCK_*) -> ret: 0 or -errno
+pub fn do_flock(args: &SyscallArgs, context: &mut ProcessContext) {
+    flock(args.arg0 as u8, args.arg1, context);
+}
+
+/// fd: arg0 as u8, iov: &[IoVec] (arg1 as *const IoVec, arg2 as count)
+pub fn do_read_v(args: &SyscallArgs, context: &mut ProcessContext) {
+    if let Some(ret) = sys_readv(args, context) {
+        context.set_rax(ret);
+    }
+}
+
+/// fd: arg0 as u8, iov: &[IoVec] (arg1 as *const IoVec, arg2 as count)
+pub fn do_write_v(args: &SyscallArgs, context: &mut ProcessContext) {
+    if let Some(ret) = sys_writev(args, context) {
+        context.set_rax(ret);
+    }
+}
+
+/// out_fd: arg0 as u8, in_fd: arg1 as u8, offset: arg2 as *mut usize, len: arg3
+pub fn do_send_file(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_send_file(args));
+}
+
+/// None
+pub fn do_yield(_args: &SyscallArgs, context: &mut ProcessContext) {
+    sys_yield(context);
+}
+
+/// nanoseconds: arg0 -> 0
+pub fn do_sleep(args: &SyscallArgs, context: &mut ProcessContext) {
+    sys_sleep(args, context);
+}
+
+/// pid: arg0 as u16, buf: &mut [SchedEvent] (arg1 as *mut SchedEvent, arg2 as count)
+///   -> count: usize
+#[cfg(feature = "debug-tools")]
+pub fn do_sched_stat(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_sched_stat(args));
+}
+
+/// pid: arg0 as u16, buf: &mut [MapEntry] (arg1 as *mut MapEntry, arg2 as count)
+///   -> total: usize
+#[cfg(feature = "debug-tools")]
+pub fn do_maps(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_maps(args));
+}
+
+/// None -> pid: u16
+pub fn do_get_pid(_args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_get_pid() as usize);
+}
+
+/// path: &str (arg0 as *const u8, arg1 as len), args: arg2 as *const ProgramArgs or 0
+///   -> pid: u16
+pub fn do_spawn(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(spawn_process(args));
+}
+
+/// path: &str (arg0 as *const u8, arg1 as len), mode: arg2 -> pid: u16
+#[cfg(feature = "debug-tools")]
+pub fn do_spawn_traced(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(spawn_traced_process(args));
+}
+
+/// path: &str (arg0 as *const u8, arg1 as len), args: arg2 as *const ProgramArgs or 0
+///   -> only -errno, the new program starts as `_start(argc, argv, envc, envp)`
+pub fn do_exec(args: &SyscallArgs, context: &mut ProcessContext) {
+    sys_exec(args, context);
+}
+
+/// pid: arg0 as u16
+pub fn do_exit(args: &SyscallArgs, context: &mut ProcessContext) {
+    exit_process(args, context);
+}
+
+/// code: arg0 as isize -> !
+pub fn do_shutdown(args: &SyscallArgs, _context: &mut ProcessContext) {
+    sys_shutdown(args);
+}
+
+/// pid: arg0 as u16 or WAIT_ANY, flags: arg1 (WAIT_UNTRACED | WAIT_NOHANG),
+/// pid out: arg2 as *mut u16 (0 to skip) -> status: isize or WAIT_STOPPED or WAIT_RUNNING or -errno
+pub fn do_wait_pid(args: &SyscallArgs, context: &mut ProcessContext) {
+    sys_wait_pid(args, context);
+}
+
+/// pid: arg0 as u16, sig: arg1 or 0 to check the process exists -> 0 or -errno
+pub fn do_kill(args: &SyscallArgs, context: &mut ProcessContext) {
+    sys_kill(args, context);
+}
+
+/// sig: arg0, handler: arg1 as address, SIG_DFL, SIG_IGN or SIG_KEEP,
+///   restorer: arg2 as address -> old handler or -errno
+pub fn do_sigaction(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_sigaction(args));
+}
+
+/// None -> the registers from before the handler, see `proc::signal`
+pub fn do_sig_return(_args: &SyscallArgs, context: &mut ProcessContext) {
+    sys_sigreturn(context);
+}
+
+/// pid: arg0 as u16 -> ret: 0 or -errno
+pub fn do_suspend(args: &SyscallArgs, context: &mut ProcessContext) {
+    sys_suspend(args, context);
+}
+
+/// pid: arg0 as u16 -> ret: 0 or -errno
+pub fn do_resume(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_resume(args));
+}
+
+/// name: &str (arg0 as *const u8, arg1 as len), value: arg2 or !0
+///   -> old value: usize or -errno
+///   (buf: arg0 as *mut u8, 0: arg1, len: arg2 -> bytes of the list to list them)
+pub fn do_sysctl(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_sysctl(args));
+}
+
+/// paths: arg0 as *const [IoVec; 2], the image file and the mount point
+///   -> loop device number or -errno
+pub fn do_mount(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_mount(args));
+}
+
+/// path: arg0 as *const u8, len: arg1, a device or a swap file -> 0 or -errno
+pub fn do_swap_on(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_swap_on(args));
+}
+
+/// None -> time: usize
+pub fn do_time(_args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_clock() as usize);
+}
+
+/// None -> nanoseconds: usize
+pub fn do_clock_monotonic(_args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_clock_monotonic() as usize);
+}
+
+/// nanoseconds: arg0 as i64 since the epoch -> 0 or -errno
+pub fn do_set_time(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_set_time(args));
+}
+
+/// offset: arg0 as isize seconds or UTC_OFFSET_KEEP -> UTC_OFFSET_BIAS + old offset or -errno
+pub fn do_utc_offset(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_utc_offset(args));
+}
+
+/// buf: &mut [u8] (arg0 as *mut u8, arg1 as len) -> len: usize or -errno
+pub fn do_get_random(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_getrandom(args));
+}
+
+/// None
+pub fn do_stat(_args: &SyscallArgs, _context: &mut ProcessContext) {
+    list_process();
+}
+
+/// None
+pub fn do_list_app(_args: &SyscallArgs, _context: &mut ProcessContext) {
+    list_app();
+}
+
+/// layout: arg0 as *const Layout -> ptr: *mut u8
+pub fn do_allocate(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(sys_allocate(args));
+}
+
+/// ptr: arg0 as *mut u8
+pub fn do_deallocate(args: &SyscallArgs, _context: &mut ProcessContext) {
+    sys_deallocate(args);
+}
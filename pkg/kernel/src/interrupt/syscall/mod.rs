@@ -1,23 +1,20 @@
-use crate::{memory::gdt, proc::*};
-use alloc::format;
+use crate::proc::*;
+use crate::utils::fmt::format_stack;
 use syscall_def::Syscall;
 use x86_64::structures::idt::{InterruptDescriptorTable, InterruptStackFrame};
 
+mod handler;
 mod service;
 use super::consts;
-use service::*;
 
 pub unsafe fn reg_idt(idt: &mut InterruptDescriptorTable) {
     idt[consts::Interrupts::Syscall as u8]
         .set_handler_fn(syscall_handler)
-        .set_stack_index(gdt::SYSCALL_IST_INDEX)
         .set_privilege_level(x86_64::PrivilegeLevel::Ring3);
 }
 
 pub extern "C" fn syscall(mut context: ProcessContext) {
-    x86_64::instructions::interrupts::without_interrupts(|| {
-        super::syscall::dispatcher(&mut context);
-    });
+    super::syscall::dispatcher(&mut context);
 }
 
 as_handler!(syscall);
@@ -28,72 +25,116 @@ pub struct SyscallArgs {
     pub arg0: usize,
     pub arg1: usize,
     pub arg2: usize,
+    pub arg3: usize,
+    pub arg4: usize,
+    pub arg5: usize,
 }
 
 pub fn dispatcher(context: &mut ProcessContext) {
-    let args = super::syscall::SyscallArgs::new(
-        Syscall::from(context.regs.rax),
-        context.regs.rdi,
-        context.regs.rsi,
-        context.regs.rdx,
-    );
-
-    match args.syscall {
-        Syscall::Brk => context.set_rax(sys_brk(&args)),
-        // op: u8, key: u32, val: usize -> ret: any
-        Syscall::Sem => sys_sem(&args, context),
-        // None -> pid: u16 or 0 or -1
-        Syscall::Fork => {sys_fork(context);},
-        // fd: arg0 as u8, buf: &[u8] (arg1 as *const u8, arg2 as len)
-        Syscall::Read => context.set_rax(sys_read(&args)),
-        // fd: arg0 as u8, buf: &[u8] (arg1 as *const u8, arg2 as len)
-        Syscall::Write => context.set_rax(sys_write(&args)),
-        // None -> pid: u16
-        Syscall::GetPid => context.set_rax(sys_get_pid() as usize),
-        // path: &str (arg0 as *const u8, arg1 as len) -> pid: u16
-        Syscall::Spawn => context.set_rax(spawn_process(&args)),
-        // pid: arg0 as u16
-        Syscall::Exit => exit_process(&args, context),
-        // pid: arg0 as u16 -> status: isize
-        Syscall::WaitPid => sys_wait_pid(&args, context),
-        // pid: arg0 as u16
-        Syscall::Kill => sys_kill(&args, context),
-        // None -> time: usize
-        Syscall::Time => context.set_rax(sys_clock() as usize),
-        // None
-        Syscall::Stat => list_process(),
-        // None
-        Syscall::ListApp => list_app(),
-
-        // layout: arg0 as *const Layout -> ptr: *mut u8
-        Syscall::Allocate => context.set_rax(sys_allocate(&args)),
-        // ptr: arg0 as *mut u8
-        Syscall::Deallocate => sys_deallocate(&args),
-        // None
-        Syscall::None => {}
+    let args = SyscallArgs::new(Syscall::from(context.regs.rax), context.syscall_args());
+
+    // an app may only make the syscalls it declared
+    if filter_syscall(context.regs.rax, context) {
+        return;
+    }
+
+    // a replayed process gets the recorded result instead
+    if replay_syscall(&args.syscall, args.arg1, args.arg2, context) {
+        crate::proc::deterministic::on_syscall(context);
+        return;
+    }
+
+    // a read of the disk may sleep until the syscall returns, see `drivers::block`
+    let pid = current_pid();
+    let nr = context.regs.rax;
+    crate::drivers::block::begin_syscall(pid);
+
+    // the gate enters with interrupts disabled,
+    // only syscalls that never switch process can be preempted
+    if args.is_preemptible() {
+        x86_64::instructions::interrupts::enable();
+    }
+
+    handler::dispatch(&args, context);
+
+    x86_64::instructions::interrupts::disable();
+
+    // its result is dropped, it runs again once the disk has read the block
+    if restart_on_disk(pid, nr, context) {
+        return;
     }
+
+    record_syscall(&args.syscall, args.arg1, context);
+    crate::proc::deterministic::on_syscall(context);
+    handle_signals(context);
 }
 
 impl SyscallArgs {
-    pub fn new(syscall: Syscall, arg0: usize, arg1: usize, arg2: usize) -> Self {
+    /// The registers past the arity of `syscall` are read as 0,
+    /// whatever the caller left in them
+    pub fn new(syscall: Syscall, mut args: [usize; 6]) -> Self {
+        args[syscall.arity()..].fill(0);
+        let [arg0, arg1, arg2, arg3, arg4, arg5] = args;
         Self {
             syscall,
             arg0,
             arg1,
             arg2,
+            arg3,
+            arg4,
+            arg5,
         }
     }
+
+    /// The arguments as passed, `arg0` to `arg5`
+    pub fn as_array(&self) -> [usize; 6] {
+        [
+            self.arg0, self.arg1, self.arg2, self.arg3, self.arg4, self.arg5,
+        ]
+    }
+
+    /// Check if the syscall can run with interrupts enabled
+    ///
+    /// the ones below may rewrite the context to switch process,
+    /// a tick between the switch and `iretq` would save the wrong context.
+    /// others run on the caller's own syscall stack and can be preempted,
+    /// as `proc` keeps scheduler and resource operations in `without_interrupts`.
+    /// I/O blocking on a pipe disables interrupts itself, see `block_on_fd`.
+    pub fn is_preemptible(&self) -> bool {
+        !matches!(
+            self.syscall,
+            Syscall::Fork
+                | Syscall::Exit
+                | Syscall::WaitPid
+                | Syscall::Kill
+                | Syscall::SigReturn
+                | Syscall::Suspend
+                | Syscall::Sem
+                | Syscall::Futex
+                | Syscall::MqSend
+                | Syscall::MqRecv
+                | Syscall::Flock
+                | Syscall::Yield
+                | Syscall::Sleep
+        )
+    }
 }
 
 impl core::fmt::Display for SyscallArgs {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         write!(
             f,
-            "SYSCALL: {:<10} (0x{:016x}, 0x{:016x}, 0x{:016x})",
-            format!("{:?}", self.syscall),
-            self.arg0,
-            self.arg1,
-            self.arg2
-        )
+            "SYSCALL: {:<10} (",
+            format_stack::<16>(format_args!("{:?}", self.syscall)).as_str()
+        )?;
+        // at least the three arguments of the old ABI, so the columns line up
+        let count = self.syscall.arity().max(3);
+        for (index, arg) in self.as_array()[..count].iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "0x{:016x}", arg)?;
+        }
+        write!(f, ")")
     }
 }
@@ -1,17 +1,79 @@
 use core::alloc::Layout;
+use core::fmt::Write as _;
+use core::mem::size_of;
 
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use syscall_def::*;
+use x86_64::structures::paging::PageTableFlags;
 use x86_64::VirtAddr;
 
-use crate::proc::*;
+use crate::fw_cfg;
+use crate::memory::uaccess::*;
+use crate::proc::{mmap::Advice, *};
+use crate::fs::vfs;
+use crate::resource::{Buffer, EventFd, TimerFd};
 use crate::utils::*;
 
 use super::SyscallArgs;
 
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+/// Longest path `sys_open` takes
+const OPEN_PATH_MAX: usize = 256;
+
 pub fn sys_clock() -> i64 {
-    clock::now()
-        .and_utc()
-        .timestamp_nanos_opt()
-        .unwrap_or_default()
+    clock::now_nanos()
+}
+
+pub fn sys_clock_monotonic() -> i64 {
+    clock::monotonic_nanos()
+}
+
+pub fn sys_set_time(args: &SyscallArgs) -> usize {
+    if !is_root(current_pid()) {
+        return errno_ret(EPERM);
+    }
+
+    // the time of day is virtual in deterministic mode
+    match clock::set_nanos(args.arg0 as i64) {
+        true => 0,
+        false => errno_ret(EPERM),
+    }
+}
+
+pub fn sys_utc_offset(args: &SyscallArgs) -> usize {
+    if args.arg0 == UTC_OFFSET_KEEP {
+        return (UTC_OFFSET_BIAS + clock::utc_offset() as isize) as usize;
+    }
+
+    if !is_root(current_pid()) {
+        return errno_ret(EPERM);
+    }
+
+    let offset = args.arg0 as isize;
+    if offset.unsigned_abs() > UTC_OFFSET_MAX as usize {
+        return errno_ret(EINVAL);
+    }
+
+    (UTC_OFFSET_BIAS + clock::set_utc_offset(offset as i32) as isize) as usize
+}
+
+/// Fill a user buffer with random bytes, see `utils::random`
+pub fn sys_getrandom(args: &SyscallArgs) -> usize {
+    let mut chunk = [0u8; 256];
+    let mut done = 0;
+
+    while done < args.arg1 {
+        let len = (args.arg1 - done).min(chunk.len());
+        random::fill(&mut chunk[..len]);
+        if let Err(errno) = copy_to_user(args.arg0 + done, &chunk[..len]) {
+            return errno_ret(errno);
+        }
+        done += len;
+    }
+
+    done
 }
 
 pub fn sys_allocate(args: &SyscallArgs) -> usize {
@@ -48,33 +110,472 @@ pub fn sys_deallocate(args: &SyscallArgs) {
 }
 
 pub fn spawn_process(args: &SyscallArgs) -> usize {
-    let name = unsafe {
-        core::str::from_utf8_unchecked(core::slice::from_raw_parts(
-            args.arg0 as *const u8,
-            args.arg1,
-        ))
+    let (name, program, stdout) = match program_args(args) {
+        Ok(program) => program,
+        Err(errno) => return errno_ret(errno),
     };
 
-    let pid = crate::proc::spawn(name);
+    // stdin is never a sensible stdout, so fd 0 keeps the console
+    let stdout = match stdout {
+        0 => None,
+        fd => Some(fd),
+    };
 
-    if pid.is_err() {
-        warn!("spawn_process: failed to spawn process: {}", name);
-        return 0;
+    match spawn_args(&name, &program, stdout) {
+        Ok(pid) => pid.0 as usize,
+        Err(err) => {
+            if err != SpawnError::Pending {
+                warn!("spawn_process: failed to spawn {}: {}", name, err);
+            }
+            errno_ret(err.errno())
+        }
+    }
+}
+
+/// Longest app name `spawn` and `exec` take
+const APP_PATH_MAX: usize = 64;
+
+pub fn sys_exec(args: &SyscallArgs, context: &mut ProcessContext) {
+    let (path, program, _) = match program_args(args) {
+        Ok(program) => program,
+        Err(errno) => return context.set_rax(errno_ret(errno)),
+    };
+
+    if let Err(err) = exec(&path, &program, context) {
+        if err != SpawnError::Pending {
+            warn!("sys_exec: failed to exec {}: {}", path, err);
+        }
+        context.set_rax(errno_ret(err.errno()));
+    }
+}
+
+/// Copy the path and `ProgramArgs` of `spawn` or `exec` from user space
+///
+/// without `ProgramArgs` the program gets its path as the only argument.
+/// Return them with the fd to use as stdout.
+fn program_args(args: &SyscallArgs) -> Result<(String, AppArgs, u8), usize> {
+    if args.arg1 > APP_PATH_MAX {
+        return Err(ENOENT);
+    }
+    let path = user_string(IoVec {
+        base: args.arg0 as *const u8,
+        len: args.arg1,
+    })?;
+
+    if args.arg2 == 0 {
+        let program = AppArgs::new(&path);
+        return Ok((path, program, 0));
+    }
+
+    let mut program = [ProgramArgs::new(&[], &[])];
+    unsafe { copy_slice_from_user(&mut program, args.arg2)? };
+    let [program] = program;
+
+    let stdout = u8::try_from(program.stdout).map_err(|_| EBADF)?;
+
+    let mut total = 0;
+    let args = AppArgs {
+        args: user_strings(program.argv, program.argc, &mut total)?,
+        env: user_strings(program.envp, program.envc, &mut total)?,
+    };
+
+    Ok((path, args, stdout))
+}
+
+/// Copy `count` strings described by `IoVec`s at `vecs` from user space
+///
+/// `total` counts the bytes taken with their `IoVec`s, up to `ARG_MAX`.
+fn user_strings(
+    vecs: *const IoVec,
+    count: usize,
+    total: &mut usize,
+) -> Result<Vec<String>, usize> {
+    *total = count
+        .checked_mul(size_of::<IoVec>())
+        .and_then(|size| total.checked_add(size))
+        .filter(|&total| total <= ARG_MAX)
+        .ok_or(E2BIG)?;
+
+    let mut iov = vec![IoVec::new(&[]); count];
+    unsafe { copy_slice_from_user(&mut iov, vecs as usize)? };
+
+    let mut strings = Vec::with_capacity(count);
+    for v in iov {
+        *total += v.len;
+        if *total > ARG_MAX {
+            return Err(E2BIG);
+        }
+        strings.push(user_string(v)?);
+    }
+
+    Ok(strings)
+}
+
+fn user_string(iov: IoVec) -> Result<String, usize> {
+    let mut buf = vec![0u8; iov.len];
+    copy_from_user(&mut buf, iov.base as usize)?;
+    String::from_utf8(buf).map_err(|_| EINVAL)
+}
+
+#[cfg(feature = "debug-tools")]
+pub fn spawn_traced_process(args: &SyscallArgs) -> usize {
+    use crate::proc::trace::TraceMode;
+
+    if args.arg1 > APP_PATH_MAX {
+        return errno_ret(ENOENT);
     }
+    let name = match user_string(IoVec {
+        base: args.arg0 as *const u8,
+        len: args.arg1,
+    }) {
+        Ok(name) => name,
+        Err(errno) => return errno_ret(errno),
+    };
+
+    let trace = match args.arg2 {
+        TRACE_RECORD => TraceMode::Record(Default::default()),
+        TRACE_REPLAY => match crate::proc::trace::load(&name) {
+            Some(trace) => TraceMode::Replay(trace),
+            None => return errno_ret(SpawnError::NoTrace.errno()),
+        },
+        _ => return errno_ret(EINVAL),
+    };
 
-    pid.unwrap().0 as usize
+    match crate::proc::spawn_traced(&name, Some(trace)) {
+        Ok(pid) => pid.0 as usize,
+        Err(err) => {
+            if err != SpawnError::Pending {
+                warn!("spawn_traced_process: failed to spawn {}: {}", name, err);
+            }
+            errno_ret(err.errno())
+        }
+    }
 }
 
-pub fn sys_read(args: &SyscallArgs) -> usize {
-    let buf = unsafe { core::slice::from_raw_parts_mut(args.arg1 as *mut u8, args.arg2) };
+/// Bytes moved between user and kernel space per step of `read` and `write`
+const COPY_CHUNK: usize = 4096;
+
+pub fn sys_read(args: &SyscallArgs, context: &mut ProcessContext) -> Option<usize> {
     let fd = args.arg0 as u8;
-    read(fd, buf) as usize
+    let mut buf = vec![0u8; args.arg2.min(COPY_CHUNK)];
+    let mut total = 0;
+
+    while total < args.arg2 {
+        let len = (args.arg2 - total).min(COPY_CHUNK);
+        let ret = if total == 0 {
+            // nothing is read yet, so a blocked read can start over
+            block_on_fd(fd, false, context, || read(fd, &mut buf[..len]))?
+        } else {
+            read(fd, &mut buf[..len])
+        };
+        if ret < 0 {
+            return Some(if total > 0 { total } else { ret as usize });
+        }
+
+        let count = ret as usize;
+        if let Err(errno) = copy_to_user(args.arg1 + total, &buf[..count]) {
+            return Some(errno_ret(errno));
+        }
+
+        total += count;
+        if count < len {
+            break;
+        }
+    }
+
+    Some(total)
 }
 
-pub fn sys_write(args: &SyscallArgs) -> usize {
-    let buf = unsafe { core::slice::from_raw_parts(args.arg1 as *const u8, args.arg2) };
+pub fn sys_write(args: &SyscallArgs, context: &mut ProcessContext) -> Option<usize> {
     let fd = args.arg0 as u8;
-    write(fd, buf) as usize
+    let mut buf = vec![0u8; args.arg2.min(COPY_CHUNK)];
+    let mut total = 0;
+
+    while total < args.arg2 {
+        let len = (args.arg2 - total).min(COPY_CHUNK);
+        if let Err(errno) = copy_from_user(&mut buf[..len], args.arg1 + total) {
+            return Some(if total > 0 { total } else { errno_ret(errno) });
+        }
+
+        let ret = if total == 0 {
+            // nothing is written yet, so a blocked write can start over
+            block_on_fd(fd, true, context, || write(fd, &buf[..len]))?
+        } else {
+            write(fd, &buf[..len])
+        };
+        if ret < 0 {
+            return Some(if total > 0 { total } else { ret as usize });
+        }
+
+        total += ret as usize;
+        if (ret as usize) < len {
+            break;
+        }
+    }
+
+    Some(total)
+}
+
+pub fn sys_readv(args: &SyscallArgs, context: &mut ProcessContext) -> Option<usize> {
+    let iov = match user_iovecs(args.arg1, args.arg2) {
+        Ok(iov) => iov,
+        Err(errno) => return Some(errno_ret(errno)),
+    };
+
+    // read into kernel buffers, the user pages may fault
+    let mut bufs = iov_bounce_buffers(&iov);
+    let mut slices = bufs.iter_mut().map(Vec::as_mut_slice).collect::<Vec<_>>();
+
+    let fd = args.arg0 as u8;
+    let ret = block_on_fd(fd, false, context, || read_vectored(fd, &mut slices))?;
+    if ret < 0 {
+        return Some(ret as usize);
+    }
+
+    let mut left = ret as usize;
+    for (v, buf) in iov.iter().zip(bufs.iter()) {
+        let len = buf.len().min(left);
+        if let Err(errno) = copy_to_user(v.base as usize, &buf[..len]) {
+            return Some(errno_ret(errno));
+        }
+        left -= len;
+    }
+
+    Some(ret as usize)
+}
+
+pub fn sys_writev(args: &SyscallArgs, context: &mut ProcessContext) -> Option<usize> {
+    let iov = match user_iovecs(args.arg1, args.arg2) {
+        Ok(iov) => iov,
+        Err(errno) => return Some(errno_ret(errno)),
+    };
+
+    // the whole write is copied in first, so it still goes out in one piece
+    let mut bufs = iov_bounce_buffers(&iov);
+    for (v, buf) in iov.iter().zip(bufs.iter_mut()) {
+        if let Err(errno) = copy_from_user(buf, v.base as usize) {
+            return Some(errno_ret(errno));
+        }
+    }
+    let slices = bufs.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+    let fd = args.arg0 as u8;
+    block_on_fd(fd, true, context, || write_vectored(fd, &slices) as usize)
+}
+
+/// Wait for the `PollFd`s at `arg0`, `arg1` of them, until one is ready
+/// or the monotonic clock reaches `arg2`, `POLL_FOREVER` for no deadline
+///
+/// the deadline is absolute, so a poll woken and run again keeps it.
+/// Return how many fds are ready, 0 at the deadline.
+pub fn sys_poll(args: &SyscallArgs, context: &mut ProcessContext) -> Option<usize> {
+    let count = args.arg1;
+    if count > POLL_MAX {
+        return Some(errno_ret(EINVAL));
+    }
+
+    let mut fds = vec![PollFd::default(); count];
+    if let Err(errno) = unsafe { copy_slice_from_user(&mut fds, args.arg0) } {
+        return Some(errno_ret(errno));
+    }
+
+    let deadline = match args.arg2 as u64 {
+        POLL_FOREVER => None,
+        deadline => Some(deadline.min(i64::MAX as u64) as i64),
+    };
+    let ready = poll(&mut fds, deadline, context)?;
+
+    Some(match copy_slice_to_user(args.arg0, &fds) {
+        Ok(()) => ready,
+        Err(errno) => errno_ret(errno),
+    })
+}
+
+/// Copy from `in_fd` to `out_fd`, from the offset at `arg2` if it is not null
+///
+/// the offset is then moved past the bytes copied, and the position of
+/// `in_fd` is left as it was, as `sendfile` does.
+pub fn sys_send_file(args: &SyscallArgs) -> usize {
+    let (out_fd, in_fd, len) = (args.arg0 as u8, args.arg1 as u8, args.arg3);
+    let mut offset = [0usize];
+    if args.arg2 != 0 {
+        if let Err(errno) = unsafe { copy_slice_from_user(&mut offset, args.arg2) } {
+            return errno_ret(errno);
+        }
+    }
+
+    let start = (args.arg2 != 0).then_some(offset[0]);
+    match send_file(out_fd, in_fd, start, len) {
+        Ok(count) => match start {
+            Some(start) => match copy_slice_to_user(args.arg2, &[start + count]) {
+                Ok(()) => count,
+                Err(errno) => errno_ret(errno),
+            },
+            None => count,
+        },
+        Err(errno) => errno_ret(errno),
+    }
+}
+
+pub fn sys_memfd() -> usize {
+    match open(Arc::new(Buffer::new())) {
+        Some(fd) => fd as usize,
+        None => errno_ret(EMFILE),
+    }
+}
+
+/// Open a pipe, return the read fd in the low byte and the write fd above it
+pub fn sys_pipe() -> usize {
+    match open_pipe() {
+        Some((read, write)) => read as usize | (write as usize) << 8,
+        None => errno_ret(EMFILE),
+    }
+}
+
+pub fn sys_open_pty() -> usize {
+    match open_pty() {
+        Some((master, slave)) => master as usize | (slave as usize) << 8,
+        None => errno_ret(EMFILE),
+    }
+}
+
+pub fn sys_timerfd(args: &SyscallArgs) -> usize {
+    match open(Arc::new(TimerFd::new(args.arg0 as u64, args.arg1 as u64))) {
+        Some(fd) => fd as usize,
+        None => errno_ret(EMFILE),
+    }
+}
+
+pub fn sys_eventfd(args: &SyscallArgs) -> usize {
+    match open(Arc::new(EventFd::new(args.arg0 as u64))) {
+        Some(fd) => fd as usize,
+        None => errno_ret(EMFILE),
+    }
+}
+
+/// Open a file the host shares through fw_cfg, read in whole
+pub fn sys_host_open(args: &SyscallArgs) -> usize {
+    let mut name = [0u8; fw_cfg::NAME_LEN];
+    if args.arg1 > name.len() {
+        return errno_ret(ENOENT);
+    }
+
+    let name = &mut name[..args.arg1];
+    if let Err(errno) = copy_from_user(name, args.arg0) {
+        return errno_ret(errno);
+    }
+
+    let name = match core::str::from_utf8(name) {
+        Ok(name) => name,
+        Err(_) => return errno_ret(EINVAL),
+    };
+
+    match vfs::open(&alloc::format!("/host/{}", name), O_RDONLY).map(open) {
+        Ok(Some(fd)) => fd as usize,
+        Ok(None) => errno_ret(EMFILE),
+        Err(err) => errno_ret(err.errno()),
+    }
+}
+
+/// Open a file or device by its path in the VFS, with the `O_*` flags
+///
+/// `/host/<name>` is what `sys_host_open` opens as `<name>`.
+pub fn sys_open(args: &SyscallArgs) -> usize {
+    if args.arg1 > OPEN_PATH_MAX {
+        return errno_ret(ENOENT);
+    }
+
+    let path = match user_string(IoVec {
+        base: args.arg0 as *const u8,
+        len: args.arg1,
+    }) {
+        Ok(path) => path,
+        Err(errno) => return errno_ret(errno),
+    };
+
+    match vfs::open(&path, args.arg2).map(open) {
+        Ok(Some(fd)) => fd as usize,
+        Ok(None) => errno_ret(EMFILE),
+        Err(err) => errno_ret(err.errno()),
+    }
+}
+
+pub fn sys_close(args: &SyscallArgs) -> usize {
+    if close(args.arg0 as u8) {
+        0
+    } else {
+        errno_ret(EBADF)
+    }
+}
+
+pub fn sys_dup2(args: &SyscallArgs) -> usize {
+    let (old, new) = (args.arg0 as u8, args.arg1 as u8);
+    if dup2(old, new) {
+        new as usize
+    } else {
+        errno_ret(EBADF)
+    }
+}
+
+pub fn sys_seek(args: &SyscallArgs) -> usize {
+    match seek(args.arg0 as u8, args.arg1 as isize, args.arg2) {
+        Ok(pos) => pos,
+        Err(errno) => errno_ret(errno),
+    }
+}
+
+pub fn sys_fstat(args: &SyscallArgs) -> usize {
+    let stat = match fd_stat(args.arg0 as u8) {
+        Some(stat) => stat,
+        None => return errno_ret(EBADF),
+    };
+
+    match copy_slice_to_user(args.arg1, &[stat]) {
+        Ok(()) => 0,
+        Err(errno) => errno_ret(errno),
+    }
+}
+
+pub fn sys_fcntl(args: &SyscallArgs) -> usize {
+    let fd = args.arg0 as u8;
+    let ret = match args.arg1 {
+        F_GETRATE => fd_stat(fd).map(|stat| stat.rate as usize),
+        F_SETRATE => set_fd_rate(fd, args.arg2),
+        F_GETRAW | F_SETRAW => return ioctl(fd, args.arg1, args.arg2).unwrap_or_else(errno_ret),
+        _ => return errno_ret(EINVAL),
+    };
+
+    ret.unwrap_or(errno_ret(EBADF))
+}
+
+/// Copy the iovec array from user space, the buffers it points to are
+/// copied through `iov_bounce_buffers` by the caller
+fn user_iovecs(ptr: usize, count: usize) -> Result<Vec<IoVec>, usize> {
+    if count > IOV_MAX {
+        warn!("user_iovecs: too many buffers: {}", count);
+        return Err(EINVAL);
+    }
+
+    let mut iov = vec![IoVec::new(&[]); count];
+    unsafe { copy_slice_from_user(&mut iov, ptr) }?;
+    Ok(iov)
+}
+
+/// Bytes `readv` and `writev` move per syscall at most,
+/// the caller sees a short count for the rest
+const IOV_BYTES_MAX: usize = 16 * COPY_CHUNK;
+
+/// Zeroed kernel buffers as long as the buffers of `iov`,
+/// up to `IOV_BYTES_MAX` in total
+fn iov_bounce_buffers(iov: &[IoVec]) -> Vec<Vec<u8>> {
+    let mut left = IOV_BYTES_MAX;
+    iov.iter()
+        .map(|v| {
+            let len = v.len.min(left);
+            left -= len;
+            vec![0u8; len]
+        })
+        .collect()
 }
 
 pub fn sys_get_pid() -> u16 {
@@ -85,22 +586,303 @@ pub fn exit_process(args: &SyscallArgs, context: &mut ProcessContext) {
     process_exit(args.arg0 as isize, context);
 }
 
+/// Power off, in test mode `code` is the result QEMU exits with
+pub fn sys_shutdown(args: &SyscallArgs) -> ! {
+    crate::shutdown(args.arg0 as isize)
+}
+
 pub fn list_process() {
     print_process_list();
 }
 
 pub fn sys_wait_pid(args: &SyscallArgs, context: &mut ProcessContext) {
+    let nohang = args.arg1 & WAIT_NOHANG != 0;
+    if args.arg0 != WAIT_ANY {
+        let pid = ProcessId(args.arg0 as u16);
+        wait_pid(pid, args.arg1 & WAIT_UNTRACED != 0, nohang, context);
+        return;
+    }
+
+    // checked first, the child is reaped once it is taken
+    if args.arg2 != 0 && !check_user_buffer(args.arg2, size_of::<u16>(), true) {
+        context.set_rax(errno_ret(EFAULT));
+        return;
+    }
+
+    match wait_any(nohang, context) {
+        Some(Ok(Some((pid, ret)))) => {
+            if args.arg2 != 0 {
+                let _ = copy_slice_to_user(args.arg2, &[pid.0]);
+            }
+            context.set_rax(ret as usize);
+        }
+        Some(Ok(None)) => context.set_rax(WAIT_RUNNING as usize),
+        Some(Err(errno)) => context.set_rax(errno_ret(errno)),
+        None => (),
+    }
+}
+
+pub fn sys_suspend(args: &SyscallArgs, context: &mut ProcessContext) {
     let pid = ProcessId(args.arg0 as u16);
-    wait_pid(pid, context);
+    if pid == KERNEL_PID {
+        context.set_rax(errno_ret(EPERM));
+        return;
+    }
+    suspend(pid, context);
+}
+
+pub fn sys_resume(args: &SyscallArgs) -> usize {
+    if resume(ProcessId(args.arg0 as u16)) {
+        0
+    } else {
+        errno_ret(ESRCH)
+    }
 }
 
 pub fn sys_kill(args: &SyscallArgs, context: &mut ProcessContext) {
     let pid = ProcessId(args.arg0 as u16);
     if pid == ProcessId(1) {
         warn!("sys_kill: cannot kill kernel!");
+        context.set_rax(errno_ret(EPERM));
+        return;
+    }
+    if args.arg1 != 0 && !is_signal(args.arg1) {
+        context.set_rax(errno_ret(EINVAL));
         return;
     }
-    kill(pid, context);
+    kill(pid, args.arg1, context);
+}
+
+pub fn sys_sigaction(args: &SyscallArgs) -> usize {
+    let sig = args.arg0;
+    if !is_signal(sig) || (args.arg1 != SIG_KEEP && !can_catch(sig)) {
+        return errno_ret(EINVAL);
+    }
+    sigaction(sig, args.arg1, args.arg2)
+}
+
+pub fn sys_sigreturn(context: &mut ProcessContext) {
+    sig_return(context);
+}
+
+pub fn sys_yield(context: &mut ProcessContext) {
+    yield_now(context);
+}
+
+pub fn sys_sleep(args: &SyscallArgs, context: &mut ProcessContext) {
+    context.set_rax(0);
+    sleep(args.arg0 as u64, context);
+}
+
+#[cfg(feature = "debug-tools")]
+pub fn sys_sched_stat(args: &SyscallArgs) -> usize {
+    let mut buf = [SchedEvent::default(); SCHED_HISTORY_LEN];
+    let count = args.arg2.min(SCHED_HISTORY_LEN);
+
+    let count = match sched_history(ProcessId(args.arg0 as u16), &mut buf[..count]) {
+        Some(count) => count,
+        None => return errno_ret(ESRCH),
+    };
+
+    match copy_slice_to_user(args.arg1, &buf[..count]) {
+        Ok(()) => count,
+        Err(errno) => errno_ret(errno),
+    }
+}
+
+pub fn sys_get_rusage(args: &SyscallArgs) -> usize {
+    let pid = match args.arg0 {
+        0 => current_pid(),
+        pid => ProcessId(pid as u16),
+    };
+
+    let Some(usage) = rusage(pid) else {
+        return errno_ret(ESRCH);
+    };
+
+    match copy_slice_to_user(args.arg1, &[usage]) {
+        Ok(()) => 0,
+        Err(errno) => errno_ret(errno),
+    }
+}
+
+pub fn sys_sysinfo(args: &SyscallArgs) -> usize {
+    match copy_slice_to_user(args.arg0, &[sysinfo()]) {
+        Ok(()) => 0,
+        Err(errno) => errno_ret(errno),
+    }
+}
+
+pub fn sys_proc_info(args: &SyscallArgs) -> usize {
+    let pid = match args.arg0 {
+        0 => current_pid(),
+        pid => ProcessId(pid as u16),
+    };
+
+    let Some(info) = proc_info(pid) else {
+        return errno_ret(ESRCH);
+    };
+
+    match copy_slice_to_user(args.arg1, &[info]) {
+        Ok(()) => 0,
+        Err(errno) => errno_ret(errno),
+    }
+}
+
+/// The process a syscall changes, `pid` or the caller for 0
+///
+/// there are no users yet, so only the process and its ancestors may
+/// change it, others get `EPERM`
+fn target_pid(pid: usize) -> Result<ProcessId, usize> {
+    let pid = match pid {
+        0 => current_pid(),
+        pid => ProcessId(pid as u16),
+    };
+
+    if !is_ancestor(current_pid(), pid) {
+        return Err(EPERM);
+    }
+    Ok(pid)
+}
+
+pub fn sys_prlimit(args: &SyscallArgs) -> usize {
+    if args.arg1 != RLIMIT_CPU {
+        return errno_ret(EINVAL);
+    }
+
+    let pid = match target_pid(args.arg0) {
+        Ok(pid) => pid,
+        Err(errno) => return errno_ret(errno),
+    };
+
+    let limit = match args.arg2 {
+        RLIMIT_KEEP => None,
+        secs => Some((secs as u64).saturating_mul(NANOS_PER_SEC)),
+    };
+
+    match cpu_limit(pid, limit) {
+        // round up, so a limit that is set never reads as unlimited
+        Some(old) => old.div_ceil(NANOS_PER_SEC) as usize,
+        None => errno_ret(ESRCH),
+    }
+}
+
+pub fn sys_nice(args: &SyscallArgs) -> usize {
+    let pid = current_pid();
+    let old = nice(pid, None).unwrap();
+    let new = old.saturating_add(args.arg0 as isize).clamp(NICE_MIN, NICE_MAX);
+    nice(pid, Some(new));
+
+    (NICE_BIAS - new) as usize
+}
+
+pub fn sys_renice(args: &SyscallArgs) -> usize {
+    let pid = match target_pid(args.arg0) {
+        Ok(pid) => pid,
+        Err(errno) => return errno_ret(errno),
+    };
+
+    let old = match nice(pid, None) {
+        Some(old) => old,
+        None => return errno_ret(ESRCH),
+    };
+
+    // and only root may lower it for another process
+    let new = (args.arg1 as isize).clamp(NICE_MIN, NICE_MAX);
+    if new < old && pid != current_pid() && !is_root(current_pid()) {
+        return errno_ret(EPERM);
+    }
+
+    match nice(pid, Some(new)) {
+        Some(old) => (NICE_BIAS - old) as usize,
+        None => errno_ret(ESRCH),
+    }
+}
+
+pub fn sys_set_priority(args: &SyscallArgs) -> usize {
+    let pid = match target_pid(args.arg0) {
+        Ok(pid) => pid,
+        Err(errno) => return errno_ret(errno),
+    };
+
+    let old = match priority(pid, None) {
+        Some(old) => old,
+        None => return errno_ret(ESRCH),
+    };
+
+    if args.arg1 == PRIO_KEEP {
+        return old;
+    }
+    if args.arg1 >= PRIO_LEVELS {
+        return errno_ret(EINVAL);
+    }
+
+    // and only root may raise it for another process
+    if args.arg1 < old && pid != current_pid() && !is_root(current_pid()) {
+        return errno_ret(EPERM);
+    }
+
+    match priority(pid, Some(args.arg1)) {
+        Some(old) => old,
+        None => errno_ret(ESRCH),
+    }
+}
+
+pub fn sys_sched_rt(args: &SyscallArgs) -> usize {
+    let pid = match target_pid(args.arg0) {
+        Ok(pid) => pid,
+        Err(errno) => return errno_ret(errno),
+    };
+
+    // and only root may put another process in the class
+    if args.arg2 != 0 && pid != current_pid() && !is_root(current_pid()) {
+        return errno_ret(EPERM);
+    }
+
+    match sched_rt(pid, args.arg1 as u64, args.arg2 as u64) {
+        Ok(()) => 0,
+        Err(errno) => errno_ret(errno),
+    }
+}
+
+pub fn sys_umask(args: &SyscallArgs) -> usize {
+    let new = match args.arg0 {
+        UMASK_KEEP => None,
+        mask => Some(mask as u16),
+    };
+
+    umask(new) as usize
+}
+
+#[cfg(feature = "debug-tools")]
+pub fn sys_maps(args: &SyscallArgs) -> usize {
+    let pid = ProcessId(args.arg0 as u16);
+
+    if !is_root(current_pid()) {
+        return errno_ret(EPERM);
+    }
+
+    let mappings = match paging::dump(pid, 0..=paging::USER_LAST) {
+        Some(mappings) => mappings,
+        None => return errno_ret(ESRCH),
+    };
+
+    let entries = mappings
+        .iter()
+        .take(args.arg2)
+        .map(|m| MapEntry {
+            start: m.virt.as_u64(),
+            end: m.virt.as_u64() + m.len,
+            phys: m.phys.as_u64(),
+            page_size: m.page_size,
+            flags: m.flags.bits(),
+        })
+        .collect::<Vec<_>>();
+
+    match copy_slice_to_user(args.arg1, &entries) {
+        Ok(()) => mappings.len(),
+        Err(errno) => errno_ret(errno),
+    }
 }
 
 pub fn sys_fork(context: &mut ProcessContext) {
@@ -108,16 +890,86 @@ pub fn sys_fork(context: &mut ProcessContext) {
     status
 }
 
+/// Start a thread at `entry` with `arg`, on the stack below `stack_top`
+pub fn sys_clone(args: &SyscallArgs) -> usize {
+    let (Ok(entry), Ok(stack_top)) = (
+        VirtAddr::try_new(args.arg0 as u64),
+        VirtAddr::try_new(args.arg1 as u64),
+    ) else {
+        return errno_ret(EINVAL);
+    };
+    if entry.is_null() {
+        return errno_ret(EINVAL);
+    }
+    // the thread pushes on it first thing
+    let Some(bottom) = args.arg1.checked_sub(16) else {
+        return errno_ret(EFAULT);
+    };
+    if !check_user_buffer(bottom, 16, true) {
+        return errno_ret(EFAULT);
+    }
+
+    match spawn_thread(entry, stack_top, args.arg2) {
+        Ok(tid) => tid.0 as usize,
+        Err(errno) => errno_ret(errno),
+    }
+}
+
 pub fn sys_sem(args: &SyscallArgs, context: &mut ProcessContext) {
     match args.arg0 {
-        0 => context.set_rax(new_sem(args.arg1 as u32, args.arg2)),
-        1 => context.set_rax(remove_sem(args.arg1 as u32)),
-        2 => sem_signal(args.arg1 as u32, context),
-        3 => sem_wait(args.arg1 as u32, context),
-        _ => context.set_rax(usize::MAX),
+        SEM_NEW => context.set_rax(new_sem(args.arg1 as u32, args.arg2)),
+        SEM_REMOVE => context.set_rax(remove_sem(args.arg1 as u32)),
+        SEM_SIGNAL => sem_signal(args.arg1 as u32, context),
+        SEM_WAIT => sem_wait(args.arg1 as u32, context),
+        SEM_TRY_WAIT => context.set_rax(sem_try_wait(args.arg1 as u32)),
+        SEM_WAIT_TIMEOUT => sem_wait_timeout(args.arg1 as u32, args.arg2 as u64, context),
+        _ => context.set_rax(errno_ret(EINVAL)),
+    }
+}
+
+pub fn sys_futex(args: &SyscallArgs, context: &mut ProcessContext) {
+    if !args.arg0.is_multiple_of(core::mem::align_of::<u32>()) {
+        return context.set_rax(errno_ret(EINVAL));
+    }
+
+    match args.arg1 {
+        FUTEX_WAIT => futex_wait(args.arg0, args.arg2 as u32, context),
+        FUTEX_WAKE => context.set_rax(futex_wake(args.arg0, args.arg2)),
+        _ => context.set_rax(errno_ret(EINVAL)),
+    }
+}
+
+pub fn sys_mq_open(args: &SyscallArgs) -> usize {
+    match mq_open(args.arg0 as u32, args.arg1, args.arg2) {
+        Ok(msg_size) => msg_size,
+        Err(errno) => errno_ret(errno),
     }
 }
 
+pub fn sys_mq_unlink(args: &SyscallArgs) -> usize {
+    match mq_unlink(args.arg0 as u32) {
+        Ok(()) => 0,
+        Err(errno) => errno_ret(errno),
+    }
+}
+
+pub fn sys_mq_send(args: &SyscallArgs, context: &mut ProcessContext) {
+    // no queue takes a longer message, so it is not copied
+    if args.arg2 > MQ_MAX_MSG_SIZE {
+        return context.set_rax(errno_ret(EMSGSIZE));
+    }
+
+    let mut msg = alloc::vec![0u8; args.arg2];
+    if let Err(errno) = copy_from_user(&mut msg, args.arg1) {
+        return context.set_rax(errno_ret(errno));
+    }
+    mq_send(args.arg0 as u32, &msg, context);
+}
+
+pub fn sys_mq_recv(args: &SyscallArgs, context: &mut ProcessContext) {
+    mq_recv(args.arg0 as u32, args.arg1, args.arg2, context);
+}
+
 pub fn sys_brk(args: &SyscallArgs) -> usize {
     info!("sys_brk: {:?}", args);
     let new_heap_end = if args.arg0 == 0 {
@@ -126,4 +978,240 @@ pub fn sys_brk(args: &SyscallArgs) -> usize {
         Some(args.arg0)
     };
     brk(new_heap_end)
-}
\ No newline at end of file
+}
+
+pub fn sys_mmap(args: &SyscallArgs) -> usize {
+    // a mapping always has flags, none is the packed form of the old ABI
+    let (prot, flags) = match args.arg3 {
+        0 => split_mmap_flags(args.arg2),
+        flags => (args.arg2, flags),
+    };
+
+    if args.arg1 == 0 {
+        return errno_ret(EINVAL);
+    }
+
+    // the fd and offset of an anonymous mapping are ignored, as on Linux
+    let file = match flags & MAP_ANONYMOUS {
+        0 => Some((args.arg4 as u8, args.arg5)),
+        _ => None,
+    };
+
+    let mut page_flags = prot_flags(prot);
+    // private mappings are copied on write after a fork
+    if flags & MAP_SHARED != 0 {
+        page_flags |= paging::SHARED;
+    }
+
+    let addr = if flags & MAP_FIXED != 0 {
+        Some(args.arg0)
+    } else {
+        None
+    };
+
+    mmap(addr, args.arg1, page_flags, file)
+}
+
+pub fn sys_msync(args: &SyscallArgs) -> usize {
+    // writes go back before it returns either way
+    if args.arg2 & !(MS_ASYNC | MS_SYNC) != 0 {
+        return errno_ret(EINVAL);
+    }
+
+    msync(args.arg0, args.arg1)
+}
+
+pub fn sys_shm_open(args: &SyscallArgs) -> usize {
+    match shm_open(args.arg0 as u32, args.arg1) {
+        Ok(size) => size,
+        Err(errno) => errno_ret(errno),
+    }
+}
+
+pub fn sys_shm_map(args: &SyscallArgs) -> usize {
+    let addr = match args.arg1 {
+        0 => None,
+        addr => match VirtAddr::try_new(addr as u64) {
+            Ok(addr) => Some(addr),
+            Err(_) => return errno_ret(EINVAL),
+        },
+    };
+
+    match shm_map(args.arg0 as u32, addr) {
+        Ok(addr) => addr.as_u64() as usize,
+        Err(errno) => errno_ret(errno),
+    }
+}
+
+pub fn sys_shm_unmap(args: &SyscallArgs) -> usize {
+    let addr = match VirtAddr::try_new(args.arg0 as u64) {
+        Ok(addr) => addr,
+        Err(_) => return errno_ret(EINVAL),
+    };
+
+    match shm_unmap(addr) {
+        Ok(()) => 0,
+        Err(errno) => errno_ret(errno),
+    }
+}
+
+#[cfg(feature = "graphics")]
+pub fn sys_map_frame_buffer(args: &SyscallArgs) -> usize {
+    let (addr, fb) = match map_frame_buffer() {
+        Ok(mapped) => mapped,
+        Err(errno) => return errno_ret(errno),
+    };
+
+    let (width, height) = fb.mode.resolution();
+    let format = match fb.mode.pixel_format() {
+        boot::PixelFormat::Rgb => FB_FORMAT_RGB,
+        _ => FB_FORMAT_BGR,
+    };
+    let info = FbInfo {
+        addr: addr.as_u64(),
+        width: width as u32,
+        height: height as u32,
+        stride: fb.mode.stride() as u32,
+        format,
+    };
+
+    match copy_slice_to_user(args.arg0, &[info]) {
+        Ok(()) => 0,
+        Err(errno) => errno_ret(errno),
+    }
+}
+
+/// Page flags for `PROT_*` bits, pages can always be read
+fn prot_flags(prot: usize) -> PageTableFlags {
+    let mut page_flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if prot & PROT_WRITE != 0 {
+        page_flags |= PageTableFlags::WRITABLE;
+    }
+    if prot & PROT_EXEC == 0 {
+        page_flags |= PageTableFlags::NO_EXECUTE;
+    }
+    page_flags
+}
+
+pub fn sys_mprotect(args: &SyscallArgs) -> usize {
+    let flags = prot_flags(args.arg2);
+    // code written at runtime has to be made executable in a second step
+    if paging::is_wx(flags) && ALLOW_WX.get() == 0 {
+        warn!("sys_mprotect: pages cannot be both writable and executable");
+        return errno_ret(EACCES);
+    }
+
+    mprotect(args.arg0, args.arg1, flags)
+}
+
+pub fn sys_munmap(args: &SyscallArgs) -> usize {
+    munmap(args.arg0, args.arg1)
+}
+
+pub fn sys_madvise(args: &SyscallArgs) -> usize {
+    let advice = match args.arg2 {
+        MADV_WILLNEED => Advice::WillNeed,
+        MADV_DONTNEED => Advice::DontNeed,
+        MADV_PIN => Advice::Pin,
+        MADV_UNPIN => Advice::Unpin,
+        _ => {
+            warn!("sys_madvise: unknown advice: {}", args.arg2);
+            return errno_ret(EINVAL);
+        }
+    };
+
+    madvise(args.arg0, args.arg1, advice)
+}
+
+/// Longest tunable name accepted by `Syscall::Sysctl`
+const SYSCTL_NAME_MAX: usize = 64;
+
+pub fn sys_sysctl(args: &SyscallArgs) -> usize {
+    let mut name = [0u8; SYSCTL_NAME_MAX];
+    if args.arg1 > name.len() {
+        return errno_ret(ENOENT);
+    }
+
+    let name = &mut name[..args.arg1];
+    if let Err(errno) = copy_from_user(name, args.arg0) {
+        return errno_ret(errno);
+    }
+
+    let name = match core::str::from_utf8(name) {
+        Ok(name) => name,
+        Err(_) => return errno_ret(EINVAL),
+    };
+
+    // an empty name lists all tunables as `name = value` lines, into the
+    // buffer at arg0 of arg2 bytes, and returns the length of the whole list
+    if name.is_empty() {
+        let mut list = String::new();
+        for tunable in sysctl::list() {
+            let _ = writeln!(list, "{} = {}", tunable.name(), tunable.get());
+        }
+        let count = list.len().min(args.arg2);
+        return match copy_to_user(args.arg0, &list.as_bytes()[..count]) {
+            Ok(()) => list.len(),
+            Err(errno) => errno_ret(errno),
+        };
+    }
+
+    match sysctl::find(name) {
+        Some(tunable) if args.arg2 == !0 => tunable.get(),
+        // the process limits and `mem.allow_wx` are not for any process to lift
+        Some(_) if !is_root(current_pid()) => errno_ret(EPERM),
+        Some(tunable) => {
+            info!("sysctl: {} = {}", name, args.arg2);
+            tunable.set(args.arg2)
+        }
+        None => errno_ret(ENOENT),
+    }
+}
+
+/// Mount the FAT16 image in a file on a directory through a loop device,
+/// only root may
+pub fn sys_mount(args: &SyscallArgs) -> usize {
+    if !is_root(current_pid()) {
+        return errno_ret(EPERM);
+    }
+
+    let paths = match user_strings(args.arg0 as *const IoVec, 2, &mut 0) {
+        Ok(paths) => paths,
+        Err(errno) => return errno_ret(errno),
+    };
+    if paths.iter().any(|path| path.len() > OPEN_PATH_MAX) {
+        return errno_ret(ENOENT);
+    }
+
+    match crate::fs::mount_image(&paths[0], &paths[1]) {
+        Ok(n) => n,
+        Err(err) => {
+            if !err.is_pending() {
+                warn!("sys_mount: failed to mount {}: {}", paths[0], err);
+            }
+            errno_ret(err.errno())
+        }
+    }
+}
+
+pub fn sys_swap_on(args: &SyscallArgs) -> usize {
+    if !is_root(current_pid()) {
+        return errno_ret(EPERM);
+    }
+    if args.arg1 > OPEN_PATH_MAX {
+        return errno_ret(ENOENT);
+    }
+
+    let path = match user_string(IoVec {
+        base: args.arg0 as *const u8,
+        len: args.arg1,
+    }) {
+        Ok(path) => path,
+        Err(errno) => return errno_ret(errno),
+    };
+
+    match swap::on(&path) {
+        Ok(()) => 0,
+        Err(errno) => errno_ret(errno),
+    }
+}
@@ -10,8 +10,6 @@ extern crate alloc;
 #[macro_use]
 extern crate log;
 #[macro_use]
-extern crate lazy_static;
-#[macro_use]
 extern crate bitflags;
 extern crate libm;
 
@@ -23,24 +21,50 @@ pub use utils::*;
 pub mod drivers;
 pub use drivers::*;
 
+pub mod cpu;
+pub mod fs;
+pub mod golden;
 pub mod interrupt;
 pub mod memory;
+#[cfg(feature = "debug-tools")]
+pub mod monitor;
+pub mod percpu;
+pub mod pmc;
 pub mod proc;
+#[cfg(feature = "debug-tools")]
+pub mod rescue;
 
 pub use alloc::format;
 use boot::BootInfo;
 
 pub fn init(boot_info: &'static BootInfo) {
+    early_println!("[+] Kernel entered, boot info at {:p}.", boot_info);
+    debugcon::init(boot_info); // set the early console port
+    debug_exit::init(boot_info); // set the test result port
     serial::init(); // init serial output
     logger::init(boot_info); // init logger system
+    cmdline::init(boot_info); // init kernel command line
+    cpu::init(); // detect cpu features
+    pmc::init(); // start the performance counters
     memory::address::init(boot_info);
     memory::gdt::init(); // init gdt
     memory::allocator::init(); // init kernel heap allocator
+    serial::init_staging(); // init staging buffer for busy serial
+    #[cfg(feature = "graphics")]
+    display::init(boot_info); // mirror the console to the framebuffer
+    fw_cfg::init(); // find the files shared by the host
+    ata::init(); // find the ATA disks
     interrupt::init(); // init interrupts
+    percpu::current().validate(); // check the tables of this cpu
     clock::init(boot_info); // init clock (uefi service)
     memory::init(boot_info); // init memory manager
+    ahci::init(); // find the SATA disks, needs frames for DMA
+    fs::init(boot_info); // mount the filesystems
     memory::user::init(); // init user heap allocator
     proc::init(boot_info); // init task manager
+    proc::deterministic::init(); // init deterministic scheduling if asked
+    proc::swap::init(); // swap to the device named by swap= if any
+    memory::protect::protect_kernel(boot_info); // remap kernel sections
 
     x86_64::instructions::interrupts::enable();
     info!("Interrupts Enabled.");
@@ -54,25 +78,26 @@ pub fn init(boot_info: &'static BootInfo) {
     info!("Stack grow test done.");
 }
 
-pub fn wait(init: proc::ProcessId) {
+/// Idle until process `pid` exits, return its exit code
+pub fn wait(pid: proc::ProcessId) -> isize {
     loop {
-        if proc::wait_no_block(init).is_none() {
+        if let Some(ret) = proc::wait_no_block(pid) {
+            return ret;
+        }
+
+        // make use of the idle time before halting
+        if !memory::zero_idle_frames() {
             x86_64::instructions::hlt();
-        } else {
-            break;
         }
     }
 }
 
-pub fn shutdown(boot_info: &'static BootInfo) -> ! {
-    info!("YatSenOS shutting down.");
-    unsafe {
-        boot_info.system_table.runtime_services().reset(
-            boot::ResetType::SHUTDOWN,
-            boot::UefiStatus::SUCCESS,
-            None,
-        );
-    }
+/// Power off, `code` is the result reported to `xtask test`
+pub fn shutdown(code: isize) -> ! {
+    info!("YatSenOS shutting down with code {}.", code);
+    // QEMU exits here when it has the debug-exit device
+    debug_exit::exit(code == 0);
+    uefi::get_uefi_runtime_for_sure().shutdown()
 }
 
 #[no_mangle]
@@ -8,14 +8,53 @@ extern crate alloc;
 
 boot::entry_point!(kernel_main);
 
+/// The app started as init unless `init=` is on the command line
+const DEFAULT_INIT: &str = "sh";
+
 pub fn kernel_main(boot_info: &'static boot::BootInfo) -> ! {
     ysos::init(boot_info);
-    ysos::wait(spawn_init());
-    ysos::shutdown(boot_info);
+    if golden::enabled() {
+        ysos::shutdown(golden::run());
+    }
+
+    let code = match spawn_init() {
+        Some(init) => ysos::wait(init),
+        None => run_rescue(),
+    };
+    ysos::shutdown(code);
 }
 
-pub fn spawn_init() -> proc::ProcessId {
+/// Run the rescue shell until it exits, return the code to shut down with
+#[cfg(feature = "debug-tools")]
+fn run_rescue() -> isize {
+    rescue::run();
+    0
+}
+
+#[cfg(not(feature = "debug-tools"))]
+fn run_rescue() -> isize {
+    log::error!("No init to run, and the rescue shell is not built in");
+    1
+}
+
+/// Spawn init, `None` to go to the rescue shell instead
+pub fn spawn_init() -> Option<proc::ProcessId> {
     // print_serial!("\x1b[1;1H\x1b[2J");
+    if cfg!(feature = "debug-tools") && cmdline::enabled("rescue") {
+        return None;
+    }
+
     proc::list_app();
-    proc::spawn("sh").unwrap()
+
+    let init = cmdline::get("init")
+        .filter(|name| !name.is_empty())
+        .unwrap_or(DEFAULT_INIT);
+
+    match proc::spawn(init) {
+        Ok(pid) => Some(pid),
+        Err(err) => {
+            log::error!("Failed to spawn init {}: {}", init, err);
+            None
+        }
+    }
 }
@@ -1,21 +1,67 @@
 // reference: https://github.com/xfoxfu/rust-xos/blob/main/kernel/src/allocator.rs
 
+use core::alloc::{GlobalAlloc, Layout};
 use linked_list_allocator::LockedHeap;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::Page;
 use x86_64::VirtAddr;
 
+use super::{guard, PAGE_SIZE};
+
 pub const HEAP_SIZE: usize = 8 * 1024 * 1024; // 8 MiB
 
 #[global_allocator]
-pub static ALLOCATOR: LockedHeap = LockedHeap::empty();
+pub static ALLOCATOR: KernelHeap = KernelHeap(LockedHeap::empty());
+
+/// Kernel heap that holds its lock with interrupts disabled
+///
+/// syscalls can be preempted, an interrupt handler must never
+/// spin on the heap lock held by the code it interrupted.
+pub struct KernelHeap(LockedHeap);
+
+impl KernelHeap {
+    pub fn used(&self) -> usize {
+        interrupts::without_interrupts(|| self.0.lock().used())
+    }
+}
+
+unsafe impl GlobalAlloc for KernelHeap {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        interrupts::without_interrupts(|| self.0.alloc(layout))
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        interrupts::without_interrupts(|| self.0.dealloc(ptr, layout))
+    }
+}
+
+/// The heap with a guard page on each side
+#[repr(C, align(4096))]
+struct GuardedHeap {
+    below: [u8; PAGE_SIZE as usize],
+    heap: [u8; HEAP_SIZE],
+    above: [u8; PAGE_SIZE as usize],
+}
 
 pub fn init() {
-    static mut HEAP: [u8; HEAP_SIZE] = [0; HEAP_SIZE];
+    static mut HEAP: GuardedHeap = GuardedHeap {
+        below: [0; PAGE_SIZE as usize],
+        heap: [0; HEAP_SIZE],
+        above: [0; PAGE_SIZE as usize],
+    };
 
-    let heap_start = VirtAddr::from_ptr(unsafe { HEAP.as_ptr() });
+    let heap_start = VirtAddr::from_ptr(unsafe { HEAP.heap.as_ptr() });
     let heap_end = heap_start + HEAP_SIZE as u64;
 
     unsafe {
-        ALLOCATOR.lock().init(HEAP.as_mut_ptr(), HEAP_SIZE);
+        ALLOCATOR.0.lock().init(HEAP.heap.as_mut_ptr(), HEAP_SIZE);
+    }
+
+    // the guard list lives on the heap, so it can only be filled now
+    for guard_page in [heap_start - PAGE_SIZE, heap_end] {
+        if !guard::protect(Page::containing_address(guard_page), "kernel heap") {
+            warn!("Kernel heap guard at {:#x} not set.", guard_page.as_u64());
+        }
     }
 
     debug!(
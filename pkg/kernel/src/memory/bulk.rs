@@ -0,0 +1,122 @@
+//! Bulk copies and fills for large buffers
+//!
+//! the kernel is built without SSE and does not save vector state on a
+//! switch, so these use string instructions: `rep movsb` on cpus with
+//! ERMS, otherwise `rep movsq` with the destination aligned first.
+//! `mem.copy_mode` set to 1 copies a byte at a time instead, for the
+//! `bench` app to compare against.
+
+use core::arch::asm;
+
+use crate::cpu::{self, Features};
+use crate::utils::sysctl::Tunable;
+
+/// 0 picks the routine by the cpu features, 1 copies byte by byte
+pub static COPY_MODE: Tunable = Tunable::new("mem.copy_mode", 0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Strategy {
+    Bytes,
+    Qwords,
+    Erms,
+}
+
+/// The routine copies use, see `mem.copy_mode`
+pub fn strategy() -> Strategy {
+    if COPY_MODE.get() == 1 {
+        Strategy::Bytes
+    } else if cpu::has(Features::ERMS) {
+        Strategy::Erms
+    } else {
+        Strategy::Qwords
+    }
+}
+
+/// Copy `len` bytes from `src` to `dst`
+///
+/// # Safety
+///
+/// both ranges must be valid and must not overlap
+pub unsafe fn copy(dst: *mut u8, src: *const u8, len: usize) {
+    match strategy() {
+        Strategy::Bytes => {
+            for i in 0..len {
+                dst.add(i).write_volatile(src.add(i).read_volatile());
+            }
+        }
+        Strategy::Erms => {
+            asm!(
+                "rep movsb",
+                inout("rcx") len => _,
+                inout("rdi") dst => _,
+                inout("rsi") src => _,
+                options(nostack, preserves_flags),
+            );
+        }
+        Strategy::Qwords => {
+            // bytes up to an aligned destination, then qwords, then the tail
+            let head = dst.align_offset(8).min(len);
+            let rest = len - head;
+            asm!(
+                "rep movsb",
+                "mov rcx, {qwords}",
+                "rep movsq",
+                "mov rcx, {tail}",
+                "rep movsb",
+                qwords = in(reg) rest / 8,
+                tail = in(reg) rest % 8,
+                inout("rcx") head => _,
+                inout("rdi") dst => _,
+                inout("rsi") src => _,
+                options(nostack, preserves_flags),
+            );
+        }
+    }
+}
+
+/// Copy `src` into `dst` of the same length
+pub fn copy_slice(dst: &mut [u8], src: &[u8]) {
+    assert_eq!(dst.len(), src.len(), "copy_slice: lengths differ");
+    unsafe { copy(dst.as_mut_ptr(), src.as_ptr(), src.len()) }
+}
+
+/// Set `len` bytes at `dst` to `value`
+///
+/// # Safety
+///
+/// the range must be valid
+pub unsafe fn fill(dst: *mut u8, value: u8, len: usize) {
+    match strategy() {
+        Strategy::Bytes => {
+            for i in 0..len {
+                dst.add(i).write_volatile(value);
+            }
+        }
+        Strategy::Erms => {
+            asm!(
+                "rep stosb",
+                inout("rcx") len => _,
+                inout("rdi") dst => _,
+                in("al") value,
+                options(nostack, preserves_flags),
+            );
+        }
+        Strategy::Qwords => {
+            let head = dst.align_offset(8).min(len);
+            let rest = len - head;
+            asm!(
+                "rep stosb",
+                "mov rcx, {qwords}",
+                "rep stosq",
+                "mov rcx, {tail}",
+                "rep stosb",
+                qwords = in(reg) rest / 8,
+                tail = in(reg) rest % 8,
+                inout("rcx") head => _,
+                inout("rdi") dst => _,
+                in("rax") value as u64 * 0x0101_0101_0101_0101,
+                options(nostack, preserves_flags),
+            );
+        }
+    }
+}
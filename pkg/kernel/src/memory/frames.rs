@@ -1,11 +1,19 @@
 // reference: https://github.com/phil-opp/blog_os/blob/post-09/src/memory.rs
 // reference: https://github.com/xfoxfu/rust-xos/blob/main/kernel/src/memory.rs
 
+use alloc::collections::BTreeMap;
 use alloc::vec::Vec;
 use boot::{MemoryMap, MemoryType};
 use x86_64::structures::paging::{FrameAllocator, FrameDeallocator, PhysFrame, Size4KiB};
 use x86_64::PhysAddr;
 
+use super::{physical_to_virtual, PAGE_SIZE};
+
+/// Frames kept zeroed ahead of time by the idle loop
+pub const ZEROED_POOL_SIZE: usize = 256;
+/// Frames zeroed by the idle loop each time it runs
+const IDLE_ZERO_BATCH: usize = 8;
+
 once_mutex!(pub FRAME_ALLOCATOR: BootInfoFrameAllocator);
 
 guard_access_fn! {
@@ -20,6 +28,12 @@ pub struct BootInfoFrameAllocator {
     used: usize,
     frames: BootInfoFrameIter,
     recycle: Vec<PhysFrame>,
+    zeroed: Vec<PhysFrame>,
+    zeroed_hits: usize,
+    zeroed_misses: usize,
+    /// references besides the first to frames mapped by several
+    /// processes, frames only mapped once are not kept here
+    shared: BTreeMap<PhysFrame, usize>,
 }
 
 impl BootInfoFrameAllocator {
@@ -35,7 +49,11 @@ impl BootInfoFrameAllocator {
             size,
             frames: create_frame_iter(memory_map),
             used: 0,
-            recycle: Vec::new()
+            recycle: Vec::new(),
+            zeroed: Vec::new(),
+            zeroed_hits: 0,
+            zeroed_misses: 0,
+            shared: BTreeMap::new(),
         }
     }
 
@@ -47,26 +65,116 @@ impl BootInfoFrameAllocator {
         self.size
     }
 
+    /// Frames not allocated, those in the zeroed pool included
+    pub fn frames_free(&self) -> usize {
+        self.size.saturating_sub(self.used)
+    }
+
     pub fn frames_recycled(&self) -> usize {
         self.recycle.len()
     }
 
+    /// Frames mapped by more than one process
+    pub fn frames_shared(&self) -> usize {
+        self.shared.len()
+    }
+
+    /// Take another reference to an allocated `frame`
+    ///
+    /// the frame is only returned to the allocator when every
+    /// reference has been deallocated.
+    pub fn share_frame(&mut self, frame: PhysFrame) {
+        *self.shared.entry(frame).or_insert(0) += 1;
+    }
+
+    /// Whether `frame` has more than one reference
+    pub fn is_shared(&self, frame: PhysFrame) -> bool {
+        self.shared.contains_key(&frame)
+    }
+
+    /// Frames in the zeroed pool, and how many zeroed allocations
+    /// were served from it or had to zero on demand
+    pub fn zeroed_stats(&self) -> (usize, usize, usize) {
+        (self.zeroed.len(), self.zeroed_hits, self.zeroed_misses)
+    }
+
+    /// Allocate a frame filled with zeros
+    ///
+    /// taken from the zeroed pool when possible
+    pub fn allocate_zeroed_frame(&mut self) -> Option<PhysFrame> {
+        if let Some(frame) = self.zeroed.pop() {
+            self.used += 1;
+            self.zeroed_hits += 1;
+            return Some(frame);
+        }
+
+        let frame = self.allocate_frame()?;
+        zero_frame(frame);
+        self.zeroed_misses += 1;
+        Some(frame)
+    }
+
+    /// Zero up to `count` free frames into the zeroed pool
+    ///
+    /// returns how many frames were zeroed
+    pub fn refill_zeroed(&mut self, count: usize) -> usize {
+        let count = count.min(ZEROED_POOL_SIZE.saturating_sub(self.zeroed.len()));
+
+        for zeroed in 0..count {
+            let frame = match self.recycle.pop().or_else(|| self.frames.next()) {
+                Some(frame) => frame,
+                None => return zeroed,
+            };
+            zero_frame(frame);
+            self.zeroed.push(frame);
+        }
+
+        count
+    }
+}
+
+/// Zero a few free frames into the pool while the system is idle
+///
+/// returns false if there was nothing to do
+pub fn zero_idle_frames() -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_frame_alloc().is_some_and(|mut alloc| alloc.refill_zeroed(IDLE_ZERO_BATCH) > 0)
+    })
+}
+
+fn zero_frame(frame: PhysFrame) {
+    unsafe {
+        super::bulk::fill(
+            physical_to_virtual(frame.start_address().as_u64()) as *mut u8,
+            0,
+            PAGE_SIZE as usize,
+        );
+    }
 }
 
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
-        if let Some(frame) = self.recycle.pop() {
-            Some(frame)
-        } else {
-            self.used += 1;
-            self.frames.next()
-        }
+        let frame = self
+            .recycle
+            .pop()
+            .or_else(|| self.frames.next())
+            .or_else(|| self.zeroed.pop())?;
+        self.used += 1;
+        Some(frame)
     }
 }
 
 impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
     unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
-        // TODO: deallocate frame
+        // drop a reference to a shared frame, it is still in use
+        if let Some(refs) = self.shared.get_mut(&frame) {
+            *refs -= 1;
+            if *refs == 0 {
+                self.shared.remove(&frame);
+            }
+            return;
+        }
+
         self.used -= 1;
         self.recycle.push(frame);
     }
@@ -1,99 +1,23 @@
-use lazy_static::lazy_static;
-use x86_64::registers::segmentation::Segment;
 use x86_64::structures::gdt::{Descriptor, GlobalDescriptorTable, SegmentSelector};
 use x86_64::structures::tss::TaskStateSegment;
 use x86_64::VirtAddr;
 
-pub const DOUBLE_FAULT_IST_INDEX: u16 = 0;
-pub const SYSCALL_IST_INDEX: u16 = 1;
-pub const PAGE_FAULT_IST_INDEX: u16 = 2;
-pub const CONTEXT_SWITCH_IST_INDEX: u16 = 0;
+use crate::percpu;
 
-pub const IST_SIZES: [usize; 4] = [0x1000, 0x1000, 0x4000, 0x1000];
-
-lazy_static! {
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.privilege_stack_table[0] = {
-            const STACK_SIZE: usize = IST_SIZES[0];
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            let stack_start = VirtAddr::from_ptr(unsafe { STACK.as_ptr() });
-            let stack_end = stack_start + STACK_SIZE as u64;
-            info!(
-                "Privilege Stack  : 0x{:016x}-0x{:016x}",
-                stack_start.as_u64(),
-                stack_end.as_u64()
-            );
-            stack_end
-        };
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = IST_SIZES[1];
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            let stack_start = VirtAddr::from_ptr(unsafe { STACK.as_ptr() });
-            let stack_end = stack_start + STACK_SIZE as u64;
-            info!(
-                "Double Fault IST : 0x{:016x}-0x{:016x}",
-                stack_start.as_u64(),
-                stack_end.as_u64()
-            );
-            stack_end
-        };
-        tss.interrupt_stack_table[SYSCALL_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = IST_SIZES[2];
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            let stack_start = VirtAddr::from_ptr(unsafe { STACK.as_ptr() });
-            let stack_end = stack_start + STACK_SIZE as u64;
-            info!(
-                "Syscall IST      : 0x{:016x}-0x{:016x}",
-                stack_start.as_u64(),
-                stack_end.as_u64()
-            );
-            stack_end
-        };
-        tss.interrupt_stack_table[PAGE_FAULT_IST_INDEX as usize] = {
-            const STACK_SIZE: usize = IST_SIZES[3];
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            let stack_start = VirtAddr::from_ptr(unsafe { STACK.as_ptr() });
-            let stack_end = stack_start + STACK_SIZE as u64;
-            info!(
-                "Page Fault IST   : 0x{:016x}-0x{:016x}",
-                stack_start.as_u64(),
-                stack_end.as_u64()
-            );
-            stack_end
-        };
-        tss
-    };
-}
+pub const CONTEXT_SWITCH_IST_INDEX: u16 = 0;
+pub const DOUBLE_FAULT_IST_INDEX: u16 = 1;
+pub const PAGE_FAULT_IST_INDEX: u16 = 2;
 
-lazy_static! {
-    static ref GDT: (GlobalDescriptorTable, KernelSelectors, UserSelectors) = {
-        let mut gdt = GlobalDescriptorTable::new();
-        let code_selector = gdt.append(Descriptor::kernel_code_segment());
-        let data_selector = gdt.append(Descriptor::kernel_data_segment());
-        let tss_selector = gdt.append(Descriptor::tss_segment(&TSS));
-        let user_code_selector = gdt.append(Descriptor::user_code_segment());
-        let user_data_selector = gdt.append(Descriptor::user_data_segment());
-        (
-            gdt,
-            KernelSelectors {
-                code_selector,
-                data_selector,
-                tss_selector,
-            },
-            UserSelectors {
-                user_code_selector,
-                user_data_selector,
-            },
-        )
-    };
-}
+/// Sizes of the interrupt stacks, by IST index
+pub const IST_SIZES: [usize; 3] = [0x1000, 0x1000, 0x1000];
+/// Size of the stack used on entry from user mode before any process runs
+pub const PRIVILEGE_STACK_SIZE: usize = 0x1000;
 
-#[derive(Debug)]
-struct KernelSelectors {
-    code_selector: SegmentSelector,
-    data_selector: SegmentSelector,
-    tss_selector: SegmentSelector,
+#[derive(Clone, Copy, Debug)]
+pub struct KernelSelectors {
+    pub code_selector: SegmentSelector,
+    pub data_selector: SegmentSelector,
+    pub tss_selector: SegmentSelector,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -102,28 +26,39 @@ pub struct UserSelectors {
     pub user_data_selector: SegmentSelector,
 }
 
-pub fn init() {
-    use x86_64::instructions::segmentation::{CS, DS, ES, FS, GS, SS};
-    use x86_64::instructions::tables::load_tss;
-    use x86_64::PrivilegeLevel;
-
-    GDT.0.load();
-    unsafe {
-        CS::set_reg(GDT.1.code_selector);
-        DS::set_reg(GDT.1.data_selector);
-        SS::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
-        ES::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
-        FS::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
-        GS::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
-        load_tss(GDT.1.tss_selector);
-    }
-
-    let mut size = 0;
+/// Build the GDT of a cpu around its `tss`
+///
+/// # Safety
+///
+/// `tss` must stay valid for as long as the GDT is loaded.
+pub unsafe fn build(
+    tss: *const TaskStateSegment,
+) -> (GlobalDescriptorTable, KernelSelectors, UserSelectors) {
+    let mut gdt = GlobalDescriptorTable::new();
+    let code_selector = gdt.append(Descriptor::kernel_code_segment());
+    let data_selector = gdt.append(Descriptor::kernel_data_segment());
+    let tss_selector = gdt.append(Descriptor::tss_segment_unchecked(tss));
+    let user_code_selector = gdt.append(Descriptor::user_code_segment());
+    let user_data_selector = gdt.append(Descriptor::user_data_segment());
+    (
+        gdt,
+        KernelSelectors {
+            code_selector,
+            data_selector,
+            tss_selector,
+        },
+        UserSelectors {
+            user_code_selector,
+            user_data_selector,
+        },
+    )
+}
 
-    for &s in IST_SIZES.iter() {
-        size += s;
-    }
+pub fn init() {
+    let cpu = percpu::current();
+    cpu.init_gdt();
 
+    let size = IST_SIZES.iter().sum::<usize>() + PRIVILEGE_STACK_SIZE;
     let (size, unit) = crate::humanized_size(size as u64);
     info!("Kernel IST Size  : {:>7.*} {}", 3, size, unit);
 
@@ -131,5 +66,12 @@ pub fn init() {
 }
 
 pub fn get_user_selector() -> UserSelectors {
-    GDT.2
+    percpu::current().user_selectors()
+}
+
+/// Set the stack the CPU switches to when entering the kernel from user mode
+///
+/// NOTE: must be called with interrupts disabled
+pub fn set_privilege_stack(top: VirtAddr) {
+    percpu::current().set_privilege_stack(top)
 }
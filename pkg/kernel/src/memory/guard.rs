@@ -0,0 +1,77 @@
+//! Guard pages around kernel regions
+//!
+//! a guard page stays in the page table with its present bit cleared,
+//! so a stray access faults at once and the page fault handler can name
+//! the region it belongs to. The kernel half of the page table is shared
+//! by every process, so a guard applies whichever process is running.
+
+use alloc::collections::BTreeMap;
+use spin::Mutex;
+use x86_64::instructions::interrupts;
+use x86_64::structures::paging::mapper::TranslateResult;
+use x86_64::structures::paging::{Mapper, Page, PageTableFlags, Size4KiB, Translate};
+use x86_64::VirtAddr;
+
+use crate::proc::PageTableContext;
+
+struct Guard {
+    name: &'static str,
+    /// the flags of the page before it was guarded
+    flags: PageTableFlags,
+}
+
+/// Guarded pages by their start address
+static GUARDS: Mutex<BTreeMap<u64, Guard>> = Mutex::new(BTreeMap::new());
+
+/// Make `page` fault on any access, `name` is reported when it does
+///
+/// return false if the page is not mapped by a 4 KiB entry.
+pub fn protect(page: Page<Size4KiB>, name: &'static str) -> bool {
+    let mut mapper = PageTableContext::new().mapper();
+
+    let flags = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped { flags, .. } if !flags.contains(PageTableFlags::HUGE_PAGE) => {
+            flags
+        }
+        _ => return false,
+    };
+
+    interrupts::without_interrupts(|| {
+        match unsafe { mapper.update_flags(page, flags - PageTableFlags::PRESENT) } {
+            Ok(flush) => flush.flush(),
+            Err(_) => return false,
+        }
+        GUARDS
+            .lock()
+            .insert(page.start_address().as_u64(), Guard { name, flags });
+        true
+    })
+}
+
+/// Make a page guarded by `protect` accessible again
+pub fn unprotect(page: Page<Size4KiB>) {
+    let mut mapper = PageTableContext::new().mapper();
+
+    interrupts::without_interrupts(|| {
+        let guard = GUARDS.lock().remove(&page.start_address().as_u64());
+        if let Some(guard) = guard {
+            unsafe {
+                mapper
+                    .update_flags(page, guard.flags)
+                    .expect("guard page lost its mapping")
+                    .flush();
+            }
+        }
+    });
+}
+
+/// The name of the region guarded by the page containing `addr`
+///
+/// called from the page fault handler, gives up if the list is in use.
+pub fn lookup(addr: VirtAddr) -> Option<&'static str> {
+    let page = Page::<Size4KiB>::containing_address(addr);
+    GUARDS
+        .try_lock()?
+        .get(&page.start_address().as_u64())
+        .map(|guard| guard.name)
+}
@@ -1,8 +1,12 @@
 pub mod address;
 pub mod allocator;
+pub mod bulk;
 mod frames;
 
 pub mod gdt;
+pub mod guard;
+pub mod protect;
+pub mod uaccess;
 pub mod user;
 
 pub use address::*;
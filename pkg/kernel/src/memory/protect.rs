@@ -0,0 +1,136 @@
+//! Tighten the kernel mappings once it is initialized
+//!
+//! `.text` is left read and execute, `.rodata` read only, and every other
+//! kernel page, including the physical memory map, is made no-execute.
+//! The section bounds come from `kernel.ld`.
+
+use boot::BootInfo;
+use x86_64::structures::paging::mapper::{MappedFrame, TranslateResult};
+use x86_64::structures::paging::{
+    Mapper, Page, PageSize, PageTableFlags, Size1GiB, Size2MiB, Size4KiB, Translate,
+};
+use x86_64::VirtAddr;
+
+use super::PAGE_SIZE;
+use crate::proc::PageTableContext;
+
+extern "C" {
+    // defined in `kernel.ld`
+    static __text_start: u8;
+    static __text_end: u8;
+    static __rodata_start: u8;
+    static __rodata_end: u8;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Section {
+    Text,
+    ReadOnly,
+    Other,
+}
+
+impl Section {
+    fn of(page: Page<Size4KiB>) -> Self {
+        let addr = page.start_address();
+        let contains = |start: &u8, end: &u8| {
+            (VirtAddr::from_ptr(start)..VirtAddr::from_ptr(end)).contains(&addr)
+        };
+
+        unsafe {
+            if contains(&__text_start, &__text_end) {
+                Section::Text
+            } else if contains(&__rodata_start, &__rodata_end) {
+                Section::ReadOnly
+            } else {
+                Section::Other
+            }
+        }
+    }
+
+    fn apply(self, flags: PageTableFlags) -> PageTableFlags {
+        match self {
+            Section::Text => flags - PageTableFlags::WRITABLE - PageTableFlags::NO_EXECUTE,
+            Section::ReadOnly => (flags - PageTableFlags::WRITABLE) | PageTableFlags::NO_EXECUTE,
+            Section::Other => flags | PageTableFlags::NO_EXECUTE,
+        }
+    }
+}
+
+/// Remap the kernel image by section, and the physical memory map as no-execute
+///
+/// NOTE: must be called after the kernel is initialized, nothing may
+/// write to `.text` or `.rodata` afterwards.
+pub fn protect_kernel(boot_info: &'static BootInfo) {
+    let mut mapper = PageTableContext::new().mapper();
+    let mut counts = [0usize; 3];
+
+    for page in boot_info.kernel_pages.iter().flat_map(|range| *range) {
+        // guard pages are not present and keep their flags
+        let flags = match mapper.translate(page.start_address()) {
+            TranslateResult::Mapped { flags, .. } => flags,
+            _ => continue,
+        };
+
+        let section = Section::of(page);
+        counts[section as usize] += 1;
+
+        unsafe {
+            match mapper.update_flags(page, section.apply(flags)) {
+                Ok(flush) => flush.flush(),
+                Err(e) => warn!("Failed to protect kernel page {:?}: {:?}", page, e),
+            }
+        }
+    }
+
+    info!(
+        "Kernel Pages     : {} text, {} read only, {} other",
+        counts[Section::Text as usize],
+        counts[Section::ReadOnly as usize],
+        counts[Section::Other as usize]
+    );
+
+    let max_phys_addr = boot_info
+        .memory_map
+        .iter()
+        .map(|desc| desc.phys_start + desc.page_count * PAGE_SIZE)
+        .max()
+        .unwrap_or(0);
+    let offset = VirtAddr::new(*super::PHYSICAL_OFFSET.get().unwrap());
+    let end = offset + max_phys_addr;
+    let mut addr = offset;
+
+    // the bootloader maps it with 1GiB pages when the cpu has them
+    while addr < end {
+        let (frame, flags) = match mapper.translate(addr) {
+            TranslateResult::Mapped { frame, flags, .. } => (frame, flags),
+            _ => {
+                addr = addr.align_down(Size2MiB::SIZE) + Size2MiB::SIZE;
+                continue;
+            }
+        };
+
+        let flags = flags | PageTableFlags::NO_EXECUTE;
+        let size = unsafe {
+            match frame {
+                MappedFrame::Size1GiB(_) => {
+                    let page = Page::<Size1GiB>::containing_address(addr);
+                    mapper.update_flags(page, flags).map(|flush| flush.flush()).ok();
+                    Size1GiB::SIZE
+                }
+                MappedFrame::Size2MiB(_) => {
+                    let page = Page::<Size2MiB>::containing_address(addr);
+                    mapper.update_flags(page, flags).map(|flush| flush.flush()).ok();
+                    Size2MiB::SIZE
+                }
+                MappedFrame::Size4KiB(_) => {
+                    let page = Page::<Size4KiB>::containing_address(addr);
+                    mapper.update_flags(page, flags).map(|flush| flush.flush()).ok();
+                    Size4KiB::SIZE
+                }
+            }
+        };
+        addr = addr.align_down(size) + size;
+    }
+
+    info!("Kernel Text Protected.");
+}
@@ -0,0 +1,192 @@
+//! Copy data between kernel and user space
+//!
+//! The copy routine does not check the user pages beforehand. If it faults
+//! and the fault cannot be resolved, the page fault handler looks up the
+//! faulting instruction in the exception table and resumes at its fixup,
+//! so a bad user pointer ends up as `EFAULT` instead of a kernel panic.
+//!
+//! The page fault handler locks the current process, so these functions
+//! must not be called while holding it.
+
+use core::arch::global_asm;
+use core::mem::size_of_val;
+
+use syscall_def::EFAULT;
+
+use super::bulk::{self, Strategy};
+
+/// User buffers must end below the kernel half of the address space
+const USER_END: usize = 0x0000_8000_0000_0000;
+
+/// Maps an instruction that may fault to the code that handles the fault
+///
+/// both fields are offsets from their own address, so the table
+/// needs no relocation wherever the kernel is loaded.
+#[repr(C)]
+struct ExceptionEntry {
+    fault: i32,
+    fixup: i32,
+}
+
+impl ExceptionEntry {
+    fn fault(&self) -> u64 {
+        (&self.fault as *const i32 as i64 + self.fault as i64) as u64
+    }
+
+    fn fixup(&self) -> u64 {
+        (&self.fixup as *const i32 as i64 + self.fixup as i64) as u64
+    }
+}
+
+extern "C" {
+    // defined in `kernel.ld`
+    static __ex_table_start: ExceptionEntry;
+    static __ex_table_end: ExceptionEntry;
+
+    /// Copy `len` bytes and return the number of bytes left uncopied
+    fn __copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize;
+
+    /// `__copy_user` moving qwords, for cpus without fast `rep movsb`
+    fn __copy_user_qwords(dst: *mut u8, src: *const u8, len: usize) -> usize;
+}
+
+// on a fault `rep movsb` stops with rcx holding the bytes left
+global_asm!(
+    ".pushsection .text.copy_user, \"ax\"",
+    ".global __copy_user",
+    "__copy_user:",
+    "    mov rcx, rdx",
+    "2:  rep movsb",
+    "    xor eax, eax",
+    "    ret",
+    "3:  mov rax, rcx",
+    "    ret",
+    ".popsection",
+    ".pushsection .ex_table, \"a\"",
+    ".balign 4",
+    ".long 2b - .",
+    ".long 3b - .",
+    ".popsection",
+);
+
+// bytes up to an aligned destination, then qwords, then the tail,
+// a fault leaves rcx of the step it is in and rdx for the steps after it
+global_asm!(
+    ".pushsection .text.copy_user, \"ax\"",
+    ".global __copy_user_qwords",
+    "__copy_user_qwords:",
+    "    mov rcx, rdi",
+    "    neg rcx",
+    "    and ecx, 7",
+    "    cmp rcx, rdx",
+    "    cmova rcx, rdx",
+    "    sub rdx, rcx",
+    "8:  rep movsb",
+    "    mov rcx, rdx",
+    "    shr rcx, 3",
+    "    and edx, 7",
+    "4:  rep movsq",
+    "    mov rcx, rdx",
+    "5:  rep movsb",
+    "    xor eax, eax",
+    "    ret",
+    "6:  lea rax, [rdx + rcx * 8]",
+    "    ret",
+    "7:  mov rax, rcx",
+    "    ret",
+    "9:  lea rax, [rdx + rcx]",
+    "    ret",
+    ".popsection",
+    ".pushsection .ex_table, \"a\"",
+    ".balign 4",
+    ".long 8b - .",
+    ".long 9b - .",
+    ".long 4b - .",
+    ".long 6b - .",
+    ".long 5b - .",
+    ".long 7b - .",
+    ".popsection",
+);
+
+/// Copy with the routine `memory::bulk` picks for this cpu
+#[inline]
+unsafe fn copy_user(dst: *mut u8, src: *const u8, len: usize) -> usize {
+    match bulk::strategy() {
+        Strategy::Erms => __copy_user(dst, src, len),
+        Strategy::Qwords => __copy_user_qwords(dst, src, len),
+        Strategy::Bytes => {
+            for i in 0..len {
+                if __copy_user(dst.add(i), src.add(i), 1) != 0 {
+                    return len - i;
+                }
+            }
+            0
+        }
+    }
+}
+
+/// Find where to resume if the instruction at `ip` faults
+pub fn search_exception_table(ip: u64) -> Option<u64> {
+    let table = unsafe {
+        let start = &__ex_table_start as *const ExceptionEntry;
+        let end = &__ex_table_end as *const ExceptionEntry;
+        core::slice::from_raw_parts(start, end.offset_from(start) as usize)
+    };
+
+    table
+        .iter()
+        .find(|entry| entry.fault() == ip)
+        .map(|entry| entry.fixup())
+}
+
+#[inline]
+fn is_user_range(addr: usize, len: usize) -> bool {
+    addr.checked_add(len).is_some_and(|end| end <= USER_END)
+}
+
+/// Copy `dst.len()` bytes from user address `src` into `dst`
+///
+/// return `Err(EFAULT)` if any of the bytes is not readable
+pub fn copy_from_user(dst: &mut [u8], src: usize) -> Result<(), usize> {
+    if !is_user_range(src, dst.len()) {
+        return Err(EFAULT);
+    }
+
+    match unsafe { copy_user(dst.as_mut_ptr(), src as *const u8, dst.len()) } {
+        0 => Ok(()),
+        _ => Err(EFAULT),
+    }
+}
+
+/// Copy `src` to user address `dst`
+///
+/// return `Err(EFAULT)` if any of the bytes is not writable,
+/// the bytes before the bad one are still copied
+pub fn copy_to_user(dst: usize, src: &[u8]) -> Result<(), usize> {
+    if !is_user_range(dst, src.len()) {
+        return Err(EFAULT);
+    }
+
+    match unsafe { copy_user(dst as *mut u8, src.as_ptr(), src.len()) } {
+        0 => Ok(()),
+        _ => Err(EFAULT),
+    }
+}
+
+/// Copy a slice of plain values to user address `dst`
+///
+/// `T` must not contain padding bytes
+pub fn copy_slice_to_user<T: Copy>(dst: usize, src: &[T]) -> Result<(), usize> {
+    let bytes = unsafe { core::slice::from_raw_parts(src.as_ptr() as *const u8, size_of_val(src)) };
+    copy_to_user(dst, bytes)
+}
+
+/// Fill a slice of plain values from user address `src`
+///
+/// # Safety
+///
+/// any bit pattern must be a valid `T`
+pub unsafe fn copy_slice_from_user<T: Copy>(dst: &mut [T], src: usize) -> Result<(), usize> {
+    let bytes = core::slice::from_raw_parts_mut(dst.as_mut_ptr() as *mut u8, size_of_val(dst));
+    copy_from_user(bytes, src)
+}
@@ -0,0 +1,217 @@
+//! A debug monitor on the serial port
+//!
+//! entered by typing `Ctrl-]` then `m` at any time. It runs inside the
+//! serial interrupt, so the rest of the system stops until it resumes,
+//! and it reads the serial port directly instead of the input buffer.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::RangeInclusive;
+use x86_64::structures::paging::Translate;
+use x86_64::VirtAddr;
+
+use crate::drivers::serial::get_serial_for_sure;
+use crate::humanized_size_short;
+use crate::memory::{physical_to_virtual, PAGE_SIZE};
+use crate::proc::{self, paging, PageTableContext, ProcessId};
+
+/// First key of the sequence that enters the monitor, `Ctrl-]`
+pub const BREAK_PREFIX: u8 = 0x1d;
+/// Second key of the sequence that enters the monitor
+pub const BREAK_KEY: u8 = b'm';
+
+/// Most bytes dumped by a single read command
+const READ_MAX: usize = 1024;
+
+const HELP: &str = "\
+Monitor commands:
+    help                    | show this help
+    ps                      | show process list
+    pt <pid> [start [end]]  | show the mappings of a process, user half by default
+    rp <addr> [len]         | read physical memory
+    rv <pid> <addr> [len]   | read virtual memory of a process
+    switch                  | resume, switching to the next process
+    resume                  | resume the interrupted process
+numbers are decimal or 0x-prefixed hex
+";
+
+/// Run the monitor until it is told to resume
+///
+/// return true if the caller should switch to the next process
+pub fn enter() -> bool {
+    println!("\n[monitor] System stopped, type `help` for help.");
+
+    loop {
+        print!("monitor> ");
+        let line = read_line();
+        let args: Vec<&str> = line.split_whitespace().collect();
+
+        match args.as_slice() {
+            [] => continue,
+            ["help"] => print!("{}", HELP),
+            ["ps"] => proc::print_process_list(),
+            ["pt", pid, range @ ..] => match (parse_num(pid), parse_range(range)) {
+                (Some(pid), Some(range)) => print_mappings(ProcessId(pid as u16), range),
+                _ => println!("usage: pt <pid> [start [end]]"),
+            },
+            ["rp", addr, len @ ..] => match (parse_num(addr), parse_len(len)) {
+                (Some(addr), Some(len)) => read_physical(addr, len),
+                _ => println!("usage: rp <addr> [len]"),
+            },
+            ["rv", pid, addr, len @ ..] => {
+                match (parse_num(pid), parse_num(addr), parse_len(len)) {
+                    (Some(pid), Some(addr), Some(len)) => {
+                        read_virtual(ProcessId(pid as u16), addr, len)
+                    }
+                    _ => println!("usage: rv <pid> <addr> [len]"),
+                }
+            }
+            ["switch"] => {
+                println!("[monitor] Resuming with a context switch.");
+                return true;
+            }
+            ["resume"] => {
+                println!("[monitor] Resuming.");
+                return false;
+            }
+            [cmd, ..] => println!("unknown command: {}, type `help` for help", cmd),
+        }
+    }
+}
+
+/// Read a line by polling the serial port, interrupts are disabled here
+fn read_line() -> String {
+    let mut line = String::new();
+
+    loop {
+        let key = match get_serial_for_sure().receive() {
+            Some(key) => key,
+            None => {
+                core::hint::spin_loop();
+                continue;
+            }
+        };
+
+        match key {
+            b'\r' | b'\n' => {
+                println!();
+                return line;
+            }
+            0x08 | 0x7f => {
+                if line.pop().is_some() {
+                    print!("\x08 \x08");
+                }
+            }
+            0x20..=0x7e => {
+                line.push(key as char);
+                print!("{}", key as char);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn parse_num(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn parse_len(args: &[&str]) -> Option<usize> {
+    match args {
+        [] => Some(64),
+        [len] => parse_num(len).map(|len| (len as usize).min(READ_MAX)),
+        _ => None,
+    }
+}
+
+fn parse_range(args: &[&str]) -> Option<RangeInclusive<u64>> {
+    match args {
+        [] => Some(0..=paging::USER_LAST),
+        [start] => Some(parse_num(start)?..=paging::USER_LAST),
+        [start, end] => Some(parse_num(start)?..=parse_num(end)?.checked_sub(1)?),
+        _ => None,
+    }
+}
+
+fn print_mappings(pid: ProcessId, range: RangeInclusive<u64>) {
+    let mappings = match paging::dump(pid, range) {
+        Some(mappings) => mappings,
+        None => {
+            println!("no page table for #{}, or it is locked", pid);
+            return;
+        }
+    };
+
+    for m in mappings.iter() {
+        let (size, unit) = humanized_size_short(m.page_size);
+        println!(
+            "{:#014x}-{:#014x} -> {:#012x} {:>3}{} {:?}",
+            m.virt.as_u64(),
+            m.virt.as_u64() + m.len,
+            m.phys.as_u64(),
+            size,
+            unit,
+            m.flags
+        );
+    }
+}
+
+fn read_physical(addr: u64, len: usize) {
+    let virt = physical_to_virtual(addr);
+    hexdump(&PageTableContext::new(), virt, addr, len);
+}
+
+fn read_virtual(pid: ProcessId, addr: u64, len: usize) {
+    match proc::try_page_table(pid) {
+        Some(page_table) => hexdump(&page_table, addr, addr, len),
+        None => println!("no page table for #{}, or it is locked", pid),
+    }
+}
+
+/// Dump `len` bytes at `virt` of `page_table`, labelled from `label`
+///
+/// every page is translated first, so unmapped memory never faults
+fn hexdump(page_table: &PageTableContext, virt: u64, label: u64, len: usize) {
+    let mapper = page_table.mapper();
+    let mut buf = Vec::with_capacity(len);
+
+    while buf.len() < len {
+        let addr = virt + buf.len() as u64;
+        let phys = match VirtAddr::try_new(addr)
+            .ok()
+            .and_then(|addr| mapper.translate_addr(addr))
+        {
+            Some(phys) => phys,
+            None => break,
+        };
+
+        let in_page = (PAGE_SIZE - addr % PAGE_SIZE) as usize;
+        let count = in_page.min(len - buf.len());
+        let src = physical_to_virtual(phys.as_u64()) as *const u8;
+        buf.extend_from_slice(unsafe { core::slice::from_raw_parts(src, count) });
+    }
+
+    for (i, line) in buf.chunks(16).enumerate() {
+        let mut text = String::with_capacity(80);
+        for byte in line {
+            text += &format!("{:02x} ", byte);
+        }
+        for _ in line.len()..16 {
+            text += "   ";
+        }
+        text.extend(line.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        println!("{:#014x}: {}", label + i as u64 * 16, text);
+    }
+
+    if buf.len() < len {
+        println!("{:#014x}: not mapped", label + buf.len() as u64);
+    }
+}
@@ -0,0 +1,279 @@
+//! State owned by each cpu
+//!
+//! every cpu gets its own GDT, TSS, interrupt stacks, IDT and the slot for
+//! the process it runs, so another cpu can be brought up without sharing
+//! any of them. The kernel process is the idle loop of the boot cpu.
+
+use core::cell::UnsafeCell;
+use x86::cpuid::CpuId;
+use x86_64::instructions::tables::{sgdt, sidt};
+use x86_64::registers::segmentation::{Segment, CS, SS};
+use x86_64::structures::gdt::{GlobalDescriptorTable, SegmentSelector};
+use x86_64::structures::idt::InterruptDescriptorTable;
+use x86_64::structures::tss::TaskStateSegment;
+use x86_64::{PrivilegeLevel, VirtAddr};
+
+use crate::memory::gdt::{self, KernelSelectors, UserSelectors, IST_SIZES, PRIVILEGE_STACK_SIZE};
+use crate::proc::Processor;
+
+#[cfg(feature = "smp")]
+pub const MAX_CPU_COUNT: usize = 8;
+/// Only the boot cpu is run
+#[cfg(not(feature = "smp"))]
+pub const MAX_CPU_COUNT: usize = 1;
+
+/// The IST entries that must not share a stack
+///
+/// a double fault can come in the middle of any other handler.
+const DISTINCT_IST: [(u16, &str); 3] = [
+    (gdt::CONTEXT_SWITCH_IST_INDEX, "context switch"),
+    (gdt::DOUBLE_FAULT_IST_INDEX, "double fault"),
+    (gdt::PAGE_FAULT_IST_INDEX, "page fault"),
+];
+
+#[repr(C, align(16))]
+struct Stacks {
+    privilege: [u8; PRIVILEGE_STACK_SIZE],
+    context_switch: [u8; IST_SIZES[gdt::CONTEXT_SWITCH_IST_INDEX as usize]],
+    double_fault: [u8; IST_SIZES[gdt::DOUBLE_FAULT_IST_INDEX as usize]],
+    page_fault: [u8; IST_SIZES[gdt::PAGE_FAULT_IST_INDEX as usize]],
+}
+
+/// The bounds of a stack, `end` is its top
+#[derive(Clone, Copy, Debug)]
+struct StackRange {
+    start: VirtAddr,
+    end: VirtAddr,
+}
+
+impl StackRange {
+    fn of(stack: &[u8]) -> Self {
+        let start = VirtAddr::from_ptr(stack.as_ptr());
+        Self {
+            start,
+            end: start + stack.len() as u64,
+        }
+    }
+}
+
+pub struct PerCpu {
+    processor: Processor,
+    /// read by the cpu on every privilege change, and its privilege
+    /// stack is replaced on every context switch
+    tss: UnsafeCell<TaskStateSegment>,
+    stacks: UnsafeCell<Stacks>,
+    gdt: spin::Once<(GlobalDescriptorTable, KernelSelectors, UserSelectors)>,
+    idt: spin::Once<InterruptDescriptorTable>,
+}
+
+// each cpu only changes its own entry, with interrupts disabled
+unsafe impl Sync for PerCpu {}
+
+impl PerCpu {
+    const fn new() -> Self {
+        Self {
+            processor: Processor::new(),
+            tss: UnsafeCell::new(TaskStateSegment::new()),
+            stacks: UnsafeCell::new(Stacks {
+                privilege: [0; PRIVILEGE_STACK_SIZE],
+                context_switch: [0; IST_SIZES[gdt::CONTEXT_SWITCH_IST_INDEX as usize]],
+                double_fault: [0; IST_SIZES[gdt::DOUBLE_FAULT_IST_INDEX as usize]],
+                page_fault: [0; IST_SIZES[gdt::PAGE_FAULT_IST_INDEX as usize]],
+            }),
+            gdt: spin::Once::new(),
+            idt: spin::Once::new(),
+        }
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY: PerCpu = PerCpu::new();
+
+static CPUS: [PerCpu; MAX_CPU_COUNT] = [EMPTY; MAX_CPU_COUNT];
+
+/// The state of the cpu this runs on
+pub fn current() -> &'static PerCpu {
+    if !cfg!(feature = "smp") {
+        return &CPUS[0];
+    }
+
+    let cpuid = CpuId::new()
+        .get_feature_info()
+        .unwrap()
+        .initial_local_apic_id() as usize;
+
+    &CPUS[cpuid]
+}
+
+/// The state of every cpu, brought up or not
+pub fn cpus() -> &'static [PerCpu] {
+    &CPUS
+}
+
+impl PerCpu {
+    pub fn processor(&self) -> &Processor {
+        &self.processor
+    }
+
+    /// The stacks by IST index, and the privilege stack
+    fn stack_ranges(&self) -> ([StackRange; 3], StackRange) {
+        let stacks = unsafe { &*self.stacks.get() };
+        let mut ist = [StackRange::of(&[]); 3];
+        ist[gdt::CONTEXT_SWITCH_IST_INDEX as usize] = StackRange::of(&stacks.context_switch);
+        ist[gdt::DOUBLE_FAULT_IST_INDEX as usize] = StackRange::of(&stacks.double_fault);
+        ist[gdt::PAGE_FAULT_IST_INDEX as usize] = StackRange::of(&stacks.page_fault);
+        (ist, StackRange::of(&stacks.privilege))
+    }
+
+    /// Point the TSS at the stacks, then build and load the GDT
+    pub fn init_gdt(&'static self) {
+        let (ist, privilege) = self.stack_ranges();
+        let tss = unsafe { &mut *self.tss.get() };
+
+        for (index, stack) in ist.iter().enumerate() {
+            tss.interrupt_stack_table[index] = stack.end;
+            info!(
+                "IST {}            : 0x{:016x}-0x{:016x}",
+                index,
+                stack.start.as_u64(),
+                stack.end.as_u64()
+            );
+        }
+
+        tss.privilege_stack_table[0] = privilege.end;
+        info!(
+            "Privilege Stack  : 0x{:016x}-0x{:016x}",
+            privilege.start.as_u64(),
+            privilege.end.as_u64()
+        );
+
+        let (gdt, selectors, _) = self.gdt.call_once(|| unsafe { gdt::build(self.tss.get()) });
+        gdt.load();
+
+        use x86_64::instructions::segmentation::{DS, ES, FS, GS};
+        use x86_64::instructions::tables::load_tss;
+
+        unsafe {
+            CS::set_reg(selectors.code_selector);
+            DS::set_reg(selectors.data_selector);
+            SS::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
+            ES::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
+            FS::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
+            GS::set_reg(SegmentSelector::new(0, PrivilegeLevel::Ring0));
+            load_tss(selectors.tss_selector);
+        }
+    }
+
+    /// Build the IDT with `register` and load it
+    pub fn init_idt(&'static self, register: impl FnOnce(&mut InterruptDescriptorTable)) {
+        self.idt
+            .call_once(|| {
+                let mut idt = InterruptDescriptorTable::new();
+                register(&mut idt);
+                idt
+            })
+            .load();
+    }
+
+    pub fn user_selectors(&self) -> UserSelectors {
+        self.gdt.get().expect("GDT not initialized").2
+    }
+
+    /// Set the stack the CPU switches to when entering the kernel from user mode
+    ///
+    /// NOTE: must be called with interrupts disabled
+    pub fn set_privilege_stack(&self, top: VirtAddr) {
+        unsafe {
+            (*self.tss.get()).privilege_stack_table[0] = top;
+        }
+    }
+
+    /// Check the tables of this cpu once they are loaded
+    ///
+    /// panics with the first thing found wrong.
+    pub fn validate(&'static self) {
+        let (gdt, kernel, user) = self.gdt.get().expect("GDT not initialized");
+        let idt = self.idt.get().expect("IDT not initialized");
+        let tss = unsafe { &*self.tss.get() };
+        let (ist, privilege) = self.stack_ranges();
+
+        for (index, (size, stack)) in IST_SIZES.iter().zip(ist.iter()).enumerate() {
+            assert!(
+                *size > 0 && *size % 16 == 0,
+                "IST {} size {:#x} is not a positive multiple of 16",
+                index,
+                size
+            );
+            assert!(
+                stack.end.is_aligned(16u64),
+                "IST {} top {:#x} is not 16 byte aligned",
+                index,
+                stack.end.as_u64()
+            );
+            assert_eq!(
+                { tss.interrupt_stack_table }[index],
+                stack.end,
+                "TSS entry of IST {} does not point at its stack",
+                index
+            );
+        }
+
+        assert!(
+            privilege.end.is_aligned(16u64),
+            "privilege stack top {:#x} is not 16 byte aligned",
+            privilege.end.as_u64()
+        );
+
+        for (i, (a, a_name)) in DISTINCT_IST.iter().enumerate() {
+            assert!(
+                (*a as usize) < IST_SIZES.len(),
+                "{} IST index {} has no stack",
+                a_name,
+                a
+            );
+            for (b, b_name) in &DISTINCT_IST[i + 1..] {
+                assert_ne!(
+                    a, b,
+                    "{} and {} handlers share an IST stack",
+                    a_name, b_name
+                );
+            }
+        }
+
+        let gdtr = sgdt();
+        assert_eq!(
+            { gdtr.base },
+            VirtAddr::from_ptr(gdt as *const GlobalDescriptorTable),
+            "the loaded GDT is not the one of this cpu"
+        );
+        let idtr = sidt();
+        assert_eq!(
+            { idtr.base },
+            VirtAddr::from_ptr(idt as *const InterruptDescriptorTable),
+            "the loaded IDT is not the one of this cpu"
+        );
+
+        assert_eq!(
+            CS::get_reg(),
+            kernel.code_selector,
+            "CS is not the kernel code selector"
+        );
+        for (name, selector, rpl) in [
+            ("kernel code", kernel.code_selector, PrivilegeLevel::Ring0),
+            ("kernel data", kernel.data_selector, PrivilegeLevel::Ring0),
+            ("TSS", kernel.tss_selector, PrivilegeLevel::Ring0),
+            ("user code", user.user_code_selector, PrivilegeLevel::Ring3),
+            ("user data", user.user_data_selector, PrivilegeLevel::Ring3),
+        ] {
+            assert!(
+                selector.index() > 0 && selector.rpl() == rpl,
+                "{} selector {:?} should be a non-null selector with RPL {:?}",
+                name,
+                selector,
+                rpl
+            );
+        }
+
+        info!("Per-CPU Tables Validated.");
+    }
+}
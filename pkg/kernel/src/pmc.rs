@@ -0,0 +1,103 @@
+//! Fixed-function performance counters, instructions retired and cycles
+//!
+//! they count from boot on, in both user and kernel mode. A process is
+//! charged what they counted between its dispatch and its switch out, see
+//! `CpuTime`, so they are never written on a switch. Without architectural
+//! performance monitoring, as on QEMU without KVM, they read as zero.
+
+use core::ops::{Add, AddAssign};
+use x86::cpuid::CpuId;
+use x86_64::registers::model_specific::Msr;
+
+use crate::cpu::{self, Features};
+
+/// Instructions retired
+const IA32_FIXED_CTR0: u32 = 0x309;
+/// Core cycles while not halted
+const IA32_FIXED_CTR1: u32 = 0x30a;
+const IA32_FIXED_CTR_CTRL: u32 = 0x38d;
+const IA32_PERF_GLOBAL_CTRL: u32 = 0x38f;
+
+/// Count in ring 0 and ring 3, four bits for each fixed counter
+const FIXED_CTR_OS_USR: u64 = 0x33;
+/// The enable bits of the first two fixed counters
+const GLOBAL_FIXED_CTR01: u64 = 0b11 << 32;
+
+/// The bits the counters have, they wrap above
+static MASK: spin::Once<u64> = spin::Once::new();
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Counters {
+    pub instructions: u64,
+    pub cycles: u64,
+}
+
+impl Counters {
+    pub const ZERO: Counters = Counters {
+        instructions: 0,
+        cycles: 0,
+    };
+
+    /// What was counted from `earlier` to this, across a wrap
+    pub fn since(&self, earlier: &Counters) -> Counters {
+        let mask = MASK.get().copied().unwrap_or(u64::MAX);
+        Counters {
+            instructions: self.instructions.wrapping_sub(earlier.instructions) & mask,
+            cycles: self.cycles.wrapping_sub(earlier.cycles) & mask,
+        }
+    }
+}
+
+impl Add for Counters {
+    type Output = Counters;
+
+    fn add(self, other: Counters) -> Counters {
+        Counters {
+            instructions: self.instructions.saturating_add(other.instructions),
+            cycles: self.cycles.saturating_add(other.cycles),
+        }
+    }
+}
+
+impl AddAssign for Counters {
+    fn add_assign(&mut self, other: Counters) {
+        *self = *self + other;
+    }
+}
+
+pub fn init() {
+    if !cpu::has(Features::PERFMON) {
+        info!("Perf Counters    : not available");
+        return;
+    }
+
+    let width = CpuId::new()
+        .get_performance_monitoring_info()
+        .map_or(0, |info| info.fixed_function_counters_bit_width() as u32);
+    let mask = match width {
+        1..=63 => (1 << width) - 1,
+        _ => u64::MAX,
+    };
+
+    unsafe {
+        Msr::new(IA32_FIXED_CTR_CTRL).write(FIXED_CTR_OS_USR);
+        Msr::new(IA32_PERF_GLOBAL_CTRL).write(GLOBAL_FIXED_CTR01);
+    }
+    MASK.call_once(|| mask);
+
+    info!("Perf Counters    : instructions and cycles, {} bits", width);
+}
+
+/// The counters of the cpu this runs on, zero without them
+pub fn read() -> Counters {
+    if MASK.get().is_none() {
+        return Counters::default();
+    }
+
+    unsafe {
+        Counters {
+            instructions: Msr::new(IA32_FIXED_CTR0).read(),
+            cycles: Msr::new(IA32_FIXED_CTR1).read(),
+        }
+    }
+}
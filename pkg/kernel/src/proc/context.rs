@@ -2,11 +2,23 @@ use volatile::{access::ReadOnly, VolatileRef};
 use x86_64::{
     registers::rflags::RFlags,
     structures::{gdt::SegmentSelector, idt::InterruptStackFrameValue},
-    VirtAddr,
+    PrivilegeLevel, VirtAddr,
 };
 
 use crate::{memory::gdt::get_user_selector, RegistersValue};
 
+/// Length of the `int 0x80` instruction that enters a syscall
+const SYSCALL_INSN_LEN: u64 = 2;
+
+/// The flags a signal handler may change before it returns, the others are kept
+const USER_FLAGS: RFlags = RFlags::CARRY_FLAG
+    .union(RFlags::PARITY_FLAG)
+    .union(RFlags::AUXILIARY_CARRY_FLAG)
+    .union(RFlags::ZERO_FLAG)
+    .union(RFlags::SIGN_FLAG)
+    .union(RFlags::DIRECTION_FLAG)
+    .union(RFlags::OVERFLOW_FLAG);
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 pub struct ProcessContextValue {
@@ -36,6 +48,72 @@ impl ProcessContext {
         self.value.regs.rax = value;
     }
 
+    /// The arguments of the syscall being made
+    ///
+    /// in `rdi`, `rsi`, `rdx`, `r10`, `r8` and `r9`, the order of Linux.
+    #[inline]
+    pub fn syscall_args(&self) -> [usize; 6] {
+        let regs = &self.value.regs;
+        [regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9]
+    }
+
+    /// Pass four arguments to the entry point, in `rdi`, `rsi`, `rdx` and `rcx`
+    #[inline]
+    pub fn set_entry_args(&mut self, arg0: usize, arg1: usize, arg2: usize, arg3: usize) {
+        self.value.regs.rdi = arg0;
+        self.value.regs.rsi = arg1;
+        self.value.regs.rdx = arg2;
+        self.value.regs.rcx = arg3;
+    }
+
+    /// Point back at the `int 0x80` the process entered the kernel with
+    ///
+    /// so the syscall runs again when the process is resumed,
+    /// `rax` still holds its number as long as no result was set.
+    #[inline]
+    pub fn rewind_syscall(&mut self) {
+        self.value.stack_frame.instruction_pointer -= SYSCALL_INSN_LEN;
+    }
+
+    /// Check if the context returns to user mode
+    #[inline]
+    pub fn is_user(&self) -> bool {
+        self.value.stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3
+    }
+
+    #[inline]
+    pub fn set_instruction_pointer(&mut self, ip: VirtAddr) {
+        self.value.stack_frame.instruction_pointer = ip;
+    }
+
+    /// Call `handler(arg)`, `stack` pointing at the address it returns to
+    pub fn enter_handler(&mut self, handler: VirtAddr, stack: VirtAddr, arg: usize) {
+        self.value.regs.rdi = arg;
+        let frame = &mut self.value.stack_frame;
+        frame.instruction_pointer = handler;
+        frame.stack_pointer = stack;
+        frame.cpu_flags.remove(RFlags::DIRECTION_FLAG);
+    }
+
+    /// Go back to what a signal handler interrupted
+    ///
+    /// only the arithmetic flags of `rflags` are taken,
+    /// the segments stay those of user mode.
+    pub fn return_from_handler(
+        &mut self,
+        regs: RegistersValue,
+        rip: VirtAddr,
+        rsp: VirtAddr,
+        rflags: u64,
+    ) {
+        self.value.regs = regs;
+        let frame = &mut self.value.stack_frame;
+        frame.instruction_pointer = rip;
+        frame.stack_pointer = rsp;
+        frame.cpu_flags =
+            (frame.cpu_flags - USER_FLAGS) | (RFlags::from_bits_truncate(rflags) & USER_FLAGS);
+    }
+
     #[inline]
     pub fn set_stack_offset(&mut self, offset: u64) {
         self.value.stack_frame.stack_pointer += offset;
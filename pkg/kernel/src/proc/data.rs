@@ -1,22 +1,31 @@
 use super::*;
-use crate::resource::ResourceSet;
+use crate::fs::vfs::Inode;
+use crate::resource::{Resource, FileDescriptorTable};
 use alloc::collections::BTreeMap;
 use spin::RwLock;
+use signal::Signals;
 use sync::*;
+use syscall_def::{DEFAULT_UMASK, MODE_PERM_MASK};
 
 #[derive(Debug, Clone)]
 pub struct ProcessData {
     pub(super) env: Arc<RwLock<BTreeMap<String, String>>>,
-    pub(super) resources: Arc<RwLock<ResourceSet>>,
+    pub(super) resources: Arc<RwLock<FileDescriptorTable>>,
     pub(super) semaphores: Arc<RwLock<SemaphoreSet>>,
+    /// file mode bits masked off on creation, kept apart by fork
+    pub(super) umask: u16,
+    /// the handlers of the process and the signals pending on it
+    pub(super) signals: Signals,
 }
 
 impl Default for ProcessData {
     fn default() -> Self {
         Self {
             env: Arc::new(RwLock::new(BTreeMap::new())),
-            resources: Arc::new(RwLock::new(ResourceSet::default())),
+            resources: Arc::new(RwLock::new(FileDescriptorTable::default())),
             semaphores: Arc::new(RwLock::new(SemaphoreSet::default())),
+            umask: DEFAULT_UMASK,
+            signals: Signals::default(),
         }
     }
 }
@@ -26,6 +35,33 @@ impl ProcessData {
         Self::default()
     }
 
+    /// The data of a forked child, with its own fd table
+    ///
+    /// the fds refer to the same resources, but closing one in either
+    /// process no longer closes it in the other.
+    pub fn fork(&self) -> Self {
+        Self {
+            env: self.env.clone(),
+            resources: Arc::new(RwLock::new(self.resources.read().fork())),
+            semaphores: self.semaphores.clone(),
+            umask: self.umask,
+            signals: self.signals.fork(),
+        }
+    }
+
+    /// The data of a thread, sharing the fd table with the process
+    ///
+    /// the handlers are copied, signals are sent to each thread on its own.
+    pub fn thread(&self) -> Self {
+        Self {
+            env: self.env.clone(),
+            resources: self.resources.clone(),
+            semaphores: self.semaphores.clone(),
+            umask: self.umask,
+            signals: self.signals.fork(),
+        }
+    }
+
     pub fn read(&self, fd: u8, buf: &mut [u8]) -> isize {
         self.resources.read().read(fd, buf)
     }
@@ -34,6 +70,74 @@ impl ProcessData {
         self.resources.read().write(fd, buf)
     }
 
+    pub fn read_vectored(&self, fd: u8, bufs: &mut [&mut [u8]]) -> isize {
+        self.resources.read().read_vectored(fd, bufs)
+    }
+
+    pub fn write_vectored(&self, fd: u8, bufs: &[&[u8]]) -> isize {
+        self.resources.read().write_vectored(fd, bufs)
+    }
+
+    pub fn send_file(
+        &self,
+        out_fd: u8,
+        in_fd: u8,
+        offset: Option<usize>,
+        len: usize,
+    ) -> Result<usize, usize> {
+        self.resources.read().send_file(out_fd, in_fd, offset, len)
+    }
+
+    pub fn open(&self, res: Arc<dyn Resource>) -> Option<u8> {
+        self.resources.write().open(res)
+    }
+
+    pub fn close(&self, fd: u8) -> bool {
+        self.resources.write().close(fd)
+    }
+
+    pub fn dup2(&self, old: u8, new: u8) -> bool {
+        self.resources.write().dup2(old, new)
+    }
+
+    /// Queue `pid` on the pipe behind `fd` if the I/O would block
+    pub fn wait_fd(&self, fd: u8, pid: ProcessId, write: bool) -> bool {
+        self.resources.read().wait(fd, pid, write)
+    }
+
+    /// The events of `fd` ready for `poll`, see `ResourceSet::poll_events`
+    pub fn poll_fd(&self, fd: u8, events: u16, pid: ProcessId) -> (u16, bool) {
+        self.resources.read().poll_events(fd, events, pid)
+    }
+
+    pub fn share_fd(&self, fd: u8) -> Option<Arc<dyn Resource>> {
+        self.resources.read().share(fd)
+    }
+
+    pub fn flock_target(&self, fd: u8) -> Option<(usize, u64)> {
+        self.resources.read().flock_target(fd)
+    }
+
+    pub fn fd_stat(&self, fd: u8) -> Option<FdStat> {
+        self.resources.read().stat(fd)
+    }
+
+    pub fn seek(&self, fd: u8, offset: isize, whence: usize) -> Result<usize, usize> {
+        self.resources.read().seek(fd, offset, whence)
+    }
+
+    pub fn ioctl(&self, fd: u8, cmd: usize, arg: usize) -> Result<usize, usize> {
+        self.resources.read().ioctl(fd, cmd, arg)
+    }
+
+    pub fn file(&self, fd: u8) -> Result<(Arc<dyn Inode>, usize), usize> {
+        self.resources.read().file(fd)
+    }
+
+    pub fn set_fd_rate(&self, fd: u8, rate: usize) -> Option<usize> {
+        self.resources.read().set_rate(fd, rate)
+    }
+
     pub fn env(&self, key: &str) -> Option<String> {
         self.env.read().get(key).cloned()
     }
@@ -43,10 +147,46 @@ impl ProcessData {
         self
     }
 
+    pub fn umask(&self) -> u16 {
+        self.umask
+    }
+
+    /// Set the file mode creation mask, return the old one
+    pub fn set_umask(&mut self, mask: u16) -> u16 {
+        core::mem::replace(&mut self.umask, mask & MODE_PERM_MASK)
+    }
+
+    /// The mode a file created with `mode` gets
+    pub fn apply_umask(&self, mode: u16) -> u16 {
+        mode & MODE_PERM_MASK & !self.umask
+    }
+
+    pub fn signals(&self) -> &Signals {
+        &self.signals
+    }
+
+    pub fn signals_mut(&mut self) -> &mut Signals {
+        &mut self.signals
+    }
+
+    /// Use `res` as stdout instead of the console
+    pub fn set_stdout(self, res: Arc<dyn Resource>) -> Self {
+        self.resources.write().replace(1, res);
+        self
+    }
+
     pub fn sem_wait(&self, key: u32, pid: ProcessId) -> SemaphoreResult {
         self.semaphores.read().wait(key, pid)
     }
 
+    pub fn sem_try_wait(&self, key: u32) -> SemaphoreResult {
+        self.semaphores.read().try_wait(key)
+    }
+
+    pub fn sem_cancel_wait(&self, key: u32, pid: ProcessId) {
+        self.semaphores.read().cancel_wait(key, pid)
+    }
+
     pub fn sem_signal(&self, key: u32) -> SemaphoreResult {
         self.semaphores.read().signal(key)
     }
@@ -0,0 +1,93 @@
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+
+use super::{processor, KERNEL_PID};
+use crate::utils::sysctl::Tunable;
+
+// deterministic scheduling, enabled by `deterministic=on` on the kernel command line
+//
+// user processes are only preempted after a fixed number of syscalls,
+// never by the timer, so the same program runs with the same interleaving.
+// the kernel idle process is still switched out by the timer.
+
+/// Syscalls a process can make before it is switched out
+pub static SLICE: Tunable = Tunable::new("sched.det_slice", 16);
+/// Timer ticks a process can run without any syscall before it is
+/// switched out anyway, which breaks the determinism, 0 means never
+pub static MAX_TICKS: Tunable = Tunable::new("sched.det_max_ticks", 1000);
+
+/// Time reported to user space at boot, 2024-01-01 00:00:00 UTC
+const EPOCH_NANOS: u64 = 1_704_067_200_000_000_000;
+/// Time that passes on each syscall
+const NANOS_PER_SYSCALL: u64 = 1_000_000;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static SLICE_USED: AtomicUsize = AtomicUsize::new(0);
+static SLICE_TICKS: AtomicUsize = AtomicUsize::new(0);
+static SYSCALLS: AtomicU64 = AtomicU64::new(0);
+
+pub fn init() {
+    if crate::utils::cmdline::enabled("deterministic") {
+        ENABLED.store(true, Ordering::Relaxed);
+        info!("Deterministic scheduling enabled.");
+    }
+}
+
+#[inline]
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Account a finished syscall, switch process at the end of the slice
+///
+/// called with interrupts disabled at the end of every syscall
+pub fn on_syscall(context: &mut super::ProcessContext) {
+    if !enabled() {
+        return;
+    }
+
+    SYSCALLS.fetch_add(1, Ordering::Relaxed);
+
+    if SLICE_USED.fetch_add(1, Ordering::Relaxed) + 1 >= SLICE.get().max(1) {
+        end_slice();
+        super::switch(context);
+    }
+}
+
+/// Check if the timer should switch out the current process
+pub fn on_tick() -> bool {
+    if !enabled() || processor::current_pid() == KERNEL_PID {
+        return true;
+    }
+
+    let max = MAX_TICKS.get();
+    if max == 0 || SLICE_TICKS.fetch_add(1, Ordering::Relaxed) + 1 < max {
+        return false;
+    }
+
+    warn!(
+        "Process #{} made no syscall in {} ticks, preempted it.",
+        processor::current_pid(),
+        max
+    );
+    end_slice();
+    true
+}
+
+/// Start the next process on a fresh slice, so where it is switched out
+/// does not depend on the syscalls made before it, e.g. by another app
+pub fn restart_slice() {
+    end_slice();
+}
+
+fn end_slice() {
+    SLICE_USED.store(0, Ordering::Relaxed);
+    SLICE_TICKS.store(0, Ordering::Relaxed);
+    // input that arrived during the slice becomes visible in order
+    crate::drivers::input::release_deferred();
+}
+
+/// Virtual time in nanoseconds, advanced by syscalls instead of the clock
+pub fn now_nanos() -> i64 {
+    (EPOCH_NANOS + SYSCALLS.load(Ordering::Relaxed) * NANOS_PER_SYSCALL) as i64
+}
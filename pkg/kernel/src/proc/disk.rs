@@ -0,0 +1,71 @@
+//! Apps on the boot partition, under [`fs::APP_DIR`]
+//!
+//! the bootloader loads at most 16 apps, the others are read from the
+//! mounted partition on first use, then kept for the rest of the boot.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use arrayvec::ArrayString;
+use boot::manifest::MANIFEST_NAME;
+use boot::{App, AppInfo};
+use spin::Mutex;
+use xmas_elf::ElfFile;
+
+use crate::fs::{self, vfs, APP_DIR};
+
+static APPS: Mutex<BTreeMap<String, &'static App<'static>>> = Mutex::new(BTreeMap::new());
+
+/// Names of the apps on the boot partition
+pub fn names() -> Vec<String> {
+    vfs::read_dir(APP_DIR)
+        .map(|entries| {
+            entries
+                .into_iter()
+                .filter(|entry| entry.kind == fs::FileType::File && !entry.name.starts_with('.'))
+                .map(|entry| entry.name)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+pub fn find(name: &str) -> Option<&'static App<'static>> {
+    let mut apps = APPS.lock();
+    if let Some(app) = apps.get(name) {
+        return Some(app);
+    }
+
+    let app_name = ArrayString::<16>::from(name).ok()?;
+    let data = vfs::read_file(&format!("{}/{}", APP_DIR, name)).ok()?;
+
+    // the app is kept with what the manifest says, which must be read first
+    let manifest = match vfs::read_file(&format!("{}/{}", APP_DIR, MANIFEST_NAME)) {
+        Err(err) if err.is_pending() => return None,
+        manifest => manifest.ok(),
+    };
+    let info = manifest
+        .as_deref()
+        .and_then(|manifest| core::str::from_utf8(manifest).ok())
+        .and_then(|manifest| AppInfo::find(manifest, app_name))
+        .unwrap_or_else(|| AppInfo::new(app_name));
+
+    // checked as the bootloader does for the apps it loads
+    if !info.hash.is_empty() && !hash::matches_hex(&hash::sha256(&data), &info.hash) {
+        warn!("App {} does not match its sha256 in the manifest", name);
+        return None;
+    }
+    if let Err(err) = ElfFile::new(&data) {
+        warn!("App {} on disk is not a valid ELF: {}", name, err);
+        return None;
+    }
+    // kept for as long as the app may be spawned again
+    let data = data.leak();
+    let elf = ElfFile::new(data).unwrap();
+
+    info!("Loaded app {} from disk, {} bytes", name, data.len());
+    let app = Box::leak(Box::new(App::new(info, elf)));
+    apps.insert(name.to_string(), app);
+    Some(app)
+}
@@ -0,0 +1,96 @@
+//! The real-time class, earliest deadline first over reserved budgets
+//!
+//! a process joins it with a period and a budget: in every period it may
+//! run for up to its budget of cpu time, before any process of the
+//! feedback queues, and the one whose period ends first goes first. Once
+//! its budget is used up it is throttled, kept out of the ready queue
+//! until its next period starts. The budget is checked on timer ticks, so
+//! it may be overrun by up to a tick. A reservation is only admitted while
+//! all of them add up to at most `RT_UTIL_MAX` of the cpu, so every one
+//! is met and the feedback queues are left the rest. A forked child or a
+//! thread starts outside the class.
+
+use syscall_def::errno::*;
+
+use crate::utils::sysctl::Tunable;
+
+/// Percent of the cpu the reservations may add up to
+pub static RT_UTIL_MAX: Tunable = Tunable::new("sched.rt_util_max", 90);
+
+/// Parts of the cpu a reservation takes, out of `UTIL_SCALE`
+const UTIL_SCALE: u64 = 1_000_000;
+
+/// Where a process goes when it is ready
+pub enum SchedClass {
+    /// the level of the feedback queues
+    Normal(usize),
+    /// the real-time queue, by the deadline of its period
+    Realtime(i64),
+    /// nowhere until its next period starts at the deadline
+    Throttled(i64),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Reservation {
+    /// nanoseconds of each period
+    period: u64,
+    /// nanoseconds of cpu time in each period
+    budget: u64,
+    /// the monotonic time the current period ends at
+    deadline: i64,
+    /// cpu time the process had used when the current period started
+    cpu_at_start: u64,
+}
+
+impl Reservation {
+    /// A reservation whose first period starts at `now`
+    ///
+    /// `EINVAL` unless the budget is not zero and fits in the period.
+    pub fn new(period: u64, budget: u64, now: i64, cpu_used: u64) -> Result<Self, usize> {
+        if budget == 0 || budget > period || period > i64::MAX as u64 {
+            return Err(EINVAL);
+        }
+
+        Ok(Self {
+            period,
+            budget,
+            deadline: now.saturating_add(period as i64),
+            cpu_at_start: cpu_used,
+        })
+    }
+
+    #[inline]
+    pub fn deadline(&self) -> i64 {
+        self.deadline
+    }
+
+    /// Start the period `now` is in with a full budget, if the last one is over
+    pub fn refresh(&mut self, now: i64, cpu_used: u64) {
+        if now < self.deadline {
+            return;
+        }
+
+        let periods = (now - self.deadline) as u64 / self.period + 1;
+        self.deadline = self
+            .deadline
+            .saturating_add(periods.saturating_mul(self.period) as i64);
+        self.cpu_at_start = cpu_used;
+    }
+
+    /// Nanoseconds of the budget left in the current period
+    pub fn budget_left(&self, cpu_used: u64) -> u64 {
+        self.budget
+            .saturating_sub(cpu_used.saturating_sub(self.cpu_at_start))
+    }
+
+    /// Parts of the cpu it takes, out of `UTIL_SCALE`
+    pub fn utilization(&self) -> u64 {
+        (self.budget as u128 * UTIL_SCALE as u128 / self.period as u128) as u64
+    }
+}
+
+/// Check if `new` fits with reservations already taking `others` of the cpu
+pub fn admit(others: u64, new: &Reservation) -> bool {
+    let max = RT_UTIL_MAX.get().min(100) as u64 * UTIL_SCALE / 100;
+    others.saturating_add(new.utilization()) <= max
+}
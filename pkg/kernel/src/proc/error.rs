@@ -0,0 +1,53 @@
+use core::fmt;
+use syscall_def::{EAGAIN, EBADF, EBUSY, ENOENT, ENOEXEC};
+
+/// Why a process could not be spawned
+///
+/// kept free of allocations, so it can be created and logged anywhere
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnError {
+    /// no app list was given by the bootloader, nor a disk mounted
+    NoApps,
+    /// no app with the given name
+    NotFound,
+    /// the process creation limits are reached
+    Limited,
+    /// no trace was recorded for the app to replay
+    NoTrace,
+    /// the fd to use as stdout is not open
+    BadFd,
+    /// the app is being read from disk, the syscall runs again once it is
+    Pending,
+    /// the ELF of the app has a segment it may not load
+    InvalidElf,
+    /// other threads share the memory exec would replace
+    Threads,
+}
+
+impl SpawnError {
+    /// The errno reported to user space
+    pub fn errno(&self) -> usize {
+        match self {
+            Self::NoApps | Self::NotFound | Self::NoTrace => ENOENT,
+            Self::Limited | Self::Pending => EAGAIN,
+            Self::BadFd => EBADF,
+            Self::InvalidElf => ENOEXEC,
+            Self::Threads => EBUSY,
+        }
+    }
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::NoApps => "no app list",
+            Self::NotFound => "app not found",
+            Self::Limited => "process creation limit reached",
+            Self::NoTrace => "no recorded trace",
+            Self::Pending => "app is being read from disk",
+            Self::BadFd => "bad stdout fd",
+            Self::InvalidElf => "invalid ELF",
+            Self::Threads => "other threads share the memory",
+        })
+    }
+}
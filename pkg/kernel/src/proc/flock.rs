@@ -0,0 +1,154 @@
+//! Advisory whole-file locks, like `flock`
+//!
+//! a lock belongs to an open fd, so forked processes sharing the fd share
+//! the lock, and it is released when the last copy of the fd is closed.
+//! A lock that is released is handed to the waiters in order.
+
+use alloc::collections::{BTreeMap, BTreeSet, VecDeque};
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::ProcessId;
+
+pub enum FlockResult {
+    Ok,
+    /// the lock is held by others and the caller asked not to wait
+    WouldBlock,
+    /// the caller has to wait until the lock is handed to it
+    Block,
+}
+
+struct Waiter {
+    pid: ProcessId,
+    owner: u64,
+    exclusive: bool,
+}
+
+#[derive(Default)]
+struct NodeLock {
+    /// the fds holding the lock
+    holders: BTreeSet<u64>,
+    exclusive: bool,
+    waiters: VecDeque<Waiter>,
+}
+
+impl NodeLock {
+    fn can_take(&self, exclusive: bool) -> bool {
+        self.holders.is_empty() || (!exclusive && !self.exclusive)
+    }
+
+    /// Hand the lock to the waiters at the front that can take it
+    fn grant(&mut self) -> Vec<ProcessId> {
+        let mut granted = Vec::new();
+        while let Some(waiter) = self.waiters.front() {
+            // a shared waiter does not pass an exclusive one
+            if !self.can_take(waiter.exclusive) {
+                break;
+            }
+            let waiter = self.waiters.pop_front().unwrap();
+            self.holders.insert(waiter.owner);
+            self.exclusive = waiter.exclusive;
+            granted.push(waiter.pid);
+        }
+        granted
+    }
+
+    fn is_unused(&self) -> bool {
+        self.holders.is_empty() && self.waiters.is_empty()
+    }
+}
+
+/// Locks by the node they are taken on
+static LOCKS: Mutex<BTreeMap<usize, NodeLock>> = Mutex::new(BTreeMap::new());
+
+/// Take the lock on `node` for the fd `owner`
+///
+/// a lock already held by `owner` is converted, which is not atomic:
+/// it is released first, so others waiting may get it in between.
+/// Return the processes the released lock was handed to, to be woken.
+pub fn lock(
+    node: usize,
+    owner: u64,
+    exclusive: bool,
+    wait: Option<ProcessId>,
+) -> (FlockResult, Vec<ProcessId>) {
+    let mut locks = LOCKS.lock();
+    let lock = locks.entry(node).or_default();
+
+    let mut granted = Vec::new();
+    if lock.holders.remove(&owner) {
+        if lock.holders.is_empty() && lock.waiters.is_empty() {
+            lock.exclusive = exclusive;
+            lock.holders.insert(owner);
+            return (FlockResult::Ok, granted);
+        }
+        granted = lock.grant();
+    }
+
+    // do not pass those already waiting
+    if lock.waiters.is_empty() && lock.can_take(exclusive) {
+        lock.holders.insert(owner);
+        lock.exclusive = exclusive;
+        return (FlockResult::Ok, granted);
+    }
+
+    let result = match wait {
+        Some(pid) => {
+            lock.waiters.push_back(Waiter {
+                pid,
+                owner,
+                exclusive,
+            });
+            FlockResult::Block
+        }
+        None => FlockResult::WouldBlock,
+    };
+
+    if lock.is_unused() {
+        locks.remove(&node);
+    }
+
+    (result, granted)
+}
+
+/// Release the lock the fd `owner` holds on `node`
+///
+/// return the processes the lock was handed to, to be woken.
+pub fn unlock(node: usize, owner: u64) -> Vec<ProcessId> {
+    let mut locks = LOCKS.lock();
+    let Some(lock) = locks.get_mut(&node) else {
+        return Vec::new();
+    };
+
+    let granted = if lock.holders.remove(&owner) {
+        lock.grant()
+    } else {
+        Vec::new()
+    };
+
+    if lock.is_unused() {
+        locks.remove(&node);
+    }
+
+    granted
+}
+
+/// Stop waiting for any lock, e.g. when `pid` is killed
+///
+/// return the processes the locks were handed to, to be woken.
+pub fn cancel_waits(pid: ProcessId) -> Vec<ProcessId> {
+    let mut locks = LOCKS.lock();
+    let mut granted = Vec::new();
+
+    for lock in locks.values_mut() {
+        let before = lock.waiters.len();
+        lock.waiters.retain(|waiter| waiter.pid != pid);
+        // a waiter that left may have held back those behind it
+        if lock.waiters.len() != before {
+            granted.append(&mut lock.grant());
+        }
+    }
+
+    locks.retain(|_, lock| !lock.is_unused());
+    granted
+}
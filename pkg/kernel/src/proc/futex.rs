@@ -0,0 +1,46 @@
+//! Wait queues on words of user memory, like `futex`
+//!
+//! a word is told apart by its address and the page table it is in, so
+//! threads sharing memory wait on the same queue. Forked processes do not,
+//! even on a shared mapping. The word itself is only read by the kernel,
+//! userspace changes it with atomics and wakes the waiters after.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use spin::Mutex;
+
+use super::ProcessId;
+
+/// A word of user memory, by the frame of its page table and its address
+pub type FutexKey = (u64, usize);
+
+/// Processes waiting on each word, in the order they came
+static WAITERS: Mutex<BTreeMap<FutexKey, VecDeque<ProcessId>>> = Mutex::new(BTreeMap::new());
+
+pub fn wait(key: FutexKey, pid: ProcessId) {
+    WAITERS.lock().entry(key).or_default().push_back(pid);
+}
+
+/// Take up to `count` processes waiting on `key`, to be woken
+pub fn wake(key: FutexKey, count: usize) -> Vec<ProcessId> {
+    let mut waiters = WAITERS.lock();
+    let Some(queue) = waiters.get_mut(&key) else {
+        return Vec::new();
+    };
+
+    let count = count.min(queue.len());
+    let woken = queue.drain(..count).collect();
+    if queue.is_empty() {
+        waiters.remove(&key);
+    }
+    woken
+}
+
+/// Stop waiting on any word, e.g. when `pid` is killed
+pub fn cancel_wait(pid: ProcessId) {
+    let mut waiters = WAITERS.lock();
+    for queue in waiters.values_mut() {
+        queue.retain(|&waiter| waiter != pid);
+    }
+    waiters.retain(|_, queue| !queue.is_empty());
+}
@@ -0,0 +1,57 @@
+use core::sync::atomic::{AtomicU64, Ordering};
+use syscall_def::{SchedEvent, SCHED_HISTORY_LEN};
+
+/// Orders events across all processes
+static SEQ: AtomicU64 = AtomicU64::new(0);
+
+/// The last scheduling events of a process, in a ring
+pub struct SchedHistory {
+    events: [SchedEvent; SCHED_HISTORY_LEN],
+    /// where the next event goes
+    next: usize,
+    len: usize,
+}
+
+impl SchedHistory {
+    pub const fn new() -> Self {
+        Self {
+            events: [SchedEvent {
+                seq: 0,
+                tick: 0,
+                kind: 0,
+                arg: 0,
+            }; SCHED_HISTORY_LEN],
+            next: 0,
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, kind: u32, arg: u32) {
+        self.events[self.next] = SchedEvent {
+            seq: SEQ.fetch_add(1, Ordering::Relaxed),
+            tick: crate::interrupt::ticks(),
+            kind,
+            arg,
+        };
+        self.next = (self.next + 1) % SCHED_HISTORY_LEN;
+        self.len = (self.len + 1).min(SCHED_HISTORY_LEN);
+    }
+
+    /// Copy the latest events into `buf`, oldest first
+    pub fn copy_to(&self, buf: &mut [SchedEvent]) -> usize {
+        let count = self.len.min(buf.len());
+        let first = (self.next + SCHED_HISTORY_LEN - count) % SCHED_HISTORY_LEN;
+
+        for (i, event) in buf.iter_mut().take(count).enumerate() {
+            *event = self.events[(first + i) % SCHED_HISTORY_LEN];
+        }
+
+        count
+    }
+}
+
+impl Default for SchedHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,65 @@
+//! Apps the host shares through fw_cfg, see `xtask --share-apps`
+//!
+//! a shared app is read and parsed on first use, then kept for the rest
+//! of the boot: what fw_cfg holds cannot change while the guest runs.
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use arrayvec::ArrayString;
+use boot::{App, AppInfo};
+use spin::Mutex;
+use xmas_elf::ElfFile;
+
+use crate::fw_cfg::{self, APP_PREFIX};
+
+/// The manifest xtask writes beside the apps
+const MANIFEST_PATH: &str = "opt/ysos/app/.manifest";
+
+static APPS: Mutex<BTreeMap<String, &'static App<'static>>> = Mutex::new(BTreeMap::new());
+
+/// Names of the apps the host shares
+pub fn names() -> Vec<&'static str> {
+    fw_cfg::files()
+        .iter()
+        .filter_map(|file| file.name.strip_prefix(APP_PREFIX))
+        .filter(|name| !name.starts_with('.'))
+        .collect()
+}
+
+pub fn find(name: &str) -> Option<&'static App<'static>> {
+    let file = fw_cfg::find(&format!("{}{}", APP_PREFIX, name))?;
+
+    let mut apps = APPS.lock();
+    if let Some(app) = apps.get(name) {
+        return Some(app);
+    }
+
+    let app_name = ArrayString::<16>::from(name).ok()?;
+    let data = fw_cfg::read(file);
+    if let Err(err) = ElfFile::new(&data) {
+        warn!("Shared app {} is not a valid ELF: {}", name, err);
+        return None;
+    }
+    // kept for as long as the app may be spawned again
+    let data = data.leak();
+    let elf = ElfFile::new(data).unwrap();
+
+    let manifest = fw_cfg::find(MANIFEST_PATH).map(fw_cfg::read);
+    let info = manifest
+        .as_deref()
+        .and_then(|manifest| core::str::from_utf8(manifest).ok())
+        .and_then(|manifest| AppInfo::find(manifest, app_name))
+        .unwrap_or_else(|| AppInfo::new(app_name));
+
+    info!(
+        "Loaded app {} shared by the host, {} bytes",
+        name,
+        data.len()
+    );
+    let app = Box::leak(Box::new(App::new(info, elf)));
+    apps.insert(name.to_string(), app);
+    Some(app)
+}
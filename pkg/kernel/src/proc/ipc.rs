@@ -0,0 +1,141 @@
+//! Message queues, named by a key like semaphores
+//!
+//! a queue holds up to its capacity of byte messages, each at most its
+//! message size, and is there for any process until it is removed.
+//! Messages are received whole and in the order they were sent. A sender
+//! finding the queue full, or a receiver finding it empty, blocks until
+//! the queue changes and then runs its syscall again.
+
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use spin::Mutex;
+use syscall_def::errno::*;
+use syscall_def::{MQ_MAX_MESSAGES, MQ_MAX_MSG_SIZE, MQ_MAX_QUEUES};
+
+use super::ProcessId;
+
+struct MessageQueue {
+    messages: VecDeque<Vec<u8>>,
+    capacity: usize,
+    msg_size: usize,
+    /// processes blocked on the queue, woken by any change
+    waiting: Vec<ProcessId>,
+}
+
+impl MessageQueue {
+    fn wait(&mut self, pid: ProcessId) {
+        if !self.waiting.contains(&pid) {
+            self.waiting.push(pid);
+        }
+    }
+}
+
+/// What a send or receive did
+pub enum Transfer {
+    /// the result of the syscall, and the processes to wake for the change
+    Done(usize, Vec<ProcessId>),
+    /// the process waits on the queue, it runs the syscall again once woken
+    Block,
+}
+
+static QUEUES: Mutex<BTreeMap<u32, MessageQueue>> = Mutex::new(BTreeMap::new());
+
+/// Open queue `key`, creating it for `capacity` messages of `msg_size` bytes
+///
+/// return its message size, `EINVAL` if it already exists with less.
+pub fn open(key: u32, capacity: usize, msg_size: usize) -> Result<usize, usize> {
+    let mut queues = QUEUES.lock();
+    if let Some(queue) = queues.get(&key) {
+        return match msg_size <= queue.msg_size {
+            true => Ok(queue.msg_size),
+            false => Err(EINVAL),
+        };
+    }
+
+    if !(1..=MQ_MAX_MESSAGES).contains(&capacity) || !(1..=MQ_MAX_MSG_SIZE).contains(&msg_size) {
+        return Err(EINVAL);
+    }
+    if queues.len() >= MQ_MAX_QUEUES {
+        return Err(ENOSPC);
+    }
+
+    queues.insert(
+        key,
+        MessageQueue {
+            messages: VecDeque::with_capacity(capacity),
+            capacity,
+            msg_size,
+            waiting: Vec::new(),
+        },
+    );
+    Ok(msg_size)
+}
+
+/// Remove queue `key` and its messages, return the processes waiting on it
+pub fn remove(key: u32) -> Result<Vec<ProcessId>, usize> {
+    let queue = QUEUES.lock().remove(&key).ok_or(ENOENT)?;
+    Ok(queue.waiting)
+}
+
+/// Put `msg` at the back of queue `key`, or wait as `pid` if it is full
+pub fn send(key: u32, msg: &[u8], pid: ProcessId) -> Result<Transfer, usize> {
+    let mut queues = QUEUES.lock();
+    let queue = queues.get_mut(&key).ok_or(ENOENT)?;
+    if msg.len() > queue.msg_size {
+        return Err(EMSGSIZE);
+    }
+
+    if queue.messages.len() >= queue.capacity {
+        queue.wait(pid);
+        return Ok(Transfer::Block);
+    }
+
+    queue.messages.push_back(msg.to_vec());
+    Ok(Transfer::Done(0, core::mem::take(&mut queue.waiting)))
+}
+
+/// Take the message at the front of queue `key`, or wait as `pid` if empty
+///
+/// `deliver` copies the message out and returns its result, the message
+/// stays in the queue if it fails. `EMSGSIZE` if it is longer than `len`.
+pub fn receive(
+    key: u32,
+    len: usize,
+    pid: ProcessId,
+    deliver: impl FnOnce(&[u8]) -> Result<(), usize>,
+) -> Result<Transfer, usize> {
+    let mut queues = QUEUES.lock();
+    let queue = queues.get_mut(&key).ok_or(ENOENT)?;
+
+    let Some(msg) = queue.messages.front() else {
+        queue.wait(pid);
+        return Ok(Transfer::Block);
+    };
+    if msg.len() > len {
+        return Err(EMSGSIZE);
+    }
+
+    deliver(msg)?;
+    let len = queue.messages.pop_front().map_or(0, |msg| msg.len());
+    let waiting = core::mem::take(&mut queue.waiting);
+    Ok(Transfer::Done(len, waiting))
+}
+
+/// A line for each queue, as shown by `Syscall::Stat`
+pub fn summary() -> String {
+    let mut output = String::new();
+    for (key, queue) in QUEUES.lock().iter() {
+        output += format!(
+            "MsgQ   : {:#010x}, {}/{} messages of {} bytes, {} waiting\n",
+            key,
+            queue.messages.len(),
+            queue.capacity,
+            queue.msg_size,
+            queue.waiting.len()
+        )
+        .as_str();
+    }
+    output
+}
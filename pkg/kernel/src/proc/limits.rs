@@ -0,0 +1,119 @@
+use crate::pmc::{self, Counters};
+use crate::utils::sysctl::Tunable;
+
+// limits on process creation, 0 means unlimited
+// they keep a fork bomb from taking the whole system down
+
+/// Processes a single process can create per second
+pub static FORK_RATE: Tunable = Tunable::new("proc.fork_rate", 32);
+/// Processes that can be created per second in total
+pub static FORK_RATE_GLOBAL: Tunable = Tunable::new("proc.fork_rate_global", 128);
+/// Live descendants a single process can have
+pub static MAX_DESCENDANTS: Tunable = Tunable::new("proc.max_descendants", 64);
+/// Live processes in total
+pub static MAX_PROCESSES: Tunable = Tunable::new("proc.max_processes", 256);
+
+/// Creation counter over a one second window
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SpawnRate {
+    second: i64,
+    count: usize,
+}
+
+impl SpawnRate {
+    pub const fn new() -> Self {
+        Self {
+            second: 0,
+            count: 0,
+        }
+    }
+
+    /// Check if one more creation at `now` stays within `limit`
+    pub fn allows(&self, now: i64, limit: usize) -> bool {
+        limit == 0 || self.second != now || self.count < limit
+    }
+
+    /// Record a creation at `now`
+    pub fn record(&mut self, now: i64) {
+        if self.second != now {
+            self.second = now;
+            self.count = 0;
+        }
+        self.count += 1;
+    }
+}
+
+/// Cpu time used by a process and its limit, in nanoseconds
+///
+/// time is counted from when the process is dispatched until it is
+/// switched out, so blocked and ready time is not charged. So are the
+/// performance counters.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CpuTime {
+    used: u64,
+    /// when the process was last dispatched, 0 while it is not running
+    since: i64,
+    /// 0 means unlimited
+    limit: u64,
+    /// the performance counters charged
+    counters: Counters,
+    /// the counters of the cpu when the process was last dispatched
+    counters_since: Counters,
+}
+
+impl CpuTime {
+    /// A fresh account, keeping the limit like `RLIMIT_CPU` across fork
+    pub const fn with_limit(limit: u64) -> Self {
+        Self {
+            used: 0,
+            since: 0,
+            limit,
+            counters: Counters::ZERO,
+            counters_since: Counters::ZERO,
+        }
+    }
+
+    pub fn start(&mut self, now: i64) {
+        self.since = now;
+        self.counters_since = pmc::read();
+    }
+
+    pub fn stop(&mut self, now: i64) {
+        if self.since != 0 {
+            self.used += now.saturating_sub(self.since).max(0) as u64;
+            self.since = 0;
+            self.counters += pmc::read().since(&self.counters_since);
+        }
+    }
+
+    /// Time used up to `now`, including the current run
+    pub fn used(&self, now: i64) -> u64 {
+        match self.since {
+            0 => self.used,
+            since => self.used + now.saturating_sub(since).max(0) as u64,
+        }
+    }
+
+    /// The performance counters charged, including the current run if it
+    /// is on this cpu
+    pub fn counters(&self) -> Counters {
+        match self.since {
+            0 => self.counters,
+            _ => self.counters + pmc::read().since(&self.counters_since),
+        }
+    }
+
+    #[inline]
+    pub fn limit(&self) -> u64 {
+        self.limit
+    }
+
+    /// Set a new limit and return the old one
+    pub fn set_limit(&mut self, limit: u64) -> u64 {
+        core::mem::replace(&mut self.limit, limit)
+    }
+
+    pub fn exceeded(&self, now: i64) -> bool {
+        self.limit != 0 && self.used(now) >= self.limit
+    }
+}
@@ -1,5 +1,3 @@
-use alloc::collections::BTreeSet;
-
 use super::*;
 use crate::{
     memory::{
@@ -8,10 +6,22 @@ use crate::{
         user::{USER_ALLOCATOR, USER_HEAP_SIZE},
         PAGE_SIZE,
     },
-    utils::humanized_size,
+    utils::{clock, humanized_size, sysctl::Tunable},
+};
+use alloc::{
+    collections::{BTreeMap, BTreeSet},
+    format,
+    sync::Weak,
 };
-use alloc::{collections::BTreeMap, collections::VecDeque, format, sync::Weak};
+use limits::*;
+use edf::{Reservation, SchedClass};
+use mlfq::{ReadyQueues, BOOST_TICKS};
 use spin::{Mutex, RwLock};
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicUsize, Ordering};
+use syscall_def::{
+    DEFAULT_UMASK, EBUSY, ECHILD, ETIMEDOUT, SCHED_DISPATCH, SCHED_ENQUEUE, SCHED_LEVEL_RT, SCHED_STOP,
+    SCHED_THROTTLE, SIGCHLD, SIG_DFL, SIG_IGN, WAIT_RUNNING, WAIT_STOPPED,
+};
 
 pub static PROCESS_MANAGER: spin::Once<ProcessManager> = spin::Once::new();
 
@@ -28,9 +38,30 @@ pub fn get_process_manager() -> &'static ProcessManager {
 
 pub struct ProcessManager {
     processes: RwLock<BTreeMap<ProcessId, Arc<Process>>>,
-    ready_queue: Mutex<VecDeque<ProcessId>>,
+    ready_queue: Mutex<ReadyQueues>,
+    /// timer ticks since every process was boosted to its priority
+    since_boost: AtomicUsize,
     app_list: boot::AppListRef,
-    wait_queue: Mutex<BTreeMap<ProcessId, BTreeSet<ProcessId>>>,
+    /// the waiters of each process, and whether they are told when it stops
+    wait_queue: Mutex<BTreeMap<ProcessId, BTreeMap<ProcessId, bool>>>,
+    /// the processes waiting for any of their children to exit
+    any_waiters: Mutex<BTreeSet<ProcessId>>,
+    /// reaped processes still current on a cpu, by the index of the cpu,
+    /// dropped once it switches away from them
+    reapable: Mutex<Vec<(ProcessId, usize)>>,
+    /// the sleeping processes by the monotonic time they wake at
+    sleep_queue: Mutex<BTreeSet<(i64, ProcessId)>>,
+    /// the semaphore each process in the sleep queue waits on, if any
+    sem_timeouts: Mutex<BTreeMap<ProcessId, u32>>,
+    /// the processes in the sleep queue polling fds, run again at the deadline
+    poll_timeouts: Mutex<BTreeSet<ProcessId>>,
+    /// the real-time processes out of budget, by when their next period starts
+    throttled: Mutex<BTreeSet<(i64, ProcessId)>>,
+    spawn_rate: Mutex<SpawnRate>,
+    /// the process waited for to stop or exit, which Ctrl+Z stops, 0 for none
+    foreground: AtomicU16,
+    /// Ctrl+Z was pressed, the foreground process is stopped at the next switch
+    stop_requested: AtomicBool,
 }
 
 impl ProcessManager {
@@ -41,8 +72,18 @@ impl ProcessManager {
         Self {
             processes: RwLock::new(processes),
             app_list,
-            ready_queue: Mutex::new(VecDeque::new()),
+            ready_queue: Mutex::new(ReadyQueues::default()),
+            since_boost: AtomicUsize::new(0),
             wait_queue: Mutex::new(BTreeMap::new()),
+            any_waiters: Mutex::new(BTreeSet::new()),
+            reapable: Mutex::new(Vec::new()),
+            sleep_queue: Mutex::new(BTreeSet::new()),
+            sem_timeouts: Mutex::new(BTreeMap::new()),
+            poll_timeouts: Mutex::new(BTreeSet::new()),
+            throttled: Mutex::new(BTreeSet::new()),
+            spawn_rate: Mutex::new(SpawnRate::new()),
+            foreground: AtomicU16::new(0),
+            stop_requested: AtomicBool::new(false),
         }
     }
 
@@ -50,9 +91,42 @@ impl ProcessManager {
         self.app_list
     }
 
-    #[inline]
+    /// Queue `pid` on the level it is on, or by its deadline if it is
+    /// real-time, unless it is throttled until its next period
     pub fn push_ready(&self, pid: ProcessId) {
-        self.ready_queue.lock().push_back(pid);
+        let class = self
+            .get_proc(&pid)
+            .map_or(SchedClass::Normal(0), |proc| proc.write().sched_class());
+        match class {
+            SchedClass::Normal(level) => {
+                self.record_sched(pid, SCHED_ENQUEUE, level as u32);
+                self.ready_queue.lock().push(pid, level);
+            }
+            SchedClass::Realtime(deadline) => {
+                self.record_sched(pid, SCHED_ENQUEUE, SCHED_LEVEL_RT);
+                self.ready_queue.lock().push_realtime(pid, deadline);
+            }
+            SchedClass::Throttled(release) => {
+                self.record_sched(pid, SCHED_THROTTLE, 0);
+                self.throttled.lock().insert((release, pid));
+                return;
+            }
+        }
+        crate::interrupt::kick_timer();
+    }
+
+    /// Check if any process is waiting to run, true if unsure
+    pub fn has_ready(&self) -> bool {
+        self.ready_queue
+            .try_lock()
+            .is_none_or(|queue| !queue.is_empty())
+    }
+
+    /// Record a scheduling event of `pid`
+    pub fn record_sched(&self, pid: ProcessId, kind: u32, arg: u32) {
+        if let Some(proc) = self.get_proc(&pid) {
+            proc.record_sched(kind, arg);
+        }
     }
 
     #[inline]
@@ -61,57 +135,432 @@ impl ProcessManager {
     }
 
     #[inline]
-    fn get_proc(&self, pid: &ProcessId) -> Option<Arc<Process>> {
+    pub(super) fn get_proc(&self, pid: &ProcessId) -> Option<Arc<Process>> {
         self.processes.read().get(pid).cloned()
     }
 
+    /// Like `get_proc`, but gives up if the process list is locked
+    #[inline]
+    pub(super) fn try_get_proc(&self, pid: &ProcessId) -> Option<Arc<Process>> {
+        self.processes.try_read()?.get(pid).cloned()
+    }
+
     pub fn current(&self) -> Arc<Process> {
         self.get_proc(&processor::current_pid())
             .expect("No current process")
     }
 
-    pub fn wait_pid(&self, pid: ProcessId) -> Option<isize> {
-        if let Some(ret) = self.get_ret(pid) {
+    /// The exit code of `pid`, or `WAIT_STOPPED` if it is stopped and
+    /// `untraced` is set. Otherwise `WAIT_RUNNING` if `nohang` is set, or
+    /// the current process is queued to be woken with it, and `pid` is in
+    /// the foreground if `untraced` is set.
+    ///
+    /// `-ECHILD` if there is no such process, or it was reaped already.
+    pub fn wait_pid(&self, pid: ProcessId, untraced: bool, nohang: bool) -> Option<isize> {
+        if let Some(ret) = self.collect(pid) {
             return Some(ret);
         };
+        if self.get_proc(&pid).is_none() {
+            return Some(-(ECHILD as isize));
+        }
+
+        let stopped = || self.get_proc(&pid).is_some_and(|p| p.read().is_stopped());
+        if untraced && stopped() {
+            return Some(WAIT_STOPPED);
+        }
+        if nohang {
+            return Some(WAIT_RUNNING);
+        }
 
         // push the current process to the wait queue
         let mut wait_queue = self.wait_queue.lock();
         let entry = wait_queue.entry(pid).or_default();
-        entry.insert(processor::current_pid());
+        entry.insert(processor::current_pid(), untraced);
+
+        if untraced {
+            self.foreground.store(pid.0, Ordering::Relaxed);
+        }
 
         None
     }
 
+    /// Stop `pid` at its next switch, and wake the processes waiting
+    /// for it to stop
+    ///
+    /// return false if there is no such process alive
+    pub fn suspend(&self, pid: ProcessId) -> bool {
+        let proc = match self.get_proc(&pid) {
+            Some(proc) if proc.read().status() != ProgramStatus::Dead => proc,
+            _ => return false,
+        };
+
+        proc.write().stop();
+        proc.record_sched(SCHED_STOP, 0);
+        let _ = self
+            .foreground
+            .compare_exchange(pid.0, 0, Ordering::Relaxed, Ordering::Relaxed);
+
+        let told: Vec<ProcessId> = match self.wait_queue.lock().get_mut(&pid) {
+            Some(waiters) => {
+                let told = waiters
+                    .iter()
+                    .filter(|(_, untraced)| **untraced)
+                    .map(|(waiter, _)| *waiter)
+                    .collect();
+                waiters.retain(|_, untraced| !*untraced);
+                told
+            }
+            None => Vec::new(),
+        };
+
+        for waiter in told {
+            self.wake_up(waiter, WAIT_STOPPED);
+        }
+
+        true
+    }
+
+    /// Let stopped process `pid` run again
+    ///
+    /// return false if there is no such process alive
+    pub fn resume(&self, pid: ProcessId) -> bool {
+        let proc = match self.get_proc(&pid) {
+            Some(proc) if proc.read().status() != ProgramStatus::Dead => proc,
+            _ => return false,
+        };
+
+        let parked = proc.write().cont();
+        if parked {
+            self.push_ready(pid);
+        }
+
+        true
+    }
+
+    /// The handler `pid` has for `sig`, `None` if it is not alive
+    pub fn signal_handler(&self, pid: ProcessId, sig: usize) -> Option<usize> {
+        let proc = self.get_proc(&pid)?;
+        let inner = proc.read();
+        if inner.status() == ProgramStatus::Dead {
+            return None;
+        }
+        Some(inner.signals().handler(sig))
+    }
+
+    /// Check if `pid` has a handler of its own for `sig`
+    pub fn catches(&self, pid: ProcessId, sig: usize) -> bool {
+        self.signal_handler(pid, sig)
+            .is_some_and(|handler| handler != SIG_DFL && handler != SIG_IGN)
+    }
+
+    /// Leave `sig` pending on `pid`, until it next returns to user mode
+    pub fn post_signal(&self, pid: ProcessId, sig: usize) {
+        if let Some(proc) = self.get_proc(&pid) {
+            let mut inner = proc.write();
+            if inner.status() != ProgramStatus::Dead {
+                inner.signals_mut().post(sig);
+            }
+        }
+    }
+
+    /// Take the next signal pending on `pid` to act on, with its handler
+    pub fn take_signal(&self, pid: ProcessId) -> Option<(usize, usize)> {
+        let proc = self.get_proc(&pid)?;
+        let mut inner = proc.write();
+        if inner.status() == ProgramStatus::Dead {
+            return None;
+        }
+        let sig = inner.signals_mut().take()?;
+        Some((sig, inner.signals().handler(sig)))
+    }
+
+    /// Ask to stop the foreground process, for Ctrl+Z
+    ///
+    /// return false if there is none. It is stopped at the next switch,
+    /// where the scheduler runs anyway, so it is safe in any interrupt.
+    pub fn request_stop_foreground(&self) -> bool {
+        if self.foreground.load(Ordering::Relaxed) == 0 {
+            return false;
+        }
+
+        self.stop_requested.store(true, Ordering::Relaxed);
+        crate::interrupt::kick_timer();
+        true
+    }
+
+    /// Stop the foreground process if Ctrl+Z was pressed since the last switch
+    pub fn apply_stop_request(&self) {
+        if !self.stop_requested.swap(false, Ordering::Relaxed) {
+            return;
+        }
+
+        match self.foreground.load(Ordering::Relaxed) {
+            0 => (),
+            pid => {
+                self.suspend(ProcessId(pid));
+            }
+        }
+    }
+
+    /// Check the creation limits for the current process,
+    /// and count the new process if it is allowed
+    ///
+    /// the kernel is only bound by the global limits
+    pub fn allow_new_process(&self) -> bool {
+        let current = self.current();
+        // in seconds of the monotonic clock, so setting the time does not reset the rates
+        let now = clock::monotonic_nanos() / 1_000_000_000;
+
+        // live descendants of every process, including itself
+        let mut subtree = BTreeMap::<ProcessId, usize>::new();
+        for proc in self.processes.read().values() {
+            if proc.read().status() == ProgramStatus::Dead {
+                continue;
+            }
+            let mut next = Some(proc.clone());
+            while let Some(proc) = next {
+                *subtree.entry(proc.pid()).or_default() += 1;
+                next = proc.read().parent();
+            }
+        }
+
+        let exceeds = |limit: &Tunable, value: usize| limit.get() != 0 && value >= limit.get();
+
+        let alive = subtree.get(&KERNEL_PID).copied().unwrap_or_default();
+        if exceeds(&MAX_PROCESSES, alive) {
+            return self.reject_new_process(&current, &MAX_PROCESSES);
+        }
+
+        let mut global = self.spawn_rate.lock();
+        if !global.allows(now, FORK_RATE_GLOBAL.get()) {
+            return self.reject_new_process(&current, &FORK_RATE_GLOBAL);
+        }
+
+        if current.pid() != KERNEL_PID {
+            if !current.write().spawn_rate().allows(now, FORK_RATE.get()) {
+                return self.reject_new_process(&current, &FORK_RATE);
+            }
+
+            // the new process is a descendant of every ancestor but the kernel
+            let mut next = Some(current.clone());
+            while let Some(proc) = next.filter(|p| p.pid() != KERNEL_PID) {
+                let descendants = subtree.get(&proc.pid()).copied().unwrap_or(1) - 1;
+                if exceeds(&MAX_DESCENDANTS, descendants) {
+                    return self.reject_new_process(&proc, &MAX_DESCENDANTS);
+                }
+                next = proc.read().parent();
+            }
+        }
+
+        global.record(now);
+        current.write().spawn_rate().record(now);
+
+        true
+    }
+
+    fn reject_new_process(&self, proc: &Arc<Process>, limit: &Tunable) -> bool {
+        warn!(
+            "Process {}#{} hit {} = {}, no more processes are created.",
+            proc.read().name(),
+            proc.pid(),
+            limit.name(),
+            limit.get()
+        );
+        false
+    }
+
     pub(super) fn get_ret(&self, pid: ProcessId) -> Option<isize> {
         self.get_proc(&pid).and_then(|p| p.read().exit_code())
     }
 
+    /// Take the exit code of `pid` and reap it, if it has exited
+    pub(super) fn collect(&self, pid: ProcessId) -> Option<isize> {
+        let ret = self.get_ret(pid)?;
+        self.reap(pid);
+        Some(ret)
+    }
+
+    /// The pid and exit code of a child of `pid` that has exited, reaping
+    /// it. Otherwise `pid` is queued to be woken when one exits, unless
+    /// `nohang` is set.
+    ///
+    /// `ECHILD` if it has no children, threads count as children.
+    pub fn wait_any(
+        &self,
+        pid: ProcessId,
+        nohang: bool,
+    ) -> Result<Option<(ProcessId, isize)>, usize> {
+        let proc = self.get_proc(&pid).ok_or(ECHILD)?;
+        let children = proc.read().children().to_vec();
+        if children.is_empty() {
+            return Err(ECHILD);
+        }
+
+        let exited = children
+            .iter()
+            .find_map(|child| Some((child.pid(), child.read().exit_code()?)));
+        if let Some((child, ret)) = exited {
+            self.reap(child);
+            return Ok(Some((child, ret)));
+        }
+
+        if !nohang {
+            self.any_waiters.lock().insert(pid);
+        }
+        Ok(None)
+    }
+
+    /// Detach dead process `pid` from its parent and drop it, once no cpu
+    /// runs it anymore
+    ///
+    /// a process is reaped when its exit code is taken, or as it exits if
+    /// nobody is left to take it.
+    fn reap(&self, pid: ProcessId) {
+        let Some(proc) = self.get_proc(&pid) else {
+            return;
+        };
+        let parent = proc.read().parent();
+        if let Some(parent) = parent {
+            parent.write().remove_child(pid);
+        }
+
+        // it may still be running on its own syscall stack
+        match processor::cpu_of(pid) {
+            Some(cpu) => self.reapable.lock().push((pid, cpu)),
+            None => drop(self.processes.write().remove(&pid)),
+        }
+    }
+
+    /// Drop the reaped processes this cpu has switched away from
+    fn reap_switched(&self) {
+        let cpu = processor::cpu_index();
+        let current = processor::current_pid();
+        let mut reapable = self.reapable.lock();
+        reapable.retain(|&(pid, on)| {
+            if on != cpu || pid == current {
+                return true;
+            }
+            drop(self.processes.write().remove(&pid));
+            false
+        });
+    }
+
+    /// Hand the children of exiting process `proc` to the kernel, and reap
+    /// the ones that have exited already, as nobody can wait for them now
+    fn adopt_children(&self, proc: &Arc<Process>) {
+        let Some(kernel) = self.get_proc(&KERNEL_PID) else {
+            return;
+        };
+        let children = proc.write().take_children();
+        for child in children {
+            if child.read().status() == ProgramStatus::Dead {
+                self.reap(child.pid());
+                continue;
+            }
+
+            child.write().adopt(Arc::downgrade(&kernel));
+            kernel.write().add_child(child);
+        }
+    }
+
     pub fn save_current(&self, context: &ProcessContext) -> ProcessId {
         let current = self.current();
         let pid = current.pid();
 
-        let mut current = current.write();
-        current.tick();
-        current.save(context);
+        current.write().save(context);
 
         // debug!("Save process {} #{}", current.name(), pid);
 
         pid
     }
 
+    /// Charge the running `pid` a timer tick, true if it keeps the cpu
+    ///
+    /// it does until its quantum is used up or a process of a higher
+    /// level is ready, and not at all once stopped. A real-time process
+    /// does until its budget is used up or one with an earlier deadline
+    /// is ready.
+    pub fn charge_tick(&self, pid: ProcessId) -> bool {
+        let boost = BOOST_TICKS.get();
+        if boost != 0 && self.since_boost.fetch_add(1, Ordering::Relaxed) + 1 >= boost {
+            self.since_boost.store(0, Ordering::Relaxed);
+            self.boost();
+        }
+
+        let Some(proc) = self.get_proc(&pid) else {
+            return false;
+        };
+        let mut inner = proc.write();
+        if inner.is_stopped() {
+            return false;
+        }
+        match inner.sched_class() {
+            SchedClass::Normal(_) => {}
+            SchedClass::Realtime(deadline) => {
+                drop(inner);
+                return !self.ready_queue.lock().has_earlier(deadline);
+            }
+            SchedClass::Throttled(_) => return false,
+        }
+        if inner.charge_tick() {
+            return false;
+        }
+        let level = inner.level();
+        drop(inner);
+
+        !self.ready_queue.lock().has_above(level)
+    }
+
+    /// Move every process back to the level of its priority
+    fn boost(&self) {
+        let processes = self.processes.read();
+        for proc in processes.values() {
+            proc.write().boost();
+        }
+
+        let mut queue = self.ready_queue.lock();
+        for pid in queue.take_all() {
+            let level = processes.get(&pid).map_or(0, |proc| proc.read().level());
+            queue.push(pid, level);
+        }
+    }
+
     pub fn switch_next(&self, context: &mut ProcessContext) -> ProcessId {
+        self.reap_switched();
         let mut pid = processor::current_pid();
 
-        while let Some(next) = self.ready_queue.lock().pop_front() {
+        loop {
+            let next = match self.ready_queue.lock().pop() {
+                Some(next) => next,
+                None => break,
+            };
             let map = self.processes.read();
-            let proc = map.get(&next).expect("Process not found");
+            // killed and reaped while it waited to run
+            let Some(proc) = map.get(&next) else {
+                continue;
+            };
 
             if !proc.read().is_ready() {
                 debug!("Process #{} is {:?}", next, proc.read().status());
                 continue;
             }
 
+            // stopped while it waited to run
+            if proc.read().is_stopped() {
+                proc.write().park();
+                continue;
+            }
+
+            // a niced process goes last on its level, unless nothing else waits
+            let mut queue = self.ready_queue.lock();
+            if !queue.is_empty() && proc.write().pass_turn() {
+                queue.push(next, proc.read().level());
+                continue;
+            }
+            drop(queue);
+
+            proc.record_sched(SCHED_DISPATCH, 0);
+
             if pid != next {
                 proc.write().restore(context);
                 processor::set_pid(next);
@@ -134,30 +583,112 @@ impl ProcessManager {
         self.current().read().write(fd, buf)
     }
 
+    #[inline]
+    pub fn read_vectored(&self, fd: u8, bufs: &mut [&mut [u8]]) -> isize {
+        self.current().read().read_vectored(fd, bufs)
+    }
+
+    #[inline]
+    pub fn write_vectored(&self, fd: u8, bufs: &[&[u8]]) -> isize {
+        self.current().read().write_vectored(fd, bufs)
+    }
+
+    #[inline]
+    pub fn send_file(
+        &self,
+        out_fd: u8,
+        in_fd: u8,
+        offset: Option<usize>,
+        len: usize,
+    ) -> Result<usize, usize> {
+        self.current().read().send_file(out_fd, in_fd, offset, len)
+    }
+
+    #[inline]
+    pub fn open(&self, res: Arc<dyn Resource>) -> Option<u8> {
+        self.current().read().open(res)
+    }
+
+    #[inline]
+    pub fn close(&self, fd: u8) -> bool {
+        self.current().read().close(fd)
+    }
+
+    #[inline]
+    pub fn dup2(&self, old: u8, new: u8) -> bool {
+        self.current().read().dup2(old, new)
+    }
+
+    #[inline]
+    pub fn fd_stat(&self, fd: u8) -> Option<FdStat> {
+        self.current().read().fd_stat(fd)
+    }
+
+    #[inline]
+    pub fn seek(&self, fd: u8, offset: isize, whence: usize) -> Result<usize, usize> {
+        self.current().read().seek(fd, offset, whence)
+    }
+
+    #[inline]
+    pub fn set_fd_rate(&self, fd: u8, rate: usize) -> Option<usize> {
+        self.current().read().set_fd_rate(fd, rate)
+    }
+
+    pub fn check_user_buffer(&self, addr: VirtAddr, len: u64, write: bool) -> bool {
+        self.current()
+            .read()
+            .vm()
+            .check_user_range(addr, len, write)
+    }
+
+    /// Start a process running `elf`, with the segments of it in `image`
+    #[allow(clippy::too_many_arguments)]
     pub fn spawn(
         &self,
-        elf: &ElfFile,
+        elf: &ElfFile<'static>,
+        image: Image,
+        stack_pages: u64,
         name: String,
+        args: &AppArgs,
         parent: Option<Weak<Process>>,
         proc_data: Option<ProcessData>,
     ) -> ProcessId {
         let kproc = self.get_proc(&KERNEL_PID).unwrap();
         let page_table = kproc.read().clone_page_table();
         let proc_vm = Some(ProcessVm::new(page_table));
+        // like `RLIMIT_CPU`, the umask, the niceness, the priority and root are kept across spawn
+        let (cpu_limit, umask, nice, priority, root) = parent
+            .as_ref()
+            .and_then(Weak::upgrade)
+            .map_or((0, DEFAULT_UMASK, 0, 0, false), |parent| {
+                let parent = parent.read();
+                (
+                    parent.cpu().limit(),
+                    parent.umask(),
+                    parent.nice(),
+                    parent.priority(),
+                    parent.is_root(),
+                )
+            });
         let proc = Process::new(name, parent, proc_vm, proc_data);
 
         let mut inner = proc.write();
+        inner.cpu_mut().set_limit(cpu_limit);
+        inner.set_umask(umask);
+        inner.set_nice(nice);
+        inner.set_priority(priority);
+        inner.set_root(root);
         inner.pause();
-        inner.load_elf(elf);
-        inner.init_stack_frame(
-            VirtAddr::new_truncate(elf.header.pt2.entry_point()),
-            VirtAddr::new_truncate(super::stack::STACK_INIT_TOP),
-        );
+        inner.load_elf(image, stack_pages);
+        inner.init_program(VirtAddr::new_truncate(elf.header.pt2.entry_point()), args);
         drop(inner);
 
         trace!("New {:#?}", &proc);
 
         let pid = proc.pid();
+        if let Some(parent) = proc.read().parent() {
+            parent.write().add_child(proc.clone());
+        }
         self.add_proc(pid, proc);
         self.push_ready(pid);
 
@@ -196,6 +727,32 @@ impl ProcessManager {
             
             proc.set_return_value(ret);
 
+            // it runs again only once resumed
+            if proc.is_stopped() {
+                proc.park();
+                return;
+            }
+
+            proc.pause();
+            self.push_ready(pid);
+        }
+    }
+
+    /// Make a blocked process ready again, leaving its registers alone
+    ///
+    /// for processes that run their syscall again, see `rewind_syscall`.
+    pub fn wake_restart(&self, pid: ProcessId) {
+        if let Some(proc) = self.get_proc(&pid) {
+            let mut proc = proc.write();
+            if proc.status() != ProgramStatus::Blocked {
+                return;
+            }
+
+            if proc.is_stopped() {
+                proc.park();
+                return;
+            }
+
             proc.pause();
             self.push_ready(pid);
         }
@@ -212,19 +769,153 @@ impl ProcessManager {
         }
     }
 
-    pub fn handle_page_fault(&self, addr: VirtAddr, err_code: PageFaultErrorCode) -> bool {
-        if !err_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
-            let cur_proc = self.current();
-            trace!(
-                "Page Fault! Checking if {:#x} is on current process's stack",
-                addr
-            );
+    /// Block `pid` until the monotonic clock reaches `deadline`
+    pub fn sleep_until(&self, pid: ProcessId, deadline: i64) {
+        self.sleep_queue.lock().insert((deadline, pid));
+        self.block(pid);
+    }
 
-            let mut inner = cur_proc.write();
-            inner.handle_page_fault(addr)
-        } else {
-            false
+    /// Block `pid` on semaphore `key` until it is signalled or `deadline`
+    pub fn sem_wait_until(&self, pid: ProcessId, key: u32, deadline: i64) {
+        self.sem_timeouts.lock().insert(pid, key);
+        self.sleep_until(pid, deadline);
+    }
+
+    /// Take `pid` out of the sleep queue if it waits on a semaphore
+    /// with a timeout, when the semaphore wakes it first
+    pub fn cancel_sem_timeout(&self, pid: ProcessId) {
+        if self.sem_timeouts.lock().remove(&pid).is_some() {
+            self.sleep_queue.lock().retain(|&(_, p)| p != pid);
+        }
+    }
+
+    /// Block `pid` polling fds until one wakes it, or `deadline` if any
+    ///
+    /// either way it runs `poll` again, see `rewind_syscall`.
+    pub fn poll_wait_until(&self, pid: ProcessId, deadline: Option<i64>) {
+        if let Some(deadline) = deadline {
+            self.poll_timeouts.lock().insert(pid);
+            self.sleep_queue.lock().insert((deadline, pid));
         }
+        self.block(pid);
+    }
+
+    /// Take `pid` out of the sleep queue if it polled with a deadline
+    /// and was woken by an fd first
+    pub fn cancel_poll_timeout(&self, pid: ProcessId) {
+        if self.poll_timeouts.lock().remove(&pid) {
+            self.sleep_queue.lock().retain(|&(_, p)| p != pid);
+        }
+    }
+
+    /// Wake the sleeping processes whose deadline is `now` or before,
+    /// and queue the throttled ones whose next period has started
+    ///
+    /// called from the timer interrupt, gives up if a queue is locked.
+    /// A process waiting on a semaphore leaves its queue with `ETIMEDOUT`,
+    /// one polling fds runs the syscall again.
+    pub fn wake_sleepers(&self, now: i64) {
+        let released = match self.throttled.try_lock() {
+            Some(mut throttled) => {
+                let later = throttled.split_off(&(now.saturating_add(1), ProcessId(0)));
+                core::mem::replace(&mut *throttled, later)
+            }
+            None => BTreeSet::new(),
+        };
+        for (_, pid) in released {
+            self.push_ready(pid);
+        }
+
+        let due = {
+            let Some(mut queue) = self.sleep_queue.try_lock() else {
+                return;
+            };
+            let later = queue.split_off(&(now.saturating_add(1), ProcessId(0)));
+            core::mem::replace(&mut *queue, later)
+        };
+
+        for (_, pid) in due {
+            // its registers are those of the syscall, which runs again
+            if self.poll_timeouts.lock().remove(&pid) {
+                self.wake_restart(pid);
+                continue;
+            }
+
+            match self.sem_timeouts.lock().remove(&pid) {
+                Some(key) => {
+                    if let Some(proc) = self.get_proc(&pid) {
+                        proc.read().sem_cancel_wait(key, pid);
+                    }
+                    self.wake_up(pid, errno_ret(ETIMEDOUT) as isize);
+                }
+                None => self.wake_up(pid, 0),
+            }
+        }
+    }
+
+    /// The deadline the next sleeping process wakes at,
+    /// `None` if none sleeps or it cannot be checked now
+    pub fn next_wake(&self) -> Option<i64> {
+        let queue = self.sleep_queue.try_lock()?;
+        let throttled = self.throttled.try_lock()?;
+        let first = |set: &BTreeSet<(i64, ProcessId)>| set.first().map(|&(at, _)| at);
+        match (first(&queue), first(&throttled)) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
+    /// Give `pid` a real-time reservation of `budget` nanoseconds of cpu
+    /// time in every `period`, or take it out of the class if `budget` is 0
+    ///
+    /// `EBUSY` if the reservations would take more of the cpu than allowed,
+    /// see `edf::admit`.
+    pub fn reserve(&self, pid: ProcessId, period: u64, budget: u64) -> Result<(), usize> {
+        let proc = self.get_proc(&pid).ok_or(ESRCH)?;
+        if budget == 0 {
+            proc.write().set_reservation(None);
+            return Ok(());
+        }
+
+        let now = clock::monotonic_nanos();
+        let cpu_used = proc.read().cpu().used(clock::now_nanos());
+        let reservation = Reservation::new(period, budget, now, cpu_used)?;
+
+        let others = self
+            .processes
+            .read()
+            .values()
+            .filter(|other| other.pid() != pid)
+            .filter_map(|other| {
+                let inner = other.read();
+                match inner.status() {
+                    ProgramStatus::Dead => None,
+                    _ => inner.reservation().map(Reservation::utilization),
+                }
+            })
+            .sum();
+        if !edf::admit(others, &reservation) {
+            return Err(EBUSY);
+        }
+
+        proc.write().set_reservation(Some(reservation));
+        Ok(())
+    }
+
+    pub fn handle_page_fault(
+        &self,
+        addr: VirtAddr,
+        err_code: PageFaultErrorCode,
+    ) -> PageFaultOutcome {
+        let cur_proc = self.current();
+        trace!(
+            "Page Fault! Checking {:#x} of current process, {:?}",
+            addr,
+            err_code
+        );
+
+        let mut inner = cur_proc.write();
+        inner.handle_page_fault(addr, err_code)
     }
 
     pub fn kill(&self, pid: ProcessId, ret: isize) {
@@ -239,17 +930,57 @@ impl ProcessManager {
 
         trace!("Kill {:#?}", &proc);
 
+        // before its fds are closed, which may hand it a lock
+        for pid in flock::cancel_waits(pid) {
+            self.wake_up(pid, 0);
+        }
+
+        // give the console back to the shell
+        crate::drivers::serial::release_raw(pid.0);
+        #[cfg(feature = "graphics")]
+        crate::drivers::display::release(pid.0);
+
         proc.kill(ret);
+        let _ = self
+            .foreground
+            .compare_exchange(pid.0, 0, Ordering::Relaxed, Ordering::Relaxed);
 
-        if let Some(pids) = self.wait_queue.lock().remove(&pid) {
-            for p in pids {
-                self.wake_up(p, ret);
-            }
+        let waiters = self.wait_queue.lock().remove(&pid);
+        let waited = waiters.is_some();
+        for p in waiters.into_iter().flat_map(BTreeMap::into_keys) {
+            self.wake_up(p, ret);
+        }
+
+        self.sleep_queue.lock().retain(|&(_, p)| p != pid);
+        self.sem_timeouts.lock().remove(&pid);
+        self.poll_timeouts.lock().remove(&pid);
+        self.throttled.lock().retain(|&(_, p)| p != pid);
+        self.any_waiters.lock().remove(&pid);
+        super::futex::cancel_wait(pid);
+
+        if pid != KERNEL_PID {
+            self.adopt_children(&proc);
+        }
+
+        // ignored unless the parent catches it
+        let parent = proc.read().parent();
+        if let Some(parent) = parent.as_ref().filter(|p| self.catches(p.pid(), SIGCHLD)) {
+            self.post_signal(parent.pid(), SIGCHLD);
+        }
+
+        // the waiters took its exit code, and the kernel takes no orphan's
+        if waited || proc.read().is_orphan() {
+            self.reap(pid);
+        }
+
+        // a parent waiting for any child looks again
+        if let Some(parent) = parent.filter(|p| self.any_waiters.lock().remove(&p.pid())) {
+            self.wake_restart(parent.pid());
         }
     }
 
     pub fn print_process_list(&self) {
-        let mut output = String::from("  PID | PPID | Process Name |  Ticks  |   Memory  | Status\n");
+        let mut output = String::from("  PID | PPID | Process Name |  User  |  Sys  | Nice | Prio |   Memory  | Status\n");
 
         self.processes
             .read()
@@ -257,7 +988,7 @@ impl ProcessManager {
             .filter(|p| p.read().status() != ProgramStatus::Dead)
             .for_each(|p| output += format!("{}\n", p).as_str());
 
-        let heap_used = ALLOCATOR.lock().used();
+        let heap_used = ALLOCATOR.used();
         let heap_size = HEAP_SIZE;
 
         output += &format_usage("Kernel", heap_used, heap_size);
@@ -276,6 +1007,34 @@ impl ProcessManager {
 
         output += &format_usage("Memory", used, total);
 
+        let (pooled, hits, misses) = alloc.zeroed_stats();
+        output += format!("Shared : {} frames\n", alloc.frames_shared()).as_str();
+
+        output += format!(
+            "Zeroed : {} frames pooled, {} served from pool, {} zeroed on demand\n",
+            pooled, hits, misses
+        )
+        .as_str();
+
+        let (slots, free, outs, ins) = super::swap::stats();
+        output += format!(
+            "Swap   : {} of {} pages used, {} swapped out, {} swapped in\n",
+            slots - free,
+            slots,
+            outs,
+            ins
+        )
+        .as_str();
+
+        let (stack_grow, lazy_loaded, fatal, cow) = fault::counts();
+        output += format!(
+            "Faults : {} stack grow, {} lazy loaded, {} copied on write, {} fatal\n",
+            stack_grow, lazy_loaded, cow, fatal
+        )
+        .as_str();
+
+        output += &super::ipc::summary();
+
         output += format!("Queue  : {:?}\n", self.ready_queue.lock()).as_str();
 
         output += &processor::print_processors();
@@ -283,6 +1042,32 @@ impl ProcessManager {
         print!("{}", output);
     }
 
+    /// Replace the program of the current process with `elf`,
+    /// with the segments of it in `image`
+    pub fn exec(
+        &self,
+        name: &str,
+        elf: &ElfFile<'static>,
+        image: Image,
+        stack_pages: u64,
+        args: &AppArgs,
+        context: &mut ProcessContext,
+    ) -> Result<(), SpawnError> {
+        let proc = self.current();
+        if proc.read().vm().page_table.using_count() > 1 {
+            return Err(SpawnError::Threads);
+        }
+
+        let kproc = self.get_proc(&KERNEL_PID).unwrap();
+        let page_table = kproc.read().clone_page_table();
+
+        proc.write()
+            .exec(name, elf, image, stack_pages, page_table, args, context);
+
+        debug!("Exec {}#{}", name, proc.pid());
+        Ok(())
+    }
+
     pub fn fork(&self) {
         // FIXME: get current process
         let proc = self.current();
@@ -303,6 +1088,16 @@ impl ProcessManager {
 
     }
 
+    /// Start a thread of the current process, see `Process::thread`
+    pub fn spawn_thread(&self, entry: VirtAddr, stack_top: VirtAddr, arg: usize) -> ProcessId {
+        let thread = self.current().thread(entry, stack_top, arg);
+
+        let tid = thread.pid();
+        self.add_proc(tid, thread);
+        self.push_ready(tid);
+        tid
+    }
+
 }
 
 fn format_usage(name: &str, used: usize, total: usize) -> String {
@@ -0,0 +1,89 @@
+//! The ready queues of the multi-level feedback queue scheduler
+//!
+//! a process starts on the level of its priority, 0 the highest, and runs
+//! for the quantum of its level. One that uses it all drops a level, one
+//! that blocks or yields before keeps its level and what it used of it,
+//! so interactive processes stay above cpu-bound ones. The highest ready
+//! level always runs first, and every `BOOST_TICKS` every process goes
+//! back to the level of its priority, so the lowest levels do not starve.
+//! Above every level is the queue of the real-time class, see `edf`.
+
+use alloc::collections::{BTreeSet, VecDeque};
+use alloc::vec::Vec;
+use syscall_def::PRIO_LEVELS;
+
+use super::ProcessId;
+use crate::utils::sysctl::Tunable;
+
+/// Ticks between two boosts of every process to its priority, 0 never
+pub static BOOST_TICKS: Tunable = Tunable::new("sched.boost_ticks", 100);
+
+/// Ticks a process runs on `level` before it drops to the next
+#[inline]
+pub fn quantum(level: usize) -> usize {
+    1 << level
+}
+
+#[derive(Default)]
+pub struct ReadyQueues {
+    /// the real-time processes by the deadline of their period
+    realtime: BTreeSet<(i64, ProcessId)>,
+    levels: [VecDeque<ProcessId>; PRIO_LEVELS],
+}
+
+impl ReadyQueues {
+    pub fn push(&mut self, pid: ProcessId, level: usize) {
+        self.levels[level.min(PRIO_LEVELS - 1)].push_back(pid);
+    }
+
+    /// Queue a real-time process, before any level
+    pub fn push_realtime(&mut self, pid: ProcessId, deadline: i64) {
+        self.realtime.insert((deadline, pid));
+    }
+
+    /// The real-time process with the earliest deadline,
+    /// or the first process of the highest level that has one
+    pub fn pop(&mut self) -> Option<ProcessId> {
+        if let Some((_, pid)) = self.realtime.pop_first() {
+            return Some(pid);
+        }
+        self.levels.iter_mut().find_map(VecDeque::pop_front)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.realtime.is_empty() && self.levels.iter().all(VecDeque::is_empty)
+    }
+
+    /// Check if a real-time process waits, or one on a level above `level`
+    pub fn has_above(&self, level: usize) -> bool {
+        !self.realtime.is_empty()
+            || self.levels[..level.min(PRIO_LEVELS)]
+                .iter()
+                .any(|queue| !queue.is_empty())
+    }
+
+    /// Check if a real-time process with a deadline before `deadline` waits
+    pub fn has_earlier(&self, deadline: i64) -> bool {
+        self.realtime
+            .first()
+            .is_some_and(|&(first, _)| first < deadline)
+    }
+
+    /// Take every process of the levels, highest first
+    pub fn take_all(&mut self) -> Vec<ProcessId> {
+        self.levels
+            .iter_mut()
+            .flat_map(|queue| queue.drain(..))
+            .collect()
+    }
+}
+
+impl core::fmt::Debug for ReadyQueues {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        let realtime = self.realtime.iter().map(|&(_, pid)| pid);
+        f.debug_list()
+            .entry(&realtime.collect::<Vec<_>>())
+            .entries(self.levels.iter())
+            .finish()
+    }
+}
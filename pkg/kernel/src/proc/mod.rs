@@ -1,11 +1,26 @@
 mod context;
 mod data;
+pub mod deterministic;
+mod disk;
+pub mod edf;
+mod error;
+pub mod flock;
+mod futex;
+mod history;
+mod host;
+mod ipc;
+pub mod limits;
 mod manager;
-mod paging;
+pub mod mlfq;
+pub mod paging;
 mod pid;
 mod process;
 mod processor;
+pub mod signal;
+pub mod swap;
+pub mod trace;
 mod vm;
+mod shm;
 mod sync;
 
 use alloc::sync::Arc;
@@ -15,16 +30,33 @@ use process::*;
 
 pub use context::ProcessContext;
 pub use data::ProcessData;
+pub use error::SpawnError;
 pub use paging::PageTableContext;
 pub use pid::ProcessId;
+pub use processor::Processor;
 pub use vm::*;
+use vm::image::Image;
 use xmas_elf::ElfFile;
 
+use crate::drivers::block::{self, SyscallEnd};
+use crate::memory::uaccess::{copy_slice_from_user, copy_slice_to_user};
+use crate::memory::{get_frame_alloc_for_sure, PAGE_SIZE};
+use crate::pty::PtyEnd;
+use crate::resource::{PipeEnd, Resource};
 use alloc::string::{String, ToString};
 use x86_64::structures::idt::PageFaultErrorCode;
+use x86_64::structures::paging::PageTableFlags;
 use x86_64::VirtAddr;
 
 use self::sync::SemaphoreResult;
+use syscall_def::{
+    errno_ret, FdStat, PollFd, ProcInfo, POLLNVAL, Rusage, SchedEvent, Syscall, Sysinfo, BLOCK_DISK, BLOCK_FLOCK, BLOCK_FUTEX, BLOCK_MQ, BLOCK_PIPE, BLOCK_POLL, BLOCK_SEM, BLOCK_SLEEP, BLOCK_WAIT_PID, EAGAIN, EBADF,
+    EEXIST, EINVAL, ENOENT, ENOMEM, ESRCH, EXIT_CPU_LIMIT, EXIT_SYSCALL_DENIED, LOCK_EX, LOCK_NB, LOCK_SH, LOCK_UN, SCHED_BLOCK,
+    SCHED_PREEMPT, SCHED_YIELD, SyscallSet, exit_code_of, SigDefault, SIGCONT, SIGSEGV, SIG_DFL,
+    SIG_IGN, SIG_KEEP,
+};
+use signal::{SigFrame, RED_ZONE};
+use trace::TraceMode;
 
 pub const KERNEL_PID: ProcessId = ProcessId(1);
 
@@ -33,6 +65,8 @@ pub enum ProgramStatus {
     Running,
     Ready,
     Blocked,
+    /// stopped by `Suspend` and off every queue, until resumed
+    Stopped,
     Dead,
 }
 
@@ -45,7 +79,10 @@ pub fn init(boot_info: &'static boot::BootInfo) {
     // kernel process
     let kproc = Process::new(String::from("kernel"), None, Some(proc_vm), None);
 
-    kproc.write().resume();
+    let mut inner = kproc.write();
+    inner.set_root(true);
+    inner.resume();
+    drop(inner);
     let app_list = boot_info.loaded_apps.as_ref();
     manager::init(kproc, app_list);
 
@@ -55,7 +92,29 @@ pub fn init(boot_info: &'static boot::BootInfo) {
 pub fn switch(context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
+        manager.apply_stop_request();
         let pid = manager.save_current(context);
+        if manager.charge_tick(pid) {
+            return;
+        }
+        manager.record_sched(pid, SCHED_PREEMPT, 0);
+        manager.push_ready(pid);
+        manager.switch_next(context);
+    });
+}
+
+/// Charge the running process a timer tick, in user or kernel mode by
+/// where `context` was interrupted
+pub fn account_tick(context: &ProcessContext) {
+    get_process_manager().current().write().tick(context.is_user());
+}
+
+/// Give up the cpu to the next ready process
+pub fn yield_now(context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let pid = manager.save_current(context);
+        manager.record_sched(pid, SCHED_YIELD, 0);
         manager.push_ready(pid);
         manager.switch_next(context);
     });
@@ -65,6 +124,10 @@ pub fn fork(context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         // FIXME: save_current as parent
+        if !manager.allow_new_process() {
+            context.set_rax(errno_ret(EAGAIN));
+            return;
+        }
         let parent = manager.save_current(context);
         // FIXME: fork to get child
         manager.fork();
@@ -76,6 +139,36 @@ pub fn fork(context: &mut ProcessContext) {
     })
 }
 
+/// Start a thread of the current process running `entry(arg)` on `stack_top`
+///
+/// the caller goes on running, the thread is queued ready.
+pub fn spawn_thread(entry: VirtAddr, stack_top: VirtAddr, arg: usize) -> Result<ProcessId, usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        if !manager.allow_new_process() {
+            return Err(EAGAIN);
+        }
+        Ok(manager.spawn_thread(entry, stack_top, arg))
+    })
+}
+
+/// Replace the program of the current process with app `name`
+///
+/// the pid, fds and family are kept, `args` are passed to the new entry.
+/// Fails while other threads share the memory of the process, they would
+/// be left running in the old program, see `SpawnError::Threads`.
+pub fn exec(name: &str, args: &AppArgs, context: &mut ProcessContext) -> Result<(), SpawnError> {
+    let app = find_app(name)?;
+    // the segments are checked before interrupts are held off,
+    // and before anything of the old program is dropped
+    let image = Image::new(&app.elf)?;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        manager.exec(name, &app.elf, image, app.info.stack_pages, args, context)
+    })
+}
+
 pub fn print_process_list() {
     x86_64::instructions::interrupts::without_interrupts(|| {
         get_process_manager().print_process_list();
@@ -88,6 +181,18 @@ pub fn env(key: &str) -> Option<String> {
     })
 }
 
+/// Get the file mode creation mask of the current process, setting it to `new`
+pub fn umask(new: Option<u16>) -> u16 {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let current = get_process_manager().current();
+        let mut inner = current.write();
+        match new {
+            Some(mask) => inner.set_umask(mask),
+            None => inner.umask(),
+        }
+    })
+}
+
 pub fn process_exit(ret: isize, context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
@@ -96,21 +201,80 @@ pub fn process_exit(ret: isize, context: &mut ProcessContext) {
     })
 }
 
-pub fn wait_pid(pid: ProcessId, context: &mut ProcessContext) {
+/// Block the current process for `nanos` of the monotonic clock
+///
+/// it is woken on the first timer tick after, see `wake_sleepers`.
+pub fn sleep(nanos: u64, context: &mut ProcessContext) {
+    if nanos == 0 {
+        return;
+    }
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let nanos = nanos.min(i64::MAX as u64) as i64;
+        let deadline = crate::utils::clock::monotonic_nanos().saturating_add(nanos);
+        let pid = manager.save_current(context);
+        manager.record_sched(pid, SCHED_BLOCK, BLOCK_SLEEP);
+        manager.sleep_until(pid, deadline);
+        manager.switch_next(context);
+    })
+}
+
+/// Make the processes whose sleep is over ready, on a timer tick
+pub fn wake_sleepers() {
+    get_process_manager().wake_sleepers(crate::utils::clock::monotonic_nanos());
+}
+
+/// Nanoseconds before the next sleeping process wakes,
+/// `None` if none sleeps or it cannot be checked now
+pub fn next_wake_in() -> Option<u64> {
+    let deadline = get_process_manager().next_wake()?;
+    Some(deadline.saturating_sub(crate::utils::clock::monotonic_nanos()).max(0) as u64)
+}
+
+/// Wait for any child to exit, return its pid and exit code
+///
+/// blocks until one does, then the syscall runs again to take it.
+/// With `nohang` it returns `None` in the result instead.
+pub fn wait_any(
+    nohang: bool,
+    context: &mut ProcessContext,
+) -> Option<Result<Option<(ProcessId, isize)>, usize>> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        match manager.wait_any(processor::current_pid(), nohang) {
+            Ok(None) if !nohang => {
+                context.rewind_syscall();
+                let current = manager.save_current(context);
+                manager.record_sched(current, SCHED_BLOCK, BLOCK_WAIT_PID);
+                manager.block(current);
+                manager.switch_next(context);
+                None
+            }
+            ret => Some(ret),
+        }
+    })
+}
+
+/// Wait for `pid` to exit, or to stop too if `untraced` is set,
+/// or only check if it has with `nohang`
+pub fn wait_pid(pid: ProcessId, untraced: bool, nohang: bool, context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
-        if let Some(ret) = manager.wait_pid(pid) {
+        if let Some(ret) = manager.wait_pid(pid, untraced, nohang) {
             context.set_rax(ret as usize);
         } else {
-            manager.save_current(context);
+            let current = manager.save_current(context);
+            manager.record_sched(current, SCHED_BLOCK, BLOCK_WAIT_PID);
             manager.current().write().block();
             manager.switch_next(context);
         }
     })
 }
 
+/// The exit code of `pid` if it has exited, reaping it
 pub(crate) fn wait_no_block(pid: ProcessId) -> Option<isize> {
-    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().get_ret(pid))
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().collect(pid))
 }
 
 pub fn read(fd: u8, buf: &mut [u8]) -> isize {
@@ -121,94 +285,1020 @@ pub fn write(fd: u8, buf: &[u8]) -> isize {
     x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().write(fd, buf))
 }
 
+pub fn read_vectored(fd: u8, bufs: &mut [&mut [u8]]) -> isize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().read_vectored(fd, bufs)
+    })
+}
+
+pub fn write_vectored(fd: u8, bufs: &[&[u8]]) -> isize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().write_vectored(fd, bufs)
+    })
+}
+
+pub fn send_file(out_fd: u8, in_fd: u8, offset: Option<usize>, len: usize) -> Result<usize, usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().send_file(out_fd, in_fd, offset, len)
+    })
+}
+
+/// Run the I/O `op` on `fd`, unless the pipe behind it would block
+///
+/// then the process is blocked until the pipe changes and `None` is returned,
+/// it runs the syscall again when woken. The check and `op` run with
+/// interrupts disabled, so the pipe cannot change in between, and a blocked
+/// process leaves them disabled until the next one is entered.
+pub fn block_on_fd<T>(
+    fd: u8,
+    write: bool,
+    context: &mut ProcessContext,
+    op: impl FnOnce() -> T,
+) -> Option<T> {
+    let enabled = x86_64::instructions::interrupts::are_enabled();
+    x86_64::instructions::interrupts::disable();
+
+    let manager = get_process_manager();
+    let pid = processor::current_pid();
+    if manager.current().read().wait_fd(fd, pid, write) {
+        context.rewind_syscall();
+        let pid = manager.save_current(context);
+        manager.record_sched(pid, SCHED_BLOCK, BLOCK_PIPE);
+        manager.block(pid);
+        manager.switch_next(context);
+        return None;
+    }
+
+    let ret = op();
+    if enabled {
+        x86_64::instructions::interrupts::enable();
+    }
+    Some(ret)
+}
+
+/// How often `poll` looks again at fds nothing wakes it for, the console
+const POLL_RECHECK_NANOS: i64 = 10_000_000;
+
+/// Set the `revents` of `fds`, blocking until one is ready or the
+/// monotonic clock reaches `deadline`
+///
+/// return how many are ready, 0 at the deadline. Otherwise the process
+/// is queued on the fds and blocked, `None` is returned and it runs the
+/// syscall again when woken, by an fd, the deadline or the next look at
+/// the console.
+pub fn poll(
+    fds: &mut [PollFd],
+    deadline: Option<i64>,
+    context: &mut ProcessContext,
+) -> Option<usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let pid = processor::current_pid();
+        // woken by an fd before the deadline, or by it
+        manager.cancel_poll_timeout(pid);
+
+        let (mut ready, mut recheck) = (0, false);
+        {
+            let proc = manager.current();
+            let proc = proc.read();
+            for fd in fds.iter_mut() {
+                let (revents, again) = match u8::try_from(fd.fd) {
+                    Ok(num) => proc.poll_fd(num, fd.events, pid),
+                    Err(_) => (POLLNVAL, false),
+                };
+                fd.revents = revents;
+                ready += (revents != 0) as usize;
+                recheck |= again;
+            }
+        }
+
+        let now = crate::utils::clock::monotonic_nanos();
+        if ready > 0 || deadline.is_some_and(|deadline| deadline <= now) {
+            return Some(ready);
+        }
+
+        let deadline = match recheck {
+            true => Some(deadline.unwrap_or(i64::MAX).min(now + POLL_RECHECK_NANOS)),
+            false => deadline,
+        };
+        context.rewind_syscall();
+        let pid = manager.save_current(context);
+        manager.record_sched(pid, SCHED_BLOCK, BLOCK_POLL);
+        manager.poll_wait_until(pid, deadline);
+        manager.switch_next(context);
+        None
+    })
+}
+
+/// Run the syscall `nr` of `pid` again if it read a block not there yet
+///
+/// the result it set is dropped, the process sleeps until the disk has
+/// read the block, unless it has already, see `drivers::block`.
+/// Return whether the syscall runs again.
+pub fn restart_on_disk(pid: ProcessId, nr: usize, context: &mut ProcessContext) -> bool {
+    let sleep = match block::end_syscall(pid) {
+        SyscallEnd::Done => return false,
+        SyscallEnd::Restart => false,
+        SyscallEnd::Sleep => true,
+    };
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        context.set_rax(nr);
+        context.rewind_syscall();
+        if sleep {
+            let manager = get_process_manager();
+            let pid = manager.save_current(context);
+            manager.record_sched(pid, SCHED_BLOCK, BLOCK_DISK);
+            manager.block(pid);
+            manager.switch_next(context);
+        }
+    });
+    true
+}
+
+/// Wake the processes blocked on a pipe, see `block_on_fd`
+pub fn wake_blocked(pids: Vec<ProcessId>) {
+    if pids.is_empty() {
+        return;
+    }
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        for pid in pids {
+            manager.wake_restart(pid);
+        }
+    })
+}
+
+/// Open `res` as a new fd of the current process
+pub fn open(res: Arc<dyn Resource>) -> Option<u8> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().open(res))
+}
+
+/// Open a new pipe in the current process, return its read and write fds
+pub fn open_pipe() -> Option<(u8, u8)> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let (read, write) = PipeEnd::pair();
+        let manager = get_process_manager();
+        let read = manager.open(Arc::new(read))?;
+        match manager.open(Arc::new(write)) {
+            Some(write) => Some((read, write)),
+            None => {
+                manager.close(read);
+                None
+            }
+        }
+    })
+}
+
+/// Open a new pty in the current process, return its master and slave fds
+pub fn open_pty() -> Option<(u8, u8)> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let (master, slave) = PtyEnd::pair();
+        let manager = get_process_manager();
+        let master = manager.open(Arc::new(master))?;
+        match manager.open(Arc::new(slave)) {
+            Some(slave) => Some((master, slave)),
+            None => {
+                manager.close(master);
+                None
+            }
+        }
+    })
+}
+
+pub fn close(fd: u8) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().close(fd))
+}
+
+/// Make `new` refer to what `old` does in the current process
+pub fn dup2(old: u8, new: u8) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().dup2(old, new))
+}
+
+pub fn fd_stat(fd: u8) -> Option<FdStat> {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().fd_stat(fd))
+}
+
+/// Move the position of the file behind `fd`, return the new one or the errno
+pub fn seek(fd: u8, offset: isize, whence: usize) -> Result<usize, usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().seek(fd, offset, whence)
+    })
+}
+
+/// Limit `fd` to `rate` bytes per second, returns the old limit
+pub fn set_fd_rate(fd: u8, rate: usize) -> Option<usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().set_fd_rate(fd, rate)
+    })
+}
+
+/// Control the device behind `fd`, e.g. `F_SETRAW` of the console
+pub fn ioctl(fd: u8, cmd: usize, arg: usize) -> Result<usize, usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().read().ioctl(fd, cmd, arg)
+    })
+}
+
+/// Check if `[addr, addr + len)` is accessible by the current user process
+pub fn check_user_buffer(addr: usize, len: usize, write: bool) -> bool {
+    let addr = match VirtAddr::try_new(addr as u64) {
+        Ok(addr) => addr,
+        Err(_) => return false,
+    };
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().check_user_buffer(addr, len as u64, write)
+    })
+}
+
 pub fn current_pid() -> ProcessId {
     x86_64::instructions::interrupts::without_interrupts(processor::current_pid)
 }
 
-pub fn kill(pid: ProcessId, context: &mut ProcessContext) {
+/// Send `sig` to `pid`, 0 only checks that it is alive
+///
+/// what the signal does by default is done now, a caught one is left
+/// pending, see `signal`. `SIGCONT` lets a stopped process run in any case.
+pub fn kill(pid: ProcessId, sig: usize, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let Some(handler) = manager.signal_handler(pid, sig) else {
+            context.set_rax(errno_ret(ESRCH));
+            return;
+        };
+
+        context.set_rax(0);
+        if sig == 0 {
+            return;
+        }
+        if sig == SIGCONT {
+            manager.resume(pid);
+        }
+
+        match (handler, SigDefault::of(sig)) {
+            (SIG_IGN, _) | (SIG_DFL, SigDefault::Ignore | SigDefault::Continue) => (),
+            (SIG_DFL, SigDefault::Stop) => suspend(pid, context),
+            (SIG_DFL, SigDefault::Terminate) => {
+                let ret = exit_code_of(sig) as isize;
+                if pid == processor::current_pid() {
+                    manager.kill_self(ret);
+                    manager.switch_next(context);
+                } else {
+                    manager.kill(pid, ret);
+                }
+            }
+            _ => manager.post_signal(pid, sig),
+        }
+    })
+}
+
+/// Get the handler of `sig` of the current process, setting it to `handler`
+/// unless it is `SIG_KEEP`, handlers return into `restorer`
+pub fn sigaction(sig: usize, handler: usize, restorer: usize) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let current = get_process_manager().current();
+        let mut inner = current.write();
+        match handler {
+            SIG_KEEP => inner.signals().handler(sig),
+            handler => inner.signals_mut().set_handler(sig, handler, restorer),
+        }
+    })
+}
+
+/// Act on the signals pending on the current process, as it returns to user mode
+///
+/// the first one caught enters its handler, see `signal`.
+pub fn handle_signals(context: &mut ProcessContext) {
+    if !context.is_user() {
+        return;
+    }
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        while let Some((sig, handler)) = manager.take_signal(processor::current_pid()) {
+            match handler {
+                SIG_IGN => (),
+                SIG_DFL if SigDefault::of(sig) != SigDefault::Terminate => (),
+                SIG_DFL => {
+                    manager.kill_self(exit_code_of(sig) as isize);
+                    manager.switch_next(context);
+                    return;
+                }
+                handler => {
+                    if !enter_handler(sig, handler, context) {
+                        warn!(
+                            "Process #{} cannot run the handler of signal {}, killed it.",
+                            processor::current_pid(),
+                            sig
+                        );
+                        manager.kill_self(exit_code_of(SIGSEGV) as isize);
+                        manager.switch_next(context);
+                    }
+                    return;
+                }
+            }
+        }
+    })
+}
+
+/// Push a `SigFrame` below the stack of the current process, and set
+/// `context` to run `handler` for `sig` on it
+///
+/// return false if the stack cannot hold the frame.
+fn enter_handler(sig: usize, handler: usize, context: &mut ProcessContext) -> bool {
+    let Ok(handler) = VirtAddr::try_new(handler as u64) else {
+        return false;
+    };
+
+    // 16 bytes aligned above the return address, as after a call
+    let size = core::mem::size_of::<SigFrame>() as u64;
+    let rsp = context.stack_frame.stack_pointer.as_u64();
+    let Some(addr) = rsp
+        .checked_sub(RED_ZONE + size)
+        .and_then(|addr| (addr & !0xf).checked_sub(8))
+        .and_then(|addr| VirtAddr::try_new(addr).ok())
+    else {
+        return false;
+    };
+
+    let current = get_process_manager().current();
+    let mut inner = current.write();
+    // so the copy below takes no page fault, this may run in the page fault handler
+    if !inner.vm_mut().fault_in(addr, size) {
+        return false;
+    }
+
+    let frame = SigFrame {
+        restorer: inner.signals().restorer(),
+        sig,
+        mask: inner.signals_mut().enter(sig) as usize,
+        regs: context.regs,
+        rip: context.stack_frame.instruction_pointer.as_u64(),
+        rsp,
+        rflags: context.stack_frame.cpu_flags.bits(),
+    };
+    drop(inner);
+
+    if copy_slice_to_user(addr.as_u64() as usize, &[frame]).is_err() {
+        return false;
+    }
+
+    context.enter_handler(handler, addr, sig);
+    true
+}
+
+/// Go back to what the handler the current process returns from interrupted
+///
+/// the process is killed if the frame it left on its stack is not valid.
+pub fn sig_return(context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+
+        // the handler returned past the restorer, the first field of the frame
+        let mut frame = SigFrame::default();
+        let addr = (context.stack_frame.stack_pointer.as_u64() as usize).wrapping_sub(8);
+        let read = unsafe { copy_slice_from_user(core::slice::from_mut(&mut frame), addr) };
+
+        match (read, VirtAddr::try_new(frame.rip), VirtAddr::try_new(frame.rsp)) {
+            (Ok(()), Ok(rip), Ok(rsp)) => {
+                manager.current().write().signals_mut().set_mask(frame.mask as u32);
+                context.return_from_handler(frame.regs, rip, rsp, frame.rflags);
+            }
+            _ => {
+                warn!(
+                    "Process #{} returned from a signal handler with a bad frame, killed it.",
+                    processor::current_pid()
+                );
+                manager.kill_self(exit_code_of(SIGSEGV) as isize);
+                manager.switch_next(context);
+            }
+        }
+    })
+}
+
+/// Send `sig` to the current process for a fault of its own, and act on it now
+///
+/// the faulting instruction cannot run again as it is,
+/// so the process is killed unless a handler takes the signal.
+pub fn fault_signal(sig: usize, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let pid = processor::current_pid();
+        let masked = manager
+            .get_proc(&pid)
+            .is_some_and(|proc| proc.read().signals().is_masked(sig));
+
+        if manager.catches(pid, sig) && !masked {
+            manager.post_signal(pid, sig);
+            handle_signals(context);
+        } else {
+            manager.kill_self(exit_code_of(sig) as isize);
+            manager.switch_next(context);
+        }
+    })
+}
+
+/// Stop `pid` until it is resumed, like `SIGSTOP`
+///
+/// the current process leaves the cpu right away.
+pub fn suspend(pid: ProcessId, context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
+        if !manager.suspend(pid) {
+            context.set_rax(errno_ret(ESRCH));
+            return;
+        }
+
+        context.set_rax(0);
         if pid == processor::current_pid() {
-            manager.kill_self(0xdead);
+            let pid = manager.save_current(context);
+            manager.push_ready(pid);
             manager.switch_next(context);
-        } else {
-            manager.kill(pid, 0xdead);
         }
     })
 }
 
-pub fn spawn(name: &str) -> Result<ProcessId, String> {
-    let app = x86_64::instructions::interrupts::without_interrupts(|| {
-        let app_list = get_process_manager().app_list()?;
+/// Let a stopped process run again, like `SIGCONT`
+pub fn resume(pid: ProcessId) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| get_process_manager().resume(pid))
+}
 
-        app_list.iter().find(|&app| app.name.eq(name))
-    });
+/// Stop the process in the foreground for Ctrl+Z, false if there is none
+pub fn stop_foreground() -> bool {
+    PROCESS_MANAGER
+        .get()
+        .is_some_and(|manager| manager.request_stop_foreground())
+}
 
-    if app.is_none() {
-        return Err(format!("App not found: {}", name));
-    };
+/// Check the creation limits before the current process creates another one
+pub fn allow_new_process() -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().allow_new_process()
+    })
+}
+
+/// Find a loaded app by its name
+///
+/// a copy the host shares is newer than the one loaded at boot, so it
+/// is preferred. Apps the bootloader had no room for are read from disk.
+pub fn find_app(name: &str) -> Result<&'static boot::App<'static>, SpawnError> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        if let Some(app) = host::find(name) {
+            return Ok(app);
+        }
+
+        let app_list = get_process_manager().app_list();
+        if let Some(app) = app_list.and_then(|list| list.iter().find(|app| app.info.name.eq(name))) {
+            return Ok(app);
+        }
+
+        if let Some(app) = disk::find(name) {
+            return Ok(app);
+        }
+        if block::is_pending(processor::current_pid()) {
+            return Err(SpawnError::Pending);
+        }
+
+        match app_list {
+            None if !crate::fs::has_boot_fs() => Err(SpawnError::NoApps),
+            _ => Err(SpawnError::NotFound),
+        }
+    })
+}
+
+/// Information of a loaded app from the build manifest
+pub fn app_info(name: &str) -> Option<&'static boot::AppInfo> {
+    find_app(name).ok().map(|app| &app.info)
+}
+
+/// The arguments and environment a program is started with
+///
+/// by convention the first argument is the name of the program,
+/// environment strings are `KEY=value`.
+#[derive(Debug, Clone, Default)]
+pub struct AppArgs {
+    pub args: Vec<String>,
+    pub env: Vec<String>,
+}
+
+impl AppArgs {
+    /// Only the name of the program, and no environment
+    pub fn new(name: &str) -> Self {
+        Self {
+            args: vec![name.to_string()],
+            env: Vec::new(),
+        }
+    }
+}
+
+pub fn spawn(name: &str) -> Result<ProcessId, SpawnError> {
+    spawn_with(name, &AppArgs::new(name), None, None)
+}
 
-    elf_spawn(name.to_string(), &app.unwrap().elf)
+/// Spawn app `name` with its syscalls recorded or replayed
+pub fn spawn_traced(name: &str, trace: Option<TraceMode>) -> Result<ProcessId, SpawnError> {
+    spawn_with(name, &AppArgs::new(name), trace, None)
 }
 
-pub fn elf_spawn(name: String, elf: &ElfFile) -> Result<ProcessId, String> {
-    let pid = x86_64::instructions::interrupts::without_interrupts(|| {
+/// Spawn app `name` with `args`, writing its stdout to `stdout`
+/// of the current process if given
+pub fn spawn_args(
+    name: &str,
+    args: &AppArgs,
+    stdout: Option<u8>,
+) -> Result<ProcessId, SpawnError> {
+    spawn_with(name, args, None, stdout)
+}
+
+fn spawn_with(
+    name: &str,
+    args: &AppArgs,
+    trace: Option<TraceMode>,
+    stdout: Option<u8>,
+) -> Result<ProcessId, SpawnError> {
+    let app = find_app(name)?;
+
+    spawn_elf(
+        name.to_string(),
+        &app.elf,
+        app.info.stack_pages,
+        args,
+        trace,
+        stdout,
+    )
+}
+
+/// Spawn a process from `elf` with `stack_pages` pages of initial stack
+///
+/// 0 pages means the default size
+pub fn elf_spawn(
+    name: String,
+    elf: &ElfFile<'static>,
+    stack_pages: u64,
+) -> Result<ProcessId, SpawnError> {
+    let args = AppArgs::new(&name);
+    spawn_elf(name, elf, stack_pages, &args, None, None)
+}
+
+fn spawn_elf(
+    name: String,
+    elf: &ElfFile<'static>,
+    stack_pages: u64,
+    args: &AppArgs,
+    trace: Option<TraceMode>,
+    stdout: Option<u8>,
+) -> Result<ProcessId, SpawnError> {
+    if !allow_new_process() {
+        return Err(SpawnError::Limited);
+    }
+
+    // the segments are checked before interrupts are held off,
+    // and before the process is made
+    let image = Image::new(elf)?;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         let process_name = name.to_lowercase();
 
-        let parent = Arc::downgrade(&manager.current());
+        let current = manager.current();
+        let data = match stdout {
+            Some(fd) => {
+                let res = current.read().share_fd(fd).ok_or(SpawnError::BadFd)?;
+                Some(ProcessData::new().set_stdout(res))
+            }
+            None => None,
+        };
 
-        let pid = manager.spawn(elf, name, Some(parent), None);
+        let parent = Arc::downgrade(&current);
+        let inherited = current.read().syscalls();
+
+        let pid = manager.spawn(elf, image, stack_pages, name, args, Some(parent), data);
+
+        // before the process gets the chance to run
+        let proc = manager.get_proc(&pid).unwrap();
+        let mut inner = proc.write();
+        if trace.is_some() {
+            inner.set_trace(trace);
+        }
+        // no more syscalls than its parent may make
+        for set in [inherited, boot::declared_syscalls(elf)].into_iter().flatten() {
+            inner.restrict_syscalls(set);
+        }
+        drop(inner);
 
         debug!("Spawned process: {}#{}", process_name, pid);
-        pid
-    });
+        Ok(pid)
+    })
+}
+
+/// Answer a syscall of the current process from its replayed trace
+///
+/// returns false if the syscall should run as usual
+pub fn replay_syscall(
+    syscall: &Syscall,
+    buf: usize,
+    len: usize,
+    context: &mut ProcessContext,
+) -> bool {
+    if !trace::is_traced(syscall) {
+        return false;
+    }
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let proc = get_process_manager().current();
+        let mut inner = proc.write();
+
+        let trace = match inner.trace_mut() {
+            Some(TraceMode::Replay(trace)) => trace,
+            _ => return false,
+        };
+
+        let (ret, count) = match trace.peek(syscall) {
+            Some(entry) => (entry.ret, entry.data.len().min(len)),
+            None => {
+                warn!(
+                    "Replay of {}#{} diverged at {:?}, running live from now on.",
+                    inner.name(),
+                    proc.pid(),
+                    syscall
+                );
+                inner.set_trace(None);
+                return false;
+            }
+        };
+
+        let writable = count == 0
+            || VirtAddr::try_new(buf as u64)
+                .is_ok_and(|addr| inner.vm().check_user_range(addr, count as u64, true));
+        // the syscall fails live, the entry is kept for the next one
+        if !writable {
+            return false;
+        }
+
+        let Some(TraceMode::Replay(trace)) = inner.trace_mut() else {
+            return false;
+        };
+        if let Some(entry) = trace.peek(syscall) {
+            unsafe {
+                core::ptr::copy_nonoverlapping(entry.data.as_ptr(), buf as *mut u8, count);
+            }
+        }
+        trace.advance();
+        context.set_rax(ret);
+
+        if trace.is_empty() {
+            info!("Replay of {}#{} finished.", inner.name(), proc.pid());
+            inner.set_trace(None);
+        }
+        true
+    })
+}
+
+/// Log the result of a syscall if the current process is recorded
+pub fn record_syscall(syscall: &Syscall, buf: usize, context: &ProcessContext) {
+    if !trace::is_traced(syscall) {
+        return;
+    }
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let proc = get_process_manager().current();
+        let mut inner = proc.write();
+
+        let trace = match inner.trace_mut() {
+            Some(TraceMode::Record(trace)) => trace,
+            _ => return,
+        };
+
+        let ret = context.regs.rax;
+        let data = match syscall {
+            // the bytes were just read into the caller's buffer
+            Syscall::Read if (ret as isize) > 0 => unsafe {
+                core::slice::from_raw_parts(buf as *const u8, ret)
+            },
+            _ => &[],
+        };
+        if trace.record(*syscall, ret, data) {
+            return;
+        }
+        let len = trace.len();
+
+        // kept as it is, a replay of it runs live once past the end
+        warn!(
+            "Trace of {}#{} is full at {} entries, recording stops.",
+            inner.name(),
+            proc.pid(),
+            len
+        );
+        if let Some(TraceMode::Record(trace)) = inner.take_trace() {
+            trace::save(inner.name(), trace);
+        }
+    })
+}
+
+/// Copy the latest scheduling events of `pid` into `buf`
+pub fn sched_history(pid: ProcessId, buf: &mut [SchedEvent]) -> Option<usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager()
+            .get_proc(&pid)
+            .map(|proc| proc.sched_history(buf))
+    })
+}
+
+/// The resources `pid` has used so far
+pub fn rusage(pid: ProcessId) -> Option<Rusage> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let proc = get_process_manager().get_proc(&pid)?;
+        let rusage = proc.read().rusage();
+        Some(rusage)
+    })
+}
+
+/// Memory and swap of the system
+pub fn sysinfo() -> Sysinfo {
+    let (total_ram, free_ram, shared_ram) =
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let alloc = get_frame_alloc_for_sure();
+            (
+                alloc.frames_total(),
+                alloc.frames_free(),
+                alloc.frames_shared(),
+            )
+        });
+    let (total_swap, free_swap, swap_outs, swap_ins) = swap::stats();
 
-    Ok(pid)
+    let bytes = |pages: usize| pages as u64 * PAGE_SIZE;
+    Sysinfo {
+        total_ram: bytes(total_ram),
+        free_ram: bytes(free_ram),
+        shared_ram: bytes(shared_ram),
+        total_swap: bytes(total_swap),
+        free_swap: bytes(free_swap),
+        swap_outs: swap_outs as u64,
+        swap_ins: swap_ins as u64,
+    }
+}
+
+/// What `pid` is and what it has used so far
+pub fn proc_info(pid: ProcessId) -> Option<ProcInfo> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().get_proc(&pid).map(|proc| proc.info())
+    })
+}
+
+/// The page table of `pid`
+///
+/// never waits for a lock, so the monitor can call it from an interrupt
+pub fn try_page_table(pid: ProcessId) -> Option<PageTableContext> {
+    let proc = get_process_manager().try_get_proc(&pid)?;
+    let inner = proc.try_read()?;
+    inner.try_vm().map(|vm| vm.page_table.share())
+}
+
+/// Kill the current process if it has used up its cpu time limit
+///
+/// called on every timer tick, return true if it was killed
+pub fn enforce_cpu_limit(context: &mut ProcessContext) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let pid = processor::current_pid();
+        if pid == KERNEL_PID {
+            return false;
+        }
+
+        let now = crate::utils::clock::now_nanos();
+        let exceeded = manager
+            .try_get_proc(&pid)
+            .and_then(|proc| proc.try_read().map(|inner| inner.cpu().exceeded(now)))
+            .unwrap_or(false);
+        if !exceeded {
+            return false;
+        }
+
+        warn!("Process #{} exceeded its cpu time limit, killed it.", pid);
+        manager.kill_self(EXIT_CPU_LIMIT as isize);
+        manager.switch_next(context);
+        true
+    })
+}
+
+/// Kill the current process if its filter does not allow syscall `num`
+///
+/// return true if it was killed, see `SyscallSet`
+pub fn filter_syscall(num: usize, context: &mut ProcessContext) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let proc = manager.current();
+        if proc.read().allows_syscall(num) {
+            return false;
+        }
+
+        warn!(
+            "Process #{} made syscall {:?} it did not declare, killed it.",
+            proc.pid(),
+            Syscall::from(num)
+        );
+        manager.kill_self(EXIT_SYSCALL_DENIED as isize);
+        manager.switch_next(context);
+        true
+    })
+}
+
+/// Check if any process is waiting to run, true if unsure
+pub fn has_ready() -> bool {
+    get_process_manager().has_ready()
+}
+
+/// Nanoseconds left before the current process hits its cpu limit or
+/// runs out of its real-time budget, `None` if it has neither or it
+/// cannot be checked now
+pub fn cpu_limit_left() -> Option<u64> {
+    let manager = get_process_manager();
+    let proc = manager.try_get_proc(&processor::current_pid())?;
+    let inner = proc.try_read()?;
+    let cpu = inner.cpu();
+    let limit = match cpu.limit() {
+        0 => None,
+        limit => Some(limit.saturating_sub(cpu.used(crate::utils::clock::now_nanos()))),
+    };
+    match (limit, inner.budget_left()) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Give `pid` a real-time reservation of `budget` nanoseconds in every
+/// `period`, or take it out of the class if `budget` is 0
+pub fn sched_rt(pid: ProcessId, period: u64, budget: u64) -> Result<(), usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().reserve(pid, period, budget)
+    })
+}
+
+/// Get the cpu time limit of `pid` in nanoseconds, setting it to `limit` if given
+///
+/// return the old limit, 0 means unlimited
+pub fn cpu_limit(pid: ProcessId, limit: Option<u64>) -> Option<u64> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let proc = get_process_manager().get_proc(&pid)?;
+        let mut inner = proc.write();
+        Some(match limit {
+            Some(limit) => inner.cpu_mut().set_limit(limit),
+            None => inner.cpu().limit(),
+        })
+    })
+}
+
+/// Get the niceness of `pid`, setting it to `nice` if given
+///
+/// return the old one
+pub fn nice(pid: ProcessId, nice: Option<isize>) -> Option<isize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let proc = get_process_manager().get_proc(&pid)?;
+        let mut inner = proc.write();
+        Some(match nice {
+            Some(nice) => inner.set_nice(nice),
+            None => inner.nice(),
+        })
+    })
+}
+
+/// Get the priority of `pid`, setting it to `priority`, `None` if it does not exist
+pub fn priority(pid: ProcessId, priority: Option<usize>) -> Option<usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let proc = get_process_manager().get_proc(&pid)?;
+        let mut inner = proc.write();
+        Some(match priority {
+            Some(priority) => inner.set_priority(priority),
+            None => inner.priority(),
+        })
+    })
+}
+
+/// Check if `pid` may do what only root may
+///
+/// there are no users yet, root is a flag the kernel starts with and every
+/// process inherits on fork and spawn, so init and what it runs are root.
+/// Adoption by the kernel does not make an orphan root.
+pub fn is_root(pid: ProcessId) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager()
+            .get_proc(&pid)
+            .is_some_and(|proc| proc.read().is_root())
+    })
+}
+
+/// Check if `ancestor` is `pid` itself or one of its ancestors
+pub fn is_ancestor(ancestor: ProcessId, pid: ProcessId) -> bool {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut next = get_process_manager().get_proc(&pid);
+        while let Some(proc) = next {
+            if proc.pid() == ancestor {
+                return true;
+            }
+            next = proc.read().parent();
+        }
+        false
+    })
 }
 
 pub fn current_proc_info() {
     debug!("{:#?}", get_process_manager().current())
 }
 
-pub fn handle_page_fault(addr: VirtAddr, err_code: PageFaultErrorCode) -> bool {
+pub fn handle_page_fault(addr: VirtAddr, err_code: PageFaultErrorCode) -> PageFaultOutcome {
     x86_64::instructions::interrupts::without_interrupts(|| {
-        get_process_manager().handle_page_fault(addr, err_code)
+        let outcome = get_process_manager().handle_page_fault(addr, err_code);
+        fault::record(&outcome);
+        outcome
     })
 }
 
 pub fn list_app() {
     x86_64::instructions::interrupts::without_interrupts(|| {
+        let shared = host::names();
+        if !shared.is_empty() {
+            println!(">>> Apps shared by the host:");
+            println!("  {}", shared.join(" "));
+        }
+
+        let on_disk = disk::names();
+        if !on_disk.is_empty() {
+            println!(">>> Apps on disk:");
+            println!("  {}", on_disk.join(" "));
+        }
+
         let app_list = get_process_manager().app_list();
         if app_list.is_none() {
             println!(">>> No app found in list!");
             return;
         }
 
-        let apps = app_list
-            .unwrap()
-            .iter()
-            .map(|app| app.name.as_str())
-            .collect::<Vec<&str>>()
-            .join(", ");
-
-        println!(">>> App list: {}", apps);
+        println!(">>> App list:");
+        for app in app_list.unwrap().iter() {
+            let info = &app.info;
+            // how many syscalls the app declared, if it did
+            let syscalls = info
+                .syscalls
+                .map_or(String::from("-"), |set| set.len().to_string());
+            println!(
+                "  {:<10} {:<8} {:>3} {:<24} {}",
+                info.name.as_str(),
+                info.version.as_str(),
+                syscalls,
+                if info.usage.is_empty() {
+                    info.name.as_str()
+                } else {
+                    info.usage.as_str()
+                },
+                info.description.as_str()
+            );
+        }
     });
 }
 
 pub fn sem_wait(key: u32, context: &mut ProcessContext) {
+    sem_wait_until(key, None, context)
+}
+
+/// Wait on semaphore `key` for at most `nanos`, `ETIMEDOUT` once they are over
+pub fn sem_wait_timeout(key: u32, nanos: u64, context: &mut ProcessContext) {
+    let nanos = nanos.min(i64::MAX as u64) as i64;
+    let deadline = crate::utils::clock::monotonic_nanos().saturating_add(nanos);
+    sem_wait_until(key, Some(deadline), context)
+}
+
+/// Take semaphore `key` only if it is free, `EAGAIN` if it is not
+pub fn sem_try_wait(key: u32) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        match manager.current().read().sem_try_wait(key) {
+            SemaphoreResult::Ok => 0,
+            SemaphoreResult::NotExist => errno_ret(ENOENT),
+            SemaphoreResult::WouldBlock => errno_ret(EAGAIN),
+            _ => unreachable!(),
+        }
+    })
+}
+
+fn sem_wait_until(key: u32, deadline: Option<i64>, context: &mut ProcessContext) {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
         let pid = processor::current_pid();
         let ret = manager.current().write().sem_wait(key, pid);
         match ret {
             SemaphoreResult::Ok => context.set_rax(0),
-            SemaphoreResult::NotExist => context.set_rax(1),
+            SemaphoreResult::NotExist => context.set_rax(errno_ret(ENOENT)),
             SemaphoreResult::Block(_pid) => {
                 // FIXME: save, block it, then switch to next
                 //        use `save_current` and `switch_next`
                 let pid = manager.save_current(context);
-                manager.block(pid);
+                manager.record_sched(pid, SCHED_BLOCK, BLOCK_SEM);
+                match deadline {
+                    Some(deadline) => manager.sem_wait_until(pid, key, deadline),
+                    None => manager.block(pid),
+                }
                 manager.switch_next(context);
             }
             _ => unreachable!(),
@@ -223,13 +1313,104 @@ pub fn sem_signal(key: u32, context: &mut ProcessContext) {
         let ret = manager.current().write().sem_signal(key);
         match ret {
             SemaphoreResult::Ok => context.set_rax(0),
-            SemaphoreResult::NotExist => context.set_rax(1),
-            SemaphoreResult::WakeUp(pid) => manager.wake_up(pid, 0),
+            SemaphoreResult::NotExist => context.set_rax(errno_ret(ENOENT)),
+            SemaphoreResult::WakeUp(pid) => {
+                manager.cancel_sem_timeout(pid);
+                manager.wake_up(pid, 0);
+                context.set_rax(0);
+            }
             _ => unreachable!(),
         }
     })
 }
 
+/// Take or release the lock on `fd`, see `Syscall::Flock`
+pub fn flock(fd: u8, op: usize, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let (node, owner) = match manager.current().read().flock_target(fd) {
+            Some(target) => target,
+            None => return context.set_rax(errno_ret(EBADF)),
+        };
+
+        let wait = (op & LOCK_NB == 0).then(processor::current_pid);
+        let (result, granted) = match op & !LOCK_NB {
+            LOCK_SH => flock::lock(node, owner, false, wait),
+            LOCK_EX => flock::lock(node, owner, true, wait),
+            LOCK_UN => (flock::FlockResult::Ok, flock::unlock(node, owner)),
+            _ => return context.set_rax(errno_ret(EINVAL)),
+        };
+
+        for pid in granted {
+            manager.wake_up(pid, 0);
+        }
+
+        match result {
+            flock::FlockResult::Ok => context.set_rax(0),
+            flock::FlockResult::WouldBlock => context.set_rax(errno_ret(EAGAIN)),
+            flock::FlockResult::Block => {
+                // woken with 0 once the lock is handed over
+                let pid = manager.save_current(context);
+                manager.record_sched(pid, SCHED_BLOCK, BLOCK_FLOCK);
+                manager.block(pid);
+                manager.switch_next(context);
+            }
+        }
+    })
+}
+
+/// Release a lock held by a handle that is dropped
+pub fn flock_release(node: usize, owner: u64) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        for pid in flock::unlock(node, owner) {
+            manager.wake_up(pid, 0);
+        }
+    })
+}
+
+/// The futex of the word at `addr` in the current address space
+fn futex_key(manager: &ProcessManager, addr: usize) -> futex::FutexKey {
+    let space = manager.current().read().vm().page_table.reg.addr;
+    (space.start_address().as_u64(), addr)
+}
+
+/// Block on the `u32` at `addr` while it holds `val`, see `Syscall::Futex`
+///
+/// fails with `EAGAIN` if it no longer does. The word is read with
+/// interrupts disabled, so a wake cannot come between it and blocking.
+pub fn futex_wait(addr: usize, val: u32, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let mut word = [0u32];
+        if let Err(errno) = unsafe { copy_slice_from_user(&mut word, addr) } {
+            return context.set_rax(errno_ret(errno));
+        }
+        if word[0] != val {
+            return context.set_rax(errno_ret(EAGAIN));
+        }
+
+        // woken with 0 by `futex_wake`
+        let manager = get_process_manager();
+        let pid = manager.save_current(context);
+        futex::wait(futex_key(manager, addr), pid);
+        manager.record_sched(pid, SCHED_BLOCK, BLOCK_FUTEX);
+        manager.block(pid);
+        manager.switch_next(context);
+    })
+}
+
+/// Wake up to `count` processes waiting on the word at `addr`, return how many
+pub fn futex_wake(addr: usize, count: usize) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let manager = get_process_manager();
+        let woken = futex::wake(futex_key(manager, addr), count);
+        for &pid in woken.iter() {
+            manager.wake_up(pid, 0);
+        }
+        woken.len()
+    })
+}
+
 pub fn new_sem(key: u32, init: usize) -> usize {
     x86_64::instructions::interrupts::without_interrupts(|| {
         let manager = get_process_manager();
@@ -237,7 +1418,7 @@ pub fn new_sem(key: u32, init: usize) -> usize {
         if ret {
             0
         } else {
-            1
+            errno_ret(EEXIST)
         }
     })
 }
@@ -249,7 +1430,7 @@ pub fn remove_sem(key: u32) -> usize {
         if ret {
             0
         } else {
-            1
+            errno_ret(ENOENT)
         }
     })
 }
@@ -259,4 +1440,154 @@ pub fn brk(addr: Option<usize>) -> usize {
         // NOTE: `brk` does not need to get write lock
         get_process_manager().current().read().brk(addr)
     })
+}
+
+/// Map `len` bytes, read from `file` at an offset if given, see `vm::mmap`
+pub fn mmap(
+    addr: Option<usize>,
+    len: usize,
+    flags: PageTableFlags,
+    file: Option<(u8, usize)>,
+) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager()
+            .current()
+            .read()
+            .mmap(addr, len, flags, file)
+    })
+}
+
+/// Write the dirty pages of shared file mappings in `[addr, addr + len)` back
+pub fn msync(addr: usize, len: usize) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().read().msync(addr, len)
+    })
+}
+
+pub fn mprotect(addr: usize, len: usize, flags: PageTableFlags) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager()
+            .current()
+            .read()
+            .mprotect(addr, len, flags)
+    })
+}
+
+pub fn munmap(addr: usize, len: usize) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let ret = get_process_manager().current().read().munmap(addr, len);
+        // it may have been the last mapping of a shared memory segment
+        shm::collect();
+        ret
+    })
+}
+
+/// Open shared memory segment `key`, creating it with `size` bytes, see `shm`
+pub fn shm_open(key: u32, size: usize) -> Result<usize, usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| shm::open(key, size))
+}
+
+/// Map segment `key` at `addr`, or where it fits if `None`
+pub fn shm_map(key: u32, addr: Option<VirtAddr>) -> Result<VirtAddr, usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let proc = get_process_manager().current();
+        let inner = proc.read();
+        shm::map(key, |frames| inner.vm().map_shared(addr, frames))
+    })
+}
+
+/// Unmap the segment mapped at `addr`, removing it if no one else maps it
+pub fn shm_unmap(addr: VirtAddr) -> Result<(), usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager().current().read().vm().unmap_shared(addr)?;
+        shm::collect();
+        Ok(())
+    })
+}
+
+/// Map the framebuffer into the current process, which takes it until
+/// it exits, return where its first pixel is mapped
+#[cfg(feature = "graphics")]
+pub fn map_frame_buffer() -> Result<(VirtAddr, &'static boot::FrameBufferInfo), usize> {
+    use x86_64::structures::paging::PhysFrame;
+    use x86_64::PhysAddr;
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let proc = get_process_manager().current();
+        let pid = proc.pid().0;
+        let info = crate::drivers::display::take(pid)?;
+
+        let start = PhysFrame::containing_address(PhysAddr::new(info.addr));
+        let end = PhysFrame::containing_address(PhysAddr::new(info.addr + info.size as u64 - 1));
+        let frames: Vec<PhysFrame> = PhysFrame::range_inclusive(start, end).collect();
+
+        // mapped as a shared memory segment, the frames are never freed
+        let Some(addr) = proc.read().vm().map_shared(None, &frames) else {
+            crate::drivers::display::release(pid);
+            return Err(ENOMEM);
+        };
+        Ok((addr + (info.addr - start.start_address().as_u64()), info))
+    })
+}
+
+/// Open message queue `key`, creating it if needed, return its message size
+pub fn mq_open(key: u32, capacity: usize, msg_size: usize) -> Result<usize, usize> {
+    x86_64::instructions::interrupts::without_interrupts(|| ipc::open(key, capacity, msg_size))
+}
+
+/// Remove message queue `key`, the processes blocked on it then fail
+/// with `ENOENT` as their syscall runs again
+pub fn mq_unlink(key: u32) -> Result<(), usize> {
+    let waiting = x86_64::instructions::interrupts::without_interrupts(|| ipc::remove(key))?;
+    wake_blocked(waiting);
+    Ok(())
+}
+
+/// Send `msg` to queue `key`, blocking while it is full
+pub fn mq_send(key: u32, msg: &[u8], context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let transfer = ipc::send(key, msg, processor::current_pid());
+        finish_transfer(transfer, context);
+    })
+}
+
+/// Receive a message of queue `key` into the `len` bytes at `buf`,
+/// blocking while it is empty, the length of the message is returned
+pub fn mq_recv(key: u32, buf: usize, len: usize, context: &mut ProcessContext) {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let transfer = ipc::receive(key, len, processor::current_pid(), |msg| {
+            copy_slice_to_user(buf, msg)
+        });
+        finish_transfer(transfer, context);
+    })
+}
+
+/// Set the result of a send or receive, or block the process on the queue
+fn finish_transfer(transfer: Result<ipc::Transfer, usize>, context: &mut ProcessContext) {
+    let manager = get_process_manager();
+    match transfer {
+        Ok(ipc::Transfer::Done(ret, waiting)) => {
+            for pid in waiting {
+                manager.wake_restart(pid);
+            }
+            context.set_rax(ret);
+        }
+        Ok(ipc::Transfer::Block) => {
+            context.rewind_syscall();
+            let pid = manager.save_current(context);
+            manager.record_sched(pid, SCHED_BLOCK, BLOCK_MQ);
+            manager.block(pid);
+            manager.switch_next(context);
+        }
+        Err(errno) => context.set_rax(errno_ret(errno)),
+    }
+}
+
+pub fn madvise(addr: usize, len: usize, advice: mmap::Advice) -> usize {
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        get_process_manager()
+            .current()
+            .read()
+            .madvise(addr, len, advice)
+    })
 }
\ No newline at end of file
@@ -1,12 +1,18 @@
 use crate::memory::*;
+use core::ops::RangeInclusive;
 use core::ptr::copy_nonoverlapping;
 
-use alloc::sync::Arc;
+use super::{swap, ProcessId, ALLOW_WX};
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use spin::Mutex;
 use x86_64::{
+    instructions::tlb,
     registers::control::{Cr3, Cr3Flags},
-    structures::paging::*,
-    VirtAddr,
+    structures::paging::{page::PageRange, page_table::PageTableEntry, *},
+    PhysAddr, VirtAddr,
 };
+use xmas_elf::program;
 
 pub struct Cr3RegValue {
     pub addr: PhysFrame,
@@ -31,6 +37,10 @@ impl PageTableContext {
         }
     }
 
+    /// Create a page table for a new process
+    ///
+    /// only the level 4 table is copied, every lower level table it points
+    /// to, including the whole kernel half, is shared by reference.
     pub fn clone_level_4(&self) -> Self {
         // 1. alloc new page table
         let mut frame_alloc = crate::memory::get_frame_alloc_for_sure();
@@ -48,9 +58,14 @@ impl PageTableContext {
         }
 
         // 3. create page table
-        Self {
-            reg: Arc::new(Cr3RegValue::new(page_table_addr, Cr3Flags::empty())),
-        }
+        Self::user(page_table_addr)
+    }
+
+    /// The page table of a user process at `frame`, seen by `user_spaces`
+    fn user(frame: PhysFrame) -> Self {
+        let reg = Arc::new(Cr3RegValue::new(frame, Cr3Flags::empty()));
+        USER_SPACES.lock().push(Arc::downgrade(&reg));
+        Self { reg }
     }
 
     pub fn using_count(&self) -> usize {
@@ -72,13 +87,466 @@ impl PageTableContext {
         }
     }
 
-    pub fn fork(&self) -> Self {
-        // forked process shares the page table
+    /// Another reference to the same page table
+    ///
+    /// nothing is copied, changes made through one are seen by both.
+    pub fn share(&self) -> Self {
         Self {
             reg: self.reg.clone(),
         }
     }
 
+    /// Copy the page table for a forked process
+    ///
+    /// only the level 4 table is copied. The kernel half, and any table
+    /// not reachable from user mode, is shared by reference for good. The
+    /// user tables below it are shared too, counted as frames are, and
+    /// made read-only from the level 4 entries down, so code and read-only
+    /// data never get copied at all. The first write under a table, or
+    /// any change to its mappings, splits the tables on its way, see
+    /// `unshare`.
+    pub fn fork(&self) -> Self {
+        let mut frame_alloc = crate::memory::get_frame_alloc_for_sure();
+        let page_table_addr = frame_alloc
+            .allocate_frame()
+            .expect("Cannot alloc page table for forked process.");
+
+        let table = table_at_mut(self.reg.addr.start_address());
+        let copy = table_at_mut(page_table_addr.start_address());
+
+        for (i, (entry, copied)) in table.iter_mut().zip(copy.iter_mut()).enumerate() {
+            let flags = entry.flags();
+            if i < 256 && flags.contains(PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE) {
+                let flags = flags - PageTableFlags::WRITABLE;
+                entry.set_flags(flags);
+                frame_alloc.share_frame(PhysFrame::containing_address(entry.addr()));
+                copied.set_addr(entry.addr(), flags);
+            } else {
+                *copied = entry.clone();
+            }
+        }
+
+        // the pages just made read-only may still be writable in the tlb
+        tlb::flush_all();
+
+        Self::user(page_table_addr)
+    }
+
+    /// Call `f` on every present page overlapping `[start, last]`, in address order
+    pub fn walk(&self, start: u64, last: u64, f: &mut impl FnMut(Mapping)) {
+        walk_table(
+            table_at(self.reg.addr.start_address()),
+            4,
+            0,
+            start,
+            last,
+            f,
+        );
+    }
+}
+
+/// Virtually and physically contiguous pages with the same flags
+#[derive(Debug, Clone, Copy)]
+pub struct Mapping {
+    pub virt: VirtAddr,
+    pub phys: PhysAddr,
+    /// Bytes mapped, a multiple of `page_size`
+    pub len: u64,
+    /// 4 KiB, 2 MiB or 1 GiB
+    pub page_size: u64,
+    pub flags: PageTableFlags,
+}
+
+/// Last address of the user half of the address space
+pub const USER_LAST: u64 = 0x0000_7fff_ffff_ffff;
+
+/// A page shared copy-on-write with a forked process
+///
+/// the page is mapped read-only, the first write to it gets a copy
+/// of the frame, or the frame itself once no one else maps it.
+pub const COW: PageTableFlags = PageTableFlags::BIT_9;
+
+/// A page written by forked processes alike, it is never copied on write
+pub const SHARED: PageTableFlags = PageTableFlags::BIT_10;
+
+/// A private page of a memory mapping, the only kind of page swapped out,
+/// see `swap`
+pub const ANON: PageTableFlags = PageTableFlags::BIT_52;
+
+/// A page swapped out, not present, the entry holds its slot in the swap
+/// area instead of a frame, and the flags to map it back with
+pub const SWAPPED: PageTableFlags = PageTableFlags::BIT_11;
+
+/// The page tables of user processes, as they are created
+static USER_SPACES: Mutex<Vec<Weak<Cr3RegValue>>> = Mutex::new(Vec::new());
+
+/// The level 4 tables of the user processes alive, oldest first
+pub fn user_spaces() -> Vec<PhysFrame> {
+    let mut spaces = USER_SPACES.lock();
+    spaces.retain(|space| space.strong_count() > 0);
+    spaces
+        .iter()
+        .filter_map(Weak::upgrade)
+        .map(|reg| reg.addr)
+        .collect()
+}
+
+/// The level 1 entry of `page`, present or not, `None` if a table above
+/// it is missing or maps a huge page
+pub fn entry_mut<'a>(
+    mapper: &'a mut OffsetPageTable<'static>,
+    page: Page,
+) -> Option<&'a mut PageTableEntry> {
+    let mut table = mapper.level_4_table_mut();
+    for index in [page.p4_index(), page.p3_index(), page.p2_index()] {
+        let entry = &table[index];
+        if !entry.flags().contains(PageTableFlags::PRESENT)
+            || entry.flags().contains(PageTableFlags::HUGE_PAGE)
+        {
+            return None;
+        }
+        table = table_at_mut(entry.addr());
+    }
+    Some(&mut table[page.p1_index()])
+}
+
+/// Whether `page` is mapped through a table still shared with a forked
+/// process, which `release_shared` drops whole
+pub fn in_shared_table(
+    mapper: &OffsetPageTable<'static>,
+    page: Page,
+    alloc: &BootInfoFrameAllocator,
+) -> bool {
+    let mut table = mapper.level_4_table();
+    for index in [page.p4_index(), page.p3_index(), page.p2_index()] {
+        let entry = &table[index];
+        if !entry.flags().contains(PageTableFlags::PRESENT)
+            || entry.flags().contains(PageTableFlags::HUGE_PAGE)
+        {
+            return false;
+        }
+        if alloc.is_shared(PhysFrame::containing_address(entry.addr())) {
+            return true;
+        }
+        table = table_at(entry.addr());
+    }
+    false
+}
+
+/// Call `f` on the level 1 entries of the user tables of `space` from
+/// `start` to `last`, in address order, until it returns false
+///
+/// return the address of the entry it stopped at.
+pub fn scan_entries(
+    space: PhysFrame,
+    start: u64,
+    last: u64,
+    f: &mut impl FnMut(VirtAddr, &mut PageTableEntry) -> bool,
+) -> Option<u64> {
+    scan_table(table_at_mut(space.start_address()), 4, 0, start, last, f)
+}
+
+/// Give `pages` of `mapper` page tables of their own, before their
+/// mappings change
+///
+/// a table on their way still shared with a forked process is copied,
+/// the tables and frames it points to are shared by the copy in turn,
+/// writable pages become `COW`. Panics like `fork` if out of frames.
+pub fn unshare(
+    mapper: &mut OffsetPageTable<'static>,
+    pages: PageRange,
+    alloc: &mut BootInfoFrameAllocator,
+) {
+    if pages.is_empty() {
+        return;
+    }
+
+    let start = pages.start.start_address().as_u64();
+    let last = pages.end.start_address().as_u64() - 1;
+    if unshare_table(mapper.level_4_table_mut(), 4, 0, start, last, alloc) {
+        tlb::flush_all();
+    }
+}
+
+/// Drop the references of `mapper` to the user tables it still shares
+/// with forked processes, for the last of its users exiting
+///
+/// the mappings under them are left to the processes still using them.
+pub fn release_shared(mapper: &mut OffsetPageTable<'static>, alloc: &mut BootInfoFrameAllocator) {
+    release_table(mapper.level_4_table_mut(), 4, alloc);
+}
+
+/// Whether user writes to `page` go through without a fault, it must be
+/// writable in every table on its way
+pub fn is_writable(mapper: &OffsetPageTable<'static>, page: Page) -> bool {
+    let mut table = mapper.level_4_table();
+    let indexes = [
+        page.p4_index(),
+        page.p3_index(),
+        page.p2_index(),
+        page.p1_index(),
+    ];
+    for (level, index) in indexes.into_iter().enumerate() {
+        let flags = table[index].flags();
+        if !flags.contains(PageTableFlags::PRESENT | PageTableFlags::WRITABLE) {
+            return false;
+        }
+        if level == 3 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            return true;
+        }
+        table = table_at(table[index].addr());
+    }
+    false
+}
+
+/// Whether pages with `flags` can be both written and executed
+pub fn is_wx(flags: PageTableFlags) -> bool {
+    flags.contains(PageTableFlags::WRITABLE) && !flags.contains(PageTableFlags::NO_EXECUTE)
+}
+
+/// Page flags for a user ELF segment with `flags`, pages can always be read
+///
+/// `None` for a segment both writable and executable, unless `ALLOW_WX`
+/// is set, so `.text` stays read-only and data is never run.
+pub fn segment_flags(flags: program::Flags) -> Option<PageTableFlags> {
+    let mut page_flags = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if !flags.is_execute() {
+        page_flags |= PageTableFlags::NO_EXECUTE;
+    }
+    if flags.is_write() {
+        page_flags |= PageTableFlags::WRITABLE;
+    }
+
+    (!is_wx(page_flags) || ALLOW_WX.get() != 0).then_some(page_flags)
+}
+
+/// Flags set by the cpu on access, they do not split a mapping
+const ACCESS_FLAGS: PageTableFlags = PageTableFlags::ACCESSED.union(PageTableFlags::DIRTY);
+
+impl Mapping {
+    /// Extend `self` by `next` if it continues it
+    fn merge(&mut self, next: &Mapping) -> bool {
+        let mergeable = self.virt + self.len == next.virt
+            && self.phys + self.len == next.phys
+            && self.page_size == next.page_size
+            && self.flags == next.flags;
+
+        if mergeable {
+            self.len += next.len;
+        }
+        mergeable
+    }
+}
+
+/// List the mappings of `pid` overlapping `range`, merged where contiguous
+///
+/// never waits for a lock, `None` if the process is gone or locked
+pub fn dump(pid: ProcessId, range: RangeInclusive<u64>) -> Option<Vec<Mapping>> {
+    let page_table = super::try_page_table(pid)?;
+    let mut mappings: Vec<Mapping> = Vec::new();
+
+    page_table.walk(*range.start(), *range.end(), &mut |mapping| {
+        let merged = mappings.last_mut().is_some_and(|last| last.merge(&mapping));
+        if !merged {
+            mappings.push(mapping);
+        }
+    });
+
+    Some(mappings)
+}
+
+fn table_at(addr: PhysAddr) -> &'static PageTable {
+    unsafe { &*(physical_to_virtual(addr.as_u64()) as *const PageTable) }
+}
+
+fn table_at_mut(addr: PhysAddr) -> &'static mut PageTable {
+    unsafe { &mut *(physical_to_virtual(addr.as_u64()) as *mut PageTable) }
+}
+
+/// Copy a user page table of `level` that is shared with a forked process
+///
+/// the tables and frames it points to are shared by both copies, and
+/// counted, see `unshare`.
+fn split_table(table: &mut PageTable, level: u32, alloc: &mut BootInfoFrameAllocator) -> PhysFrame {
+    let frame = alloc
+        .allocate_frame()
+        .expect("Cannot alloc page table for forked process.");
+    let copy = table_at_mut(frame.start_address());
+
+    for (entry, copied) in table.iter_mut().zip(copy.iter_mut()) {
+        let mut flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            // a page swapped out is shared by its slot instead
+            if level == 1 && flags.contains(SWAPPED) {
+                swap::share(entry);
+            }
+            *copied = entry.clone();
+            continue;
+        }
+
+        if level == 1 {
+            if flags.contains(PageTableFlags::WRITABLE) && !flags.contains(SHARED) {
+                flags = (flags - PageTableFlags::WRITABLE) | COW;
+                entry.set_flags(flags);
+            }
+            alloc.share_frame(PhysFrame::containing_address(entry.addr()));
+        } else if !flags.contains(PageTableFlags::HUGE_PAGE) {
+            // writes under the table fault until it is split too
+            flags -= PageTableFlags::WRITABLE;
+            entry.set_flags(flags);
+            alloc.share_frame(PhysFrame::containing_address(entry.addr()));
+        }
+        copied.set_addr(entry.addr(), flags);
+    }
+
+    frame
+}
+
+/// Split the user tables of `level` below `table` that are shared and
+/// overlap `[start, last]`, return whether any entry changed
+fn unshare_table(
+    table: &mut PageTable,
+    level: u32,
+    base: u64,
+    start: u64,
+    last: u64,
+    alloc: &mut BootInfoFrameAllocator,
+) -> bool {
+    let entry_size = 1u64 << (12 + 9 * (level - 1));
+    let mut changed = false;
+
+    // only the user half, which is never sign extended
+    let count = if level == 4 { 256 } else { 512 };
+    for (i, entry) in table.iter_mut().enumerate().take(count) {
+        let virt = base + i as u64 * entry_size;
+        if virt + (entry_size - 1) < start || virt > last {
+            continue;
+        }
+
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE)
+            || flags.contains(PageTableFlags::HUGE_PAGE)
+        {
+            continue;
+        }
+
+        // the pages decide whether they can be written once it is private
+        let lower = PhysFrame::containing_address(entry.addr());
+        if alloc.is_shared(lower) {
+            let copy = split_table(table_at_mut(entry.addr()), level - 1, alloc);
+            // drop the reference to the shared table
+            unsafe { alloc.deallocate_frame(lower) };
+            entry.set_frame(copy, flags | PageTableFlags::WRITABLE);
+            changed = true;
+        } else if !flags.contains(PageTableFlags::WRITABLE) {
+            entry.set_flags(flags | PageTableFlags::WRITABLE);
+            changed = true;
+        }
+
+        if level > 2 {
+            let lower = table_at_mut(entry.addr());
+            changed |= unshare_table(lower, level - 1, virt, start, last, alloc);
+        }
+    }
+
+    changed
+}
+
+fn release_table(table: &mut PageTable, level: u32, alloc: &mut BootInfoFrameAllocator) {
+    let count = if level == 4 { 256 } else { 512 };
+    for entry in table.iter_mut().take(count) {
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE)
+            || flags.contains(PageTableFlags::HUGE_PAGE)
+        {
+            continue;
+        }
+
+        let lower = PhysFrame::containing_address(entry.addr());
+        if alloc.is_shared(lower) {
+            unsafe { alloc.deallocate_frame(lower) };
+            entry.set_unused();
+        } else if level > 2 {
+            release_table(table_at_mut(entry.addr()), level - 1, alloc);
+        }
+    }
+}
+
+fn walk_table(
+    table: &PageTable,
+    level: u32,
+    base: u64,
+    start: u64,
+    last: u64,
+    f: &mut impl FnMut(Mapping),
+) {
+    let entry_size = 1u64 << (12 + 9 * (level - 1));
+
+    for (i, entry) in table.iter().enumerate() {
+        let mut virt = base + i as u64 * entry_size;
+        if level == 4 && i >= 256 {
+            // the upper half is sign extended
+            virt |= 0xffff_0000_0000_0000;
+        }
+
+        if virt + (entry_size - 1) < start || virt > last {
+            continue;
+        }
+
+        let flags = entry.flags();
+        if !flags.contains(PageTableFlags::PRESENT) {
+            continue;
+        }
+
+        if level == 1 || flags.contains(PageTableFlags::HUGE_PAGE) {
+            f(Mapping {
+                virt: VirtAddr::new_truncate(virt),
+                phys: entry.addr(),
+                len: entry_size,
+                page_size: entry_size,
+                flags: flags - ACCESS_FLAGS,
+            });
+        } else {
+            walk_table(table_at(entry.addr()), level - 1, virt, start, last, f);
+        }
+    }
+}
+
+fn scan_table(
+    table: &mut PageTable,
+    level: u32,
+    base: u64,
+    start: u64,
+    last: u64,
+    f: &mut impl FnMut(VirtAddr, &mut PageTableEntry) -> bool,
+) -> Option<u64> {
+    let entry_size = 1u64 << (12 + 9 * (level - 1));
+
+    // only the user half, which is never sign extended
+    let count = if level == 4 { 256 } else { 512 };
+    for (i, entry) in table.iter_mut().enumerate().take(count) {
+        let virt = base + i as u64 * entry_size;
+        if virt + (entry_size - 1) < start || virt > last {
+            continue;
+        }
+
+        if level == 1 {
+            if !f(VirtAddr::new(virt), entry) {
+                return Some(virt);
+            }
+            continue;
+        }
+
+        let flags = entry.flags();
+        if flags.contains(PageTableFlags::PRESENT) && !flags.contains(PageTableFlags::HUGE_PAGE) {
+            let stopped = scan_table(table_at_mut(entry.addr()), level - 1, virt, start, last, f);
+            if stopped.is_some() {
+                return stopped;
+            }
+        }
+    }
+
+    None
 }
 
 impl Default for PageTableContext {
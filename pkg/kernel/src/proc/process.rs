@@ -2,24 +2,74 @@ use super::*;
 use alloc::sync::Weak;
 use spin::*;
 use crate::humanized_size;
+use crate::utils::clock;
+use crate::memory::gdt;
+use limits::{CpuTime, SpawnRate};
+use stack::{StackArgs, SyscallStack};
+use x86_64::structures::paging::PageTableFlags;
+use vm::image::Image;
+use vm::mmap::{Advice, FileMap};
+use crate::fs::vfs::FileType;
+use trace::TraceMode;
+use history::SchedHistory;
+use syscall_def::{
+    ProcInfo, Rusage, SchedEvent, NICE_MAX, NICE_MIN, PRIO_LEVELS, PROC_BLOCKED, PROC_DEAD,
+    PROC_NAME_LEN, PROC_READY, PROC_RUNNING, PROC_STOPPED, SCHED_EXIT, EACCES, ENODEV,
+    O_RDWR, O_WRONLY,
+};
+
+use super::edf::{Reservation, SchedClass};
+use super::mlfq;
+
+/// Niceness worth one tick more, or one turn less, of the cpu
+const NICE_STEP: isize = 5;
 
 #[derive(Clone)]
 pub struct Process {
     pid: ProcessId,
     inner: Arc<RwLock<ProcessInner>>,
+    // locked apart from `inner`, events are recorded while it is held
+    history: Arc<Mutex<SchedHistory>>,
 }
 
 pub struct ProcessInner {
     name: String,
     parent: Option<Weak<Process>>,
     children: Vec<Arc<Process>>,
+    /// adopted by the kernel after its parent exited, reaped as it exits
+    orphan: bool,
+    /// timer ticks that came while it ran
     ticks_passed: usize,
+    /// of them, the ones that came in user mode
+    user_ticks: usize,
     status: ProgramStatus,
     context: ProcessContext,
     exit_code: Option<isize>,
     proc_data: Option<ProcessData>,
     proc_vm: Option<ProcessVm>,
-
+    // kept until the process is dropped,
+    // as a dying process is still running on it
+    syscall_stack: Option<SyscallStack>,
+    spawn_rate: SpawnRate,
+    cpu: CpuTime,
+    trace: Option<TraceMode>,
+    /// the syscalls the process may make, any if `None`
+    syscalls: Option<SyscallSet>,
+    /// stopped by `Suspend`, it does not run again until resumed
+    stopped: bool,
+    nice: isize,
+    /// turns passed on the ready queue
+    turns: isize,
+    /// the level it starts on and is boosted back to, 0 is the highest
+    priority: usize,
+    /// the level of the ready queue it goes on
+    level: usize,
+    /// ticks run on `level`
+    slice_used: usize,
+    /// the reservation of the real-time class, see `edf`
+    rt: Option<Reservation>,
+    /// may do what only root may, see `proc::is_root`
+    root: bool,
 }
 
 impl Process {
@@ -56,10 +106,25 @@ impl Process {
             status: ProgramStatus::Ready,
             context: ProcessContext::default(),
             ticks_passed: 0,
+            user_ticks: 0,
             exit_code: None,
             children: Vec::new(),
+            orphan: false,
             proc_vm: Some(proc_vm),
             proc_data: Some(proc_data.unwrap_or_default()),
+            syscall_stack: None,
+            spawn_rate: SpawnRate::new(),
+            cpu: CpuTime::default(),
+            trace: None,
+            syscalls: None,
+            stopped: false,
+            nice: 0,
+            turns: 0,
+            priority: 0,
+            level: 0,
+            slice_used: 0,
+            rt: None,
+            root: false,
         };
 
         trace!("New process {}#{} created.", &inner.name, pid);
@@ -68,11 +133,52 @@ impl Process {
         Arc::new(Self {
             pid,
             inner: Arc::new(RwLock::new(inner)),
+            history: Arc::new(Mutex::new(SchedHistory::new())),
         })
     }
 
 
+    /// Record a scheduling event, see `syscall_def::sched`
+    pub fn record_sched(&self, kind: u32, arg: u32) {
+        self.history.lock().push(kind, arg);
+    }
+
+    /// Copy the latest scheduling events into `buf`, oldest first
+    pub fn sched_history(&self, buf: &mut [SchedEvent]) -> usize {
+        self.history.lock().copy_to(buf)
+    }
+
+    /// What the process is and what it used, as `Syscall::ProcInfo` returns
+    pub fn info(&self) -> ProcInfo {
+        let inner = self.inner.read();
+        let (user_time, kernel_time) = inner.cpu_split();
+
+        let mut name = [0; PROC_NAME_LEN];
+        let len = inner.name.len().min(PROC_NAME_LEN);
+        name[..len].copy_from_slice(&inner.name.as_bytes()[..len]);
+
+        ProcInfo {
+            pid: self.pid.0,
+            ppid: inner.parent().map_or(0, |p| p.pid.0),
+            state: match inner.shown_status() {
+                ProgramStatus::Running => PROC_RUNNING,
+                ProgramStatus::Ready => PROC_READY,
+                ProgramStatus::Blocked => PROC_BLOCKED,
+                ProgramStatus::Stopped => PROC_STOPPED,
+                ProgramStatus::Dead => PROC_DEAD,
+            },
+            name,
+            user_ticks: inner.user_ticks as u64,
+            kernel_ticks: (inner.ticks_passed - inner.user_ticks) as u64,
+            user_time,
+            kernel_time,
+            memory: inner.proc_vm.as_ref().map_or(0, |vm| vm.memory_usage()),
+        }
+    }
+
     pub fn kill(&self, ret: isize) {
+        self.record_sched(SCHED_EXIT, ret as u32);
+
         let mut inner = self.inner.write();
 
         debug!(
@@ -103,6 +209,7 @@ impl Process {
         let child = Arc::new(Self {
             pid: child_pid,
             inner: Arc::new(RwLock::new(child_inner)),
+            history: Arc::new(Mutex::new(SchedHistory::new())),
         });
         // FIXME: add child to current process's children list
         inner.children.push(child.clone());
@@ -114,6 +221,39 @@ impl Process {
         child
     }
 
+    /// Create a thread running `entry(arg)` on the stack at `stack_top`
+    ///
+    /// it is a child sharing the memory and fds of this process, waited
+    /// for like one. It must exit, `entry` has nowhere to return to.
+    pub fn thread(
+        self: &Arc<Self>,
+        entry: VirtAddr,
+        stack_top: VirtAddr,
+        arg: usize,
+    ) -> Arc<Self> {
+        let mut inner = self.write();
+
+        let thread_inner = inner.thread(Arc::downgrade(self), entry, stack_top, arg);
+        let tid = ProcessId::new();
+
+        debug!(
+            "Thread {}#{} of {}#{}",
+            thread_inner.name(),
+            tid,
+            inner.name(),
+            self.pid
+        );
+
+        let thread = Arc::new(Self {
+            pid: tid,
+            inner: Arc::new(RwLock::new(thread_inner)),
+            history: Arc::new(Mutex::new(SchedHistory::new())),
+        });
+        inner.children.push(thread.clone());
+
+        thread
+    }
+
 }
 
 impl ProcessInner {
@@ -121,14 +261,67 @@ impl ProcessInner {
         &self.name
     }
 
-    pub fn tick(&mut self) {
+    /// Count a timer tick that came while it ran, in user mode or not
+    pub fn tick(&mut self, user: bool) {
         self.ticks_passed += 1;
+        self.user_ticks += user as usize;
+    }
+
+    #[inline]
+    pub fn cpu(&self) -> &CpuTime {
+        &self.cpu
+    }
+
+    #[inline]
+    pub fn cpu_mut(&mut self) -> &mut CpuTime {
+        &mut self.cpu
+    }
+
+    /// The resources used so far, as `Syscall::GetRusage` returns
+    pub fn rusage(&self) -> Rusage {
+        let counters = self.cpu.counters();
+        Rusage {
+            cpu_time: self.cpu.used(clock::now_nanos()),
+            ticks: self.ticks_passed as u64,
+            instructions: counters.instructions,
+            cycles: counters.cycles,
+        }
     }
 
     pub fn status(&self) -> ProgramStatus {
         self.status
     }
 
+    /// The status, or `Stopped` if it was stopped while ready or running
+    ///
+    /// a stopped process may not have left the ready queue yet.
+    fn shown_status(&self) -> ProgramStatus {
+        match self.status {
+            ProgramStatus::Ready | ProgramStatus::Running if self.stopped => {
+                ProgramStatus::Stopped
+            }
+            status => status,
+        }
+    }
+
+    /// Nanoseconds of cpu time in user and in kernel mode
+    ///
+    /// only the total is measured, it is split as the ticks were, all in
+    /// one mode if every tick came in it.
+    pub fn cpu_split(&self) -> (u64, u64) {
+        let total = self.cpu.used(clock::now_nanos());
+        let kernel_ticks = self.ticks_passed - self.user_ticks;
+        if kernel_ticks == 0 {
+            return (total, 0);
+        }
+        if self.user_ticks == 0 {
+            return (0, total);
+        }
+
+        let user = total as u128 * self.user_ticks as u128 / self.ticks_passed as u128;
+        (user as u64, total - user as u64)
+    }
+
     pub fn pause(&mut self) {
         self.status = ProgramStatus::Ready;
     }
@@ -145,20 +338,163 @@ impl ProcessInner {
         self.status == ProgramStatus::Ready
     }
 
+    /// Stop the process, it leaves the cpu at its next switch
+    /// and is not woken until `cont`
+    pub fn stop(&mut self) {
+        self.stopped = true;
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// Take a stopped process out of scheduling, it is on no queue
+    pub fn park(&mut self) {
+        self.status = ProgramStatus::Stopped;
+    }
+
+    /// Let a stopped process run again
+    ///
+    /// return true if it was parked and has to be put back on the ready queue
+    pub fn cont(&mut self) -> bool {
+        self.stopped = false;
+        if self.status != ProgramStatus::Stopped {
+            return false;
+        }
+        self.status = ProgramStatus::Ready;
+        true
+    }
+
+    pub fn nice(&self) -> isize {
+        self.nice
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.root
+    }
+
+    pub fn set_root(&mut self, root: bool) {
+        self.root = root;
+    }
+
+    /// Set the niceness, clamped to `NICE_MIN..=NICE_MAX`
+    ///
+    /// return the old one
+    pub fn set_nice(&mut self, nice: isize) -> isize {
+        self.turns = 0;
+        core::mem::replace(&mut self.nice, nice.clamp(NICE_MIN, NICE_MAX))
+    }
+
+    /// Whether to let the others on its level run first, a process
+    /// above nice 0 passes one turn for every `NICE_STEP` above it
+    pub fn pass_turn(&mut self) -> bool {
+        if self.turns < self.nice / NICE_STEP {
+            self.turns += 1;
+            true
+        } else {
+            self.turns = 0;
+            false
+        }
+    }
+
+    pub fn priority(&self) -> usize {
+        self.priority
+    }
+
+    /// Set the priority and move to its level, clamped to `PRIO_LEVELS`
+    ///
+    /// return the old one
+    pub fn set_priority(&mut self, priority: usize) -> usize {
+        let priority = priority.min(PRIO_LEVELS - 1);
+        self.level = priority;
+        self.slice_used = 0;
+        core::mem::replace(&mut self.priority, priority)
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// Count a tick run, return true if the quantum of the level is used up
+    /// and the process dropped to the next level
+    ///
+    /// a process below nice 0 runs one more tick for every `NICE_STEP` below it.
+    pub fn charge_tick(&mut self) -> bool {
+        self.slice_used += 1;
+        let bonus = (-self.nice / NICE_STEP).max(0) as usize;
+        if self.slice_used < mlfq::quantum(self.level) + bonus {
+            return false;
+        }
+
+        self.slice_used = 0;
+        self.level = (self.level + 1).min(PRIO_LEVELS - 1);
+        true
+    }
+
+    /// Go back to the level of the priority
+    pub fn boost(&mut self) {
+        self.level = self.priority;
+        self.slice_used = 0;
+    }
+
+    #[inline]
+    pub fn reservation(&self) -> Option<&Reservation> {
+        self.rt.as_ref()
+    }
+
+    /// Join the real-time class with `rt`, or leave it with `None`
+    pub fn set_reservation(&mut self, rt: Option<Reservation>) {
+        self.rt = rt;
+    }
+
+    /// Where the process goes when it is ready, starting a new period
+    /// of its reservation if the last one is over
+    pub fn sched_class(&mut self) -> SchedClass {
+        let Some(rt) = self.rt.as_mut() else {
+            return SchedClass::Normal(self.level);
+        };
+
+        let cpu_used = self.cpu.used(clock::now_nanos());
+        rt.refresh(clock::monotonic_nanos(), cpu_used);
+        match rt.budget_left(cpu_used) {
+            0 => SchedClass::Throttled(rt.deadline()),
+            _ => SchedClass::Realtime(rt.deadline()),
+        }
+    }
+
+    /// Nanoseconds of the real-time budget left in this period, if any
+    pub fn budget_left(&self) -> Option<u64> {
+        let rt = self.rt.as_ref()?;
+        Some(rt.budget_left(self.cpu.used(clock::now_nanos())))
+    }
+
     pub fn exit_code(&self) -> Option<isize> {
         self.exit_code
     }
 
+    pub fn spawn_rate(&mut self) -> &mut SpawnRate {
+        &mut self.spawn_rate
+    }
+
     pub fn vm(&self) -> &ProcessVm {
         self.proc_vm.as_ref().unwrap()
     }
 
+    /// The memory of the process, `None` once it is dead
+    pub fn try_vm(&self) -> Option<&ProcessVm> {
+        self.proc_vm.as_ref()
+    }
+
     pub fn vm_mut(&mut self) -> &mut ProcessVm {
         self.proc_vm.as_mut().unwrap()
     }
 
-    pub fn handle_page_fault(&mut self, addr: VirtAddr) -> bool {
-        self.vm_mut().handle_page_fault(addr)
+    pub fn handle_page_fault(
+        &mut self,
+        addr: VirtAddr,
+        err_code: PageFaultErrorCode,
+    ) -> PageFaultOutcome {
+        self.vm_mut().handle_page_fault(addr, err_code)
     }
 
     pub fn set_return_value(&mut self, ret: isize) {
@@ -169,13 +505,46 @@ impl ProcessInner {
         self.vm().page_table.clone_level_4()
     }
 
-    pub fn load_elf(&mut self, elf: &ElfFile) {
-        self.vm_mut().load_elf(elf)
+    pub fn set_trace(&mut self, trace: Option<TraceMode>) {
+        self.trace = trace;
+    }
+
+    pub fn take_trace(&mut self) -> Option<TraceMode> {
+        self.trace.take()
+    }
+
+    pub fn trace_mut(&mut self) -> Option<&mut TraceMode> {
+        self.trace.as_mut()
+    }
+
+    pub fn syscalls(&self) -> Option<SyscallSet> {
+        self.syscalls
+    }
+
+    /// Allow only the syscalls in `set` from now on
+    ///
+    /// on top of the current filter, which can never be widened.
+    pub fn restrict_syscalls(&mut self, set: SyscallSet) {
+        self.syscalls = Some(match self.syscalls {
+            Some(current) => current & set,
+            None => set,
+        });
+    }
+
+    /// Check if the filter of the process allows syscall `num`
+    pub fn allows_syscall(&self, num: usize) -> bool {
+        self.syscalls.is_none_or(|set| set.contains(num))
+    }
+
+    pub fn load_elf(&mut self, image: Image, stack_pages: u64) {
+        self.vm_mut().load_elf(image, stack_pages);
+        self.syscall_stack = Some(SyscallStack::new());
     }
 
     /// Save the process's context
     /// mark the process as ready
     pub(super) fn save(&mut self, context: &ProcessContext) {
+        self.cpu.stop(clock::now_nanos());
         self.context.save(context);
         self.status = ProgramStatus::Ready;
     }
@@ -185,38 +554,131 @@ impl ProcessInner {
     pub(super) fn restore(&mut self, context: &mut ProcessContext) {
         self.context.restore(context);
         self.vm().page_table.load();
+        if let Some(stack) = self.syscall_stack.as_ref() {
+            gdt::set_privilege_stack(stack.top());
+        }
+        self.cpu.start(clock::now_nanos());
         self.status = ProgramStatus::Running;
     }
 
-    pub fn init_stack_frame(&mut self, entry: VirtAddr, stack_top: VirtAddr) {
-        self.context.init_stack_frame(entry, stack_top)
-    }
-
     pub fn parent(&self) -> Option<Arc<Process>> {
         self.parent.as_ref().and_then(|p| p.upgrade())
     }
 
+    #[inline]
+    pub fn children(&self) -> &[Arc<Process>] {
+        &self.children
+    }
+
+    #[inline]
+    pub fn add_child(&mut self, child: Arc<Process>) {
+        self.children.push(child);
+    }
+
+    /// Forget child `pid` once it is reaped
+    pub fn remove_child(&mut self, pid: ProcessId) {
+        self.children.retain(|child| child.pid() != pid);
+    }
+
+    /// Give up every child, as the process exits
+    pub fn take_children(&mut self) -> Vec<Arc<Process>> {
+        core::mem::take(&mut self.children)
+    }
+
+    /// Become a child of `parent`, the kernel, after the parent exited
+    pub fn adopt(&mut self, parent: Weak<Process>) {
+        self.parent = Some(parent);
+        self.orphan = true;
+    }
+
+    #[inline]
+    pub fn is_orphan(&self) -> bool {
+        self.orphan
+    }
+
     pub fn kill(&mut self, ret: isize) {
+        if let Some(TraceMode::Record(trace)) = self.trace.take() {
+            trace::save(&self.name, trace);
+        }
+
         self.proc_vm.take();
         self.proc_data.take();
         self.exit_code = Some(ret);
         self.status = ProgramStatus::Dead;
     }
 
+    /// Replace the program with `elf`, keeping the pid, fds and family
+    ///
+    /// the new page table is loaded before the old memory is released,
+    /// which may free the old one. Memory still shared with forked
+    /// processes is left to them. `context` is set to start the program
+    /// with `args`, see `init_program`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn exec(
+        &mut self,
+        name: &str,
+        elf: &ElfFile<'static>,
+        image: Image,
+        stack_pages: u64,
+        page_table: PageTableContext,
+        args: &AppArgs,
+        context: &mut ProcessContext,
+    ) {
+        if let Some(TraceMode::Record(trace)) = self.trace.take() {
+            trace::save(&self.name, trace);
+        }
+
+        let mut vm = ProcessVm::new(page_table);
+        vm.load_elf(image, stack_pages);
+        vm.page_table.load();
+        drop(self.proc_vm.replace(vm));
+
+        self.name = name.to_ascii_lowercase();
+        self.signals_mut().exec();
+        // the new program cannot make syscalls the old one was denied
+        if let Some(set) = boot::declared_syscalls(elf) {
+            self.restrict_syscalls(set);
+        }
+
+        self.context = ProcessContext::default();
+        self.init_program(VirtAddr::new_truncate(elf.header.pt2.entry_point()), args);
+        self.context.restore(context);
+    }
+
+    /// Set the context to start the program at `entry` with `args`
+    ///
+    /// they are copied to the stack, which must be mapped already, and
+    /// passed as `_start(argc, argv, envc, envp)`. Without them if they
+    /// do not fit in the top page of the stack.
+    pub fn init_program(&mut self, entry: VirtAddr, args: &AppArgs) {
+        let stack = self.vm().push_args(args).unwrap_or_else(|| {
+            warn!("Failed to pass the arguments of {}.", self.name);
+            StackArgs {
+                rsp: stack::STACK_INIT_TOP,
+                ..Default::default()
+            }
+        });
+
+        self.context.init_stack_frame(entry, VirtAddr::new(stack.rsp));
+        self.context.set_entry_args(
+            stack.argc,
+            stack.argv as usize,
+            stack.envc,
+            stack.envp as usize,
+        );
+    }
+
     pub fn fork(&mut self, parent: Weak<Process>) -> ProcessInner {
         // 这里不能改self，因为self是从上面的inner继承来的，实际还是在一个parent里面
         // 应该返回一个构造而不是Self
 
         // FIXME: fork the process virtual memory struct
-        // FIXME: calculate the real stack offset
-        let new_vm = self.vm().fork(self.children.len() as u64);
-        let offset = new_vm.stack.stack_offset(&self.vm().stack);
+        // the child has its own address space, the stack stays where it is
+        let new_vm = self.vm().fork();
 
-        // FIXME: update `rsp` in interrupt stack frame
         // FIXME: set the return value 0 for child with `context.set_rax`
         // FIXME: clone the process data struct
         let mut new_context = self.context;
-        new_context.set_stack_offset(offset);
         new_context.set_rax(0);
 
         // FIXME: construct the child process inner
@@ -225,23 +687,184 @@ impl ProcessInner {
             name: self.name.clone(),
             parent: Some(parent),
             children: Vec::new(),
+            orphan: false,
             ticks_passed: 0,
+            user_ticks: 0,
             status: ProgramStatus::Ready,
             context: new_context,
             exit_code: None,
-            proc_data: self.proc_data.clone(),
+            proc_data: self.proc_data.as_ref().map(ProcessData::fork),
             proc_vm: Some(new_vm),
+            syscall_stack: Some(SyscallStack::new()),
+            spawn_rate: SpawnRate::new(),
+            cpu: CpuTime::with_limit(self.cpu.limit()),
+            trace: None,
+            syscalls: self.syscalls,
+            stopped: false,
+            nice: self.nice,
+            turns: 0,
+            priority: self.priority,
+            level: self.priority,
+            slice_used: 0,
+            rt: None,
+            root: self.root,
         }
 
     }
 
+    /// The inner of a thread, see `Process::thread`
+    pub fn thread(
+        &self,
+        parent: Weak<Process>,
+        entry: VirtAddr,
+        stack_top: VirtAddr,
+        arg: usize,
+    ) -> ProcessInner {
+        let mut context = ProcessContext::default();
+        context.init_stack_frame(entry, stack_top);
+        context.set_entry_args(arg, 0, 0, 0);
+
+        Self {
+            name: self.name.clone(),
+            parent: Some(parent),
+            children: Vec::new(),
+            orphan: false,
+            ticks_passed: 0,
+            user_ticks: 0,
+            status: ProgramStatus::Ready,
+            context,
+            exit_code: None,
+            proc_data: self.proc_data.as_ref().map(ProcessData::thread),
+            proc_vm: Some(self.vm().thread()),
+            syscall_stack: Some(SyscallStack::new()),
+            spawn_rate: SpawnRate::new(),
+            cpu: CpuTime::with_limit(self.cpu.limit()),
+            trace: None,
+            syscalls: self.syscalls,
+            stopped: false,
+            nice: self.nice,
+            turns: 0,
+            priority: self.priority,
+            level: self.priority,
+            slice_used: 0,
+            rt: None,
+            root: self.root,
+        }
+    }
+
     pub fn brk(&self, addr: Option<usize>) -> usize {
-        match self.vm().brk(addr.map(|a| VirtAddr::new(a as u64))) {
+        let addr = match addr.map(|a| VirtAddr::try_new(a as u64)).transpose() {
+            Ok(addr) => addr,
+            Err(_) => return !0,
+        };
+        match self.vm().brk(addr) {
             Some(addr) => addr.as_u64() as usize,
             None => !0,
         }
     }
 
+    /// Map `len` bytes at `addr`, or where they fit, read from `file`
+    /// at an offset if given, return the address or `-errno`
+    pub fn mmap(
+        &self,
+        addr: Option<usize>,
+        len: usize,
+        flags: PageTableFlags,
+        file: Option<(u8, usize)>,
+    ) -> usize {
+        let count = (len as u64).div_ceil(crate::memory::PAGE_SIZE);
+        let addr = match addr.map(|a| VirtAddr::try_new(a as u64)).transpose() {
+            Ok(addr) => addr,
+            Err(_) => return errno_ret(EINVAL),
+        };
+
+        let file = match file.map(|(fd, offset)| self.map_file(fd, offset, flags)) {
+            Some(Ok(file)) => Some(file),
+            Some(Err(errno)) => return errno_ret(errno),
+            None => None,
+        };
+
+        match self.vm().mmap(addr, count, flags, file) {
+            Some(addr) => addr.as_u64() as usize,
+            None => errno_ret(ENOMEM),
+        }
+    }
+
+    /// The part of the file `fd` from `offset` a mapping with `flags` reads
+    ///
+    /// the fd must be readable, and writable too if writes to the mapping
+    /// go back to the file.
+    fn map_file(&self, fd: u8, offset: usize, flags: PageTableFlags) -> Result<FileMap, usize> {
+        if !(offset as u64).is_multiple_of(crate::memory::PAGE_SIZE) {
+            return Err(EINVAL);
+        }
+
+        let (inode, access) = self.file(fd)?;
+        if inode.metadata().kind != FileType::File {
+            return Err(ENODEV);
+        }
+
+        let writes_back = flags.contains(paging::SHARED | PageTableFlags::WRITABLE);
+        if access == O_WRONLY || (writes_back && access != O_RDWR) {
+            return Err(EACCES);
+        }
+
+        Ok(FileMap {
+            inode,
+            offset: offset as u64,
+        })
+    }
+
+    pub fn mprotect(&self, addr: usize, len: usize, flags: PageTableFlags) -> usize {
+        let count = (len as u64).div_ceil(crate::memory::PAGE_SIZE);
+        let addr = match VirtAddr::try_new(addr as u64) {
+            Ok(addr) if addr.is_aligned(crate::memory::PAGE_SIZE) => addr,
+            _ => return errno_ret(EINVAL),
+        };
+        if self.vm().mprotect(addr, count, flags) {
+            0
+        } else {
+            errno_ret(ENOMEM)
+        }
+    }
+
+    pub fn munmap(&self, addr: usize, len: usize) -> usize {
+        let count = (len as u64).div_ceil(crate::memory::PAGE_SIZE);
+        let addr = match VirtAddr::try_new(addr as u64) {
+            Ok(addr) => addr,
+            Err(_) => return errno_ret(EINVAL),
+        };
+        match self.vm().munmap(addr, count) {
+            Ok(()) => 0,
+            Err(_) => errno_ret(EINVAL),
+        }
+    }
+
+    pub fn msync(&self, addr: usize, len: usize) -> usize {
+        let count = (len as u64).div_ceil(crate::memory::PAGE_SIZE);
+        let addr = match VirtAddr::try_new(addr as u64) {
+            Ok(addr) => addr,
+            Err(_) => return errno_ret(EINVAL),
+        };
+        match self.vm().msync(addr, count) {
+            Ok(()) => 0,
+            Err(errno) => errno_ret(errno),
+        }
+    }
+
+    pub fn madvise(&self, addr: usize, len: usize, advice: Advice) -> usize {
+        let count = (len as u64).div_ceil(crate::memory::PAGE_SIZE);
+        let addr = match VirtAddr::try_new(addr as u64) {
+            Ok(addr) if addr.is_aligned(crate::memory::PAGE_SIZE) => addr,
+            _ => return errno_ret(EINVAL),
+        };
+        if self.vm().madvise(addr, count, advice) {
+            0
+        } else {
+            errno_ret(ENOMEM)
+        }
+    }
+
 }
 
 impl core::ops::Deref for Process {
@@ -283,6 +906,7 @@ impl core::fmt::Debug for Process {
             .field("status", &inner.status)
             .field("context", &inner.context)
             .field("vm", &inner.proc_vm)
+            .field("syscall_stack", &inner.syscall_stack)
             .finish()
     }
 }
@@ -292,16 +916,25 @@ impl core::fmt::Display for Process {
         let inner = self.inner.read();
         let (size, unit) = 
             humanized_size(inner.proc_vm.as_ref().map_or(0, |vm| vm.memory_usage()));
+        let status = inner.shown_status();
+        // the level and priority, or the real-time class
+        let prio = match inner.rt {
+            Some(_) => String::from("rt"),
+            None => format!("{:>2}/{}", inner.level, inner.priority),
+        };
         write!(
             f,
-            " #{:-3} | #{:-3} | {:12} | {:7} | {:>5.1} {} | {:?}",
+            " #{:-3} | #{:-3} | {:12} | {:6} | {:5} | {:>4} | {:>4} | {:>5.1} {} | {:?}",
             self.pid.0,
             inner.parent().map(|p| p.pid.0).unwrap_or(0),
             inner.name,
-            inner.ticks_passed,
+            inner.user_ticks,
+            inner.ticks_passed - inner.user_ticks,
+            inner.nice,
+            prio,
             size, 
             unit,
-            inner.status
+            status
         )?;
         Ok(())
     }
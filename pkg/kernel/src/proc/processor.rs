@@ -1,30 +1,19 @@
 use core::sync::atomic::{AtomicU16, Ordering};
 
+use crate::percpu;
 use crate::proc::ProcessId;
 use alloc::{string::String, vec::Vec};
-use x86::cpuid::CpuId;
-
-const MAX_CPU_COUNT: usize = 8;
-
-#[allow(clippy::declare_interior_mutable_const)]
-const EMPTY: Processor = Processor::new(); // means no process
-
-static PROCESSORS: [Processor; MAX_CPU_COUNT] = [EMPTY; MAX_CPU_COUNT];
 
 fn current() -> &'static Processor {
-    let cpuid = CpuId::new()
-        .get_feature_info()
-        .unwrap()
-        .initial_local_apic_id() as usize;
-
-    &PROCESSORS[cpuid]
+    percpu::current().processor()
 }
 
 pub fn print_processors() -> String {
     alloc::format!(
         "CPUs   : {}\n",
-        PROCESSORS
+        percpu::cpus()
             .iter()
+            .map(|cpu| cpu.processor())
             .enumerate()
             .filter(|(_, p)| !p.is_free())
             .map(|(i, p)| alloc::format!("[{}: {}]", i, p.get_pid().unwrap()))
@@ -52,6 +41,22 @@ pub fn current_pid() -> ProcessId {
     current().get_pid().expect("No current process")
 }
 
+/// The index in `percpu::cpus` of the cpu this runs on
+pub fn cpu_index() -> usize {
+    let current = percpu::current();
+    percpu::cpus()
+        .iter()
+        .position(|cpu| core::ptr::eq(cpu, current))
+        .unwrap_or(0)
+}
+
+/// The index of the cpu `pid` is the current process of, if any
+pub fn cpu_of(pid: ProcessId) -> Option<usize> {
+    percpu::cpus()
+        .iter()
+        .position(|cpu| cpu.processor().get_pid() == Some(pid))
+}
+
 impl Processor {
     #[inline]
     pub fn is_free(&self) -> bool {
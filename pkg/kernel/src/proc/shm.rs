@@ -0,0 +1,110 @@
+//! Shared memory segments, named by a key like semaphores
+//!
+//! the frames of a segment are allocated when it is first opened, and
+//! every process mapping it gets all of them, writable and shared with
+//! its forked children too. Each page mapped holds a reference to its
+//! frame, see `BootInfoFrameAllocator::share_frame`, so the segment knows
+//! it is no longer used once none of its frames is shared. It is then
+//! removed, when a process unmaps it or exits. A segment opened and never
+//! mapped stays until it is.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use spin::Mutex;
+use syscall_def::errno::*;
+use syscall_def::SHM_MAX_SIZE;
+use x86_64::structures::paging::{FrameDeallocator, PhysFrame};
+
+use crate::memory::{get_frame_alloc_for_sure, PAGE_SIZE};
+
+struct Segment {
+    frames: Vec<PhysFrame>,
+    /// it has been mapped, and is removed once it is not anymore
+    mapped: bool,
+}
+
+impl Segment {
+    fn size(&self) -> usize {
+        self.frames.len() * PAGE_SIZE as usize
+    }
+}
+
+static SEGMENTS: Mutex<BTreeMap<u32, Segment>> = Mutex::new(BTreeMap::new());
+
+/// Open segment `key`, creating it with at least `size` bytes of zeros
+///
+/// return its size, `EINVAL` if it already exists with less than `size`.
+pub fn open(key: u32, size: usize) -> Result<usize, usize> {
+    let mut segments = SEGMENTS.lock();
+    if let Some(segment) = segments.get(&key) {
+        return match size <= segment.size() {
+            true => Ok(segment.size()),
+            false => Err(EINVAL),
+        };
+    }
+
+    if size == 0 || size > SHM_MAX_SIZE {
+        return Err(EINVAL);
+    }
+
+    let alloc = &mut *get_frame_alloc_for_sure();
+    let count = size.div_ceil(PAGE_SIZE as usize);
+    let mut frames = Vec::with_capacity(count);
+    for _ in 0..count {
+        match alloc.allocate_zeroed_frame() {
+            Some(frame) => frames.push(frame),
+            None => {
+                for frame in frames {
+                    unsafe { alloc.deallocate_frame(frame) };
+                }
+                return Err(ENOMEM);
+            }
+        }
+    }
+
+    let segment = Segment {
+        frames,
+        mapped: false,
+    };
+    let size = segment.size();
+    segments.insert(key, segment);
+    Ok(size)
+}
+
+/// Map segment `key` with `map`, which takes a reference to each frame
+pub fn map<T>(key: u32, map: impl FnOnce(&[PhysFrame]) -> Option<T>) -> Result<T, usize> {
+    let mut segments = SEGMENTS.lock();
+    let segment = segments.get_mut(&key).ok_or(ENOENT)?;
+    let ret = map(&segment.frames).ok_or(ENOMEM)?;
+    segment.mapped = true;
+    Ok(ret)
+}
+
+/// Pages of the segment whose first frame is `frame`, to unmap it
+pub fn pages_from(frame: PhysFrame) -> Option<u64> {
+    SEGMENTS
+        .lock()
+        .values()
+        .find(|segment| segment.frames.first() == Some(&frame))
+        .map(|segment| segment.frames.len() as u64)
+}
+
+/// Remove the segments no longer mapped by any process, freeing their frames
+pub fn collect() {
+    let mut segments = SEGMENTS.lock();
+    if segments.is_empty() {
+        return;
+    }
+
+    let alloc = &mut *get_frame_alloc_for_sure();
+    segments.retain(|key, segment| {
+        let used = !segment.mapped || segment.frames.iter().any(|&f| alloc.is_shared(f));
+        if !used {
+            debug!("Shared memory segment {:#x} removed", key);
+            for &frame in segment.frames.iter() {
+                unsafe { alloc.deallocate_frame(frame) };
+            }
+        }
+        used
+    });
+}
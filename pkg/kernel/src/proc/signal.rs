@@ -0,0 +1,125 @@
+//! Signals sent to a process, and the handlers it runs for them
+//!
+//! a signal without a handler is acted on when it is sent, so a blocked
+//! process is killed right away. One with a handler is left pending until
+//! the process next returns to user mode, from a syscall, a timer tick or
+//! a page fault. Its context is then saved in a `SigFrame` on the user
+//! stack and it enters the handler, which returns into the restorer `lib`
+//! registered with it, a trampoline making `Syscall::SigReturn` to load
+//! the context back. A signal is masked while its handler runs. A blocked
+//! process takes its signals once woken, its syscall is not interrupted.
+
+use syscall_def::{NSIG, SIG_DFL, SIG_IGN};
+
+use crate::RegistersValue;
+
+/// Bytes below the stack pointer a function may use without moving it
+pub const RED_ZONE: u64 = 128;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Signals {
+    /// a bit for each signal sent and not acted on yet
+    pending: u32,
+    /// a bit for each signal whose handler runs now
+    masked: u32,
+    handlers: [usize; NSIG],
+    /// where every handler returns to
+    restorer: usize,
+}
+
+impl Default for Signals {
+    fn default() -> Self {
+        Self {
+            pending: 0,
+            masked: 0,
+            handlers: [SIG_DFL; NSIG],
+            restorer: 0,
+        }
+    }
+}
+
+impl Signals {
+    pub fn handler(&self, sig: usize) -> usize {
+        self.handlers[sig]
+    }
+
+    /// Set the handler of `sig`, and the restorer of every handler with
+    /// it unless it is `SIG_DFL` or `SIG_IGN`, return the old handler
+    pub fn set_handler(&mut self, sig: usize, handler: usize, restorer: usize) -> usize {
+        if handler != SIG_DFL && handler != SIG_IGN {
+            self.restorer = restorer;
+        }
+        core::mem::replace(&mut self.handlers[sig], handler)
+    }
+
+    pub fn restorer(&self) -> usize {
+        self.restorer
+    }
+
+    pub fn post(&mut self, sig: usize) {
+        self.pending |= 1 << sig;
+    }
+
+    /// Take the lowest pending signal that is not masked
+    pub fn take(&mut self) -> Option<usize> {
+        let ready = self.pending & !self.masked;
+        if ready == 0 {
+            return None;
+        }
+
+        let sig = ready.trailing_zeros() as usize;
+        self.pending &= !(1 << sig);
+        Some(sig)
+    }
+
+    pub fn is_masked(&self, sig: usize) -> bool {
+        self.masked & (1 << sig) != 0
+    }
+
+    /// Mask `sig` while its handler runs, return the mask to restore after
+    pub fn enter(&mut self, sig: usize) -> u32 {
+        let mask = self.masked;
+        self.masked |= 1 << sig;
+        mask
+    }
+
+    pub fn set_mask(&mut self, mask: u32) {
+        self.masked = mask;
+    }
+
+    /// The signals of a forked child, the same handlers and none pending
+    pub fn fork(&self) -> Self {
+        Self {
+            pending: 0,
+            ..*self
+        }
+    }
+
+    /// Reset the caught signals for a new program, ignored ones stay ignored
+    pub fn exec(&mut self) {
+        for handler in self.handlers.iter_mut() {
+            if *handler != SIG_IGN {
+                *handler = SIG_DFL;
+            }
+        }
+        self.masked = 0;
+        self.restorer = 0;
+    }
+}
+
+/// What is pushed on the user stack to run a handler
+///
+/// the handler returns into `restorer`, leaving the stack pointer just
+/// above it, where `Syscall::SigReturn` finds the rest.
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+pub struct SigFrame {
+    pub restorer: usize,
+    pub sig: usize,
+    /// the signals masked before the handler ran
+    pub mask: usize,
+    pub regs: RegistersValue,
+    pub rip: u64,
+    pub rsp: u64,
+    pub rflags: u64,
+}
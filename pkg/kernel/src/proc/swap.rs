@@ -0,0 +1,380 @@
+//! Swap, anonymous pages of user processes written out to a block device
+//!
+//! the swap area is a whole disk or partition, e.g. `disk1p2`, or a file
+//! attached as a loop device, named by `swap=` on the kernel command line
+//! or by `Syscall::SwapOn`. What it held before is overwritten. It is made
+//! of slots of a page each, counted by the page table entries that refer
+//! to them, as a forked child shares the pages its parent swapped out.
+//!
+//! only private anonymous pages of memory mappings are swapped out, they
+//! are marked `ANON` when filled. Once fewer than `mem.swap_low` frames
+//! are free, a page fault first frees some with the clock algorithm: the
+//! hand goes over the marked pages of every address space, a page accessed
+//! since it last passed gets another round with its accessed bit cleared,
+//! one that was not is written to a free slot. Its entry then keeps the
+//! slot in place of the frame, marked `SWAPPED` and not present, and the
+//! next fault on it reads it back, see `MemoryMap::fill_page`.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use spin::Mutex;
+use syscall_def::errno::*;
+use x86_64::instructions::interrupts::without_interrupts;
+use x86_64::instructions::tlb;
+use x86_64::structures::paging::{
+    page_table::PageTableEntry, FrameAllocator, FrameDeallocator, OffsetPageTable, Page,
+    PageTableFlags, PhysFrame,
+};
+use x86_64::{PhysAddr, VirtAddr};
+
+use super::paging::{self, ANON, SWAPPED};
+use super::vm::mmap::{MMAP_END, MMAP_START};
+use super::{current_pid, FaultReason};
+use crate::drivers::block::{self, BlockDevice, BlockError, BLOCK_SIZE};
+use crate::drivers::partition;
+use crate::fs::loopdev::{self, LoopDevice};
+use crate::memory::{physical_to_virtual, BootInfoFrameAllocator, PAGE_SIZE};
+use crate::utils::cmdline;
+use crate::utils::sysctl::Tunable;
+
+/// Free frames below which a page fault swaps pages out first
+pub static SWAP_LOW: Tunable = Tunable::new("mem.swap_low", 256);
+/// Most pages swapped out by one page fault
+const RECLAIM_BATCH: usize = 32;
+const BLOCKS_PER_PAGE: usize = PAGE_SIZE as usize / BLOCK_SIZE;
+
+struct SwapArea {
+    dev: Arc<dyn BlockDevice>,
+    /// the device or file it was turned on with
+    name: String,
+    /// entries referring to each slot, 0 for a free one
+    refs: Vec<u32>,
+    free: usize,
+    /// where the search for a free slot starts
+    next: usize,
+    /// a write failed, no more pages are swapped out
+    failed: bool,
+}
+
+impl SwapArea {
+    fn take_slot(&mut self) -> Option<usize> {
+        let count = self.refs.len();
+        let slot = (0..count)
+            .map(|i| (self.next + i) % count)
+            .find(|&slot| self.refs[slot] == 0)?;
+        self.refs[slot] = 1;
+        self.free -= 1;
+        self.next = slot + 1;
+        Some(slot)
+    }
+
+    fn put_slot(&mut self, slot: usize) {
+        if let Some(refs) = self.refs.get_mut(slot).filter(|refs| **refs > 0) {
+            *refs -= 1;
+            if *refs == 0 {
+                self.free += 1;
+            }
+        }
+    }
+
+    /// Write `frame` to `slot`, waiting for the disk, as a page fault
+    /// cannot run again the way a syscall does
+    fn write_page(&self, slot: usize, frame: PhysFrame) -> Result<(), BlockError> {
+        let bytes = unsafe { frame_bytes(frame) };
+        block::wait_for_disk(current_pid(), || {
+            for (i, block) in bytes.as_chunks::<BLOCK_SIZE>().0.iter().enumerate() {
+                self.dev.write_block(slot * BLOCKS_PER_PAGE + i, block)?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Read `slot` into `frame`, waiting for the disk
+    fn read_page(&self, slot: usize, frame: PhysFrame) -> Result<(), BlockError> {
+        let bytes = unsafe { frame_bytes(frame) };
+        block::wait_for_disk(current_pid(), || {
+            for (i, block) in bytes.as_chunks_mut::<BLOCK_SIZE>().0.iter_mut().enumerate() {
+                self.dev.read_block(slot * BLOCKS_PER_PAGE + i, block)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+static AREA: Mutex<Option<SwapArea>> = Mutex::new(None);
+
+/// The clock hand, the level 4 table of an address space and a page in it
+static HAND: Mutex<(Option<PhysFrame>, u64)> = Mutex::new((None, MMAP_START));
+
+static SWAP_OUTS: AtomicUsize = AtomicUsize::new(0);
+static SWAP_INS: AtomicUsize = AtomicUsize::new(0);
+
+/// Swap to what `swap=` on the kernel command line names, if anything
+pub fn init() {
+    let Some(path) = cmdline::get("swap").filter(|path| !path.is_empty()) else {
+        return;
+    };
+
+    if let Err(errno) = on(path) {
+        warn!("Failed to swap on {}: errno {}", path, errno);
+    }
+}
+
+/// Swap to the device or file at `path`, e.g. `/dev/disk1p2` or `/tmp/swap`
+///
+/// a device is named as under `/dev`, any other path is a swap file,
+/// attached as a loop device. `EBUSY` if swap is on already.
+pub fn on(path: &str) -> Result<(), usize> {
+    let name = path.trim_start_matches("/dev/");
+    let (dev, file): (Arc<dyn BlockDevice>, _) =
+        match partition::find(name).or_else(|| loopdev::find(name)) {
+            Some(dev) => (dev, None),
+            None if path.starts_with('/') => {
+                let file = Arc::new(LoopDevice::new(path).map_err(|err| err.errno())?);
+                (file.clone(), Some(file))
+            }
+            None => return Err(ENODEV),
+        };
+
+    let slots = dev.block_count() / BLOCKS_PER_PAGE;
+    if slots == 0 {
+        return Err(EINVAL);
+    }
+
+    without_interrupts(|| {
+        let mut area = AREA.lock();
+        if area.is_some() {
+            return Err(EBUSY);
+        }
+
+        if let Some(file) = file {
+            loopdev::attach(file);
+        }
+        *area = Some(SwapArea {
+            dev,
+            name: path.into(),
+            refs: vec![0; slots],
+            free: slots,
+            next: 0,
+            failed: false,
+        });
+        Ok(())
+    })?;
+
+    info!("Swap on {}, {} pages.", path, slots);
+    Ok(())
+}
+
+/// Pages of the swap area and how many are free,
+/// pages swapped out and swapped back in since boot
+pub fn stats() -> (usize, usize, usize, usize) {
+    let (total, free) = without_interrupts(|| {
+        AREA.lock()
+            .as_ref()
+            .map_or((0, 0), |area| (area.refs.len(), area.free))
+    });
+    (
+        total,
+        free,
+        SWAP_OUTS.load(Ordering::Relaxed),
+        SWAP_INS.load(Ordering::Relaxed),
+    )
+}
+
+/// Whether `entry` is of a page swapped out
+pub fn is_swapped(entry: &PageTableEntry) -> bool {
+    let flags = entry.flags();
+    flags.contains(SWAPPED) && !flags.contains(PageTableFlags::PRESENT)
+}
+
+/// The entry of `page` if it is swapped out
+pub fn swapped_entry<'a>(
+    mapper: &'a mut OffsetPageTable<'static>,
+    page: Page,
+) -> Option<&'a mut PageTableEntry> {
+    paging::entry_mut(mapper, page).filter(|entry| is_swapped(entry))
+}
+
+fn slot_of(entry: &PageTableEntry) -> usize {
+    (entry.addr().as_u64() / PAGE_SIZE) as usize
+}
+
+/// Another entry refers to the slot of `entry`, the one of a forked process
+pub fn share(entry: &PageTableEntry) {
+    if let Some(refs) = AREA
+        .lock()
+        .as_mut()
+        .and_then(|area| area.refs.get_mut(slot_of(entry)))
+    {
+        *refs += 1;
+    }
+}
+
+/// Drop the page swapped out in `entry`, its slot is free once no
+/// other entry refers to it
+pub fn discard(entry: &mut PageTableEntry) {
+    if let Some(area) = AREA.lock().as_mut() {
+        area.put_slot(slot_of(entry));
+    }
+    entry.set_unused();
+}
+
+/// Map the page swapped out in `entry` back with `flags`
+pub fn protect(entry: &mut PageTableEntry, flags: PageTableFlags) {
+    let kept = entry.flags() & ANON;
+    entry.set_addr(
+        entry.addr(),
+        (flags - PageTableFlags::PRESENT) | kept | SWAPPED,
+    );
+}
+
+/// Read the page swapped out in `entry` back into a new frame, mapped
+/// at `addr` with the flags it had
+pub fn swap_in(
+    entry: &mut PageTableEntry,
+    addr: VirtAddr,
+    alloc: &mut BootInfoFrameAllocator,
+) -> Result<(), FaultReason> {
+    let slot = slot_of(entry);
+    let mut area = AREA.lock();
+    let area = area.as_mut().ok_or(FaultReason::Io)?;
+
+    let frame = alloc.allocate_frame().ok_or(FaultReason::OutOfMemory)?;
+    if let Err(err) = area.read_page(slot, frame) {
+        warn!(
+            "Swap: failed to read slot {} of {}: {}",
+            slot, area.name, err
+        );
+        unsafe { alloc.deallocate_frame(frame) };
+        return Err(FaultReason::Io);
+    }
+
+    trace!("Swap in {:#x} from slot {}", addr, slot);
+
+    let flags = (entry.flags() - SWAPPED) | PageTableFlags::PRESENT;
+    entry.set_frame(frame, flags);
+    tlb::flush(addr);
+    area.put_slot(slot);
+    SWAP_INS.fetch_add(1, Ordering::Relaxed);
+    Ok(())
+}
+
+/// Swap pages out if fewer than `SWAP_LOW` frames are free,
+/// called on each page fault
+pub fn balance(alloc: &mut BootInfoFrameAllocator) {
+    if alloc.frames_free() >= SWAP_LOW.get() {
+        return;
+    }
+
+    let freed = reclaim(RECLAIM_BATCH, alloc);
+    trace!("Swap: {} pages swapped out", freed);
+}
+
+/// Swap out up to `want` pages with the clock algorithm, return how many were
+fn reclaim(want: usize, alloc: &mut BootInfoFrameAllocator) -> usize {
+    let mut area = AREA.lock();
+    let Some(area) = area.as_mut().filter(|area| !area.failed && area.free > 0) else {
+        return 0;
+    };
+
+    let spaces = paging::user_spaces();
+    if spaces.is_empty() {
+        return 0;
+    }
+
+    let mut hand = HAND.lock();
+    let (mut index, mut start) = match spaces.iter().position(|&space| Some(space) == hand.0) {
+        Some(index) => (index, hand.1),
+        None => (0, MMAP_START),
+    };
+
+    let mut freed = 0;
+    // twice around, the first round may only clear the accessed bits
+    for _ in 0..=2 * spaces.len() {
+        let space = spaces[index];
+        let stopped = paging::scan_entries(space, start, MMAP_END - 1, &mut |addr, entry| {
+            match evict(area, addr, entry, alloc) {
+                Some(true) => freed += 1,
+                Some(false) => {}
+                None => return false,
+            }
+            freed < want
+        });
+
+        if let Some(addr) = stopped {
+            *hand = (Some(space), addr);
+            return freed;
+        }
+
+        index = (index + 1) % spaces.len();
+        start = MMAP_START;
+    }
+
+    *hand = (Some(spaces[index]), start);
+    freed
+}
+
+/// Give the page of `entry` another round if it was accessed, otherwise
+/// write it to a free slot and free its frame
+///
+/// return whether it was swapped out, `None` if no more can be.
+fn evict(
+    area: &mut SwapArea,
+    addr: VirtAddr,
+    entry: &mut PageTableEntry,
+    alloc: &mut BootInfoFrameAllocator,
+) -> Option<bool> {
+    let flags = entry.flags();
+    if !flags.contains(PageTableFlags::PRESENT | ANON) {
+        return Some(false);
+    }
+
+    // mapped by a forked process too, until it is copied on write
+    let frame = PhysFrame::containing_address(entry.addr());
+    if alloc.is_shared(frame) {
+        return Some(false);
+    }
+
+    if flags.contains(PageTableFlags::ACCESSED) {
+        entry.set_flags(flags - PageTableFlags::ACCESSED);
+        tlb::flush(addr);
+        return Some(false);
+    }
+
+    let slot = area.take_slot()?;
+    if let Err(err) = area.write_page(slot, frame) {
+        area.put_slot(slot);
+        // a block still being read is no fault of the device, a later
+        // page fault tries again
+        if !matches!(err, BlockError::Pending(_)) {
+            warn!(
+                "Swap: failed to write slot {} of {}, no longer swapping out: {}",
+                slot, area.name, err
+            );
+            area.failed = true;
+        }
+        return None;
+    }
+
+    trace!("Swap out {:#x} to slot {}", addr, slot);
+
+    let kept = flags - PageTableFlags::PRESENT - PageTableFlags::DIRTY;
+    entry.set_addr(PhysAddr::new(slot as u64 * PAGE_SIZE), kept | SWAPPED);
+    tlb::flush(addr);
+    unsafe { alloc.deallocate_frame(frame) };
+    SWAP_OUTS.fetch_add(1, Ordering::Relaxed);
+    Some(true)
+}
+
+/// The bytes of `frame`, through the physical memory map
+///
+/// # Safety
+///
+/// nothing else may use the frame while the slice is alive.
+unsafe fn frame_bytes<'a>(frame: PhysFrame) -> &'a mut [u8] {
+    let ptr = physical_to_virtual(frame.start_address().as_u64()) as *mut u8;
+    core::slice::from_raw_parts_mut(ptr, PAGE_SIZE as usize)
+}
@@ -25,6 +25,8 @@ pub enum SemaphoreResult {
     NotExist,
     Block(ProcessId),
     WakeUp(ProcessId),
+    /// the semaphore is taken and the caller does not wait
+    WouldBlock,
 }
 
 impl Semaphore {
@@ -52,6 +54,20 @@ impl Semaphore {
         SemaphoreResult::Ok
     }
 
+    /// Take the semaphore if the count is not 0, never wait
+    pub fn try_wait(&mut self) -> SemaphoreResult {
+        if self.count == 0 {
+            return SemaphoreResult::WouldBlock;
+        }
+        self.count -= 1;
+        SemaphoreResult::Ok
+    }
+
+    /// Leave the wait queue, when the wait timed out
+    pub fn cancel_wait(&mut self, pid: ProcessId) {
+        self.wait_queue.retain(|&waiter| waiter != pid);
+    }
+
     /// Signal the semaphore (release/up/verhogen)
     ///
     /// if the wait queue is not empty, then pop a process from the wait queue
@@ -106,6 +122,20 @@ impl SemaphoreSet {
         // FIXME: return NotExist if the semaphore is not exist
     }
 
+    pub fn try_wait(&self, key: u32) -> SemaphoreResult {
+        match self.sems.get(&SemaphoreId::new(key)) {
+            Some(sem) => sem.lock().try_wait(),
+            None => SemaphoreResult::NotExist,
+        }
+    }
+
+    /// Stop `pid` waiting on the semaphore, if it still exists
+    pub fn cancel_wait(&self, key: u32, pid: ProcessId) {
+        if let Some(sem) = self.sems.get(&SemaphoreId::new(key)) {
+            sem.lock().cancel_wait(pid);
+        }
+    }
+
     /// Signal the semaphore (release/up/verhogen)
     pub fn signal(&self, key: u32) -> SemaphoreResult {
         let sid = SemaphoreId::new(key);
@@ -0,0 +1,105 @@
+use alloc::{collections::*, string::String, vec::Vec};
+use spin::Mutex;
+use syscall_def::Syscall;
+
+// record and replay of syscall traces
+//
+// a recorded process logs the results of the syscalls that bring
+// outside input into it, the trace is kept under the app's name
+// when it exits. a replayed process of the same app is then given
+// the recorded results instead of live ones, until it diverges.
+
+/// Traces recorded so far, by app name
+///
+/// kept in memory, as there is no file system to write them to
+static TRACES: Mutex<BTreeMap<String, SyscallTrace>> = Mutex::new(BTreeMap::new());
+
+/// Entries a trace holds at most, recording stops at either limit
+const TRACE_MAX_ENTRIES: usize = 4096;
+/// Bytes of data the entries of a trace hold at most
+const TRACE_MAX_BYTES: usize = 1 << 20;
+
+/// Check if the result of `syscall` is recorded
+pub fn is_traced(syscall: &Syscall) -> bool {
+    matches!(
+        syscall,
+        Syscall::Read | Syscall::Time | Syscall::ClockMonotonic | Syscall::GetPid
+    )
+}
+
+#[derive(Debug, Clone)]
+pub struct TraceEntry {
+    pub syscall: Syscall,
+    pub ret: usize,
+    /// bytes the syscall wrote to user memory
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SyscallTrace {
+    entries: VecDeque<TraceEntry>,
+    /// bytes of data in the entries
+    bytes: usize,
+}
+
+impl SyscallTrace {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Add an entry, false if the trace is full and it was left out
+    pub fn record(&mut self, syscall: Syscall, ret: usize, data: &[u8]) -> bool {
+        if self.entries.len() >= TRACE_MAX_ENTRIES || self.bytes + data.len() > TRACE_MAX_BYTES {
+            return false;
+        }
+
+        self.bytes += data.len();
+        self.entries.push_back(TraceEntry {
+            syscall,
+            ret,
+            data: data.to_vec(),
+        });
+        true
+    }
+
+    /// The next entry, if it was recorded for `syscall`
+    ///
+    /// it stays next until `advance`, so a syscall that could not be
+    /// answered with it runs live without losing it.
+    pub fn peek(&self, syscall: &Syscall) -> Option<&TraceEntry> {
+        let entry = self.entries.front()?;
+        if core::mem::discriminant(&entry.syscall) != core::mem::discriminant(syscall) {
+            return None;
+        }
+        Some(entry)
+    }
+
+    /// Move past the next entry, once it has been replayed
+    pub fn advance(&mut self) {
+        if let Some(entry) = self.entries.pop_front() {
+            self.bytes -= entry.data.len();
+        }
+    }
+}
+
+/// How the syscalls of a process are traced
+#[derive(Debug)]
+pub enum TraceMode {
+    Record(SyscallTrace),
+    Replay(SyscallTrace),
+}
+
+/// Keep the trace recorded for app `name`, replacing the old one
+pub fn save(name: &str, trace: SyscallTrace) {
+    info!("Saved syscall trace of {}: {} entries", name, trace.len());
+    TRACES.lock().insert(String::from(name), trace);
+}
+
+/// A copy of the trace recorded for app `name`
+pub fn load(name: &str) -> Option<SyscallTrace> {
+    TRACES.lock().get(name).cloned()
+}
@@ -0,0 +1,97 @@
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use x86_64::structures::paging::{mapper::MapToError, Size4KiB};
+
+/// How a page fault was served, or why it could not be
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PageFaultOutcome {
+    /// the stack grew down to the address
+    StackGrow,
+    /// a page of a memory mapping or of the program was filled on first access
+    LazyLoaded,
+    /// a page shared with a forked process was written
+    CopyOnWrite,
+    Fatal {
+        reason: FaultReason,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FaultReason {
+    /// the page is present, but the access is not allowed
+    ProtectionViolation,
+    /// the address is not on the stack or in any mapping
+    Unmapped,
+    /// no frame was left to back the page
+    OutOfMemory,
+    /// the page could not be mapped for another reason
+    MapFailed,
+    /// the stack would grow past its limit of `limit` bytes
+    StackOverflow { limit: u64 },
+    /// the page could not be read from the mapped file
+    Io,
+}
+
+impl PageFaultOutcome {
+    #[inline]
+    pub fn is_handled(&self) -> bool {
+        !matches!(self, PageFaultOutcome::Fatal { .. })
+    }
+
+    fn index(&self) -> usize {
+        match self {
+            PageFaultOutcome::StackGrow => 0,
+            PageFaultOutcome::LazyLoaded => 1,
+            PageFaultOutcome::Fatal { .. } => 2,
+            PageFaultOutcome::CopyOnWrite => 3,
+        }
+    }
+}
+
+impl From<MapToError<Size4KiB>> for FaultReason {
+    fn from(err: MapToError<Size4KiB>) -> Self {
+        match err {
+            MapToError::FrameAllocationFailed => FaultReason::OutOfMemory,
+            _ => FaultReason::MapFailed,
+        }
+    }
+}
+
+impl fmt::Display for FaultReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FaultReason::ProtectionViolation => f.write_str("protection violation"),
+            FaultReason::Unmapped => f.write_str("address not mapped"),
+            FaultReason::OutOfMemory => f.write_str("out of memory"),
+            FaultReason::MapFailed => f.write_str("failed to map the page"),
+            FaultReason::StackOverflow { limit } => {
+                write!(f, "stack overflow, past its limit of {} KiB", limit / 1024)
+            }
+            FaultReason::Io => f.write_str("failed to read the mapped file"),
+        }
+    }
+}
+
+/// Page faults seen so far, by outcome
+static COUNTS: [AtomicUsize; 4] = [
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+    AtomicUsize::new(0),
+];
+
+pub fn record(outcome: &PageFaultOutcome) {
+    COUNTS[outcome.index()].fetch_add(1, Ordering::Relaxed);
+}
+
+/// Page faults served by stack growth, by lazy loading,
+/// fatal ones, and those served by copy on write
+pub fn counts() -> (usize, usize, usize, usize) {
+    (
+        COUNTS[0].load(Ordering::Relaxed),
+        COUNTS[1].load(Ordering::Relaxed),
+        COUNTS[2].load(Ordering::Relaxed),
+        COUNTS[3].load(Ordering::Relaxed),
+    )
+}
@@ -3,11 +3,12 @@ use core::sync::atomic::{AtomicU64, Ordering};
 use alloc::sync::Arc;
 use x86::current;
 use x86_64::{
-    structures::paging::{mapper::UnmapError, Page},
+    structures::paging::{page::PageRange, Page},
     VirtAddr,
 };
 
 use super::{FrameAllocatorRef, MapperRef};
+use crate::proc::paging;
 
 // user process runtime heap
 // 0x100000000 bytes -> 4GiB
@@ -40,7 +41,16 @@ impl Heap {
         }
     }
 
+    /// The heap of a forked child, it grows on its own
     pub fn fork(&self) -> Self {
+        Self {
+            base: self.base,
+            end: Arc::new(AtomicU64::new(self.end.load(Ordering::Relaxed))),
+        }
+    }
+
+    /// The heap of a thread, growing along with the one it shares
+    pub fn share(&self) -> Self {
         Self {
             base: self.base,
             end: self.end.clone(),
@@ -88,11 +98,13 @@ impl Heap {
         if diff > 0 {
             // expand heap
             let range = Page::range_inclusive(current_end_page, new_end_page - 1);
+            paging::unshare(mapper, Page::range(current_end_page, new_end_page), alloc);
             elf::map_range(range, mapper, alloc, true).ok()?;
         }
         else if diff < 0 {
             // shrink heap
             let range = Page::range_inclusive(new_end_page, current_end_page - 1);
+            paging::unshare(mapper, Page::range(new_end_page, current_end_page), alloc);
             elf::unmap_range(range, mapper, alloc, true).ok()?;
         }
 
@@ -103,31 +115,27 @@ impl Heap {
 
     }
 
-    pub(super) fn clean_up(
-        &self,
-        mapper: MapperRef,
-        dealloc: FrameAllocatorRef,
-    ) -> Result<(), UnmapError> {
-        if self.memory_usage() == 0 {
-            return Ok(());
-        }
-
-        // FIXME: load the current end address and **reset it to base** (use `swap`)
+    /// Reset the heap to empty and return the pages it had, for the last
+    /// user of the page table to free, see `ProcessVm::clean_up`
+    pub(super) fn take_pages(&self) -> PageRange {
         let end = self.end.swap(self.base.as_u64(), Ordering::Relaxed);
-
         let start_page = Page::containing_address(self.base);
-        let end_page = Page::containing_address(VirtAddr::new(end));
-        let range = Page::range_inclusive(start_page, end_page);
-
-        // FIXME: unmap the heap pages
-        elf::unmap_range(range, mapper, dealloc, true)?;
+        if end == self.base.as_u64() {
+            return Page::range(start_page, start_page);
+        }
 
-        Ok(())
+        let end_page = Page::containing_address(VirtAddr::new(end));
+        Page::range(start_page, end_page + 1)
     }
 
     pub fn memory_usage(&self) -> u64 {
         self.end.load(Ordering::Relaxed) - self.base.as_u64()
     }
+
+    /// Check if `addr` is below the current break
+    pub fn contains(&self, addr: VirtAddr) -> bool {
+        addr >= self.base && addr.as_u64() < self.end.load(Ordering::Relaxed)
+    }
 }
 
 impl core::fmt::Debug for Heap {
@@ -0,0 +1,194 @@
+//! The program of a process, its pages filled from the ELF on first access
+//!
+//! only the loadable segments are recorded when the program starts,
+//! nothing is mapped. A page fault in a segment maps a zeroed frame with
+//! the bytes of the file that fall in the page copied in, so code that
+//! never runs is never copied, and the BSS is zeroed a page at a time.
+//! The ELF is kept by the app it came from for as long as the kernel
+//! runs, see `disk::find`, so forks and threads fill their pages from it
+//! too.
+
+use alloc::vec::Vec;
+use core::ptr::copy_nonoverlapping;
+use x86_64::structures::paging::{page::PageRangeInclusive, *};
+use x86_64::VirtAddr;
+use xmas_elf::{program, ElfFile};
+
+use super::{FaultReason, FrameAllocatorRef, MapperRef, PageFaultOutcome};
+use crate::memory::{physical_to_virtual, PAGE_SIZE};
+use crate::proc::paging::{self, segment_flags};
+use crate::proc::SpawnError;
+
+/// Segments must end below the kernel half of the address space
+const USER_END: u64 = 0x0000_8000_0000_0000;
+
+/// A loadable segment
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    /// where it starts in memory, not page aligned
+    start: u64,
+    /// bytes in memory, the ones past the file are zero
+    mem_size: u64,
+    /// the bytes of the file in it
+    data: &'static [u8],
+    flags: PageTableFlags,
+}
+
+impl Segment {
+    fn pages(&self) -> PageRangeInclusive {
+        let start = Page::containing_address(VirtAddr::new(self.start));
+        let end = Page::containing_address(VirtAddr::new(self.start + self.mem_size - 1));
+        Page::range_inclusive(start, end)
+    }
+
+    fn contains(&self, page: Page) -> bool {
+        let pages = self.pages();
+        pages.start <= page && page <= pages.end
+    }
+
+    /// Copy the bytes of the file in `page` to `dst`, the memory of the page
+    ///
+    /// # Safety
+    ///
+    /// `dst` must be valid for writes of a page.
+    unsafe fn copy_to(&self, page: Page, dst: *mut u8) {
+        let page_start = page.start_address().as_u64();
+        let from = self.start.max(page_start);
+        let to = (self.start + self.data.len() as u64).min(page_start + PAGE_SIZE);
+        if from >= to {
+            return;
+        }
+
+        let src = self.data.as_ptr().add((from - self.start) as usize);
+        copy_nonoverlapping(
+            src,
+            dst.add((from - page_start) as usize),
+            (to - from) as usize,
+        );
+    }
+}
+
+/// The loadable segments of a program
+#[derive(Clone, Debug, Default)]
+pub struct Image {
+    segments: Vec<Segment>,
+}
+
+impl Image {
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// The loadable segments of `elf`, leaving out the ones past the end
+    /// of the file or outside user space
+    ///
+    /// fails if a segment is both writable and executable, see `segment_flags`.
+    pub fn new(elf: &ElfFile<'static>) -> Result<Self, SpawnError> {
+        let input: &'static [u8] = elf.input;
+        let mut segments = Vec::new();
+        let loadable = elf
+            .program_iter()
+            .filter(|segment| segment.get_type() == Ok(program::Type::Load))
+            .filter(|segment| segment.mem_size() > 0);
+
+        for segment in loadable {
+            let (offset, file_size) = (segment.offset(), segment.file_size());
+            let data = offset
+                .checked_add(file_size)
+                .and_then(|end| input.get(offset as usize..end as usize));
+            let end = segment.virtual_addr().checked_add(segment.mem_size());
+            if data.is_none()
+                || file_size > segment.mem_size()
+                || !end.is_some_and(|end| end <= USER_END)
+            {
+                warn!("Skipping bad ELF segment: {:#x?}", segment);
+                continue;
+            }
+
+            let Some(flags) = segment_flags(segment.flags()) else {
+                warn!(
+                    "Refusing writable and executable ELF segment: {:#x?}",
+                    segment
+                );
+                return Err(SpawnError::InvalidElf);
+            };
+
+            segments.push(Segment {
+                start: segment.virtual_addr(),
+                mem_size: segment.mem_size(),
+                data: data.unwrap(),
+                flags,
+            });
+        }
+
+        Ok(Self { segments })
+    }
+
+    /// The pages of each segment
+    pub fn pages(&self) -> impl Iterator<Item = PageRangeInclusive> + '_ {
+        self.segments.iter().map(Segment::pages)
+    }
+
+    /// Whether the page at `addr` is writable, `None` if it is in no segment
+    pub fn is_writable(&self, addr: VirtAddr) -> Option<bool> {
+        let page = Page::containing_address(addr);
+        self.segment_of(page)
+            .map(|segment| segment.flags.contains(PageTableFlags::WRITABLE))
+    }
+
+    fn segment_of(&self, page: Page) -> Option<&Segment> {
+        self.segments.iter().find(|segment| segment.contains(page))
+    }
+
+    /// Fill the page at `addr` from the file, `None` if it is in no segment
+    ///
+    /// a page shared by two segments gets the bytes of both, and the
+    /// flags of the first.
+    pub fn handle_page_fault(
+        &self,
+        addr: VirtAddr,
+        write: bool,
+        mapper: MapperRef,
+        alloc: FrameAllocatorRef,
+    ) -> Option<PageFaultOutcome> {
+        let page = Page::containing_address(addr);
+        let flags = self.segment_of(page)?.flags;
+        if write && !flags.contains(PageTableFlags::WRITABLE) {
+            return Some(PageFaultOutcome::Fatal {
+                reason: FaultReason::ProtectionViolation,
+            });
+        }
+
+        trace!("Load program page {:#x}", page.start_address());
+
+        let Some(frame) = alloc.allocate_zeroed_frame() else {
+            error!("Load program page failed: out of frames");
+            return Some(PageFaultOutcome::Fatal {
+                reason: FaultReason::OutOfMemory,
+            });
+        };
+
+        let dst = physical_to_virtual(frame.start_address().as_u64()) as *mut u8;
+        let overlapping = self
+            .segments
+            .iter()
+            .filter(|segment| segment.contains(page));
+        for segment in overlapping {
+            unsafe { segment.copy_to(page, dst) };
+        }
+
+        paging::unshare(mapper, Page::range(page, page + 1), alloc);
+
+        Some(match unsafe { mapper.map_to(page, frame, flags, alloc) } {
+            Ok(flush) => {
+                flush.flush();
+                PageFaultOutcome::LazyLoaded
+            }
+            Err(err) => {
+                error!("Load program page failed: {:?}", err);
+                unsafe { alloc.deallocate_frame(frame) };
+                PageFaultOutcome::Fatal { reason: err.into() }
+            }
+        })
+    }
+}
@@ -0,0 +1,734 @@
+use alloc::{collections::BTreeMap, sync::Arc};
+use core::fmt;
+use core::sync::atomic::{AtomicU64, Ordering};
+use spin::RwLock;
+use x86_64::{
+    structures::paging::{
+        mapper::{MappedFrame, TranslateResult, UnmapError},
+        page::*,
+        *,
+    },
+    VirtAddr,
+};
+
+use crate::fs::{vfs::Inode, FsResult};
+use crate::memory::{physical_to_virtual, PAGE_SIZE};
+use crate::proc::paging::{self, ANON, SHARED};
+use crate::proc::swap;
+
+use super::{protect_page, FaultReason, FrameAllocatorRef, MapperRef, PageFaultOutcome};
+use syscall_def::errno::{EINVAL, ENOMEM};
+
+// user process memory mappings
+// 0x1000000000 bytes -> 64GiB
+// from 0x0000_1000_0000_0000 to 0x0000_100f_ffff_ffff
+pub const MMAP_START: u64 = 0x1000_0000_0000;
+pub const MMAP_PAGES: u64 = 0x1000000;
+pub const MMAP_SIZE: u64 = MMAP_PAGES * crate::memory::PAGE_SIZE;
+pub const MMAP_END: u64 = MMAP_START + MMAP_SIZE;
+
+/// A range of pages created by `mmap`, the range is [start, end)
+#[derive(Debug, Clone)]
+pub struct MapRegion {
+    pub start: Page,
+    pub end: Page,
+    pub flags: PageTableFlags,
+    /// pinned pages are kept resident
+    pub pinned: bool,
+    /// the file the pages are read from, anonymous pages start zeroed
+    pub file: Option<FileMap>,
+}
+
+/// The part of a file a region maps
+///
+/// a page is read from the file on first access. Pages of a `SHARED`
+/// region are written back once dirty, on `msync`, `munmap` and exit,
+/// the ones of a private region never are. Only forked processes share
+/// the pages, other mappings of the file see the writes once written back.
+#[derive(Clone)]
+pub struct FileMap {
+    pub inode: Arc<dyn Inode>,
+    /// where the first page of the region is in the file, page aligned
+    pub offset: u64,
+}
+
+impl fmt::Debug for FileMap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("FileMap")
+            .field("offset", &self.offset)
+            .finish()
+    }
+}
+
+/// Advice given by `madvise` on a range of mapped pages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Advice {
+    /// fill the pages now instead of on first access
+    WillNeed,
+    /// drop the pages, they read as zero on next access
+    DontNeed,
+    /// fill the pages and keep them resident
+    Pin,
+    /// allow the pages to be dropped again
+    Unpin,
+}
+
+impl MapRegion {
+    pub fn contains(&self, page: Page) -> bool {
+        self.start <= page && page < self.end
+    }
+
+    /// The part of the region before `page`
+    fn until(&self, page: Page) -> Self {
+        Self {
+            end: page,
+            ..self.clone()
+        }
+    }
+
+    /// The part of the region from `page` on, mapping the file from there
+    fn from(&self, page: Page) -> Self {
+        let file = self.file.as_ref().map(|file| FileMap {
+            inode: file.inode.clone(),
+            offset: file.offset + (page - self.start) * PAGE_SIZE,
+        });
+        Self {
+            start: page,
+            file,
+            ..self.clone()
+        }
+    }
+
+    /// Whether writes to its pages go back to the file
+    fn writes_back(&self) -> bool {
+        self.file.is_some() && self.flags.contains(SHARED)
+    }
+
+    /// Whether its pages may be swapped out, private anonymous ones
+    /// that are not pinned
+    fn swappable(&self) -> bool {
+        self.file.is_none() && !self.flags.contains(SHARED) && !self.pinned
+    }
+}
+
+/// The page `count` pages after `start`, `None` if the range is not
+/// in the area of memory mappings
+fn end_of(start: Page, count: u64) -> Option<Page> {
+    let start = start.start_address().as_u64();
+    let end = count
+        .checked_mul(PAGE_SIZE)
+        .and_then(|len| start.checked_add(len))?;
+    (MMAP_START <= start && end <= MMAP_END).then(|| Page::containing_address(VirtAddr::new(end)))
+}
+
+/// Memory mappings of a process
+///
+/// frames are only allocated when a page is touched for the first time
+pub struct MemoryMap {
+    /// regions indexed by their start address
+    ///
+    /// copied for forked processes, which share the pages mapped at the time
+    regions: Arc<RwLock<BTreeMap<u64, MapRegion>>>,
+
+    /// count of pages that are actually mapped
+    usage: Arc<AtomicU64>,
+}
+
+impl MemoryMap {
+    pub fn empty() -> Self {
+        Self {
+            regions: Arc::new(RwLock::new(BTreeMap::new())),
+            usage: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn fork(&self) -> Self {
+        Self {
+            regions: Arc::new(RwLock::new(self.regions.read().clone())),
+            usage: Arc::new(AtomicU64::new(self.usage.load(Ordering::Relaxed))),
+        }
+    }
+
+    /// The memory mappings of a thread, the same regions as the one it shares
+    pub fn share(&self) -> Self {
+        Self {
+            regions: self.regions.clone(),
+            usage: self.usage.clone(),
+        }
+    }
+
+    /// Fill the pages of shared regions that are not mapped yet
+    ///
+    /// called before a fork, a page filled after it would not be shared.
+    pub(super) fn fill_shared(&self, mapper: MapperRef, alloc: FrameAllocatorRef) -> bool {
+        self.regions
+            .read()
+            .values()
+            .filter(|region| region.flags.contains(SHARED))
+            .all(|region| self.fill_range(region, mapper, alloc))
+    }
+
+    /// Reserve `count` pages with `flags`, filled from `file` if given
+    ///
+    /// if `addr` is given, the region must start exactly there,
+    /// otherwise the first gap that fits is used.
+    pub fn map(
+        &self,
+        addr: Option<VirtAddr>,
+        count: u64,
+        flags: PageTableFlags,
+        file: Option<FileMap>,
+    ) -> Option<VirtAddr> {
+        if count == 0 || count > MMAP_PAGES {
+            return None;
+        }
+
+        let mut regions = self.regions.write();
+
+        let start = match addr {
+            Some(addr) => {
+                let start = Page::from_start_address(addr).ok()?;
+                let end = end_of(start, count)?;
+                let overlapped = regions
+                    .values()
+                    .any(|region| region.start < end && start < region.end);
+                if overlapped {
+                    return None;
+                }
+                start
+            }
+            None => {
+                let mut start = Page::containing_address(VirtAddr::new(MMAP_START));
+                for region in regions.values() {
+                    if region.start - start >= count {
+                        break;
+                    }
+                    start = start.max(region.end);
+                }
+                end_of(start, count)?;
+                start
+            }
+        };
+
+        let region = MapRegion {
+            start,
+            end: start + count,
+            flags,
+            pinned: false,
+            file,
+        };
+
+        trace!("Map region: {:?}", region);
+
+        regions.insert(start.start_address().as_u64(), region);
+        Some(start.start_address())
+    }
+
+    /// Map `frames` at `addr` or the first gap that fits, for memory
+    /// shared with other processes, each page takes a reference to its frame
+    pub fn map_frames(
+        &self,
+        addr: Option<VirtAddr>,
+        frames: &[PhysFrame],
+        flags: PageTableFlags,
+        mapper: MapperRef,
+        alloc: FrameAllocatorRef,
+    ) -> Option<VirtAddr> {
+        let count = frames.len() as u64;
+        let start = self.map(addr, count, flags, None)?;
+        let first = Page::containing_address(start);
+        paging::unshare(mapper, Page::range(first, first + count), alloc);
+
+        for (page, &frame) in Page::range(first, first + count).zip(frames) {
+            alloc.share_frame(frame);
+            match unsafe { mapper.map_to(page, frame, flags, alloc) } {
+                Ok(flush) => flush.flush(),
+                Err(err) => {
+                    error!("Map shared page failed: {:?}", err);
+                    unsafe { alloc.deallocate_frame(frame) };
+                    self.unmap(start, count, mapper, alloc).ok();
+                    return None;
+                }
+            }
+            self.usage.fetch_add(1, Ordering::Relaxed);
+        }
+
+        Some(start)
+    }
+
+    /// Remove `count` pages starting at `addr` from the mappings
+    ///
+    /// regions partially covered are shrunk or split, dirty pages of
+    /// shared files are written back, and frames that have been touched
+    /// are returned to the allocator.
+    pub fn unmap(
+        &self,
+        addr: VirtAddr,
+        count: u64,
+        mapper: MapperRef,
+        dealloc: FrameAllocatorRef,
+    ) -> Result<(), UnmapError> {
+        let start = Page::from_start_address(addr).map_err(|_| UnmapError::PageNotMapped)?;
+        let end = end_of(start, count).ok_or(UnmapError::PageNotMapped)?;
+
+        let mut regions = self.regions.write();
+
+        let overlapped = regions
+            .values()
+            .filter(|region| region.start < end && start < region.end)
+            .cloned()
+            .collect::<alloc::vec::Vec<_>>();
+
+        for region in overlapped {
+            regions.remove(&region.start.start_address().as_u64());
+
+            if region.start < start {
+                regions.insert(region.start.start_address().as_u64(), region.until(start));
+            }
+
+            if end < region.end {
+                regions.insert(end.start_address().as_u64(), region.from(end));
+            }
+
+            let range = Page::range(region.start.max(start), region.end.min(end));
+            if let Err(err) = write_back(&region, range, mapper) {
+                warn!("Unmap: failed to write back {:?}: {}", region, err);
+            }
+            self.unmap_touched(range, mapper, dealloc)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write the dirty pages of shared files in `count` pages from `addr`
+    /// back, for `msync`
+    ///
+    /// the whole range must be covered by mappings.
+    pub fn sync(&self, addr: VirtAddr, count: u64, mapper: MapperRef) -> Result<(), usize> {
+        let start = Page::from_start_address(addr).map_err(|_| EINVAL)?;
+        let end = end_of(start, count).ok_or(ENOMEM)?;
+
+        let regions = self.regions.read();
+        let mut cursor = start;
+        for region in regions
+            .values()
+            .filter(|region| region.start < end && start < region.end)
+        {
+            if region.start > cursor {
+                return Err(ENOMEM);
+            }
+            let range = Page::range(region.start.max(start), region.end.min(end));
+            write_back(region, range, mapper).map_err(|err| err.errno())?;
+            cursor = region.end;
+        }
+
+        match cursor >= end {
+            true => Ok(()),
+            false => Err(ENOMEM),
+        }
+    }
+
+    /// Find the region that contains `addr`
+    pub fn region_of(&self, addr: VirtAddr) -> Option<MapRegion> {
+        let page = Page::containing_address(addr);
+        self.regions
+            .read()
+            .range(..=page.start_address().as_u64())
+            .next_back()
+            .map(|(_, region)| region.clone())
+            .filter(|region| region.contains(page))
+    }
+
+    /// Apply `advice` to `count` pages starting at `addr`
+    ///
+    /// the whole range must be covered by mappings,
+    /// and pinned pages can not be dropped.
+    pub fn advise(
+        &self,
+        addr: VirtAddr,
+        count: u64,
+        advice: Advice,
+        mapper: MapperRef,
+        alloc: FrameAllocatorRef,
+    ) -> bool {
+        let start = match Page::from_start_address(addr) {
+            Ok(start) => start,
+            Err(_) => return false,
+        };
+        let end = match end_of(start, count) {
+            Some(end) => end,
+            None => return false,
+        };
+
+        let mut regions = self.regions.write();
+
+        let covered = match Self::split_covered(&mut regions, start, end) {
+            Some(covered) => covered,
+            None => return false,
+        };
+
+        match advice {
+            Advice::WillNeed => covered
+                .iter()
+                .all(|region| self.fill_range(region, mapper, alloc)),
+            Advice::DontNeed => {
+                if covered.iter().any(|region| region.pinned) {
+                    return false;
+                }
+                // dropped pages of shared files are read back in when used
+                for region in covered.iter() {
+                    let range = Page::range(region.start, region.end);
+                    if write_back(region, range, mapper).is_err() {
+                        return false;
+                    }
+                }
+                self.unmap_touched(Page::range(start, end), mapper, alloc)
+                    .is_ok()
+            }
+            Advice::Pin | Advice::Unpin => {
+                let pinned = advice == Advice::Pin;
+                for region in covered.iter() {
+                    if let Some(region) = regions.get_mut(&region.start.start_address().as_u64()) {
+                        region.pinned = pinned;
+                    }
+
+                    // pinned pages are read back in and never swapped out
+                    let region = MapRegion {
+                        pinned,
+                        ..region.clone()
+                    };
+                    if pinned && !self.fill_range(&region, mapper, alloc) {
+                        return false;
+                    }
+                    mark_swappable(&region, mapper, alloc);
+                }
+                true
+            }
+        }
+    }
+
+    /// Change the protection of `count` pages starting at `addr` to `flags`
+    ///
+    /// the whole range must be covered by mappings. Pages still shared
+    /// with a forked process stay read-only until written, see `COW`.
+    pub fn protect(
+        &self,
+        addr: VirtAddr,
+        count: u64,
+        flags: PageTableFlags,
+        mapper: MapperRef,
+        alloc: FrameAllocatorRef,
+    ) -> bool {
+        let start = match Page::from_start_address(addr) {
+            Ok(start) => start,
+            Err(_) => return false,
+        };
+        let end = match end_of(start, count) {
+            Some(end) => end,
+            None => return false,
+        };
+
+        let mut regions = self.regions.write();
+
+        let covered = match Self::split_covered(&mut regions, start, end) {
+            Some(covered) => covered,
+            None => return false,
+        };
+
+        for region in covered.iter() {
+            // whether the region is shared with forked processes stays the same
+            let flags = flags | (region.flags & SHARED);
+            if let Some(region) = regions.get_mut(&region.start.start_address().as_u64()) {
+                region.flags = flags;
+            }
+
+            for page in Page::range(region.start, region.end) {
+                protect_page(page, flags, mapper, alloc);
+            }
+        }
+
+        true
+    }
+
+    /// Fill the page at `addr`, `None` if it is not in a mapping
+    pub fn handle_page_fault(
+        &self,
+        addr: VirtAddr,
+        mapper: MapperRef,
+        alloc: FrameAllocatorRef,
+    ) -> Option<PageFaultOutcome> {
+        let region = self.region_of(addr)?;
+
+        let page = Page::containing_address(addr);
+
+        trace!(
+            "Fill mapped page {:#x} of {:?}",
+            page.start_address(),
+            region
+        );
+
+        Some(match self.fill_page(page, &region, mapper, alloc) {
+            Ok(()) => PageFaultOutcome::LazyLoaded,
+            Err(reason) => PageFaultOutcome::Fatal { reason },
+        })
+    }
+
+    /// The regions making up `[start, end)`, split on its boundaries
+    ///
+    /// `None` if the range is not wholly covered by mappings.
+    fn split_covered(
+        regions: &mut BTreeMap<u64, MapRegion>,
+        start: Page,
+        end: Page,
+    ) -> Option<alloc::vec::Vec<MapRegion>> {
+        Self::split_at(regions, start);
+        Self::split_at(regions, end);
+
+        let covered = regions
+            .range(start.start_address().as_u64()..end.start_address().as_u64())
+            .map(|(_, region)| region.clone())
+            .collect::<alloc::vec::Vec<_>>();
+
+        let mut cursor = start;
+        for region in covered.iter() {
+            if region.start != cursor {
+                return None;
+            }
+            cursor = region.end;
+        }
+
+        (cursor == end).then_some(covered)
+    }
+
+    /// Split the region that contains `page` into two regions at `page`
+    fn split_at(regions: &mut BTreeMap<u64, MapRegion>, page: Page) {
+        let region = regions
+            .range(..page.start_address().as_u64())
+            .next_back()
+            .map(|(_, region)| region.clone())
+            .filter(|region| region.contains(page));
+
+        if let Some(region) = region {
+            regions.insert(region.start.start_address().as_u64(), region.until(page));
+            regions.insert(page.start_address().as_u64(), region.from(page));
+        }
+    }
+
+    /// Fill all pages of `region` that are not mapped yet
+    fn fill_range(&self, region: &MapRegion, mapper: MapperRef, alloc: FrameAllocatorRef) -> bool {
+        // a page swapped out still translates, to its slot
+        Page::range(region.start, region.end).all(|page| {
+            (mapper.translate_page(page).is_ok() && swap::swapped_entry(mapper, page).is_none())
+                || self.fill_page(page, region, mapper, alloc).is_ok()
+        })
+    }
+
+    /// Back `page` of `region` with a zeroed frame, with the bytes of
+    /// the file in the page read in, if it maps one
+    ///
+    /// a page swapped out is read back in instead, it is still counted
+    /// in the usage.
+    fn fill_page(
+        &self,
+        page: Page,
+        region: &MapRegion,
+        mapper: MapperRef,
+        alloc: FrameAllocatorRef,
+    ) -> Result<(), FaultReason> {
+        paging::unshare(mapper, Page::range(page, page + 1), alloc);
+
+        if let Some(entry) = swap::swapped_entry(mapper, page) {
+            return swap::swap_in(entry, page.start_address(), alloc);
+        }
+
+        let frame = match alloc.allocate_zeroed_frame() {
+            Some(frame) => frame,
+            None => {
+                error!("Map page failed: out of frames");
+                return Err(FaultReason::OutOfMemory);
+            }
+        };
+
+        // past the end of the file the page stays zeroed
+        if let Some(file) = &region.file {
+            let offset = file.offset + (page - region.start) * PAGE_SIZE;
+            let buf = unsafe { frame_bytes(frame) };
+            if let Err(err) = file.inode.read_at(offset as usize, buf) {
+                // a read pending in a syscall is retried with it
+                if !err.is_pending() {
+                    warn!("Map page failed: {}", err);
+                }
+                unsafe { alloc.deallocate_frame(frame) };
+                return Err(FaultReason::Io);
+            }
+        }
+
+        let flags = match region.swappable() {
+            true => region.flags | ANON,
+            false => region.flags,
+        };
+        match unsafe { mapper.map_to(page, frame, flags, alloc) } {
+            Ok(flush) => flush.flush(),
+            Err(err) => {
+                error!("Map page failed: {:?}", err);
+                unsafe { alloc.deallocate_frame(frame) };
+                return Err(err.into());
+            }
+        }
+
+        self.usage.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Unmap every region, for the last user of the page table exiting,
+    /// before `paging::release_shared`
+    ///
+    /// pages under a table still shared with a forked process are written
+    /// back and uncounted, but left mapped for the table to be dropped whole.
+    pub(super) fn clean_up(
+        &self,
+        mapper: MapperRef,
+        dealloc: FrameAllocatorRef,
+    ) -> Result<(), UnmapError> {
+        let regions = core::mem::take(&mut *self.regions.write());
+
+        for region in regions.values() {
+            let range = Page::range(region.start, region.end);
+            if let Err(err) = write_back(region, range, mapper) {
+                warn!("Clean up: failed to write back {:?}: {}", region, err);
+            }
+
+            for page in range {
+                if !paging::in_shared_table(mapper, page, dealloc) {
+                    self.unmap_page(page, mapper, dealloc)?;
+                } else if mapper.translate_page(page).is_ok()
+                    || swap::swapped_entry(mapper, page).is_some()
+                {
+                    self.usage.fetch_sub(1, Ordering::Relaxed);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Unmap the pages in `range` that have been filled by the page fault handler,
+    /// or swapped out since
+    fn unmap_touched(
+        &self,
+        range: PageRange,
+        mapper: MapperRef,
+        dealloc: FrameAllocatorRef,
+    ) -> Result<(), UnmapError> {
+        paging::unshare(mapper, range, dealloc);
+
+        for page in range {
+            self.unmap_page(page, mapper, dealloc)?;
+        }
+
+        Ok(())
+    }
+
+    /// Unmap `page` if it is mapped or swapped out, its tables must not
+    /// be shared
+    fn unmap_page(
+        &self,
+        page: Page,
+        mapper: MapperRef,
+        dealloc: FrameAllocatorRef,
+    ) -> Result<(), UnmapError> {
+        match mapper.unmap(page) {
+            Ok((frame, flush)) => {
+                unsafe { dealloc.deallocate_frame(frame) };
+                flush.flush();
+                self.usage.fetch_sub(1, Ordering::Relaxed);
+            }
+            Err(UnmapError::PageNotMapped) => {
+                // a page swapped out gives its slot back
+                if let Some(entry) = swap::swapped_entry(mapper, page) {
+                    swap::discard(entry);
+                    self.usage.fetch_sub(1, Ordering::Relaxed);
+                }
+            }
+            Err(err) => return Err(err),
+        }
+        Ok(())
+    }
+
+    pub fn memory_usage(&self) -> u64 {
+        self.usage.load(Ordering::Relaxed) * crate::memory::PAGE_SIZE
+    }
+}
+
+/// Write the dirty pages of `region` in `range` back to its file, if it
+/// is shared, and mark them clean
+///
+/// the bytes of a page past the end of the file are not written.
+fn write_back(region: &MapRegion, range: PageRange, mapper: MapperRef) -> FsResult<()> {
+    let Some(file) = region.file.as_ref().filter(|_| region.writes_back()) else {
+        return Ok(());
+    };
+
+    let size = file.inode.metadata().size as u64;
+    for page in range {
+        let (frame, flags) = match mapper.translate(page.start_address()) {
+            TranslateResult::Mapped {
+                frame: MappedFrame::Size4KiB(frame),
+                flags,
+                ..
+            } if flags.contains(PageTableFlags::DIRTY) => (frame, flags),
+            _ => continue,
+        };
+
+        let offset = file.offset + (page - region.start) * PAGE_SIZE;
+        if offset < size {
+            let len = (size - offset).min(PAGE_SIZE) as usize;
+            let bytes = unsafe { frame_bytes(frame) };
+            file.inode.write_at(offset as usize, &bytes[..len])?;
+        }
+
+        if let Ok(flush) = unsafe { mapper.update_flags(page, flags - PageTableFlags::DIRTY) } {
+            flush.flush();
+        }
+    }
+
+    Ok(())
+}
+
+/// Mark the pages of `region` in memory as swappable or not, see `ANON`
+fn mark_swappable(region: &MapRegion, mapper: MapperRef, alloc: FrameAllocatorRef) {
+    let range = Page::range(region.start, region.end);
+    paging::unshare(mapper, range, alloc);
+
+    for page in range {
+        let Some(entry) = paging::entry_mut(mapper, page) else {
+            continue;
+        };
+        let flags = entry.flags();
+        if flags.contains(PageTableFlags::PRESENT) {
+            match region.swappable() {
+                true => entry.set_flags(flags | ANON),
+                false => entry.set_flags(flags - ANON),
+            }
+        }
+    }
+}
+
+/// The bytes of `frame`, through the physical memory map
+///
+/// # Safety
+///
+/// nothing else may use the frame while the slice is alive.
+unsafe fn frame_bytes<'a>(frame: PhysFrame) -> &'a mut [u8] {
+    let ptr = physical_to_virtual(frame.start_address().as_u64()) as *mut u8;
+    core::slice::from_raw_parts_mut(ptr, PAGE_SIZE as usize)
+}
+
+impl core::fmt::Debug for MemoryMap {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("MemoryMap")
+            .field("regions", &self.regions.read().len())
+            .field("usage", &self.usage.load(Ordering::Relaxed))
+            .finish()
+    }
+}
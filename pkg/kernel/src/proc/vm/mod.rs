@@ -1,22 +1,38 @@
 use alloc::{format, vec::Vec};
 use boot::KernelPages;
 use x86_64::{
+    structures::idt::PageFaultErrorCode,
     structures::paging::{
-        mapper::{CleanUp, UnmapError},
+        mapper::{CleanUp, MappedFrame, TranslateResult, UnmapError},
         page::*,
         *,
     },
     VirtAddr,
 };
-use xmas_elf::ElfFile;
-use crate::{humanized_size, memory::*};
+use syscall_def::errno::EINVAL;
+use crate::{humanized_size, memory::*, utils::sysctl::Tunable};
 
+pub mod fault;
 pub mod heap;
+pub mod image;
+pub mod mmap;
 pub mod stack;
 
-use self::{heap::Heap, stack::Stack};
+pub use self::fault::{FaultReason, PageFaultOutcome};
 
-use super::PageTableContext;
+use self::{
+    heap::Heap,
+    image::Image,
+    mmap::{Advice, FileMap, MemoryMap},
+    stack::{Stack, StackArgs},
+};
+
+use super::paging::{self, ANON, COW, SHARED};
+use super::swap;
+use super::{AppArgs, PageTableContext};
+
+/// 1 lets ELF segments and `mprotect` make pages both writable and executable
+pub static ALLOW_WX: Tunable = Tunable::new("mem.allow_wx", 0);
 
 // See the documentation for the `KernelPages` type
 // Ignore when you not reach this part
@@ -27,7 +43,8 @@ type MapperRef<'a> = &'a mut OffsetPageTable<'static>;
 type FrameAllocatorRef<'a> = &'a mut BootInfoFrameAllocator;
 
 pub struct ProcessVm {
-    // page table is shared by parent and child
+    // page tables are shared with a forked child until written, the
+    // pages in them copy-on-write
     pub(super) page_table: PageTableContext,
 
     // stack is pre-process allocated
@@ -36,10 +53,15 @@ pub struct ProcessVm {
     // heap is allocated by brk syscall
     pub(super) heap: Heap,
 
-    // code is hold by the first process
-    // these fields will be empty for other processes
+    // memory mappings are created by mmap syscall
+    pub(super) mmap: MemoryMap,
+
+    // code pages, shared with forked children until their tables split
     pub(super) code: Vec<PageRangeInclusive>,
     pub(super) code_usage: u64,
+
+    // the program the code pages are filled from as they are touched
+    pub(super) image: Image,
 }
 
 impl ProcessVm {
@@ -48,8 +70,10 @@ impl ProcessVm {
             page_table,
             stack: Stack::empty(),
             heap: Heap::empty(),
+            mmap: MemoryMap::empty(),
             code: Vec::new(),
             code_usage: 0,
+            image: Image::empty(),
         }
     }
 
@@ -85,51 +109,295 @@ impl ProcessVm {
         )
     }
 
-    pub fn load_elf(&mut self, elf: &ElfFile) {
-        let mapper = &mut self.page_table.mapper();
+    pub fn mmap(
+        &self,
+        addr: Option<VirtAddr>,
+        count: u64,
+        flags: PageTableFlags,
+        file: Option<FileMap>,
+    ) -> Option<VirtAddr> {
+        self.mmap.map(addr, count, flags, file)
+    }
 
+    /// Write the dirty pages of shared files in `count` pages from `addr` back
+    pub fn msync(&self, addr: VirtAddr, count: u64) -> Result<(), usize> {
+        self.mmap.sync(addr, count, &mut self.page_table.mapper())
+    }
+
+    /// Change the protection of `count` pages from `addr` to `flags`
+    ///
+    /// the pages must all be in memory mappings, or all in the stack,
+    /// the heap or the code. Pages of those not mapped yet get their
+    /// usual flags when they are.
+    pub fn mprotect(&self, addr: VirtAddr, count: u64, flags: PageTableFlags) -> bool {
+        let mapper = &mut self.page_table.mapper();
         let alloc = &mut *get_frame_alloc_for_sure();
 
-        self.load_elf_code(elf, mapper, alloc);
-        self.stack.init(mapper, alloc);
+        if self.mmap.protect(addr, count, flags, mapper, alloc) {
+            return true;
+        }
+
+        let end = count
+            .checked_mul(PAGE_SIZE)
+            .and_then(|len| addr.as_u64().checked_add(len))
+            .and_then(|end| VirtAddr::try_new(end).ok());
+        let (start, end) = match (Page::<Size4KiB>::from_start_address(addr), end) {
+            (Ok(start), Some(end)) => (start, Page::containing_address(end)),
+            _ => return false,
+        };
+
+        let range = Page::range(start, end);
+        if !range.into_iter().all(|page| self.is_fixed(page)) {
+            return false;
+        }
+
+        for page in range {
+            protect_page(page, flags, mapper, alloc);
+        }
+
+        true
     }
 
-    fn load_elf_code(&mut self, elf: &ElfFile, mapper: MapperRef, alloc: FrameAllocatorRef) {
-        // FIXME: make the `load_elf` function return the code pages
-        self.code =
-            elf::load_elf(elf, *PHYSICAL_OFFSET.get().unwrap(), mapper, alloc, true).unwrap();
+    /// Check if `page` is on the stack, in the heap or in the code
+    fn is_fixed(&self, page: Page) -> bool {
+        let addr = page.start_address();
+        self.stack.is_on_stack(addr)
+            || self.heap.contains(addr)
+            || self
+                .code
+                .iter()
+                .any(|range| range.start <= page && page <= range.end)
+    }
 
-        // FIXME: calculate code usage
-        self.code_usage = self.code.iter().map(|range| range.count()).sum::<usize>() as u64 * crate::memory::PAGE_SIZE;
+    pub fn munmap(&self, addr: VirtAddr, count: u64) -> Result<(), UnmapError> {
+        self.mmap.unmap(
+            addr,
+            count,
+            &mut self.page_table.mapper(),
+            &mut get_frame_alloc_for_sure(),
+        )
+    }
 
+    /// Map the frames of a shared memory segment, see `shm`
+    pub fn map_shared(&self, addr: Option<VirtAddr>, frames: &[PhysFrame]) -> Option<VirtAddr> {
+        let flags = PageTableFlags::PRESENT
+            | PageTableFlags::WRITABLE
+            | PageTableFlags::USER_ACCESSIBLE
+            | PageTableFlags::NO_EXECUTE
+            | SHARED;
+
+        self.mmap.map_frames(
+            addr,
+            frames,
+            flags,
+            &mut self.page_table.mapper(),
+            &mut get_frame_alloc_for_sure(),
+        )
     }
 
-    pub fn fork(&self, stack_offset_count: u64) -> Self {
-        let owned_page_table = self.page_table.fork();
-        let mapper = &mut owned_page_table.mapper();
+    /// Unmap the shared memory segment mapped from `addr`
+    pub fn unmap_shared(&self, addr: VirtAddr) -> Result<(), usize> {
+        let page = Page::<Size4KiB>::from_start_address(addr).map_err(|_| EINVAL)?;
+        let frame = self
+            .page_table
+            .mapper()
+            .translate_page(page)
+            .map_err(|_| EINVAL)?;
+        let count = super::shm::pages_from(frame).ok_or(EINVAL)?;
+        self.munmap(addr, count).map_err(|_| EINVAL)
+    }
+
+    pub fn madvise(&self, addr: VirtAddr, count: u64, advice: Advice) -> bool {
+        self.mmap.advise(
+            addr,
+            count,
+            advice,
+            &mut self.page_table.mapper(),
+            &mut get_frame_alloc_for_sure(),
+        )
+    }
+
+    /// Record the segments of `image` to be loaded as they are touched,
+    /// and map the stack
+    pub fn load_elf(&mut self, image: Image, stack_pages: u64) {
+        let mapper = &mut self.page_table.mapper();
 
         let alloc = &mut *get_frame_alloc_for_sure();
 
+        self.image = image;
+        self.code = self.image.pages().collect();
+        // counted as the pages are loaded
+        self.code_usage = 0;
+
+        self.stack.init(stack_pages, mapper, alloc);
+    }
+
+    /// Copy `args` to the top of the stack, see `stack::push_args`
+    pub fn push_args(&self, args: &AppArgs) -> Option<StackArgs> {
+        stack::push_args(args, &self.page_table.mapper())
+    }
+
+    pub fn fork(&self) -> Self {
+        // shared mappings only stay shared for the pages mapped now
+        if !self
+            .mmap
+            .fill_shared(&mut self.page_table.mapper(), &mut get_frame_alloc_for_sure())
+        {
+            warn!("Fork: failed to fill shared mappings.");
+        }
+
         Self {
-            page_table: owned_page_table,
-            stack: self.stack.fork(mapper, alloc, stack_offset_count),
+            page_table: self.page_table.fork(),
+            stack: self.stack.fork(),
             heap: self.heap.fork(),
+            mmap: self.mmap.fork(),
+            code: self.code.clone(),
+            code_usage: self.code_usage,
+            image: self.image.clone(),
+        }
+    }
 
-            // do not share code info
-            code: Vec::new(),
-            code_usage: 0,
+    /// The memory of a thread, all of it shared with this process
+    ///
+    /// only the stack is its own, the thread brings it in memory both
+    /// can reach. Nothing is freed until the last of them exits.
+    pub fn thread(&self) -> Self {
+        Self {
+            page_table: self.page_table.share(),
+            stack: Stack::empty(),
+            heap: self.heap.share(),
+            mmap: self.mmap.share(),
+            code: self.code.clone(),
+            code_usage: self.code_usage,
+            image: self.image.clone(),
         }
     }
 
-    pub fn handle_page_fault(&mut self, addr: VirtAddr) -> bool {
+    pub fn handle_page_fault(
+        &mut self,
+        addr: VirtAddr,
+        err_code: PageFaultErrorCode,
+    ) -> PageFaultOutcome {
         let mapper = &mut self.page_table.mapper();
         let alloc = &mut *get_frame_alloc_for_sure();
 
-        self.stack.handle_page_fault(addr, mapper, alloc)
+        // make room before taking a frame, if memory runs low
+        swap::balance(alloc);
+
+        if err_code.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+            if err_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
+                return copy_on_write(addr, mapper, alloc);
+            }
+            return PageFaultOutcome::Fatal {
+                reason: FaultReason::ProtectionViolation,
+            };
+        }
+
+        let write = err_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE);
+        self.stack
+            .handle_page_fault(addr, mapper, alloc)
+            .or_else(|| self.mmap.handle_page_fault(addr, mapper, alloc))
+            .or_else(|| {
+                let outcome = self.image.handle_page_fault(addr, write, mapper, alloc)?;
+                if outcome.is_handled() {
+                    self.code_usage += PAGE_SIZE;
+                }
+                Some(outcome)
+            })
+            .unwrap_or(PageFaultOutcome::Fatal {
+                reason: FaultReason::Unmapped,
+            })
+    }
+
+    /// Make `[addr, addr + len)` writable now, as user writes to it would
+    ///
+    /// for writes from where a page fault cannot be taken. Return false
+    /// if any of it cannot be written from user mode.
+    pub fn fault_in(&mut self, addr: VirtAddr, len: u64) -> bool {
+        if len == 0 {
+            return true;
+        }
+        if !self.check_user_range(addr, len, true) {
+            return false;
+        }
+
+        let start_page = Page::<Size4KiB>::containing_address(addr);
+        let end_page = Page::<Size4KiB>::containing_address(addr + (len - 1));
+        for page in Page::range_inclusive(start_page, end_page) {
+            let write = PageFaultErrorCode::CAUSED_BY_WRITE | PageFaultErrorCode::USER_MODE;
+            let mapper = self.page_table.mapper();
+            // a page under tables shared with a forked process is not yet
+            if paging::is_writable(&mapper, page) {
+                continue;
+            }
+            // a page swapped out is in the table too, but not present
+            let present = matches!(
+                mapper.translate(page.start_address()),
+                TranslateResult::Mapped { flags, .. } if flags.contains(PageTableFlags::PRESENT)
+            );
+            let err_code = match present {
+                true => write | PageFaultErrorCode::PROTECTION_VIOLATION,
+                false => write,
+            };
+            let outcome = self.handle_page_fault(page.start_address(), err_code);
+            if matches!(outcome, PageFaultOutcome::Fatal { .. }) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Check if `[addr, addr + len)` can be accessed from user mode
+    ///
+    /// pages on the stack, in memory mappings or in the program that are
+    /// not mapped yet are accepted, they will be filled by the page fault
+    /// handler on first access.
+    pub fn check_user_range(&self, addr: VirtAddr, len: u64, write: bool) -> bool {
+        if len == 0 {
+            return true;
+        }
+
+        let end = match addr
+            .as_u64()
+            .checked_add(len - 1)
+            .and_then(|end| VirtAddr::try_new(end).ok())
+        {
+            Some(end) => end,
+            None => return false,
+        };
+
+        let mapper = self.page_table.mapper();
+        let start_page = Page::<Size4KiB>::containing_address(addr);
+        let end_page = Page::<Size4KiB>::containing_address(end);
+
+        Page::range_inclusive(start_page, end_page).all(|page| {
+            match mapper.translate(page.start_address()) {
+                TranslateResult::Mapped { flags, .. } => {
+                    flags.contains(PageTableFlags::USER_ACCESSIBLE)
+                        && (!write || flags.intersects(PageTableFlags::WRITABLE | COW))
+                }
+                _ => {
+                    self.stack.is_on_stack(page.start_address())
+                        || self
+                            .mmap
+                            .region_of(page.start_address())
+                            .is_some_and(|region| {
+                                !write || region.flags.contains(PageTableFlags::WRITABLE)
+                            })
+                        || self
+                            .image
+                            .is_writable(page.start_address())
+                            .is_some_and(|writable| !write || writable)
+                }
+            }
+        })
     }
 
     pub(super) fn memory_usage(&self) -> u64 {
-        self.stack.memory_usage() + self.heap.memory_usage() + self.code_usage
+        self.stack.memory_usage()
+            + self.heap.memory_usage()
+            + self.mmap.memory_usage()
+            + self.code_usage
     }
 
     pub(super) fn clean_up(&mut self) -> Result<(), UnmapError> {
@@ -138,17 +406,18 @@ impl ProcessVm {
         
         let start_count = dealloc.frames_recycled();
 
-        // FIXME: implement the `clean_up` function for `Stack`
-        self.stack.clean_up(mapper, dealloc)?;
-
         if self.page_table.using_count() == 1 {
-            // free heap
-            // FIXME: implement the `clean_up` function for `Heap`
-            self.heap.clean_up(mapper, dealloc)?;
+            // free memory mappings
+            self.mmap.clean_up(mapper, dealloc)?;
+
+            // the rest under tables still shared is left to forked processes
+            paging::release_shared(mapper, dealloc);
 
-            // free code
-            for page_range in self.code.iter() {
-                elf::unmap_range(*page_range, mapper, dealloc, true)?;
+            // free stack, heap and code, no table is shared anymore
+            release_pages(self.stack.take_pages(), mapper, dealloc)?;
+            release_pages(self.heap.take_pages(), mapper, dealloc)?;
+            for range in &self.code {
+                release_pages(*range, mapper, dealloc)?;
             }
 
             unsafe {
@@ -158,6 +427,9 @@ impl ProcessVm {
                 // free P4
                 dealloc.deallocate_frame(self.page_table.reg.addr);
             }
+        } else {
+            // the page table is still in use, only the stack is this one's
+            self.stack.clean_up(mapper, dealloc)?;
         }
 
         // NOTE: maybe print how many frames are recycled
@@ -177,6 +449,159 @@ impl ProcessVm {
     }
 }
 
+/// Free the pages of `pages` still mapped or swapped out, the tables
+/// over them must not be shared, see `paging::release_shared`
+fn release_pages(
+    pages: impl IntoIterator<Item = Page>,
+    mapper: MapperRef,
+    dealloc: FrameAllocatorRef,
+) -> Result<(), UnmapError> {
+    for page in pages {
+        match mapper.unmap(page) {
+            Ok((frame, flush)) => {
+                unsafe { dealloc.deallocate_frame(frame) };
+                flush.flush();
+            }
+            Err(UnmapError::PageNotMapped) => {
+                if let Some(entry) = swap::swapped_entry(mapper, page) {
+                    swap::discard(entry);
+                }
+            }
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Set the flags of `page` if it is mapped or swapped out
+///
+/// a writable page whose frame is still shared with a forked process
+/// stays read-only until written, see `COW`. Whether it may be swapped
+/// out stays the same.
+pub(super) fn protect_page(
+    page: Page,
+    flags: PageTableFlags,
+    mapper: MapperRef,
+    alloc: FrameAllocatorRef,
+) {
+    paging::unshare(mapper, Page::range(page, page + 1), alloc);
+
+    let (frame, old_flags) = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped {
+            frame: MappedFrame::Size4KiB(frame),
+            flags,
+            ..
+        } if flags.contains(PageTableFlags::PRESENT) => (frame, flags),
+        _ => {
+            if let Some(entry) = swap::swapped_entry(mapper, page) {
+                swap::protect(entry, flags);
+            }
+            return;
+        }
+    };
+
+    let mut page_flags = flags | (old_flags & ANON);
+    if flags.contains(PageTableFlags::WRITABLE)
+        && !flags.contains(SHARED)
+        && alloc.is_shared(frame)
+    {
+        page_flags = (page_flags - PageTableFlags::WRITABLE) | COW;
+    }
+
+    if let Ok(flush) = unsafe { mapper.update_flags(page, page_flags) } {
+        flush.flush();
+    }
+}
+
+/// Resolve a write to a copy-on-write page
+///
+/// the tables on its way are split first, see `paging::unshare`. The
+/// page then gets its own copy of the frame, unless no other process
+/// maps the frame anymore, then it is just made writable again.
+fn copy_on_write(addr: VirtAddr, mapper: MapperRef, alloc: FrameAllocatorRef) -> PageFaultOutcome {
+    let page = Page::<Size4KiB>::containing_address(addr);
+
+    let writable = match mapper.translate(addr) {
+        TranslateResult::Mapped {
+            frame: MappedFrame::Size4KiB(_),
+            flags,
+            ..
+        } => flags.intersects(PageTableFlags::WRITABLE | COW),
+        _ => false,
+    };
+    if !writable {
+        return PageFaultOutcome::Fatal {
+            reason: FaultReason::ProtectionViolation,
+        };
+    }
+
+    paging::unshare(mapper, Page::range(page, page + 1), alloc);
+
+    let (frame, flags) = match mapper.translate(addr) {
+        TranslateResult::Mapped {
+            frame: MappedFrame::Size4KiB(frame),
+            flags,
+            ..
+        } if flags.contains(COW) => (frame, (flags - COW) | PageTableFlags::WRITABLE),
+        // only the tables were shared, the page was writable all along
+        _ => return PageFaultOutcome::CopyOnWrite,
+    };
+
+    trace!("Copy on write {:#x} from {:?}", page.start_address(), frame);
+
+    if !alloc.is_shared(frame) {
+        return match unsafe { mapper.update_flags(page, flags) } {
+            Ok(flush) => {
+                flush.flush();
+                PageFaultOutcome::CopyOnWrite
+            }
+            Err(_) => PageFaultOutcome::Fatal {
+                reason: FaultReason::MapFailed,
+            },
+        };
+    }
+
+    let copy = match alloc.allocate_frame() {
+        Some(copy) => copy,
+        None => {
+            error!("Copy on write failed: out of frames");
+            return PageFaultOutcome::Fatal {
+                reason: FaultReason::OutOfMemory,
+            };
+        }
+    };
+
+    unsafe {
+        bulk::copy(
+            physical_to_virtual(copy.start_address().as_u64()) as *mut u8,
+            physical_to_virtual(frame.start_address().as_u64()) as *const u8,
+            PAGE_SIZE as usize,
+        );
+    }
+
+    let remapped = mapper
+        .unmap(page)
+        .map_err(|_| FaultReason::MapFailed)
+        .and_then(|(_, flush)| {
+            flush.ignore();
+            unsafe { mapper.map_to(page, copy, flags, alloc) }.map_err(FaultReason::from)
+        });
+
+    match remapped {
+        Ok(flush) => {
+            flush.flush();
+            // drop the reference to the shared frame
+            unsafe { alloc.deallocate_frame(frame) };
+            PageFaultOutcome::CopyOnWrite
+        }
+        Err(reason) => {
+            error!("Copy on write failed: {:?}", reason);
+            unsafe { alloc.deallocate_frame(copy) };
+            PageFaultOutcome::Fatal { reason }
+        }
+    }
+}
+
 impl core::fmt::Debug for ProcessVm {
     fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         let (size, unit) = humanized_size(self.memory_usage());
@@ -184,6 +609,7 @@ impl core::fmt::Debug for ProcessVm {
         f.debug_struct("ProcessVm")
             .field("stack", &self.stack)
             .field("heap", &self.heap)
+            .field("mmap", &self.mmap)
             .field("memory_usage", &format!("{} {}", size, unit))
             .field("page_table", &self.page_table)
             .finish()
@@ -195,5 +621,8 @@ impl Drop for ProcessVm {
         if let Err(err) = self.clean_up() {
             error!("Failed to clean up process memory: {:?}", err);
         }
+
+        // the last mapping of a shared memory segment may have gone with it
+        super::shm::collect();
     }
 }
\ No newline at end of file
@@ -1,13 +1,25 @@
-use core::ptr::copy_nonoverlapping;
+use alloc::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::mem::size_of;
+use core::ptr::NonNull;
+
+use syscall_def::IoVec;
 
 use x86_64::{
-    structures::paging::{mapper::{MapToError, UnmapError}, page::*, Page},
+    structures::paging::{
+        mapper::{MapToError, UnmapError},
+        page::*,
+        Mapper, OffsetPageTable, Page,
+    },
     VirtAddr,
 };
 
-use crate::proc::{processor, KERNEL_PID};
+use crate::memory::{bulk, guard, physical_to_virtual, PAGE_SIZE};
+use crate::proc::{paging, processor, AppArgs, KERNEL_PID};
+use crate::utils::sysctl::Tunable;
 
-use super::{FrameAllocatorRef, MapperRef};
+use super::{FaultReason, FrameAllocatorRef, MapperRef, PageFaultOutcome};
 
 // 0xffff_ff00_0000_0000 is the kernel's address space
 pub const STACK_MAX: u64 = 0x4000_0000_0000;
@@ -46,6 +58,18 @@ const KSTACK_INIT_PAGE: Page<Size4KiB> = Page::containing_address(VirtAddr::new(
 const KSTACK_INIT_TOP_PAGE: Page<Size4KiB> =
     Page::containing_address(VirtAddr::new(KSTACK_INIT_TOP));
 
+// kernel stack used by a process inside syscalls
+pub const SYSCALL_STACK_SIZE: usize = 0x4000;
+
+/// Most pages a stack may grow to, the rest of its region is left unmapped
+/// as a guard, and a fault in it kills the process
+pub static STACK_LIMIT: Tunable = Tunable::new("mem.stack_pages", 2048);
+
+/// Pages a stack may grow to, keeping at least a guard page in its region
+fn stack_limit() -> u64 {
+    (STACK_LIMIT.get() as u64).clamp(STACK_DEF_PAGE, STACK_MAX_PAGES - 1)
+}
+
 pub struct Stack {
     range: PageRange<Size4KiB>,
     usage: u64,
@@ -73,40 +97,53 @@ impl Stack {
         }
     }
 
-    pub fn init(&mut self, mapper: MapperRef, alloc: FrameAllocatorRef) {
+    /// Map the initial stack, with at least `pages` pages
+    pub fn init(&mut self, pages: u64, mapper: MapperRef, alloc: FrameAllocatorRef) {
         debug_assert!(self.usage == 0, "Stack is not empty.");
 
-        self.range = elf::map_pages(STACK_INIT_BOT, STACK_DEF_PAGE, mapper, alloc, true).unwrap();
-        self.usage = STACK_DEF_PAGE;
-    }
+        let pages = pages.clamp(STACK_DEF_PAGE, STACK_MAX_PAGES - 1);
+        let bot = STACK_MAX - pages * crate::memory::PAGE_SIZE;
 
-    pub fn stack_offset(&self, old_stack: &Stack) -> u64 {
-        let cur_stack_base = self.range.start.start_address().as_u64();
-        let old_stack_base = old_stack.range.start.start_address().as_u64();
-        let offset = cur_stack_base - old_stack_base;
-        debug_assert!(offset % STACK_MAX_SIZE != 0, "Invalid stack offset.");
-        offset
+        self.range = elf::map_pages(bot, pages, mapper, alloc, true).unwrap();
+        self.usage = pages;
     }
 
+    /// Grow the stack to `addr`, `None` if it is not on the stack
+    ///
+    /// fatal if `addr` is past the limit of the stack, in its guard.
     pub fn handle_page_fault(
         &mut self,
         addr: VirtAddr,
         mapper: MapperRef,
         alloc: FrameAllocatorRef,
-    ) -> bool {
+    ) -> Option<PageFaultOutcome> {
         if !self.is_on_stack(addr) {
-            return false;
+            return None;
+        }
+
+        let limit = stack_limit().max(self.usage);
+        let top = self.range.end.start_address().as_u64();
+        if addr.as_u64() < top.saturating_sub(limit * PAGE_SIZE) {
+            warn!(
+                "Stack overflow at {:#x}: past the limit of {} pages, see `mem.stack_pages`",
+                addr, limit
+            );
+            return Some(PageFaultOutcome::Fatal {
+                reason: FaultReason::StackOverflow {
+                    limit: limit * PAGE_SIZE,
+                },
+            });
         }
 
         if let Err(m) = self.grow_stack(addr, mapper, alloc) {
             error!("Grow stack failed: {:?}", m);
-            return false;
+            return Some(PageFaultOutcome::Fatal { reason: m.into() });
         }
 
-        true
+        Some(PageFaultOutcome::StackGrow)
     }
 
-    fn is_on_stack(&self, addr: VirtAddr) -> bool {
+    pub(super) fn is_on_stack(&self, addr: VirtAddr) -> bool {
         let addr = addr.as_u64();
         let cur_stack_bot = self.range.start.start_address().as_u64();
         trace!("Current stack bot: {:#x}", cur_stack_bot);
@@ -138,6 +175,7 @@ impl Stack {
             info!("Page fault on kernel at {:#x}", addr);
         }
 
+        paging::unshare(mapper, Page::range(new_start_page, self.range.start), alloc);
         elf::map_pages(
             new_start_page.start_address().as_u64(),
             page_count,
@@ -152,51 +190,16 @@ impl Stack {
         Ok(())
     }
 
-    pub fn fork(
-        &self,
-        mapper: MapperRef,
-        alloc: FrameAllocatorRef,
-        stack_offset_count: u64,
-    ) -> Self {
-        // FIXME: alloc & map new stack for child (see instructions)
-        // 这里的offset是child个数 即多少个max_stack
-        let mut new_stack_top = self.range.start.start_address().as_u64() - stack_offset_count * STACK_MAX_SIZE;
-        while elf::map_pages(new_stack_top, self.usage, mapper, alloc, true).is_err() {
-            trace!("Failed to map new stack on {:#x}, retrying...", new_stack_top);
-            new_stack_top -= STACK_MAX_SIZE;
-        }
-
-        // FIXME: copy the *entire stack* from parent to child
-        self.clone_range(
-            self.range.start.start_address().as_u64(),
-            new_stack_top,
-            self.usage,
-        );
-
-        let start = Page::containing_address(VirtAddr::new(new_stack_top));
-        // FIXME: return the new stack
+    /// The stack of a forked child, at the same address
+    ///
+    /// the pages are shared copy-on-write by the forked page table.
+    pub fn fork(&self) -> Self {
         Self {
-            range: Page::range(start, start + self.usage),
-            usage: self.usage
+            range: self.range,
+            usage: self.usage,
         }
     }
 
-    /// Clone a range of memory
-    ///
-    /// - `src_addr`: the address of the source memory
-    /// - `dest_addr`: the address of the target memory
-    /// - `size`: the count of pages to be cloned
-    fn clone_range(&self, cur_addr: u64, dest_addr: u64, size: u64) {
-        trace!("Clone range: {:#x} -> {:#x}", cur_addr, dest_addr);
-        unsafe {
-            copy_nonoverlapping::<u64>(
-                cur_addr as *mut u64,
-                dest_addr as *mut u64,
-                (size * Size4KiB::SIZE / 8) as usize,
-            );
-        }
-    }
-    
     pub fn memory_usage(&self) -> u64 {
         self.usage * crate::memory::PAGE_SIZE
     }
@@ -209,7 +212,7 @@ impl Stack {
         dealloc: FrameAllocatorRef,
     ) -> Result<(), UnmapError> {
         if self.usage == 0 {
-            warn!("Stack is empty, no need to clean up.");
+            debug!("Stack is empty, no need to clean up.");
             return Ok(());
         }
 
@@ -217,6 +220,7 @@ impl Stack {
 
         let start = self.range.start.start_address().as_u64();
 
+        paging::unshare(mapper, self.range, dealloc);
         elf::unmap_pages(start, self.usage, mapper, dealloc, true)?;
 
         self.usage = 0;
@@ -224,6 +228,134 @@ impl Stack {
         Ok(())
     }
 
+    /// Leave the stack empty and return the pages it had, for the last
+    /// user of the page table to free, see `ProcessVm::clean_up`
+    pub fn take_pages(&mut self) -> PageRange {
+        let pages = Page::range(self.range.start, self.range.start + self.usage);
+        self.usage = 0;
+        pages
+    }
+
+}
+
+/// Where `push_args` left the arguments on a new stack
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StackArgs {
+    pub argc: usize,
+    pub argv: u64,
+    pub envc: usize,
+    pub envp: u64,
+    /// the stack pointer to start with
+    pub rsp: u64,
+}
+
+/// Copy `args` to the top page of the stack mapped by `mapper`, for a new program
+///
+/// the strings go first, then the `IoVec` arrays describing the arguments
+/// and the environment. The stack pointer is aligned as if the entry point
+/// was called. `None` if the top page is not mapped or too small for them.
+pub fn push_args(args: &AppArgs, mapper: &OffsetPageTable<'static>) -> Option<StackArgs> {
+    let page = Page::<Size4KiB>::containing_address(VirtAddr::new(STACK_MAX - 1));
+    let frame = mapper.translate_page(page).ok()?;
+    let base = page.start_address().as_u64();
+
+    let mut block = vec![0u8; PAGE_SIZE as usize];
+    let mut top = block.len();
+
+    let strings = args.args.iter().chain(args.env.iter());
+    let mut vecs = Vec::with_capacity(args.args.len() + args.env.len());
+    for string in strings {
+        top = top.checked_sub(string.len())?;
+        block[top..top + string.len()].copy_from_slice(string.as_bytes());
+        vecs.push((base + top as u64, string.len() as u64));
+    }
+
+    // `IoVec` is `repr(C)`, the address then the length
+    let array = top.checked_sub(vecs.len() * size_of::<IoVec>())? & !0xf;
+    for (i, (addr, len)) in vecs.iter().enumerate() {
+        let at = array + i * size_of::<IoVec>();
+        block[at..at + 8].copy_from_slice(&addr.to_ne_bytes());
+        block[at + 8..at + 16].copy_from_slice(&len.to_ne_bytes());
+    }
+
+    // room for the return address of the entry point
+    let rsp = array.checked_sub(8)?;
+
+    unsafe {
+        bulk::copy(
+            physical_to_virtual(frame.start_address().as_u64()) as *mut u8,
+            block.as_ptr(),
+            block.len(),
+        );
+    }
+
+    let argv = base + array as u64;
+    Some(StackArgs {
+        argc: args.args.len(),
+        argv,
+        envc: args.env.len(),
+        envp: argv + (args.args.len() * size_of::<IoVec>()) as u64,
+        rsp: base + rsp as u64,
+    })
+}
+
+/// Kernel stack of a user process
+///
+/// loaded into the TSS when the process is scheduled,
+/// so every process enters `int 0x80` on its own stack.
+/// A kernel stack on the kernel heap, with a guard page below it
+pub struct SyscallStack(NonNull<u8>);
+
+// the stack is only used by its process, and never moves
+unsafe impl Send for SyscallStack {}
+unsafe impl Sync for SyscallStack {}
+
+impl Default for SyscallStack {
+    fn default() -> Self {
+        let layout = Self::layout();
+        let base = unsafe { alloc_zeroed(layout) };
+        let base = NonNull::new(base).unwrap_or_else(|| handle_alloc_error(layout));
+
+        let guard_page = Page::containing_address(VirtAddr::from_ptr(base.as_ptr()));
+        if !guard::protect(guard_page, "syscall stack") {
+            warn!("Syscall stack guard at {:#x} not set.", base.as_ptr() as u64);
+        }
+
+        Self(base)
+    }
+}
+
+impl SyscallStack {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The guard page and the stack above it
+    fn layout() -> Layout {
+        Layout::from_size_align(SYSCALL_STACK_SIZE + PAGE_SIZE as usize, PAGE_SIZE as usize)
+            .unwrap()
+    }
+
+    pub fn top(&self) -> VirtAddr {
+        (VirtAddr::from_ptr(self.0.as_ptr()) + PAGE_SIZE + SYSCALL_STACK_SIZE as u64)
+            .align_down(16u64)
+    }
+}
+
+impl Drop for SyscallStack {
+    fn drop(&mut self) {
+        // the allocator writes into freed memory, open the guard first
+        guard::unprotect(Page::containing_address(VirtAddr::from_ptr(self.0.as_ptr())));
+        unsafe { dealloc(self.0.as_ptr(), Self::layout()) }
+    }
+}
+
+impl core::fmt::Debug for SyscallStack {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.debug_tuple("SyscallStack")
+            .field(&format_args!("{:#x}", self.top().as_u64()))
+            .finish()
+    }
 }
 
 impl core::fmt::Debug for Stack {
@@ -0,0 +1,156 @@
+//! A minimal shell built into the kernel
+//!
+//! started instead of init when `rescue` is on the kernel command line,
+//! or when init cannot be spawned. It needs no user space to work.
+
+use alloc::vec::Vec;
+use xmas_elf::program::Type;
+
+use crate::drivers::input;
+use crate::memory::{allocator, get_frame_alloc_for_sure, user, PAGE_SIZE};
+use crate::proc;
+use crate::utils::{cmdline, humanized_size, sysctl};
+
+const HELP: &str = "\
+Rescue shell commands:
+    help                  | show this help
+    ps                    | show process list
+    mem                   | show memory usage
+    apps                  | show loaded apps
+    app <name>            | show the manifest and ELF of an app
+    run <name>            | run an app and wait for it
+    sysctl [name [value]] | show or set kernel tunables
+    cmdline               | show the kernel command line
+    exit                  | leave the shell and shut down
+";
+
+/// Run the rescue shell until `exit`
+pub fn run() {
+    warn!("Entering rescue shell, type `help` for help.");
+
+    loop {
+        print!("rescue# ");
+        let line = input::get_line();
+        let args: Vec<&str> = line.split_whitespace().collect();
+
+        match args.as_slice() {
+            [] => continue,
+            ["help"] => print!("{}", HELP),
+            ["ps"] => proc::print_process_list(),
+            ["mem"] => print_memory(),
+            ["apps"] => proc::list_app(),
+            ["app", name] => print_app(name),
+            ["run", name] => run_app(name),
+            ["sysctl", rest @ ..] => sysctl_command(rest),
+            ["cmdline"] => println!("{}", cmdline::get_all()),
+            ["exit"] => break,
+            [cmd, ..] => println!("unknown command: {}, type `help` for help", cmd),
+        }
+    }
+}
+
+fn print_usage(name: &str, used: usize, total: usize) {
+    let (used, used_unit) = humanized_size(used as u64);
+    let (total, total_unit) = humanized_size(total as u64);
+    println!(
+        "{:<12}: {:>8.3} {:<3} / {:>8.3} {}",
+        name, used, used_unit, total, total_unit
+    );
+}
+
+fn print_memory() {
+    let (frames_used, frames_total, recycled, zeroed) =
+        x86_64::instructions::interrupts::without_interrupts(|| {
+            let alloc = get_frame_alloc_for_sure();
+            (
+                alloc.frames_used(),
+                alloc.frames_total(),
+                alloc.frames_recycled(),
+                alloc.zeroed_stats().0,
+            )
+        });
+
+    print_usage(
+        "Kernel heap",
+        allocator::ALLOCATOR.used(),
+        allocator::HEAP_SIZE,
+    );
+    print_usage(
+        "User heap",
+        user::USER_ALLOCATOR.lock().used(),
+        user::USER_HEAP_SIZE,
+    );
+    print_usage(
+        "Frames",
+        frames_used * PAGE_SIZE as usize,
+        frames_total * PAGE_SIZE as usize,
+    );
+    println!(
+        "{:<12}: {} recycled, {} zeroed in pool",
+        "", recycled, zeroed
+    );
+}
+
+fn print_app(name: &str) {
+    let app = match proc::find_app(name) {
+        Ok(app) => app,
+        Err(err) => {
+            println!("{}: {}", name, err);
+            return;
+        }
+    };
+
+    let info = &app.info;
+    println!("name        : {}", info.name);
+    println!("version     : {}", info.version);
+    println!("description : {}", info.description);
+    println!("usage       : {}", info.usage);
+    println!("stack pages : {}", info.stack_pages);
+    let verified = match info.hash.is_empty() {
+        true => "not in manifest",
+        false if hash::matches_hex(&hash::sha256(app.elf.input), &info.hash) => "verified",
+        false => "MISMATCH",
+    };
+    println!("sha256      : {} ({})", info.hash, verified);
+    println!("elf size    : {} bytes", app.elf.input.len());
+    println!("entry       : {:#x}", app.elf.header.pt2.entry_point());
+
+    for ph in app.elf.program_iter() {
+        if ph.get_type() == Ok(Type::Load) {
+            println!(
+                "  load {:#012x} {:>8} bytes, {:?}",
+                ph.virtual_addr(),
+                ph.mem_size(),
+                ph.flags()
+            );
+        }
+    }
+}
+
+fn run_app(name: &str) {
+    match proc::spawn(name) {
+        Ok(pid) => {
+            let ret = crate::wait(pid);
+            println!("{}#{} exited with code {}", name, pid, ret);
+        }
+        Err(err) => println!("failed to spawn {}: {}", name, err),
+    }
+}
+
+fn sysctl_command(args: &[&str]) {
+    match args {
+        [] => sysctl::list().for_each(|t| println!("{} = {}", t.name(), t.get())),
+        [name] => match sysctl::find(name) {
+            Some(tunable) => println!("{} = {}", name, tunable.get()),
+            None => println!("unknown tunable: {}", name),
+        },
+        [name, value, ..] => match (sysctl::find(name), value.parse::<usize>()) {
+            (Some(tunable), Ok(value)) => {
+                let old = tunable.set(value);
+                println!("{} = {} (was {})", name, value, old);
+            }
+            (None, _) => println!("unknown tunable: {}", name),
+            (_, Err(_)) => println!("cannot parse value: {}", value),
+        },
+    }
+}
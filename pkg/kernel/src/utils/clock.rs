@@ -1,15 +1,31 @@
 use super::uefi;
 use boot::BootInfo;
 use chrono::naive::*;
+use chrono::DateTime;
+use core::sync::atomic::{AtomicI32, AtomicI64, Ordering};
+use spin::Once;
+
+use crate::drivers::rtc;
+
+/// Seconds local time is ahead of UTC, `timezone` of `boot.conf`
+static UTC_OFFSET: AtomicI32 = AtomicI32::new(0);
+/// Nanoseconds the time of day is ahead of the RTC, what it
+/// cannot hold of the time last set
+static RTC_DELTA: AtomicI64 = AtomicI64::new(0);
 
 pub fn init(boot_info: &'static BootInfo) {
     if uefi::get_uefi_runtime().is_none() {
         uefi::init(boot_info);
     }
+    UTC_OFFSET.store(boot_info.utc_offset, Ordering::Relaxed);
 }
 
-pub fn now() -> NaiveDateTime {
-    let time = uefi::get_uefi_runtime_for_sure().get_time();
+/// The time of the RTC, to the nanosecond if UEFI gives them
+fn rtc_now() -> NaiveDateTime {
+    // the runtime is locked with `try_lock`, a preempted holder would make it panic
+    let time = x86_64::instructions::interrupts::without_interrupts(|| {
+        uefi::get_uefi_runtime_for_sure().get_time()
+    });
     NaiveDate::from_ymd_opt(time.year() as i32, time.month() as u32, time.day() as u32)
         .unwrap_or_default()
         .and_hms_nano_opt(
@@ -20,3 +36,68 @@ pub fn now() -> NaiveDateTime {
         )
         .unwrap_or_default()
 }
+
+fn rtc_nanos() -> i64 {
+    rtc_now().and_utc().timestamp_nanos_opt().unwrap_or_default()
+}
+
+/// The time of day, UTC
+pub fn now() -> NaiveDateTime {
+    DateTime::from_timestamp_nanos(now_nanos()).naive_utc()
+}
+
+/// Nanoseconds since the epoch, virtual in deterministic mode
+pub fn now_nanos() -> i64 {
+    if crate::proc::deterministic::enabled() {
+        return crate::proc::deterministic::now_nanos();
+    }
+
+    rtc_nanos().saturating_add(RTC_DELTA.load(Ordering::Relaxed))
+}
+
+/// Set the time of day to `nanos` since the epoch, and the RTC with it
+///
+/// the monotonic clock does not move. Fails in deterministic mode,
+/// where the time is virtual.
+pub fn set_nanos(nanos: i64) -> bool {
+    if crate::proc::deterministic::enabled() {
+        return false;
+    }
+
+    x86_64::instructions::interrupts::without_interrupts(|| {
+        let before = now_nanos();
+        // the RTC keeps whole seconds, the delta the rest, or all if it cannot be set
+        rtc::set(DateTime::from_timestamp_nanos(nanos).naive_utc());
+        RTC_DELTA.store(nanos.saturating_sub(rtc_nanos()), Ordering::Relaxed);
+        MONOTONIC_SHIFT.fetch_add(nanos.saturating_sub(before), Ordering::Relaxed);
+    });
+    true
+}
+
+/// Seconds local time is ahead of UTC
+pub fn utc_offset() -> i32 {
+    UTC_OFFSET.load(Ordering::Relaxed)
+}
+
+/// Set the seconds local time is ahead of UTC, return the old offset
+pub fn set_utc_offset(offset: i32) -> i32 {
+    UTC_OFFSET.swap(offset, Ordering::Relaxed)
+}
+
+/// The unshifted time of day on the first read of the monotonic clock
+static MONOTONIC_START: Once<i64> = Once::new();
+/// The latest time the monotonic clock gave
+static MONOTONIC_LAST: AtomicI64 = AtomicI64::new(0);
+/// How far the time of day was moved by setting it
+static MONOTONIC_SHIFT: AtomicI64 = AtomicI64::new(0);
+
+/// Nanoseconds since the monotonic clock was first read
+///
+/// it follows `now_nanos`, but does not move when the time is set,
+/// and never goes backwards when the RTC does.
+pub fn monotonic_nanos() -> i64 {
+    let unshifted = || now_nanos().saturating_sub(MONOTONIC_SHIFT.load(Ordering::Relaxed));
+    let start = *MONOTONIC_START.call_once(unshifted);
+    let now = unshifted().saturating_sub(start);
+    MONOTONIC_LAST.fetch_max(now, Ordering::Relaxed).max(now)
+}
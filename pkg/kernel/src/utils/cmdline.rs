@@ -0,0 +1,35 @@
+/// Kernel command line, from `cmdline` in boot.conf
+///
+/// options are split by spaces, each is `key=value` or a bare `key`,
+/// e.g. `cmdline=deterministic=on`
+static CMDLINE: spin::Once<&'static str> = spin::Once::new();
+
+pub fn init(boot_info: &'static boot::BootInfo) {
+    CMDLINE.call_once(|| boot_info.cmdline);
+
+    if !boot_info.cmdline.is_empty() {
+        info!("Kernel command line: {}", boot_info.cmdline);
+    }
+}
+
+/// The whole command line
+pub fn get_all() -> &'static str {
+    CMDLINE.get().copied().unwrap_or_default()
+}
+
+/// The value of option `key`, empty for a bare key
+pub fn get(key: &str) -> Option<&'static str> {
+    CMDLINE
+        .get()?
+        .split_whitespace()
+        .map(|opt| opt.split_once('=').unwrap_or((opt, "")))
+        .find(|(k, _)| *k == key)
+        .map(|(_, v)| v)
+}
+
+/// Check if option `key` is switched on
+///
+/// a bare key, `on`, `yes`, `true` and `1` all count as on
+pub fn enabled(key: &str) -> bool {
+    matches!(get(key), Some("" | "on" | "yes" | "true" | "1"))
+}
@@ -0,0 +1,80 @@
+use core::fmt;
+
+/// Formats into a fixed buffer on the stack
+///
+/// used where the kernel allocator must not be touched,
+/// output that does not fit is cut off and marked as truncated.
+pub struct StackWriter<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> StackWriter<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only whole chars are ever copied in
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl<const N: usize> Default for StackWriter<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> fmt::Write for StackWriter<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let space = N - self.len;
+        let mut count = s.len().min(space);
+        while !s.is_char_boundary(count) {
+            count -= 1;
+        }
+
+        self.buf[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+
+        if count < s.len() {
+            self.truncated = true;
+        }
+
+        // never fail, so formatting goes on and the prefix is kept
+        Ok(())
+    }
+}
+
+/// Format `args` into a [`StackWriter`] of `N` bytes
+pub fn format_stack<const N: usize>(args: fmt::Arguments) -> StackWriter<N> {
+    let mut writer = StackWriter::new();
+    let _ = fmt::write(&mut writer, args);
+    writer
+}
+
+/// Displays bytes as UTF-8, replacing invalid sequences with U+FFFD
+///
+/// like `String::from_utf8_lossy`, without allocating.
+pub struct Lossy<'a>(pub &'a [u8]);
+
+impl fmt::Display for Lossy<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for chunk in self.0.utf8_chunks() {
+            f.write_str(chunk.valid())?;
+            if !chunk.invalid().is_empty() {
+                f.write_str("\u{FFFD}")?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,5 +1,10 @@
 use log::{LevelFilter, Metadata, Record};
 
+use super::fmt::format_stack;
+
+/// Longest log line, longer ones are cut off
+const LOG_LINE_MAX: usize = 256;
+
 pub fn init(boot_info: &'static boot::BootInfo) {
     static LOGGER: Logger = Logger;
     log::set_logger(&LOGGER).unwrap();
@@ -25,17 +30,24 @@ impl log::Log for Logger {
     }
 
     fn log(&self, record: &Record) {
+        // format on the stack, so logging never calls into the allocator,
+        // even from interrupt context or while the heap is locked
+        let line = format_stack::<LOG_LINE_MAX>(*record.args());
+        let more = if line.is_truncated() { "..." } else { "" };
+        let msg = line.as_str();
+
         match record.level() {
             log::Level::Error => println_warn!(
-                "[E] {}@{}: {}",
+                "[E] {}@{}: {}{}",
                 record.file_static().unwrap_or(""),
                 record.line().unwrap_or(0),
-                record.args()
+                msg,
+                more
             ),
-            log::Level::Warn => println_warn!("[!] {}", record.args()),
-            log::Level::Info => println!("[+] {}", record.args()),
-            log::Level::Debug => println_serial!("[D] {}", record.args()),
-            log::Level::Trace => println_serial!("[T] {}", record.args()),
+            log::Level::Warn => println_warn!("[!] {}{}", msg, more),
+            log::Level::Info => println!("[+] {}{}", msg, more),
+            log::Level::Debug => println_serial!("[D] {}{}", msg, more),
+            log::Level::Trace => println_serial!("[T] {}{}", msg, more),
         }
     }
 
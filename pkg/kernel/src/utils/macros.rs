@@ -1,5 +1,8 @@
-use crate::serial::{get_serial, SERIAL};
-use alloc::string::ToString;
+#[cfg(feature = "graphics")]
+use crate::display;
+use crate::early::EarlyWriter;
+use crate::serial::{flush_staging, get_serial, StagingWriter, SERIAL};
+use crate::utils::fmt::{format_stack, Lossy};
 use core::fmt::*;
 use x86_64::instructions::interrupts;
 
@@ -71,6 +74,14 @@ macro_rules! print_serial {
     ($($arg:tt)*) => ($crate::utils::print_serial_internal(format_args!($($arg)*)));
 }
 
+/// Print without locks or allocation, from anywhere and at any time
+///
+/// for bring-up and fatal paths, see `drivers::early`.
+#[macro_export]
+macro_rules! early_print {
+    ($($arg:tt)*) => ($crate::drivers::early::print(format_args!($($arg)*)));
+}
+
 #[macro_export]
 macro_rules! println {
     () => ($crate::print!("\n\r"));
@@ -89,52 +100,100 @@ macro_rules! println_serial {
     ($($arg:tt)*) => ($crate::print_serial!("{}\n\r", format_args!($($arg)*)));
 }
 
-#[doc(hidden)]
-pub fn print_internal(args: Arguments) {
+#[macro_export]
+macro_rules! early_println {
+    () => ($crate::early_print!("\n\r"));
+    ($($arg:tt)*) => ($crate::early_print!("{}\n\r", format_args!($($arg)*)));
+}
+
+/// Write to serial, or stage the output if the port is busy
+///
+/// never spins on the serial lock, so it is safe in interrupt context.
+/// Before the serial port is initialized, the output goes to the early console.
+fn write_serial(args: Arguments) {
     interrupts::without_interrupts(|| {
-        if let Some(mut serial) = get_serial() {
+        if SERIAL.get().is_none() {
+            EarlyWriter.write_fmt(args).unwrap();
+        } else if let Some(mut serial) = get_serial() {
+            flush_staging(&mut serial);
             serial.write_fmt(args).unwrap();
+        } else {
+            StagingWriter.write_fmt(args).unwrap();
         }
     });
 }
 
+/// Mirror to the framebuffer console, when it is built in
+#[cfg(feature = "graphics")]
+fn write_display(args: Arguments) {
+    display::print(args);
+}
+
+#[cfg(not(feature = "graphics"))]
+fn write_display(_args: Arguments) {}
+
+#[doc(hidden)]
+pub fn print_internal(args: Arguments) {
+    write_serial(args);
+    interrupts::without_interrupts(|| write_display(args));
+}
+
 #[doc(hidden)]
 pub fn print_warn_internal(args: Arguments) {
+    write_serial(args);
+    interrupts::without_interrupts(|| write_display(args));
+}
+
+/// Write the bytes of a `write` to the console
+///
+/// serial and the display are each locked once for the whole buffer,
+/// and invalid UTF-8 is replaced without allocating a copy.
+pub fn print_bytes(bytes: &[u8]) {
     interrupts::without_interrupts(|| {
-        if let Some(mut serial) = get_serial() {
-            serial.write_fmt(args).unwrap();
-        }
+        write_serial(format_args!("{}", Lossy(bytes)));
+        write_display(format_args!("{}", Lossy(bytes)));
     });
 }
 
 #[doc(hidden)]
 pub fn print_serial_internal(args: Arguments) {
-    interrupts::without_interrupts(|| {
-        if let Some(mut serial) = get_serial() {
-            serial.write_fmt(args).unwrap();
-        }
-    });
+    write_serial(args);
 }
 
 #[cfg_attr(not(test), panic_handler)]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    unsafe { SERIAL.get().unwrap().force_unlock() };
+    // the panic may come from the serial driver itself
+    if let Some(serial) = SERIAL.get() {
+        unsafe { serial.force_unlock() };
+    }
 
+    // the panic may come from the allocator, so nothing here allocates
     let location = if let Some(location) = info.location() {
-        alloc::format!(
+        format_stack::<128>(format_args!(
             "{} @ {}:{}",
             location.file(),
             location.line(),
             location.column()
-        )
+        ))
     } else {
-        "Unknown location".to_string()
+        format_stack(format_args!("Unknown location"))
     };
-    let msg = if let Some(msg) = info.message() {
-        alloc::format!("{}", msg)
+    // before the logger is up, `error!` goes nowhere
+    if log::max_level() == log::LevelFilter::Off {
+        early_println!("\n\rERROR: panicked at {}", location.as_str());
+        if let Some(msg) = info.message() {
+            early_println!("{}", msg);
+        }
+        loop {}
+    }
+
+    if let Some(msg) = info.message() {
+        error!("\n\n\rERROR: panicked at {}\n\n\r{}", location.as_str(), msg);
     } else {
-        "No more message...".to_string()
-    };
-    error!("\n\n\rERROR: panicked at {}\n\n\r{}", location, msg);
+        error!(
+            "\n\n\rERROR: panicked at {}\n\n\rNo more message...",
+            location.as_str()
+        );
+    }
     loop {}
 }
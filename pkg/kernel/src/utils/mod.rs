@@ -1,14 +1,18 @@
-mod uefi;
-
 #[macro_use]
 mod macros;
 #[macro_use]
 mod regs;
 
 pub mod clock;
+pub mod cmdline;
+pub mod fmt;
 pub mod func;
 pub mod logger;
+pub mod pty;
+pub mod random;
 pub mod resource;
+pub mod sysctl;
+pub mod uefi;
 
 pub use macros::*;
 pub use regs::*;
@@ -0,0 +1,333 @@
+//! Pseudo-terminals, a pair of fds acting as a terminal and its screen
+//!
+//! what the master writes is typed on the terminal, and what programs on
+//! the slave write is shown on it, read from the master. Between them is
+//! the line discipline: unless the slave is in raw mode, input is edited
+//! a line at a time, backspace and Ctrl+U erase, Ctrl+D ends the input,
+//! and every key is echoed back. Output gets `\r` before each `\n`.
+//! Ctrl+C and Ctrl+Z are passed on as bytes, no signal is sent.
+
+use alloc::collections::VecDeque;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use spin::Mutex;
+use syscall_def::errno::EINVAL;
+use syscall_def::{FdStat, FD_KIND_PTY, F_GETRAW, F_GETWINSZ, F_SETRAW, F_SETWINSZ};
+
+use crate::proc::ProcessId;
+use crate::resource::Resource;
+
+/// Bytes each way a pty holds before writes to it come up short
+const PTY_SIZE: usize = 4096;
+/// Bytes of a line being edited, the keys after are dropped
+const LINE_MAX: usize = 1024;
+
+const ERASE: u8 = 0x7f;
+const BACKSPACE: u8 = 0x08;
+/// Ctrl+U
+const KILL_LINE: u8 = 0x15;
+/// Ctrl+D
+const END_OF_INPUT: u8 = 0x04;
+
+/// The size of a new pty, as the kernel console
+const DEFAULT_COLS: u16 = 80;
+const DEFAULT_ROWS: u16 = 25;
+
+#[derive(Debug)]
+struct Pty {
+    /// what the slave reads, whole lines unless raw
+    input: VecDeque<u8>,
+    /// the line being edited
+    line: Vec<u8>,
+    /// Ctrl+D on an empty line, the next read of the slave returns 0
+    end_of_input: bool,
+    /// what the master reads, written by the slave and echoed
+    output: VecDeque<u8>,
+    raw: bool,
+    cols: u16,
+    rows: u16,
+    masters: usize,
+    slaves: usize,
+    /// processes blocked on either end, woken by any change
+    waiting: Vec<ProcessId>,
+}
+
+impl Default for Pty {
+    fn default() -> Self {
+        Self {
+            input: VecDeque::new(),
+            line: Vec::new(),
+            end_of_input: false,
+            output: VecDeque::new(),
+            raw: false,
+            cols: DEFAULT_COLS,
+            rows: DEFAULT_ROWS,
+            masters: 0,
+            slaves: 0,
+            waiting: Vec::new(),
+        }
+    }
+}
+
+impl Pty {
+    fn would_block(&self, master: bool, write: bool) -> bool {
+        match (master, write) {
+            (true, false) => self.slaves > 0 && self.output.is_empty(),
+            (true, true) => self.slaves > 0 && self.input_full(),
+            (false, false) => self.masters > 0 && self.input.is_empty() && !self.end_of_input,
+            // a line ending is written as two bytes
+            (false, true) => self.masters > 0 && self.output.len() + 2 > PTY_SIZE,
+        }
+    }
+
+    fn input_full(&self) -> bool {
+        self.input.len() + self.line.len() >= PTY_SIZE
+    }
+
+    fn echo(&mut self, bytes: &[u8]) {
+        let room = PTY_SIZE - self.output.len();
+        self.output.extend(&bytes[..bytes.len().min(room)]);
+    }
+
+    /// Take a key typed on the master, return false if there is no room for it
+    fn type_key(&mut self, key: u8) -> bool {
+        if self.input_full() {
+            return false;
+        }
+
+        if self.raw {
+            self.input.push_back(key);
+            return true;
+        }
+
+        match key {
+            b'\r' | b'\n' => {
+                self.line.push(b'\n');
+                self.input.extend(self.line.drain(..));
+                self.echo(b"\r\n");
+            }
+            ERASE | BACKSPACE => {
+                if self.erase_char() {
+                    self.echo(b"\x08 \x08");
+                }
+            }
+            KILL_LINE => {
+                while self.erase_char() {
+                    self.echo(b"\x08 \x08");
+                }
+            }
+            END_OF_INPUT if self.line.is_empty() => self.end_of_input = true,
+            END_OF_INPUT => self.input.extend(self.line.drain(..)),
+            _ if self.line.len() < LINE_MAX => {
+                self.line.push(key);
+                self.echo(&[key]);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Erase the last character of the line, all the bytes of it in UTF-8
+    fn erase_char(&mut self) -> bool {
+        while let Some(byte) = self.line.pop() {
+            // continuation bytes are 0b10xxxxxx
+            if byte & 0xc0 != 0x80 {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Read what the slave has to, 0 takes the end of input
+    fn read_input(&mut self, buf: &mut [u8]) -> usize {
+        if self.input.is_empty() {
+            self.end_of_input = false;
+            return 0;
+        }
+
+        let count = buf.len().min(self.input.len());
+        for (slot, byte) in buf.iter_mut().zip(self.input.drain(..count)) {
+            *slot = byte;
+        }
+        count
+    }
+
+    /// Take what the slave writes, return the bytes of `buf` taken
+    fn write_output(&mut self, buf: &[u8]) -> usize {
+        for (count, &byte) in buf.iter().enumerate() {
+            let converted = byte == b'\n' && !self.raw;
+            if self.output.len() + 1 + converted as usize > PTY_SIZE {
+                return count;
+            }
+            if converted {
+                self.output.push_back(b'\r');
+            }
+            self.output.push_back(byte);
+        }
+        buf.len()
+    }
+
+    fn ioctl(&mut self, cmd: usize, arg: usize) -> Result<usize, usize> {
+        let size = self.cols as usize | (self.rows as usize) << 16;
+        match cmd {
+            F_GETRAW => Ok(self.raw as usize),
+            F_SETRAW => {
+                let old = core::mem::replace(&mut self.raw, arg != 0);
+                // what was edited so far is passed on as it is
+                if self.raw {
+                    self.input.extend(self.line.drain(..));
+                }
+                Ok(old as usize)
+            }
+            F_GETWINSZ => Ok(size),
+            F_SETWINSZ => {
+                let (cols, rows) = (arg as u16, (arg >> 16) as u16);
+                if cols == 0 || rows == 0 {
+                    return Err(EINVAL);
+                }
+                (self.cols, self.rows) = (cols, rows);
+                Ok(size)
+            }
+            _ => Err(EINVAL),
+        }
+    }
+}
+
+/// One end of a pty, counted by the pty while it is open
+pub struct PtyEnd {
+    pty: Arc<Mutex<Pty>>,
+    master: bool,
+}
+
+impl PtyEnd {
+    fn new(pty: Arc<Mutex<Pty>>, master: bool) -> Self {
+        {
+            let mut pty = pty.lock();
+            if master {
+                pty.masters += 1;
+            } else {
+                pty.slaves += 1;
+            }
+        }
+        Self { pty, master }
+    }
+
+    /// The master and the slave end of a new pty
+    pub fn pair() -> (Self, Self) {
+        let pty = Arc::new(Mutex::new(Pty::default()));
+        (Self::new(pty.clone(), true), Self::new(pty, false))
+    }
+
+    /// Run `f` on the pty, waking the processes blocked on it if it changed
+    fn with<R>(&self, f: impl FnOnce(&mut Pty) -> (R, bool)) -> R {
+        let mut pty = self.pty.lock();
+        let (ret, changed) = f(&mut pty);
+        let waiting = match changed {
+            true => core::mem::take(&mut pty.waiting),
+            false => Vec::new(),
+        };
+        drop(pty);
+
+        crate::proc::wake_blocked(waiting);
+        ret
+    }
+}
+
+impl Resource for PtyEnd {
+    /// On the master, what was written to the slave, 0 once every slave
+    /// fd is closed. On the slave, the lines typed, 0 after Ctrl+D or
+    /// once every master fd is closed.
+    fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        if self.master {
+            return Some(self.with(|pty| {
+                let count = buf.len().min(pty.output.len());
+                for (slot, byte) in buf.iter_mut().zip(pty.output.drain(..count)) {
+                    *slot = byte;
+                }
+                (count, count > 0)
+            }));
+        }
+
+        Some(self.with(|pty| {
+            let count = pty.read_input(buf);
+            (count, count > 0)
+        }))
+    }
+
+    /// Type `buf` on the master, or show it from the slave,
+    /// `None` once every fd of the other end is closed
+    fn write(&self, buf: &[u8]) -> Option<usize> {
+        self.with(|pty| {
+            let count = if self.master {
+                if pty.slaves == 0 {
+                    return (None, false);
+                }
+                buf.iter().take_while(|&&key| pty.type_key(key)).count()
+            } else {
+                if pty.masters == 0 {
+                    return (None, false);
+                }
+                pty.write_output(buf)
+            };
+            (Some(count), count > 0)
+        })
+    }
+
+    fn poll(&self, pid: ProcessId, write: bool) -> bool {
+        let mut pty = self.pty.lock();
+        let block = pty.would_block(self.master, write);
+        if block {
+            pty.waiting.push(pid);
+        }
+        block
+    }
+
+    /// `F_GETRAW`, `F_SETRAW`, `F_GETWINSZ` and `F_SETWINSZ`, on either end
+    fn ioctl(&self, cmd: usize, arg: usize) -> Result<usize, usize> {
+        self.with(|pty| {
+            let ret = pty.ioctl(cmd, arg);
+            (ret, cmd == F_SETRAW)
+        })
+    }
+
+    fn stat(&self) -> FdStat {
+        FdStat {
+            kind: FD_KIND_PTY,
+            ..FdStat::default()
+        }
+    }
+
+    fn node(&self) -> usize {
+        Arc::as_ptr(&self.pty) as *const () as usize
+    }
+}
+
+impl Debug for PtyEnd {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let pty = self.pty.lock();
+        write!(
+            f,
+            "PtyEnd({}, {} bytes in, {} bytes out)",
+            if self.master { "master" } else { "slave" },
+            pty.input.len(),
+            pty.output.len()
+        )
+    }
+}
+
+impl Drop for PtyEnd {
+    fn drop(&mut self) {
+        let mut pty = self.pty.lock();
+        if self.master {
+            pty.masters -= 1;
+        } else {
+            pty.slaves -= 1;
+        }
+        // the last end of its kind going away ends their wait too
+        let waiting = core::mem::take(&mut pty.waiting);
+        drop(pty);
+
+        crate::proc::wake_blocked(waiting);
+    }
+}
@@ -0,0 +1,69 @@
+//! Random numbers for the kernel and `Syscall::GetRandom`
+//!
+//! `rdrand` is used when the cpu has it. Otherwise, and when it keeps
+//! failing, a xorshift generator seeded from the time stamp counter is,
+//! which is not fit for secrets.
+
+use core::arch::asm;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use crate::cpu::{self, Features};
+
+/// `rdrand` may fail when the hardware is drained, retry this many times
+const RDRAND_RETRIES: usize = 10;
+
+static STATE: AtomicU64 = AtomicU64::new(0);
+
+fn rdrand() -> Option<u64> {
+    for _ in 0..RDRAND_RETRIES {
+        let (value, ok): (u64, u8);
+        unsafe {
+            asm!(
+                "rdrand {0}",
+                "setc {1}",
+                out(reg) value,
+                out(reg_byte) ok,
+                options(nomem, nostack)
+            );
+        }
+        if ok != 0 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// xorshift64*, seeded on first use
+fn xorshift() -> u64 {
+    let mut next = 0;
+    STATE
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |state| {
+            let mut x = match state {
+                0 => (unsafe { core::arch::x86_64::_rdtsc() }) | 1,
+                state => state,
+            };
+            x ^= x >> 12;
+            x ^= x << 25;
+            x ^= x >> 27;
+            next = x.wrapping_mul(0x2545_f491_4f6c_dd1d);
+            Some(x)
+        })
+        .ok();
+    next
+}
+
+pub fn random_u64() -> u64 {
+    if cpu::has(Features::RDRAND) {
+        if let Some(value) = rdrand() {
+            return value;
+        }
+    }
+    xorshift()
+}
+
+pub fn fill(buf: &mut [u8]) {
+    for chunk in buf.chunks_mut(8) {
+        let bytes = random_u64().to_le_bytes();
+        chunk.copy_from_slice(&bytes[..chunk.len()]);
+    }
+}
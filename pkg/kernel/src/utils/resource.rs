@@ -1,42 +1,372 @@
+use crate::drivers::block::{self, BLOCK_SIZE, READ_AHEAD};
 use crate::drivers::input::*;
-use alloc::{collections::BTreeMap, string::String};
+use crate::fs::vfs::Inode;
+use crate::memory::bulk;
+use crate::proc::ProcessId;
+use crate::utils::fmt::Lossy;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::{sync::Arc, vec::Vec};
+use core::fmt::Debug;
+use core::sync::atomic::{AtomicU64, Ordering};
 use spin::Mutex;
+use syscall_def::errno::{EBADF, EBUSY, EINVAL, EIO, ENODEV, ENOTTY, ESPIPE};
+use syscall_def::{
+    FdStat, FD_KIND_CONSOLE, FD_KIND_FILE, FD_KIND_PIPE, F_GETRAW, F_SETRAW, O_ACCMODE, O_APPEND,
+    O_RDONLY, O_WRONLY, POLLIN, POLLNVAL, POLLOUT, SEEK_CUR, SEEK_END, SEEK_SET,
+};
 
-#[derive(Debug, Clone)]
+/// Size of the kernel bounce buffer used by `send_file`
+const SEND_FILE_CHUNK: usize = 4096;
+/// Bytes a capture buffer holds before writes to it come up short
+const BUFFER_MAX: usize = 64 * 1024;
+/// Bytes a pipe holds before its writers block
+const PIPE_SIZE: usize = 4096;
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+/// Lock nodes of the resources not backed by a buffer of their own
+const CONSOLE_NODE: usize = 1;
+const NULL_NODE: usize = 2;
+const KLOG_NODE: usize = 3;
+
+static NEXT_HANDLE: AtomicU64 = AtomicU64::new(1);
+
+/// Whether a read or write of a resource would block, for `Syscall::Poll`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Readiness {
+    Ready,
+    /// the process is queued to be woken by a change, see `Resource::poll`
+    Waiting,
+    /// nothing wakes the process, it has to look again after a while
+    Recheck,
+}
+
+/// Something a fd refers to
+///
+/// fds opened from one another, by fork, `dup2` or spawn, share it, so it
+/// is dropped with the last of them. There are no sockets yet.
+pub trait Resource: Send + Sync + Debug {
+    /// Read into `buf`, `None` if it cannot be read
+    fn read(&self, buf: &mut [u8]) -> Option<usize>;
+
+    /// Write `buf`, `None` if it cannot be written
+    fn write(&self, buf: &[u8]) -> Option<usize>;
+
+    /// Queue `pid` to be woken if reading or writing would block now
+    ///
+    /// only pipes block, other resources return what they have.
+    fn poll(&self, _pid: ProcessId, _write: bool) -> bool {
+        false
+    }
+
+    /// Whether reading or writing would block now, queueing `pid` to be
+    /// woken by a change if it would, as `poll` does
+    ///
+    /// resources that never block but may have nothing to read, like the
+    /// console, tell it apart here.
+    fn readiness(&self, pid: ProcessId, write: bool) -> Readiness {
+        match self.poll(pid, write) {
+            true => Readiness::Waiting,
+            false => Readiness::Ready,
+        }
+    }
+
+    /// Control the device behind the resource, with a `F_*` command
+    /// of `Syscall::Fcntl` not about the fd itself
+    fn ioctl(&self, _cmd: usize, _arg: usize) -> Result<usize, usize> {
+        Err(ENOTTY)
+    }
+
+    /// A fd of the resource is closed, which may not be the last one
+    fn close(&self) {}
+
+    /// Move the position of a file, see `SEEK_SET`
+    fn seek(&self, _offset: isize, _whence: usize) -> Result<usize, usize> {
+        Err(ESPIPE)
+    }
+
+    /// The kind, size and position for `FdStat`, the rest is the fd's
+    fn stat(&self) -> FdStat {
+        FdStat::default()
+    }
+
+    /// Identify the device or buffer behind the resource, for file locks
+    fn node(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+
+    /// The inode and access mode of an open file, for `mmap`
+    fn file(&self) -> Option<(Arc<dyn Inode>, usize)> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum StdIO {
     Stdin,
     Stdout,
     Stderr,
 }
 
+impl Resource for StdIO {
+    fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        if !matches!(self, StdIO::Stdin) {
+            return None;
+        }
+
+        // take what the kernel input buffer has
+        let mut count = 0;
+        while let Some(slot) = buf.get_mut(count) {
+            match try_pop_key() {
+                Some(ch) => *slot = ch,
+                None => break,
+            }
+            count += 1;
+        }
+        Some(count)
+    }
+
+    fn write(&self, buf: &[u8]) -> Option<usize> {
+        match self {
+            StdIO::Stdin => return None,
+            StdIO::Stdout if crate::drivers::serial::is_raw() => {
+                crate::drivers::serial::write_bytes(buf)
+            }
+            StdIO::Stdout => crate::print_bytes(buf),
+            StdIO::Stderr => warn!("{}", Lossy(buf)),
+        }
+        Some(buf.len())
+    }
+
+    /// Keys are not waited for, the input buffer is filled by interrupts
+    fn readiness(&self, _pid: ProcessId, write: bool) -> Readiness {
+        match self {
+            StdIO::Stdin if !write && !has_key() => Readiness::Recheck,
+            _ => Readiness::Ready,
+        }
+    }
+
+    /// Get, or set with `F_SETRAW`, whether the console is in raw mode
+    ///
+    /// return the old mode, only one process has it at a time.
+    fn ioctl(&self, cmd: usize, arg: usize) -> Result<usize, usize> {
+        let pid = crate::proc::current_pid().0;
+        let owner = crate::drivers::serial::raw_owner();
+        match cmd {
+            F_GETRAW => (),
+            F_SETRAW if owner.is_some_and(|owner| owner != pid) => return Err(EBUSY),
+            F_SETRAW => crate::drivers::serial::set_raw((arg != 0).then_some(pid)),
+            _ => return Err(EINVAL),
+        }
+        Ok(owner.is_some() as usize)
+    }
+
+    fn stat(&self) -> FdStat {
+        FdStat {
+            kind: FD_KIND_CONSOLE,
+            ..FdStat::default()
+        }
+    }
+
+    fn node(&self) -> usize {
+        CONSOLE_NODE
+    }
+}
+
+/// Reads nothing, and takes every write
+#[derive(Debug)]
+pub struct Null;
+
+impl Resource for Null {
+    fn read(&self, _buf: &mut [u8]) -> Option<usize> {
+        Some(0)
+    }
+
+    fn write(&self, buf: &[u8]) -> Option<usize> {
+        Some(buf.len())
+    }
+
+    fn node(&self) -> usize {
+        NULL_NODE
+    }
+}
+
+/// Writes go to the kernel log, a line each, like `/dev/kmsg`
+#[derive(Debug)]
+pub struct Klog;
+
+impl Resource for Klog {
+    fn read(&self, _buf: &mut [u8]) -> Option<usize> {
+        None
+    }
+
+    fn write(&self, buf: &[u8]) -> Option<usize> {
+        let pid = crate::proc::current_pid();
+        for line in buf.split(|&byte| byte == b'\n').filter(|line| !line.is_empty()) {
+            info!("#{}: {}", pid, Lossy(line));
+        }
+        Some(buf.len())
+    }
+
+    fn node(&self) -> usize {
+        KLOG_NODE
+    }
+}
+
+/// The open fds of a process and the resources behind them
 #[derive(Debug)]
-pub struct ResourceSet {
-    pub handles: BTreeMap<u8, Mutex<Resource>>,
+pub struct FileDescriptorTable {
+    pub handles: BTreeMap<u8, Mutex<Handle>>,
 }
 
-impl Default for ResourceSet {
+impl Default for FileDescriptorTable {
     fn default() -> Self {
         let mut res = Self {
             handles: BTreeMap::new(),
         };
 
-        res.open(Resource::Console(StdIO::Stdin));
-        res.open(Resource::Console(StdIO::Stdout));
-        res.open(Resource::Console(StdIO::Stderr));
+        res.open(Arc::new(StdIO::Stdin));
+        res.open(Arc::new(StdIO::Stdout));
+        res.open(Arc::new(StdIO::Stderr));
 
         res
     }
 }
 
-impl ResourceSet {
-    pub fn open(&mut self, res: Resource) -> u8 {
-        let fd = self.handles.len() as u8;
-        self.handles.insert(fd, Mutex::new(res));
-        fd
+impl FileDescriptorTable {
+    /// Open `res` as the lowest free fd
+    pub fn open(&mut self, res: Arc<dyn Resource>) -> Option<u8> {
+        let fd = (0..=u8::MAX).find(|fd| !self.handles.contains_key(fd))?;
+        self.handles.insert(fd, Mutex::new(Handle::new(res)));
+        Some(fd)
+    }
+
+    /// Open `res` as `fd`, closing what was there before
+    pub fn replace(&mut self, fd: u8, res: Arc<dyn Resource>) {
+        self.handles.insert(fd, Mutex::new(Handle::new(res)));
+    }
+
+    /// Open the resource behind `fd` again, e.g. for another process
+    pub fn share(&self, fd: u8) -> Option<Arc<dyn Resource>> {
+        self.handles.get(&fd).map(|h| h.lock().res.clone())
     }
 
     pub fn close(&mut self, fd: u8) -> bool {
-        self.handles.remove(&fd).is_some()
+        match self.handles.remove(&fd) {
+            Some(handle) => {
+                handle.lock().res.close();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Make `new` refer to the resource behind `old`, closing what was there
+    ///
+    /// the two fds share the resource, but not statistics or rate limits.
+    pub fn dup2(&mut self, old: u8, new: u8) -> bool {
+        if old == new {
+            return self.handles.contains_key(&old);
+        }
+
+        match self.share(old) {
+            Some(res) => {
+                self.replace(new, res);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// A table of the same fds for a forked process
+    ///
+    /// each fd opens its resource again, so closing it in one process
+    /// leaves it open in the other. Statistics and rate limits start over.
+    pub fn fork(&self) -> Self {
+        Self {
+            handles: self
+                .handles
+                .iter()
+                .map(|(fd, h)| (*fd, Mutex::new(Handle::new(h.lock().res.clone()))))
+                .collect(),
+        }
+    }
+
+    /// Queue `pid` on the pipe behind `fd` if reading or writing it would block
+    pub fn wait(&self, fd: u8, pid: ProcessId, write: bool) -> bool {
+        self.handles
+            .get(&fd)
+            .is_some_and(|h| h.lock().res.poll(pid, write))
+    }
+
+    /// The events of `fd` out of `events` that are ready, `POLLNVAL` if it
+    /// is not open, and whether it is to be checked again after a while
+    ///
+    /// `pid` is queued to be woken on the resource if some are not ready.
+    pub fn poll_events(&self, fd: u8, events: u16, pid: ProcessId) -> (u16, bool) {
+        let Some(handle) = self.handles.get(&fd) else {
+            return (POLLNVAL, false);
+        };
+        let res = handle.lock().res.clone();
+
+        let mut revents = 0;
+        let mut recheck = false;
+        for (event, write) in [(POLLIN, false), (POLLOUT, true)] {
+            if events & event == 0 {
+                continue;
+            }
+            match res.readiness(pid, write) {
+                Readiness::Ready => revents |= event,
+                Readiness::Waiting => (),
+                Readiness::Recheck => recheck = true,
+            }
+        }
+        (revents, recheck)
+    }
+
+    /// The node `fd` locks and the id of its handle, see `proc::flock`
+    ///
+    /// the lock is released when the handle is dropped.
+    pub fn flock_target(&self, fd: u8) -> Option<(usize, u64)> {
+        self.handles.get(&fd).map(|h| {
+            let mut h = h.lock();
+            h.locked = true;
+            (h.res.node(), h.id)
+        })
+    }
+
+    pub fn stat(&self, fd: u8) -> Option<FdStat> {
+        self.handles.get(&fd).map(|h| h.lock().stat())
+    }
+
+    /// Move the position of the file behind `fd`, see `SEEK_SET`
+    ///
+    /// return the new position, or the errno
+    pub fn seek(&self, fd: u8, offset: isize, whence: usize) -> Result<usize, usize> {
+        let handle = self.handles.get(&fd).ok_or(EBADF)?;
+        let res = handle.lock().res.clone();
+        res.seek(offset, whence)
+    }
+
+    /// The inode and access mode of the file behind `fd`, see `Resource::file`
+    pub fn file(&self, fd: u8) -> Result<(Arc<dyn Inode>, usize), usize> {
+        let handle = self.handles.get(&fd).ok_or(EBADF)?;
+        let res = handle.lock().res.clone();
+        res.file().ok_or(ENODEV)
+    }
+
+    /// Control the device behind `fd`, see `Resource::ioctl`
+    pub fn ioctl(&self, fd: u8, cmd: usize, arg: usize) -> Result<usize, usize> {
+        let handle = self.handles.get(&fd).ok_or(EBADF)?;
+        let res = handle.lock().res.clone();
+        res.ioctl(cmd, arg)
+    }
+
+    /// Limit `fd` to `rate` bytes per second, 0 removes the limit
+    ///
+    /// return the old limit
+    pub fn set_rate(&self, fd: u8, rate: usize) -> Option<usize> {
+        self.handles.get(&fd).map(|h| h.lock().set_rate(rate))
     }
 
     pub fn read(&self, fd: u8, buf: &mut [u8]) -> isize {
@@ -54,55 +384,720 @@ impl ResourceSet {
             -1
         }
     }
-}
 
-pub enum Resource {
-    Console(StdIO),
-    Null,
-}
+    /// Read into several buffers in order, holding the handle only once
+    ///
+    /// stops at the first short read, like `readv`
+    pub fn read_vectored(&self, fd: u8, bufs: &mut [&mut [u8]]) -> isize {
+        let handle = match self.handles.get(&fd) {
+            Some(handle) => handle,
+            None => return -1,
+        };
 
-impl Resource {
-    pub fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
-        match self {
-            Resource::Console(stdio) => match stdio {
-                &mut StdIO::Stdin => {
-                    // just read from kernel input buffer
-                    if let Some(ch) = try_pop_key() {
-                        buf[0] = ch;
-                        Some(1)
-                    } else {
-                        Some(0)
+        let mut res = handle.lock();
+        let mut total = 0;
+
+        for buf in bufs.iter_mut() {
+            match res.read(buf) {
+                Some(count) => {
+                    total += count;
+                    if count < buf.len() {
+                        break;
                     }
                 }
-                _ => None,
-            },
-            Resource::Null => Some(0),
+                None if total == 0 => return -1,
+                None => break,
+            }
         }
+
+        total as isize
     }
 
-    pub fn write(&mut self, buf: &[u8]) -> Option<usize> {
-        match self {
-            Resource::Console(stdio) => match *stdio {
-                StdIO::Stdin => None,
-                StdIO::Stdout => {
-                    print!("{}", String::from_utf8_lossy(buf));
-                    Some(buf.len())
+    /// Write several buffers in order, holding the handle only once
+    ///
+    /// so the output of one `writev` is never interleaved with other writers
+    pub fn write_vectored(&self, fd: u8, bufs: &[&[u8]]) -> isize {
+        let handle = match self.handles.get(&fd) {
+            Some(handle) => handle,
+            None => return -1,
+        };
+
+        let mut res = handle.lock();
+        let mut total = 0;
+
+        for buf in bufs.iter() {
+            match res.write(buf) {
+                Some(count) => {
+                    total += count;
+                    if count < buf.len() {
+                        break;
+                    }
                 }
-                StdIO::Stderr => {
-                    warn!("{}", String::from_utf8_lossy(buf));
-                    Some(buf.len())
+                None if total == 0 => return -1,
+                None => break,
+            }
+        }
+
+        total as isize
+    }
+
+    /// Copy up to `len` bytes from `in_fd` to `out_fd` without leaving the kernel
+    ///
+    /// stops at the first short read or write, like `sendfile`, or at a
+    /// block the disk has yet to read once some bytes are out. With
+    /// `offset` the input is read from there, and its position is put back
+    /// after, so `in_fd` must be seekable. Once bytes are written, the count
+    /// is returned even if the position could not be put back.
+    pub fn send_file(
+        &self,
+        out_fd: u8,
+        in_fd: u8,
+        offset: Option<usize>,
+        len: usize,
+    ) -> Result<usize, usize> {
+        if out_fd == in_fd {
+            return Err(EINVAL);
+        }
+
+        let (input, output) = match (self.handles.get(&in_fd), self.handles.get(&out_fd)) {
+            (Some(input), Some(output)) => (input, output),
+            _ => return Err(EBADF),
+        };
+
+        let mut input = input.lock();
+        let mut output = output.lock();
+
+        let Some(offset) = offset else {
+            return copy_between(&mut input, &mut output, len);
+        };
+        let offset = isize::try_from(offset).map_err(|_| EINVAL)?;
+        let pos = input.res.seek(0, SEEK_CUR)?;
+        input.res.seek(offset, SEEK_SET)?;
+        let ret = copy_between(&mut input, &mut output, len);
+        // the bytes are already out, so the count is what to report,
+        // the position is left where the copy stopped if it can't go back
+        if input.res.seek(pos as isize, SEEK_SET).is_err() {
+            warn!("send_file: failed to restore the offset of fd {}", in_fd);
+        }
+        ret
+    }
+}
+
+/// Copy up to `len` bytes from `input` to `output`, see `send_file`
+fn copy_between(input: &mut Handle, output: &mut Handle, len: usize) -> Result<usize, usize> {
+    let mut buf = vec![0u8; len.min(SEND_FILE_CHUNK)];
+    let mut total = 0;
+
+    while total < len {
+        let chunk = (len - total).min(buf.len());
+
+        let read = match input.read(&mut buf[..chunk]) {
+            Some(0) => break,
+            Some(count) => count,
+            None if total == 0 => return Err(EIO),
+            None => break,
+        };
+
+        let written = output.write(&buf[..read]);
+        let count = written.unwrap_or(0);
+        if count < read {
+            // the bytes read but not written are read again by the next call
+            let _ = input.res.seek(count as isize - read as isize, SEEK_CUR);
+        }
+
+        match written {
+            Some(count) => {
+                total += count;
+                if count < read || read < chunk {
+                    break;
                 }
-            },
-            Resource::Null => Some(buf.len()),
+            }
+            None if total == 0 => return Err(EIO),
+            None => break,
+        }
+    }
+
+    // the output cannot be taken back, so a block missed on the disk
+    // after some of it ends the copy short instead of running it again
+    if total > 0 {
+        block::cancel_sleep(crate::proc::current_pid());
+    }
+
+    Ok(total)
+}
+
+/// An open resource with its I/O statistics and rate limit
+///
+/// every read and write on a fd goes through here,
+/// so vectored I/O and `send_file` are accounted too.
+#[derive(Debug)]
+pub struct Handle {
+    /// tells handles apart, even of the same resource
+    id: u64,
+    res: Arc<dyn Resource>,
+    stat: FdStat,
+    limit: Option<RateLimit>,
+    /// a `flock` was taken through this handle
+    locked: bool,
+}
+
+impl Handle {
+    pub fn new(res: Arc<dyn Resource>) -> Self {
+        Self {
+            id: NEXT_HANDLE.fetch_add(1, Ordering::Relaxed),
+            res,
+            stat: FdStat::default(),
+            limit: None,
+            locked: false,
+        }
+    }
+
+    pub fn stat(&self) -> FdStat {
+        let res = self.res.stat();
+        FdStat {
+            rate: self.limit.map_or(0, |limit| limit.rate),
+            kind: res.kind,
+            size: res.size,
+            pos: res.pos,
+            ..self.stat
+        }
+    }
+
+    pub fn set_rate(&mut self, rate: usize) -> usize {
+        let old = self.limit.map_or(0, |limit| limit.rate);
+        self.limit = match rate {
+            0 => None,
+            rate => Some(RateLimit::new(rate as u64, super::clock::now_nanos())),
+        };
+        old as usize
+    }
+
+    pub fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let allowed = self.take(buf.len());
+        if allowed == 0 && !buf.is_empty() {
+            return Some(0);
+        }
+
+        let count = self.res.read(&mut buf[..allowed]);
+        self.give_back(allowed - count.unwrap_or(0));
+
+        let count = count?;
+        self.stat.read_ops += 1;
+        self.stat.read_bytes += count as u64;
+        Some(count)
+    }
+
+    pub fn write(&mut self, buf: &[u8]) -> Option<usize> {
+        let allowed = self.take(buf.len());
+        if allowed == 0 && !buf.is_empty() {
+            return Some(0);
+        }
+
+        let count = self.res.write(&buf[..allowed]);
+        self.give_back(allowed - count.unwrap_or(0));
+
+        let count = count?;
+        self.stat.write_ops += 1;
+        self.stat.write_bytes += count as u64;
+        Some(count)
+    }
+
+    /// Take up to `len` bytes from the rate limit
+    fn take(&mut self, len: usize) -> usize {
+        let limit = match self.limit.as_mut() {
+            Some(limit) => limit,
+            None => return len,
+        };
+
+        let allowed = limit.take(len as u64, super::clock::now_nanos()) as usize;
+        if allowed < len {
+            self.stat.throttled += 1;
+        }
+        allowed
+    }
+
+    /// Return the bytes taken but not transferred
+    fn give_back(&mut self, len: usize) {
+        if let Some(limit) = self.limit.as_mut() {
+            limit.tokens = (limit.tokens + len as u64).min(limit.rate);
+        }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        if self.locked {
+            crate::proc::flock_release(self.res.node(), self.id);
+        }
+    }
+}
+
+/// A token bucket holding at most one second worth of bytes
+#[derive(Debug, Clone, Copy)]
+struct RateLimit {
+    rate: u64,
+    tokens: u64,
+    /// when the tokens were last refilled, in nanoseconds
+    last: i64,
+}
+
+impl RateLimit {
+    fn new(rate: u64, now: i64) -> Self {
+        Self {
+            rate,
+            tokens: rate,
+            last: now,
+        }
+    }
+
+    fn take(&mut self, len: u64, now: i64) -> u64 {
+        let elapsed = now.saturating_sub(self.last).max(0) as u128;
+        let refill = (elapsed * self.rate as u128 / NANOS_PER_SEC) as u64;
+
+        if self.tokens + refill >= self.rate {
+            self.tokens = self.rate;
+            self.last = now;
+        } else if refill > 0 {
+            // keep the remainder of a partial token for the next refill
+            self.tokens += refill;
+            self.last += (refill as u128 * NANOS_PER_SEC / self.rate as u128) as i64;
+        }
+
+        let count = len.min(self.tokens);
+        self.tokens -= count;
+        count
+    }
+}
+
+/// Bytes written are kept until read, e.g. to capture the output of a program
+#[derive(Debug, Default)]
+pub struct Buffer {
+    data: Mutex<VecDeque<u8>>,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Resource for Buffer {
+    fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        let mut data = self.data.lock();
+        let count = buf.len().min(data.len());
+        // the queue may wrap around, so it is copied in two parts
+        let (front, back) = data.as_slices();
+        let split = count.min(front.len());
+        bulk::copy_slice(&mut buf[..split], &front[..split]);
+        bulk::copy_slice(&mut buf[split..count], &back[..count - split]);
+        data.drain(..count);
+        Some(count)
+    }
+
+    fn write(&self, buf: &[u8]) -> Option<usize> {
+        let mut data = self.data.lock();
+        let count = buf.len().min(BUFFER_MAX - data.len());
+        data.extend(&buf[..count]);
+        Some(count)
+    }
+}
+
+/// A timer read through a timerfd
+///
+/// a read returns the expirations since the last read as a `u64`,
+/// or nothing if there are none yet.
+#[derive(Debug, Default)]
+struct Timer {
+    /// next expiration in nanoseconds, 0 when disarmed
+    next: i64,
+    /// nanoseconds between expirations, 0 for a one-shot timer
+    interval: i64,
+}
+
+impl Timer {
+    /// Expire after `initial` then every `interval` nanoseconds,
+    /// an `initial` of 0 disarms the timer
+    fn arm(&mut self, initial: u64, interval: u64, now: i64) {
+        self.next = match initial {
+            0 => 0,
+            initial => now.saturating_add(initial as i64),
+        };
+        self.interval = interval as i64;
+    }
+
+    /// Take the expirations up to `now`
+    fn expirations(&mut self, now: i64) -> u64 {
+        if self.next == 0 || now < self.next {
+            return 0;
+        }
+
+        if self.interval == 0 {
+            self.next = 0;
+            return 1;
+        }
+
+        let count = (now - self.next) / self.interval + 1;
+        self.next += count * self.interval;
+        count as u64
+    }
+}
+
+#[derive(Debug)]
+pub struct TimerFd {
+    timer: Mutex<Timer>,
+}
+
+impl TimerFd {
+    /// A timer expiring after `initial` then every `interval` nanoseconds
+    pub fn new(initial: u64, interval: u64) -> Self {
+        let mut timer = Timer::default();
+        timer.arm(initial, interval, super::clock::now_nanos());
+        Self {
+            timer: Mutex::new(timer),
+        }
+    }
+}
+
+impl Resource for TimerFd {
+    fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        let buf = buf.get_mut(..8)?;
+        match self.timer.lock().expirations(super::clock::now_nanos()) {
+            0 => Some(0),
+            count => {
+                buf.copy_from_slice(&count.to_le_bytes());
+                Some(8)
+            }
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> Option<usize> {
+        // two `u64`: the initial expiration and the interval
+        let initial = u64::from_le_bytes(buf.get(..8)?.try_into().ok()?);
+        let interval = u64::from_le_bytes(buf.get(8..16)?.try_into().ok()?);
+        self.timer
+            .lock()
+            .arm(initial, interval, super::clock::now_nanos());
+        Some(16)
+    }
+}
+
+/// A counter, writes add to it and a read takes it all, like `eventfd`
+#[derive(Debug)]
+pub struct EventFd {
+    count: Mutex<u64>,
+}
+
+impl EventFd {
+    pub fn new(value: u64) -> Self {
+        Self {
+            count: Mutex::new(value),
+        }
+    }
+}
+
+impl Resource for EventFd {
+    fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        let buf = buf.get_mut(..8)?;
+        match core::mem::take(&mut *self.count.lock()) {
+            0 => Some(0),
+            value => {
+                buf.copy_from_slice(&value.to_le_bytes());
+                Some(8)
+            }
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> Option<usize> {
+        let value = u64::from_le_bytes(buf.get(..8)?.try_into().ok()?);
+        let mut count = self.count.lock();
+        // the counter never reaches `u64::MAX`, the writer has to retry
+        match count.checked_add(value).filter(|&sum| sum < u64::MAX) {
+            Some(sum) => {
+                *count = sum;
+                Some(8)
+            }
+            None => Some(0),
+        }
+    }
+}
+
+/// A file of the VFS, read and written where the last access stopped
+pub struct OpenFile {
+    inode: Arc<dyn Inode>,
+    pos: Mutex<usize>,
+    /// one of `O_RDONLY`, `O_WRONLY` and `O_RDWR`
+    access: usize,
+    append: bool,
+    ahead: Mutex<ReadAhead>,
+}
+
+/// Blocks a file read in order is first read ahead by
+const READ_AHEAD_MIN: usize = 4;
+
+/// Where the next read starts if the file is read in order,
+/// and the bytes read ahead of the last one
+#[derive(Debug, Default)]
+struct ReadAhead {
+    next: usize,
+    window: usize,
+}
+
+impl OpenFile {
+    /// `inode` opened with the access mode and `O_APPEND` of `flags`
+    pub fn new(inode: Arc<dyn Inode>, flags: usize) -> Self {
+        Self {
+            inode,
+            pos: Mutex::new(0),
+            access: flags & O_ACCMODE,
+            append: flags & O_APPEND != 0,
+            ahead: Mutex::new(ReadAhead::default()),
         }
     }
+
+    /// Read ahead of a read of `len` bytes at `pos`
+    ///
+    /// a read where the last one stopped doubles the window up to
+    /// `block.readahead`, any other read starts over. Return the window.
+    fn read_ahead(&self, pos: usize, len: usize) -> usize {
+        let max = READ_AHEAD.get() * BLOCK_SIZE;
+        if max == 0 {
+            return 0;
+        }
+
+        let ahead = self.ahead.lock();
+        let window = match pos == ahead.next {
+            true => (ahead.window * 2).max(READ_AHEAD_MIN * BLOCK_SIZE).min(max),
+            false => 0,
+        };
+        // the read itself too, so its blocks are asked for together
+        self.inode.prefetch(pos, len + window);
+        window
+    }
+}
+
+impl Resource for OpenFile {
+    fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        if self.access == O_WRONLY {
+            return None;
+        }
+        let mut pos = self.pos.lock();
+        let window = self.read_ahead(*pos, buf.len());
+        let count = self.inode.read_at(*pos, buf).ok()?;
+        *pos += count;
+
+        // once done, so a read run again after a miss is not counted twice
+        *self.ahead.lock() = ReadAhead {
+            next: *pos,
+            window,
+        };
+        Some(count)
+    }
+
+    fn write(&self, buf: &[u8]) -> Option<usize> {
+        if self.access == O_RDONLY {
+            return None;
+        }
+        let mut pos = self.pos.lock();
+        if self.append {
+            *pos = self.inode.metadata().size;
+        }
+        let count = self.inode.write_at(*pos, buf).ok()?;
+        *pos += count;
+        Some(count)
+    }
+
+    fn seek(&self, offset: isize, whence: usize) -> Result<usize, usize> {
+        let mut pos = self.pos.lock();
+        let base = match whence {
+            SEEK_SET => 0,
+            SEEK_CUR => *pos,
+            SEEK_END => self.inode.metadata().size,
+            _ => return Err(EINVAL),
+        };
+        // past the end is fine, a write there fills the gap
+        *pos = base.checked_add_signed(offset).ok_or(EINVAL)?;
+        Ok(*pos)
+    }
+
+    fn stat(&self) -> FdStat {
+        FdStat {
+            kind: FD_KIND_FILE,
+            size: self.inode.metadata().size as u64,
+            pos: *self.pos.lock() as u64,
+            ..FdStat::default()
+        }
+    }
+
+    /// The inode, so every open of a file takes the same locks
+    fn node(&self) -> usize {
+        Arc::as_ptr(&self.inode) as *const () as usize
+    }
+
+    fn file(&self) -> Option<(Arc<dyn Inode>, usize)> {
+        Some((self.inode.clone(), self.access))
+    }
 }
 
-impl core::fmt::Debug for Resource {
+impl Debug for OpenFile {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
-        match self {
-            Resource::Console(stdio) => write!(f, "Console({:?})", stdio),
-            Resource::Null => write!(f, "Null"),
+        let size = self.inode.metadata().size;
+        write!(f, "OpenFile({}/{} bytes)", self.pos.lock(), size)
+    }
+}
+
+/// The ring buffer between the two ends of a pipe
+///
+/// readers block while it is empty and read 0 bytes once every write end
+/// is closed, writers block while it is full and fail once every read end is.
+#[derive(Debug, Default)]
+pub struct Pipe {
+    data: VecDeque<u8>,
+    readers: usize,
+    writers: usize,
+    /// processes blocked on either end, woken by any change
+    waiting: Vec<ProcessId>,
+}
+
+impl Pipe {
+    fn would_block(&self, write: bool) -> bool {
+        if write {
+            self.readers > 0 && self.data.len() == PIPE_SIZE
+        } else {
+            self.writers > 0 && self.data.is_empty()
+        }
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let count = buf.len().min(self.data.len());
+        let (front, back) = self.data.as_slices();
+        let split = count.min(front.len());
+        bulk::copy_slice(&mut buf[..split], &front[..split]);
+        bulk::copy_slice(&mut buf[split..count], &back[..count - split]);
+        self.data.drain(..count);
+        count
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Option<usize> {
+        if self.readers == 0 {
+            return None;
+        }
+
+        let count = buf.len().min(PIPE_SIZE - self.data.len());
+        self.data.extend(&buf[..count]);
+        Some(count)
+    }
+}
+
+/// One end of a pipe, counted by the pipe while it is open
+pub struct PipeEnd {
+    pipe: Arc<Mutex<Pipe>>,
+    write: bool,
+}
+
+impl PipeEnd {
+    fn new(pipe: Arc<Mutex<Pipe>>, write: bool) -> Self {
+        {
+            let mut pipe = pipe.lock();
+            if write {
+                pipe.writers += 1;
+            } else {
+                pipe.readers += 1;
+            }
+        }
+        Self { pipe, write }
+    }
+
+    /// The read and the write end of a new pipe
+    pub fn pair() -> (Self, Self) {
+        let pipe = Arc::new(Mutex::new(Pipe::default()));
+        (Self::new(pipe.clone(), false), Self::new(pipe, true))
+    }
+}
+
+impl Resource for PipeEnd {
+    fn read(&self, buf: &mut [u8]) -> Option<usize> {
+        if self.write {
+            return None;
+        }
+
+        let mut pipe = self.pipe.lock();
+        let count = pipe.read(buf);
+        let waiting = match count {
+            0 => Vec::new(),
+            _ => core::mem::take(&mut pipe.waiting),
+        };
+        drop(pipe);
+
+        crate::proc::wake_blocked(waiting);
+        Some(count)
+    }
+
+    fn write(&self, buf: &[u8]) -> Option<usize> {
+        if !self.write {
+            return None;
+        }
+
+        let mut pipe = self.pipe.lock();
+        let count = pipe.write(buf);
+        let waiting = match count {
+            Some(0) | None => Vec::new(),
+            Some(_) => core::mem::take(&mut pipe.waiting),
+        };
+        drop(pipe);
+
+        crate::proc::wake_blocked(waiting);
+        count
+    }
+
+    fn poll(&self, pid: ProcessId, write: bool) -> bool {
+        if self.write != write {
+            return false;
+        }
+
+        let mut pipe = self.pipe.lock();
+        let block = pipe.would_block(write);
+        if block {
+            pipe.waiting.push(pid);
+        }
+        block
+    }
+
+    fn stat(&self) -> FdStat {
+        FdStat {
+            kind: FD_KIND_PIPE,
+            ..FdStat::default()
+        }
+    }
+
+    fn node(&self) -> usize {
+        Arc::as_ptr(&self.pipe) as *const () as usize
+    }
+}
+
+impl Debug for PipeEnd {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "PipeEnd({}, {} bytes)",
+            if self.write { "write" } else { "read" },
+            self.pipe.lock().data.len()
+        )
+    }
+}
+
+impl Drop for PipeEnd {
+    fn drop(&mut self) {
+        let mut pipe = self.pipe.lock();
+        if self.write {
+            pipe.writers -= 1;
+        } else {
+            pipe.readers -= 1;
         }
+        // the last end of its kind going away ends their wait too
+        let waiting = core::mem::take(&mut pipe.waiting);
+        drop(pipe);
+
+        crate::proc::wake_blocked(waiting);
     }
 }
+
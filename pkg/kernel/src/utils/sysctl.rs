@@ -0,0 +1,61 @@
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::drivers::block;
+use crate::memory::bulk;
+use crate::proc::{self, deterministic, edf, limits, mlfq};
+
+/// A kernel setting that can be changed at runtime by `Syscall::Sysctl`
+pub struct Tunable {
+    name: &'static str,
+    value: AtomicUsize,
+}
+
+impl Tunable {
+    pub const fn new(name: &'static str, value: usize) -> Self {
+        Self {
+            name,
+            value: AtomicUsize::new(value),
+        }
+    }
+
+    #[inline]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[inline]
+    pub fn get(&self) -> usize {
+        self.value.load(Ordering::Relaxed)
+    }
+
+    /// Set a new value and return the old one
+    #[inline]
+    pub fn set(&self, value: usize) -> usize {
+        self.value.swap(value, Ordering::Relaxed)
+    }
+}
+
+static TUNABLES: &[&Tunable] = &[
+    &limits::FORK_RATE,
+    &limits::FORK_RATE_GLOBAL,
+    &limits::MAX_DESCENDANTS,
+    &limits::MAX_PROCESSES,
+    &deterministic::SLICE,
+    &deterministic::MAX_TICKS,
+    &mlfq::BOOST_TICKS,
+    &edf::RT_UTIL_MAX,
+    &bulk::COPY_MODE,
+    &proc::ALLOW_WX,
+    &proc::swap::SWAP_LOW,
+    &proc::stack::STACK_LIMIT,
+    &block::READ_AHEAD,
+];
+
+/// Find a tunable by its dotted name, e.g. `proc.max_processes`
+pub fn find(name: &str) -> Option<&'static Tunable> {
+    TUNABLES.iter().find(|t| t.name == name).copied()
+}
+
+pub fn list() -> impl Iterator<Item = &'static Tunable> {
+    TUNABLES.iter().copied()
+}
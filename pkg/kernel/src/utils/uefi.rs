@@ -1,4 +1,3 @@
-use crate::{guard_access_fn, once_mutex};
 use boot::*;
 
 once_mutex!(UEFI_SERVICE: UefiRuntime);
@@ -27,4 +26,11 @@ impl UefiRuntime {
     pub fn get_time(&self) -> Time {
         self.runtime_service.get_time().unwrap()
     }
+
+    pub fn shutdown(&self) -> ! {
+        unsafe {
+            self.runtime_service
+                .reset(ResetType::SHUTDOWN, UefiStatus::SUCCESS, None)
+        }
+    }
 }
@@ -0,0 +1,123 @@
+//! Command line argument parsing
+//!
+//! parses flags (`-v`, `-abc`), long options (`--name`, `--name=value`)
+//! and plain values from a list of words. Everything after `--` is a value.
+
+use alloc::vec::Vec;
+use core::slice::Iter;
+use core::str::Chars;
+
+use crate::errln;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Arg<'a> {
+    /// `-v`, each letter of `-abc` is a flag of its own
+    Short(char),
+    /// `--name` or `--name=value`
+    Long(&'a str, Option<&'a str>),
+    /// anything else
+    Value(&'a str),
+}
+
+/// An iterator over the parsed arguments
+pub struct Parser<'a> {
+    args: Iter<'a, &'a str>,
+    shorts: Chars<'a>,
+    values_only: bool,
+}
+
+impl<'a> Parser<'a> {
+    /// Parse `args`, which should not include the program name
+    pub fn new(args: &'a [&'a str]) -> Self {
+        Self {
+            args: args.iter(),
+            shorts: "".chars(),
+            values_only: false,
+        }
+    }
+
+    /// Take the next word as the value of the current option, e.g. `-o out`
+    pub fn value(&mut self) -> Option<&'a str> {
+        let rest = self.shorts.as_str();
+        if !rest.is_empty() {
+            // `-oout` is `-o out`
+            self.shorts = "".chars();
+            return Some(rest);
+        }
+        self.args.next().copied()
+    }
+
+    /// Collect the remaining values, failing on any flag or option
+    pub fn values(self) -> Result<Vec<&'a str>, Arg<'a>> {
+        self.map(|arg| match arg {
+            Arg::Value(value) => Ok(value),
+            other => Err(other),
+        })
+        .collect()
+    }
+}
+
+impl<'a> Iterator for Parser<'a> {
+    type Item = Arg<'a>;
+
+    fn next(&mut self) -> Option<Arg<'a>> {
+        if let Some(c) = self.shorts.next() {
+            return Some(Arg::Short(c));
+        }
+
+        let arg = *self.args.next()?;
+        if self.values_only {
+            return Some(Arg::Value(arg));
+        }
+
+        if arg == "--" {
+            self.values_only = true;
+            return self.next();
+        }
+
+        if let Some(long) = arg.strip_prefix("--") {
+            return Some(match long.split_once('=') {
+                Some((name, value)) => Arg::Long(name, Some(value)),
+                None => Arg::Long(long, None),
+            });
+        }
+
+        match arg.strip_prefix('-') {
+            Some(shorts) if !shorts.is_empty() => {
+                self.shorts = shorts.chars();
+                self.next()
+            }
+            // a lone `-` usually means stdin
+            _ => Some(Arg::Value(arg)),
+        }
+    }
+}
+
+impl core::fmt::Display for Arg<'_> {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Arg::Short(c) => write!(f, "-{}", c),
+            Arg::Long(name, Some(value)) => write!(f, "--{}={}", name, value),
+            Arg::Long(name, None) => write!(f, "--{}", name),
+            Arg::Value(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// Print a usage message to stderr
+///
+/// `options` are pairs of the option and its description, e.g.
+/// `("-v, --verbose", "print more")`
+pub fn print_usage(name: &str, usage: &str, options: &[(&str, &str)]) {
+    errln!("usage: {} {}", name, usage);
+
+    let width = options.iter().map(|(opt, _)| opt.len()).max().unwrap_or(0);
+    for (opt, desc) in options {
+        errln!("    {:<width$}  {}", opt, desc, width = width);
+    }
+}
+
+/// Report an argument that the program does not understand
+pub fn unexpected(name: &str, arg: Arg) {
+    errln!("{}: unexpected argument `{}`", name, arg);
+}
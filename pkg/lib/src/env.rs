@@ -0,0 +1,58 @@
+//! The arguments and environment the program was started with
+//!
+//! the kernel leaves the strings on top of the stack, where they stay
+//! for as long as the program runs. `entry!` collects them before `main`.
+
+use alloc::vec::Vec;
+use syscall_def::IoVec;
+
+static mut ARGS: &[&str] = &[];
+static mut VARS: &[&str] = &[];
+
+/// Collect the strings passed to `_start`, return the arguments
+///
+/// # Safety
+///
+/// called once by `entry!` with the arguments of `_start`,
+/// after the heap is set up and before anything else runs.
+pub unsafe fn init(
+    argc: usize,
+    argv: *const IoVec,
+    envc: usize,
+    envp: *const IoVec,
+) -> &'static [&'static str] {
+    ARGS = collect(argc, argv);
+    VARS = collect(envc, envp);
+    ARGS
+}
+
+unsafe fn collect(count: usize, vecs: *const IoVec) -> &'static [&'static str] {
+    if count == 0 || vecs.is_null() {
+        return &[];
+    }
+
+    let strings: Vec<&'static str> = core::slice::from_raw_parts(vecs, count)
+        .iter()
+        .map(|v| {
+            core::str::from_utf8(core::slice::from_raw_parts(v.base, v.len)).unwrap_or_default()
+        })
+        .collect();
+    strings.leak()
+}
+
+/// The arguments, the first one is the name of the program by convention
+pub fn args() -> &'static [&'static str] {
+    unsafe { ARGS }
+}
+
+/// The environment, as `KEY=value` strings
+pub fn vars() -> &'static [&'static str] {
+    unsafe { VARS }
+}
+
+/// The value of the environment variable `key`
+pub fn var(key: &str) -> Option<&'static str> {
+    vars()
+        .iter()
+        .find_map(|var| var.strip_prefix(key)?.strip_prefix('='))
+}
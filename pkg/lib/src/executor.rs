@@ -0,0 +1,256 @@
+//! A single-threaded async runtime
+//!
+//! `block_on` runs a future and every task started by `spawn_local`
+//! until the future completes. While no task is ready the reactor blocks
+//! in `sys_poll` on the fds tasks wait for, until the first timer is due,
+//! and wakes the tasks whose fds are ready.
+//!
+//! the runtime lives in process memory, do not fork inside `block_on`.
+
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::future::Future;
+use core::pin::Pin;
+use core::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use crate::time::{Duration, Instant};
+use crate::{sys_poll, sys_read, sys_write, PollFd, POLLIN, POLLOUT};
+
+type Task = Pin<Box<dyn Future<Output = ()>>>;
+
+/// Task id of the future passed to `block_on`
+const MAIN_TASK: usize = usize::MAX;
+/// How long a write cut short by the rate limit of its fd waits to go on
+const RATE_RETRY: Duration = Duration::from_millis(1);
+
+#[derive(Default)]
+struct Runtime {
+    running: bool,
+    tasks: BTreeMap<usize, Task>,
+    ready: VecDeque<usize>,
+    next_task: usize,
+    /// wakers by deadline, the second key keeps them apart
+    timers: BTreeMap<(Instant, usize), Waker>,
+    next_timer: usize,
+    /// tasks waiting for an fd, woken once `sys_poll` finds it ready
+    io: Vec<(PollFd, Waker)>,
+}
+
+struct Global(RefCell<Option<Runtime>>);
+
+// only one process runs the runtime, and it has a single thread
+unsafe impl Sync for Global {}
+
+static RUNTIME: Global = Global(RefCell::new(None));
+
+fn with_runtime<R>(f: impl FnOnce(&mut Runtime) -> R) -> R {
+    let mut runtime = RUNTIME.0.borrow_mut();
+    match runtime.as_mut() {
+        Some(runtime) if runtime.running => f(runtime),
+        _ => panic!("no async runtime, call this inside `block_on`"),
+    }
+}
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(clone_waker, wake, wake, drop_waker);
+
+fn raw_waker(task: usize) -> RawWaker {
+    RawWaker::new(task as *const (), &VTABLE)
+}
+
+unsafe fn clone_waker(data: *const ()) -> RawWaker {
+    raw_waker(data as usize)
+}
+
+unsafe fn wake(data: *const ()) {
+    let task = data as usize;
+    // a waker may outlive `block_on`, then there is no one to wake
+    if let Some(runtime) = RUNTIME.0.borrow_mut().as_mut() {
+        if !runtime.ready.contains(&task) {
+            runtime.ready.push_back(task);
+        }
+    }
+}
+
+unsafe fn drop_waker(_data: *const ()) {}
+
+fn waker(task: usize) -> Waker {
+    unsafe { Waker::from_raw(raw_waker(task)) }
+}
+
+/// Run `future` to completion, along with the tasks it spawns
+///
+/// tasks still pending when `future` completes are dropped.
+pub fn block_on<F: Future>(future: F) -> F::Output {
+    {
+        let mut runtime = RUNTIME.0.borrow_mut();
+        assert!(
+            runtime.as_ref().is_none_or(|rt| !rt.running),
+            "`block_on` cannot be nested"
+        );
+        *runtime = Some(Runtime {
+            running: true,
+            ready: VecDeque::from([MAIN_TASK]),
+            ..Default::default()
+        });
+    }
+
+    let mut future = core::pin::pin!(future);
+    let main_waker = waker(MAIN_TASK);
+
+    let output = loop {
+        let next = with_runtime(|rt| rt.ready.pop_front());
+
+        match next {
+            Some(MAIN_TASK) => {
+                let mut cx = Context::from_waker(&main_waker);
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    break output;
+                }
+            }
+            Some(id) => {
+                // take the task out, so it can spawn and wake while polled
+                let task = with_runtime(|rt| rt.tasks.remove(&id));
+                if let Some(mut task) = task {
+                    let waker = waker(id);
+                    let mut cx = Context::from_waker(&waker);
+                    if task.as_mut().poll(&mut cx).is_pending() {
+                        with_runtime(|rt| rt.tasks.insert(id, task));
+                    }
+                }
+            }
+            None => turn(),
+        }
+    };
+
+    // drop the leftover tasks outside of the borrow
+    let runtime = RUNTIME.0.borrow_mut().take();
+    drop(runtime);
+
+    output
+}
+
+/// Wait for an fd or a timer when no task is ready, and wake their tasks
+fn turn() {
+    let (mut fds, wakers, deadline) = with_runtime(|rt| {
+        let (fds, wakers): (Vec<_>, Vec<_>) = core::mem::take(&mut rt.io).into_iter().unzip();
+        let deadline = rt.timers.keys().next().map(|&(at, _)| at);
+        (fds, wakers, deadline)
+    });
+
+    if fds.is_empty() && deadline.is_none() {
+        panic!("async runtime: every task is blocked forever");
+    }
+
+    // with no fds it sleeps until the first timer
+    let polled = sys_poll(&mut fds, deadline).is_ok();
+
+    let now = Instant::now();
+    let due = with_runtime(|rt| {
+        let later = rt.timers.split_off(&(now + Duration::from_nanos(1), 0));
+        core::mem::replace(&mut rt.timers, later)
+    });
+    due.into_values().for_each(Waker::wake);
+
+    for (fd, waker) in fds.into_iter().zip(wakers) {
+        // a failed poll wakes every task to find out for itself
+        if !polled || fd.revents != 0 {
+            waker.wake();
+        } else {
+            with_runtime(|rt| rt.io.push((fd, waker)));
+        }
+    }
+}
+
+/// Start `future` as a task of the current runtime
+pub fn spawn_local<F: Future<Output = ()> + 'static>(future: F) {
+    with_runtime(|rt| {
+        let id = rt.next_task;
+        rt.next_task += 1;
+        rt.tasks.insert(id, Box::pin(future));
+        rt.ready.push_back(id);
+    });
+}
+
+/// Complete after `duration`
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        deadline: Instant::now() + duration,
+    }
+}
+
+pub struct Sleep {
+    deadline: Instant,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        if Instant::now() >= self.deadline {
+            return Poll::Ready(());
+        }
+
+        with_runtime(|rt| {
+            let key = (self.deadline, rt.next_timer);
+            rt.next_timer += 1;
+            rt.timers.insert(key, cx.waker().clone());
+        });
+        Poll::Pending
+    }
+}
+
+/// Let the other tasks run once
+pub fn yield_now() -> impl Future<Output = ()> {
+    let mut yielded = false;
+    core::future::poll_fn(move |cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+}
+
+/// Complete once `fd` has one of the `POLL*` `events`, or is not open
+pub async fn ready(fd: u8, events: u16) {
+    core::future::poll_fn(|cx| {
+        let mut fds = [PollFd::new(fd, events)];
+        // a deadline already passed only checks the fd
+        match sys_poll(&mut fds, Some(Instant::now())) {
+            Ok(0) => {
+                let waker = cx.waker().clone();
+                with_runtime(|rt| rt.io.push((fds[0], waker)));
+                Poll::Pending
+            }
+            // an error is left to the I/O that follows
+            _ => Poll::Ready(()),
+        }
+    })
+    .await
+}
+
+/// Read from `fd` into `buf` once there is data
+///
+/// the read waits for `fd` to be ready first, so it does not block the
+/// other tasks, and a read of 0 bytes is the end of the input.
+pub async fn read(fd: u8, buf: &mut [u8]) -> Option<usize> {
+    ready(fd, POLLIN).await;
+    sys_read(fd, buf)
+}
+
+/// Write all of `buf` to `fd`, waiting out its rate limit
+///
+/// return false if the write fails
+pub async fn write_all(fd: u8, mut buf: &[u8]) -> bool {
+    while !buf.is_empty() {
+        ready(fd, POLLOUT).await;
+        match sys_write(fd, buf) {
+            Some(0) => sleep(RATE_RETRY).await,
+            Some(count) => buf = &buf[count..],
+            None => return false,
+        }
+    }
+    true
+}
@@ -0,0 +1,69 @@
+use core::cell::UnsafeCell;
+
+use crate::*;
+
+/// Most hooks a program can register, as `ATEXIT_MAX` in C
+pub const AT_EXIT_MAX: usize = 32;
+
+/// A hook and the process that registered it
+///
+/// forked processes share memory with their parent,
+/// so each one must run only the hooks it registered.
+type Hook = Option<(u16, fn())>;
+
+struct Hooks {
+    lock: SpinLock,
+    hooks: UnsafeCell<[Hook; AT_EXIT_MAX]>,
+}
+
+unsafe impl Sync for Hooks {}
+
+static HOOKS: Hooks = Hooks {
+    lock: SpinLock::new(),
+    hooks: UnsafeCell::new([None; AT_EXIT_MAX]),
+};
+
+impl Hooks {
+    fn with<R>(&self, f: impl FnOnce(&mut [Hook; AT_EXIT_MAX]) -> R) -> R {
+        self.lock.acquire();
+        let ret = f(unsafe { &mut *self.hooks.get() });
+        self.lock.release();
+        ret
+    }
+}
+
+/// Register `f` to be run by `exit`, hooks run in reverse order of registration
+///
+/// return false if there are already `AT_EXIT_MAX` hooks
+pub fn at_exit(f: fn()) -> bool {
+    let pid = sys_get_pid();
+    HOOKS.with(|hooks| match hooks.iter_mut().find(|hook| hook.is_none()) {
+        Some(slot) => {
+            *slot = Some((pid, f));
+            true
+        }
+        None => false,
+    })
+}
+
+/// Run the exit hooks of this process, then exit with `code`
+///
+/// returning from `main` and panicking end up here too.
+pub fn exit(code: usize) -> ! {
+    let pid = sys_get_pid();
+
+    // take each hook out before running it, so a hook may register
+    // more hooks, and one that panics is not run again
+    while let Some(f) = HOOKS.with(|hooks| {
+        hooks
+            .iter_mut()
+            .rev()
+            .find(|hook| matches!(hook, Some((owner, _)) if *owner == pid))
+            .and_then(|hook| hook.take())
+            .map(|(_, f)| f)
+    }) {
+        f();
+    }
+
+    sys_exit(code)
+}
@@ -0,0 +1,157 @@
+//! Formatting without the heap
+//!
+//! used where allocating is not possible or not wise,
+//! such as the panic handler or before the allocator is set up.
+
+use core::fmt::{self, Write};
+
+/// Enough for any `u64` in base 2, or an `i64` in base 10 with its sign
+pub const NUM_BUF_SIZE: usize = 65;
+
+/// Format `n` in `radix` (2 to 36) into the end of `buf`
+pub fn utoa(mut n: u64, radix: u32, buf: &mut [u8; NUM_BUF_SIZE]) -> &str {
+    assert!((2..=36).contains(&radix), "radix must be in 2..=36");
+
+    let mut pos = buf.len();
+    loop {
+        pos -= 1;
+        buf[pos] = match (n % radix as u64) as u8 {
+            d @ 0..=9 => b'0' + d,
+            d => b'a' + d - 10,
+        };
+        n /= radix as u64;
+        if n == 0 {
+            break;
+        }
+    }
+
+    // only ascii digits were written
+    unsafe { core::str::from_utf8_unchecked(&buf[pos..]) }
+}
+
+/// Format `n` in base 10 into the end of `buf`
+pub fn itoa(n: i64, buf: &mut [u8; NUM_BUF_SIZE]) -> &str {
+    let len = utoa(n.unsigned_abs(), 10, buf).len();
+    let mut pos = buf.len() - len;
+
+    if n < 0 {
+        pos -= 1;
+        buf[pos] = b'-';
+    }
+
+    unsafe { core::str::from_utf8_unchecked(&buf[pos..]) }
+}
+
+/// Format `n` with `precision` digits after the point
+///
+/// values too large for a `u64` integer part are written as `inf`,
+/// the last digit is rounded half away from zero.
+pub fn ftoa<const N: usize>(n: f64, precision: usize, buf: &mut StackBuf<N>) -> &str {
+    buf.clear();
+
+    if n.is_nan() {
+        buf.push_str("NaN");
+        return buf.as_str();
+    }
+
+    if n.is_sign_negative() {
+        buf.push_str("-");
+    }
+
+    let n = if n < 0.0 { -n } else { n };
+    let scale = (0..precision).fold(1u64, |scale, _| scale.saturating_mul(10));
+    let scaled = n * scale as f64 + 0.5;
+
+    if !n.is_finite() || scaled >= u64::MAX as f64 {
+        buf.push_str("inf");
+        return buf.as_str();
+    }
+
+    let scaled = scaled as u64;
+    let mut num = [0; NUM_BUF_SIZE];
+    buf.push_str(utoa(scaled / scale, 10, &mut num));
+
+    if precision > 0 {
+        buf.push_str(".");
+        let frac = utoa(scaled % scale, 10, &mut num);
+        for _ in frac.len()..precision {
+            buf.push_str("0");
+        }
+        buf.push_str(frac);
+    }
+
+    buf.as_str()
+}
+
+/// A fixed size buffer that implements `core::fmt::Write`
+///
+/// output that does not fit is dropped and marks the buffer truncated,
+/// it is never cut in the middle of a character.
+pub struct StackBuf<const N: usize> {
+    buf: [u8; N],
+    len: usize,
+    truncated: bool,
+}
+
+impl<const N: usize> StackBuf<N> {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        // only whole `str`s are ever copied in
+        unsafe { core::str::from_utf8_unchecked(&self.buf[..self.len]) }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.truncated = false;
+    }
+
+    /// Append as much of `s` as fits, return false if some was dropped
+    pub fn push_str(&mut self, s: &str) -> bool {
+        let mut count = s.len().min(N - self.len);
+        while !s.is_char_boundary(count) {
+            count -= 1;
+        }
+
+        self.buf[self.len..self.len + count].copy_from_slice(&s.as_bytes()[..count]);
+        self.len += count;
+        self.truncated |= count < s.len();
+        !self.truncated
+    }
+}
+
+impl<const N: usize> Default for StackBuf<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Write for StackBuf<N> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        match self.push_str(s) {
+            true => Ok(()),
+            false => Err(fmt::Error),
+        }
+    }
+}
+
+/// Format `args` into a new `StackBuf`, truncating what does not fit
+pub fn format_stack<const N: usize>(args: fmt::Arguments) -> StackBuf<N> {
+    let mut buf = StackBuf::new();
+    let _ = buf.write_fmt(args);
+    buf
+}
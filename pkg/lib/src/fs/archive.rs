@@ -0,0 +1,299 @@
+//! Reading ustar and cpio archives
+//!
+//! `Archive::new` finds the format from the first header, and
+//! `Archive::entries` walks the entries without copying their data.
+//! Only the "newc" cpio flavor used by initramfs is read, with or
+//! without checksums.
+
+use alloc::string::String;
+use core::fmt;
+
+const BLOCK_SIZE: usize = 512;
+const USTAR_MAGIC: &[u8] = b"ustar";
+const CPIO_MAGIC: &[u8] = b"07070";
+const CPIO_HEADER_SIZE: usize = 110;
+const CPIO_TRAILER: &str = "TRAILER!!!";
+
+/// File type bits of a mode
+const S_IFMT: u32 = 0o170000;
+const S_IFREG: u32 = 0o100000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFLNK: u32 = 0o120000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Ustar,
+    /// `newc`, magic `070701`, or `070702` with checksums
+    Cpio,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    File,
+    Dir,
+    Symlink,
+    /// hard links, devices and the like, by their tar type flag
+    /// or cpio file type bits
+    Other(u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveError {
+    UnknownFormat,
+    /// the archive ends in the middle of an entry
+    Truncated,
+    BadChecksum,
+    /// a header field could not be parsed
+    BadHeader,
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ArchiveError::UnknownFormat => "not a ustar or newc cpio archive",
+            ArchiveError::Truncated => "archive is truncated",
+            ArchiveError::BadChecksum => "header checksum mismatch",
+            ArchiveError::BadHeader => "malformed header",
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Entry<'a> {
+    pub name: String,
+    pub kind: EntryKind,
+    /// permission bits
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// seconds since the epoch
+    pub mtime: u64,
+    /// the target of a symlink
+    pub link: String,
+    pub data: &'a [u8],
+}
+
+impl Entry<'_> {
+    #[inline]
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+}
+
+pub struct Archive<'a> {
+    data: &'a [u8],
+    format: Format,
+}
+
+impl<'a> Archive<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, ArchiveError> {
+        let format = if data.starts_with(CPIO_MAGIC) {
+            Format::Cpio
+        } else if data.get(257..262) == Some(USTAR_MAGIC) {
+            Format::Ustar
+        } else {
+            return Err(ArchiveError::UnknownFormat);
+        };
+
+        Ok(Self { data, format })
+    }
+
+    #[inline]
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn entries(&self) -> Entries<'a> {
+        Entries {
+            rest: self.data,
+            format: self.format,
+            done: false,
+        }
+    }
+
+    /// Find the entry called `name`
+    pub fn find(&self, name: &str) -> Result<Option<Entry<'a>>, ArchiveError> {
+        for entry in self.entries() {
+            let entry = entry?;
+            if entry.name.trim_end_matches('/') == name.trim_end_matches('/') {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// An iterator over the entries of an archive, stops after an error
+pub struct Entries<'a> {
+    rest: &'a [u8],
+    format: Format,
+    done: bool,
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<Entry<'a>, ArchiveError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let entry = match self.format {
+            Format::Ustar => self.next_ustar(),
+            Format::Cpio => self.next_cpio(),
+        };
+
+        match entry {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(err) => {
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], ArchiveError> {
+    if buf.len() < len {
+        return Err(ArchiveError::Truncated);
+    }
+    let (head, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(head)
+}
+
+#[inline]
+fn align_up(n: usize, align: usize) -> usize {
+    n.div_ceil(align) * align
+}
+
+/// A NUL terminated or NUL padded string field
+fn c_str(field: &[u8]) -> Result<&str, ArchiveError> {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    core::str::from_utf8(&field[..len]).map_err(|_| ArchiveError::BadHeader)
+}
+
+/// An octal number field of a tar header, padded with spaces or NULs
+fn octal(field: &[u8]) -> Result<u64, ArchiveError> {
+    let digits = c_str(field)?.trim_matches(' ');
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| ArchiveError::BadHeader)
+}
+
+/// An eight digit hex field of a cpio header
+fn hex(field: &[u8]) -> Result<u32, ArchiveError> {
+    let digits = core::str::from_utf8(field).map_err(|_| ArchiveError::BadHeader)?;
+    u32::from_str_radix(digits, 16).map_err(|_| ArchiveError::BadHeader)
+}
+
+fn kind_of_mode(mode: u32) -> EntryKind {
+    match mode & S_IFMT {
+        S_IFREG => EntryKind::File,
+        S_IFDIR => EntryKind::Dir,
+        S_IFLNK => EntryKind::Symlink,
+        other => EntryKind::Other(other),
+    }
+}
+
+impl<'a> Entries<'a> {
+    fn next_ustar(&mut self) -> Result<Option<Entry<'a>>, ArchiveError> {
+        // the archive ends with zeroed blocks, or just ends
+        if self.rest.is_empty() {
+            return Ok(None);
+        }
+
+        let header = take(&mut self.rest, BLOCK_SIZE)?;
+        if header.iter().all(|&b| b == 0) {
+            return Ok(None);
+        }
+
+        // the checksum is taken with its own field as spaces
+        let sum: u64 = header
+            .iter()
+            .enumerate()
+            .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b } as u64)
+            .sum();
+        if octal(&header[148..156])? != sum {
+            return Err(ArchiveError::BadChecksum);
+        }
+
+        let mut name = String::from(c_str(&header[345..500])?);
+        if !name.is_empty() {
+            name.push('/');
+        }
+        name.push_str(c_str(&header[0..100])?);
+
+        let size = octal(&header[124..136])? as usize;
+        let kind = match header[156] {
+            b'0' | 0 => EntryKind::File,
+            b'5' => EntryKind::Dir,
+            b'2' => EntryKind::Symlink,
+            flag => EntryKind::Other(flag as u32),
+        };
+
+        let data = take(&mut self.rest, size)?;
+        let padding = align_up(size, BLOCK_SIZE) - size;
+        self.rest = self.rest.get(padding..).unwrap_or(&[]);
+
+        Ok(Some(Entry {
+            name,
+            kind,
+            mode: octal(&header[100..108])? as u32 & 0o7777,
+            uid: octal(&header[108..116])? as u32,
+            gid: octal(&header[116..124])? as u32,
+            mtime: octal(&header[136..148])?,
+            link: String::from(c_str(&header[157..257])?),
+            data,
+        }))
+    }
+
+    fn next_cpio(&mut self) -> Result<Option<Entry<'a>>, ArchiveError> {
+        // entries are aligned to 4 bytes from the start of the archive,
+        // every entry keeps the rest aligned, so the offset is not needed
+        let header = take(&mut self.rest, CPIO_HEADER_SIZE)?;
+        if !header.starts_with(CPIO_MAGIC) || !matches!(header[5], b'1' | b'2') {
+            return Err(ArchiveError::BadHeader);
+        }
+
+        let field = |i: usize| hex(&header[6 + i * 8..14 + i * 8]);
+        let mode = field(1)?;
+        let size = field(6)? as usize;
+        let name_size = field(11)? as usize;
+
+        let name = take(&mut self.rest, name_size)?;
+        let name = String::from(c_str(name)?);
+        let padding = align_up(CPIO_HEADER_SIZE + name_size, 4) - CPIO_HEADER_SIZE - name_size;
+        take(&mut self.rest, padding)?;
+
+        if name == CPIO_TRAILER {
+            return Ok(None);
+        }
+
+        let data = take(&mut self.rest, size)?;
+        let padding = align_up(size, 4) - size;
+        self.rest = self.rest.get(padding..).unwrap_or(&[]);
+
+        let kind = kind_of_mode(mode);
+        let link = match kind {
+            EntryKind::Symlink => String::from(c_str(data)?),
+            _ => String::new(),
+        };
+
+        Ok(Some(Entry {
+            name,
+            kind,
+            mode: mode & 0o7777,
+            uid: field(2)?,
+            gid: field(3)?,
+            mtime: field(5)? as u64,
+            link,
+            data,
+        }))
+    }
+}
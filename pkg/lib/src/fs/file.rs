@@ -0,0 +1,90 @@
+use alloc::vec::Vec;
+
+use crate::{
+    sys_close, sys_fstat, sys_open, sys_read, sys_seek, sys_write, write_all, O_CREAT, O_RDONLY,
+    O_TRUNC, O_WRONLY, SEEK_CUR, SEEK_END, SEEK_SET,
+};
+
+/// Where `File::seek` moves to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeekFrom {
+    Start(usize),
+    Current(isize),
+    End(isize),
+}
+
+/// A file of the VFS open by its path, closed when dropped
+///
+/// errors are the errno the kernel returned.
+#[derive(Debug)]
+pub struct File {
+    fd: u8,
+}
+
+impl File {
+    /// Open the file at `path` for reading
+    pub fn open(path: &str) -> Result<Self, usize> {
+        Self::open_with(path, O_RDONLY)
+    }
+
+    /// Open the file at `path` for writing, emptied or made if there is none
+    pub fn create(path: &str) -> Result<Self, usize> {
+        Self::open_with(path, O_WRONLY | O_CREAT | O_TRUNC)
+    }
+
+    /// Open the file at `path` with the `O_*` flags, see `sys_open`
+    pub fn open_with(path: &str, flags: usize) -> Result<Self, usize> {
+        sys_open(path, flags).map(|fd| Self { fd })
+    }
+
+    pub fn fd(&self) -> u8 {
+        self.fd
+    }
+
+    /// Read from the position, 0 bytes at the end
+    pub fn read(&mut self, buf: &mut [u8]) -> Option<usize> {
+        sys_read(self.fd, buf)
+    }
+
+    /// Read from the position to the end
+    pub fn read_to_end(&mut self) -> Option<Vec<u8>> {
+        let mut data = Vec::with_capacity(self.size().unwrap_or(0));
+        let mut buf = [0u8; 512];
+        loop {
+            match self.read(&mut buf)? {
+                0 => return Some(data),
+                count => data.extend_from_slice(&buf[..count]),
+            }
+        }
+    }
+
+    pub fn write(&mut self, buf: &[u8]) -> Option<usize> {
+        sys_write(self.fd, buf)
+    }
+
+    /// Write all of `buf`, return false if a write fails
+    pub fn write_all(&mut self, buf: &[u8]) -> bool {
+        write_all(self.fd, buf)
+    }
+
+    /// Move the position, return the new one
+    pub fn seek(&mut self, pos: SeekFrom) -> Result<usize, usize> {
+        let (offset, whence) = match pos {
+            SeekFrom::Start(offset) => (offset as isize, SEEK_SET),
+            SeekFrom::Current(offset) => (offset, SEEK_CUR),
+            SeekFrom::End(offset) => (offset, SEEK_END),
+        };
+        sys_seek(self.fd, offset, whence)
+    }
+
+    /// Bytes of the file
+    pub fn size(&self) -> Option<usize> {
+        sys_fstat(self.fd).map(|stat| stat.size as usize)
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        sys_close(self.fd);
+    }
+}
@@ -0,0 +1,8 @@
+//! Files of the VFS, and file formats and helpers for files
+//!
+//! the formats work on bytes already in memory, read with `File`.
+
+pub mod archive;
+mod file;
+
+pub use file::{File, SeekFrom};
@@ -0,0 +1,255 @@
+//! Drawing to pixels, on a framebuffer or an offscreen surface
+//!
+//! both are a `Canvas`, which clips what is drawn to its size.
+//! Text is drawn with the fonts of `text`, a terminal screen with `term`.
+
+pub mod term;
+pub mod text;
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const BLACK: Color = Color::new(0, 0, 0);
+    pub const WHITE: Color = Color::new(0xff, 0xff, 0xff);
+    pub const LIGHT_GRAY: Color = Color::new(0xaa, 0xaa, 0xaa);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// The color of `0xRRGGBB`
+    pub const fn from_rgb(rgb: u32) -> Self {
+        Self::new((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+    }
+
+    pub const fn to_rgb(self) -> u32 {
+        (self.r as u32) << 16 | (self.g as u32) << 8 | self.b as u32
+    }
+}
+
+/// A rectangle of pixels, `x` and `y` its top left corner
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+impl Rect {
+    pub const fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[inline]
+    pub fn contains(&self, x: usize, y: usize) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+
+    /// The part of this rectangle inside `other`, empty if none
+    pub fn intersect(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        let x_end = (self.x + self.width).min(other.x + other.width);
+        let y_end = (self.y + self.height).min(other.y + other.height);
+        Rect::new(x, y, x_end.saturating_sub(x), y_end.saturating_sub(y))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.width == 0 || self.height == 0
+    }
+}
+
+/// Something to draw pixels on
+///
+/// pixels outside of it are dropped, so callers need not clip.
+pub trait Canvas {
+    fn width(&self) -> usize;
+
+    fn height(&self) -> usize;
+
+    fn put_pixel(&mut self, x: usize, y: usize, color: Color);
+
+    /// The whole canvas as a rectangle
+    fn bounds(&self) -> Rect {
+        Rect::new(0, 0, self.width(), self.height())
+    }
+
+    /// Fill `rect`, clipped to the canvas
+    fn fill_rect(&mut self, rect: Rect, color: Color) {
+        let rect = rect.intersect(&self.bounds());
+        for y in rect.y..rect.y + rect.height {
+            for x in rect.x..rect.x + rect.width {
+                self.put_pixel(x, y, color);
+            }
+        }
+    }
+
+    fn clear(&mut self, color: Color) {
+        self.fill_rect(self.bounds(), color);
+    }
+}
+
+/// An offscreen canvas in memory, to be copied to another with `blit`
+pub struct Surface {
+    width: usize,
+    height: usize,
+    /// `0xRRGGBB` of each pixel, row by row
+    pixels: Vec<u32>,
+}
+
+impl Surface {
+    pub fn new(width: usize, height: usize, color: Color) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![color.to_rgb(); width * height],
+        }
+    }
+
+    pub fn get_pixel(&self, x: usize, y: usize) -> Option<Color> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        Some(Color::from_rgb(self.pixels[y * self.width + x]))
+    }
+
+    /// Move every pixel up `rows` rows, filling those left at the bottom
+    pub fn scroll_up(&mut self, rows: usize, fill: Color) {
+        let rows = rows.min(self.height);
+        self.pixels.copy_within(rows * self.width.., 0);
+        let kept = (self.height - rows) * self.width;
+        self.pixels[kept..].fill(fill.to_rgb());
+    }
+
+    /// Copy the pixels to `canvas` with the top left corner at `x`, `y`
+    pub fn blit(&self, canvas: &mut dyn Canvas, x: usize, y: usize) {
+        let dest = Rect::new(x, y, self.width, self.height).intersect(&canvas.bounds());
+        for dy in 0..dest.height {
+            let row = &self.pixels[dy * self.width..][..dest.width];
+            for (dx, &rgb) in row.iter().enumerate() {
+                canvas.put_pixel(dest.x + dx, dest.y + dy, Color::from_rgb(rgb));
+            }
+        }
+    }
+}
+
+impl Canvas for Surface {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    #[inline]
+    fn put_pixel(&mut self, x: usize, y: usize, color: Color) {
+        if x < self.width && y < self.height {
+            self.pixels[y * self.width + x] = color.to_rgb();
+        }
+    }
+}
+
+/// The order of the bytes of a 32 bit framebuffer pixel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb,
+    Bgr,
+}
+
+/// A 32 bit framebuffer mapped into the process
+pub struct FrameBuffer {
+    base: *mut u32,
+    width: usize,
+    height: usize,
+    /// pixels from the start of a row to the next, at least `width`
+    stride: usize,
+    format: PixelFormat,
+}
+
+impl FrameBuffer {
+    /// The framebuffer mapped at `base`
+    ///
+    /// # Safety
+    ///
+    /// `base` must be mapped writable for `stride * height` pixels for
+    /// as long as the framebuffer is used.
+    pub unsafe fn from_raw(
+        base: *mut u32,
+        width: usize,
+        height: usize,
+        stride: usize,
+        format: PixelFormat,
+    ) -> Self {
+        Self {
+            base,
+            width,
+            height,
+            stride: stride.max(width),
+            format,
+        }
+    }
+
+    /// The framebuffer of the screen, see `sys_map_frame_buffer`
+    ///
+    /// return the errno, `EBUSY` if another process has it.
+    pub fn map() -> Result<Self, usize> {
+        let info = crate::sys_map_frame_buffer()?;
+        let format = match info.format {
+            crate::FB_FORMAT_RGB => PixelFormat::Rgb,
+            _ => PixelFormat::Bgr,
+        };
+
+        // mapped for as long as the process lives
+        Ok(unsafe {
+            Self::from_raw(
+                info.addr as *mut u32,
+                info.width as usize,
+                info.height as usize,
+                info.stride as usize,
+                format,
+            )
+        })
+    }
+
+    #[inline]
+    fn encode(&self, color: Color) -> u32 {
+        let (r, g, b) = (color.r as u32, color.g as u32, color.b as u32);
+        match self.format {
+            PixelFormat::Rgb => r | g << 8 | b << 16,
+            PixelFormat::Bgr => b | g << 8 | r << 16,
+        }
+    }
+}
+
+impl Canvas for FrameBuffer {
+    fn width(&self) -> usize {
+        self.width
+    }
+
+    fn height(&self) -> usize {
+        self.height
+    }
+
+    #[inline]
+    fn put_pixel(&mut self, x: usize, y: usize, color: Color) {
+        if x < self.width && y < self.height {
+            let pixel = self.encode(color);
+            unsafe { self.base.add(y * self.stride + x).write_volatile(pixel) };
+        }
+    }
+}
@@ -0,0 +1,370 @@
+//! A terminal screen drawn on a surface, for terminal emulators
+//!
+//! what a program writes to it is UTF-8 text with the ANSI escape
+//! sequences the kernel console knows: cursor movement, erasing and
+//! SGR colors. The cells are drawn on the `Surface` of the terminal as
+//! they change, to be copied wherever the terminal is shown.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use super::text::{Font, TextStyle};
+use super::{Canvas, Color, Surface};
+
+const TAB_WIDTH: usize = 8;
+/// Parameters of an escape sequence kept, the rest are ignored
+const MAX_PARAMS: usize = 8;
+
+/// The 16 colors of ANSI escape codes, as VGA shows them
+pub const PALETTE: [Color; 16] = [
+    Color::new(0x00, 0x00, 0x00),
+    Color::new(0xaa, 0x00, 0x00),
+    Color::new(0x00, 0xaa, 0x00),
+    Color::new(0xaa, 0x55, 0x00),
+    Color::new(0x00, 0x00, 0xaa),
+    Color::new(0xaa, 0x00, 0xaa),
+    Color::new(0x00, 0xaa, 0xaa),
+    Color::new(0xaa, 0xaa, 0xaa),
+    Color::new(0x55, 0x55, 0x55),
+    Color::new(0xff, 0x55, 0x55),
+    Color::new(0x55, 0xff, 0x55),
+    Color::new(0xff, 0xff, 0x55),
+    Color::new(0x55, 0x55, 0xff),
+    Color::new(0xff, 0x55, 0xff),
+    Color::new(0x55, 0xff, 0xff),
+    Color::new(0xff, 0xff, 0xff),
+];
+
+const DEFAULT_FG: u8 = 7;
+const DEFAULT_BG: u8 = 0;
+
+/// A character and its colors, as indices into the palette
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    ch: char,
+    fg: u8,
+    bg: u8,
+}
+
+impl Cell {
+    /// An erased cell keeps the background color in use
+    const fn blank(bg: u8) -> Self {
+        Self {
+            ch: ' ',
+            fg: DEFAULT_FG,
+            bg,
+        }
+    }
+}
+
+/// The colors set by SGR escape sequences
+#[derive(Clone, Copy)]
+struct Attr {
+    fg: u8,
+    bg: u8,
+    bold: bool,
+}
+
+impl Attr {
+    const DEFAULT: Attr = Attr {
+        fg: DEFAULT_FG,
+        bg: DEFAULT_BG,
+        bold: false,
+    };
+
+    fn cell(&self, ch: char) -> Cell {
+        // bold is drawn as the bright variant
+        let fg = if self.bold && self.fg < 8 {
+            self.fg + 8
+        } else {
+            self.fg
+        };
+        Cell {
+            ch,
+            fg,
+            bg: self.bg,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum State {
+    Normal,
+    /// after ESC
+    Escape,
+    /// after ESC [, until the final byte
+    Csi,
+}
+
+pub struct Terminal {
+    surface: Surface,
+    font: Font,
+    cols: usize,
+    rows: usize,
+    /// `rows * cols` cells
+    screen: Vec<Cell>,
+    col: usize,
+    row: usize,
+    attr: Attr,
+    state: State,
+    params: [u16; MAX_PARAMS],
+    /// the parameter being read
+    param: usize,
+    /// the bytes of a UTF-8 sequence read so far
+    utf8: [u8; 4],
+    utf8_len: usize,
+}
+
+impl Terminal {
+    /// A terminal of `cols` by `rows` cells of `font`
+    pub fn new(font: Font, cols: usize, rows: usize) -> Self {
+        let (cols, rows) = (cols.max(1), rows.max(1));
+        let surface = Surface::new(
+            cols * font.width,
+            rows * font.height,
+            PALETTE[DEFAULT_BG as usize],
+        );
+
+        let mut term = Self {
+            surface,
+            font,
+            cols,
+            rows,
+            screen: vec![Cell::blank(DEFAULT_BG); cols * rows],
+            col: 0,
+            row: 0,
+            attr: Attr::DEFAULT,
+            state: State::Normal,
+            params: [0; MAX_PARAMS],
+            param: 0,
+            utf8: [0; 4],
+            utf8_len: 0,
+        };
+        term.draw_cursor(true);
+        term
+    }
+
+    /// The columns and rows of cells
+    #[inline]
+    pub fn size(&self) -> (usize, usize) {
+        (self.cols, self.rows)
+    }
+
+    /// Where the terminal is drawn, to copy to the screen with `Surface::blit`
+    #[inline]
+    pub fn surface(&self) -> &Surface {
+        &self.surface
+    }
+
+    /// Take what a program wrote, drawing it as it goes
+    pub fn write(&mut self, bytes: &[u8]) {
+        self.draw_cursor(false);
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+        self.draw_cursor(true);
+    }
+
+    fn draw(&mut self, col: usize, row: usize, cell: Cell, inverted: bool) {
+        let (mut fg, mut bg) = (PALETTE[cell.fg as usize], PALETTE[cell.bg as usize]);
+        if inverted {
+            core::mem::swap(&mut fg, &mut bg);
+        }
+
+        let style = TextStyle::new(fg).with_bg(bg);
+        let (x, y) = (col * self.font.width, row * self.font.height);
+        self.font
+            .draw_char(&mut self.surface, x, y, cell.ch, &style);
+    }
+
+    fn draw_at(&mut self, index: usize) {
+        let cell = self.screen[index];
+        self.draw(index % self.cols, index / self.cols, cell, false);
+    }
+
+    /// The cursor is drawn inverted, at the last column while a wrap is pending
+    fn draw_cursor(&mut self, shown: bool) {
+        let col = self.col.min(self.cols - 1);
+        let cell = self.screen[self.row * self.cols + col];
+        self.draw(col, self.row, cell, shown);
+    }
+
+    fn erase(&mut self, start: usize, end: usize) {
+        let blank = Cell::blank(self.attr.bg);
+        for index in start..end {
+            self.screen[index] = blank;
+            self.draw_at(index);
+        }
+    }
+
+    /// Move the screen up a line
+    fn scroll(&mut self) {
+        self.screen.copy_within(self.cols.., 0);
+        let blank = Cell::blank(self.attr.bg);
+        let last = self.screen.len() - self.cols;
+        self.screen[last..].fill(blank);
+
+        self.surface
+            .scroll_up(self.font.height, PALETTE[blank.bg as usize]);
+    }
+
+    fn new_line(&mut self) {
+        self.col = 0;
+        if self.row + 1 < self.rows {
+            self.row += 1;
+        } else {
+            self.scroll();
+        }
+    }
+
+    fn put(&mut self, ch: char) {
+        if self.col >= self.cols {
+            self.new_line();
+        }
+
+        let index = self.row * self.cols + self.col;
+        self.screen[index] = self.attr.cell(ch);
+        self.draw_at(index);
+        self.col += 1;
+    }
+
+    fn write_byte(&mut self, byte: u8) {
+        match self.state {
+            State::Normal => self.write_normal(byte),
+            State::Escape => {
+                self.state = match byte {
+                    b'[' => {
+                        self.params = [0; MAX_PARAMS];
+                        self.param = 0;
+                        State::Csi
+                    }
+                    _ => State::Normal,
+                }
+            }
+            State::Csi => match byte {
+                b'0'..=b'9' => {
+                    if let Some(param) = self.params.get_mut(self.param) {
+                        *param = param
+                            .saturating_mul(10)
+                            .saturating_add((byte - b'0') as u16);
+                    }
+                }
+                b';' => self.param += 1,
+                0x40..=0x7e => {
+                    self.state = State::Normal;
+                    self.execute(byte);
+                }
+                // private markers like `?` and intermediate bytes
+                _ => {}
+            },
+        }
+    }
+
+    fn write_normal(&mut self, byte: u8) {
+        if byte >= 0x80 {
+            return self.write_utf8(byte);
+        }
+        self.utf8_len = 0;
+
+        match byte {
+            0x1b => self.state = State::Escape,
+            b'\n' => self.new_line(),
+            b'\r' => self.col = 0,
+            0x08 => self.col = self.col.saturating_sub(1),
+            b'\t' => {
+                self.col = ((self.col / TAB_WIDTH + 1) * TAB_WIDTH).min(self.cols - 1);
+            }
+            0x00..=0x1f | 0x7f => {}
+            byte => self.put(byte as char),
+        }
+    }
+
+    /// Gather a UTF-8 sequence, a bad one is drawn as `?`
+    fn write_utf8(&mut self, byte: u8) {
+        let is_lead = byte >= 0xc0;
+        if is_lead || self.utf8_len == 0 || self.utf8_len == self.utf8.len() {
+            if self.utf8_len != 0 || !is_lead {
+                self.put('?');
+            }
+            self.utf8_len = 0;
+            if !is_lead {
+                return;
+            }
+        }
+
+        self.utf8[self.utf8_len] = byte;
+        self.utf8_len += 1;
+
+        let need = match self.utf8[0] {
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            _ => 4,
+        };
+        if self.utf8_len == need {
+            let ch = core::str::from_utf8(&self.utf8[..need])
+                .ok()
+                .and_then(|s| s.chars().next())
+                .unwrap_or('?');
+            self.utf8_len = 0;
+            self.put(ch);
+        }
+    }
+
+    /// Parameter `index`, `default` when it is left out or 0
+    fn param(&self, index: usize, default: usize) -> usize {
+        match self.params.get(index) {
+            Some(&value) if value != 0 => value as usize,
+            _ => default,
+        }
+    }
+
+    /// Run the escape sequence ended by `action`
+    fn execute(&mut self, action: u8) {
+        let cursor = self.row * self.cols + self.col.min(self.cols - 1);
+
+        match action {
+            b'm' => self.select_graphic(),
+            b'H' | b'f' => {
+                self.row = (self.param(0, 1) - 1).min(self.rows - 1);
+                self.col = (self.param(1, 1) - 1).min(self.cols - 1);
+            }
+            b'A' => self.row = self.row.saturating_sub(self.param(0, 1)),
+            b'B' => self.row = (self.row + self.param(0, 1)).min(self.rows - 1),
+            b'C' => self.col = (self.col + self.param(0, 1)).min(self.cols - 1),
+            b'D' => self.col = self.col.saturating_sub(self.param(0, 1)),
+            b'J' => match self.param(0, 0) {
+                0 => self.erase(cursor, self.screen.len()),
+                1 => self.erase(0, cursor + 1),
+                _ => self.erase(0, self.screen.len()),
+            },
+            b'K' => {
+                let line = self.row * self.cols;
+                match self.param(0, 0) {
+                    0 => self.erase(cursor, line + self.cols),
+                    1 => self.erase(line, cursor + 1),
+                    _ => self.erase(line, line + self.cols),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// SGR, `ESC [ ... m`, colors and bold
+    fn select_graphic(&mut self) {
+        for index in 0..=self.param.min(MAX_PARAMS - 1) {
+            match self.params[index] {
+                0 => self.attr = Attr::DEFAULT,
+                1 => self.attr.bold = true,
+                22 => self.attr.bold = false,
+                7 => core::mem::swap(&mut self.attr.fg, &mut self.attr.bg),
+                code @ 30..=37 => self.attr.fg = (code - 30) as u8,
+                39 => self.attr.fg = DEFAULT_FG,
+                code @ 40..=47 => self.attr.bg = (code - 40) as u8,
+                49 => self.attr.bg = DEFAULT_BG,
+                code @ 90..=97 => self.attr.fg = (code - 90) as u8 + 8,
+                code @ 100..=107 => self.attr.bg = (code - 100) as u8 + 8,
+                _ => {}
+            }
+        }
+    }
+}
@@ -0,0 +1,289 @@
+//! Bitmap fonts in the PSF1 and PSF2 formats of the Linux console
+//!
+//! a font is read whole into memory, from the VFS with `Font::load`.
+//! Glyphs are rows of bits, the most significant bit is leftmost, each
+//! row padded to a byte. With a unicode table a char is found through
+//! it, otherwise a char is the index of its glyph. What the font lacks
+//! is drawn as `?`.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use super::{Canvas, Color, Rect};
+use crate::errno::EINVAL;
+use crate::fs::File;
+
+/// The font the kernel console uses, on the boot partition
+pub const DEFAULT_FONT: &str = "/boot/FONTS/DEFAULT.PSF";
+
+const PSF1_MAGIC: [u8; 2] = [0x36, 0x04];
+const PSF1_HEADER_SIZE: usize = 4;
+/// The font has 512 glyphs, not 256
+const PSF1_MODE512: u8 = 0x01;
+const PSF1_MODEHASTAB: u8 = 0x02;
+const PSF1_MODESEQ: u8 = 0x04;
+const PSF1_SEPARATOR: u16 = 0xffff;
+const PSF1_STARTSEQ: u16 = 0xfffe;
+
+const PSF2_MAGIC: u32 = 0x864a_b572;
+const PSF2_HEADER_SIZE: usize = 32;
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+const PSF2_SEPARATOR: u8 = 0xff;
+const PSF2_STARTSEQ: u8 = 0xfe;
+
+/// How text is drawn
+#[derive(Clone, Copy, Debug)]
+pub struct TextStyle {
+    pub fg: Color,
+    /// the background of each glyph, transparent if `None`
+    pub bg: Option<Color>,
+    /// nothing is drawn outside of it, if set
+    pub clip: Option<Rect>,
+}
+
+impl TextStyle {
+    pub const fn new(fg: Color) -> Self {
+        Self {
+            fg,
+            bg: None,
+            clip: None,
+        }
+    }
+
+    pub const fn with_bg(self, bg: Color) -> Self {
+        Self {
+            bg: Some(bg),
+            ..self
+        }
+    }
+
+    pub const fn with_clip(self, clip: Rect) -> Self {
+        Self {
+            clip: Some(clip),
+            ..self
+        }
+    }
+}
+
+pub struct Font {
+    pub width: usize,
+    pub height: usize,
+    bytes_per_row: usize,
+    glyph_size: usize,
+    count: usize,
+    /// where the glyphs start in `data`
+    offset: usize,
+    data: Vec<u8>,
+    /// the glyph of each char, empty without a unicode table
+    unicode: BTreeMap<char, usize>,
+}
+
+impl Font {
+    /// Read the font file at `path`
+    ///
+    /// return the errno of the read, or `EINVAL` if it is not a PSF font.
+    pub fn load(path: &str) -> Result<Self, usize> {
+        let data = File::open(path)?.read_to_end().ok_or(EINVAL)?;
+        Self::parse(data).ok_or(EINVAL)
+    }
+
+    /// Parse a PSF1 or PSF2 font, `None` if it is neither
+    pub fn parse(data: Vec<u8>) -> Option<Self> {
+        if data.starts_with(&PSF1_MAGIC) {
+            Self::parse_psf1(data)
+        } else {
+            Self::parse_psf2(data)
+        }
+    }
+
+    fn parse_psf1(data: Vec<u8>) -> Option<Self> {
+        let mode = *data.get(2)?;
+        let height = *data.get(3)? as usize;
+        let count = if mode & PSF1_MODE512 != 0 { 512 } else { 256 };
+
+        let end = PSF1_HEADER_SIZE + count * height;
+        if data.len() < end || height == 0 {
+            return None;
+        }
+
+        let mut unicode = BTreeMap::new();
+        if mode & (PSF1_MODEHASTAB | PSF1_MODESEQ) != 0 {
+            let mut entries = data[end..]
+                .as_chunks::<2>()
+                .0
+                .iter()
+                .map(|&pair| u16::from_le_bytes(pair));
+            for glyph in 0..count {
+                let mut in_sequence = false;
+                for entry in entries.by_ref() {
+                    match entry {
+                        PSF1_SEPARATOR => break,
+                        PSF1_STARTSEQ => in_sequence = true,
+                        _ if in_sequence => (),
+                        code => {
+                            if let Some(ch) = char::from_u32(code as u32) {
+                                unicode.entry(ch).or_insert(glyph);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(Self {
+            width: 8,
+            height,
+            bytes_per_row: 1,
+            glyph_size: height,
+            count,
+            offset: PSF1_HEADER_SIZE,
+            data,
+            unicode,
+        })
+    }
+
+    fn parse_psf2(data: Vec<u8>) -> Option<Self> {
+        let field = |i: usize| -> Option<usize> {
+            let bytes = data.get(i * 4..i * 4 + 4)?;
+            Some(u32::from_le_bytes(bytes.try_into().ok()?) as usize)
+        };
+
+        if field(0)? != PSF2_MAGIC as usize {
+            return None;
+        }
+
+        let offset = field(2)?.max(PSF2_HEADER_SIZE);
+        let flags = field(3)? as u32;
+        let count = field(4)?;
+        let glyph_size = field(5)?;
+        let height = field(6)?;
+        let width = field(7)?;
+
+        let bytes_per_row = width.div_ceil(8);
+        let end = offset.checked_add(count.checked_mul(glyph_size)?)?;
+        if data.len() < end || width == 0 || glyph_size < bytes_per_row * height {
+            return None;
+        }
+
+        let mut unicode = BTreeMap::new();
+        if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+            let mut entries = data[end..].split(|&b| b == PSF2_SEPARATOR);
+            for glyph in 0..count {
+                let Some(entry) = entries.next() else {
+                    break;
+                };
+                // sequences of several chars follow the single ones
+                let singles = entry.split(|&b| b == PSF2_STARTSEQ).next().unwrap_or(&[]);
+                for ch in core::str::from_utf8(singles).unwrap_or_default().chars() {
+                    unicode.entry(ch).or_insert(glyph);
+                }
+            }
+        }
+
+        Some(Self {
+            width,
+            height,
+            bytes_per_row,
+            glyph_size,
+            count,
+            offset,
+            data,
+            unicode,
+        })
+    }
+
+    /// The glyph of `ch`, if the font has one
+    fn index(&self, ch: char) -> Option<usize> {
+        let index = match self.unicode.is_empty() {
+            true => ch as usize,
+            false => *self.unicode.get(&ch)?,
+        };
+        (index < self.count).then_some(index)
+    }
+
+    /// The rows of `ch`, a `?` for what the font lacks
+    pub fn glyph(&self, ch: char) -> &[u8] {
+        let index = self.index(ch).or(self.index('?')).unwrap_or(0);
+        let start = self.offset + index * self.glyph_size;
+        &self.data[start..start + self.glyph_size]
+    }
+
+    /// Check if the font has a glyph for `ch`
+    pub fn has_glyph(&self, ch: char) -> bool {
+        self.index(ch).is_some()
+    }
+
+    /// The width and height `text` takes, its lines split at `\n`
+    pub fn measure(&self, text: &str) -> (usize, usize) {
+        let lines = text.split('\n');
+        let (count, longest) = lines.fold((0, 0), |(count, longest), line| {
+            (count + 1, longest.max(line.chars().count()))
+        });
+        (longest * self.width, count * self.height)
+    }
+
+    /// Draw `ch` with its top left corner at `x`, `y`
+    pub fn draw_char(
+        &self,
+        canvas: &mut dyn Canvas,
+        x: usize,
+        y: usize,
+        ch: char,
+        style: &TextStyle,
+    ) {
+        let cell = Rect::new(x, y, self.width, self.height);
+        let clip = match style.clip {
+            Some(clip) => cell.intersect(&clip),
+            None => cell,
+        }
+        .intersect(&canvas.bounds());
+        if clip.is_empty() {
+            return;
+        }
+
+        let glyph = self.glyph(ch);
+        for (dy, row) in glyph
+            .chunks(self.bytes_per_row)
+            .take(self.height)
+            .enumerate()
+        {
+            for dx in 0..self.width {
+                if !clip.contains(x + dx, y + dy) {
+                    continue;
+                }
+                match row[dx / 8] & (0x80 >> (dx % 8)) != 0 {
+                    true => canvas.put_pixel(x + dx, y + dy, style.fg),
+                    false => {
+                        if let Some(bg) = style.bg {
+                            canvas.put_pixel(x + dx, y + dy, bg);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Draw `text` from `x`, `y`, a `\n` starts a line below at `x`
+    ///
+    /// return where the next char would go.
+    pub fn draw_str(
+        &self,
+        canvas: &mut dyn Canvas,
+        x: usize,
+        y: usize,
+        text: &str,
+        style: &TextStyle,
+    ) -> (usize, usize) {
+        let (mut cx, mut cy) = (x, y);
+        for ch in text.chars() {
+            if ch == '\n' {
+                cx = x;
+                cy += self.height;
+                continue;
+            }
+            self.draw_char(canvas, cx, cy, ch, style);
+            cx += self.width;
+        }
+        (cx, cy)
+    }
+}
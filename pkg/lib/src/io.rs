@@ -1,6 +1,240 @@
 use crate::*;
 use alloc::string::{String, ToString};
 use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+
+/// Size of the stdout buffer, longer lines are written in pieces
+pub const STDOUT_BUF_SIZE: usize = 1024;
+/// Default capacity of a `BufWriter`
+pub const BUF_SIZE: usize = 4096;
+
+/// Write all of `buf` to `fd`, waiting for its rate limit when needed
+///
+/// return false if the write fails
+pub fn write_all(fd: u8, mut buf: &[u8]) -> bool {
+    while !buf.is_empty() {
+        match sys_write(fd, buf) {
+            Some(0) => sys_yield(),
+            Some(count) => buf = &buf[count..],
+            None => return false,
+        }
+    }
+    true
+}
+
+/// Write all of `bufs` to `fd` in order, with a syscall for as many
+/// of them as the kernel takes at once
+///
+/// return false if the write fails
+pub fn write_all_vectored(fd: u8, bufs: &[&[u8]]) -> bool {
+    let mut bufs: Vec<&[u8]> = bufs.iter().copied().filter(|buf| !buf.is_empty()).collect();
+    let mut first = 0;
+
+    while first < bufs.len() {
+        let iov: Vec<IoVec> = bufs[first..].iter().map(|buf| IoVec::new(buf)).collect();
+        match sys_writev(fd, &iov) {
+            Some(0) => sys_yield(),
+            Some(mut count) => {
+                // drop what was written, the last of it may be cut short
+                while count > 0 {
+                    let len = bufs[first].len();
+                    if count < len {
+                        bufs[first] = &bufs[first][count..];
+                        break;
+                    }
+                    count -= len;
+                    first += 1;
+                }
+            }
+            None => return false,
+        }
+    }
+    true
+}
+
+/// When a `BufWriter` hands its data to the kernel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufMode {
+    /// when a newline is written or the buffer is full
+    Line,
+    /// only when the buffer is full
+    Block,
+}
+
+/// Collects small writes to an fd into fewer syscalls
+///
+/// the rest of the data is written when it is dropped.
+pub struct BufWriter {
+    fd: u8,
+    mode: BufMode,
+    buf: Vec<u8>,
+}
+
+impl BufWriter {
+    /// A block buffered writer of `BUF_SIZE` bytes
+    pub fn new(fd: u8) -> Self {
+        Self::with_capacity(fd, BUF_SIZE, BufMode::Block)
+    }
+
+    pub fn with_capacity(fd: u8, capacity: usize, mode: BufMode) -> Self {
+        Self {
+            fd,
+            mode,
+            buf: Vec::with_capacity(capacity.max(1)),
+        }
+    }
+
+    pub fn write(&mut self, bytes: &[u8]) {
+        // too long to buffer, goes out with what is buffered in one syscall
+        if bytes.len() >= self.buf.capacity() {
+            write_all_vectored(self.fd, &[&self.buf, bytes]);
+            self.buf.clear();
+            return;
+        }
+
+        if self.buf.len() + bytes.len() > self.buf.capacity() {
+            self.flush();
+        }
+
+        self.buf.extend_from_slice(bytes);
+        if self.mode == BufMode::Line && bytes.contains(&b'\n') {
+            self.flush();
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if !self.buf.is_empty() {
+            write_all(self.fd, &self.buf);
+            self.buf.clear();
+        }
+    }
+}
+
+impl Drop for BufWriter {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// The line buffer behind `print!`
+///
+/// a static array instead of a `BufWriter`, since it must work before
+/// anything is allocated. Forked processes get a copy of it, so
+/// `sys_fork` flushes it first.
+struct StdoutBuffer {
+    lock: SpinLock,
+    inner: UnsafeCell<(usize, [u8; STDOUT_BUF_SIZE])>,
+}
+
+unsafe impl Sync for StdoutBuffer {}
+
+static STDOUT_BUFFER: StdoutBuffer = StdoutBuffer {
+    lock: SpinLock::new(),
+    inner: UnsafeCell::new((0, [0; STDOUT_BUF_SIZE])),
+};
+
+impl StdoutBuffer {
+    fn write(&self, mut bytes: &[u8]) {
+        self.lock.acquire();
+        let (len, buf) = unsafe { &mut *self.inner.get() };
+
+        while !bytes.is_empty() {
+            let count = bytes.len().min(STDOUT_BUF_SIZE - *len);
+            buf[*len..*len + count].copy_from_slice(&bytes[..count]);
+            *len += count;
+
+            if *len == STDOUT_BUF_SIZE || bytes[..count].contains(&b'\n') {
+                write_all(1, &buf[..*len]);
+                *len = 0;
+            }
+            bytes = &bytes[count..];
+        }
+
+        self.lock.release();
+    }
+
+    fn flush(&self) {
+        self.lock.acquire();
+        let (len, buf) = unsafe { &mut *self.inner.get() };
+        if *len > 0 {
+            write_all(1, &buf[..*len]);
+            *len = 0;
+        }
+        self.lock.release();
+    }
+}
+
+/// Write out whatever `print!` has buffered
+///
+/// registered as an exit hook by `lib::init` and in forked children.
+pub fn flush_stdout() {
+    STDOUT_BUFFER.flush();
+}
+
+/// Reads the lines of a fd as they come, for pipes, files and ptys
+///
+/// a line ends at `\n`, which is not kept, and a read of 0 bytes is the
+/// end of the input. Unlike `Stdin::read_line`, nothing is echoed.
+pub struct LineReader {
+    fd: u8,
+    buf: Vec<u8>,
+    eof: bool,
+}
+
+impl LineReader {
+    pub fn new(fd: u8) -> Self {
+        Self {
+            fd,
+            buf: Vec::new(),
+            eof: false,
+        }
+    }
+
+    /// The next line, `None` at the end of the input or once a read fails
+    pub fn read_line(&mut self) -> Option<String> {
+        loop {
+            if let Some(end) = self.buf.iter().position(|&c| c == b'\n') {
+                let line = String::from_utf8_lossy(&self.buf[..end]).into_owned();
+                self.buf.drain(..=end);
+                return Some(line);
+            }
+
+            if self.eof {
+                // the last line may have no newline
+                if self.buf.is_empty() {
+                    return None;
+                }
+                let line = String::from_utf8_lossy(&self.buf).into_owned();
+                self.buf.clear();
+                return Some(line);
+            }
+
+            let mut chunk = [0u8; 256];
+            match sys_read(self.fd, &mut chunk) {
+                Some(0) | None => self.eof = true,
+                Some(count) => self.buf.extend_from_slice(&chunk[..count]),
+            }
+        }
+    }
+}
+
+/// The lines of stdin, see `Stdin::lines`
+pub struct Lines {
+    /// `None` on the console
+    reader: Option<LineReader>,
+}
+
+impl Iterator for Lines {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        match self.reader.as_mut() {
+            Some(reader) => reader.read_line(),
+            None => stdin().try_read_line().filter(|line| !line.is_empty()),
+        }
+    }
+}
 
 pub struct Stdin;
 pub struct Stdout;
@@ -11,7 +245,39 @@ impl Stdin {
         Self
     }
 
+    /// Whether stdin is the console, where `read_line` echoes and edits
+    /// what is typed, and there is no end of the input
+    pub fn is_console(&self) -> bool {
+        sys_fstat(0).is_some_and(|stat| stat.kind == FD_KIND_CONSOLE)
+    }
+
+    /// The lines of stdin, edited on the console until an empty line or
+    /// Ctrl+D, otherwise read by a `LineReader` to the end of the input
+    pub fn lines(&self) -> Lines {
+        Lines {
+            reader: (!self.is_console()).then(|| LineReader::new(0)),
+        }
+    }
+
+    /// Read a line, empty at the end of the input, see `try_read_line`
     pub fn read_line(&self) -> String {
+        self.try_read_line().unwrap_or_default()
+    }
+
+    /// Read a line without its newline, `None` at the end of the input
+    ///
+    /// on the console what is typed is echoed and backspace erases, and
+    /// Ctrl+D on an empty line ends the input. Otherwise the bytes are
+    /// read as they are, one at a time so none past the line are taken,
+    /// and reading 0 bytes is the end.
+    pub fn try_read_line(&self) -> Option<String> {
+        // show the prompt before waiting for input
+        flush_stdout();
+
+        if !self.is_console() {
+            return read_raw_line(0);
+        }
+
         // allocate string
         let mut line = String::new();
 
@@ -22,6 +288,9 @@ impl Stdin {
 
             if ret.is_none() {
                 continue;
+            } else if ret == Some(0) {
+                // no input yet, let others run
+                sys_yield();
             } else {
                 for i in 0..ret.unwrap() {
                     let c = buf[i];
@@ -29,8 +298,9 @@ impl Stdin {
                     match c {
                         13 => {
                             sys_write(1, "\n".as_bytes());
-                            return line;
+                            return Some(line);
                         }
+                        0x04 if line.is_empty() => return None,
                         0x08 | 0x7F => {
                             line.pop();
                             sys_write(1, "\x08\x20\x08".as_bytes());
@@ -46,13 +316,33 @@ impl Stdin {
     }
 }
 
+/// Read a line of `fd` a byte at a time, `None` if it is at its end
+fn read_raw_line(fd: u8) -> Option<String> {
+    let mut line = Vec::new();
+    let mut c = [0u8];
+    loop {
+        match sys_read(fd, &mut c) {
+            Some(1) if c[0] == b'\n' => break,
+            Some(1) => line.push(c[0]),
+            // the last line may have no newline
+            _ if !line.is_empty() => break,
+            _ => return None,
+        }
+    }
+    Some(String::from_utf8_lossy(&line).into_owned())
+}
+
 impl Stdout {
     fn new() -> Self {
         Self
     }
 
     pub fn write(&self, s: &str) {
-        sys_write(1, s.as_bytes());
+        STDOUT_BUFFER.write(s.as_bytes());
+    }
+
+    pub fn flush(&self) {
+        STDOUT_BUFFER.flush();
     }
 }
 
@@ -62,6 +352,8 @@ impl Stderr {
     }
 
     pub fn write(&self, s: &str) {
+        // keep the order of output to the same console
+        flush_stdout();
         sys_write(2, s.as_bytes());
     }
 }
@@ -0,0 +1,480 @@
+//! Typed channels between forked processes, and message queues
+//!
+//! a forked child gets a copy of the memory of its parent, not a share
+//! of it, so a channel is a ring of message slots in a shared mapping,
+//! guarded by semaphores. Messages are encoded in a compact varint
+//! format, so the same encoding can go over a byte stream.
+//!
+//! `MessageQueue` is kept by the kernel instead, named by a key any
+//! process can open, so it also works between processes that are not
+//! related. Its messages are bytes, or values encoded as `Message`.
+
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::marker::PhantomData;
+use core::mem::size_of;
+use core::sync::atomic::{AtomicU32, Ordering};
+
+use crate::{
+    sys_get_pid, sys_mmap, sys_mq_open, sys_mq_recv, sys_mq_send, sys_mq_unlink, sys_munmap,
+    SemError, Semaphore, MAP_ANONYMOUS, MAP_SHARED, PROT_READ, PROT_WRITE,
+};
+
+/// Semaphore keys used by channels, three for each
+const KEY_BASE: u32 = 0x1bc0_0000;
+static NEXT_KEY: AtomicU32 = AtomicU32::new(KEY_BASE);
+
+/// A value that can be sent over a channel
+pub trait Message: Sized {
+    fn encode(&self, buf: &mut Vec<u8>);
+
+    /// Read a value from the front of `buf`, advancing it
+    fn decode(buf: &mut &[u8]) -> Option<Self>;
+}
+
+fn encode_varint(mut n: u64, buf: &mut Vec<u8>) {
+    while n >= 0x80 {
+        buf.push(n as u8 | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+fn decode_varint(buf: &mut &[u8]) -> Option<u64> {
+    let mut n = 0u64;
+    for shift in (0..64).step_by(7) {
+        let (&byte, rest) = buf.split_first()?;
+        *buf = rest;
+        n |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(n);
+        }
+    }
+    None
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if buf.len() < len {
+        return None;
+    }
+    let (head, rest) = buf.split_at(len);
+    *buf = rest;
+    Some(head)
+}
+
+macro_rules! impl_unsigned {
+    ($($t:ty),*) => {$(
+        impl Message for $t {
+            fn encode(&self, buf: &mut Vec<u8>) {
+                encode_varint(*self as u64, buf);
+            }
+
+            fn decode(buf: &mut &[u8]) -> Option<Self> {
+                <$t>::try_from(decode_varint(buf)?).ok()
+            }
+        }
+    )*};
+}
+
+// signed values are zigzag encoded, so small negative values stay short
+macro_rules! impl_signed {
+    ($($t:ty),*) => {$(
+        impl Message for $t {
+            fn encode(&self, buf: &mut Vec<u8>) {
+                let n = *self as i64;
+                encode_varint(((n << 1) ^ (n >> 63)) as u64, buf);
+            }
+
+            fn decode(buf: &mut &[u8]) -> Option<Self> {
+                let n = decode_varint(buf)?;
+                <$t>::try_from((n >> 1) as i64 ^ -((n & 1) as i64)).ok()
+            }
+        }
+    )*};
+}
+
+impl_unsigned!(u8, u16, u32, u64, usize);
+impl_signed!(i8, i16, i32, i64, isize);
+
+impl Message for bool {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.push(*self as u8);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        match u8::decode(buf)? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+}
+
+impl Message for char {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        (*self as u32).encode(buf);
+    }
+
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        char::from_u32(u32::decode(buf)?)
+    }
+}
+
+impl Message for f64 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.to_le_bytes());
+    }
+
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        Some(f64::from_le_bytes(take(buf, 8)?.try_into().ok()?))
+    }
+}
+
+impl Message for () {
+    fn encode(&self, _buf: &mut Vec<u8>) {}
+
+    fn decode(_buf: &mut &[u8]) -> Option<Self> {
+        Some(())
+    }
+}
+
+impl Message for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        buf.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        let len = usize::decode(buf)?;
+        String::from_utf8(take(buf, len)?.to_vec()).ok()
+    }
+}
+
+impl<T: Message> Message for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.len().encode(buf);
+        self.iter().for_each(|item| item.encode(buf));
+    }
+
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        let len = usize::decode(buf)?;
+        // every item takes at least a byte, do not trust `len` further
+        let mut items = Vec::with_capacity(len.min(buf.len()));
+        for _ in 0..len {
+            items.push(T::decode(buf)?);
+        }
+        Some(items)
+    }
+}
+
+impl<T: Message> Message for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            None => buf.push(0),
+            Some(value) => {
+                buf.push(1);
+                value.encode(buf);
+            }
+        }
+    }
+
+    fn decode(buf: &mut &[u8]) -> Option<Self> {
+        match u8::decode(buf)? {
+            0 => Some(None),
+            1 => Some(Some(T::decode(buf)?)),
+            _ => None,
+        }
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($name:ident)+) => {
+        impl<$($name: Message),+> Message for ($($name,)+) {
+            #[allow(non_snake_case)]
+            fn encode(&self, buf: &mut Vec<u8>) {
+                let ($($name,)+) = self;
+                $($name.encode(buf);)+
+            }
+
+            fn decode(buf: &mut &[u8]) -> Option<Self> {
+                Some(($($name::decode(buf)?,)+))
+            }
+        }
+    };
+}
+
+impl_tuple!(A);
+impl_tuple!(A B);
+impl_tuple!(A B C);
+impl_tuple!(A B C D);
+
+/// Bytes of the longest message a channel carries, once encoded
+pub const MESSAGE_MAX: usize = 1020;
+
+/// A message in the ring of a channel
+#[repr(C)]
+struct Slot {
+    len: u32,
+    bytes: [u8; MESSAGE_MAX],
+}
+
+/// The start of the mapping of a channel, its slots follow
+#[repr(C)]
+struct Ring {
+    /// the slot of the next message to receive
+    head: usize,
+    /// messages in the ring
+    len: usize,
+}
+
+/// The ring of a channel and the semaphores that guard it
+struct Shared {
+    ring: *mut Ring,
+    capacity: usize,
+    /// held while the ring is changed
+    mutex: Semaphore,
+    /// counts messages ready to be received
+    items: Semaphore,
+    /// counts slots free to send to
+    slots: Semaphore,
+    /// the process that created the semaphores, the one to remove them
+    owner: u16,
+}
+
+unsafe impl Sync for Shared {}
+unsafe impl Send for Shared {}
+
+impl Shared {
+    fn new(capacity: usize) -> Option<Self> {
+        let size = capacity
+            .checked_mul(size_of::<Slot>())?
+            .checked_add(size_of::<Ring>())?;
+        let addr = sys_mmap(0, size, PROT_READ | PROT_WRITE, MAP_SHARED | MAP_ANONYMOUS)?;
+
+        loop {
+            let key = NEXT_KEY.fetch_add(3, Ordering::Relaxed);
+            match Self::with_keys(key, capacity) {
+                // the mapping starts zeroed, an empty ring
+                Ok(sems) => {
+                    return Some(Self {
+                        ring: addr as *mut Ring,
+                        capacity,
+                        mutex: sems[0],
+                        items: sems[1],
+                        slots: sems[2],
+                        owner: sys_get_pid(),
+                    })
+                }
+                // the app may use these keys itself, try the next ones
+                Err(SemError::Exists) => continue,
+                Err(_) => {
+                    sys_munmap(addr, size);
+                    return None;
+                }
+            }
+        }
+    }
+
+    /// Create the mutex, items and slots semaphores, removing
+    /// those created if one cannot be
+    fn with_keys(key: u32, capacity: usize) -> Result<[Semaphore; 3], SemError> {
+        let sems = [key, key + 1, key + 2].map(Semaphore::new);
+        for (i, value) in [1, 0, capacity].into_iter().enumerate() {
+            if let Err(err) = sems[i].init(value) {
+                sems[..i].iter().for_each(|sem| {
+                    let _ = sem.remove();
+                });
+                return Err(err);
+            }
+        }
+        Ok(sems)
+    }
+
+    fn size(&self) -> usize {
+        size_of::<Ring>() + self.capacity * size_of::<Slot>()
+    }
+
+    fn slot(&self, index: usize) -> *mut Slot {
+        unsafe { (self.ring.add(1) as *mut Slot).add(index % self.capacity) }
+    }
+
+    fn push(&self, frame: &[u8]) {
+        self.slots.wait();
+        {
+            let _guard = self.mutex.acquire();
+            let ring = unsafe { &mut *self.ring };
+            let slot = unsafe { &mut *self.slot(ring.head + ring.len) };
+            slot.len = frame.len() as u32;
+            slot.bytes[..frame.len()].copy_from_slice(frame);
+            ring.len += 1;
+        }
+        self.items.signal();
+    }
+
+    fn pop(&self) -> Vec<u8> {
+        self.items.wait();
+        let frame = {
+            let _guard = self.mutex.acquire();
+            let ring = unsafe { &mut *self.ring };
+            let slot = unsafe { &*self.slot(ring.head) };
+            ring.head = (ring.head + 1) % self.capacity;
+            ring.len -= 1;
+
+            let len = (slot.len as usize).min(MESSAGE_MAX);
+            slot.bytes[..len].to_vec()
+        };
+        self.slots.signal();
+        frame
+    }
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        // every process has its own mapping, but a forked child has a
+        // copy of the semaphores, which must leave them to the others
+        sys_munmap(self.ring as usize, self.size());
+        if sys_get_pid() == self.owner {
+            let _ = self.mutex.remove();
+            let _ = self.items.remove();
+            let _ = self.slots.remove();
+        }
+    }
+}
+
+/// The sending half of a channel, may be shared by any number of senders
+pub struct Sender<T> {
+    shared: Arc<Shared>,
+    _marker: PhantomData<fn(T)>,
+}
+
+/// The receiving half of a channel, may be shared by any number of receivers
+pub struct Receiver<T> {
+    shared: Arc<Shared>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+/// Create a channel holding up to `capacity` messages, `None` if its
+/// mapping or semaphores cannot be
+///
+/// create it before forking, the halves work in any process forked
+/// after. The semaphores are removed when the creating process drops
+/// both halves.
+pub fn channel<T: Message>(capacity: usize) -> Option<(Sender<T>, Receiver<T>)> {
+    let shared = Arc::new(Shared::new(capacity.max(1))?);
+
+    Some((
+        Sender {
+            shared: shared.clone(),
+            _marker: PhantomData,
+        },
+        Receiver {
+            shared,
+            _marker: PhantomData,
+        },
+    ))
+}
+
+impl<T: Message> Sender<T> {
+    /// Send `msg`, blocking while the channel is full
+    ///
+    /// return false if it is longer than `MESSAGE_MAX` once encoded
+    pub fn send(&self, msg: &T) -> bool {
+        let mut frame = Vec::new();
+        msg.encode(&mut frame);
+        if frame.len() > MESSAGE_MAX {
+            return false;
+        }
+
+        self.shared.push(&frame);
+        true
+    }
+}
+
+impl<T: Message> Receiver<T> {
+    /// Receive a message, blocking while the channel is empty
+    ///
+    /// `None` if the message is not a `T`.
+    pub fn recv(&self) -> Option<T> {
+        let frame = self.shared.pop();
+        T::decode(&mut frame.as_slice())
+    }
+}
+
+impl<T> Clone for Sender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> Clone for Receiver<T> {
+    fn clone(&self) -> Self {
+        Self {
+            shared: self.shared.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+/// A message queue of the kernel, see `sys_mq_open`
+///
+/// it is a plain key and a message size, so it can be shared by forked
+/// processes. The queue stays until it is removed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageQueue {
+    key: u32,
+    msg_size: usize,
+}
+
+impl MessageQueue {
+    /// Open queue `key`, creating it for `capacity` messages of `msg_size` bytes
+    pub fn open(key: u32, capacity: usize, msg_size: usize) -> Result<Self, usize> {
+        let msg_size = sys_mq_open(key, capacity, msg_size)?;
+        Ok(Self { key, msg_size })
+    }
+
+    #[inline]
+    pub fn key(&self) -> u32 {
+        self.key
+    }
+
+    /// Bytes of the longest message the queue takes
+    #[inline]
+    pub fn msg_size(&self) -> usize {
+        self.msg_size
+    }
+
+    /// Send `msg`, blocking while the queue is full
+    pub fn send(&self, msg: &[u8]) -> Result<(), usize> {
+        sys_mq_send(self.key, msg)
+    }
+
+    /// Receive the next message, blocking while the queue is empty
+    pub fn recv(&self) -> Result<Vec<u8>, usize> {
+        let mut buf = alloc::vec![0; self.msg_size];
+        let len = sys_mq_recv(self.key, &mut buf)?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    /// Send `msg` encoded, `Err(EMSGSIZE)` if it is longer than the message size
+    pub fn send_msg<T: Message>(&self, msg: &T) -> Result<(), usize> {
+        let mut buf = Vec::new();
+        msg.encode(&mut buf);
+        self.send(&buf)
+    }
+
+    /// Receive the next message and decode it, `None` if it is not a `T`
+    pub fn recv_msg<T: Message>(&self) -> Result<Option<T>, usize> {
+        let buf = self.recv()?;
+        Ok(T::decode(&mut buf.as_slice()))
+    }
+
+    /// Remove the queue, for every process that opened it
+    pub fn remove(self) -> Result<(), usize> {
+        sys_mq_unlink(self.key)
+    }
+}
@@ -12,24 +12,44 @@ extern crate syscall_def;
 #[macro_use]
 pub mod io;
 pub mod allocator;
+pub mod args;
+pub mod env;
+pub mod executor;
+pub mod format;
+pub mod fs;
+pub mod gfx;
+pub mod ipc;
 pub extern crate alloc;
+pub extern crate compress;
+pub extern crate hash;
 
+mod exit;
+pub mod raw;
+pub mod shm;
+pub mod signal;
 mod syscall;
 pub mod sync;
+pub mod thread;
+pub mod time;
+pub mod ttyrec;
 mod utils;
 
 use core::fmt::*;
 
 pub use alloc::*;
 pub use chrono::*;
+pub use exit::*;
 pub use io::*;
 pub use syscall::*;
 pub use utils::*;
 pub use sync::*;
+pub use thread::{sys_spawn_thread, JoinHandle};
 
 pub fn init() {
     #[cfg(feature = "brk_alloc")]
     crate::allocator::init();
+
+    at_exit(flush_stdout);
 }
 
 #[macro_export]
@@ -54,12 +74,23 @@ macro_rules! errln {
     ($($arg:tt)*) => ($crate::err!("{}\n", format_args!($($arg)*)));
 }
 
+/// Most bytes `print!` formats on the stack before using the heap
+const PRINT_STACK_SIZE: usize = 256;
+
 #[doc(hidden)]
 pub fn _print(args: Arguments) {
-    stdout().write(format!("{}", args).as_str());
+    let buf = format::format_stack::<PRINT_STACK_SIZE>(args);
+    match buf.is_truncated() {
+        false => stdout().write(buf.as_str()),
+        true => stdout().write(format!("{}", args).as_str()),
+    }
 }
 
 #[doc(hidden)]
 pub fn _err(args: Arguments) {
-    stderr().write(format!("{}", args).as_str());
+    let buf = format::format_stack::<PRINT_STACK_SIZE>(args);
+    match buf.is_truncated() {
+        false => stderr().write(buf.as_str()),
+        true => stderr().write(format!("{}", args).as_str()),
+    }
 }
@@ -1,36 +1,70 @@
-use crate::errln;
-use alloc::string::ToString;
+use crate::format::StackBuf;
+use core::fmt::Write;
 
 #[macro_export]
 macro_rules! entry {
     ($fn:ident) => {
         #[export_name = "_start"]
-        pub extern "C" fn __impl_start() {
+        extern "C" fn __impl_start(
+            argc: usize,
+            argv: *const lib::IoVec,
+            envc: usize,
+            envp: *const lib::IoVec,
+        ) {
             lib::init();
-            let ret = $fn();
-            lib::sys_exit(ret as usize);
+            let args = unsafe { lib::env::init(argc, argv, envc, envp) };
+            let ret = $fn(args);
+            lib::exit(ret as usize);
         }
     };
 }
 
+/// Declare the syscalls the app makes, besides `BASE_SYSCALLS`
+///
+/// the kernel kills the app on any other one. Apps spawned by it can
+/// make no more than it, so apps that run others should not use it.
+///
+/// ```ignore
+/// allow_syscalls!(Read, Fork, WaitPid);
+/// ```
+#[macro_export]
+macro_rules! allow_syscalls {
+    ($($name:ident),* $(,)?) => {
+        #[used]
+        #[link_section = ".note.ysos.syscalls"]
+        static SYSCALL_NOTE: lib::SyscallNote = lib::SyscallNote::new(
+            lib::SyscallSet::from_nums(&[$(lib::Syscall::$name as u16),*]).union(lib::BASE_SYSCALLS),
+        );
+    };
+}
+
+/// Most bytes of a panic message, longer ones are cut
+const PANIC_MSG_SIZE: usize = 512;
+
 #[cfg_attr(not(test), panic_handler)]
 fn panic(info: &core::panic::PanicInfo) -> ! {
-    let location = if let Some(location) = info.location() {
-        alloc::format!(
+    // the heap may be broken or not set up yet, so format on the stack
+    let mut buf = StackBuf::<PANIC_MSG_SIZE>::new();
+
+    let _ = write!(buf, "\n\n\rERROR: panicked at ");
+    let _ = match info.location() {
+        Some(location) => write!(
+            buf,
             "{}@{}:{}",
             location.file(),
             location.line(),
             location.column()
-        )
-    } else {
-        "Unknown location".to_string()
+        ),
+        None => write!(buf, "Unknown location"),
     };
-    let msg = if let Some(msg) = info.message() {
-        alloc::format!("{}", msg)
+    if let Some(msg) = info.message() {
+        let _ = write!(buf, "\n\n\r{}", msg);
     } else {
-        "No more message...".to_string()
-    };
-    errln!("\n\n\rERROR: panicked at {}\n\n\r{}", location, msg);
+        let _ = write!(buf, "\n\n\rNo more message...");
+    }
+
+    crate::stderr().write(buf.as_str());
+    crate::stderr().write(if buf.is_truncated() { "...\n" } else { "\n" });
 
-    crate::sys_exit(1);
+    crate::exit(1);
 }
@@ -0,0 +1,70 @@
+//! A stub for each syscall, taking its arguments as they are passed
+//!
+//! generated from `syscall_def::for_each_syscall`, one `sys_*` function
+//! named as the syscall in snake case with as many `usize` as its arity,
+//! returning `rax` untouched. The wrappers of `lib` are built on these; a program
+//! may call them for a syscall `lib` has no wrapper of.
+
+use syscall_def::Syscall;
+
+macro_rules! stub {
+    ($name:ident, $fn:ident, 0) => {
+        #[inline(always)]
+        pub fn $fn() -> usize {
+            syscall!(Syscall::$name)
+        }
+    };
+    ($name:ident, $fn:ident, 1) => {
+        #[inline(always)]
+        pub fn $fn(arg0: usize) -> usize {
+            syscall!(Syscall::$name, arg0)
+        }
+    };
+    ($name:ident, $fn:ident, 2) => {
+        #[inline(always)]
+        pub fn $fn(arg0: usize, arg1: usize) -> usize {
+            syscall!(Syscall::$name, arg0, arg1)
+        }
+    };
+    ($name:ident, $fn:ident, 3) => {
+        #[inline(always)]
+        pub fn $fn(arg0: usize, arg1: usize, arg2: usize) -> usize {
+            syscall!(Syscall::$name, arg0, arg1, arg2)
+        }
+    };
+    ($name:ident, $fn:ident, 4) => {
+        #[inline(always)]
+        pub fn $fn(arg0: usize, arg1: usize, arg2: usize, arg3: usize) -> usize {
+            syscall!(Syscall::$name, arg0, arg1, arg2, arg3)
+        }
+    };
+    ($name:ident, $fn:ident, 5) => {
+        #[inline(always)]
+        pub fn $fn(arg0: usize, arg1: usize, arg2: usize, arg3: usize, arg4: usize) -> usize {
+            syscall!(Syscall::$name, arg0, arg1, arg2, arg3, arg4)
+        }
+    };
+    ($name:ident, $fn:ident, 6) => {
+        #[inline(always)]
+        pub fn $fn(
+            arg0: usize,
+            arg1: usize,
+            arg2: usize,
+            arg3: usize,
+            arg4: usize,
+            arg5: usize,
+        ) -> usize {
+            syscall!(Syscall::$name, arg0, arg1, arg2, arg3, arg4, arg5)
+        }
+    };
+}
+
+macro_rules! stubs {
+    ($($name:ident($arity:literal) = $num:literal,)*) => {
+        paste::paste! {
+            $(stub!($name, [<sys_ $name:snake>], $arity);)*
+        }
+    };
+}
+
+syscall_def::for_each_syscall!(stubs);
@@ -0,0 +1,63 @@
+//! Memory shared between processes that are not related
+//!
+//! a segment is named by a key, like a semaphore. It is removed once the
+//! last process mapping it unmaps it or exits, so keep it mapped until
+//! the others have opened it.
+
+use core::sync::atomic::AtomicU8;
+
+use crate::{sys_shm_map, sys_shm_open, sys_shm_unmap};
+
+/// A shared memory segment mapped in this process, unmapped when dropped
+#[derive(Debug)]
+pub struct SharedMemory {
+    addr: usize,
+    len: usize,
+}
+
+impl SharedMemory {
+    /// Open segment `key` with at least `size` bytes and map it
+    pub fn open(key: u32, size: usize) -> Result<Self, usize> {
+        Self::open_at(key, size, None)
+    }
+
+    /// Open segment `key` and map it at `addr`, an address the processes
+    /// sharing it agreed on, so pointers into it mean the same to them all
+    pub fn open_at(key: u32, size: usize, addr: Option<usize>) -> Result<Self, usize> {
+        let len = sys_shm_open(key, size)?;
+        let addr = sys_shm_map(key, addr)?;
+        Ok(Self { addr, len })
+    }
+
+    #[inline]
+    pub fn addr(&self) -> usize {
+        self.addr
+    }
+
+    /// Bytes of the segment, whole pages
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The bytes of the segment, as atomics since other processes write them
+    pub fn bytes(&self) -> &[AtomicU8] {
+        unsafe { core::slice::from_raw_parts(self.addr as *const AtomicU8, self.len) }
+    }
+
+    /// A pointer to the start of the segment, for anything laid out in it
+    pub fn as_ptr<T>(&self) -> *mut T {
+        self.addr as *mut T
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        let _ = sys_shm_unmap(self.addr);
+    }
+}
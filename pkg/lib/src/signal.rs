@@ -0,0 +1,68 @@
+//! Signals sent with `sys_kill`, and the handlers a process sets for them
+//!
+//! a handler runs when the process next returns from the kernel, and
+//! returns into `restorer`, which makes `Syscall::SigReturn` to go back
+//! to what it interrupted. The signal is masked while its handler runs.
+
+use crate::syscall::sys_sigaction;
+use syscall_def::Syscall;
+
+pub use syscall_def::signal::*;
+
+/// What a process does when it takes a signal
+#[derive(Clone, Copy, Debug)]
+pub enum SigHandler {
+    /// what the signal does by default, see `SigDefault`
+    Default,
+    Ignore,
+    /// called with the number of the signal
+    Handle(extern "C" fn(usize)),
+}
+
+impl SigHandler {
+    fn to_raw(self) -> usize {
+        match self {
+            Self::Default => SIG_DFL,
+            Self::Ignore => SIG_IGN,
+            Self::Handle(handler) => handler as usize,
+        }
+    }
+
+    fn from_raw(raw: usize) -> Self {
+        match raw {
+            SIG_DFL => Self::Default,
+            SIG_IGN => Self::Ignore,
+            // only ever set from a `Handle`
+            raw => {
+                Self::Handle(unsafe { core::mem::transmute::<usize, extern "C" fn(usize)>(raw) })
+            }
+        }
+    }
+}
+
+// where every handler returns to, on the stack the kernel left
+core::arch::global_asm!(
+    ".global __sig_restorer",
+    "__sig_restorer:",
+    "mov rax, {}",
+    "int 0x80",
+    "ud2",
+    const Syscall::SigReturn as usize,
+);
+
+extern "C" {
+    fn __sig_restorer() -> !;
+}
+
+/// Set what the current process does when it takes `sig`, return what it did
+///
+/// fails with `EINVAL` for `SIGKILL` and `SIGSTOP`, which cannot be caught.
+pub fn signal(sig: usize, handler: SigHandler) -> Result<SigHandler, usize> {
+    sys_sigaction(sig, handler.to_raw(), __sig_restorer as *const () as usize)
+        .map(SigHandler::from_raw)
+}
+
+/// What the current process does when it takes `sig`
+pub fn handler(sig: usize) -> Result<SigHandler, usize> {
+    sys_sigaction(sig, SIG_KEEP, 0).map(SigHandler::from_raw)
+}
@@ -1,7 +1,7 @@
-use core::{
-    hint::spin_loop,
-    sync::atomic::{AtomicBool, Ordering},
-};
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::result::Result;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 
 use crate::*;
 
@@ -19,7 +19,8 @@ impl SpinLock {
     pub fn acquire(&self) {
         // FIXME: acquire the lock, spin if the lock is not available
         while self.bolt.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
-            spin_loop();
+            // the holder can not run until we give up the cpu
+            sys_yield();
         }
 
         self.bolt.store(true, Ordering::Release);
@@ -33,9 +34,52 @@ impl SpinLock {
 
 unsafe impl Sync for SpinLock {} // Why? Check reflection question 5
 
+/// Why a semaphore operation failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SemError {
+    /// a semaphore with this key already exists
+    Exists,
+    /// no semaphore with this key, it was never created or was removed
+    NotExist,
+    /// taken, returned by `sys_try_wait_sem`
+    WouldBlock,
+    /// still taken when the timeout of `sys_wait_sem_timeout` was over
+    TimedOut,
+    /// any other errno returned by the kernel
+    Other(usize),
+}
+
+impl SemError {
+    pub fn from_errno(errno: usize) -> Self {
+        match errno {
+            errno::EEXIST => Self::Exists,
+            errno::ENOENT => Self::NotExist,
+            errno::EAGAIN => Self::WouldBlock,
+            errno::ETIMEDOUT => Self::TimedOut,
+            other => Self::Other(other),
+        }
+    }
+}
+
+impl core::fmt::Display for SemError {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match self {
+            Self::Exists => write!(f, "semaphore already exists"),
+            Self::NotExist => write!(f, "semaphore does not exist"),
+            Self::WouldBlock => write!(f, "semaphore is taken"),
+            Self::TimedOut => write!(f, "semaphore wait timed out"),
+            Self::Other(errno) => write!(f, "semaphore error {}", errno),
+        }
+    }
+}
+
+/// A semaphore named by its key
+///
+/// it is a plain key, so it can be a `static` shared by forked processes.
+/// `wait` and `signal` panic if the semaphore does not exist,
+/// use the `sys_*_sem` functions to handle that.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Semaphore {
-    /* FIXME: record the sem key */
     key: u32,
 }
 
@@ -44,27 +88,95 @@ impl Semaphore {
         Semaphore { key }
     }
 
+    /// Create the semaphore with `value` and remove it when dropped
+    pub fn create(key: u32, value: usize) -> Result<OwnedSemaphore, SemError> {
+        let sem = Semaphore::new(key);
+        sem.init(value)?;
+        Ok(OwnedSemaphore(sem))
+    }
+
     #[inline(always)]
-    pub fn init(&self, value: usize) -> bool {
+    pub fn key(&self) -> u32 {
+        self.key
+    }
+
+    #[inline(always)]
+    pub fn init(&self, value: usize) -> Result<(), SemError> {
         sys_new_sem(self.key, value)
     }
 
     #[inline(always)]
     pub fn wait(&self) {
-        sys_wait_sem(self.key);
+        if let Err(err) = sys_wait_sem(self.key) {
+            panic!("wait on semaphore {:#x}: {}", self.key, err);
+        }
+    }
+
+    /// Take the semaphore if it is free, return false if it is not
+    pub fn try_wait(&self) -> bool {
+        match sys_try_wait_sem(self.key) {
+            Ok(()) => true,
+            Err(SemError::WouldBlock) => false,
+            Err(err) => panic!("try wait on semaphore {:#x}: {}", self.key, err),
+        }
+    }
+
+    /// Wait for at most `timeout`, return false if it was over first
+    pub fn wait_timeout(&self, timeout: core::time::Duration) -> bool {
+        match sys_wait_sem_timeout(self.key, timeout) {
+            Ok(()) => true,
+            Err(SemError::TimedOut) => false,
+            Err(err) => panic!("wait on semaphore {:#x}: {}", self.key, err),
+        }
     }
 
     #[inline(always)]
     pub fn signal(&self) {
-        sys_signal_sem(self.key);
+        if let Err(err) = sys_signal_sem(self.key) {
+            panic!("signal semaphore {:#x}: {}", self.key, err);
+        }
+    }
+
+    /// Wait now and signal when the guard is dropped
+    pub fn acquire(&self) -> SemGuard<'_> {
+        self.wait();
+        SemGuard(self)
     }
 
     #[inline(always)]
-    pub fn remove(&self) {
-        sys_del_sem(self.key);
+    pub fn remove(&self) -> Result<(), SemError> {
+        sys_del_sem(self.key)
     }
+}
+
+/// A semaphore removed when dropped, returned by `Semaphore::create`
+///
+/// a forked child that exits does not drop it, only its creator does.
+#[derive(Debug)]
+pub struct OwnedSemaphore(Semaphore);
+
+impl core::ops::Deref for OwnedSemaphore {
+    type Target = Semaphore;
+
+    fn deref(&self) -> &Semaphore {
+        &self.0
+    }
+}
+
+impl Drop for OwnedSemaphore {
+    fn drop(&mut self) {
+        let _ = self.0.remove();
+    }
+}
 
-    /* FIXME: other functions with syscall... */
+/// Signals its semaphore when dropped, returned by `Semaphore::acquire`
+#[must_use = "the semaphore is signalled as soon as the guard is dropped"]
+pub struct SemGuard<'a>(&'a Semaphore);
+
+impl Drop for SemGuard<'_> {
+    fn drop(&mut self) {
+        self.0.signal();
+    }
 }
 
 unsafe impl Sync for Semaphore {}
@@ -75,3 +187,145 @@ macro_rules! semaphore_array {
         [ $($crate::Semaphore::new($x),)* ]
     }
 }
+
+/// `Mutex::state`: nobody holds it
+const UNLOCKED: u32 = 0;
+/// Held, nobody waits for it
+const LOCKED: u32 = 1;
+/// Held, and someone may wait for it, to be woken on unlock
+const CONTENDED: u32 = 2;
+
+/// A lock on `T` for the threads of a process, blocking on a futex
+///
+/// taking and releasing it makes no syscall unless another thread waits.
+/// Forked processes have their own copy of it, see `sys_futex_wait`.
+pub struct Mutex<T: ?Sized> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: ?Sized + Send> Send for Mutex<T> {}
+unsafe impl<T: ?Sized + Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(value),
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+impl<T: ?Sized> Mutex<T> {
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        if self
+            .state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .is_err()
+        {
+            // whoever unlocks it next has to wake a waiter
+            while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+                let _ = sys_futex_wait(&self.state, CONTENDED);
+            }
+        }
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state
+            .compare_exchange(UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed)
+            .ok()
+            .map(|_| MutexGuard { mutex: self })
+    }
+
+    fn unlock(&self) {
+        if self.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            sys_futex_wake(&self.state, 1);
+        }
+    }
+}
+
+/// Unlocks its mutex when dropped, returned by `Mutex::lock`
+#[must_use = "the mutex is unlocked as soon as the guard is dropped"]
+pub struct MutexGuard<'a, T: ?Sized> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T: ?Sized> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T: ?Sized> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        self.mutex.unlock();
+    }
+}
+
+/// Waits for a change made under a `Mutex`, blocking on a futex
+///
+/// the futex holds a count of notifications, a thread that is notified
+/// between unlocking the mutex and blocking finds it changed and returns.
+/// Waking up without a notification may happen, check the condition again.
+pub struct Condvar {
+    seq: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self {
+            seq: AtomicU32::new(0),
+        }
+    }
+
+    /// Unlock the mutex of `guard` until notified, then lock it again
+    pub fn wait<'a, T: ?Sized>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let seq = self.seq.load(Ordering::Relaxed);
+        let mutex = guard.mutex;
+        drop(guard);
+
+        let _ = sys_futex_wait(&self.seq, seq);
+        mutex.lock()
+    }
+
+    /// Wait until `condition` is false, checking it with the mutex locked
+    pub fn wait_while<'a, T: ?Sized>(
+        &self,
+        mut guard: MutexGuard<'a, T>,
+        mut condition: impl FnMut(&mut T) -> bool,
+    ) -> MutexGuard<'a, T> {
+        while condition(&mut guard) {
+            guard = self.wait(guard);
+        }
+        guard
+    }
+
+    pub fn notify_one(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        sys_futex_wake(&self.seq, 1);
+    }
+
+    pub fn notify_all(&self) {
+        self.seq.fetch_add(1, Ordering::Release);
+        sys_futex_wake(&self.seq, usize::MAX);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,5 +1,44 @@
+use alloc::vec::Vec;
 use chrono::{naive::*, DateTime, Utc};
-use syscall_def::Syscall;
+use core::sync::atomic::AtomicU32;
+use core::time::Duration;
+use syscall_def::{
+    check_ret, ProgramArgs, FUTEX_WAIT, FUTEX_WAKE, NICE_BIAS, SEM_NEW, SEM_REMOVE,
+    SEM_SIGNAL, SEM_TRY_WAIT, SEM_WAIT, SEM_WAIT_TIMEOUT, UMASK_KEEP, UTC_OFFSET_BIAS,
+    UTC_OFFSET_KEEP, WAIT_ANY, WAIT_NOHANG, WAIT_RUNNING, WAIT_UNTRACED,
+};
+
+use crate::raw;
+use crate::SemError;
+
+pub use syscall_def::errno;
+pub use syscall_def::{
+    ARG_MAX, FdStat, IoVec, MapEntry, F_GETRATE, F_GETRAW, F_GETWINSZ, F_SETRATE, F_SETRAW,
+    F_SETWINSZ, LOCK_EX, LOCK_NB, LOCK_SH, LOCK_UN,
+    MADV_DONTNEED, MADV_PIN, MADV_UNPIN, MADV_WILLNEED, MAP_ANONYMOUS, MAP_FIXED, MAP_PRIVATE,
+    MAP_SHARED, MS_ASYNC, MS_SYNC, PAGE_HUGE, PAGE_NO_EXECUTE, PAGE_USER, PAGE_WRITABLE, PROT_EXEC, PROT_READ,
+    PROT_WRITE, TRACE_RECORD, TRACE_REPLAY, FD_KIND_CONSOLE, FD_KIND_FILE, FD_KIND_OTHER,
+    FD_KIND_PIPE, FD_KIND_PTY, O_APPEND, O_CREAT, O_RDONLY, O_RDWR, O_TRUNC, O_WRONLY, SEEK_CUR,
+    SEEK_END, SEEK_SET, Sysinfo, PollFd, POLLIN, POLLNVAL, POLLOUT, POLL_MAX, FbInfo,
+    FB_FORMAT_BGR, FB_FORMAT_RGB,
+};
+pub use syscall_def::sched;
+pub use syscall_def::{Syscall, SyscallNote, SyscallSet, WaitStatus, EXIT_SYSCALL_DENIED};
+
+/// The syscalls of every app, added to the ones it declares
+///
+/// `lib` makes them to exit, run exit hooks, print, flush a `BufWriter`,
+/// wait out a rate limit, return from signal handlers and allocate.
+pub const BASE_SYSCALLS: SyscallSet = SyscallSet::from_nums(&[
+    Syscall::Exit as u16,
+    Syscall::GetPid as u16,
+    Syscall::Write as u16,
+    Syscall::WriteV as u16,
+    Syscall::Yield as u16,
+    Syscall::SigReturn as u16,
+    Syscall::Allocate as u16,
+    Syscall::Deallocate as u16,
+]);
 
 #[inline(always)]
 pub fn sys_write(fd: u8, buf: &[u8]) -> Option<usize> {
@@ -31,6 +70,219 @@ pub fn sys_read(fd: u8, buf: &mut [u8]) -> Option<usize> {
     }
 }
 
+#[inline(always)]
+pub fn sys_writev(fd: u8, iov: &[IoVec]) -> Option<usize> {
+    let ret = syscall!(
+        Syscall::WriteV,
+        fd as u64,
+        iov.as_ptr() as u64,
+        iov.len() as u64
+    ) as isize;
+    if ret.is_negative() {
+        None
+    } else {
+        Some(ret as usize)
+    }
+}
+
+#[inline(always)]
+pub fn sys_readv(fd: u8, iov: &mut [IoVec]) -> Option<usize> {
+    let ret = syscall!(
+        Syscall::ReadV,
+        fd as u64,
+        iov.as_ptr() as u64,
+        iov.len() as u64
+    ) as isize;
+    if ret.is_negative() {
+        None
+    } else {
+        Some(ret as usize)
+    }
+}
+
+/// Fill `buf` with random bytes, from `rdrand` when the cpu has it
+#[inline(always)]
+pub fn sys_getrandom(buf: &mut [u8]) -> Option<usize> {
+    let ret = syscall!(Syscall::GetRandom, buf.as_mut_ptr() as u64, buf.len() as u64);
+    check_ret(ret).ok()
+}
+
+/// Open an in-memory buffer, bytes written to it are kept until read
+#[inline(always)]
+pub fn sys_memfd() -> Option<u8> {
+    check_ret(syscall!(Syscall::MemFd)).ok().map(|fd| fd as u8)
+}
+
+/// Open a pipe, return its read and write fds
+///
+/// reads block while it is empty and return 0 once every write fd is closed,
+/// writes block while it is full and fail once every read fd is closed.
+#[inline(always)]
+pub fn sys_pipe() -> Option<(u8, u8)> {
+    check_ret(syscall!(Syscall::Pipe))
+        .ok()
+        .map(|fds| (fds as u8, (fds >> 8) as u8))
+}
+
+/// Open a pty, returns its master and slave fds
+///
+/// what is written to the master is read from the slave a line at a time,
+/// echoed back unless the slave is in raw mode, see `F_SETRAW`. What is
+/// written to the slave is read from the master. Reads of either end
+/// return 0 once every fd of the other is closed.
+#[inline(always)]
+pub fn sys_open_pty() -> Option<(u8, u8)> {
+    check_ret(syscall!(Syscall::OpenPty))
+        .ok()
+        .map(|fds| (fds as u8, (fds >> 8) as u8))
+}
+
+/// Open a file the host shares, by its path under the shared folder
+///
+/// the file is read only, see `xtask --share`.
+#[inline(always)]
+pub fn sys_host_open(name: &str) -> Option<u8> {
+    let ret = syscall!(Syscall::HostOpen, name.as_ptr() as u64, name.len() as u64);
+    check_ret(ret).ok().map(|fd| fd as u8)
+}
+
+/// Open a file or device by its path, `flags` is one of `O_RDONLY`,
+/// `O_WRONLY` and `O_RDWR`, or'ed with `O_CREAT`, `O_TRUNC` and `O_APPEND`
+///
+/// `/host/<name>` opens what `sys_host_open` does for `<name>`, see
+/// `lib::fs::File` to read and write files. return the errno on failure
+#[inline(always)]
+pub fn sys_open(path: &str, flags: usize) -> Result<u8, usize> {
+    let ret = syscall!(
+        Syscall::Open,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        flags
+    );
+    check_ret(ret).map(|fd| fd as u8)
+}
+
+/// Mount the FAT16 image in the file at `image` on `dir`, only root may
+///
+/// return the number of the loop device it is read through, `/dev/loop<n>`,
+/// or the errno on failure
+#[inline(always)]
+pub fn sys_mount(image: &str, dir: &str) -> Result<usize, usize> {
+    let paths = [IoVec::new(image.as_bytes()), IoVec::new(dir.as_bytes())];
+    check_ret(syscall!(Syscall::Mount, paths.as_ptr() as usize))
+}
+
+/// Swap to the device or file at `path`, e.g. `/dev/disk1p2` or a file
+/// of the size of the swap area, only root may
+///
+/// what it held is overwritten. Return the errno on failure, `EBUSY`
+/// if swap is on already.
+#[inline(always)]
+pub fn sys_swap_on(path: &str) -> Result<(), usize> {
+    let ret = syscall!(Syscall::SwapOn, path.as_ptr() as u64, path.len() as u64);
+    check_ret(ret).map(|_| ())
+}
+
+/// Move the position of the file behind `fd` by `offset` from `whence`,
+/// one of `SEEK_SET`, `SEEK_CUR` and `SEEK_END`
+///
+/// return the new position, or the errno on failure
+#[inline(always)]
+pub fn sys_seek(fd: u8, offset: isize, whence: usize) -> Result<usize, usize> {
+    check_ret(syscall!(Syscall::Seek, fd as u64, offset, whence))
+}
+
+/// Create a timer fd expiring after `initial` then every `interval`
+///
+/// reading 8 bytes from it gives the expirations since the last read as
+/// a `u64`, or 0 bytes if there are none. Writing two `u64`, the initial
+/// and the interval in nanoseconds, arms it again, an initial 0 disarms it.
+#[inline(always)]
+pub fn sys_timerfd(initial: Duration, interval: Duration) -> Option<u8> {
+    let ret = syscall!(
+        Syscall::TimerFd,
+        initial.as_nanos() as u64,
+        interval.as_nanos() as u64
+    );
+    check_ret(ret).ok().map(|fd| fd as u8)
+}
+
+/// Create an event fd holding a counter of `value`
+///
+/// writing a `u64` adds to the counter, reading 8 bytes takes the counter
+/// and resets it to 0, or reads 0 bytes if it is 0 already.
+#[inline(always)]
+pub fn sys_eventfd(value: u64) -> Option<u8> {
+    check_ret(syscall!(Syscall::EventFd, value)).ok().map(|fd| fd as u8)
+}
+
+#[inline(always)]
+pub fn sys_close(fd: u8) -> bool {
+    syscall!(Syscall::Close, fd as u64) == 0
+}
+
+/// Make `new` refer to what `old` does, closing what `new` was before
+///
+/// the fds share the resource and its position, like a forked fd.
+#[inline(always)]
+pub fn sys_dup2(old: u8, new: u8) -> bool {
+    check_ret(syscall!(Syscall::Dup2, old as u64, new as u64)).is_ok()
+}
+
+/// Get the I/O statistics of `fd`, and the size and position of a file
+#[inline(always)]
+pub fn sys_fstat(fd: u8) -> Option<FdStat> {
+    let mut stat = FdStat::default();
+    let ret = syscall!(Syscall::Fstat, fd as u64, &mut stat as *mut FdStat as u64);
+    check_ret(ret).ok().map(|_| stat)
+}
+
+/// Control `fd`, `cmd` is one of `F_GETRATE`, `F_SETRATE`, `F_GETRAW`,
+/// `F_SETRAW`, and `F_GETWINSZ` and `F_SETWINSZ` of a pty
+#[inline(always)]
+pub fn sys_fcntl(fd: u8, cmd: usize, arg: usize) -> Option<usize> {
+    let ret = syscall!(Syscall::Fcntl, fd as u64, cmd as u64, arg as u64);
+    check_ret(ret).ok()
+}
+
+/// Wait until one of `fds` is ready or the monotonic clock reaches
+/// `deadline`, forever without one, and set the `revents` of each
+///
+/// return how many are ready, 0 at the deadline. A deadline already
+/// passed only checks them, and with no fds it sleeps until it.
+#[inline(always)]
+pub fn sys_poll(
+    fds: &mut [PollFd],
+    deadline: Option<crate::time::Instant>,
+) -> Result<usize, usize> {
+    let deadline = deadline.map_or(syscall_def::POLL_FOREVER, |at| at.as_nanos());
+    let ret = syscall!(
+        Syscall::Poll,
+        fds.as_mut_ptr() as u64,
+        fds.len() as u64,
+        deadline
+    );
+    check_ret(ret)
+}
+
+#[inline(always)]
+pub fn sys_send_file(
+    out_fd: u8,
+    in_fd: u8,
+    offset: Option<&mut usize>,
+    len: usize,
+) -> Option<usize> {
+    let offset = offset.map_or(core::ptr::null_mut(), |off| off as *mut usize);
+    let ret = syscall!(
+        Syscall::SendFile,
+        out_fd as u64,
+        in_fd as u64,
+        offset as u64,
+        len as u64
+    );
+    check_ret(ret).ok()
+}
+
 #[inline(always)]
 pub fn sys_allocate(layout: &core::alloc::Layout) -> *mut u8 {
     syscall!(Syscall::Allocate, layout as *const _) as *mut u8
@@ -52,26 +304,299 @@ pub fn sys_exit(code: usize) -> ! {
     unreachable!();
 }
 
+/// Power off, `xtask test` passes when `code` is 0
+#[inline(always)]
+pub fn sys_shutdown(code: isize) -> ! {
+    syscall!(Syscall::Shutdown, code as usize);
+    unreachable!();
+}
+
 #[inline(always)]
 pub fn sys_wait_pid(pid: u16) -> isize {
-    syscall!(Syscall::WaitPid, pid as u64) as isize
+    syscall!(Syscall::WaitPid, pid as u64, 0, 0) as isize
+}
+
+/// Wait for any child to exit, like `waitpid(-1)`, threads included
+///
+/// return its pid and exit code, `ECHILD` if there is no child left
+#[inline(always)]
+pub fn sys_wait_any() -> Result<(u16, isize), usize> {
+    let mut pid = 0u16;
+    let ret = syscall!(Syscall::WaitPid, WAIT_ANY, 0, &mut pid as *mut u16) as isize;
+    match pid {
+        0 => Err(-ret as usize),
+        pid => Ok((pid, ret)),
+    }
+}
+
+/// Check if process `pid` has exited without waiting, like `waitpid`
+/// with `WNOHANG`
+///
+/// return its exit code, `None` while it runs
+#[inline(always)]
+pub fn sys_try_wait_pid(pid: u16) -> Option<isize> {
+    let ret = syscall!(Syscall::WaitPid, pid as u64, WAIT_NOHANG, 0) as isize;
+    (ret != WAIT_RUNNING).then_some(ret)
+}
+
+/// Take any child that has exited without waiting, like `waitpid(-1)`
+/// with `WNOHANG`
+///
+/// return its pid and exit code, `None` if none has exited yet,
+/// `ECHILD` if there is no child left
+#[inline(always)]
+pub fn sys_try_wait_any() -> Result<Option<(u16, isize)>, usize> {
+    let mut pid = 0u16;
+    let ret = syscall!(Syscall::WaitPid, WAIT_ANY, WAIT_NOHANG, &mut pid as *mut u16) as isize;
+    match (pid, ret) {
+        (0, WAIT_RUNNING) => Ok(None),
+        (0, ret) => Err(-ret as usize),
+        (pid, ret) => Ok(Some((pid, ret))),
+    }
+}
+
+/// Wait for process `pid` to exit or to stop, like `waitpid` with `WUNTRACED`
+///
+/// while waiting, `pid` is in the foreground: Ctrl+Z on the console stops it.
+#[inline(always)]
+pub fn sys_wait_pid_untraced(pid: u16) -> WaitStatus {
+    WaitStatus::from_ret(syscall!(Syscall::WaitPid, pid as u64, WAIT_UNTRACED, 0) as isize)
+}
+
+/// Nanoseconds since the unix epoch, virtual time in deterministic mode
+#[inline(always)]
+pub fn sys_time_nanos() -> i64 {
+    syscall!(Syscall::Time) as i64
+}
+
+/// Nanoseconds on a clock that never goes backwards, see `time::Instant`
+#[inline(always)]
+pub fn sys_clock_monotonic() -> u64 {
+    syscall!(Syscall::ClockMonotonic) as u64
 }
 
 #[inline(always)]
 pub fn sys_time() -> DateTime<Utc> {
-    let time = syscall!(Syscall::Time) as i64;
+    let time = sys_time_nanos();
     const BILLION: i64 = 1_000_000_000;
     DateTime::from_timestamp(time / BILLION, (time % BILLION) as u32).unwrap_or_default()
 }
 
+/// Set the time of day, and the RTC with it, only root may
+///
+/// return the errno on failure
+#[inline(always)]
+pub fn sys_set_time(time: DateTime<Utc>) -> Result<(), usize> {
+    let nanos = time.timestamp_nanos_opt().ok_or(errno::EINVAL)?;
+    check_ret(syscall!(Syscall::SetTime, nanos as u64)).map(|_| ())
+}
+
+/// Seconds local time is ahead of UTC
+#[inline(always)]
+pub fn sys_utc_offset() -> i32 {
+    (syscall!(Syscall::UtcOffset, UTC_OFFSET_KEEP) as isize - UTC_OFFSET_BIAS) as i32
+}
+
+/// Set the seconds local time is ahead of UTC, only root may
+///
+/// return the old offset, or the errno on failure
+#[inline(always)]
+pub fn sys_set_utc_offset(offset: i32) -> Result<i32, usize> {
+    check_ret(syscall!(Syscall::UtcOffset, offset as isize as usize))
+        .map(|ret| (ret as isize - UTC_OFFSET_BIAS) as i32)
+}
+
 #[inline(always)]
 pub fn sys_stat() {
     syscall!(Syscall::Stat);
 }
 
+/// Start app `path` through `raw::sys_spawn` or `raw::sys_exec`
+fn start_program(
+    syscall: fn(usize, usize, usize) -> usize,
+    path: &str,
+    args: &[&str],
+    env: &[&str],
+    stdout: u8,
+) -> usize {
+    let argv: Vec<IoVec> = args.iter().map(|arg| IoVec::new(arg.as_bytes())).collect();
+    let envp: Vec<IoVec> = env.iter().map(|var| IoVec::new(var.as_bytes())).collect();
+    let program = ProgramArgs::new(&argv, &envp).with_stdout(stdout);
+
+    syscall(
+        path.as_ptr() as usize,
+        path.len(),
+        &program as *const ProgramArgs as usize,
+    )
+}
+
+/// Spawn app `path` with its name as the only argument
+///
+/// the environment is passed on, see `env::vars`.
 #[inline(always)]
 pub fn sys_spawn(path: &str) -> u16 {
-    syscall!(Syscall::Spawn, path.as_ptr() as u64, path.len() as u64) as u16
+    sys_spawn_args(path, &[path], crate::env::vars())
+}
+
+/// Spawn app `path` with `args` and the `KEY=value` strings of `env`
+///
+/// the first argument is the name of the app by convention.
+pub fn sys_spawn_args(path: &str, args: &[&str], env: &[&str]) -> u16 {
+    let ret = start_program(raw::sys_spawn, path, args, env, 0);
+    check_ret(ret).map_or(0, |pid| pid as u16)
+}
+
+/// Replace the current process with app `path`, passing it `args`
+///
+/// the first argument is the name of the app by convention, and the
+/// environment is passed on. What `print!` buffered is written out first.
+/// Returns only on failure, with the errno, `EBUSY` while other threads
+/// of the process are still running.
+pub fn sys_exec(path: &str, args: &[&str]) -> usize {
+    crate::flush_stdout();
+
+    let ret = start_program(raw::sys_exec, path, args, crate::env::vars(), 0);
+    check_ret(ret).err().unwrap_or_default()
+}
+
+/// Spawn an app writing its stdout to `fd` of the caller, e.g. a `sys_memfd`
+#[inline(always)]
+pub fn sys_spawn_with_stdout(path: &str, fd: u8) -> u16 {
+    let ret = start_program(raw::sys_spawn, path, &[path], crate::env::vars(), fd);
+    check_ret(ret).map_or(0, |pid| pid as u16)
+}
+
+/// Spawn an app with its syscalls traced, `mode` is `TRACE_RECORD` or `TRACE_REPLAY`
+#[inline(always)]
+pub fn sys_spawn_traced(path: &str, mode: usize) -> u16 {
+    let ret = syscall!(
+        Syscall::SpawnTraced,
+        path.as_ptr() as u64,
+        path.len() as u64,
+        mode as u64
+    );
+    check_ret(ret).map_or(0, |pid| pid as u16)
+}
+
+/// Read the latest scheduling events of `pid` into `buf`, oldest first
+#[inline(always)]
+pub fn sys_sched_stat(pid: u16, buf: &mut [sched::SchedEvent]) -> Option<usize> {
+    let ret = syscall!(
+        Syscall::SchedStat,
+        pid as u64,
+        buf.as_mut_ptr() as u64,
+        buf.len() as u64
+    );
+    check_ret(ret).ok()
+}
+
+/// Memory and swap of the system
+#[inline(always)]
+pub fn sys_sysinfo() -> Sysinfo {
+    let mut info = Sysinfo::default();
+    syscall!(Syscall::Sysinfo, &mut info as *mut _ as u64);
+    info
+}
+
+/// The resources `pid` (0 for self) has used so far, `None` if there is
+/// no such process
+#[inline(always)]
+pub fn sys_get_rusage(pid: u16) -> Option<sched::Rusage> {
+    let mut usage = sched::Rusage::default();
+    let ret = syscall!(Syscall::GetRusage, pid as u64, &mut usage as *mut _ as u64);
+    check_ret(ret).ok().map(|_| usage)
+}
+
+/// What `pid` (0 for self) is and what it has used so far, `None` if
+/// there is no such process
+#[inline(always)]
+pub fn sys_proc_info(pid: u16) -> Option<sched::ProcInfo> {
+    let mut info = sched::ProcInfo::default();
+    let ret = syscall!(Syscall::ProcInfo, pid as u64, &mut info as *mut _ as u64);
+    check_ret(ret).ok().map(|_| info)
+}
+
+/// Get a resource limit of `pid` (0 for self) and set it to `new`,
+/// or pass `RLIMIT_KEEP` to leave it
+///
+/// return the old limit
+#[inline(always)]
+pub fn sys_prlimit(pid: u16, resource: usize, new: usize) -> Option<usize> {
+    check_ret(syscall!(Syscall::Prlimit, pid as u64, resource, new)).ok()
+}
+
+/// Add `delta` to the niceness of the current process, within
+/// `NICE_MIN..=NICE_MAX`
+///
+/// return the new niceness
+#[inline(always)]
+pub fn sys_nice(delta: isize) -> isize {
+    NICE_BIAS - syscall!(Syscall::Nice, delta) as isize
+}
+
+/// Set the niceness of `pid` (0 for self), only root may lower
+/// it for another process
+///
+/// return the old niceness, or the errno on failure
+#[inline(always)]
+pub fn sys_renice(pid: u16, nice: isize) -> Result<isize, usize> {
+    check_ret(syscall!(Syscall::Renice, pid as u64, nice))
+        .map(|ret| NICE_BIAS - ret as isize)
+}
+
+/// Set the priority of `pid` (0 for self), a level of the scheduler below
+/// `PRIO_LEVELS`, 0 the highest. Only root may raise it for another process,
+/// `PRIO_KEEP` only reads it
+///
+/// return the old priority, or the errno on failure
+#[inline(always)]
+pub fn sys_set_priority(pid: u16, priority: usize) -> Result<usize, usize> {
+    check_ret(syscall!(Syscall::SetPriority, pid as u64, priority))
+}
+
+/// Put `pid` (0 for self) in the real-time class, to run for up to `budget`
+/// of cpu time in every `period` before any other process. A zero `budget`
+/// takes it out of the class. Only root may put another process in it
+///
+/// return the errno on failure, `EBUSY` if the cpu is already reserved
+#[inline(always)]
+pub fn sys_sched_rt(pid: u16, period: Duration, budget: Duration) -> Result<(), usize> {
+    let period = period.as_nanos().min(u64::MAX as u128) as u64;
+    let budget = budget.as_nanos().min(u64::MAX as u128) as u64;
+    check_ret(syscall!(Syscall::SchedRt, pid as u64, period, budget)).map(|_| ())
+}
+
+/// Take or release an advisory lock on `fd`, `op` is one of `LOCK_*`
+///
+/// waits for the lock unless `LOCK_NB` is given, then fails with `EAGAIN`.
+/// return the errno on failure
+#[inline(always)]
+pub fn sys_flock(fd: u8, op: usize) -> Result<(), usize> {
+    check_ret(syscall!(Syscall::Flock, fd as u64, op)).map(|_| ())
+}
+
+/// Set the file mode creation mask, `None` only reads it
+///
+/// return the old mask, spawned and forked processes start with it
+#[inline(always)]
+pub fn sys_umask(mask: Option<u16>) -> u16 {
+    let mask = mask.map_or(UMASK_KEEP, |mask| mask as usize);
+    syscall!(Syscall::Umask, mask) as u16
+}
+
+/// Read the user mappings of `pid` into `buf`, only root may
+///
+/// return the number of mappings, which may be more than `buf` holds,
+/// or the errno on failure
+#[inline(always)]
+pub fn sys_maps(pid: u16, buf: &mut [MapEntry]) -> Result<usize, usize> {
+    let ret = syscall!(
+        Syscall::Maps,
+        pid as u64,
+        buf.as_mut_ptr() as u64,
+        buf.len() as u64
+    );
+    check_ret(ret)
 }
 
 #[inline(always)]
@@ -79,34 +604,208 @@ pub fn sys_get_pid() -> u16 {
     syscall!(Syscall::GetPid) as u16
 }
 
+/// Send `sig` to process `pid`, 0 only checks that it is alive
+///
+/// fails with `ESRCH` if it is not, see `signal` for what each signal does.
 #[inline(always)]
-pub fn sys_kill(pid: u16) {
-    syscall!(Syscall::Kill, pid as u64);
+pub fn sys_kill(pid: u16, sig: usize) -> Result<(), usize> {
+    check_ret(syscall!(Syscall::Kill, pid as u64, sig as u64)).map(|_| ())
 }
 
+/// Set the handler of `sig` to `handler`, which returns into `restorer`,
+/// unless it is `SIG_KEEP`, return the old handler, see `signal::signal`
+#[inline(always)]
+pub fn sys_sigaction(sig: usize, handler: usize, restorer: usize) -> Result<usize, usize> {
+    check_ret(syscall!(
+        Syscall::Sigaction,
+        sig as u64,
+        handler as u64,
+        restorer as u64
+    ))
+}
+
+/// Stop process `pid` until `sys_resume`, like `SIGSTOP`
+#[inline(always)]
+pub fn sys_suspend(pid: u16) -> bool {
+    check_ret(syscall!(Syscall::Suspend, pid as u64)).is_ok()
+}
+
+/// Let a stopped process run again, like `SIGCONT`
+#[inline(always)]
+pub fn sys_resume(pid: u16) -> bool {
+    check_ret(syscall!(Syscall::Resume, pid as u64)).is_ok()
+}
+
+#[inline(always)]
+pub fn sys_yield() {
+    syscall!(Syscall::Yield);
+}
+
+/// Block for `nanos` nanoseconds of the monotonic clock
+///
+/// it wakes on the first timer tick after, so may sleep a bit longer.
+#[inline(always)]
+pub fn sys_nanosleep(nanos: u64) {
+    syscall!(Syscall::Sleep, nanos as usize);
+}
+
+/// Block for `millisecs` milliseconds, see `sys_nanosleep`
+#[inline(always)]
+pub fn sys_sleep(millisecs: u64) {
+    sys_nanosleep(millisecs.saturating_mul(1_000_000));
+}
+
+/// Fork the current process, returns 0 in the child
+///
+/// panics if the kernel refuses to create the process,
+/// use `sys_try_fork` to handle it
 #[inline(always)]
 pub fn sys_fork() -> u16 {
-    syscall!(Syscall::Fork) as u16
+    sys_try_fork().expect("fork failed")
+}
+
+/// Fork the current process, returns 0 in the child or the errno
+///
+/// what `print!` buffered is written out first, or both would print it.
+#[inline(always)]
+pub fn sys_try_fork() -> Result<u16, usize> {
+    crate::flush_stdout();
+
+    let pid = check_ret(syscall!(Syscall::Fork))? as u16;
+    if pid == 0 {
+        // exit hooks belong to the parent, the child needs its own
+        crate::at_exit(crate::flush_stdout);
+    }
+    Ok(pid)
+}
+
+/// Start a thread running `entry(arg)` with the stack pointer at `stack_top`
+///
+/// returns its tid, see `thread::sys_spawn_thread` to run a closure.
+#[inline(always)]
+pub fn sys_clone(
+    entry: extern "C" fn(usize) -> !,
+    stack_top: usize,
+    arg: usize,
+) -> Result<u16, usize> {
+    let entry = entry as *const () as usize;
+    check_ret(syscall!(Syscall::Clone, entry, stack_top, arg)).map(|tid| tid as u16)
 }
 
-#[inline(always)] 
-pub fn sys_new_sem(key: u32, val: usize) -> bool {
-    syscall!(Syscall::Sem, 0, key as usize, val) == 0
+#[inline(always)]
+pub fn sys_new_sem(key: u32, val: usize) -> Result<(), SemError> {
+    sem_ret(syscall!(Syscall::Sem, SEM_NEW, key as usize, val))
 }
 
 #[inline(always)]
-pub fn sys_del_sem(key: u32) -> bool {
-    syscall!(Syscall::Sem, 1, key as usize, 0) == 0
+pub fn sys_del_sem(key: u32) -> Result<(), SemError> {
+    sem_ret(syscall!(Syscall::Sem, SEM_REMOVE, key as usize, 0))
 }
 
+/// Block while the `u32` at `addr` holds `val`, until woken by `sys_futex_wake`
+///
+/// returns `Err(EAGAIN)` at once if it holds something else. Only threads
+/// sharing memory wait on the same word, see `thread`.
 #[inline(always)]
-pub fn sys_signal_sem(key: u32) {
-    syscall!(Syscall::Sem, 2, key as usize);
+pub fn sys_futex_wait(addr: &AtomicU32, val: u32) -> Result<(), usize> {
+    let addr = addr.as_ptr() as usize;
+    check_ret(syscall!(Syscall::Futex, addr, FUTEX_WAIT, val as usize)).map(|_| ())
 }
 
+/// Wake up to `count` threads waiting on `addr`, returns how many were
 #[inline(always)]
-pub fn sys_wait_sem(key: u32) {
-    syscall!(Syscall::Sem, 3, key as usize);
+pub fn sys_futex_wake(addr: &AtomicU32, count: usize) -> usize {
+    let addr = addr.as_ptr() as usize;
+    check_ret(syscall!(Syscall::Futex, addr, FUTEX_WAKE, count)).unwrap_or(0)
+}
+
+#[inline(always)]
+pub fn sys_signal_sem(key: u32) -> Result<(), SemError> {
+    sem_ret(syscall!(Syscall::Sem, SEM_SIGNAL, key as usize, 0))
+}
+
+#[inline(always)]
+pub fn sys_wait_sem(key: u32) -> Result<(), SemError> {
+    sem_ret(syscall!(Syscall::Sem, SEM_WAIT, key as usize, 0))
+}
+
+/// Take the semaphore only if it is free, `Err(SemError::WouldBlock)` if not
+#[inline(always)]
+pub fn sys_try_wait_sem(key: u32) -> Result<(), SemError> {
+    sem_ret(syscall!(Syscall::Sem, SEM_TRY_WAIT, key as usize, 0))
+}
+
+/// Wait on the semaphore for at most `timeout`, `Err(SemError::TimedOut)` then
+#[inline(always)]
+pub fn sys_wait_sem_timeout(key: u32, timeout: Duration) -> Result<(), SemError> {
+    let nanos = timeout.as_nanos().min(u64::MAX as u128) as usize;
+    sem_ret(syscall!(Syscall::Sem, SEM_WAIT_TIMEOUT, key as usize, nanos))
+}
+
+#[inline(always)]
+fn sem_ret(ret: usize) -> Result<(), SemError> {
+    check_ret(ret).map(|_| ()).map_err(SemError::from_errno)
+}
+
+/// Open shared memory segment `key`, creating it with `size` bytes of zeros
+///
+/// returns its size, `Err(EINVAL)` if it exists with less than `size`.
+/// See `shm::SharedMemory` to open and map it at once.
+#[inline(always)]
+pub fn sys_shm_open(key: u32, size: usize) -> Result<usize, usize> {
+    check_ret(syscall!(Syscall::ShmOpen, key as usize, size))
+}
+
+/// Open message queue `key`, creating it for `capacity` messages of up to
+/// `msg_size` bytes if it does not exist
+///
+/// returns its message size, `Err(EINVAL)` if it exists with less than
+/// `msg_size`. See `ipc::MessageQueue`.
+#[inline(always)]
+pub fn sys_mq_open(key: u32, capacity: usize, msg_size: usize) -> Result<usize, usize> {
+    check_ret(syscall!(Syscall::MqOpen, key as usize, capacity, msg_size))
+}
+
+/// Remove message queue `key`, processes blocked on it get `Err(ENOENT)`
+#[inline(always)]
+pub fn sys_mq_unlink(key: u32) -> Result<(), usize> {
+    check_ret(syscall!(Syscall::MqUnlink, key as usize)).map(|_| ())
+}
+
+/// Send `msg` to queue `key`, blocking while it is full
+#[inline(always)]
+pub fn sys_mq_send(key: u32, msg: &[u8]) -> Result<(), usize> {
+    check_ret(syscall!(Syscall::MqSend, key as usize, msg.as_ptr(), msg.len())).map(|_| ())
+}
+
+/// Receive the next message of queue `key` into `buf`, blocking while it
+/// is empty, returns its length, `Err(EMSGSIZE)` if `buf` is too short
+#[inline(always)]
+pub fn sys_mq_recv(key: u32, buf: &mut [u8]) -> Result<usize, usize> {
+    check_ret(syscall!(Syscall::MqRecv, key as usize, buf.as_mut_ptr(), buf.len()))
+}
+
+/// Map segment `key` at `addr`, or where it fits if `None`, returns the address
+#[inline(always)]
+pub fn sys_shm_map(key: u32, addr: Option<usize>) -> Result<usize, usize> {
+    check_ret(syscall!(Syscall::ShmMap, key as usize, addr.unwrap_or(0)))
+}
+
+/// Unmap the segment mapped at `addr`, it is removed once no process maps it
+#[inline(always)]
+pub fn sys_shm_unmap(addr: usize) -> Result<(), usize> {
+    check_ret(syscall!(Syscall::ShmUnmap, addr)).map(|_| ())
+}
+
+/// Take the framebuffer to draw on and map it, the kernel console stays
+/// off the screen until the process exits
+///
+/// return the errno, `EBUSY` if another process has it.
+#[inline(always)]
+pub fn sys_map_frame_buffer() -> Result<FbInfo, usize> {
+    let mut info = FbInfo::default();
+    let ret = syscall!(Syscall::MapFrameBuffer, &mut info as *mut _ as u64);
+    check_ret(ret).map(|_| info)
 }
 
 #[inline(always)]
@@ -116,4 +815,83 @@ pub fn sys_brk(addr: Option<usize>) -> Option<usize> {
         BRK_FAILED => None,
         ret => Some(ret),
     }
-}
\ No newline at end of file
+}
+
+#[inline(always)]
+pub fn sys_mmap(addr: usize, len: usize, prot: usize, flags: usize) -> Option<usize> {
+    let ret = syscall!(Syscall::Mmap, addr, len, prot, flags, 0, 0);
+    check_ret(ret).ok()
+}
+
+/// Map `len` bytes of the file `fd` from `offset`, a multiple of the page size
+///
+/// pages are read from the file on first access. Writes to a `MAP_SHARED`
+/// mapping go back to the file on `sys_msync`, `sys_munmap` and exit, the
+/// fd must then be opened `O_RDWR` if `prot` has `PROT_WRITE`.
+///
+/// the pages of a `MAP_SHARED` mapping are shared only with the processes
+/// forked after it, not with other mappings of the file, even in the same
+/// process. Those see the writes once they are written back, in the pages
+/// they read after.
+#[inline(always)]
+pub fn sys_mmap_file(
+    addr: usize,
+    len: usize,
+    prot: usize,
+    flags: usize,
+    fd: u8,
+    offset: usize,
+) -> Result<usize, usize> {
+    check_ret(syscall!(Syscall::Mmap, addr, len, prot, flags, fd, offset))
+}
+
+/// Write the dirty pages of shared file mappings in `[addr, addr + len)` back
+#[inline(always)]
+pub fn sys_msync(addr: usize, len: usize, flags: usize) -> Result<(), usize> {
+    check_ret(syscall!(Syscall::Msync, addr, len, flags)).map(|_| ())
+}
+
+/// Change the protection of the pages in `[addr, addr + len)` to `prot`
+///
+/// the pages must be in memory mappings, the stack, the heap or the code.
+/// `PROT_WRITE | PROT_EXEC` is refused unless the `mem.allow_wx` sysctl is 1.
+#[inline(always)]
+pub fn sys_mprotect(addr: usize, len: usize, prot: usize) -> bool {
+    syscall!(Syscall::MProtect, addr, len, prot) == 0
+}
+
+#[inline(always)]
+pub fn sys_munmap(addr: usize, len: usize) -> bool {
+    syscall!(Syscall::Munmap, addr, len) == 0
+}
+
+#[inline(always)]
+pub fn sys_madvise(addr: usize, len: usize, advice: usize) -> bool {
+    syscall!(Syscall::Madvise, addr, len, advice) == 0
+}
+
+/// Read a kernel tunable
+#[inline(always)]
+pub fn sys_sysctl_get(name: &str) -> Option<usize> {
+    let ret = syscall!(Syscall::Sysctl, name.as_ptr(), name.len(), !0usize);
+    check_ret(ret).ok()
+}
+
+/// Set a kernel tunable, returns the old value
+///
+/// only root may, see `sys_set_time`, others get `EPERM`.
+#[inline(always)]
+pub fn sys_sysctl_set(name: &str, value: usize) -> Result<usize, usize> {
+    let ret = syscall!(Syscall::Sysctl, name.as_ptr(), name.len(), value);
+    check_ret(ret)
+}
+
+/// List all kernel tunables as `name = value` lines into `buf`
+///
+/// returns the length of the whole list, which is cut short
+/// if it is longer than `buf`.
+#[inline(always)]
+pub fn sys_sysctl_list(buf: &mut [u8]) -> Option<usize> {
+    let ret = syscall!(Syscall::Sysctl, buf.as_mut_ptr(), 0, buf.len());
+    check_ret(ret).ok()
+}
@@ -0,0 +1,112 @@
+//! Threads, sharing the memory and fds of the process that spawns them
+//!
+//! a thread is a child process made by `Syscall::Clone` on a stack of its
+//! own, allocated here. It runs a closure, leaves the result where the
+//! `JoinHandle` finds it and exits, so joining is waiting for its pid.
+//! Signals and `sys_get_pid` see each thread as a process of its own.
+
+use alloc::boxed::Box;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cell::UnsafeCell;
+
+use crate::{flush_stdout, sys_clone, sys_exit, sys_wait_pid};
+
+/// Bytes of stack a thread gets
+pub const THREAD_STACK_SIZE: usize = 0x10000;
+
+/// Where a thread leaves what its closure returned
+struct Packet<T> {
+    result: UnsafeCell<Option<T>>,
+}
+
+// written by the thread before it exits, read only after it has
+unsafe impl<T: Send> Sync for Packet<T> {}
+
+struct ThreadStart<F, T> {
+    f: F,
+    packet: Arc<Packet<T>>,
+}
+
+extern "C" fn thread_main<F, T>(arg: usize) -> !
+where
+    F: FnOnce() -> T,
+{
+    let start = unsafe { Box::from_raw(arg as *mut ThreadStart<F, T>) };
+    let ThreadStart { f, packet } = *start;
+
+    let ret = f();
+    unsafe { *packet.result.get() = Some(ret) };
+    drop(packet);
+
+    // no exit hooks are run, they belong to the process
+    flush_stdout();
+    sys_exit(0)
+}
+
+/// A thread to wait for, see `join`
+///
+/// dropping it detaches the thread, its stack is never freed then.
+pub struct JoinHandle<T> {
+    tid: u16,
+    packet: Arc<Packet<T>>,
+    /// 16 bytes aligned, as the stack pointer must be
+    stack: Option<Vec<u128>>,
+}
+
+impl<T> JoinHandle<T> {
+    pub fn tid(&self) -> u16 {
+        self.tid
+    }
+
+    /// Wait for the thread to finish, and take what it returned
+    ///
+    /// if it exited without returning, e.g. it panicked or was killed,
+    /// its exit code is returned as the error instead.
+    pub fn join(mut self) -> Result<T, isize> {
+        let code = sys_wait_pid(self.tid);
+        self.stack.take();
+
+        unsafe { (*self.packet.result.get()).take() }.ok_or(code)
+    }
+}
+
+impl<T> Drop for JoinHandle<T> {
+    fn drop(&mut self) {
+        if let Some(stack) = self.stack.take() {
+            core::mem::forget(stack);
+        }
+    }
+}
+
+/// Run `f` in a new thread, returns the errno if it cannot be started
+pub fn sys_spawn_thread<F, T>(f: F) -> Result<JoinHandle<T>, usize>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    let packet = Arc::new(Packet {
+        result: UnsafeCell::new(None),
+    });
+    let mut stack = vec![0u128; THREAD_STACK_SIZE / 16];
+
+    // 8 bytes below a 16 bytes boundary, as if `thread_main` was called
+    let stack_top = stack.as_mut_ptr_range().end as usize - 8;
+    let start = Box::into_raw(Box::new(ThreadStart {
+        f,
+        packet: packet.clone(),
+    }));
+
+    match sys_clone(thread_main::<F, T>, stack_top, start as usize) {
+        Ok(tid) => Ok(JoinHandle {
+            tid,
+            packet,
+            stack: Some(stack),
+        }),
+        Err(errno) => {
+            drop(unsafe { Box::from_raw(start) });
+            Err(errno)
+        }
+    }
+}
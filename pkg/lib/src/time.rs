@@ -0,0 +1,266 @@
+//! Points in time and the durations between them, and the time of day
+//!
+//! an `Instant` is read from the monotonic clock, which never goes
+//! backwards, so it is what timing code should measure with.
+//! `sys_time` is the time of day and may be set or go backwards,
+//! it is UTC, `local_now` is in the time zone of `boot.conf`.
+
+use alloc::string::String;
+use chrono::{DateTime, Datelike, FixedOffset, NaiveDate, NaiveDateTime, NaiveTime, Timelike};
+use core::fmt::Write;
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+
+pub use core::time::Duration;
+
+use crate::{sys_clock_monotonic, sys_nanosleep, sys_time, sys_utc_offset};
+
+/// A point in time on the monotonic clock, only comparable with another
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Instant {
+    nanos: u64,
+}
+
+impl Instant {
+    pub fn now() -> Self {
+        Self {
+            nanos: sys_clock_monotonic(),
+        }
+    }
+
+    /// Nanoseconds of the monotonic clock, for deadlines of syscalls
+    pub(crate) fn as_nanos(&self) -> u64 {
+        self.nanos
+    }
+
+    /// The time from `earlier` to this, zero if `earlier` is later
+    pub fn duration_since(&self, earlier: Instant) -> Duration {
+        Duration::from_nanos(self.nanos.saturating_sub(earlier.nanos))
+    }
+
+    /// The time since this instant
+    pub fn elapsed(&self) -> Duration {
+        Self::now().duration_since(*self)
+    }
+
+    pub fn checked_add(&self, duration: Duration) -> Option<Instant> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        Some(Self {
+            nanos: self.nanos.checked_add(nanos)?,
+        })
+    }
+
+    pub fn checked_sub(&self, duration: Duration) -> Option<Instant> {
+        let nanos = u64::try_from(duration.as_nanos()).ok()?;
+        Some(Self {
+            nanos: self.nanos.checked_sub(nanos)?,
+        })
+    }
+}
+
+impl Add<Duration> for Instant {
+    type Output = Instant;
+
+    /// Saturates, so a deadline too far away is never reached instead of wrapping
+    fn add(self, duration: Duration) -> Instant {
+        self.checked_add(duration)
+            .unwrap_or(Self { nanos: u64::MAX })
+    }
+}
+
+impl AddAssign<Duration> for Instant {
+    fn add_assign(&mut self, duration: Duration) {
+        *self = *self + duration;
+    }
+}
+
+impl Sub<Duration> for Instant {
+    type Output = Instant;
+
+    fn sub(self, duration: Duration) -> Instant {
+        self.checked_sub(duration).unwrap_or(Self { nanos: 0 })
+    }
+}
+
+impl SubAssign<Duration> for Instant {
+    fn sub_assign(&mut self, duration: Duration) {
+        *self = *self - duration;
+    }
+}
+
+impl Sub<Instant> for Instant {
+    type Output = Duration;
+
+    fn sub(self, earlier: Instant) -> Duration {
+        self.duration_since(earlier)
+    }
+}
+
+/// Block until `duration` has passed
+pub fn sleep(duration: Duration) {
+    sys_nanosleep(duration.as_nanos().min(u64::MAX as u128) as u64);
+}
+
+/// The default format of `format`, as `date` prints
+pub const DATE_FORMAT: &str = "%a %b %e %H:%M:%S %Z %Y";
+
+const WEEKDAYS: [&str; 7] = [
+    "Monday",
+    "Tuesday",
+    "Wednesday",
+    "Thursday",
+    "Friday",
+    "Saturday",
+    "Sunday",
+];
+
+const MONTHS: [&str; 12] = [
+    "January",
+    "February",
+    "March",
+    "April",
+    "May",
+    "June",
+    "July",
+    "August",
+    "September",
+    "October",
+    "November",
+    "December",
+];
+
+/// The time zone of local time
+pub fn local_offset() -> FixedOffset {
+    FixedOffset::east_opt(sys_utc_offset()).unwrap_or(FixedOffset::east_opt(0).unwrap())
+}
+
+/// The time of day in the local time zone
+pub fn local_now() -> DateTime<FixedOffset> {
+    sys_time().with_timezone(&local_offset())
+}
+
+/// Format `time` as `strftime` does with `fmt`
+///
+/// knows `%Y %C %y %m %d %e %j %H %I %M %S %p %a %A %b %B %u %s %z %Z
+/// %F %T %R %D %n %t %%`, others are kept as they are.
+pub fn format(time: &DateTime<FixedOffset>, fmt: &str) -> String {
+    let mut out = String::new();
+    let mut chars = fmt.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            out.push(c);
+            continue;
+        }
+
+        let weekday = time.weekday().num_days_from_monday() as usize;
+        let month = time.month0() as usize;
+        let _ = match chars.next() {
+            Some('Y') => write!(out, "{}", time.year()),
+            Some('C') => write!(out, "{:02}", time.year() / 100),
+            Some('y') => write!(out, "{:02}", time.year() % 100),
+            Some('m') => write!(out, "{:02}", time.month()),
+            Some('d') => write!(out, "{:02}", time.day()),
+            Some('e') => write!(out, "{:2}", time.day()),
+            Some('j') => write!(out, "{:03}", time.ordinal()),
+            Some('H') => write!(out, "{:02}", time.hour()),
+            Some('I') => write!(out, "{:02}", time.hour12().1),
+            Some('M') => write!(out, "{:02}", time.minute()),
+            Some('S') => write!(out, "{:02}", time.second()),
+            Some('p') => out.write_str(if time.hour12().0 { "PM" } else { "AM" }),
+            Some('a') => out.write_str(&WEEKDAYS[weekday][..3]),
+            Some('A') => out.write_str(WEEKDAYS[weekday]),
+            Some('b') => out.write_str(&MONTHS[month][..3]),
+            Some('B') => out.write_str(MONTHS[month]),
+            Some('u') => write!(out, "{}", weekday + 1),
+            Some('s') => write!(out, "{}", time.timestamp()),
+            Some('z') => write_offset(&mut out, time.offset(), ""),
+            Some('Z') => match time.offset().local_minus_utc() {
+                0 => out.write_str("UTC"),
+                _ => write_offset(&mut out, time.offset(), ":"),
+            },
+            Some('F') => write!(out, "{}", format(time, "%Y-%m-%d")),
+            Some('T') => write!(out, "{}", format(time, "%H:%M:%S")),
+            Some('R') => write!(out, "{}", format(time, "%H:%M")),
+            Some('D') => write!(out, "{}", format(time, "%m/%d/%y")),
+            Some('n') => out.write_char('\n'),
+            Some('t') => out.write_char('\t'),
+            Some('%') => out.write_char('%'),
+            Some(c) => write!(out, "%{}", c),
+            None => out.write_char('%'),
+        };
+    }
+    out
+}
+
+/// Write `offset` as `+HHMM`, with `sep` between the hours and minutes
+fn write_offset(out: &mut String, offset: &FixedOffset, sep: &str) -> core::fmt::Result {
+    let secs = offset.local_minus_utc();
+    let sign = if secs < 0 { '-' } else { '+' };
+    let mins = secs.unsigned_abs() / 60;
+    write!(out, "{}{:02}{}{:02}", sign, mins / 60, sep, mins % 60)
+}
+
+/// Parse an offset from UTC in seconds, `UTC` or `[+-]HH[:MM]`
+pub fn parse_offset(s: &str) -> Option<i32> {
+    if s.eq_ignore_ascii_case("utc") {
+        return Some(0);
+    }
+
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'+' => (1, &s[1..]),
+        b'-' => (-1, &s[1..]),
+        _ => return None,
+    };
+    let (hours, minutes) = match rest.split_once(':') {
+        Some((hours, minutes)) => (hours, minutes),
+        None if rest.len() == 4 => rest.split_at(2),
+        None => (rest, "0"),
+    };
+
+    let hours = hours.parse::<i32>().ok().filter(|h| (0..=14).contains(h))?;
+    let minutes = minutes
+        .parse::<i32>()
+        .ok()
+        .filter(|m| (0..60).contains(m))?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Parse `YYYY-MM-DD HH:MM[:SS]`, `YYYY-MM-DD` at midnight, or
+/// `HH:MM[:SS]` on `today`
+pub fn parse(s: &str, today: NaiveDate) -> Option<NaiveDateTime> {
+    let s = s.trim();
+    let (date, time) = match s.split_once([' ', 'T']) {
+        Some((date, time)) => (Some(date), Some(time)),
+        None if s.contains('-') => (Some(s), None),
+        None => (None, Some(s)),
+    };
+
+    let date = match date {
+        Some(date) => {
+            let mut fields = date.split('-');
+            let year = fields.next()?.parse().ok()?;
+            let month = fields.next()?.parse().ok()?;
+            let day = fields.next()?.parse().ok()?;
+            if fields.next().is_some() {
+                return None;
+            }
+            NaiveDate::from_ymd_opt(year, month, day)?
+        }
+        None => today,
+    };
+
+    let time = match time {
+        Some(time) => {
+            let mut fields = time.split(':');
+            let hour = fields.next()?.parse().ok()?;
+            let minute = fields.next()?.parse().ok()?;
+            let second = fields.next().map_or(Some(0), |s| s.parse().ok())?;
+            if fields.next().is_some() {
+                return None;
+            }
+            NaiveTime::from_hms_opt(hour, minute, second)?
+        }
+        None => NaiveTime::MIN,
+    };
+
+    Some(date.and_time(time))
+}
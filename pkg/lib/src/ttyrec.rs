@@ -0,0 +1,62 @@
+//! Terminal sessions recorded in the format of `ttyrec`
+//!
+//! a recording is a list of frames, each what was shown at once: a
+//! header of the time it was shown, seconds and microseconds since the
+//! epoch, and the length of the bytes after it, all `u32` little endian.
+
+use alloc::vec::Vec;
+
+/// Bytes of the header of a frame
+pub const HEADER_SIZE: usize = 12;
+
+/// What was shown at once, and when
+#[derive(Clone, Copy, Debug)]
+pub struct Frame<'a> {
+    /// microseconds since the epoch
+    pub time: u64,
+    pub data: &'a [u8],
+}
+
+impl Frame<'_> {
+    /// Append the frame to a recording
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        let (sec, usec) = (self.time / 1_000_000, self.time % 1_000_000);
+        out.extend_from_slice(&(sec as u32).to_le_bytes());
+        out.extend_from_slice(&(usec as u32).to_le_bytes());
+        out.extend_from_slice(&(self.data.len() as u32).to_le_bytes());
+        out.extend_from_slice(self.data);
+    }
+}
+
+/// The frames of a recording, until the end or a frame cut short
+pub struct Frames<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Frames<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    /// Bytes left after the frames read, not a whole frame if not empty
+    pub fn remainder(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl<'a> Iterator for Frames<'a> {
+    type Item = Frame<'a>;
+
+    fn next(&mut self) -> Option<Frame<'a>> {
+        let header = self.data.get(..HEADER_SIZE)?;
+        let field = |i: usize| u32::from_le_bytes(header[i * 4..i * 4 + 4].try_into().unwrap());
+        let len = field(2) as usize;
+        let data = self.data.get(HEADER_SIZE..HEADER_SIZE + len)?;
+
+        self.data = &self.data[HEADER_SIZE + len..];
+        Some(Frame {
+            time: field(0) as u64 * 1_000_000 + field(1) as u64,
+            data,
+        })
+    }
+}
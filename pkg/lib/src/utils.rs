@@ -1,10 +1,6 @@
 use crate::*;
 
-pub fn sleep(millisecs: i64) {
-    let start = sys_time();
-    let dur = Duration::try_milliseconds(millisecs).unwrap();
-    let mut current = start;
-    while current - start < dur {
-        current = sys_time();
-    }
+/// Sleep for `millisecs`, see `time::sleep`
+pub fn sleep(millisecs: u64) {
+    time::sleep(time::Duration::from_millis(millisecs));
 }
@@ -0,0 +1,43 @@
+//! The arguments and environment of a new program
+
+use crate::IoVec;
+
+/// Most bytes of arguments and environment a program is started with,
+/// counting an `IoVec` for each string, so they fit in the top page of its stack
+pub const ARG_MAX: usize = 3072;
+
+/// The argument vector and environment list of `Syscall::Spawn` and `Syscall::Exec`
+///
+/// environment strings are `KEY=value`. The program finds the strings on
+/// top of its stack, with the entry point called as
+/// `_start(argc, argv, envc, envp)`, the vectors again as `IoVec`s.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct ProgramArgs {
+    pub argv: *const IoVec,
+    pub argc: usize,
+    pub envp: *const IoVec,
+    pub envc: usize,
+    /// fd of the caller to use as stdout, 0 keeps the console,
+    /// ignored by `Exec` which keeps every fd
+    pub stdout: usize,
+}
+
+impl ProgramArgs {
+    pub fn new(argv: &[IoVec], envp: &[IoVec]) -> Self {
+        Self {
+            argv: argv.as_ptr(),
+            argc: argv.len(),
+            envp: envp.as_ptr(),
+            envc: envp.len(),
+            stdout: 0,
+        }
+    }
+
+    pub fn with_stdout(self, fd: u8) -> Self {
+        Self {
+            stdout: fd as usize,
+            ..self
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Error numbers returned by syscalls, as `-errno`
+//!
+//! values follow Linux so they stay familiar
+
+/// Operation not permitted
+pub const EPERM: usize = 1;
+/// No such file or directory
+pub const ENOENT: usize = 2;
+/// No such process
+pub const ESRCH: usize = 3;
+/// Input/output error
+pub const EIO: usize = 5;
+/// Argument list too long
+pub const E2BIG: usize = 7;
+/// Exec format error
+pub const ENOEXEC: usize = 8;
+/// Bad file descriptor
+pub const EBADF: usize = 9;
+/// No child processes
+pub const ECHILD: usize = 10;
+/// Resource temporarily unavailable
+pub const EAGAIN: usize = 11;
+/// Out of memory
+pub const ENOMEM: usize = 12;
+/// Permission denied
+pub const EACCES: usize = 13;
+/// Bad address
+pub const EFAULT: usize = 14;
+/// Device or resource busy
+pub const EBUSY: usize = 16;
+/// File exists
+pub const EEXIST: usize = 17;
+/// No such device
+pub const ENODEV: usize = 19;
+/// Not a directory
+pub const ENOTDIR: usize = 20;
+/// Is a directory
+pub const EISDIR: usize = 21;
+/// Invalid argument
+pub const EINVAL: usize = 22;
+/// Too many open files
+pub const EMFILE: usize = 24;
+/// Not a terminal
+pub const ENOTTY: usize = 25;
+/// No space left on device, or no room for another object of its kind
+pub const ENOSPC: usize = 28;
+/// Illegal seek
+pub const ESPIPE: usize = 29;
+/// Read-only file system
+pub const EROFS: usize = 30;
+/// Function not implemented
+pub const ENOSYS: usize = 38;
+/// Message too long
+pub const EMSGSIZE: usize = 90;
+/// Timed out, a wait with a timeout ran out of time
+pub const ETIMEDOUT: usize = 110;
+
+/// Encode `errno` as a syscall return value
+#[inline]
+pub const fn errno_ret(errno: usize) -> usize {
+    (-(errno as isize)) as usize
+}
+
+/// Decode a syscall return value, negative values are `-errno`
+#[inline]
+pub const fn check_ret(ret: usize) -> Result<usize, usize> {
+    if (ret as isize) < 0 {
+        Err((-(ret as isize)) as usize)
+    } else {
+        Ok(ret)
+    }
+}
@@ -0,0 +1,164 @@
+//! Syscall filters apps declare in an ELF note
+//!
+//! an app lists the syscalls it makes in a note in `SYSCALL_NOTE_SECTION`,
+//! the kernel kills it on any other one. Apps without the note may make
+//! any syscall.
+
+use core::ops::BitAnd;
+
+/// Section of the note, kept by the app linker script
+pub const SYSCALL_NOTE_SECTION: &str = ".note.ysos.syscalls";
+/// Owner of the note, padded to 4 bytes
+pub const NOTE_NAME: [u8; 8] = *b"ysos\0\0\0\0";
+/// Type of the note, its descriptor is a `SyscallSet`
+pub const NT_SYSCALLS: u32 = 1;
+
+/// Exit code of a process killed for a syscall it did not declare,
+/// as a shell reports a process killed by `SIGSYS`
+pub const EXIT_SYSCALL_DENIED: usize = 128 + 31;
+
+/// Syscall numbers below this are the Linux ones
+const LOW_COUNT: usize = 512;
+/// Syscall numbers from this up are the ones of this kernel
+const HIGH_BASE: usize = 65536 - 64;
+
+const WORDS: usize = (LOW_COUNT + 64) / 32;
+
+/// A set of syscall numbers, as a bitmap
+///
+/// it covers the Linux numbers below 512 and the 64 numbers
+/// below 65536 this kernel uses, others are never contained.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyscallSet {
+    bits: [u32; WORDS],
+}
+
+impl SyscallSet {
+    pub const fn empty() -> Self {
+        Self { bits: [0; WORDS] }
+    }
+
+    /// A set of the syscall numbers in `nums`
+    pub const fn from_nums(nums: &[u16]) -> Self {
+        let mut set = Self::empty();
+        let mut i = 0;
+        while i < nums.len() {
+            set = set.with(nums[i] as usize);
+            i += 1;
+        }
+        set
+    }
+
+    /// The set with `num` added, if it can hold it
+    pub const fn with(mut self, num: usize) -> Self {
+        if let Some(bit) = bit_of(num) {
+            self.bits[bit / 32] |= 1 << (bit % 32);
+        }
+        self
+    }
+
+    pub const fn union(mut self, other: Self) -> Self {
+        let mut i = 0;
+        while i < WORDS {
+            self.bits[i] |= other.bits[i];
+            i += 1;
+        }
+        self
+    }
+
+    pub const fn contains(&self, num: usize) -> bool {
+        match bit_of(num) {
+            Some(bit) => self.bits[bit / 32] & (1 << (bit % 32)) != 0,
+            None => false,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.bits.iter().map(|word| word.count_ones() as usize).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The syscall numbers in the set, in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..WORDS * 32)
+            .filter(|bit| self.bits[bit / 32] & (1 << (bit % 32)) != 0)
+            .map(|bit| match bit {
+                bit if bit < LOW_COUNT => bit,
+                bit => HIGH_BASE + bit - LOW_COUNT,
+            })
+    }
+
+    /// Find the set in the notes of `data`, a note section
+    pub fn from_note(mut data: &[u8]) -> Option<Self> {
+        while data.len() >= 12 {
+            let word = |i: usize| u32::from_le_bytes(data[i * 4..i * 4 + 4].try_into().unwrap());
+            let (name_len, desc_len, ty) = (word(0) as usize, word(1) as usize, word(2));
+
+            let desc_start = 12 + name_len.next_multiple_of(4);
+            let next = desc_start.checked_add(desc_len.next_multiple_of(4))?;
+            let name = data.get(12..12 + name_len)?;
+            let desc = data.get(desc_start..desc_start + desc_len)?;
+
+            if name == &NOTE_NAME[..name_len.min(8)]
+                && ty == NT_SYSCALLS
+                && desc.len() == WORDS * 4
+            {
+                let mut set = Self::empty();
+                for (word, bytes) in set.bits.iter_mut().zip(desc.as_chunks::<4>().0) {
+                    *word = u32::from_le_bytes(*bytes);
+                }
+                return Some(set);
+            }
+
+            data = data.get(next..)?;
+        }
+        None
+    }
+}
+
+impl BitAnd for SyscallSet {
+    type Output = Self;
+
+    fn bitand(mut self, other: Self) -> Self {
+        for (word, other) in self.bits.iter_mut().zip(other.bits.iter()) {
+            *word &= other;
+        }
+        self
+    }
+}
+
+const fn bit_of(num: usize) -> Option<usize> {
+    if num < LOW_COUNT {
+        Some(num)
+    } else if num >= HIGH_BASE && num < 65536 {
+        Some(LOW_COUNT + num - HIGH_BASE)
+    } else {
+        None
+    }
+}
+
+/// The ELF note holding a `SyscallSet`, see `lib::allow_syscalls!`
+#[repr(C)]
+pub struct SyscallNote {
+    name_len: u32,
+    desc_len: u32,
+    ty: u32,
+    name: [u8; 8],
+    desc: SyscallSet,
+}
+
+impl SyscallNote {
+    pub const fn new(set: SyscallSet) -> Self {
+        Self {
+            name_len: 5,
+            desc_len: core::mem::size_of::<SyscallSet>() as u32,
+            ty: NT_SYSCALLS,
+            name: NOTE_NAME,
+            desc: set,
+        }
+    }
+}
@@ -0,0 +1,148 @@
+/// Maximum number of buffers accepted by a single `readv`/`writev`
+pub const IOV_MAX: usize = 1024;
+
+/// A buffer descriptor for vectored I/O
+///
+/// Layout is shared by the kernel and user space, see `Syscall::ReadV`
+/// and `Syscall::WriteV`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct IoVec {
+    pub base: *const u8,
+    pub len: usize,
+}
+
+impl IoVec {
+    pub fn new(buf: &[u8]) -> Self {
+        Self {
+            base: buf.as_ptr(),
+            len: buf.len(),
+        }
+    }
+
+    pub fn new_mut(buf: &mut [u8]) -> Self {
+        Self {
+            base: buf.as_mut_ptr(),
+            len: buf.len(),
+        }
+    }
+}
+
+/// Get the rate limit of a fd, see `Syscall::Fcntl`
+pub const F_GETRATE: usize = 0x400;
+/// Set the rate limit of a fd in bytes per second, 0 removes it
+pub const F_SETRATE: usize = 0x401;
+/// Get whether the console or a pty is in raw mode, see `Syscall::Fcntl`
+pub const F_GETRAW: usize = 0x402;
+/// Put the console or a pty in raw mode with 1, or back with 0
+///
+/// in raw mode serial input is passed on untouched, without looking for
+/// the monitor break key, and output is sent as bytes. Only one process
+/// has it at a time, and it is left when that process exits.
+///
+/// a pty in raw mode passes what the master writes to the slave as it
+/// is, without editing lines or echoing them, see `Syscall::OpenPty`.
+pub const F_SETRAW: usize = 0x403;
+/// Get the size of a pty, `cols | rows << 16`
+pub const F_GETWINSZ: usize = 0x404;
+/// Set the size of a pty to `cols | rows << 16`, return the old one
+pub const F_SETWINSZ: usize = 0x405;
+
+/// Access modes of `Syscall::Open`, one of them is or'ed with the flags
+pub const O_RDONLY: usize = 0;
+pub const O_WRONLY: usize = 1;
+pub const O_RDWR: usize = 2;
+pub const O_ACCMODE: usize = 3;
+/// Flags of `Syscall::Open`, numbered as Linux does
+///
+/// create the file if there is none
+pub const O_CREAT: usize = 0o100;
+/// Empty the file if it is opened for writing
+pub const O_TRUNC: usize = 0o1000;
+/// Write at the end of the file, wherever the position is
+pub const O_APPEND: usize = 0o2000;
+
+/// Whence of `Syscall::Seek`: from the start, the position or the end
+pub const SEEK_SET: usize = 0;
+pub const SEEK_CUR: usize = 1;
+pub const SEEK_END: usize = 2;
+
+/// Kinds of the resource behind a fd, see `FdStat::kind`
+pub const FD_KIND_OTHER: u32 = 0;
+pub const FD_KIND_FILE: u32 = 1;
+pub const FD_KIND_PIPE: u32 = 2;
+pub const FD_KIND_CONSOLE: u32 = 3;
+pub const FD_KIND_PTY: u32 = 4;
+
+/// A fd and the events `Syscall::Poll` waits for on it
+///
+/// the kernel sets `revents` to the ones that are ready, or to `POLLNVAL`
+/// if the fd is not open. Laid out as `struct pollfd` of Linux.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PollFd {
+    pub fd: u32,
+    pub events: u16,
+    pub revents: u16,
+}
+
+impl PollFd {
+    pub const fn new(fd: u8, events: u16) -> Self {
+        Self {
+            fd: fd as u32,
+            events,
+            revents: 0,
+        }
+    }
+}
+
+/// Events of `PollFd`: a read, or a write, would not block, so a read
+/// of 0 bytes is the end of the input
+pub const POLLIN: u16 = 0x1;
+pub const POLLOUT: u16 = 0x4;
+/// Set in `revents` of a fd that is not open
+pub const POLLNVAL: u16 = 0x20;
+/// Most fds a single `Syscall::Poll` takes
+pub const POLL_MAX: usize = 256;
+/// Deadline of `Syscall::Poll` to wait for as long as it takes
+pub const POLL_FOREVER: u64 = u64::MAX;
+
+/// I/O statistics of a fd, returned by `Syscall::Fstat`
+///
+/// forked processes share their fds, and so the statistics.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FdStat {
+    pub read_bytes: u64,
+    pub read_ops: u64,
+    pub write_bytes: u64,
+    pub write_ops: u64,
+    /// Operations cut short by the rate limit
+    pub throttled: u64,
+    /// Bytes per second, 0 means unlimited
+    pub rate: u64,
+    /// One of the `FD_KIND_*`
+    pub kind: u32,
+    /// Bytes of a file, 0 for the other kinds
+    pub size: u64,
+    /// Where the next read or write of a file starts
+    pub pos: u64,
+}
+
+/// File mode bits a new process starts masking off, see `Syscall::Umask`
+pub const DEFAULT_UMASK: u16 = 0o022;
+/// The permission bits of a file mode
+pub const MODE_PERM_MASK: u16 = 0o777;
+/// Read the file mode creation mask without changing it
+pub const UMASK_KEEP: usize = usize::MAX;
+
+/// Operations of `Syscall::Flock`
+///
+/// take a shared lock
+pub const LOCK_SH: usize = 1;
+/// Take an exclusive lock
+pub const LOCK_EX: usize = 2;
+/// Or'ed with `LOCK_SH` or `LOCK_EX` to fail with `EAGAIN` instead of waiting
+pub const LOCK_NB: usize = 4;
+/// Release the lock
+pub const LOCK_UN: usize = 8;
@@ -2,32 +2,164 @@
 
 use num_enum::FromPrimitive;
 
+pub mod args;
+pub mod errno;
+pub mod filter;
+pub mod io;
 pub mod macros;
+pub mod mm;
+pub mod sched;
+pub mod signal;
+pub mod time;
+pub mod trace;
 
-#[repr(usize)]
-#[derive(Clone, Debug, FromPrimitive)]
-pub enum Syscall {
-    Read = 0,
-    Write = 1,
+pub use args::*;
+pub use errno::*;
+pub use filter::*;
+pub use io::*;
+pub use mm::*;
+pub use sched::*;
+pub use signal::*;
+pub use time::*;
+pub use trace::*;
 
-    Brk = 12,
+/// Call `$callback!` with every syscall, as `Name(arity) = number, ...`
+///
+/// the one list the kernel dispatcher, the `Syscall` enum and the stubs
+/// of `lib` are all generated from. Numbers follow Linux where there is
+/// a match, the ones of YSOS count down from 65535. A number is never
+/// reused once given out, so old programs keep running on new kernels;
+/// a syscall taken out leaves a gap, which the kernel answers with ENOSYS.
+/// The arity is how many of the arguments, `arg0` to `arg5`, it takes.
+#[macro_export]
+macro_rules! for_each_syscall {
+    ($callback:ident) => {
+        $callback! {
+            Read(3) = 0,
+            Write(3) = 1,
+            Open(3) = 2,
+            Close(1) = 3,
+            Fstat(2) = 5,
+            Poll(3) = 7,
+            Seek(3) = 8,
 
-    GetPid = 39,
+            Mmap(6) = 9,
+            MProtect(3) = 10,
+            Munmap(2) = 11,
+            Brk(1) = 12,
 
-    Fork = 58,
-    Spawn = 59,
-    Exit = 60,
-    WaitPid = 61,
-    Kill = 62,
-    Sem = 63,
+            Sigaction(3) = 13,
+            SigReturn(0) = 15,
 
-    Time = 201,
+            ReadV(3) = 19,
+            WriteV(3) = 20,
 
-    ListApp = 65529,
-    Stat = 65530,
-    Allocate = 65533,
-    Deallocate = 65534,
+            Pipe(0) = 22,
 
-    #[num_enum(default)]
-    None = 65535,
+            Yield(0) = 24,
+
+            Msync(3) = 26,
+
+            Sleep(1) = 35,
+
+            Madvise(3) = 28,
+            ShmOpen(2) = 29,
+            ShmMap(2) = 30,
+
+            Dup2(2) = 33,
+
+            GetPid(0) = 39,
+            SendFile(4) = 40,
+
+            Clone(3) = 56,
+            Fork(0) = 58,
+            Spawn(3) = 59,
+            Exit(1) = 60,
+            WaitPid(3) = 61,
+            Kill(2) = 62,
+            Sem(3) = 63,
+            ShmUnmap(1) = 67,
+
+            Fcntl(3) = 72,
+            Flock(2) = 73,
+
+            Umask(1) = 95,
+
+            GetRusage(2) = 98,
+            Sysinfo(1) = 99,
+
+            Sysctl(3) = 156,
+
+            Mount(1) = 165,
+            SwapOn(2) = 167,
+
+            Shutdown(1) = 169,
+
+            Time(0) = 201,
+            Futex(3) = 202,
+
+            MqOpen(3) = 240,
+            MqUnlink(1) = 241,
+            MqSend(3) = 242,
+            MqRecv(3) = 243,
+
+            TimerFd(2) = 283,
+            EventFd(1) = 284,
+
+            Prlimit(3) = 302,
+
+            GetRandom(2) = 318,
+            MemFd(0) = 319,
+
+            MapFrameBuffer(1) = 65512,
+            ProcInfo(2) = 65513,
+            SchedRt(3) = 65514,
+            OpenPty(0) = 65515,
+            SetPriority(2) = 65516,
+            UtcOffset(1) = 65517,
+            SetTime(1) = 65518,
+            ClockMonotonic(0) = 65519,
+            Renice(2) = 65520,
+            Nice(1) = 65521,
+            Resume(1) = 65522,
+            Suspend(1) = 65523,
+            Exec(3) = 65524,
+            HostOpen(2) = 65525,
+            Maps(3) = 65526,
+            SchedStat(3) = 65527,
+            SpawnTraced(3) = 65528,
+            ListApp(0) = 65529,
+            Stat(0) = 65530,
+            Allocate(1) = 65533,
+            Deallocate(2) = 65534,
+        }
+    };
 }
+
+macro_rules! define_syscalls {
+    ($($name:ident($arity:literal) = $num:literal,)*) => {
+        #[repr(usize)]
+        #[derive(Clone, Copy, Debug, PartialEq, Eq, FromPrimitive)]
+        pub enum Syscall {
+            $($name = $num,)*
+            /// any number not given out
+            #[num_enum(default)]
+            None = 65535,
+        }
+
+        impl Syscall {
+            /// Every syscall, in the order they are listed
+            pub const ALL: &'static [Syscall] = &[$(Syscall::$name,)*];
+
+            /// Arguments the syscall takes, the registers after are ignored
+            pub const fn arity(self) -> usize {
+                match self {
+                    $(Syscall::$name => $arity,)*
+                    Syscall::None => 0,
+                }
+            }
+        }
+    };
+}
+
+for_each_syscall!(define_syscalls);
@@ -0,0 +1,114 @@
+/// Pages may be read
+pub const PROT_READ: usize = 0x1;
+/// Pages may be written
+pub const PROT_WRITE: usize = 0x2;
+/// Pages may be executed
+pub const PROT_EXEC: usize = 0x4;
+
+/// Share the mapping with forked processes
+pub const MAP_SHARED: usize = 0x01;
+/// Keep the mapping private to the process
+pub const MAP_PRIVATE: usize = 0x02;
+/// Place the mapping exactly at the given address
+pub const MAP_FIXED: usize = 0x10;
+/// The mapping is not backed by any file
+pub const MAP_ANONYMOUS: usize = 0x20;
+
+/// Returned by `mmap` on failure, before it returned `-errno`
+pub const MAP_FAILED: usize = !0;
+
+/// Schedule the dirty pages to be written back, done at once like `MS_SYNC`
+pub const MS_ASYNC: usize = 1;
+/// Write the dirty pages back before returning
+pub const MS_SYNC: usize = 4;
+
+/// Fill the pages now instead of on first access
+pub const MADV_WILLNEED: usize = 3;
+/// Drop the pages, they read as zero on next access
+pub const MADV_DONTNEED: usize = 4;
+/// Fill the pages and keep them resident until unpinned
+pub const MADV_PIN: usize = 0x100;
+/// Allow pinned pages to be dropped again
+pub const MADV_UNPIN: usize = 0x101;
+
+/// Bytes of the largest segment of `Syscall::ShmOpen`, 4 MiB
+pub const SHM_MAX_SIZE: usize = 4 << 20;
+
+/// Pack protection and mapping flags into a single syscall argument
+///
+/// the form `Syscall::Mmap` took when the ABI only carried three
+/// arguments, `prot` in the low byte and `flags` in the next one.
+/// The kernel still takes it when the fourth argument is 0.
+#[inline]
+pub const fn mmap_flags(prot: usize, flags: usize) -> usize {
+    (prot & 0xff) | ((flags & 0xff) << 8)
+}
+
+/// Split a packed argument built by [`mmap_flags`] into `(prot, flags)`
+#[inline]
+pub const fn split_mmap_flags(packed: usize) -> (usize, usize) {
+    (packed & 0xff, (packed >> 8) & 0xff)
+}
+
+/// Memory and swap of the system, returned by `Syscall::Sysinfo`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Sysinfo {
+    /// Bytes of memory usable by the kernel and the processes
+    pub total_ram: u64,
+    pub free_ram: u64,
+    /// Bytes of memory mapped by more than one process
+    pub shared_ram: u64,
+    /// Bytes of the swap area, 0 without one
+    pub total_swap: u64,
+    pub free_swap: u64,
+    /// Pages swapped out since boot
+    pub swap_outs: u64,
+    /// Pages swapped back in since boot
+    pub swap_ins: u64,
+}
+
+/// Pixel formats of `FbInfo`, the order of the bytes of a 32 bit pixel
+pub const FB_FORMAT_RGB: u32 = 0;
+pub const FB_FORMAT_BGR: u32 = 1;
+
+/// The framebuffer mapped by `Syscall::MapFrameBuffer`
+///
+/// the process has the screen to itself until it exits, the kernel
+/// console draws its lines again then.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FbInfo {
+    /// Where the first pixel is mapped
+    pub addr: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Pixels from the start of a row to the next, at least `width`
+    pub stride: u32,
+    /// One of the `FB_FORMAT_*` formats
+    pub format: u32,
+}
+
+/// The pages are writable
+pub const PAGE_WRITABLE: u64 = 1 << 1;
+/// The pages are accessible from user space
+pub const PAGE_USER: u64 = 1 << 2;
+/// The pages are mapped by a 2 MiB or 1 GiB entry
+pub const PAGE_HUGE: u64 = 1 << 7;
+/// The pages cannot be executed
+pub const PAGE_NO_EXECUTE: u64 = 1 << 63;
+
+/// A mapping returned by `Syscall::Maps`
+///
+/// virtually and physically contiguous pages with the same flags.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MapEntry {
+    pub start: u64,
+    /// Exclusive end of the mapping
+    pub end: u64,
+    pub phys: u64,
+    pub page_size: u64,
+    /// Page table entry flags, see `PAGE_*`
+    pub flags: u64,
+}
@@ -0,0 +1,220 @@
+/// Scheduling events kept for each process
+pub const SCHED_HISTORY_LEN: usize = 32;
+
+/// The process was put on the ready queue, `arg` is the level it is on,
+/// `SCHED_LEVEL_RT` for the real-time class
+pub const SCHED_ENQUEUE: u32 = 1;
+/// The process was picked to run
+pub const SCHED_DISPATCH: u32 = 2;
+/// The process was switched out while it could still run
+pub const SCHED_PREEMPT: u32 = 3;
+/// The process gave up the cpu with `yield`
+pub const SCHED_YIELD: u32 = 4;
+/// The process was blocked, `arg` is one of the `BLOCK_*` reasons
+pub const SCHED_BLOCK: u32 = 5;
+/// The process exited, `arg` is the low half of the exit code
+pub const SCHED_EXIT: u32 = 6;
+/// The process was stopped by `Suspend` or Ctrl+Z
+pub const SCHED_STOP: u32 = 7;
+/// The process used up its real-time budget, it waits for its next period
+pub const SCHED_THROTTLE: u32 = 8;
+
+/// The level of `SCHED_ENQUEUE` for a process of the real-time class
+pub const SCHED_LEVEL_RT: u32 = u32::MAX;
+
+/// Blocked waiting for another process to exit
+pub const BLOCK_WAIT_PID: u32 = 1;
+/// Blocked on a semaphore
+pub const BLOCK_SEM: u32 = 2;
+/// Blocked waiting for a file lock
+pub const BLOCK_FLOCK: u32 = 3;
+/// Blocked reading an empty pipe or writing a full one
+pub const BLOCK_PIPE: u32 = 4;
+/// Blocked until a disk reads the block a syscall needs
+pub const BLOCK_DISK: u32 = 5;
+/// Blocked in `Syscall::Sleep` until its deadline
+pub const BLOCK_SLEEP: u32 = 6;
+/// Blocked in `Syscall::Futex` until woken
+pub const BLOCK_FUTEX: u32 = 7;
+/// Blocked sending to a full message queue or receiving from an empty one
+pub const BLOCK_MQ: u32 = 8;
+/// Blocked in `Syscall::Poll` until a fd is ready or its deadline
+pub const BLOCK_POLL: u32 = 9;
+
+/// Exit code of a process killed for exceeding its cpu time limit,
+/// as a shell reports a process killed by `SIGXCPU`
+pub const EXIT_CPU_LIMIT: usize = 128 + 24;
+
+/// Bounds of the niceness of a process, the lower the more cpu it gets
+pub const NICE_MIN: isize = -20;
+pub const NICE_MAX: isize = 19;
+/// `Syscall::Nice` and `Syscall::Renice` return `NICE_BIAS - nice`,
+/// which is never negative, as `getpriority` does
+pub const NICE_BIAS: isize = 20;
+
+/// Levels of the scheduler, a priority is one of them, 0 the highest
+pub const PRIO_LEVELS: usize = 4;
+/// Passed as the new priority to `Syscall::SetPriority` to only read it
+pub const PRIO_KEEP: usize = usize::MAX;
+
+/// Flag of `Syscall::WaitPid`: return when the process stops too, like `WUNTRACED`
+pub const WAIT_UNTRACED: usize = 1;
+/// Flag of `Syscall::WaitPid`: return `WAIT_RUNNING` instead of blocking, like `WNOHANG`
+pub const WAIT_NOHANG: usize = 2;
+/// Pid of `Syscall::WaitPid` to wait for any child to exit, like `-1`
+pub const WAIT_ANY: usize = usize::MAX;
+/// What `Syscall::WaitPid` returns for a process that stopped,
+/// no process may exit with it
+pub const WAIT_STOPPED: isize = isize::MIN;
+/// What `Syscall::WaitPid` returns with `WAIT_NOHANG` while nothing has
+/// exited yet, no process may exit with it either
+pub const WAIT_RUNNING: isize = isize::MIN + 1;
+
+/// How a process waited for with `WAIT_UNTRACED` changed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WaitStatus {
+    Exited(isize),
+    Stopped,
+}
+
+impl WaitStatus {
+    pub fn from_ret(ret: isize) -> Self {
+        match ret {
+            WAIT_STOPPED => Self::Stopped,
+            code => Self::Exited(code),
+        }
+    }
+}
+
+/// Resources of `Syscall::Prlimit`
+///
+/// cpu time in seconds, 0 means unlimited
+pub const RLIMIT_CPU: usize = 0;
+/// Passed as the new limit to only read the current one
+pub const RLIMIT_KEEP: usize = usize::MAX;
+
+/// Operations of `Syscall::Sem`, passed as the first argument
+pub const SEM_NEW: usize = 0;
+pub const SEM_REMOVE: usize = 1;
+pub const SEM_SIGNAL: usize = 2;
+pub const SEM_WAIT: usize = 3;
+/// Take the semaphore only if it is free, `EAGAIN` if not
+pub const SEM_TRY_WAIT: usize = 4;
+/// Wait at most the nanoseconds in the third argument, `ETIMEDOUT` then
+pub const SEM_WAIT_TIMEOUT: usize = 5;
+
+/// Operations of `Syscall::Futex`, passed as the second argument
+///
+/// block while the `u32` at the address still holds the value
+pub const FUTEX_WAIT: usize = 0;
+/// Wake up to the value of processes waiting on the address
+pub const FUTEX_WAKE: usize = 1;
+
+/// Most messages a queue of `Syscall::MqOpen` may hold
+pub const MQ_MAX_MESSAGES: usize = 64;
+/// Bytes of the largest message of a queue
+pub const MQ_MAX_MSG_SIZE: usize = 4096;
+/// Message queues there may be at once
+pub const MQ_MAX_QUEUES: usize = 64;
+
+/// The resources a process used, returned by `Syscall::GetRusage`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Rusage {
+    /// Nanoseconds of cpu time, in both user and kernel mode
+    pub cpu_time: u64,
+    /// Timer ticks run
+    pub ticks: u64,
+    /// Instructions retired, 0 without performance counters
+    pub instructions: u64,
+    /// Cpu cycles while not halted, 0 without performance counters
+    pub cycles: u64,
+}
+
+/// Bytes of the name in `ProcInfo`, a longer one is cut short
+pub const PROC_NAME_LEN: usize = 16;
+
+/// States of a process, see `ProcInfo::state`
+pub const PROC_RUNNING: u32 = 0;
+pub const PROC_READY: u32 = 1;
+pub const PROC_BLOCKED: u32 = 2;
+/// stopped by `Syscall::Suspend` or a signal, until resumed
+pub const PROC_STOPPED: u32 = 3;
+/// exited and not yet waited for
+pub const PROC_DEAD: u32 = 4;
+
+/// What a process is and what it used, returned by `Syscall::ProcInfo`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ProcInfo {
+    pub pid: u16,
+    /// 0 for the kernel
+    pub ppid: u16,
+    /// One of the `PROC_*` states
+    pub state: u32,
+    /// The name, padded with NULs
+    pub name: [u8; PROC_NAME_LEN],
+    /// Timer ticks that came while it ran in user mode
+    pub user_ticks: u64,
+    /// Timer ticks that came while it ran in the kernel, in a syscall
+    pub kernel_ticks: u64,
+    /// Nanoseconds of cpu time in user mode, the total split by the ticks
+    pub user_time: u64,
+    /// Nanoseconds of cpu time in the kernel
+    pub kernel_time: u64,
+    /// Bytes of memory mapped
+    pub memory: u64,
+}
+
+impl ProcInfo {
+    /// The name up to the padding
+    pub fn name(&self) -> &str {
+        let len = self
+            .name
+            .iter()
+            .position(|&b| b == 0)
+            .unwrap_or(PROC_NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("?")
+    }
+}
+
+/// A scheduling event returned by `Syscall::SchedStat`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SchedEvent {
+    /// Global order of the event
+    pub seq: u64,
+    /// Timer ticks since boot
+    pub tick: u64,
+    /// One of the `SCHED_*` kinds
+    pub kind: u32,
+    /// Extra data depending on `kind`
+    pub arg: u32,
+}
+
+impl SchedEvent {
+    pub fn kind_name(&self) -> &'static str {
+        match self.kind {
+            SCHED_ENQUEUE => "enqueue",
+            SCHED_DISPATCH => "dispatch",
+            SCHED_PREEMPT => "preempt",
+            SCHED_YIELD => "yield",
+            SCHED_BLOCK => match self.arg {
+                BLOCK_WAIT_PID => "block (wait pid)",
+                BLOCK_SEM => "block (semaphore)",
+                BLOCK_FLOCK => "block (file lock)",
+                BLOCK_PIPE => "block (pipe)",
+                BLOCK_DISK => "block (disk)",
+                BLOCK_SLEEP => "block (sleep)",
+                BLOCK_FUTEX => "block (futex)",
+                BLOCK_MQ => "block (message queue)",
+                BLOCK_POLL => "block (poll)",
+                _ => "block",
+            },
+            SCHED_EXIT => "exit",
+            SCHED_STOP => "stop",
+            SCHED_THROTTLE => "throttle",
+            _ => "unknown",
+        }
+    }
+}
@@ -0,0 +1,55 @@
+/// Signals are numbered `1..NSIG`, as on Linux
+pub const NSIG: usize = 32;
+
+pub const SIGINT: usize = 2;
+pub const SIGKILL: usize = 9;
+pub const SIGSEGV: usize = 11;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+pub const SIGCONT: usize = 18;
+pub const SIGSTOP: usize = 19;
+
+/// The handler of `Syscall::Sigaction` to do what the signal does by default
+pub const SIG_DFL: usize = 0;
+/// The handler of `Syscall::Sigaction` to ignore the signal
+pub const SIG_IGN: usize = 1;
+/// Passed as the handler to `Syscall::Sigaction` to only read the current one
+pub const SIG_KEEP: usize = usize::MAX;
+
+/// What a signal does to a process that has no handler for it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SigDefault {
+    /// exit with `exit_code_of` the signal
+    Terminate,
+    Ignore,
+    /// stop until `SIGCONT`, like `Syscall::Suspend`
+    Stop,
+    /// run again if stopped, like `Syscall::Resume`
+    Continue,
+}
+
+impl SigDefault {
+    pub fn of(sig: usize) -> Self {
+        match sig {
+            SIGCHLD => Self::Ignore,
+            SIGSTOP => Self::Stop,
+            SIGCONT => Self::Continue,
+            _ => Self::Terminate,
+        }
+    }
+}
+
+/// Check if `sig` is a signal
+pub const fn is_signal(sig: usize) -> bool {
+    sig > 0 && sig < NSIG
+}
+
+/// Check if a handler may be set for `sig`, it may not for `SIGKILL` and `SIGSTOP`
+pub const fn can_catch(sig: usize) -> bool {
+    is_signal(sig) && sig != SIGKILL && sig != SIGSTOP
+}
+
+/// Exit code of a process killed by `sig`, as a shell reports it
+pub const fn exit_code_of(sig: usize) -> usize {
+    128 + sig
+}
@@ -0,0 +1,7 @@
+/// Furthest local time may be from UTC, in seconds
+pub const UTC_OFFSET_MAX: i32 = 24 * 3600;
+/// `Syscall::UtcOffset` returns `UTC_OFFSET_BIAS + offset`,
+/// which is never negative
+pub const UTC_OFFSET_BIAS: isize = UTC_OFFSET_MAX as isize;
+/// Passed as the new offset to only read the current one
+pub const UTC_OFFSET_KEEP: usize = usize::MAX;
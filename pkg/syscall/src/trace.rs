@@ -0,0 +1,4 @@
+/// Record the syscall results of the spawned process
+pub const TRACE_RECORD: usize = 1;
+/// Answer the spawned process from the trace recorded for the same app
+pub const TRACE_REPLAY: usize = 2;
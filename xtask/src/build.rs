@@ -0,0 +1,211 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::golden::{self, GOLDEN_NAME, TEST_DIR};
+use crate::manifest::{self, Package};
+use crate::{debug, info, workspace_root, Options, Task};
+
+fn cargo() -> Command {
+    Command::new(std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into()))
+}
+
+/// Build the bootloader, kernel and apps, and assemble the ESP
+pub fn build(options: &Options) -> Result<(), String> {
+    let root = workspace_root();
+    let target = root.join("target");
+    let esp = &options.esp;
+
+    // build uefi boot loader
+    info("Building", "bootloader...");
+    options.execute_ok(
+        cargo()
+            .args(["build", "--release"])
+            .current_dir(root.join("pkg").join("boot")),
+    )?;
+    copy_to_esp(
+        options,
+        &target.join("x86_64-unknown-uefi/release/ysos_boot.efi"),
+        &esp.join("EFI/BOOT/BOOTX64.EFI"),
+    )?;
+
+    // copy kernel config
+    let config = root.join("pkg/kernel/config/boot.conf");
+    if config.exists() {
+        let dst = esp.join("EFI/BOOT/boot.conf");
+        copy_to_esp(options, &config, &dst)?;
+        if options.task == Task::Test {
+            test_config(options, &dst)?;
+        }
+    }
+
+    // the console font, for apps that draw text with `lib::gfx::text`
+    copy_to_esp(
+        options,
+        &root.join("pkg/kernel/assets/font.psf"),
+        &esp.join("FONTS/DEFAULT.PSF"),
+    )?;
+
+    let (profile_args, profile_dir) = if options.debug_info {
+        (["--profile=release-with-debug"], "release-with-debug")
+    } else {
+        (["--release"], "release")
+    };
+
+    // build kernel
+    info("Building", "kernel...");
+    let mut kernel = cargo();
+    kernel.arg("build").args(profile_args);
+    if let Some(features) = &options.features {
+        kernel.args(["--no-default-features", "--features", features]);
+    }
+    options.execute_ok(kernel.current_dir(root.join("pkg").join("kernel")))?;
+    copy_to_esp(
+        options,
+        &target
+            .join("x86_64-unknown-none")
+            .join(profile_dir)
+            .join("ysos_kernel"),
+        &esp.join("KERNEL.ELF"),
+    )?;
+
+    // golden outputs of apps removed since the last build must not stay
+    let tests = esp.join(TEST_DIR);
+    if tests.exists() && !options.dry_run {
+        fs::remove_dir_all(&tests).map_err(|err| err.to_string())?;
+    }
+
+    // build apps
+    let mut entries = Vec::new();
+    for app in apps(&root)? {
+        let app_path = root.join("pkg").join("app").join(&app);
+        let package = Package::read(&app_path.join("Cargo.toml"))?;
+
+        info("Building", &format!("app {}...", app));
+        options.execute_ok(
+            cargo()
+                .arg("build")
+                .args(profile_args)
+                .current_dir(&app_path),
+        )?;
+
+        let output = esp.join("APP").join(&app);
+        copy_to_esp(
+            options,
+            &target
+                .join("x86_64-unknown-ysos")
+                .join(profile_dir)
+                .join(&package.name),
+            &output,
+        )?;
+        strip(options, &output)?;
+
+        entries.push(manifest::entry(options, &app, &package, &output)?);
+
+        // what it must print in test mode, see `golden`
+        let golden = app_path.join(GOLDEN_NAME);
+        if golden.is_file() {
+            copy_to_esp(options, &golden, &tests.join(format!("{}.out", app)))?;
+        }
+    }
+
+    manifest::write(
+        options,
+        &esp.join("APP").join(manifest::MANIFEST_NAME),
+        &entries,
+    )
+}
+
+/// Add `golden::TEST_CMDLINE` to the kernel command line in `config`
+fn test_config(options: &Options, config: &Path) -> Result<(), String> {
+    if options.dry_run {
+        return Ok(());
+    }
+
+    let content = fs::read_to_string(config)
+        .map_err(|err| format!("failed to read {}: {}", config.display(), err))?;
+    let mut lines: Vec<String> = content.lines().map(String::from).collect();
+    match lines.iter_mut().find(|line| line.starts_with("cmdline=")) {
+        Some(line) => {
+            if !line.ends_with('=') {
+                line.push(' ');
+            }
+            line.push_str(golden::TEST_CMDLINE);
+        }
+        None => lines.push(format!("cmdline={}", golden::TEST_CMDLINE)),
+    }
+
+    fs::write(config, lines.join("\n") + "\n")
+        .map_err(|err| format!("failed to write {}: {}", config.display(), err))
+}
+
+/// Remove the ESP and the cargo outputs
+pub fn clean(options: &Options) -> Result<(), String> {
+    if options.esp.exists() {
+        info("Removing", &options.esp.display().to_string());
+        if !options.dry_run {
+            fs::remove_dir_all(&options.esp).map_err(|err| err.to_string())?;
+        }
+    }
+
+    options.execute_ok(cargo().arg("clean").current_dir(workspace_root()))
+}
+
+/// Names of the apps under `pkg/app`, in order
+fn apps(root: &Path) -> Result<Vec<String>, String> {
+    let mut apps = fs::read_dir(root.join("pkg").join("app"))
+        .map_err(|err| format!("failed to list apps: {}", err))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().join("Cargo.toml").is_file())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .filter(|name| name != "config" && name != ".cargo")
+        .collect::<Vec<_>>();
+
+    apps.sort();
+    Ok(apps)
+}
+
+fn copy_to_esp(options: &Options, src: &Path, dst: &Path) -> Result<(), String> {
+    if options.verbose || options.dry_run {
+        debug(
+            "Copying",
+            &format!("{} -> {}", src.display(), dst.display()),
+        );
+    }
+
+    if options.dry_run {
+        return Ok(());
+    }
+
+    if !src.is_file() {
+        return Err(format!("{} is not a file", src.display()));
+    }
+
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+    }
+
+    fs::copy(src, dst)
+        .map(|_| ())
+        .map_err(|err| format!("failed to copy {}: {}", src.display(), err))
+}
+
+/// Drop the debug info of an app, if a strip tool is found
+fn strip(options: &Options, path: &Path) -> Result<(), String> {
+    match find_in_path("llvm-strip").or_else(|| find_in_path("strip")) {
+        Some(strip) => options.execute_ok(Command::new(strip).arg("--strip-debug").arg(path)),
+        None => {
+            if options.verbose {
+                debug("Skipping", "strip, no strip found in PATH");
+            }
+            Ok(())
+        }
+    }
+}
+
+pub fn find_in_path(name: &str) -> Option<PathBuf> {
+    let paths = std::env::var_os("PATH")?;
+    std::env::split_paths(&paths)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
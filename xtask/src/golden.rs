@@ -0,0 +1,86 @@
+//! Golden outputs of the apps, checked by the kernel in test mode
+//!
+//! `pkg/app/<app>/golden.out` is what the app must print when the kernel
+//! runs it alone with `test` on its command line, see `golden` of the
+//! kernel. The kernel frames what every app printed on debugcon, and
+//! `xtask test --bless` writes it back as the golden outputs, so an app
+//! gets its first one from an empty `golden.out`.
+
+use std::fs;
+
+use crate::{info, workspace_root, Options};
+
+/// The golden output of an app, next to its `Cargo.toml`
+pub const GOLDEN_NAME: &str = "golden.out";
+/// Where the golden outputs go in the ESP, as `<app>.out`,
+/// kept in sync with `golden::TEST_DIR` of the kernel
+pub const TEST_DIR: &str = "TEST";
+/// Options added to the kernel command line in test mode
+pub const TEST_CMDLINE: &str = "test deterministic=on";
+
+/// Written by the kernel before the output of an app, then its name,
+/// its length in bytes and `>>>\n`
+const FRAME_START: &[u8] = b"\n<<<golden ";
+
+/// The outputs framed in `log`, by app, the last one of each
+fn outputs(log: &[u8]) -> Vec<(String, Vec<u8>)> {
+    let mut outputs: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut rest = log;
+
+    while let Some(at) = rest
+        .windows(FRAME_START.len())
+        .position(|window| window == FRAME_START)
+    {
+        rest = &rest[at + FRAME_START.len()..];
+        let Some(end) = rest.windows(4).position(|window| window == b">>>\n") else {
+            break;
+        };
+        let header = String::from_utf8_lossy(&rest[..end]).into_owned();
+        rest = &rest[end + 4..];
+
+        let Some((name, len)) = header.split_once(' ') else {
+            continue;
+        };
+        let Some(output) = len.parse::<usize>().ok().and_then(|len| rest.get(..len)) else {
+            continue;
+        };
+
+        outputs.retain(|(app, _)| app != name);
+        outputs.push((name.to_string(), output.to_vec()));
+        rest = &rest[output.len()..];
+    }
+
+    outputs
+}
+
+/// Write what the apps printed in the last test run as their golden outputs
+pub fn bless(options: &Options) -> Result<(), String> {
+    if options.dry_run {
+        return Ok(());
+    }
+
+    let log = fs::read(&options.debugcon)
+        .map_err(|err| format!("failed to read {}: {}", options.debugcon.display(), err))?;
+    let outputs = outputs(&log);
+    if outputs.is_empty() {
+        return Err(format!(
+            "no app output in {}, was the kernel run in test mode?",
+            options.debugcon.display()
+        ));
+    }
+
+    let apps = workspace_root().join("pkg").join("app");
+    for (app, output) in outputs {
+        let dir = apps.join(&app);
+        if !dir.join("Cargo.toml").is_file() {
+            return Err(format!("no app {} for its output", app));
+        }
+
+        let path = dir.join(GOLDEN_NAME);
+        fs::write(&path, output)
+            .map_err(|err| format!("failed to write {}: {}", path.display(), err))?;
+        info("Blessed", &path.display().to_string());
+    }
+
+    Ok(())
+}
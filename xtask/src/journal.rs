@@ -0,0 +1,65 @@
+//! Journals of FAT16 images, see `fs::journal` of the kernel
+//!
+//! the journal takes the reserved sectors after the boot sector, so the
+//! image is to be made with enough of them, e.g.
+//! `mkfs.fat -C -F 16 -R 64 disk.img 65536`. The kernel then writes the
+//! FATs and directories of the image through it, and replays it at mount.
+
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+
+use crate::{info, Options};
+
+/// Kept in sync with `journal::MAGIC` of the kernel
+const MAGIC: &[u8; 8] = b"YSJOURNL";
+const SECTOR_SIZE: usize = 512;
+/// Fewest reserved sectors for a journal, the boot sector included,
+/// so the biggest update the kernel makes of a directory fits in it
+const MIN_RESERVED: usize = 16;
+
+/// Make the reserved sectors of the image given by `--disk` an empty journal
+pub fn format(options: &Options) -> Result<(), String> {
+    let path = options
+        .disk
+        .as_ref()
+        .ok_or_else(|| String::from("journal needs --disk <image>"))?;
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path)
+        .map_err(|err| format!("failed to open {}: {}", path.display(), err))?;
+
+    let mut boot = [0; SECTOR_SIZE];
+    file.read_exact(&mut boot)
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+
+    let u16_at = |i: usize| u16::from_le_bytes([boot[i], boot[i + 1]]) as usize;
+    if boot[510..] != [0x55, 0xAA] || u16_at(11) != SECTOR_SIZE {
+        return Err(format!(
+            "{} is not a FAT volume with 512 byte sectors",
+            path.display()
+        ));
+    }
+    let reserved = u16_at(14);
+    if reserved < MIN_RESERVED {
+        return Err(format!(
+            "{} has {} reserved sectors, a journal needs {}, see mkfs.fat -R",
+            path.display(),
+            reserved,
+            MIN_RESERVED
+        ));
+    }
+
+    // the header alone, marked clean with no blocks listed
+    let mut header = [0; SECTOR_SIZE];
+    header[..MAGIC.len()].copy_from_slice(MAGIC);
+
+    info("Formatting", &format!("journal of {}...", path.display()));
+    if options.dry_run {
+        return Ok(());
+    }
+
+    // right after the boot sector just read
+    file.write_all(&header)
+        .map_err(|err| format!("failed to write {}: {}", path.display(), err))
+}
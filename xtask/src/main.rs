@@ -0,0 +1,223 @@
+//! Build and run YatSenOS, a Rust replacement for `ysos.py`
+//!
+//! ```text
+//! cargo xtask build   [options]
+//! cargo xtask run     [options]
+//! cargo xtask launch  [options]
+//! cargo xtask test    [options]
+//! cargo xtask journal --disk <image>
+//! cargo xtask clean
+//! ```
+
+mod build;
+mod golden;
+mod journal;
+mod manifest;
+mod qemu;
+
+use std::path::PathBuf;
+use std::process::{exit, Command};
+
+const USAGE: &str = "\
+Usage: cargo xtask <build|run|launch|test|journal|clean> [options]
+
+Tasks:
+    build       build the bootloader, kernel and apps into the ESP
+    run         build, then launch QEMU
+    launch      launch QEMU with the current ESP
+    test        build, then launch QEMU in test mode and check the exit code
+    journal     format the journal of the FAT16 image given by --disk
+    clean       remove the ESP and cargo build outputs
+
+Options:
+    --debug-info        build the kernel with debug info
+    --features <list>   kernel features instead of the default ones,
+                        \"\" for a minimal kernel
+    --gdb               wait for gdb on --gdb-listen before starting
+    --gdb-listen <addr> gdb server address, default 0.0.0.0:1234
+    --intdbg            log interrupts and cpu resets
+    --graphic           show the QEMU window instead of -nographic
+    -m, --memory <size> memory size, default 96M
+    --bios <path>       OVMF firmware, default assets/OVMF.fd
+    --esp <path>        ESP directory, default esp
+    --debugcon <path>   file for the debug console, default target/debugcon.log
+    --share <dir>       pass the files under dir to the guest through fw_cfg
+    --share-apps        pass the built apps too, so they are run without a reboot
+    --disk <image>      attach a raw disk image on AHCI, written through
+    --bless             with test, write what the apps printed as their golden outputs
+    --dry-run           print commands instead of running them
+    -v, --verbose       print commands before running them
+    -h, --help          show this help";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Task {
+    Build,
+    Run,
+    Launch,
+    Test,
+    Journal,
+    Clean,
+}
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub task: Task,
+    pub debug_info: bool,
+    pub features: Option<String>,
+    pub gdb: bool,
+    pub gdb_listen: String,
+    pub intdbg: bool,
+    pub graphic: bool,
+    pub memory: String,
+    pub bios: PathBuf,
+    pub esp: PathBuf,
+    pub debugcon: PathBuf,
+    pub share: Option<PathBuf>,
+    pub share_apps: bool,
+    /// raw image attached as a SATA disk, changes are kept
+    pub disk: Option<PathBuf>,
+    /// write the outputs of a test run as the golden ones
+    pub bless: bool,
+    pub dry_run: bool,
+    pub verbose: bool,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let task = match args.next().as_deref() {
+            Some("build") => Task::Build,
+            Some("run") => Task::Run,
+            Some("launch") => Task::Launch,
+            Some("test") => Task::Test,
+            Some("journal") => Task::Journal,
+            Some("clean") => Task::Clean,
+            Some("-h" | "--help") | None => return Err(String::new()),
+            Some(other) => return Err(format!("unknown task: {}", other)),
+        };
+
+        let root = workspace_root();
+        let mut options = Self {
+            task,
+            debug_info: false,
+            features: None,
+            gdb: false,
+            gdb_listen: String::from("0.0.0.0:1234"),
+            intdbg: false,
+            graphic: false,
+            memory: String::from("96M"),
+            bios: root.join("assets").join("OVMF.fd"),
+            esp: root.join("esp"),
+            debugcon: root.join("target").join("debugcon.log"),
+            share: None,
+            share_apps: false,
+            disk: None,
+            bless: false,
+            dry_run: false,
+            verbose: false,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = |name: &str| {
+                args.next()
+                    .ok_or_else(|| format!("missing value for {}", name))
+            };
+
+            match arg.as_str() {
+                "--debug-info" => options.debug_info = true,
+                "--features" => options.features = Some(value(&arg)?),
+                "--gdb" => options.gdb = true,
+                "--gdb-listen" => options.gdb_listen = value(&arg)?,
+                "--intdbg" => options.intdbg = true,
+                "--graphic" => options.graphic = true,
+                "-m" | "--memory" => options.memory = value(&arg)?,
+                "--bios" => options.bios = value(&arg)?.into(),
+                "--esp" => options.esp = value(&arg)?.into(),
+                "--debugcon" => options.debugcon = value(&arg)?.into(),
+                "--share" => options.share = Some(value(&arg)?.into()),
+                "--share-apps" => options.share_apps = true,
+                "--disk" => options.disk = Some(value(&arg)?.into()),
+                "--bless" => options.bless = true,
+                "--dry-run" => options.dry_run = true,
+                "-v" | "--verbose" => options.verbose = true,
+                "-h" | "--help" => return Err(String::new()),
+                other => return Err(format!("unknown option: {}", other)),
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Run `cmd`, or only print it in dry run mode
+    pub fn execute(&self, cmd: &mut Command) -> Result<i32, String> {
+        if self.verbose || self.dry_run {
+            debug("Executing", &format!("{:?}", cmd));
+        }
+
+        if self.dry_run {
+            return Ok(0);
+        }
+
+        let status = cmd
+            .status()
+            .map_err(|err| format!("{:?} failed to start: {}", cmd.get_program(), err))?;
+
+        status
+            .code()
+            .ok_or_else(|| format!("{:?} was killed by a signal", cmd.get_program()))
+    }
+
+    /// Run `cmd` and require it to succeed
+    pub fn execute_ok(&self, cmd: &mut Command) -> Result<(), String> {
+        match self.execute(cmd)? {
+            0 => Ok(()),
+            code => Err(format!("{:?} failed with code {}", cmd.get_program(), code)),
+        }
+    }
+}
+
+/// The directory of the top level `Cargo.toml`
+pub fn workspace_root() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("xtask is not inside the workspace")
+        .to_path_buf()
+}
+
+pub fn info(step: &str, content: &str) {
+    println!("\x1b[1;32m[+] {}:\x1b[0m \x1b[1m{}\x1b[0m", step, content);
+}
+
+pub fn error(step: &str, content: &str) {
+    eprintln!("\x1b[1;31m[E] {}:\x1b[0m \x1b[1m{}\x1b[0m", step, content);
+}
+
+pub fn debug(step: &str, content: &str) {
+    println!("\x1b[1;34m[?] {}:\x1b[0m \x1b[1m{}\x1b[0m", step, content);
+}
+
+fn main() {
+    let options = match Options::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            if !err.is_empty() {
+                error("Error", &err);
+            }
+            println!("{}", USAGE);
+            exit(if err.is_empty() { 0 } else { 2 });
+        }
+    };
+
+    let result = match options.task {
+        Task::Build => build::build(&options),
+        Task::Run => build::build(&options).and_then(|_| qemu::launch(&options)),
+        Task::Launch => qemu::launch(&options),
+        Task::Test => build::build(&options).and_then(|_| qemu::test(&options)),
+        Task::Journal => journal::format(&options),
+        Task::Clean => build::clean(&options),
+    };
+
+    if let Err(err) = result {
+        error("Error", &err);
+        exit(1);
+    }
+}
@@ -0,0 +1,114 @@
+use std::fs;
+use std::path::Path;
+
+use hash::{sha256, Hex};
+
+use crate::{info, Options};
+
+/// Kept in sync with `boot::manifest::MANIFEST_NAME`
+pub const MANIFEST_NAME: &str = ".manifest";
+
+/// The parts of an app's `Cargo.toml` that go into the manifest
+#[derive(Debug, Default)]
+pub struct Package {
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    /// from `[package.metadata.ysos]`
+    pub stack: u64,
+    /// from `[package.metadata.ysos]`
+    pub usage: String,
+}
+
+impl Package {
+    /// Read the keys we need from a `Cargo.toml`
+    ///
+    /// only plain `key = value` lines are understood,
+    /// which is all the app manifests use.
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let content = fs::read_to_string(path)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+
+        let mut package = Self::default();
+        let mut section = String::new();
+
+        for line in content.lines().map(str::trim) {
+            if line.starts_with('[') {
+                section = line.trim_matches(|c| c == '[' || c == ']').to_string();
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((key, value)) if !line.starts_with('#') => (key.trim(), value.trim()),
+                _ => continue,
+            };
+            let text = value.trim_matches('"').to_string();
+
+            match (section.as_str(), key) {
+                ("package", "name") => package.name = text,
+                ("package", "version") => package.version = text,
+                ("package", "description") => package.description = text,
+                ("package.metadata.ysos", "stack") => package.stack = value.parse().unwrap_or(0),
+                ("package.metadata.ysos", "usage") => package.usage = text,
+                _ => {}
+            }
+        }
+
+        if package.name.is_empty() {
+            return Err(format!("no package name in {}", path.display()));
+        }
+
+        Ok(package)
+    }
+}
+
+/// One manifest line for `app`, built into `binary`
+///
+/// `name|version|stack pages|sha256|usage|description`
+pub fn entry(
+    options: &Options,
+    app: &str,
+    package: &Package,
+    binary: &Path,
+) -> Result<String, String> {
+    let digest = if options.dry_run {
+        String::new()
+    } else {
+        let content = fs::read(binary)
+            .map_err(|err| format!("failed to read {}: {}", binary.display(), err))?;
+        Hex(&sha256(&content)).to_string()
+    };
+
+    let stack = package.stack.to_string();
+    let fields = [
+        app,
+        &package.version,
+        &stack,
+        &digest,
+        &package.usage,
+        &package.description,
+    ];
+
+    // `|` splits the fields, keep it out of the values
+    Ok(fields
+        .iter()
+        .map(|field| field.replace('|', "/"))
+        .collect::<Vec<_>>()
+        .join("|"))
+}
+
+pub fn write(options: &Options, path: &Path, entries: &[String]) -> Result<(), String> {
+    info("Writing", &format!("manifest of {} apps...", entries.len()));
+
+    if options.dry_run {
+        return Ok(());
+    }
+
+    let mut content = String::from("# name|version|stack pages|sha256|usage|description\n");
+    for entry in entries {
+        content.push_str(entry);
+        content.push('\n');
+    }
+
+    fs::write(path, content).map_err(|err| format!("failed to write {}: {}", path.display(), err))
+}
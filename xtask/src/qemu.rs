@@ -0,0 +1,170 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::build::find_in_path;
+use crate::{golden, info, Options};
+
+/// I/O port of the `isa-debug-exit` device used in test mode,
+/// kept in sync with `debug_exit_port` of `boot.conf`
+pub const DEBUG_EXIT_PORT: u16 = 0xf4;
+/// Written to [`DEBUG_EXIT_PORT`] by the kernel when all tests pass
+pub const TEST_SUCCESS: i32 = 0x10;
+/// Written to [`DEBUG_EXIT_PORT`] by the kernel when a test fails
+pub const TEST_FAILURE: i32 = 0x11;
+
+/// Kept in sync with `fw_cfg::SHARE_PREFIX` of the kernel
+const SHARE_PREFIX: &str = "opt/ysos/";
+/// Longest fw_cfg file name QEMU takes, NUL excluded
+const FW_CFG_NAME_MAX: usize = 55;
+
+fn qemu_exe() -> Result<PathBuf, String> {
+    find_in_path("qemu-system-x86_64")
+        // optional path C:\Program Files\qemu for Windows
+        .or_else(|| {
+            let exe = PathBuf::from("C:\\Program Files\\qemu\\qemu-system-x86_64.exe");
+            exe.is_file().then_some(exe)
+        })
+        .ok_or_else(|| String::from("qemu-system-x86_64 not found in PATH"))
+}
+
+fn qemu(options: &Options) -> Result<Command, String> {
+    let mut cmd = Command::new(qemu_exe()?);
+
+    if let Some(dir) = options.debugcon.parent().filter(|_| !options.dry_run) {
+        std::fs::create_dir_all(dir)
+            .map_err(|err| format!("failed to create {}: {}", dir.display(), err))?;
+    }
+
+    cmd.arg("-bios")
+        .arg(&options.bios)
+        .args(["-net", "none", "-m", &options.memory])
+        .arg("-drive")
+        .arg(format!("format=raw,file=fat:{}", options.esp.display()))
+        .arg("-snapshot")
+        // what the kernel prints before the serial port is up
+        .arg("-debugcon")
+        .arg(format!("file:{}", options.debugcon.display()));
+
+    if !options.graphic {
+        cmd.arg("-nographic");
+    }
+
+    // left out of -snapshot, so what the kernel writes is there next time
+    if let Some(disk) = &options.disk {
+        let disk = disk.display().to_string().replace(',', ",,");
+        cmd.arg("-drive")
+            .arg(format!(
+                "if=none,id=disk,format=raw,snapshot=off,file={}",
+                disk
+            ))
+            .args([
+                "-device",
+                "ahci,id=ahci",
+                "-device",
+                "ide-hd,drive=disk,bus=ahci.0",
+            ]);
+    }
+
+    for (name, path) in shared_files(options)? {
+        // a comma in an option value is written twice
+        let name = name.replace(',', ",,");
+        let path = path.display().to_string().replace(',', ",,");
+        cmd.arg("-fw_cfg")
+            .arg(format!("name={},file={}", name, path));
+    }
+
+    if options.gdb {
+        cmd.args(["-gdb", &format!("tcp:{}", options.gdb_listen), "-S"]);
+    } else if options.intdbg {
+        cmd.args(["-no-reboot", "-d", "int,cpu_reset"]);
+    }
+
+    Ok(cmd)
+}
+
+/// The fw_cfg names and paths of the files shared with the guest
+///
+/// fw_cfg is read once at boot, so files changed later are not seen
+/// until QEMU is launched again.
+fn shared_files(options: &Options) -> Result<Vec<(String, PathBuf)>, String> {
+    let mut files = Vec::new();
+
+    if let Some(dir) = &options.share {
+        walk(dir, SHARE_PREFIX, &mut files)?;
+    }
+
+    if options.share_apps {
+        let apps = options.esp.join("APP");
+        walk(&apps, &format!("{}app/", SHARE_PREFIX), &mut files)?;
+    }
+
+    if let Some((name, _)) = files.iter().find(|(name, _)| name.len() > FW_CFG_NAME_MAX) {
+        return Err(format!(
+            "shared file name {} is longer than {} bytes",
+            name, FW_CFG_NAME_MAX
+        ));
+    }
+
+    Ok(files)
+}
+
+fn walk(dir: &Path, prefix: &str, files: &mut Vec<(String, PathBuf)>) -> Result<(), String> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|err| format!("failed to read {}: {}", dir.display(), err))?;
+
+    for entry in entries {
+        let path = entry
+            .map_err(|err| format!("failed to read {}: {}", dir.display(), err))?
+            .path();
+        let name = format!("{}{}", prefix, path.file_name().unwrap().to_string_lossy());
+
+        if path.is_dir() {
+            walk(&path, &format!("{}/", name), files)?;
+        } else {
+            files.push((name, path));
+        }
+    }
+
+    Ok(())
+}
+
+/// Launch QEMU with the ESP
+pub fn launch(options: &Options) -> Result<(), String> {
+    info("Launching", "QEMU...");
+    options.execute(&mut qemu(options)?).map(|_| ())
+}
+
+/// Launch QEMU in test mode and check how the kernel exited
+///
+/// the kernel reports through the `isa-debug-exit` device,
+/// QEMU then exits with `(code << 1) | 1`. With `--bless` the outputs
+/// of the apps are written as their golden ones instead, see `golden`.
+pub fn test(options: &Options) -> Result<(), String> {
+    let mut cmd = qemu(options)?;
+    cmd.arg("-device")
+        .arg(format!(
+            "isa-debug-exit,iobase={:#x},iosize=0x04",
+            DEBUG_EXIT_PORT
+        ))
+        .arg("-no-reboot");
+
+    info("Testing", "launching QEMU in test mode...");
+    let code = options.execute(&mut cmd)?;
+
+    if options.dry_run {
+        return Ok(());
+    }
+    if options.bless {
+        return golden::bless(options);
+    }
+
+    match code {
+        code if code == (TEST_SUCCESS << 1) | 1 => {
+            info("Testing", "all tests passed");
+            Ok(())
+        }
+        code if code == (TEST_FAILURE << 1) | 1 => Err(String::from("kernel tests failed")),
+        0 => Err(String::from("QEMU exited without a test result")),
+        code => Err(format!("QEMU exited with unexpected code {}", code)),
+    }
+}
@@ -2,6 +2,8 @@
 
 import os
 import shutil
+import hashlib
+import tomllib
 import subprocess
 import argparse
 
@@ -147,15 +149,14 @@ def build():
 
     # build apps
     apps = get_apps()
-    for app in apps:
+    manifest = []
+    for app in sorted(apps):
         app_path = os.path.join(os.getcwd(), 'pkg', 'app', app)
 
-        # read Cargo.toml to get the package name
-        with open(os.path.join(app_path, 'Cargo.toml'), 'r') as f:
-            for line in f.readlines():
-                if 'name' in line:
-                    app_name = line.split('"')[1]
-                    break
+        # read Cargo.toml to get the package name and metadata
+        with open(os.path.join(app_path, 'Cargo.toml'), 'rb') as f:
+            package = tomllib.load(f)['package']
+        app_name = package['name']
 
         info('Building', f'app {app}...')
         execute_command([cargo_exe, 'build', profile], app_path)
@@ -163,6 +164,58 @@ def build():
             os.getcwd(), 'target', 'x86_64-unknown-ysos', profile_dir, app_name)
         copy_to_esp(compile_output, os.path.join('APP', app))
 
+        app_output = os.path.join(os.getcwd(), args.boot, 'APP', app)
+        strip_app(app_output)
+        manifest.append(manifest_entry(app, package, app_output))
+
+    write_manifest(manifest)
+
+
+def strip_app(path: str):
+    strip_exe = shutil.which('llvm-strip') or shutil.which('strip')
+
+    if strip_exe is None:
+        debug('Skipping', f'strip {path}, no strip found in PATH')
+        return
+
+    execute_command([strip_exe, '--strip-debug', path])
+
+
+def manifest_entry(app: str, package: dict, path: str) -> str:
+    metadata = package.get('metadata', {}).get('ysos', {})
+
+    digest = ''
+    if not args.dry_run:
+        with open(path, 'rb') as f:
+            digest = hashlib.sha256(f.read()).hexdigest()
+
+    fields = [
+        app,
+        package.get('version', ''),
+        str(metadata.get('stack', 0)),
+        digest,
+        metadata.get('usage', ''),
+        package.get('description', ''),
+    ]
+
+    # `|` splits the fields, keep it out of the values
+    return '|'.join(field.replace('|', '/') for field in fields)
+
+
+def write_manifest(entries: list):
+    # the bootloader skips files starting with a dot when loading apps
+    dst = os.path.join(os.getcwd(), args.boot, 'APP', '.manifest')
+
+    if args.dry_run:
+        debug('Would write', f'app manifest -> {dst}')
+        return
+
+    info('Writing', f'manifest of {len(entries)} apps...')
+    with open(dst, 'w') as f:
+        f.write('# name|version|stack pages|sha256|usage|description\n')
+        for entry in entries:
+            f.write(entry + '\n')
+
 
 def clean():
     if os.path.exists(args.boot):